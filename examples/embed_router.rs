@@ -0,0 +1,65 @@
+//! Embedding Example
+//!
+//! This example routes requests with the library API instead of the HTTP server:
+//! it shows the routing decision for a few requests, then sends one to OpenAI if
+//! OPENAI_API_KEY is set.
+//! Run with: cargo run --example embed_router
+
+use claude_code_mux::{route_and_dispatch, AnthropicRequest, ProviderRegistryBuilder, RouterBuilder};
+
+const CONFIG: &str = r#"
+[router]
+default = "fast"
+think = "smart"
+
+[[providers]]
+name = "openai"
+provider_type = "openai"
+api_key = "$OPENAI_API_KEY"
+models = []
+
+[[models]]
+name = "fast"
+mappings = [{ priority = 1, provider = "openai", actual_model = "gpt-5-mini" }]
+
+[[models]]
+name = "smart"
+mappings = [{ priority = 1, provider = "openai", actual_model = "gpt-5" }]
+"#;
+
+fn request(model: &str, prompt: &str, thinking: bool) -> anyhow::Result<AnthropicRequest> {
+    let mut body = serde_json::json!({
+        "model": model,
+        "max_tokens": 1024,
+        "messages": [{"role": "user", "content": prompt}]
+    });
+    if thinking {
+        body["thinking"] = serde_json::json!({"type": "enabled", "budget_tokens": 4000});
+    }
+    Ok(serde_json::from_value(body)?)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let router = RouterBuilder::from_toml(CONFIG)?.build()?;
+
+    // Routing alone doesn't call a provider
+    for (prompt, thinking) in [("Rename this variable", false), ("Plan the migration", true)] {
+        let mut request = request("claude-sonnet-4-5", prompt, thinking)?;
+        let decision = router.route(&mut request)?;
+        println!("{:<25} → {} ({})", prompt, decision.model_name, decision.route_type);
+    }
+
+    if std::env::var("OPENAI_API_KEY").is_err() {
+        println!();
+        println!("Set OPENAI_API_KEY to send a request");
+        return Ok(());
+    }
+
+    let registry = ProviderRegistryBuilder::from_config(router.config()).build()?;
+    let dispatched = route_and_dispatch(&router, &registry, request("claude-sonnet-4-5", "Say hello", false)?).await?;
+    println!();
+    println!("Answered by {}/{}:", dispatched.provider, dispatched.actual_model);
+    println!("{}", serde_json::to_string_pretty(&dispatched.response.content)?);
+    Ok(())
+}
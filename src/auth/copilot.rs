@@ -0,0 +1,228 @@
+//! GitHub Copilot authentication: the GitHub device-code flow for a GitHub OAuth
+//! token, exchanged for short-lived Copilot session tokens.
+//!
+//! Stored as an `OAuthToken` whose `refresh_token` is the (long-lived) GitHub token
+//! and whose `access_token` is the latest Copilot session token.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+
+use super::token_store::{OAuthToken, TokenStore};
+
+/// OAuth app used by the Copilot editor plugins
+const CLIENT_ID: &str = "Iv1.b507a08c87ecfe98";
+
+/// API used when the session token response doesn't name one
+pub const DEFAULT_API_BASE: &str = "https://api.githubcopilot.com";
+
+/// Editor headers the Copilot API expects on every request
+pub const EDITOR_HEADERS: &[(&str, &str)] = &[
+    ("Editor-Version", "vscode/1.99.3"),
+    ("Editor-Plugin-Version", "copilot-chat/0.26.7"),
+    ("Copilot-Integration-Id", "vscode-chat"),
+    ("User-Agent", "GitHubCopilotChat/0.26.7"),
+];
+
+/// Code the user enters at `verification_uri` to authorize this device
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeviceCode {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    /// Seconds until the codes expire
+    pub expires_in: u64,
+    /// Minimum seconds between polls
+    pub interval: u64,
+}
+
+/// Result of polling for the device authorization
+#[derive(Debug)]
+pub enum DevicePoll {
+    /// The user hasn't entered the code yet
+    Pending,
+    /// Polling too fast: wait this many more seconds between polls
+    SlowDown(u64),
+    /// Authorized: the GitHub OAuth token
+    Complete(SecretString),
+}
+
+/// A Copilot session token and the API it is valid for
+#[derive(Debug, Clone)]
+pub struct CopilotSession {
+    pub token: SecretString,
+    pub expires_at: DateTime<Utc>,
+    pub api_base: String,
+}
+
+/// `github.com`, or the GitHub Enterprise host (`https://` prefix optional)
+fn github_host(enterprise_url: Option<&str>) -> String {
+    enterprise_url
+        .map(|url| url.trim_start_matches("https://").trim_start_matches("http://").trim_end_matches('/').to_string())
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| "github.com".to_string())
+}
+
+/// Start the device flow
+pub async fn request_device_code(client: &reqwest::Client, enterprise_url: Option<&str>) -> Result<DeviceCode> {
+    let response = client
+        .post(format!("https://{}/login/device/code", github_host(enterprise_url)))
+        .header("Accept", "application/json")
+        .form(&[("client_id", CLIENT_ID), ("scope", "read:user")])
+        .send()
+        .await
+        .context("Failed to request device code")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow!("Device code request failed: {} - {}", status, body));
+    }
+    response.json().await.context("Failed to parse device code response")
+}
+
+/// Check once whether the user has authorized the device
+pub async fn poll_device_code(
+    client: &reqwest::Client,
+    device_code: &str,
+    enterprise_url: Option<&str>,
+) -> Result<DevicePoll> {
+    #[derive(Deserialize)]
+    struct TokenResponse {
+        access_token: Option<String>,
+        error: Option<String>,
+        error_description: Option<String>,
+        interval: Option<u64>,
+    }
+
+    let response: TokenResponse = client
+        .post(format!("https://{}/login/oauth/access_token", github_host(enterprise_url)))
+        .header("Accept", "application/json")
+        .form(&[
+            ("client_id", CLIENT_ID),
+            ("device_code", device_code),
+            ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+        ])
+        .send()
+        .await
+        .context("Failed to poll for device authorization")?
+        .json()
+        .await
+        .context("Failed to parse device authorization response")?;
+
+    match (response.access_token, response.error.as_deref()) {
+        (Some(token), _) => Ok(DevicePoll::Complete(SecretString::new(token))),
+        (None, Some("authorization_pending")) => Ok(DevicePoll::Pending),
+        (None, Some("slow_down")) => Ok(DevicePoll::SlowDown(response.interval.unwrap_or(5))),
+        (None, error) => Err(anyhow!(
+            "Device authorization failed: {}",
+            response.error_description.as_deref().or(error).unwrap_or("unknown error")
+        )),
+    }
+}
+
+/// Exchange a GitHub OAuth token for a Copilot session token
+pub async fn exchange_session_token(
+    client: &reqwest::Client,
+    github_token: &str,
+    enterprise_url: Option<&str>,
+) -> Result<CopilotSession> {
+    #[derive(Deserialize)]
+    struct SessionResponse {
+        token: String,
+        /// Unix seconds
+        expires_at: i64,
+        #[serde(default)]
+        endpoints: Option<Endpoints>,
+    }
+
+    #[derive(Deserialize)]
+    struct Endpoints {
+        api: Option<String>,
+    }
+
+    let mut request = client
+        .get(format!("https://api.{}/copilot_internal/v2/token", github_host(enterprise_url)))
+        .header("Authorization", format!("token {}", github_token))
+        .header("Accept", "application/json");
+    for (name, value) in EDITOR_HEADERS {
+        request = request.header(*name, *value);
+    }
+
+    let response = request.send().await.context("Failed to get Copilot session token")?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow!(
+            "Copilot session token request failed: {} - {} (does this GitHub account have a Copilot subscription?)",
+            status, body
+        ));
+    }
+
+    let session: SessionResponse = response.json().await.context("Failed to parse Copilot session token")?;
+    Ok(CopilotSession {
+        token: SecretString::new(session.token),
+        expires_at: Utc.timestamp_opt(session.expires_at, 0).single().unwrap_or_else(Utc::now),
+        api_base: session
+            .endpoints
+            .and_then(|endpoints| endpoints.api)
+            .unwrap_or_else(|| DEFAULT_API_BASE.to_string())
+            .trim_end_matches('/')
+            .to_string(),
+    })
+}
+
+/// Get a fresh session token for `provider_id` from its stored GitHub token,
+/// saving it back to the store
+pub async fn refresh_session(client: &reqwest::Client, token_store: &TokenStore, provider_id: &str) -> Result<CopilotSession> {
+    let stored = token_store.get_or_reload(provider_id).ok_or_else(|| {
+        anyhow!("No Copilot login for '{}': run `ccm copilot-login {}`", provider_id, provider_id)
+    })?;
+    let session = exchange_session_token(
+        client,
+        stored.refresh_token.expose_secret(),
+        stored.enterprise_url.as_deref(),
+    )
+    .await?;
+
+    token_store.save(OAuthToken {
+        access_token: session.token.clone(),
+        expires_at: session.expires_at,
+        ..stored
+    })?;
+    Ok(session)
+}
+
+/// Store a newly authorized GitHub token, checking it can get a Copilot session
+pub async fn complete_login(
+    client: &reqwest::Client,
+    token_store: &TokenStore,
+    provider_id: &str,
+    github_token: SecretString,
+    enterprise_url: Option<&str>,
+) -> Result<CopilotSession> {
+    let session = exchange_session_token(client, github_token.expose_secret(), enterprise_url).await?;
+    token_store.save(OAuthToken {
+        provider_id: provider_id.to_string(),
+        access_token: session.token.clone(),
+        refresh_token: github_token,
+        expires_at: session.expires_at,
+        enterprise_url: enterprise_url.map(|url| github_host(Some(url))),
+        project_id: None,
+    })?;
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_github_host() {
+        assert_eq!(github_host(None), "github.com");
+        assert_eq!(github_host(Some("https://ghe.example.com/")), "ghe.example.com");
+        assert_eq!(github_host(Some("ghe.example.com")), "ghe.example.com");
+        assert_eq!(github_host(Some("")), "github.com");
+    }
+}
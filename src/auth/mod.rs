@@ -1,3 +1,4 @@
+pub mod copilot;
 pub mod oauth;
 pub mod token_store;
 
@@ -27,7 +27,7 @@ impl PKCEVerifier {
         let mut hasher = Sha256::new();
         hasher.update(verifier.as_bytes());
         let challenge_bytes = hasher.finalize();
-        let challenge = URL_SAFE_NO_PAD.encode(&challenge_bytes);
+        let challenge = URL_SAFE_NO_PAD.encode(challenge_bytes);
 
         Self { verifier, challenge }
     }
@@ -229,8 +229,6 @@ impl OAuthClient {
         let response = if is_gemini {
             // Google OAuth uses form-urlencoded with client_secret
             tracing::debug!("🔍 Gemini token exchange:");
-            tracing::debug!("  code: {}", auth_code);
-            tracing::debug!("  code_verifier: {}", verifier);
             tracing::debug!("  redirect_uri: {}", &self.config.redirect_uri);
             tracing::debug!("  client_id: {}", &self.config.client_id);
 
@@ -256,8 +254,6 @@ impl OAuthClient {
         } else if is_openai_codex {
             // OpenAI uses form-urlencoded and only needs code + code_verifier
             tracing::debug!("🔍 OpenAI token exchange:");
-            tracing::debug!("  code: {}", auth_code);
-            tracing::debug!("  code_verifier: {}", verifier);
             tracing::debug!("  redirect_uri: {}", &self.config.redirect_uri);
             tracing::debug!("  client_id: {}", &self.config.client_id);
 
@@ -409,10 +405,9 @@ impl OAuthClient {
             return Err(anyhow!("Token refresh failed: {} - {}", status, body));
         }
 
-        // Debug: Log the raw response body
+        // The body holds the new tokens, so it isn't logged
         let response_text = response.text().await
             .context("Failed to read response body")?;
-        tracing::debug!("🔍 Token refresh response body: {}", response_text);
 
         let token_response: TokenResponse = serde_json::from_str(&response_text)
             .context("Failed to parse token response")?;
@@ -59,6 +59,14 @@ impl OAuthToken {
     }
 }
 
+/// Whether a persistence error came from a read-only filesystem / unwritable location
+fn is_read_only(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|e| e.downcast_ref::<std::io::Error>())
+        .any(|e| matches!(e.kind(), std::io::ErrorKind::ReadOnlyFilesystem | std::io::ErrorKind::PermissionDenied))
+}
+
 /// Token storage - persists to JSON file
 #[derive(Debug, Clone)]
 pub struct TokenStore {
@@ -88,17 +96,18 @@ impl TokenStore {
     }
 
     /// Get default token store path
-    /// ~/.claude-code-mux/oauth_tokens.json
+    /// <state dir>/oauth_tokens.json
     pub fn default_path() -> Result<PathBuf> {
-        let home = dirs::home_dir()
-            .context("Failed to get home directory")?;
-        let config_dir = home.join(".claude-code-mux");
-        fs::create_dir_all(&config_dir)
-            .context("Failed to create config directory")?;
+        let config_dir = crate::paths::state_dir();
+        // Best effort: on a read-only filesystem tokens still work in memory
+        if let Err(e) = fs::create_dir_all(config_dir) {
+            tracing::warn!("⚠️  Failed to create {}: {}", config_dir.display(), e);
+        }
         Ok(config_dir.join("oauth_tokens.json"))
     }
 
     /// Create a token store at the default location
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> Result<Self> {
         let path = Self::default_path()?;
         Self::new(path)
@@ -115,8 +124,14 @@ impl TokenStore {
             tokens.insert(provider_id, token);
         }
 
-        // Persist to file
-        self.persist()?;
+        // Persist to file. A read-only filesystem keeps the token in memory only.
+        if let Err(e) = self.persist() {
+            if is_read_only(&e) {
+                tracing::warn!("⚠️  Token kept in memory only: {:#}", e);
+                return Ok(());
+            }
+            return Err(e);
+        }
 
         Ok(())
     }
@@ -128,6 +143,31 @@ impl TokenStore {
         tokens.get(provider_id).cloned()
     }
 
+    /// Get token for a provider, re-reading the token file if it isn't loaded
+    /// (e.g. `ccm copilot-login` saved it while the server was running)
+    pub fn get_or_reload(&self, provider_id: &str) -> Option<OAuthToken> {
+        if let Some(token) = self.get(provider_id) {
+            return Some(token);
+        }
+        let content = fs::read_to_string(&self.file_path).ok()?;
+        let on_disk: HashMap<String, OAuthToken> = serde_json::from_str(&content).ok()?;
+        let token = on_disk.get(provider_id)?.clone();
+        self.tokens.write()
+            .expect("Token store lock poisoned during write - cannot proceed safely")
+            .insert(provider_id.to_string(), token.clone());
+        Some(token)
+    }
+
+    /// Adopt a token saved by another instance if it expires later than ours
+    /// (it was refreshed more recently). Returns whether it replaced ours.
+    pub fn merge(&self, token: OAuthToken) -> Result<bool> {
+        let newer = self.get(&token.provider_id).is_none_or(|current| token.expires_at > current.expires_at);
+        if newer {
+            self.save(token)?;
+        }
+        Ok(newer)
+    }
+
     /// Remove token for a provider
     pub fn remove(&self, provider_id: &str) -> Result<()> {
         {
@@ -0,0 +1,228 @@
+//! Client-side setup: edits Claude Code's `settings.json` so it talks to CCM.
+//!
+//! - `env.ANTHROPIC_BASE_URL` points at the local server
+//! - `env.ANTHROPIC_API_KEY` is set to a placeholder unless a key is already there
+//!   (CCM uses its own provider credentials, so any non-empty value works)
+//! - `statusLine` runs the CCM statusline script
+//!
+//! Every other key is left untouched. The previous file is backed up before writing.
+
+use anyhow::{bail, Context, Result};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+/// Placeholder API key written when the user has none
+pub const PLACEHOLDER_API_KEY: &str = "ccm";
+
+const BASE_URL_VAR: &str = "ANTHROPIC_BASE_URL";
+const API_KEY_VAR: &str = "ANTHROPIC_API_KEY";
+const STATUSLINE_SCRIPT: &str = "statusline.sh";
+
+/// Claude Code's user settings file ($CLAUDE_CONFIG_DIR/settings.json or ~/.claude/settings.json)
+pub fn settings_path() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("CLAUDE_CONFIG_DIR").filter(|d| !d.is_empty()) {
+        return Ok(PathBuf::from(dir).join("settings.json"));
+    }
+    let home = dirs::home_dir().context("Failed to get home directory")?;
+    Ok(home.join(".claude").join("settings.json"))
+}
+
+/// What `install` changed, for reporting
+#[derive(Debug, Default, PartialEq)]
+pub struct InstallReport {
+    pub base_url: String,
+    /// Existing API key was kept
+    pub kept_api_key: bool,
+}
+
+/// Point Claude Code at CCM
+pub fn install(settings: &mut Value, base_url: &str, api_key: Option<&str>, statusline: Option<&Path>) -> Result<InstallReport> {
+    let root = as_object(settings)?;
+    let env = root.entry("env").or_insert_with(|| Value::Object(Map::new()));
+    let Some(env) = env.as_object_mut() else {
+        bail!("\"env\" in settings.json is not an object");
+    };
+
+    env.insert(BASE_URL_VAR.to_string(), Value::String(base_url.to_string()));
+
+    let existing_key = env.get(API_KEY_VAR).and_then(|v| v.as_str()).filter(|k| !k.is_empty()).is_some();
+    let kept_api_key = match api_key {
+        Some(key) => {
+            env.insert(API_KEY_VAR.to_string(), Value::String(key.to_string()));
+            false
+        }
+        None if existing_key => true,
+        None => {
+            env.insert(API_KEY_VAR.to_string(), Value::String(PLACEHOLDER_API_KEY.to_string()));
+            false
+        }
+    };
+
+    if let Some(script) = statusline {
+        root.insert(
+            "statusLine".to_string(),
+            serde_json::json!({
+                "type": "command",
+                "command": script.display().to_string(),
+                "padding": 0,
+            }),
+        );
+    }
+
+    Ok(InstallReport {
+        base_url: base_url.to_string(),
+        kept_api_key,
+    })
+}
+
+/// Undo `install`: remove the base URL, the placeholder key and a CCM statusline
+/// (`statusline` or any claude-code-mux statusline.sh). A real API key and unrelated
+/// statusline commands are kept.
+pub fn uninstall(settings: &mut Value, statusline: &Path) -> Result<bool> {
+    let root = as_object(settings)?;
+    let mut changed = false;
+
+    if let Some(env) = root.get_mut("env").and_then(|e| e.as_object_mut()) {
+        changed |= env.remove(BASE_URL_VAR).is_some();
+        if env.get(API_KEY_VAR).and_then(|v| v.as_str()) == Some(PLACEHOLDER_API_KEY) {
+            env.remove(API_KEY_VAR);
+            changed = true;
+        }
+        if env.is_empty() {
+            root.remove("env");
+        }
+    }
+
+    let ccm_statusline = root
+        .get("statusLine")
+        .and_then(|s| s.get("command"))
+        .and_then(|c| c.as_str())
+        .map(|c| {
+            Path::new(c) == statusline || (c.ends_with(STATUSLINE_SCRIPT) && c.contains("claude-code-mux"))
+        })
+        .unwrap_or(false);
+    if ccm_statusline {
+        root.remove("statusLine");
+        changed = true;
+    }
+
+    Ok(changed)
+}
+
+fn as_object(settings: &mut Value) -> Result<&mut Map<String, Value>> {
+    if settings.is_null() {
+        *settings = Value::Object(Map::new());
+    }
+    settings.as_object_mut().context("settings.json is not a JSON object")
+}
+
+/// Read settings.json. A missing or empty file is an empty object; invalid JSON is an
+/// error so we never overwrite a file we couldn't understand.
+pub fn load(path: &Path) -> Result<Value> {
+    match std::fs::read_to_string(path) {
+        Ok(content) if content.trim().is_empty() => Ok(Value::Object(Map::new())),
+        Ok(content) => serde_json::from_str(&content)
+            .with_context(|| format!("{} is not valid JSON; fix or move it first", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Value::Object(Map::new())),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// Back up the existing file (if any), then write settings atomically.
+/// Returns the backup path.
+pub fn save(path: &Path, settings: &Value) -> Result<Option<PathBuf>> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+
+    let backup = if path.exists() {
+        let stamp = chrono::Local::now().format("%Y%m%d%H%M%S").to_string();
+        let mut backup = path.with_extension(format!("json.bak-{}", stamp));
+        let mut n = 1;
+        while backup.exists() {
+            backup = path.with_extension(format!("json.bak-{}-{}", stamp, n));
+            n += 1;
+        }
+        std::fs::copy(path, &backup)
+            .with_context(|| format!("Failed to back up {}", path.display()))?;
+        Some(backup)
+    } else {
+        None
+    };
+
+    let tmp = path.with_extension("json.ccm-tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(settings)? + "\n")
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, path)
+        .with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_install_preserves_other_settings() {
+        let mut settings = json!({"model": "opus", "env": {"FOO": "bar"}});
+        let script = PathBuf::from("/home/u/.claude-code-mux/statusline.sh");
+        let report = install(&mut settings, "http://127.0.0.1:13456", None, Some(&script)).unwrap();
+
+        assert!(!report.kept_api_key);
+        assert_eq!(settings["model"], "opus");
+        assert_eq!(settings["env"]["FOO"], "bar");
+        assert_eq!(settings["env"]["ANTHROPIC_BASE_URL"], "http://127.0.0.1:13456");
+        assert_eq!(settings["env"]["ANTHROPIC_API_KEY"], PLACEHOLDER_API_KEY);
+        assert_eq!(settings["statusLine"]["command"], "/home/u/.claude-code-mux/statusline.sh");
+    }
+
+    #[test]
+    fn test_install_keeps_existing_api_key() {
+        let mut settings = json!({"env": {"ANTHROPIC_API_KEY": "sk-real"}});
+        let report = install(&mut settings, "http://127.0.0.1:13456", None, None).unwrap();
+
+        assert!(report.kept_api_key);
+        assert_eq!(settings["env"]["ANTHROPIC_API_KEY"], "sk-real");
+        assert!(settings.get("statusLine").is_none());
+    }
+
+    #[test]
+    fn test_uninstall_reverts_install() {
+        let mut settings = json!({"model": "opus"});
+        let script = PathBuf::from("/home/u/.claude-code-mux/statusline.sh");
+        install(&mut settings, "http://127.0.0.1:13456", None, Some(&script)).unwrap();
+
+        assert!(uninstall(&mut settings, &script).unwrap());
+        assert_eq!(settings, json!({"model": "opus"}));
+        assert!(!uninstall(&mut settings, &script).unwrap());
+    }
+
+    #[test]
+    fn test_rejects_non_object_env() {
+        let mut settings = json!({"env": "oops"});
+        assert!(install(&mut settings, "http://x", None, None).is_err());
+    }
+
+    #[test]
+    fn test_save_backs_up_and_load_rejects_invalid_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+
+        assert_eq!(load(&path).unwrap(), json!({}));
+        assert!(save(&path, &json!({"a": 1})).unwrap().is_none());
+
+        let backup = save(&path, &json!({"a": 2})).unwrap().unwrap();
+        assert_eq!(load(&backup).unwrap(), json!({"a": 1}));
+        assert_eq!(load(&path).unwrap(), json!({"a": 2}));
+
+        // A second save in the same second must not clobber the first backup
+        let second = save(&path, &json!({"a": 3})).unwrap().unwrap();
+        assert_ne!(second, backup);
+        assert_eq!(load(&backup).unwrap(), json!({"a": 1}));
+
+        std::fs::write(&path, "{not json").unwrap();
+        assert!(load(&path).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+//! `ccm config get/set`: dotted-path reads and edits of the config file that keep
+//! its comments and formatting.
+//!
+//! Paths are dot-separated keys (`router.default`, `server.quota.enabled`). Array
+//! entries are selected by index or by their `name` (`providers.openrouter.enabled`,
+//! `models.0.mappings.1.priority`). Quote segments that contain dots:
+//! `pricing."glm-4.6".input`.
+
+use super::validate::{self, ConfigProblem};
+use super::AppConfig;
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use toml_edit::{Array, ArrayOfTables, DocumentMut, InlineTable, Item, Table, Value};
+
+/// Split a dotted path into segments, honouring double-quoted segments
+pub fn parse_path(path: &str) -> Result<Vec<String>> {
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut was_quoted = false;
+    for c in path.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                was_quoted = true;
+            }
+            '.' if !quoted => {
+                if current.is_empty() && !was_quoted {
+                    bail!("Empty segment in path '{}'", path);
+                }
+                segments.push(std::mem::take(&mut current));
+                was_quoted = false;
+            }
+            c => current.push(c),
+        }
+    }
+    if quoted {
+        bail!("Unterminated quote in path '{}'", path);
+    }
+    if current.is_empty() && !was_quoted {
+        bail!("Empty segment in path '{}'", path);
+    }
+    segments.push(current);
+    Ok(segments)
+}
+
+/// Parse a command-line value as TOML (`42`, `true`, `["a", "b"]`), falling back
+/// to a plain string (`glm-4.6`)
+pub fn parse_value(raw: &str, as_string: bool) -> Value {
+    if as_string {
+        return Value::from(raw);
+    }
+    raw.parse::<Value>().unwrap_or_else(|_| Value::from(raw))
+}
+
+/// A position in the document while walking a path
+enum Cursor<'a> {
+    Table(&'a mut Table),
+    Inline(&'a mut InlineTable),
+    Tables(&'a mut ArrayOfTables),
+    Array(&'a mut Array),
+}
+
+impl<'a> Cursor<'a> {
+    fn from_item(item: &'a mut Item) -> Option<Self> {
+        match item {
+            Item::Table(t) => Some(Cursor::Table(t)),
+            Item::ArrayOfTables(a) => Some(Cursor::Tables(a)),
+            Item::Value(Value::InlineTable(t)) => Some(Cursor::Inline(t)),
+            Item::Value(Value::Array(a)) => Some(Cursor::Array(a)),
+            _ => None,
+        }
+    }
+
+    fn from_value(value: &'a mut Value) -> Option<Self> {
+        match value {
+            Value::InlineTable(t) => Some(Cursor::Inline(t)),
+            Value::Array(a) => Some(Cursor::Array(a)),
+            _ => None,
+        }
+    }
+
+    /// Step into `segment`. Missing keys of standard tables become new tables when `create`.
+    fn descend(self, segment: &str, create: bool) -> Result<Cursor<'a>> {
+        let missing = || anyhow::anyhow!("'{}' not found", segment);
+        let not_container = || anyhow::anyhow!("'{}' is not a table or array", segment);
+        match self {
+            Cursor::Table(t) => {
+                if !t.contains_key(segment) && create {
+                    let mut table = Table::new();
+                    table.set_implicit(true);
+                    t.insert(segment, Item::Table(table));
+                }
+                let item = t.get_mut(segment).ok_or_else(missing)?;
+                Cursor::from_item(item).ok_or_else(not_container)
+            }
+            Cursor::Inline(t) => {
+                if !t.contains_key(segment) && create {
+                    t.insert(segment, Value::InlineTable(InlineTable::new()));
+                }
+                let value = t.get_mut(segment).ok_or_else(missing)?;
+                Cursor::from_value(value).ok_or_else(not_container)
+            }
+            Cursor::Tables(tables) => {
+                let index = element_index(tables.iter().map(|t| t.get("name").and_then(|n| n.as_str())), segment)
+                    .ok_or_else(missing)?;
+                Ok(Cursor::Table(tables.get_mut(index).ok_or_else(missing)?))
+            }
+            Cursor::Array(array) => {
+                let index = element_index(
+                    array.iter().map(|v| v.as_inline_table().and_then(|t| t.get("name")).and_then(|n| n.as_str())),
+                    segment,
+                )
+                .ok_or_else(missing)?;
+                let value = array.get_mut(index).ok_or_else(missing)?;
+                Cursor::from_value(value).ok_or_else(not_container)
+            }
+        }
+    }
+}
+
+/// Index of an array element by position or by its `name`
+fn element_index<'n>(names: impl Iterator<Item = Option<&'n str>>, segment: &str) -> Option<usize> {
+    let names: Vec<Option<&str>> = names.collect();
+    match segment.parse::<usize>() {
+        Ok(index) if index < names.len() => Some(index),
+        _ => names.iter().position(|name| *name == Some(segment)),
+    }
+}
+
+/// Walk to the container holding the last path segment
+fn walk<'a>(doc: &'a mut DocumentMut, segments: &[String], create: bool) -> Result<Cursor<'a>> {
+    let mut cursor = Cursor::Table(doc.as_table_mut());
+    for segment in &segments[..segments.len() - 1] {
+        cursor = cursor.descend(segment, create)?;
+    }
+    Ok(cursor)
+}
+
+/// Read a value: strings are printed bare, anything else as TOML
+pub fn get(doc: &DocumentMut, path: &str) -> Result<String> {
+    let segments = parse_path(path)?;
+    let key = segments.last().expect("parse_path returns at least one segment");
+    let mut doc = doc.clone();
+
+    let item = match walk(&mut doc, &segments, false)? {
+        Cursor::Table(t) => t.get(key).cloned(),
+        Cursor::Inline(t) => t.get(key).cloned().map(Item::Value),
+        Cursor::Tables(tables) => {
+            let names = tables.iter().map(|t| t.get("name").and_then(|n| n.as_str()));
+            element_index(names, key).and_then(|i| tables.get(i)).cloned().map(Item::Table)
+        }
+        Cursor::Array(array) => {
+            let names = array.iter().map(|v| v.as_inline_table().and_then(|t| t.get("name")).and_then(|n| n.as_str()));
+            element_index(names, key).and_then(|i| array.get(i)).cloned().map(Item::Value)
+        }
+    };
+
+    match item.with_context(|| format!("'{}' is not set", path))? {
+        Item::Value(Value::String(s)) => Ok(s.value().clone()),
+        Item::Value(value) => Ok(value.decorated("", "").to_string()),
+        item => {
+            let mut out = DocumentMut::new();
+            out.insert(key, item);
+            Ok(out.to_string().trim_end().to_string())
+        }
+    }
+}
+
+/// Set a value, creating missing tables along the way. An existing value keeps its
+/// trailing comment.
+pub fn set(doc: &mut DocumentMut, path: &str, value: Value) -> Result<()> {
+    let segments = parse_path(path)?;
+    let key = segments.last().expect("parse_path returns at least one segment");
+
+    match walk(doc, &segments, true)? {
+        Cursor::Table(t) => {
+            let value = match t.get(key).and_then(|item| item.as_value()) {
+                Some(old) => value.decorated(old.decor().prefix().cloned().unwrap_or_default(), old.decor().suffix().cloned().unwrap_or_default()),
+                None => value,
+            };
+            if t.get(key).is_some_and(|item| !item.is_value()) {
+                bail!("'{}' is a table; set one of its keys instead", path);
+            }
+            t.insert(key, Item::Value(value));
+        }
+        Cursor::Inline(t) => {
+            let value = match t.get(key) {
+                Some(old) => value.decorated(old.decor().prefix().cloned().unwrap_or_default(), old.decor().suffix().cloned().unwrap_or_default()),
+                None => value,
+            };
+            t.insert(key, value);
+        }
+        Cursor::Tables(_) | Cursor::Array(_) => bail!("'{}' is an array entry; set one of its keys instead", path),
+    }
+    Ok(())
+}
+
+/// Set `path` in the config file. The edit is only written if the result still
+/// loads; remaining validation problems are returned as warnings.
+pub fn set_in_file(config_path: &Path, path: &str, value: Value) -> Result<Vec<ConfigProblem>> {
+    let content = std::fs::read_to_string(config_path)
+        .with_context(|| format!("Failed to read config file: {}", config_path.display()))?;
+    let mut doc: DocumentMut = content
+        .parse()
+        .with_context(|| format!("Failed to parse config file: {}", config_path.display()))?;
+
+    set(&mut doc, path, value)?;
+
+    let updated = doc.to_string();
+    let config: AppConfig = toml::from_str(&updated).with_context(|| format!("Setting {} would make the config invalid", path))?;
+
+    // Write to a temp file first so a failed write can't truncate the config
+    let tmp = config_path.with_extension("toml.tmp");
+    std::fs::write(&tmp, &updated).with_context(|| format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, config_path).with_context(|| format!("Failed to replace {}", config_path.display()))?;
+
+    Ok(validate::validate(&config, Some(&updated)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"[server]
+port = 13456 # default port
+
+[router]
+default = "sonnet"   # main model
+
+[[providers]]
+name = "zai"
+provider_type = "z.ai"
+api_key = "key"
+models = []
+
+[[models]]
+name = "sonnet"
+mappings = [{ priority = 1, provider = "zai", actual_model = "glm-4.6" }]
+
+[pricing."glm-4.6"]
+input = 0.6
+output = 2.2
+"#;
+
+    #[test]
+    fn test_parse_path() {
+        assert_eq!(parse_path("router.default").unwrap(), vec!["router", "default"]);
+        assert_eq!(parse_path("pricing.\"glm-4.6\".input").unwrap(), vec!["pricing", "glm-4.6", "input"]);
+        assert!(parse_path("router..default").is_err());
+        assert!(parse_path("pricing.\"glm").is_err());
+    }
+
+    #[test]
+    fn test_get() {
+        let doc: DocumentMut = CONFIG.parse().unwrap();
+        assert_eq!(get(&doc, "router.default").unwrap(), "sonnet");
+        assert_eq!(get(&doc, "server.port").unwrap(), "13456");
+        assert_eq!(get(&doc, "providers.zai.provider_type").unwrap(), "z.ai");
+        assert_eq!(get(&doc, "models.0.mappings.0.actual_model").unwrap(), "glm-4.6");
+        assert_eq!(get(&doc, "pricing.\"glm-4.6\".output").unwrap(), "2.2");
+        assert!(get(&doc, "router.think").is_err());
+        assert!(get(&doc, "providers.missing.name").is_err());
+    }
+
+    #[test]
+    fn test_set_keeps_comments() {
+        let mut doc: DocumentMut = CONFIG.parse().unwrap();
+        set(&mut doc, "router.default", parse_value("glm-4.6", false)).unwrap();
+        set(&mut doc, "server.quota.enabled", parse_value("false", false)).unwrap();
+        set(&mut doc, "providers.zai.enabled", parse_value("false", false)).unwrap();
+        set(&mut doc, "models.sonnet.mappings.0.priority", parse_value("2", false)).unwrap();
+        set(&mut doc, "router.think", parse_value("4.6", true)).unwrap();
+        assert!(set(&mut doc, "providers.0", parse_value("x", false)).is_err());
+
+        let out = doc.to_string();
+        assert!(out.contains("default = \"glm-4.6\"   # main model"));
+        assert!(out.contains("port = 13456 # default port"));
+        assert!(out.contains("[server.quota]\nenabled = false"));
+        assert!(out.contains("think = \"4.6\""));
+        assert!(out.contains("priority = 2,"));
+
+        let config: AppConfig = toml::from_str(&out).unwrap();
+        assert!(!config.server.quota.enabled);
+        assert!(!config.providers[0].is_enabled());
+    }
+}
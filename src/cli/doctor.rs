@@ -0,0 +1,330 @@
+//! `ccm doctor`: check the local setup and print what to fix.
+//!
+//! - The config file loads and validates
+//! - The server port is free, or held by this CCM instance
+//! - The PID file isn't stale
+//! - OAuth providers have a stored token that isn't expired
+//! - Every enabled provider's base URL answers
+//! - Claude Code's settings point at CCM and run the statusline script
+
+use super::{claude_code, validate, AppConfig};
+use crate::auth::TokenStore;
+use crate::pid;
+use crate::providers::{AuthType, ProviderConfig};
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+const REACHABILITY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// Result of one check
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+    /// What to do about it
+    pub fix: Option<String>,
+}
+
+impl Finding {
+    fn ok(message: impl Into<String>) -> Self {
+        Self { severity: Severity::Ok, message: message.into(), fix: None }
+    }
+
+    fn warning(message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { severity: Severity::Warning, message: message.into(), fix: Some(fix.into()) }
+    }
+
+    fn error(message: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { severity: Severity::Error, message: message.into(), fix: Some(fix.into()) }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let icon = match self.severity {
+            Severity::Ok => "✅",
+            Severity::Warning => "⚠️ ",
+            Severity::Error => "❌",
+        };
+        write!(f, "{} {}", icon, self.message)?;
+        if let Some(ref fix) = self.fix {
+            write!(f, "\n   → {}", fix)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run every check. Checks that need a loaded config are skipped when it doesn't load.
+pub async fn run(config_path: &Path) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let config = match AppConfig::from_file(&config_path.to_path_buf()) {
+        Ok(config) => config,
+        Err(e) => {
+            findings.push(Finding::error(
+                format!("{} doesn't load: {:#}", config_path.display(), e),
+                "Fix the file (`ccm config set` edits it without loading it), then run `ccm doctor` again",
+            ));
+            findings.push(pid_finding(pid::read_pid_info().ok().map(|i| (i.pid, pid::is_process_running(i.pid)))));
+            return findings;
+        }
+    };
+
+    let source = std::fs::read_to_string(config_path).ok();
+    let problems = validate::validate(&config, source.as_deref());
+    if problems.is_empty() {
+        findings.push(Finding::ok(format!("Config {} is valid", config_path.display())));
+    }
+    for problem in problems {
+        findings.push(Finding::error(format!("Config: {}", problem), "Fix the config entry"));
+    }
+
+    let running = pid::read_pid_info().ok().map(|i| (i.pid, pid::is_process_running(i.pid)));
+    findings.push(pid_finding(running));
+    findings.push(port_finding(&config, running).await);
+
+    if let Ok(store) = TokenStore::default() {
+        findings.extend(oauth_findings(&config.providers, &store, chrono::Utc::now()));
+    }
+    findings.extend(reachability_findings(&config.providers).await);
+
+    match claude_code::settings_path().and_then(|path| claude_code::load(&path).map(|s| (path, s))) {
+        Ok((path, settings)) => {
+            let script = crate::paths::state_dir().join("statusline.sh");
+            findings.extend(claude_code_findings(&path, &settings, &config, &script, script.exists()));
+        }
+        Err(e) => findings.push(Finding::warning(
+            format!("Claude Code settings can't be read: {:#}", e),
+            "Fix or remove the file, then run `ccm install-claude-code`",
+        )),
+    }
+    findings
+}
+
+/// PID file state: `running` is the recorded PID and whether it is alive
+fn pid_finding(running: Option<(u32, bool)>) -> Finding {
+    match running {
+        Some((pid, true)) => Finding::ok(format!("CCM is running (PID {})", pid)),
+        Some((pid, false)) => Finding::warning(
+            format!("Stale PID file {} (PID {} is not running)", pid::get_pid_file().display(), pid),
+            "Run `ccm stop` to remove it",
+        ),
+        None => Finding::ok("No PID file (CCM is not running)"),
+    }
+}
+
+async fn port_finding(config: &AppConfig, running: Option<(u32, bool)>) -> Finding {
+    let (host, port) = (config.server.host.as_str(), config.server.port);
+    let owner = pid::probe_health(host, port).await.and_then(|body| body.get("pid").and_then(|p| p.as_u64()));
+    match (owner, running) {
+        (Some(owner), Some((pid, true))) if owner == pid as u64 => {
+            Finding::ok(format!("Port {} is served by this CCM instance", port))
+        }
+        (Some(owner), _) => Finding::error(
+            format!("Port {} is served by another CCM instance (PID {})", port, owner),
+            "Stop it, or give this config another `server.port`",
+        ),
+        (None, _) if std::net::TcpListener::bind((host, port)).is_err() && running.is_none_or(|(_, alive)| !alive) => {
+            Finding::error(
+                format!("Port {} on {} is in use by another program", port, host),
+                format!("Free the port (`lsof -i :{}` shows who holds it) or change `server.port`", port),
+            )
+        }
+        (None, _) => Finding::ok(format!("Port {} is available", port)),
+    }
+}
+
+/// OAuth providers need a stored token; an expired one is refreshed on use, which
+/// fails if the refresh token was revoked too
+fn oauth_findings(providers: &[ProviderConfig], store: &TokenStore, now: chrono::DateTime<chrono::Utc>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for provider in providers.iter().filter(|p| p.is_enabled() && p.auth_type == AuthType::OAuth) {
+        let id = provider.oauth_provider.as_deref().unwrap_or(&provider.name);
+        match store.get(id) {
+            None => findings.push(Finding::error(
+                format!("Provider \"{}\" uses OAuth but has no stored token ({})", provider.name, id),
+                "Log in from the admin UI (`ccm start`, then open http://127.0.0.1:<port>)",
+            )),
+            Some(token) if token.expires_at <= now => findings.push(Finding::warning(
+                format!("OAuth token for \"{}\" expired at {}", provider.name, token.expires_at.format("%Y-%m-%d %H:%M UTC")),
+                "It is refreshed on the next request; if that fails, log in again from the admin UI",
+            )),
+            Some(token) => findings.push(Finding::ok(format!(
+                "OAuth token for \"{}\" is valid until {}",
+                provider.name,
+                token.expires_at.format("%Y-%m-%d %H:%M UTC")
+            ))),
+        }
+    }
+    findings
+}
+
+/// Base URL a provider calls, when known without building it
+fn provider_url(provider: &ProviderConfig) -> Option<String> {
+    if let Some(ref url) = provider.base_url {
+        return Some(url.clone());
+    }
+    let default = match provider.provider_type.as_str() {
+        "openai" => "https://api.openai.com/v1",
+        "openrouter" => "https://openrouter.ai/api/v1",
+        "anthropic" => "https://api.anthropic.com",
+        "gemini" => "https://generativelanguage.googleapis.com",
+        "ollama" => crate::providers::ollama::DEFAULT_OLLAMA_BASE_URL,
+        _ => return None,
+    };
+    Some(default.to_string())
+}
+
+/// Any HTTP answer (even 401 or 404) means the host is reachable
+async fn reachability_findings(providers: &[ProviderConfig]) -> Vec<Finding> {
+    let Ok(client) = reqwest::Client::builder().timeout(REACHABILITY_TIMEOUT).build() else {
+        return Vec::new();
+    };
+    let checks = providers
+        .iter()
+        .filter(|p| p.is_enabled())
+        .filter_map(|p| Some((p, provider_url(p)?)))
+        .map(|(provider, url)| {
+            let client = client.clone();
+            async move {
+                match client.get(&url).send().await {
+                    Ok(_) => Finding::ok(format!("Provider \"{}\" is reachable at {}", provider.name, url)),
+                    Err(e) => Finding::error(
+                        format!("Provider \"{}\" is unreachable at {}: {}", provider.name, url, e),
+                        "Check `base_url`, your network, proxy settings, or that the local server is running",
+                    ),
+                }
+            }
+        });
+    futures::future::join_all(checks).await
+}
+
+/// Claude Code's settings.json should point at this CCM and run its statusline
+fn claude_code_findings(
+    settings_path: &Path,
+    settings: &Value,
+    config: &AppConfig,
+    script: &Path,
+    script_exists: bool,
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let expected = format!("http://127.0.0.1:{}", config.server.port);
+    match settings["env"]["ANTHROPIC_BASE_URL"].as_str() {
+        Some(url) if url.trim_end_matches('/').ends_with(&format!(":{}", config.server.port)) => {
+            findings.push(Finding::ok(format!("Claude Code uses CCM ({})", url)))
+        }
+        Some(url) => findings.push(Finding::warning(
+            format!("Claude Code's ANTHROPIC_BASE_URL is {}, not {}", url, expected),
+            "Run `ccm install-claude-code` to update it",
+        )),
+        None => findings.push(Finding::warning(
+            format!("{} doesn't set ANTHROPIC_BASE_URL, so Claude Code doesn't use CCM", settings_path.display()),
+            "Run `ccm install-claude-code` (or export ANTHROPIC_BASE_URL yourself)",
+        )),
+    }
+
+    let command = settings["statusLine"]["command"].as_str();
+    if !script_exists {
+        findings.push(Finding::warning(
+            format!("Statusline script is not installed ({})", script.display()),
+            "Run `ccm install-statusline`",
+        ));
+    } else if command.is_some_and(|c| Path::new(c) == script) {
+        findings.push(Finding::ok("Statusline is installed"));
+    } else {
+        findings.push(Finding::warning(
+            "Claude Code doesn't run the CCM statusline script",
+            "Run `ccm install-claude-code` to add it to statusLine",
+        ));
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::token_store::OAuthToken;
+    use secrecy::SecretString;
+
+    fn config() -> AppConfig {
+        toml::from_str(
+            r#"
+            [server]
+            port = 13456
+
+            [router]
+            default = "m"
+
+            [[providers]]
+            name = "claude-max"
+            provider_type = "anthropic"
+            auth_type = "oauth"
+            models = []
+
+            [[providers]]
+            name = "gemini-cli"
+            provider_type = "gemini"
+            auth_type = "oauth"
+            oauth_provider = "gemini"
+            models = []
+
+            [[models]]
+            name = "m"
+            [[models.mappings]]
+            provider = "claude-max"
+            actual_model = "m"
+            priority = 1
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_oauth_findings() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = TokenStore::new(dir.path().join("tokens.json")).unwrap();
+        let now = chrono::Utc::now();
+        store
+            .save(OAuthToken {
+                provider_id: "gemini".to_string(),
+                access_token: SecretString::new("a".to_string()),
+                refresh_token: SecretString::new("r".to_string()),
+                expires_at: now - chrono::Duration::hours(1),
+                enterprise_url: None,
+                project_id: None,
+            })
+            .unwrap();
+
+        let findings = oauth_findings(&config().providers, &store, now);
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert!(findings[0].message.contains("claude-max"));
+        assert_eq!(findings[1].severity, Severity::Warning);
+        assert!(findings[1].message.contains("expired"));
+    }
+
+    #[test]
+    fn test_claude_code_findings() {
+        let config = config();
+        let script = Path::new("/state/statusline.sh");
+        let settings = serde_json::json!({
+            "env": {"ANTHROPIC_BASE_URL": "http://127.0.0.1:13456"},
+            "statusLine": {"type": "command", "command": "/state/statusline.sh"}
+        });
+        let findings = claude_code_findings(Path::new("settings.json"), &settings, &config, script, true);
+        assert!(findings.iter().all(|f| f.severity == Severity::Ok), "{:?}", findings);
+
+        let settings = serde_json::json!({"env": {"ANTHROPIC_BASE_URL": "http://127.0.0.1:3456"}});
+        let findings = claude_code_findings(Path::new("settings.json"), &settings, &config, script, false);
+        assert!(findings.iter().all(|f| f.severity == Severity::Warning), "{:?}", findings);
+        assert!(findings[1].fix.as_deref().unwrap().contains("install-statusline"));
+    }
+}
@@ -0,0 +1,533 @@
+//! Configuration from environment variables, for running without a config file
+//! (e.g. in a container with a read-only filesystem).
+//!
+//! - `CCM_CONFIG_JSON` - the whole config as JSON (same shape as config.toml)
+//! - `CCM_<KEY>__<KEY>...` - any config path, `__` between keys, e.g. `CCM_SERVER__PORT`,
+//!   `CCM_SERVER__TIMEOUTS__API_TIMEOUT_MS`; providers and models are selected by index
+//!   or by name (`CCM_PROVIDERS__OPENROUTER__API_KEY`)
+//! - `CCM_SERVER_<FIELD>` / `CCM_ROUTER_<FIELD>` - e.g. `CCM_SERVER_PORT`, `CCM_ROUTER_DEFAULT`
+//! - `CCM_PROVIDERS_<N>_<FIELD>` - e.g. `CCM_PROVIDERS_0_NAME`, `CCM_PROVIDERS_0_API_KEY`
+//! - `CCM_MODELS_<N>_NAME`, `CCM_MODELS_<N>_MAPPINGS_<M>_<FIELD>`
+//!
+//! Individual variables are applied on top of the config file or JSON blob. Strings in
+//! the file or blob may also reference the environment with `${VAR}` (see [`interpolate`]).
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+/// Environment variable holding a complete JSON config
+pub const CONFIG_JSON_VAR: &str = "CCM_CONFIG_JSON";
+
+const PREFIX: &str = "CCM_";
+
+/// Collect CCM_* variables from the process environment
+pub fn config_vars() -> Vec<(String, String)> {
+    let mut vars: Vec<(String, String)> = std::env::vars().filter(|(k, _)| k.starts_with(PREFIX)).collect();
+    vars.sort();
+    vars
+}
+
+/// Parse the CCM_CONFIG_JSON blob, if present
+pub fn config_json(vars: &[(String, String)]) -> Result<Option<Value>> {
+    let Some((_, blob)) = vars.iter().find(|(k, _)| k == CONFIG_JSON_VAR) else {
+        return Ok(None);
+    };
+    let value = serde_json::from_str(blob).with_context(|| format!("Failed to parse {}", CONFIG_JSON_VAR))?;
+    Ok(Some(value))
+}
+
+/// True if any variable would contribute configuration
+pub fn has_config(vars: &[(String, String)]) -> bool {
+    vars.iter().any(|(k, _)| k == CONFIG_JSON_VAR || config_path(k).is_some())
+}
+
+/// Apply CCM_<SECTION>_... overrides to a config tree. A value takes the type of the
+/// value it replaces; new values are typed by field.
+pub fn apply_overrides(config: &mut Value, vars: &[(String, String)]) -> Result<()> {
+    for (key, raw) in vars {
+        let Some(path) = config_path(key) else {
+            continue;
+        };
+        let field = match path.last() {
+            Some(Segment::Key(field)) => field.clone(),
+            _ => continue,
+        };
+        set_path(config, &path, |existing| typed_value(existing, &field, raw))
+            .with_context(|| format!("Invalid configuration variable {}", key))?;
+    }
+
+    // Providers created from env vars don't need to list models
+    if let Some(providers) = config.get_mut("providers").and_then(|p| p.as_array_mut()) {
+        for provider in providers.iter_mut().filter_map(|p| p.as_object_mut()) {
+            provider.entry("models").or_insert_with(|| Value::Array(Vec::new()));
+        }
+    }
+    Ok(())
+}
+
+/// Replace `${VAR}` and `${VAR:-default}` in every string of a config tree with
+/// `lookup(VAR)`. Only upper-case names are references, so prompt rule capture
+/// groups like `${name}` are left alone; `$${` is a literal `${`. A variable that
+/// isn't set and has no default is an error.
+pub fn interpolate(config: &mut Value, lookup: &impl Fn(&str) -> Option<String>) -> Result<()> {
+    interpolate_at(config, "", lookup)
+}
+
+fn interpolate_at(value: &mut Value, path: &str, lookup: &impl Fn(&str) -> Option<String>) -> Result<()> {
+    match value {
+        Value::String(s) if s.contains("${") => {
+            *s = interpolate_str(s, lookup).with_context(|| format!("Invalid configuration value at {}", path))?;
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                interpolate_at(item, &format!("{}[{}]", path, i), lookup)?;
+            }
+        }
+        Value::Object(map) => {
+            for (key, item) in map.iter_mut() {
+                let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                interpolate_at(item, &path, lookup)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn interpolate_str(s: &str, lookup: &impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            // `$${` escapes the reference
+            out.push_str(&rest[..start - 1]);
+            out.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        out.push_str(&rest[..start]);
+        let body_start = start + 2;
+        let Some(len) = rest[body_start..].find('}') else {
+            anyhow::bail!("unterminated ${{ in \"{}\"", s);
+        };
+        let body = &rest[body_start..body_start + len];
+        let (name, default) = match body.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (body, None),
+        };
+        let is_var = name.starts_with(|c: char| c.is_ascii_uppercase() || c == '_')
+            && name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+        if is_var {
+            match lookup(name).or_else(|| default.map(String::from)) {
+                Some(value) => out.push_str(&value),
+                None => anyhow::bail!("environment variable {} is not set (use ${{{}:-}} to allow it)", name, name),
+            }
+        } else {
+            out.push_str(&rest[start..body_start + len + 1]);
+        }
+        rest = &rest[body_start + len + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Undo [`interpolate`] for a config tree edited in its resolved form (the admin UI):
+/// a string that still equals what the reference at the same place in `raw` resolves
+/// to gets the reference back, so saving doesn't write out the environment's values.
+/// List entries with a `name` (providers, models) are matched by name, others by index.
+pub fn restore_references(value: &mut Value, raw: &Value, lookup: &impl Fn(&str) -> Option<String>) {
+    match (value, raw) {
+        (Value::String(s), Value::String(reference))
+            if reference.contains("${") && interpolate_str(reference, lookup).is_ok_and(|resolved| resolved == *s) =>
+        {
+            *s = reference.clone();
+        }
+        (Value::Array(items), Value::Array(raw_items)) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                let raw_item = match item.get("name").and_then(Value::as_str) {
+                    Some(name) => raw_items.iter().find(|raw| raw.get("name").and_then(Value::as_str) == Some(name)),
+                    None => raw_items.get(i),
+                };
+                if let Some(raw_item) = raw_item {
+                    restore_references(item, raw_item, lookup);
+                }
+            }
+        }
+        (Value::Object(map), Value::Object(raw_map)) => {
+            for (key, item) in map.iter_mut() {
+                if let Some(raw_item) = raw_map.get(key) {
+                    restore_references(item, raw_item, lookup);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+    /// Entry of a providers/models list by name
+    Name(String),
+}
+
+/// Lists whose entries `CCM_<LIST>__<NAME>__...` selects by `name`
+const NAMED_LISTS: &[&str] = &["providers", "models"];
+
+/// Map a variable name to a config path, or None if it isn't a config variable
+fn config_path(key: &str) -> Option<Vec<Segment>> {
+    let rest = key.strip_prefix(PREFIX)?;
+    if rest.contains("__") {
+        return nested_path(rest);
+    }
+    let tokens: Vec<&str> = rest.split('_').collect();
+    let field = |tokens: &[&str]| -> Option<Segment> {
+        if tokens.is_empty() || tokens.iter().any(|t| t.is_empty()) {
+            return None;
+        }
+        Some(Segment::Key(tokens.join("_").to_lowercase()))
+    };
+    let index = |token: &str| token.parse::<usize>().ok().map(Segment::Index);
+
+    match tokens.as_slice() {
+        ["SERVER", rest @ ..] => Some(vec![Segment::Key("server".into()), field(rest)?]),
+        ["ROUTER", rest @ ..] => Some(vec![Segment::Key("router".into()), field(rest)?]),
+        ["PROVIDERS", n, rest @ ..] => Some(vec![Segment::Key("providers".into()), index(n)?, field(rest)?]),
+        ["MODELS", n, "MAPPINGS", m, rest @ ..] => Some(vec![
+            Segment::Key("models".into()),
+            index(n)?,
+            Segment::Key("mappings".into()),
+            index(m)?,
+            field(rest)?,
+        ]),
+        ["MODELS", n, rest @ ..] => Some(vec![Segment::Key("models".into()), index(n)?, field(rest)?]),
+        _ => None,
+    }
+}
+
+/// `SERVER__TIMEOUTS__API_TIMEOUT_MS` style path: keys separated by `__`, numbers
+/// index lists, and other segments after `PROVIDERS`/`MODELS` select by name
+fn nested_path(rest: &str) -> Option<Vec<Segment>> {
+    let mut path = Vec::new();
+    for token in rest.split("__") {
+        if token.is_empty() {
+            return None;
+        }
+        let in_named_list = matches!(path.last(), Some(Segment::Key(key)) if NAMED_LISTS.contains(&key.as_str()));
+        let segment = match token.parse::<usize>() {
+            Ok(index) if !path.is_empty() => Segment::Index(index),
+            _ if in_named_list => Segment::Name(token.to_lowercase()),
+            _ => Segment::Key(token.to_lowercase()),
+        };
+        path.push(segment);
+    }
+    matches!(path.last(), Some(Segment::Key(_))).then_some(path)
+}
+
+/// Whether a list entry's name matches a name from a variable, which can't hold
+/// `-` or `.` (`Z_AI` selects "z.ai")
+fn name_matches(entry: &Value, name: &str) -> bool {
+    let normalize = |s: &str| -> String {
+        s.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' }).collect()
+    };
+    entry.get("name").and_then(|n| n.as_str()).is_some_and(|n| normalize(n) == normalize(name))
+}
+
+/// Convert a raw string to the JSON type of the value it replaces, or for a new
+/// value, the type the field expects
+fn typed_value(existing: Option<&Value>, field: &str, raw: &str) -> Value {
+    let string = || Value::String(raw.to_string());
+    match existing {
+        Some(Value::String(_)) => return string(),
+        Some(Value::Number(_)) => return parse_number(raw).unwrap_or_else(string),
+        Some(Value::Bool(_)) => return parse_bool(raw).map(Value::Bool).unwrap_or_else(string),
+        Some(Value::Array(_)) => return list_value(raw),
+        _ => {}
+    }
+    match field {
+        "port" | "priority" | "suggest_max_tokens" | "long_context_threshold" | "websearch_summarizer_threshold" => {
+            parse_number(raw).unwrap_or_else(string)
+        }
+        "enabled" | "strip_match" | "inject_continuation_prompt" | "force_non_streaming" | "force_streaming"
+        | "prompt_tools" | "inject_identity" => parse_bool(raw).map(Value::Bool).unwrap_or_else(string),
+        "models" => list_value(raw),
+        // Durations, limits and amounts
+        _ if NUMERIC_SUFFIXES.iter().any(|suffix| field.ends_with(suffix)) => parse_number(raw).unwrap_or_else(string),
+        _ => match raw.trim() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => string(),
+        },
+    }
+}
+
+/// Field name endings of numeric settings (`api_timeout_ms`, `interval_secs`, `daily_usd`, ...)
+const NUMERIC_SUFFIXES: &[&str] =
+    &["_ms", "_secs", "_seconds", "_minutes", "_hours", "_tokens", "_threshold", "_usd", "_percent", "_samples", "attempts"];
+
+fn parse_number(raw: &str) -> Option<Value> {
+    let raw = raw.trim();
+    raw.parse::<i64>()
+        .map(Value::from)
+        .ok()
+        .or_else(|| raw.parse::<f64>().ok().and_then(serde_json::Number::from_f64).map(Value::Number))
+}
+
+fn parse_bool(raw: &str) -> Option<bool> {
+    match raw.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" => Some(true),
+        "false" | "0" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// A TOML array (`["a", 1]`), or a comma-separated list of strings
+fn list_value(raw: &str) -> Value {
+    if raw.trim().starts_with('[') {
+        if let Ok(value) = format!("v = {}", raw).parse::<toml::Table>() {
+            if let Ok(list) = serde_json::to_value(&value["v"]) {
+                return list;
+            }
+        }
+    }
+    Value::Array(
+        raw.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| Value::String(s.to_string()))
+            .collect(),
+    )
+}
+
+fn set_path(root: &mut Value, path: &[Segment], value: impl FnOnce(Option<&Value>) -> Value) -> Result<()> {
+    let mut current = root;
+    for (i, segment) in path.iter().enumerate() {
+        let last = i == path.len() - 1;
+        match segment {
+            Segment::Key(key) => {
+                if current.is_null() {
+                    *current = Value::Object(Default::default());
+                }
+                let obj = current
+                    .as_object_mut()
+                    .with_context(|| format!("'{}' is not a table", key))?;
+                if last {
+                    let value = value(obj.get(key));
+                    obj.insert(key.clone(), value);
+                    return Ok(());
+                }
+                current = obj.entry(key.clone()).or_insert(Value::Null);
+            }
+            Segment::Index(index) => {
+                if current.is_null() {
+                    *current = Value::Array(Vec::new());
+                }
+                let arr = current.as_array_mut().context("expected an array")?;
+                while arr.len() <= *index {
+                    arr.push(Value::Object(Default::default()));
+                }
+                current = &mut arr[*index];
+            }
+            Segment::Name(name) => {
+                if current.is_null() {
+                    *current = Value::Array(Vec::new());
+                }
+                let arr = current.as_array_mut().context("expected an array")?;
+                let index = match arr.iter().position(|entry| name_matches(entry, name)) {
+                    Some(index) => index,
+                    None => {
+                        arr.push(serde_json::json!({ "name": name }));
+                        arr.len() - 1
+                    }
+                };
+                current = &mut arr[index];
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_config_path() {
+        assert_eq!(
+            config_path("CCM_SERVER_LOG_LEVEL"),
+            Some(vec![Segment::Key("server".into()), Segment::Key("log_level".into())])
+        );
+        assert_eq!(
+            config_path("CCM_MODELS_1_MAPPINGS_0_ACTUAL_MODEL"),
+            Some(vec![
+                Segment::Key("models".into()),
+                Segment::Index(1),
+                Segment::Key("mappings".into()),
+                Segment::Index(0),
+                Segment::Key("actual_model".into()),
+            ])
+        );
+        assert_eq!(config_path("CCM_PROVIDERS_X_NAME"), None);
+        assert_eq!(config_path("CCM_SERVER_"), None);
+        assert_eq!(config_path("CCM_CONFIG_JSON"), None);
+        assert_eq!(config_path("CCM_STATE_DIR"), None);
+    }
+
+    #[test]
+    fn test_apply_overrides() {
+        let mut config = json!({"server": {"port": 13456}, "router": {"default": "a"}});
+        let env = vars(&[
+            ("CCM_SERVER_PORT", "8080"),
+            ("CCM_ROUTER_DEFAULT", "glm-4.6"),
+            ("CCM_PROVIDERS_0_NAME", "zai"),
+            ("CCM_PROVIDERS_0_PROVIDER_TYPE", "z.ai"),
+            ("CCM_PROVIDERS_0_API_KEY", "12345"),
+            ("CCM_PROVIDERS_0_ENABLED", "true"),
+            ("CCM_MODELS_0_NAME", "glm-4.6"),
+            ("CCM_MODELS_0_MAPPINGS_0_PROVIDER", "zai"),
+            ("CCM_MODELS_0_MAPPINGS_0_ACTUAL_MODEL", "glm-4.6"),
+            ("CCM_MODELS_0_MAPPINGS_0_PRIORITY", "1"),
+            ("CCM_UNRELATED", "x"),
+        ]);
+        apply_overrides(&mut config, &env).unwrap();
+
+        assert_eq!(config["server"]["port"], json!(8080));
+        assert_eq!(config["router"]["default"], json!("glm-4.6"));
+        assert_eq!(config["providers"][0]["api_key"], json!("12345"));
+        assert_eq!(config["providers"][0]["enabled"], json!(true));
+        assert_eq!(config["providers"][0]["models"], json!([]));
+        assert_eq!(config["models"][0]["mappings"][0]["priority"], json!(1));
+
+        let parsed: crate::cli::AppConfig = serde_json::from_value(config).unwrap();
+        assert_eq!(parsed.server.port, 8080);
+        assert_eq!(parsed.providers[0].provider_type, "z.ai");
+        assert_eq!(parsed.models[0].mappings[0].actual_model, "glm-4.6");
+    }
+
+    #[test]
+    fn test_nested_overrides() {
+        assert_eq!(
+            config_path("CCM_SERVER__TIMEOUTS__API_TIMEOUT_MS"),
+            Some(vec![
+                Segment::Key("server".into()),
+                Segment::Key("timeouts".into()),
+                Segment::Key("api_timeout_ms".into()),
+            ])
+        );
+        assert_eq!(config_path("CCM_PROVIDERS__OPENROUTER"), None);
+        assert_eq!(config_path("CCM_SERVER____PORT"), None);
+
+        let mut config = json!({
+            "server": {"port": 13456, "host": "127.0.0.1"},
+            "router": {"default": "a"},
+            "providers": [
+                {"name": "z.ai", "provider_type": "z.ai", "api_key": "old", "models": ["glm-4.6"]},
+                {"name": "openrouter", "provider_type": "openrouter", "api_key": "old", "enabled": true}
+            ]
+        });
+        let env = vars(&[
+            ("CCM_SERVER__PORT", "8080"),
+            ("CCM_SERVER__TIMEOUTS__API_TIMEOUT_MS", "60000"),
+            ("CCM_ROUTER__DEFAULT", "4.6"),
+            ("CCM_ROUTER__THINK_KEYWORDS__ENABLED", "false"),
+            ("CCM_PROVIDERS__Z_AI__API_KEY", "12345"),
+            ("CCM_PROVIDERS__Z_AI__MODELS", "glm-4.6, glm-4.5"),
+            ("CCM_PROVIDERS__OPENROUTER__ENABLED", "no"),
+            ("CCM_PROVIDERS__1__HEADERS__X_TITLE", "ccm"),
+            ("CCM_PROVIDERS__OLLAMA__PROVIDER_TYPE", "ollama"),
+            ("CCM_MODELS__FAST__MAPPINGS__0__PROVIDER", "ollama"),
+            ("CCM_MODELS__FAST__MAPPINGS__0__ACTUAL_MODEL", "llama3.2"),
+            ("CCM_MODELS__FAST__MAPPINGS__0__PRIORITY", "1"),
+        ]);
+        apply_overrides(&mut config, &env).unwrap();
+
+        // Replaced values keep their type; new ones are typed by field
+        assert_eq!(config["server"]["port"], json!(8080));
+        assert_eq!(config["server"]["timeouts"]["api_timeout_ms"], json!(60000));
+        assert_eq!(config["router"]["default"], json!("4.6"));
+        assert_eq!(config["router"]["think_keywords"]["enabled"], json!(false));
+        // Providers and models by (normalized) name, created when missing
+        assert_eq!(config["providers"][0]["api_key"], json!("12345"));
+        assert_eq!(config["providers"][0]["models"], json!(["glm-4.6", "glm-4.5"]));
+        assert_eq!(config["providers"][1]["enabled"], json!(false));
+        assert_eq!(config["providers"][1]["headers"]["x_title"], json!("ccm"));
+        assert_eq!(config["providers"][2], json!({"name": "ollama", "provider_type": "ollama", "models": []}));
+        assert_eq!(config["models"][0]["name"], json!("fast"));
+
+        let parsed: crate::cli::AppConfig = serde_json::from_value(config).unwrap();
+        assert_eq!(parsed.server.timeouts.api_timeout_ms, 60000);
+        assert_eq!(parsed.models[0].mappings[0].actual_model, "llama3.2");
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let lookup = |name: &str| match name {
+            "OPENAI_API_KEY" => Some("sk-live".to_string()),
+            "HOST" => Some("10.0.0.2".to_string()),
+            _ => None,
+        };
+        let mut config = json!({
+            "server": {"host": "${HOST}", "port": 13456},
+            "providers": [{"api_key": "${OPENAI_API_KEY}", "base_url": "http://${HOST}:${PORT:-11434}/v1"}],
+            "router": {"prompt_rules": [{"pattern": "(?P<name>\\w+)", "model": "prefix-${name}"}]},
+            "note": "$${HOST} and $PLAIN"
+        });
+        interpolate(&mut config, &lookup).unwrap();
+        assert_eq!(config["server"]["host"], json!("10.0.0.2"));
+        assert_eq!(config["providers"][0]["api_key"], json!("sk-live"));
+        assert_eq!(config["providers"][0]["base_url"], json!("http://10.0.0.2:11434/v1"));
+        // Capture group references and escapes are left alone
+        assert_eq!(config["router"]["prompt_rules"][0]["model"], json!("prefix-${name}"));
+        assert_eq!(config["note"], json!("${HOST} and $PLAIN"));
+
+        let err = interpolate(&mut json!({"providers": [{"api_key": "${MISSING}"}]}), &lookup).unwrap_err();
+        assert!(format!("{:#}", err).contains("providers[0].api_key: environment variable MISSING is not set"));
+        assert!(interpolate(&mut json!({"a": "${OPEN"}), &lookup).is_err());
+    }
+
+    #[test]
+    fn test_restore_references() {
+        let lookup = |name: &str| (name == "HOST").then(|| "10.0.0.2".to_string());
+        let raw = json!({
+            "router": {"default": "${DEFAULT_MODEL:-glm-4.6}"},
+            "providers": [
+                {"name": "local", "base_url": "http://${HOST}:11434/v1", "api_key": "${LOCAL_KEY:-}"},
+                {"name": "zai", "base_url": "https://api.z.ai"}
+            ],
+            "models": [{"name": "fast", "mappings": [{"actual_model": "${FAST_MODEL:-glm-4.5-air}"}]}],
+            "note": "$${HOST}"
+        });
+        // Saved from the admin UI: reordered, one reference edited away
+        let mut saved = json!({
+            "router": {"default": "glm-4.6"},
+            "providers": [
+                {"name": "zai", "base_url": "https://api.z.ai"},
+                {"name": "local", "base_url": "http://10.0.0.2:11434/v1", "api_key": "ollama"}
+            ],
+            "models": [{"name": "fast", "mappings": [{"actual_model": "glm-4.5-air"}]}],
+            "note": "${HOST}"
+        });
+        restore_references(&mut saved, &raw, &lookup);
+        assert_eq!(saved["router"]["default"], json!("${DEFAULT_MODEL:-glm-4.6}"));
+        assert_eq!(saved["providers"][1]["base_url"], json!("http://${HOST}:11434/v1"));
+        // An edited value is saved as entered
+        assert_eq!(saved["providers"][1]["api_key"], json!("ollama"));
+        assert_eq!(saved["models"][0]["mappings"][0]["actual_model"], json!("${FAST_MODEL:-glm-4.5-air}"));
+        assert_eq!(saved["note"], json!("$${HOST}"));
+    }
+
+    #[test]
+    fn test_config_json_blob() {
+        let env = vars(&[(CONFIG_JSON_VAR, r#"{"router": {"default": "m"}}"#)]);
+        assert!(has_config(&env));
+        assert_eq!(config_json(&env).unwrap(), Some(json!({"router": {"default": "m"}})));
+        assert!(config_json(&vars(&[(CONFIG_JSON_VAR, "{")])).is_err());
+        assert!(!has_config(&vars(&[("CCM_STATE_DIR", "/tmp")])));
+    }
+}
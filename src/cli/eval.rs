@@ -0,0 +1,267 @@
+//! `ccm eval`: run a suite of prompts with assertions against one or more models
+//! through the running mux, and print a scoreboard.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Per-request timeout, generous enough for slow reasoning models
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Eval suite file (TOML)
+#[derive(Debug, Deserialize)]
+pub struct Suite {
+    /// Models to evaluate when none are given on the command line
+    #[serde(default)]
+    pub models: Vec<String>,
+    pub cases: Vec<Case>,
+}
+
+/// One prompt and what its answer must satisfy
+#[derive(Debug, Deserialize)]
+pub struct Case {
+    pub name: String,
+    pub prompt: String,
+    pub system: Option<String>,
+    #[serde(default = "default_max_tokens")]
+    pub max_tokens: u32,
+    /// Anthropic tool definitions offered to the model
+    #[serde(default)]
+    pub tools: Vec<Value>,
+    #[serde(default)]
+    pub expect: Expect,
+}
+
+fn default_max_tokens() -> u32 {
+    1024
+}
+
+/// Assertions on a case's response. All given assertions must hold.
+#[derive(Debug, Default, Deserialize)]
+pub struct Expect {
+    /// Regex that must match the response text
+    pub regex: Option<String>,
+    /// Tool the model must call
+    pub tool: Option<String>,
+    /// Upper bound on end-to-end latency
+    pub max_latency_ms: Option<u64>,
+}
+
+/// Outcome of one case against one model
+#[derive(Debug)]
+pub struct CaseResult {
+    pub model: String,
+    pub latency: Duration,
+    /// Failed assertions (or the request error); empty = passed
+    pub failures: Vec<String>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Load a suite and compile-check its regexes
+pub fn load(path: &Path) -> Result<Suite> {
+    let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read suite {}", path.display()))?;
+    let suite: Suite = toml::from_str(&content).with_context(|| format!("Invalid suite {}", path.display()))?;
+    for case in &suite.cases {
+        if let Some(ref pattern) = case.expect.regex {
+            Regex::new(pattern).with_context(|| format!("Invalid regex in case '{}'", case.name))?;
+        }
+    }
+    Ok(suite)
+}
+
+/// Run every case against every model. Requests carry `X-CCM-Bypass` so each model
+/// is served by its own `[[models]]` mappings rather than re-routed. `api_key` is
+/// sent as `x-api-key` when the mux requires one (`[server.auth]`).
+pub async fn run(suite: &Suite, models: &[String], base_url: &str, api_key: Option<&str>) -> Result<Vec<CaseResult>> {
+    let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+    let url = format!("{}/v1/messages", base_url.trim_end_matches('/'));
+    let mut results = Vec::new();
+
+    for model in models {
+        for case in &suite.cases {
+            let start = Instant::now();
+            let mut request = client
+                .post(&url)
+                .header("x-ccm-bypass", "true")
+                .header("anthropic-version", "2023-06-01");
+            if let Some(api_key) = api_key {
+                request = request.header("x-api-key", api_key);
+            }
+            let response = request.json(&request_body(case, model)).send().await;
+            let body = match response {
+                Ok(response) => {
+                    let status = response.status();
+                    let body: Value = response.json().await.unwrap_or(Value::Null);
+                    if status.is_success() {
+                        Ok(body)
+                    } else {
+                        let message = body["error"]["message"].as_str().unwrap_or("no error message");
+                        Err(format!("HTTP {}: {}", status.as_u16(), message))
+                    }
+                }
+                Err(e) => Err(format!("request failed: {}", e)),
+            };
+            let latency = start.elapsed();
+
+            let failures = match body {
+                Ok(body) => check(case, &body, latency),
+                Err(e) => vec![e],
+            };
+            let mark = if failures.is_empty() { "✅" } else { "❌" };
+            println!("{} {} / {} ({}ms)", mark, model, case.name, latency.as_millis());
+            for failure in &failures {
+                println!("     {}", failure);
+            }
+            results.push(CaseResult { model: model.clone(), latency, failures });
+        }
+    }
+    Ok(results)
+}
+
+fn request_body(case: &Case, model: &str) -> Value {
+    let mut body = json!({
+        "model": model,
+        "max_tokens": case.max_tokens,
+        "messages": [{"role": "user", "content": case.prompt}],
+    });
+    if let Some(ref system) = case.system {
+        body["system"] = json!(system);
+    }
+    if !case.tools.is_empty() {
+        body["tools"] = json!(case.tools);
+    }
+    body
+}
+
+/// Failed assertions for an Anthropic Messages response
+pub fn check(case: &Case, response: &Value, latency: Duration) -> Vec<String> {
+    let blocks = response["content"].as_array().cloned().unwrap_or_default();
+    let text: String = blocks.iter().filter_map(|b| b["text"].as_str()).collect::<Vec<_>>().join("\n");
+    let tools: Vec<&str> = blocks
+        .iter()
+        .filter(|b| b["type"] == "tool_use")
+        .filter_map(|b| b["name"].as_str())
+        .collect();
+
+    let mut failures = Vec::new();
+    if let Some(ref pattern) = case.expect.regex {
+        // Validated in load()
+        if !Regex::new(pattern).is_ok_and(|re| re.is_match(&text)) {
+            failures.push(format!("text does not match /{}/", pattern));
+        }
+    }
+    if let Some(ref tool) = case.expect.tool {
+        if !tools.iter().any(|t| t == tool) {
+            let called = if tools.is_empty() { "none".to_string() } else { tools.join(", ") };
+            failures.push(format!("tool '{}' not called (called: {})", tool, called));
+        }
+    }
+    if let Some(max) = case.expect.max_latency_ms {
+        if latency.as_millis() > max as u128 {
+            failures.push(format!("latency {}ms > {}ms", latency.as_millis(), max));
+        }
+    }
+    failures
+}
+
+/// Per-model pass count and latency, best model first
+pub fn scoreboard(results: &[CaseResult]) -> String {
+    let mut models: Vec<&str> = Vec::new();
+    for result in results {
+        if !models.contains(&result.model.as_str()) {
+            models.push(&result.model);
+        }
+    }
+
+    let mut rows: Vec<(&str, usize, usize, u128)> = models
+        .into_iter()
+        .map(|model| {
+            let runs: Vec<&CaseResult> = results.iter().filter(|r| r.model == model).collect();
+            let passed = runs.iter().filter(|r| r.passed()).count();
+            let avg_ms = runs.iter().map(|r| r.latency.as_millis()).sum::<u128>() / runs.len().max(1) as u128;
+            (model, passed, runs.len(), avg_ms)
+        })
+        .collect();
+    rows.sort_by(|a, b| b.1.cmp(&a.1).then(a.3.cmp(&b.3)));
+
+    let width = rows.iter().map(|r| r.0.len()).max().unwrap_or(5).max(5);
+    let mut out = format!("{:<width$}  {:>7}  {:>9}\n", "model", "passed", "avg (ms)", width = width);
+    for (model, passed, total, avg_ms) in rows {
+        let score = format!("{}/{}", passed, total);
+        out.push_str(&format!("{:<width$}  {:>7}  {:>9}\n", model, score, avg_ms, width = width));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case(expect: Expect) -> Case {
+        Case {
+            name: "c".to_string(),
+            prompt: "p".to_string(),
+            system: None,
+            max_tokens: 16,
+            tools: vec![],
+            expect,
+        }
+    }
+
+    #[test]
+    fn test_check_assertions() {
+        let response = json!({
+            "content": [
+                {"type": "text", "text": "Reading the file now"},
+                {"type": "tool_use", "id": "t1", "name": "Read", "input": {}}
+            ]
+        });
+
+        let pass = case(Expect { regex: Some("(?i)reading".into()), tool: Some("Read".into()), max_latency_ms: Some(1000) });
+        assert!(check(&pass, &response, Duration::from_millis(200)).is_empty());
+
+        let fail = case(Expect { regex: Some("^done$".into()), tool: Some("Bash".into()), max_latency_ms: Some(100) });
+        let failures = check(&fail, &response, Duration::from_millis(200));
+        assert_eq!(failures.len(), 3);
+        assert!(failures[1].contains("called: Read"));
+    }
+
+    #[test]
+    fn test_suite_and_scoreboard() {
+        let suite: Suite = toml::from_str(
+            r#"
+models = ["a"]
+
+[[cases]]
+name = "greets"
+prompt = "say hi"
+expect = { regex = "(?i)hi" }
+"#,
+        )
+        .unwrap();
+        assert_eq!(suite.cases[0].max_tokens, 1024);
+
+        let result = |model: &str, ms: u64, failures: Vec<String>| CaseResult {
+            model: model.to_string(),
+            latency: Duration::from_millis(ms),
+            failures,
+        };
+        let board = scoreboard(&[
+            result("slow", 900, vec![]),
+            result("flaky", 100, vec!["nope".to_string()]),
+            result("slow", 1100, vec![]),
+            result("flaky", 100, vec![]),
+        ]);
+        let lines: Vec<&str> = board.lines().collect();
+        assert!(lines[1].starts_with("slow") && lines[1].contains("2/2") && lines[1].contains("1000"));
+        assert!(lines[2].starts_with("flaky") && lines[2].contains("1/2"));
+    }
+}
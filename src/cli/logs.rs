@@ -0,0 +1,232 @@
+//! `ccm logs`: print and follow the log file a daemonized server writes to
+//! (`logs/ccm.log` in the state directory). Lines are filtered by the timestamp and
+//! level tracing puts at the start of each entry; lines without one (startup banner,
+//! wrapped messages, panics) belong to the entry before them.
+
+use crate::paths;
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+/// How often `--follow` checks the file for new output
+const FOLLOW_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Log file of a server started with `--daemon`
+pub fn log_path() -> PathBuf {
+    paths::state_dir().join("logs").join("ccm.log")
+}
+
+/// Minimum severity to show
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn parse(token: &str) -> Option<Self> {
+        match token {
+            "ERROR" => Some(Level::Error),
+            "WARN" => Some(Level::Warn),
+            "INFO" => Some(Level::Info),
+            "DEBUG" => Some(Level::Debug),
+            "TRACE" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+}
+
+/// Parse `--since`: a duration back from `now` (`30s`, `10m`, `2h`, `1d`) or an RFC 3339 timestamp
+pub fn parse_since(value: &str, now: DateTime<Utc>) -> anyhow::Result<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount
+        .parse()
+        .with_context(|| format!("Invalid --since '{}': expected e.g. 10m, 2h, 1d or an RFC 3339 timestamp", value))?;
+    let duration = match unit {
+        "s" => Duration::seconds(amount),
+        "m" => Duration::minutes(amount),
+        "h" => Duration::hours(amount),
+        "d" => Duration::days(amount),
+        _ => anyhow::bail!("Invalid --since unit '{}': use s, m, h or d", unit),
+    };
+    Ok(now - duration)
+}
+
+/// Decides which lines to print. Keeps the header of the current entry so its
+/// continuation lines are kept or dropped along with it.
+#[derive(Debug, Default)]
+pub struct LogFilter {
+    since: Option<DateTime<Utc>>,
+    level: Option<Level>,
+    current: (Option<DateTime<Utc>>, Option<Level>),
+}
+
+impl LogFilter {
+    pub fn new(since: Option<DateTime<Utc>>, level: Option<Level>) -> Self {
+        Self { since, level, current: (None, None) }
+    }
+
+    /// Whether `line` passes the filter
+    pub fn accept(&mut self, line: &str) -> bool {
+        if let Some(header) = parse_header(&strip_ansi(line)) {
+            self.current = (Some(header.0), Some(header.1));
+        }
+        let (time, level) = self.current;
+        let recent = match (self.since, time) {
+            (Some(since), Some(time)) => time >= since,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        let severe = match (self.level, level) {
+            (Some(min), Some(level)) => level <= min,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        recent && severe
+    }
+}
+
+/// Timestamp and level of a tracing line (`2025-01-01T12:00:00.123456Z  INFO target: message`)
+fn parse_header(line: &str) -> Option<(DateTime<Utc>, Level)> {
+    let mut tokens = line.split_whitespace();
+    let time = DateTime::parse_from_rfc3339(tokens.next()?).ok()?;
+    let level = Level::parse(tokens.next()?)?;
+    Some((time.with_timezone(&Utc), level))
+}
+
+/// Remove terminal color codes (older log files, or output from a terminal-attached run)
+fn strip_ansi(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // CSI sequence: ESC [ parameters final-byte
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Print the last `lines` matching lines (0 for all), then keep printing new ones if `follow` is set
+pub async fn run(follow: bool, since: Option<String>, level: Option<Level>, lines: usize) -> anyhow::Result<()> {
+    let path = log_path();
+    let since = since.map(|s| parse_since(&s, Utc::now())).transpose()?;
+    let mut filter = LogFilter::new(since, level);
+
+    let file = File::open(&path).with_context(|| {
+        format!(
+            "No log file at {}. Logs are written there by a server started with 'ccm start --daemon'; a foreground server logs to the terminal",
+            path.display()
+        )
+    })?;
+    let mut reader = BufReader::new(file);
+    let mut tail = VecDeque::new();
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 {
+        if line.ends_with('\n') {
+            if filter.accept(&line) {
+                tail.push_back(strip_ansi(line.trim_end_matches(['\r', '\n'])));
+                if lines > 0 && tail.len() > lines {
+                    tail.pop_front();
+                }
+            }
+            line.clear();
+        }
+    }
+    for entry in tail {
+        println!("{}", entry);
+    }
+    if !follow {
+        return Ok(());
+    }
+
+    // `line` holds a trailing partial line, if any; new output is appended to it
+    let mut position = reader.stream_position()?;
+    loop {
+        tokio::time::sleep(FOLLOW_INTERVAL).await;
+        let Ok(mut file) = File::open(&path) else {
+            continue;
+        };
+        let len = file.metadata()?.len();
+        if len < position {
+            // Truncated or replaced: start over from the top
+            position = 0;
+            line.clear();
+        }
+        if len == position {
+            continue;
+        }
+        file.seek(SeekFrom::Start(position))?;
+        let mut chunk = Vec::new();
+        file.take(len - position).read_to_end(&mut chunk)?;
+        position = len;
+
+        line.push_str(&String::from_utf8_lossy(&chunk));
+        while let Some(end) = line.find('\n') {
+            let complete: String = line.drain(..=end).collect();
+            if filter.accept(&complete) {
+                println!("{}", strip_ansi(complete.trim_end_matches(['\r', '\n'])));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_since() {
+        let now = DateTime::parse_from_rfc3339("2025-06-01T12:00:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(parse_since("10m", now).unwrap().to_rfc3339(), "2025-06-01T11:50:00+00:00");
+        assert_eq!(parse_since("2h", now).unwrap().to_rfc3339(), "2025-06-01T10:00:00+00:00");
+        assert_eq!(parse_since("1d", now).unwrap().to_rfc3339(), "2025-05-31T12:00:00+00:00");
+        assert_eq!(
+            parse_since("2025-06-01T08:00:00+02:00", now).unwrap().to_rfc3339(),
+            "2025-06-01T06:00:00+00:00"
+        );
+        assert!(parse_since("10w", now).is_err());
+        assert!(parse_since("yesterday", now).is_err());
+    }
+
+    #[test]
+    fn test_log_filter() {
+        let lines = [
+            "🚀 Claude Code Mux v0.6.3",
+            "2025-06-01T11:00:00.000001Z  INFO ccm::server: Starting",
+            "2025-06-01T11:30:00.000001Z \x1b[33m WARN\x1b[0m ccm::router: slow provider",
+            "  continued warning detail",
+            "2025-06-01T11:45:00.000001Z DEBUG ccm::server: request body",
+            "2025-06-01T11:55:00.000001Z ERROR ccm::server: upstream failed",
+        ];
+        let kept = |filter: &mut LogFilter| -> Vec<usize> {
+            (0..lines.len()).filter(|&i| filter.accept(lines[i])).collect()
+        };
+
+        assert_eq!(kept(&mut LogFilter::new(None, None)), vec![0, 1, 2, 3, 4, 5]);
+        // Continuation lines follow their entry; the header-less banner has no level
+        assert_eq!(kept(&mut LogFilter::new(None, Some(Level::Warn))), vec![2, 3, 5]);
+        assert_eq!(kept(&mut LogFilter::new(None, Some(Level::Info))), vec![1, 2, 3, 5]);
+
+        let since = DateTime::parse_from_rfc3339("2025-06-01T11:40:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(kept(&mut LogFilter::new(Some(since), None)), vec![4, 5]);
+        assert_eq!(kept(&mut LogFilter::new(Some(since), Some(Level::Error))), vec![5]);
+    }
+}
@@ -1,10 +1,26 @@
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::path::PathBuf;
 use anyhow::{Context, Result};
 use crate::providers::ProviderConfig;
+use crate::providers::budget::BudgetConfig;
+use crate::providers::slo::SloConfig;
+use crate::providers::pricing::ModelPrice;
+
+pub mod claude_code;
+pub mod config_edit;
+pub mod doctor;
+pub mod env;
+pub mod eval;
+pub mod logs;
+pub mod reconcile;
+pub mod service;
+pub mod top;
+pub mod validate;
 
 /// Application configuration
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[non_exhaustive]
 pub struct AppConfig {
     #[serde(default)]
     pub server: ServerConfig,
@@ -13,6 +29,15 @@ pub struct AppConfig {
     pub providers: Vec<ProviderConfig>,
     #[serde(default)]
     pub models: Vec<ModelConfig>,
+    /// Prices keyed by "provider/actual_model" or just "actual_model"
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub pricing: std::collections::BTreeMap<String, ModelPrice>,
+    /// Daily/monthly spend limits per provider or model
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub budgets: Vec<BudgetConfig>,
+    /// Turn latency objectives per route type
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub slos: Vec<SloConfig>,
 }
 
 /// Server configuration
@@ -29,6 +54,53 @@ pub struct ServerConfig {
     pub timeouts: TimeoutConfig,
     #[serde(default)]
     pub tracing: TracingConfig,
+    #[serde(default)]
+    pub dataset: DatasetConfig,
+    #[serde(default)]
+    pub usage: UsageConfig,
+    #[serde(default)]
+    pub access_log: AccessLogConfig,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    #[serde(default)]
+    pub synthetic_stream: SyntheticStreamConfig,
+    #[serde(default)]
+    pub quota: QuotaConfig,
+    #[serde(default)]
+    pub cache_warmup: CacheWarmupConfig,
+    #[serde(default)]
+    pub hedging: HedgingConfig,
+    #[serde(default)]
+    pub guardrails: GuardrailsConfig,
+    /// How often to check provider model lists for configured models that were
+    /// retired (0 = never; model_not_found errors are still reported)
+    #[serde(default = "default_model_check_interval_hours")]
+    pub model_check_interval_hours: u64,
+    /// Replace `error` events inside a stream (e.g. overloaded_error) with a text
+    /// block explaining the interruption and a clean `message_stop`
+    #[serde(default)]
+    pub convert_stream_errors: bool,
+    /// Reload automatically when the config file changes on disk
+    #[serde(default = "default_true")]
+    pub watch_config: bool,
+    /// How `/v1/messages/count_tokens` is answered
+    #[serde(default)]
+    pub count_tokens: CountTokensMode,
+    /// Share rate limit windows, budget spend and OAuth tokens with other instances
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shared_state: Option<SharedStateConfig>,
+    /// API keys clients must present on `/v1/*` (open when unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<AuthConfig>,
+    /// Password for the admin UI and admin API (open when unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin: Option<AdminConfig>,
+    /// Fetch prices from a URL on a schedule; `[pricing]` entries take precedence
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pricing_source: Option<PricingSourceConfig>,
+    /// Probe providers on an interval and skip the ones that are down
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health_checks: Option<crate::providers::health_check::HealthCheckConfig>,
 }
 
 /// Message tracing configuration
@@ -53,8 +125,155 @@ impl Default for TracingConfig {
     }
 }
 
+/// Dataset recording configuration (fine-tuning JSONL)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DatasetConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_dataset_path")]
+    pub path: String,
+    /// Route types to record (e.g. ["default", "think"]). Empty = all routes.
+    #[serde(default)]
+    pub routes: Vec<String>,
+    /// Tool results longer than this are truncated
+    #[serde(default = "default_max_tool_result_chars")]
+    pub max_tool_result_chars: usize,
+}
+
+impl Default for DatasetConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: default_dataset_path(),
+            routes: Vec::new(),
+            max_tool_result_chars: default_max_tool_result_chars(),
+        }
+    }
+}
+
+fn default_dataset_path() -> String {
+    crate::paths::state_file("dataset.jsonl").display().to_string()
+}
+
+/// State shared by instances using the same provider accounts (`[server.shared_state]`)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SharedStateConfig {
+    /// SQLite database every instance can reach (e.g. on an NFS/SMB mount)
+    pub path: String,
+    /// This instance's name in the database (default: hostname). Must differ per instance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// How often to exchange state with the other instances
+    #[serde(default = "default_shared_state_sync_secs")]
+    pub sync_interval_secs: u64,
+}
+
+fn default_shared_state_sync_secs() -> u64 {
+    5
+}
+
+/// Inbound client authentication (`[server.auth]`)
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct AuthConfig {
+    /// Accepted keys, sent as `x-api-key` or `Authorization: Bearer`; `$VAR` reads the environment
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+}
+
+/// Admin UI and admin API access (`[server.admin]`)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AdminConfig {
+    /// Entered on `/login`, or sent as `Authorization: Bearer` by scripts; `$VAR` reads the environment
+    pub password: String,
+    /// How long a login lasts
+    #[serde(default = "default_admin_session_hours")]
+    pub session_hours: u64,
+}
+
+fn default_admin_session_hours() -> u64 {
+    12
+}
+
+/// Remote price table (`[server.pricing_source]`)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PricingSourceConfig {
+    /// JSON in `[pricing]` shape (USD per million tokens) or LiteLLM's
+    /// model_prices_and_context_window.json (USD per token)
+    pub url: String,
+    #[serde(default = "default_pricing_refresh_hours")]
+    pub refresh_interval_hours: u64,
+}
+
+fn default_pricing_refresh_hours() -> u64 {
+    24
+}
+
+/// Per-request usage history (SQLite)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UsageConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_usage_path")]
+    pub path: String,
+    /// Delete rows older than this many days (0 = keep forever)
+    #[serde(default = "default_usage_retention_days")]
+    pub retention_days: u32,
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: default_usage_path(),
+            retention_days: default_usage_retention_days(),
+        }
+    }
+}
+
+/// One JSON line per `/v1` request (`[server.access_log]`)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AccessLogConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "default_access_log_path")]
+    pub path: String,
+    /// Rotate to `<path>.1` past this size (0 = never)
+    #[serde(default = "default_access_log_max_size_mb")]
+    pub max_size_mb: u64,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: default_access_log_path(),
+            max_size_mb: default_access_log_max_size_mb(),
+        }
+    }
+}
+
+fn default_access_log_path() -> String {
+    crate::paths::state_file("access.jsonl").display().to_string()
+}
+
+fn default_access_log_max_size_mb() -> u64 {
+    100
+}
+
+fn default_usage_path() -> String {
+    crate::paths::state_file("usage.db").display().to_string()
+}
+
+fn default_usage_retention_days() -> u32 {
+    90
+}
+
+fn default_max_tool_result_chars() -> usize {
+    2000
+}
+
 fn default_tracing_path() -> String {
-    "~/.claude-code-mux/trace.jsonl".to_string()
+    crate::paths::state_file("trace.jsonl").display().to_string()
 }
 
 fn default_true() -> bool {
@@ -70,10 +289,32 @@ impl Default for ServerConfig {
             log_level: default_log_level(),
             timeouts: TimeoutConfig::default(),
             tracing: TracingConfig::default(),
+            dataset: DatasetConfig::default(),
+            usage: UsageConfig::default(),
+            access_log: AccessLogConfig::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            synthetic_stream: SyntheticStreamConfig::default(),
+            quota: QuotaConfig::default(),
+            cache_warmup: CacheWarmupConfig::default(),
+            hedging: HedgingConfig::default(),
+            guardrails: GuardrailsConfig::default(),
+            model_check_interval_hours: default_model_check_interval_hours(),
+            convert_stream_errors: false,
+            watch_config: true,
+            count_tokens: CountTokensMode::default(),
+            shared_state: None,
+            auth: None,
+            admin: None,
+            pricing_source: None,
+            health_checks: None,
         }
     }
 }
 
+fn default_model_check_interval_hours() -> u64 {
+    24
+}
+
 fn default_port() -> u16 {
     3456
 }
@@ -86,13 +327,250 @@ fn default_log_level() -> String {
     "info".to_string()
 }
 
-/// Timeout configuration
+/// Circuit breaker configuration (per provider/model mapping)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CircuitBreakerConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Consecutive failures before a mapping's circuit opens
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// How long an open mapping is tried last before going half-open
+    #[serde(default = "default_open_duration")]
+    pub open_duration_secs: u64,
+    /// How often to probe half-open mappings in the background (0 = no probes,
+    /// the next real request decides instead)
+    #[serde(default = "default_probe_interval")]
+    pub probe_interval_secs: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            failure_threshold: default_failure_threshold(),
+            open_duration_secs: default_open_duration(),
+            probe_interval_secs: default_probe_interval(),
+        }
+    }
+}
+
+fn default_failure_threshold() -> u32 {
+    3
+}
+
+fn default_open_duration() -> u64 {
+    30
+}
+
+fn default_probe_interval() -> u64 {
+    15
+}
+
+/// Pre-emptive spillover based on provider rate limit headers
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct QuotaConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Try a provider's mappings last once its tightest limit has less than this
+    /// fraction left (until the limit resets)
+    #[serde(default = "default_spillover_below")]
+    pub spillover_below: f64,
+    /// On a 429 whose Retry-After is at most this long, wait and retry the same
+    /// mapping once before failing over. 0 = fail over immediately.
+    #[serde(default)]
+    pub max_retry_wait_ms: u64,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            spillover_below: default_spillover_below(),
+            max_retry_wait_ms: 0,
+        }
+    }
+}
+
+fn default_spillover_below() -> f64 {
+    0.05
+}
+
+/// Prompt cache priming on the first request of a session
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CacheWarmupConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// How long the first request waits for the priming request (0 = don't wait)
+    #[serde(default = "default_cache_warmup_wait_ms")]
+    pub wait_ms: u64,
+}
+
+impl Default for CacheWarmupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            wait_ms: default_cache_warmup_wait_ms(),
+        }
+    }
+}
+
+fn default_cache_warmup_wait_ms() -> u64 {
+    3000
+}
+
+/// Hedged streaming requests: race the second mapping when the first is slow to start
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HedgingConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Start the second mapping if the first hasn't produced a token after this long
+    #[serde(default = "default_hedge_delay_ms")]
+    pub delay_ms: u64,
+    /// Route types to hedge (e.g. ["default", "think"]). Empty = all routes.
+    #[serde(default)]
+    pub routes: Vec<String>,
+}
+
+impl Default for HedgingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            delay_ms: default_hedge_delay_ms(),
+            routes: Vec::new(),
+        }
+    }
+}
+
+impl HedgingConfig {
+    /// Hedging is enabled for this route type
+    pub fn applies_to(&self, route: &str) -> bool {
+        self.enabled && (self.routes.is_empty() || self.routes.iter().any(|r| r.eq_ignore_ascii_case(route)))
+    }
+}
+
+fn default_hedge_delay_ms() -> u64 {
+    2000
+}
+
+/// Secret scanning of outbound prompts
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GuardrailsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub action: GuardrailAction,
+    /// Providers that receive prompts unscanned (e.g. local models)
+    #[serde(default)]
+    pub trusted_providers: Vec<String>,
+    /// Extra patterns, checked along with the built-in secret patterns
+    #[serde(default)]
+    pub rules: Vec<GuardrailRule>,
+    /// Also flag random-looking tokens with at least this Shannon entropy (bits per char)
+    #[serde(default)]
+    pub entropy_threshold: Option<f64>,
+    /// Minimum token length for the entropy check
+    #[serde(default = "default_entropy_min_length")]
+    pub entropy_min_length: usize,
+}
+
+impl Default for GuardrailsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            action: GuardrailAction::default(),
+            trusted_providers: Vec::new(),
+            rules: Vec::new(),
+            entropy_threshold: None,
+            entropy_min_length: default_entropy_min_length(),
+        }
+    }
+}
+
+fn default_entropy_min_length() -> usize {
+    32
+}
+
+/// What to do when a secret is found
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardrailAction {
+    /// Replace the secret with a `[REDACTED:<rule>]` marker
+    #[default]
+    Redact,
+    /// Reject the request
+    Block,
+}
+
+/// Named secret pattern
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GuardrailRule {
+    pub name: String,
+    pub pattern: String,
+}
+
+/// Pacing for SSE streams synthesized from non-streaming responses (`force_non_streaming`)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SyntheticStreamConfig {
+    /// Text/thinking deltas are split into chunks of this many characters (0 = one delta per block)
+    #[serde(default = "default_synthetic_chunk_chars")]
+    pub chunk_chars: usize,
+    /// Pause between deltas so the client renders progressively
+    #[serde(default = "default_synthetic_delay_ms")]
+    pub delay_ms: u64,
+    /// Upper bound on the total pause per response; long responses get shorter pauses
+    #[serde(default = "default_synthetic_max_total_delay_ms")]
+    pub max_total_delay_ms: u64,
+}
+
+impl Default for SyntheticStreamConfig {
+    fn default() -> Self {
+        Self {
+            chunk_chars: default_synthetic_chunk_chars(),
+            delay_ms: default_synthetic_delay_ms(),
+            max_total_delay_ms: default_synthetic_max_total_delay_ms(),
+        }
+    }
+}
+
+impl SyntheticStreamConfig {
+    /// Pause before each of `deltas` delta events
+    pub fn delay_per_delta(&self, deltas: usize) -> std::time::Duration {
+        let per_delta = match deltas {
+            0 => 0,
+            n => self.delay_ms.min(self.max_total_delay_ms / n as u64),
+        };
+        std::time::Duration::from_millis(per_delta)
+    }
+}
+
+fn default_synthetic_chunk_chars() -> usize {
+    64
+}
+
+fn default_synthetic_delay_ms() -> u64 {
+    10
+}
+
+fn default_synthetic_max_total_delay_ms() -> u64 {
+    2000
+}
+
+/// Limits on each provider attempt (`[server.timeouts]`, 0 = no limit)
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TimeoutConfig {
+    /// Whole attempt, including the full streamed response
     #[serde(default = "default_api_timeout")]
     pub api_timeout_ms: u64,
+    /// Until a provider answers with response headers
     #[serde(default = "default_connect_timeout")]
     pub connect_timeout_ms: u64,
+    /// From the response headers to the first content delta of a stream
+    #[serde(default = "default_first_token_timeout")]
+    pub first_token_timeout_ms: u64,
+    /// `count_tokens` calls; past it the count is estimated locally
+    #[serde(default = "default_count_tokens_timeout")]
+    pub count_tokens_timeout_ms: u64,
 }
 
 impl Default for TimeoutConfig {
@@ -100,6 +578,8 @@ impl Default for TimeoutConfig {
         Self {
             api_timeout_ms: default_api_timeout(),
             connect_timeout_ms: default_connect_timeout(),
+            first_token_timeout_ms: default_first_token_timeout(),
+            count_tokens_timeout_ms: default_count_tokens_timeout(),
         }
     }
 }
@@ -112,13 +592,37 @@ fn default_connect_timeout() -> u64 {
     10_000 // 10 seconds
 }
 
+fn default_first_token_timeout() -> u64 {
+    120_000 // 2 minutes
+}
+
+fn default_count_tokens_timeout() -> u64 {
+    2_000 // 2 seconds
+}
+
 /// Router configuration
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct RouterConfig {
     pub default: String,
     pub background: Option<String>,
     pub think: Option<String>,
     pub websearch: Option<String>,
+    /// Model for follow-up requests carrying more than websearch_summarizer_threshold
+    /// estimated tokens of web search results (a long-context model that condenses them)
+    pub websearch_summarizer: Option<String>,
+    /// Estimated tokens of `web_search_tool_result` blocks above which websearch_summarizer is used (default: 8000)
+    pub websearch_summarizer_threshold: Option<u32>,
+    /// Model for Claude Code's suggestion/autocomplete micro-requests
+    pub suggest: Option<String>,
+    /// Regex matched against the system prompt to detect suggestion requests.
+    /// If empty/null, defaults to a pattern for prompt-suggestion/autocomplete prompts.
+    pub suggest_regex: Option<String>,
+    /// Requests asking for more than this many tokens are never treated as suggestions (default: 512)
+    pub suggest_max_tokens: Option<u32>,
+    /// Model for requests whose estimated input exceeds long_context_threshold
+    pub long_context: Option<String>,
+    /// Estimated input tokens (system + messages + tools) above which long_context is used (default: 60000)
+    pub long_context_threshold: Option<u32>,
     /// Regex pattern for auto-mapping models (e.g., "^claude-").
     /// If empty/null, defaults to Claude models only.
     pub auto_map_regex: Option<String>,
@@ -128,13 +632,213 @@ pub struct RouterConfig {
     /// Prompt-based routing rules. Routes to specific models when patterns match user prompt.
     #[serde(default)]
     pub prompt_rules: Vec<PromptRule>,
+    /// Thinking budgets (and optionally models) by think keyword in the user's prompt
+    #[serde(default, skip_serializing_if = "ThinkKeywordsConfig::is_disabled")]
+    pub think_keywords: ThinkKeywordsConfig,
+    /// Per-client routing overrides (matched by User-Agent / x-app header, first match wins)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clients: Vec<ClientRoute>,
+    /// How to order mappings that share the same priority
+    #[serde(default)]
+    pub tie_break: TieBreak,
+    /// Providers tried (with the requested model name) for models that have no
+    /// [[models]] entry and no direct provider match
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback_mappings: Vec<FallbackMapping>,
+    /// Providers each route type may be sent to, keyed by route type
+    /// (e.g. `[router.route_providers.think] allow = ["anthropic"]`)
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub route_providers: std::collections::BTreeMap<String, ProviderFilter>,
+    /// Regions requests may be processed in (matched against provider `region`).
+    /// Empty = unrestricted. A model's own `residency` takes precedence.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub residency: Vec<String>,
+}
+
+impl RouterConfig {
+    /// Provider filter for a route type. Keys match case-insensitively, ignoring `-` and `_`,
+    /// so `web_search`, `websearch` and `web-search` are the same route.
+    pub fn provider_filter(&self, route: &str) -> Option<&ProviderFilter> {
+        let route = route_key(route);
+        self.route_providers.iter().find(|(key, _)| route_key(key) == route).map(|(_, filter)| filter)
+    }
+}
+
+/// Route type name compared case-insensitively, ignoring `-` and `_`
+pub fn route_key(route: &str) -> String {
+    route.chars().filter(|c| *c != '-' && *c != '_').collect::<String>().to_ascii_lowercase()
+}
+
+/// Allow/deny list of providers for one route type
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ProviderFilter {
+    /// Only these providers may serve the route. Empty = any provider.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+    /// These providers never serve the route (checked after allow)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+}
+
+impl ProviderFilter {
+    pub fn permits(&self, provider: &str) -> bool {
+        (self.allow.is_empty() || self.allow.iter().any(|p| p == provider))
+            && !self.deny.iter().any(|p| p == provider)
+    }
+}
+
+/// Generic provider mapping for unmapped models
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct FallbackMapping {
+    /// Priority for this mapping (1 = highest priority)
+    #[serde(default = "default_fallback_priority")]
+    pub priority: u32,
+    /// Provider name
+    pub provider: String,
+    /// Inject continuation prompt after tool results (for models that stop prematurely)
+    #[serde(default)]
+    pub inject_continuation_prompt: bool,
+    /// Continuation prompt text, trigger and placement (implies inject_continuation_prompt)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continuation: Option<ContinuationConfig>,
+    /// Append a system note naming the provider/model that actually serves the request
+    #[serde(default)]
+    pub inject_identity: bool,
+}
+
+/// Where the continuation prompt goes in the tool-result message
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContinuationPlacement {
+    /// Before the tool results
+    #[default]
+    Prepend,
+    /// After the tool results (required by Anthropic's own API)
+    Append,
+}
+
+/// Continuation prompt settings (`[models.mappings.continuation]`)
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ContinuationConfig {
+    /// Reminder text (default: the built-in todo-list reminder)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Only inject after this many consecutive tool-result turns
+    #[serde(default = "default_min_tool_rounds")]
+    pub min_tool_rounds: usize,
+    #[serde(default)]
+    pub placement: ContinuationPlacement,
+}
+
+impl Default for ContinuationConfig {
+    fn default() -> Self {
+        Self {
+            text: None,
+            min_tool_rounds: default_min_tool_rounds(),
+            placement: ContinuationPlacement::default(),
+        }
+    }
+}
+
+fn default_min_tool_rounds() -> usize {
+    1
+}
+
+fn default_fallback_priority() -> u32 {
+    1
+}
+
+/// Where `/v1/messages/count_tokens` gets its answer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CountTokensMode {
+    /// The routed provider, estimated locally if it doesn't answer in time
+    #[default]
+    Provider,
+    /// Always a local tokenizer count (approximate, no upstream calls)
+    Local,
+}
+
+/// Ordering for model mappings with equal priority
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TieBreak {
+    /// Keep the order mappings appear in the config
+    #[default]
+    Config,
+    /// Prefer the higher recent success rate, then lower latency
+    SuccessRate,
+}
+
+/// Which user messages a prompt rule's strip_match rewrites
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StripScope {
+    /// Only the message that started the current turn
+    #[default]
+    Turn,
+    /// Every user message in the history, so tags don't linger after compaction
+    All,
+}
+
+impl StripScope {
+    fn is_turn(&self) -> bool {
+        *self == StripScope::Turn
+    }
+}
+
+/// How long a prompt rule match lasts within a session (keyed by metadata.user_id)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleLifetime {
+    /// Applies to every turn whose turn-starting message matches
+    #[default]
+    Turn,
+    /// Applies to the first matching turn only; later matches in the session are ignored
+    Once,
+    /// Once matched, applies to every later request in the session
+    Sticky,
+}
+
+impl RuleLifetime {
+    fn is_turn(&self) -> bool {
+        *self == RuleLifetime::Turn
+    }
+}
+
+/// Routing overrides for a specific client (e.g. aider, custom scripts)
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ClientRoute {
+    /// Display name used in logs
+    pub name: String,
+    /// Regex matched against the User-Agent header
+    pub user_agent: Option<String>,
+    /// Regex matched against the x-app header
+    pub x_app: Option<String>,
+    /// Overrides router.default for this client
+    pub default: Option<String>,
+    /// Overrides router.background for this client
+    pub background: Option<String>,
+    /// Overrides router.think for this client
+    pub think: Option<String>,
+    /// Overrides router.websearch for this client
+    pub websearch: Option<String>,
+    /// Overrides router.suggest for this client
+    pub suggest: Option<String>,
+    /// Overrides router.long_context for this client
+    pub long_context: Option<String>,
+    /// Prompt rules checked before the global ones for this client
+    #[serde(default)]
+    pub prompt_rules: Vec<PromptRule>,
 }
 
 /// Prompt-based routing rule
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct PromptRule {
     /// Regex pattern to match against user prompt content.
     /// Can include capture groups: (pattern) or named: (?P<name>pattern)
+    /// May be empty when the rule only matches on metadata.
+    #[serde(default)]
     pub pattern: String,
     /// Model to route to when pattern matches.
     /// Can reference capture groups: $1, $name, ${1}, ${name}, or mixed like "prefix-$1"
@@ -142,60 +846,402 @@ pub struct PromptRule {
     /// Strip the matched phrase from the prompt (default: false)
     #[serde(default)]
     pub strip_match: bool,
+    /// Which user messages strip_match applies to (default: the turn-starting one)
+    #[serde(default, skip_serializing_if = "StripScope::is_turn")]
+    pub strip_scope: StripScope,
+    /// How long a match lasts within a session (default: the matching turn)
+    #[serde(default, skip_serializing_if = "RuleLifetime::is_turn")]
+    pub lifetime: RuleLifetime,
+    /// Regex patterns matched against request.metadata fields (all must match).
+    /// Keys may use dots for nested values, e.g. { user_id = "^user_abc", "tags.team" = "infra" }
+    #[serde(default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+    pub metadata: std::collections::HashMap<String, String>,
+}
+
+/// Think keyword elevation: "think", "think hard" and "ultrathink" in the turn-starting
+/// prompt enable thinking with increasing budgets, as Claude Code does for Anthropic models
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ThinkKeywordsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Checked in order, first match wins: list the strongest level first
+    #[serde(default = "default_think_levels")]
+    pub levels: Vec<ThinkLevel>,
+}
+
+impl Default for ThinkKeywordsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            levels: default_think_levels(),
+        }
+    }
+}
+
+impl ThinkKeywordsConfig {
+    fn is_disabled(&self) -> bool {
+        !self.enabled
+    }
+}
+
+/// One think keyword level
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ThinkLevel {
+    /// Regex matched against the turn-starting user message
+    pub pattern: String,
+    /// Thinking budget for matching requests (a larger client budget is kept)
+    pub budget_tokens: u32,
+    /// Model for matching requests (default: router.think, if set)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+/// Claude Code's keywords and budgets
+fn default_think_levels() -> Vec<ThinkLevel> {
+    let level = |pattern: &str, budget_tokens| ThinkLevel { pattern: pattern.to_string(), budget_tokens, model: None };
+    vec![
+        level(
+            r"(?i)\b(ultrathink|think (harder|intensely|longer|really hard|super hard|very hard))\b",
+            31_999,
+        ),
+        level(r"(?i)\b(megathink|think (hard|deeply|more|a lot|about it))\b", 10_000),
+        level(r"(?i)\bthink\b", 4_000),
+    ]
 }
 
 /// Model configuration with 1:N provider mappings
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[non_exhaustive]
 pub struct ModelConfig {
     /// External model name (used in API requests)
     pub name: String,
     /// List of provider mappings with priorities (fallback support)
     pub mappings: Vec<ModelMapping>,
+    /// Regions this model's requests may be processed in; overrides router.residency
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub residency: Option<Vec<String>>,
+    /// How requests are spread over the mappings
+    #[serde(default, skip_serializing_if = "MappingStrategy::is_priority")]
+    pub strategy: MappingStrategy,
+    /// Order the mappings are tried in
+    #[serde(default, skip_serializing_if = "MappingOrdering::is_priority")]
+    pub ordering: MappingOrdering,
+    /// On background routes, skip mappings priced above this (blended USD per
+    /// million tokens, see `ModelPrice::blended_per_mtok`); unpriced mappings stay
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cost_per_mtok: Option<f64>,
+}
+
+/// How a model's requests are spread over its mappings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MappingStrategy {
+    /// Always start with the highest-priority mapping (ties per `router.tie_break`)
+    #[default]
+    Priority,
+    /// Cycle equal-priority mappings per request, weighted by recent success rate
+    RoundRobin,
+}
+
+impl MappingStrategy {
+    fn is_priority(&self) -> bool {
+        *self == MappingStrategy::Priority
+    }
+}
+
+/// Order a model's mappings are tried in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MappingOrdering {
+    /// By `priority` (ties per `router.tie_break`)
+    #[default]
+    Priority,
+    /// Fastest recent mean latency first; `priority` only breaks ties
+    Latency,
+    /// Cheapest configured price first, unpriced mappings last; `priority` only breaks ties
+    Cost,
+}
+
+impl MappingOrdering {
+    pub fn is_priority(&self) -> bool {
+        *self == MappingOrdering::Priority
+    }
 }
 
 /// Model mapping to a specific provider
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[non_exhaustive]
 pub struct ModelMapping {
     /// Priority for this mapping (1 = highest priority)
     pub priority: u32,
     /// Provider name
     pub provider: String,
-    /// Actual model name to use with the provider
+    /// Actual model name to use with the provider.
+    /// May contain "{requested}" (the model name the client sent), optionally
+    /// piped through filters: "{requested|strip_prefix:claude-|lower}"
     pub actual_model: String,
     /// Inject continuation prompt after tool results (for models that stop prematurely)
     #[serde(default)]
     pub inject_continuation_prompt: bool,
+    /// Continuation prompt text, trigger and placement (implies inject_continuation_prompt)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continuation: Option<ContinuationConfig>,
+    /// Call the upstream non-streaming and synthesize the SSE stream for streaming
+    /// clients (for providers whose streaming is unreliable)
+    #[serde(default)]
+    pub force_non_streaming: bool,
+    /// Call the upstream streaming for non-streaming clients and collect the stream
+    /// into one JSON response (for providers that time out on long non-streaming calls)
+    #[serde(default)]
+    pub force_streaming: bool,
+    /// Describe tools in the system prompt and parse tool calls from the model's text
+    /// (for models with no function calling). Implies non-streaming upstream calls.
+    #[serde(default)]
+    pub prompt_tools: bool,
+    /// Append a system note naming the provider/model that actually serves the request
+    /// (stops non-Claude models from introducing themselves as Claude)
+    #[serde(default)]
+    pub inject_identity: bool,
+    /// Extra sentences for the identity note, e.g. knowledge cutoff and capabilities
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity_details: Option<String>,
+    /// Route types this mapping may serve (e.g. `["background", "default"]`). Empty = all.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<String>,
+    /// Provider-specific fields merged into the outbound request JSON, e.g. OpenRouter's
+    /// `provider` preferences or vLLM's `top_k`. Tables merge key by key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extra_body: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+impl ModelConfig {
+    /// A model with these mappings and default settings
+    #[allow(dead_code)] // Part of the embedding API, not used by the binary
+    pub fn new(name: impl Into<String>, mappings: Vec<ModelMapping>) -> Self {
+        Self {
+            name: name.into(),
+            mappings,
+            residency: None,
+            strategy: MappingStrategy::default(),
+            ordering: MappingOrdering::default(),
+            max_cost_per_mtok: None,
+        }
+    }
 }
 
-impl ModelConfig {}
+impl ModelMapping {
+    /// A mapping with default settings
+    #[allow(dead_code)] // Part of the embedding API, not used by the binary
+    pub fn new(priority: u32, provider: impl Into<String>, actual_model: impl Into<String>) -> Self {
+        Self {
+            priority,
+            provider: provider.into(),
+            actual_model: actual_model.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Whether this mapping may serve `route` (a route type name, see `routes`)
+    pub fn serves_route(&self, route: &str) -> bool {
+        let route = route_key(route);
+        self.routes.is_empty() || self.routes.iter().any(|r| route_key(r) == route)
+    }
+
+    /// Effective continuation prompt settings, if injection is enabled
+    pub fn continuation(&self) -> Option<Cow<'_, ContinuationConfig>> {
+        match &self.continuation {
+            Some(config) => Some(Cow::Borrowed(config)),
+            None if self.inject_continuation_prompt => Some(Cow::Owned(ContinuationConfig::default())),
+            None => None,
+        }
+    }
+
+    /// Render actual_model placeholders for a request.
+    ///
+    /// Supported filters: `strip_prefix:X`, `strip_suffix:X`, `replace:FROM:TO`, `lower`, `upper`.
+    /// Unknown placeholders are left as-is; unknown filters are ignored.
+    pub fn resolve_actual_model(&self, requested: &str) -> String {
+        let template = &self.actual_model;
+        if !template.contains('{') {
+            return template.clone();
+        }
+
+        let mut result = String::with_capacity(template.len());
+        let mut rest = template.as_str();
+        while let Some(start) = rest.find('{') {
+            let Some(len) = rest[start..].find('}') else {
+                break;
+            };
+            result.push_str(&rest[..start]);
+
+            let placeholder = &rest[start + 1..start + len];
+            let mut parts = placeholder.split('|');
+            if parts.next().map(str::trim) == Some("requested") {
+                let mut value = requested.to_string();
+                for filter in parts {
+                    value = apply_model_filter(&value, filter.trim());
+                }
+                result.push_str(&value);
+            } else {
+                result.push_str(&rest[start..start + len + 1]);
+            }
+            rest = &rest[start + len + 1..];
+        }
+        result.push_str(rest);
+        result
+    }
+}
+
+/// Apply one actual_model template filter
+fn apply_model_filter(value: &str, filter: &str) -> String {
+    let (name, arg) = filter.split_once(':').unwrap_or((filter, ""));
+    match name {
+        "strip_prefix" => value.strip_prefix(arg).unwrap_or(value).to_string(),
+        "strip_suffix" => value.strip_suffix(arg).unwrap_or(value).to_string(),
+        "replace" => match arg.split_once(':') {
+            Some((from, to)) if !from.is_empty() => value.replace(from, to),
+            _ => value.to_string(),
+        },
+        "lower" => value.to_lowercase(),
+        "upper" => value.to_uppercase(),
+        _ => {
+            tracing::warn!("⚠️  Unknown actual_model filter '{}', ignoring", filter);
+            value.to_string()
+        }
+    }
+}
 
 impl AppConfig {
+    /// Find the [[models]] entry for a routed model name (case-insensitive)
+    pub fn find_model(&self, name: &str) -> Option<&ModelConfig> {
+        self.models.iter().find(|m| m.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Build a model config from router.fallback_mappings that sends the
+    /// requested model name as-is. None if no fallback mappings are configured.
+    pub fn fallback_model_config(&self, name: &str) -> Option<ModelConfig> {
+        if self.router.fallback_mappings.is_empty() {
+            return None;
+        }
+
+        Some(ModelConfig {
+            name: name.to_string(),
+            mappings: self
+                .router
+                .fallback_mappings
+                .iter()
+                .map(|f| ModelMapping {
+                    priority: f.priority,
+                    provider: f.provider.clone(),
+                    actual_model: name.to_string(),
+                    inject_continuation_prompt: f.inject_continuation_prompt,
+                    continuation: f.continuation.clone(),
+                    inject_identity: f.inject_identity,
+                    ..Default::default()
+                })
+                .collect(),
+            residency: None,
+            strategy: MappingStrategy::Priority,
+            ordering: MappingOrdering::Priority,
+            max_cost_per_mtok: None,
+        })
+    }
+
+    /// Residency policy for a model: its own `residency`, else router.residency.
+    /// Empty = unrestricted.
+    pub fn residency_for<'a>(&'a self, model: Option<&'a ModelConfig>) -> &'a [String] {
+        model.and_then(|m| m.residency.as_deref()).unwrap_or(&self.router.residency)
+    }
+
+    /// Configured region of a provider
+    pub fn provider_region(&self, provider: &str) -> Option<&str> {
+        self.providers.iter().find(|p| p.name == provider).and_then(|p| p.region.as_deref())
+    }
+
+    /// Provider is in a region the policy allows. Untagged providers only pass an empty policy.
+    pub fn residency_permits(&self, policy: &[String], provider: &str) -> bool {
+        policy.is_empty()
+            || self
+                .provider_region(provider)
+                .is_some_and(|region| policy.iter().any(|r| r.eq_ignore_ascii_case(region)))
+    }
+
+    /// Price of a provider's model: "provider/model" entries win over "model" ones
+    pub fn price_for(&self, provider: &str, model: &str) -> Option<&ModelPrice> {
+        self.pricing
+            .get(&format!("{}/{}", provider, model))
+            .or_else(|| self.pricing.get(model))
+    }
+
     /// Get default config file path
-    /// Returns ~/.claude-code-mux/config.toml (cross-platform)
+    /// Returns ~/.claude-code-mux/config.toml (cross-platform), or
+    /// ~/.claude-code-mux/config.<name>.toml for a named instance
     pub fn default_path() -> Result<PathBuf> {
         let home = dirs::home_dir()
             .context("Failed to get home directory")?;
         let config_dir = home.join(".claude-code-mux");
-        std::fs::create_dir_all(&config_dir)
-            .with_context(|| format!("Failed to create config directory: {}", config_dir.display()))?;
-        Ok(config_dir.join("config.toml"))
+        // Best effort: the directory may be on a read-only filesystem
+        if let Err(e) = std::fs::create_dir_all(&config_dir) {
+            eprintln!("Warning: Failed to create config directory {}: {}", config_dir.display(), e);
+        }
+        Ok(match crate::paths::instance() {
+            Some(name) => config_dir.join(format!("config.{}.toml", name)),
+            None => config_dir.join("config.toml"),
+        })
     }
 
-    /// Load configuration from a TOML file
+    /// Load configuration from a TOML file, with `${VAR}` references resolved and CCM_*
+    /// environment variables applied on top. Without a config file, CCM_CONFIG_JSON or
+    /// CCM_* variables alone are enough.
     pub fn from_file(path: &PathBuf) -> Result<Self> {
-        // Check if file exists, if not create a default one
-        if !path.exists() {
-            Self::create_default_config(path)?;
-        }
+        Self::load(path, &env::config_vars())
+    }
+
+    /// Configuration from a TOML string, with the same defaults as a config file and
+    /// `${VAR}` references and `$VAR` API keys resolved from the environment
+    #[allow(dead_code)] // Used by the embedding API (`embed`), not the binary
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        let mut value: serde_json::Value = toml::from_str(toml).context("Failed to parse config")?;
+        env::interpolate(&mut value, &|name| std::env::var(name).ok())?;
+        let mut config: AppConfig = serde_json::from_value(value).context("Invalid configuration")?;
+        config.resolve_env_vars();
+        Ok(config)
+    }
+
+    fn load(path: &PathBuf, vars: &[(String, String)]) -> Result<Self> {
+        let mut value: serde_json::Value = if let Some(json) = env::config_json(vars)? {
+            json
+        } else if path.exists() {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+            toml::from_str(&content)
+                .with_context(|| format!("Failed to parse config file: {}", path.display()))?
+        } else if env::has_config(vars) {
+            // Environment-only configuration: don't create a config file
+            serde_json::Value::Object(Default::default())
+        } else {
+            // Create a default config file; fall back to the built-in default on read-only filesystems
+            let content = match Self::create_default_config(path) {
+                Ok(()) => std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read config file: {}", path.display()))?,
+                Err(e) => {
+                    eprintln!("Warning: {:#}. Using built-in default config.", e);
+                    Self::default_config_content()
+                }
+            };
+            toml::from_str(&content)
+                .with_context(|| format!("Failed to parse config file: {}", path.display()))?
+        };
 
-        let content = std::fs::read_to_string(path)
-            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        env::interpolate(&mut value, &|name| std::env::var(name).ok())
+            .with_context(|| format!("Failed to resolve environment references in {}", path.display()))?;
+        env::apply_overrides(&mut value, vars)?;
 
-        let mut config: AppConfig = toml::from_str(&content)
-            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+        let mut config: AppConfig = serde_json::from_value(value)
+            .with_context(|| format!("Invalid configuration ({} / CCM_* environment)", path.display()))?;
 
         // Resolve environment variables
-        config.resolve_env_vars()?;
+        config.resolve_env_vars();
 
         Ok(config)
     }
@@ -253,17 +1299,123 @@ impl AppConfig {
 host = "127.0.0.1"
 port = 13456
 log_level = "info"
+# model_check_interval_hours = 24   # Warn when a provider's model list drops a configured model (0 = off)
+# convert_stream_errors = false    # End streams cleanly with an explanation when the provider sends a mid-stream error
+# watch_config = true             # Reload automatically when this file changes (invalid edits keep the running config)
+# count_tokens = "provider"       # "local" answers token counts with a local tokenizer, without calling providers
 
+# Limits on each provider attempt; a provider that runs past one is failed over (0 = no limit)
 [server.timeouts]
-api_timeout_ms = 600000      # 10 minutes
-connect_timeout_ms = 10000   # 10 seconds
+api_timeout_ms = 600000           # 10 minutes for the whole response
+connect_timeout_ms = 10000        # 10 seconds until the response headers
+first_token_timeout_ms = 120000   # 2 minutes from there to the first token
+count_tokens_timeout_ms = 2000    # 2 seconds for a token count, then it's estimated locally
+
+# Circuit breaker: after repeated failures a mapping is tried last, then probed
+# in the background and failed back to once it recovers
+# [server.circuit_breaker]
+# enabled = true
+# failure_threshold = 3       # Consecutive failures before opening
+# open_duration_secs = 30     # Cooldown before half-open
+# probe_interval_secs = 15    # Background probe interval (0 = disable probes)
+
+# Spill over to the next mapping when a provider's rate limit headers show
+# its quota is nearly exhausted (until the limit resets)
+# [server.quota]
+# enabled = true
+# spillover_below = 0.05      # Fraction of the tightest limit left
+# max_retry_wait_ms = 0       # Wait out a 429's Retry-After up to this long and retry once
+#                             # (0 = fail over immediately; the provider is tried last until
+#                             # the window passes either way)
+
+# Prime the prompt cache on a session's first request to an Anthropic-style provider:
+# a 1-token request with the cached system prompt/tools prefix is sent first
+# [server.cache_warmup]
+# enabled = false
+# wait_ms = 3000              # Max time the first request waits for priming (0 = fire and forget)
+
+# Hedged requests for streaming: if the top mapping hasn't sent a token after
+# delay_ms, the second mapping is started too and whichever streams first wins
+# [server.hedging]
+# enabled = false
+# delay_ms = 2000
+# routes = ["default", "think"]   # Route types to hedge (empty = all)
+
+# Scan prompts for secrets (API keys, private keys) before they leave for a provider
+# [server.guardrails]
+# enabled = false
+# action = "redact"               # "redact" or "block"
+# trusted_providers = ["ollama"]  # Providers that receive prompts unscanned
+# entropy_threshold = 4.5         # Also flag random-looking tokens (omit to disable)
+# entropy_min_length = 32
+# [[server.guardrails.rules]]
+# name = "internal_token"
+# pattern = "itk_[A-Za-z0-9]{32}"
+
+# Pacing for streams synthesized for force_non_streaming mappings
+# [server.synthetic_stream]
+# chunk_chars = 64            # Characters per text delta (0 = whole block at once)
+# delay_ms = 10               # Pause between deltas
+# max_total_delay_ms = 2000   # Cap on total pause per response
 
 # Message tracing for debugging (logs full request/response to JSONL)
 # [server.tracing]
 # enabled = true
-# path = "~/.claude-code-mux/trace.jsonl"
+# path = "~/.claude-code-mux/trace.jsonl"   # default: <state dir>/trace.jsonl
 # omit_system_prompt = true  # Omit large system prompts from traces
 
+# Record sanitized prompt/response pairs as OpenAI fine-tuning JSONL
+# (system prompt stripped, tool results truncated)
+# [server.dataset]
+# enabled = true
+# path = "~/.claude-code-mux/dataset.jsonl" # default: <state dir>/dataset.jsonl
+# routes = ["default", "think"]   # Route types to record (empty = all)
+# max_tool_result_chars = 2000
+
+# Per-request usage history in SQLite, queried with GET /api/usage
+# [server.usage]
+# enabled = true
+# path = "~/.claude-code-mux/usage.db"   # default: <state dir>/usage.db
+# retention_days = 90                     # 0 = keep forever
+
+# Access log: one JSON line per /v1 request (route, model, provider, status,
+# latency, tokens, client key hash). On by default; no message content.
+# [server.access_log]
+# enabled = true
+# path = "~/.claude-code-mux/access.jsonl"   # default: <state dir>/access.jsonl
+# max_size_mb = 100                         # rotates to access.jsonl.1; 0 = never
+
+# Optional: Share rate limit windows, budget spend and OAuth tokens with CCM on
+# other machines using the same accounts, through a SQLite file they all reach
+# [server.shared_state]
+# path = "/mnt/nas/ccm/shared.db"
+# instance = "laptop"                     # default: hostname
+# sync_interval_secs = 5
+
+# Optional: Probe each provider in the background and skip providers that are
+# down (status at /api/providers/health)
+# [server.health_checks]
+# interval_secs = 60
+# probe = "auto"                          # auto | models | completion
+# down_after = 2                          # consecutive failed probes
+
+# Optional: Require an API key on /v1/* (x-api-key or Authorization: Bearer),
+# e.g. when CCM listens on the LAN. Give clients one of these keys.
+# [server.auth]
+# api_keys = ["$CCM_API_KEY"]
+
+# Optional: Require a password for the admin UI, /api/config*, /api/reload and
+# /api/oauth/* (login at /login, or Authorization: Bearer for scripts)
+# [server.admin]
+# password = "$CCM_ADMIN_PASSWORD"
+# session_hours = 12
+
+# Optional: Keep model prices up to date from a published price list.
+# [pricing] entries below win over fetched prices.
+# [server.pricing_source]
+# url = "https://raw.githubusercontent.com/BerriAI/litellm/main/model_prices_and_context_window.json"
+# refresh_interval_hours = 24
+
 [router]
 # Default model to use when no routing conditions are met
 # You MUST configure at least one provider and model before using CCM
@@ -278,18 +1430,82 @@ default = "placeholder-model"
 # Optional: Model for web search tasks (e.g., "glm-4.6")
 # websearch = ""
 
+# Optional: Model for the follow-up of a web search once its results are large
+# (a long-context model that condenses them before they reach the main session)
+# websearch_summarizer = ""
+# websearch_summarizer_threshold = 8000   # Estimated tokens of search results
+
+# Optional: Model for tiny suggestion/autocomplete requests (e.g., a local model)
+# suggest = ""
+# suggest_regex = ""          # System prompt pattern (default: prompt suggestion/autocomplete)
+# suggest_max_tokens = 512    # Larger requests are never treated as suggestions
+
+# Optional: Model for requests with a large estimated input (system + messages + tools)
+# long_context = ""
+# long_context_threshold = 60000
+
 # Optional: Regex pattern for auto-mapping models (e.g., "^claude-")
 # auto_map_regex = ""
 
 # Optional: Regex pattern for detecting background tasks (e.g., "(?i)claude.*haiku")
 # background_regex = ""
 
+# Optional: Ordering for mappings with the same priority
+# "config" (default) keeps config file order; "success_rate" prefers the mapping with the
+# higher recent success rate, then lower latency
+# tie_break = "config"
+
 # Optional: Prompt-based routing rules (first match wins)
 # Routes to specific models when patterns match user prompt content
 # [[router.prompt_rules]]
 # pattern = "(?i)commit.*changes"   # Regex pattern to match
 # model = "fast-model"              # Model to route to
 # strip_match = false               # Strip matched phrase from prompt (default: false)
+# strip_scope = "turn"              # "turn" = turn-starting message only, "all" = every user message
+# lifetime = "turn"                 # "turn" = each matching turn, "once" = first match per session,
+#                                   # "sticky" = every request in the session after the first match
+#
+# Rules can also match on request metadata (e.g. route a teammate's sessions elsewhere)
+# [[router.prompt_rules]]
+# metadata = { user_id = "^user_abc123" }  # Regex per metadata field (all must match)
+# model = "teammate-model"
+
+# Optional: "think" / "think hard" / "ultrathink" in the prompt enable thinking with
+# 4000 / 10000 / 31999 tokens (Claude Code's budgets) and route to the think model
+# [router.think_keywords]
+# enabled = true
+# Custom levels replace the defaults (first match wins, strongest first):
+# levels = [
+#   { pattern = "(?i)\\bultrathink\\b", budget_tokens = 31999, model = "opus-model" },
+#   { pattern = "(?i)\\bthink\\b", budget_tokens = 4000 },
+# ]
+
+# Optional: Per-client overrides for other Anthropic-SDK tools sharing the mux
+# (matched by User-Agent / x-app header regex, first match wins)
+# [[router.clients]]
+# name = "aider"
+# user_agent = "(?i)aider"
+# default = "cheap-model"
+# think = "reasoning-model"
+
+# Optional: Providers tried for model names with no [[models]] entry
+# (the requested model name is sent as-is, e.g. brand-new models via OpenRouter)
+# [[router.fallback_mappings]]
+# provider = "openrouter"
+# priority = 1
+
+# Optional: Restrict which providers a route type may use, enforced at dispatch
+# (e.g. keep Plan Mode content away from cheap providers). Mappings to other
+# providers are skipped; the request fails if none remain.
+# [router.route_providers.think]
+# allow = ["anthropic", "openrouter"]
+# [router.route_providers.background]
+# deny = ["expensive-provider"]
+
+# Optional: Data residency. Tag providers with `region = "us" | "eu" | "cn" | "local"`
+# and list the regions requests may be processed in. Mappings to other (or untagged)
+# providers are skipped and logged. A model's own `residency = [...]` overrides this.
+# residency = ["eu", "local"]
 
 # Providers configuration
 # Add providers via the web UI or edit this section
@@ -298,9 +1514,25 @@ default = "placeholder-model"
 # name = "my-provider"
 # provider_type = "anthropic"  # or "openai", "openrouter", etc.
 # auth_type = "api_key"        # or "oauth"
-# api_key = "your-api-key-here"
+# api_key = "${MY_PROVIDER_API_KEY}"  # or the key itself; ${VAR} reads the environment
 # enabled = true
+# region = "us"                # Optional data-residency tag (us/eu/cn/local)
+# cache_ttl = "1h"             # Optional prompt cache TTL ("5m" or "1h"), Anthropic-compatible only
 # models = []
+#
+# Optional: retry transient errors (5xx, 529, connection failures) against this
+# provider with exponential backoff before failing over to the next mapping
+# [providers.retry]
+# attempts = 3                 # Total tries including the first
+# backoff_ms = 500             # First delay, doubled each retry
+# max_backoff_ms = 8000
+# jitter = 0.2                 # ±20% random spread
+#
+# Optional: keep local models loaded (Ollama, llama.cpp, LM Studio) so the first
+# request after an idle period doesn't wait for the model to load
+# [providers.keep_warm]
+# interval_secs = 240          # Warm-up interval; Ollama is asked to keep models for twice this
+# models = ["qwen2.5-coder:7b"] # Default: the provider's mapped models and router targets
 
 # Models configuration
 # Add models via the web UI or edit this section
@@ -312,19 +1544,69 @@ default = "placeholder-model"
 # provider = "my-provider"
 # actual_model = "claude-sonnet-4-5"
 # priority = 1
+#
+# actual_model can forward the client's model name with rewriting, e.g.
+# actual_model = "anthropic/{requested|strip_prefix:claude-}"
+#
+# routes limits a mapping to some route types (default: all), e.g. keep a free,
+# flaky provider off Plan Mode:
+# routes = ["background", "default"]
+
+# Prices in USD per million tokens, for cost in the metrics log and /api/debug/stats.
+# Keys are "provider/actual_model" or just "actual_model".
+# [pricing."anthropic/claude-sonnet-4-5"]
+# input = 3.0
+# output = 15.0
+# cache_read = 0.3             # default: input price
+# cache_write = 3.75           # default: input price
+
+# Spend limits (UTC day / calendar month) on priced models, by provider and/or
+# actual_model. on_exceeded = "fallback" tries the next mapping, "reject" returns 429.
+# [[budgets]]
+# provider = "anthropic"
+# daily_usd = 20.0
+# monthly_usd = 200.0
+# on_exceeded = "fallback"
+
+# Latency objectives per route type, over whole turns (request to last byte).
+# Violations and recoveries are logged (and POSTed to webhook if set); compliance
+# is exported on /metrics.
+# [[slos]]
+# route = "think"
+# percentile = 90
+# max_latency_secs = 30.0
+# window_minutes = 60          # rolling window
+# min_samples = 20             # turns needed before the SLO is evaluated
+# webhook = "https://hooks.slack.com/services/..."
 "#.to_string()
     }
 
-    /// Resolve environment variables in configuration
-    fn resolve_env_vars(&mut self) -> Result<()> {
+    /// Resolve environment variables in configuration. Unset provider key variables
+    /// are left as `$VAR` for validation to report.
+    pub(crate) fn resolve_env_vars(&mut self) {
         // Resolve server API key
         if let Some(ref key) = self.server.api_key {
-            if key.starts_with('$') {
-                let env_var = &key[1..];
+            if let Some(env_var) = key.strip_prefix('$') {
                 self.server.api_key = std::env::var(env_var).ok();
             }
         }
 
+        // Resolve client API keys; unset variables stay `$VAR` for validation to report
+        if let Some(auth) = &mut self.server.auth {
+            for key in &mut auth.api_keys {
+                if let Some(value) = key.strip_prefix('$').and_then(|env_var| std::env::var(env_var).ok()) {
+                    *key = value;
+                }
+            }
+        }
+
+        // Resolve the admin password; an unset variable stays `$VAR` for validation to report
+        if let Some(admin) = &mut self.server.admin {
+            if let Some(value) = admin.password.strip_prefix('$').and_then(|env_var| std::env::var(env_var).ok()) {
+                admin.password = value;
+            }
+        }
+
         // Resolve provider API keys (only for enabled providers)
         for provider in &mut self.providers {
             // Skip disabled providers
@@ -333,19 +1615,12 @@ default = "placeholder-model"
             }
 
             // Only resolve env vars for API key auth
-            if let Some(ref api_key) = provider.api_key {
-                if api_key.starts_with('$') {
-                    let env_var = &api_key[1..];
-                    if let Ok(value) = std::env::var(env_var) {
-                        provider.api_key = Some(value);
-                    } else {
-                        anyhow::bail!("Environment variable {} not found for provider {}", env_var, provider.name);
-                    }
+            if let Some(env_var) = provider.api_key().and_then(|key| key.strip_prefix('$')) {
+                if let Ok(value) = std::env::var(env_var) {
+                    provider.api_key = Some(value.into());
                 }
             }
         }
-
-        Ok(())
     }
 }
 
@@ -388,3 +1663,243 @@ default = "placeholder-model"
 //         assert_eq!(config.router.default, "default");
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fallback_model_config() {
+        let mut config: AppConfig = toml::from_str(
+            r#"
+[router]
+default = "glm-4.6"
+
+[[router.fallback_mappings]]
+provider = "openrouter"
+
+[[router.fallback_mappings]]
+provider = "backup"
+priority = 2
+
+[[models]]
+name = "glm-4.6"
+mappings = []
+"#,
+        )
+        .unwrap();
+
+        assert!(config.find_model("GLM-4.6").is_some());
+        assert!(config.find_model("brand-new-model").is_none());
+
+        let fallback = config.fallback_model_config("brand-new-model").unwrap();
+        assert_eq!(fallback.name, "brand-new-model");
+        let mappings: Vec<(u32, &str, &str)> = fallback
+            .mappings
+            .iter()
+            .map(|m| (m.priority, m.provider.as_str(), m.actual_model.as_str()))
+            .collect();
+        assert_eq!(
+            mappings,
+            vec![(1, "openrouter", "brand-new-model"), (2, "backup", "brand-new-model")]
+        );
+
+        config.router.fallback_mappings.clear();
+        assert!(config.fallback_model_config("brand-new-model").is_none());
+    }
+
+    #[test]
+    fn test_synthetic_stream_delay_is_capped() {
+        let pacing = SyntheticStreamConfig { chunk_chars: 64, delay_ms: 10, max_total_delay_ms: 2000 };
+        assert_eq!(pacing.delay_per_delta(0), std::time::Duration::ZERO);
+        assert_eq!(pacing.delay_per_delta(50), std::time::Duration::from_millis(10));
+        assert_eq!(pacing.delay_per_delta(1000), std::time::Duration::from_millis(2));
+    }
+
+    #[test]
+    fn test_resolve_actual_model() {
+        let mapping = |actual_model: &str| ModelMapping {
+            priority: 1,
+            provider: "p".to_string(),
+            actual_model: actual_model.to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(mapping("glm-4.6").resolve_actual_model("claude-sonnet-4-5"), "glm-4.6");
+        assert_eq!(mapping("{requested}").resolve_actual_model("claude-sonnet-4-5"), "claude-sonnet-4-5");
+        assert_eq!(
+            mapping("anthropic/{requested|strip_prefix:claude-}").resolve_actual_model("claude-sonnet-4-5"),
+            "anthropic/sonnet-4-5"
+        );
+        assert_eq!(
+            mapping("{requested | strip_suffix:-latest | replace:-:. | upper}").resolve_actual_model("gpt-5-latest"),
+            "GPT.5"
+        );
+        // Unknown placeholders and filters pass through
+        assert_eq!(mapping("{other}-{requested|bogus}").resolve_actual_model("m"), "{other}-m");
+        assert_eq!(mapping("broken{requested").resolve_actual_model("m"), "broken{requested");
+    }
+
+    #[test]
+    fn test_load_env_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing/config.toml");
+        let vars = vec![
+            ("CCM_ROUTER_DEFAULT".to_string(), "glm-4.6".to_string()),
+            ("CCM_SERVER_PORT".to_string(), "9999".to_string()),
+        ];
+
+        let config = AppConfig::load(&path, &vars).unwrap();
+        assert_eq!(config.router.default, "glm-4.6");
+        assert_eq!(config.server.port, 9999);
+        assert_eq!(config.server.host, "127.0.0.1");
+        // No config file is created for env-only configuration
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_load_file_with_env_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[server]\nport = 1234\n\n[router]\ndefault = \"a\"\n").unwrap();
+
+        let vars = vec![("CCM_ROUTER_THINK".to_string(), "b".to_string())];
+        let config = AppConfig::load(&path, &vars).unwrap();
+        assert_eq!(config.server.port, 1234);
+        assert_eq!(config.router.default, "a");
+        assert_eq!(config.router.think.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn test_route_provider_filter() {
+        let config: AppConfig = toml::from_str(
+            r#"
+[server]
+[router]
+default = "m"
+
+[router.route_providers.think]
+allow = ["anthropic", "openrouter"]
+deny = ["openrouter"]
+
+[router.route_providers.web_search]
+deny = ["cheap"]
+"#,
+        )
+        .unwrap();
+
+        let think = config.router.provider_filter("think").unwrap();
+        assert!(think.permits("anthropic"));
+        assert!(!think.permits("openrouter"));
+        assert!(!think.permits("cheap"));
+
+        let search = config.router.provider_filter("web-search").unwrap();
+        assert!(search.permits("anthropic"));
+        assert!(!search.permits("cheap"));
+
+        assert!(config.router.provider_filter("default").is_none());
+    }
+
+    #[test]
+    fn test_mapping_routes() {
+        let mapping: ModelMapping = toml::from_str(
+            r#"
+priority = 1
+provider = "free"
+actual_model = "m"
+routes = ["background", "Web_Search"]
+"#,
+        )
+        .unwrap();
+        assert!(mapping.serves_route("background"));
+        assert!(mapping.serves_route("web-search"));
+        assert!(!mapping.serves_route("think"));
+        assert!(ModelMapping::default().serves_route("think"));
+    }
+
+    #[test]
+    fn test_mapping_extra_body() {
+        let mapping: ModelMapping = toml::from_str(
+            r#"
+priority = 1
+provider = "openrouter"
+actual_model = "m"
+extra_body = { top_k = 20, provider = { order = ["groq"], allow_fallbacks = false } }
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::Value::Object(mapping.extra_body.unwrap()),
+            serde_json::json!({"top_k": 20, "provider": {"order": ["groq"], "allow_fallbacks": false}})
+        );
+    }
+
+    #[test]
+    fn test_residency_policy() {
+        let config: AppConfig = toml::from_str(
+            r#"
+[router]
+default = "m"
+residency = ["eu", "local"]
+
+[[providers]]
+name = "eu-host"
+provider_type = "openai"
+region = "EU"
+models = []
+
+[[providers]]
+name = "openai"
+provider_type = "openai"
+region = "us"
+models = []
+
+[[providers]]
+name = "untagged"
+provider_type = "openai"
+models = []
+
+[[models]]
+name = "m"
+residency = ["us"]
+mappings = []
+"#,
+        )
+        .unwrap();
+
+        let global = config.residency_for(None);
+        assert!(config.residency_permits(global, "eu-host"));
+        assert!(!config.residency_permits(global, "openai"));
+        assert!(!config.residency_permits(global, "untagged"));
+
+        let model = config.residency_for(config.find_model("m"));
+        assert!(config.residency_permits(model, "openai"));
+        assert!(!config.residency_permits(model, "eu-host"));
+
+        assert!(config.residency_permits(&[], "untagged"));
+    }
+
+    #[test]
+    fn test_price_lookup() {
+        let config: AppConfig = toml::from_str(
+            r#"
+[router]
+default = "m"
+
+[pricing."glm-4.6"]
+input = 0.6
+output = 2.2
+
+[pricing."openrouter/z-ai/glm-4.6"]
+input = 0.5
+output = 1.75
+cache_read = 0.11
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.price_for("zai", "glm-4.6").unwrap().input, 0.6);
+        assert_eq!(config.price_for("openrouter", "z-ai/glm-4.6").unwrap().cache_read, Some(0.11));
+        assert!(config.price_for("openrouter", "glm-4.5").is_none());
+    }
+}
@@ -0,0 +1,278 @@
+//! `ccm reconcile`: compare the spend providers report through their own usage
+//! APIs with the cost CCM recorded in its usage history, and flag drift (usually
+//! a stale `[pricing]` entry).
+//!
+//! Supported sources:
+//! - OpenRouter: `GET /key` (`usage_daily` / `usage_monthly` of the configured key)
+//! - Anthropic: the Admin API cost report (organization-wide, needs an admin key)
+
+use crate::cli::AppConfig;
+use crate::message_tracing::usage::ProviderCost;
+use crate::message_tracing::UsageStore;
+use crate::providers::{AuthType, ProviderConfig};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Utc};
+use serde_json::Value;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const OPENROUTER_BASE_URL: &str = "https://openrouter.ai/api/v1";
+const ANTHROPIC_COST_REPORT_URL: &str = "https://api.anthropic.com/v1/organizations/cost_report";
+/// Differences below this are rounding, whatever the percentage
+const MIN_DRIFT_USD: f64 = 0.01;
+
+/// Reporting window (UTC, like the providers' own reports)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Period {
+    /// Since midnight
+    Day,
+    /// Since the first of the month
+    Month,
+}
+
+impl Period {
+    pub fn start(self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let today = now.date_naive();
+        let first = match self {
+            Period::Day => today,
+            Period::Month => today.with_day(1).expect("day 1 exists in every month"),
+        };
+        first.and_hms_opt(0, 0, 0).expect("midnight exists").and_utc()
+    }
+}
+
+/// Provider-reported vs locally recorded spend for one usage source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reconciliation {
+    pub source: String,
+    /// CCM providers whose local cost is compared against the source
+    pub providers: Vec<String>,
+    pub reported_usd: f64,
+    pub local_usd: f64,
+    /// Requests with no `[pricing]` entry, missing from `local_usd`
+    pub unpriced_requests: u64,
+}
+
+impl Reconciliation {
+    /// Local minus reported, as a percentage of reported (None when nothing was reported)
+    pub fn drift_percent(&self) -> Option<f64> {
+        (self.reported_usd > 0.0).then(|| (self.local_usd - self.reported_usd) / self.reported_usd * 100.0)
+    }
+
+    pub fn drifted(&self, threshold_percent: f64) -> bool {
+        (self.local_usd - self.reported_usd).abs() >= MIN_DRIFT_USD
+            && self.drift_percent().is_none_or(|drift| drift.abs() > threshold_percent)
+    }
+}
+
+/// Everything `ccm reconcile` found
+#[derive(Debug, Default)]
+pub struct Report {
+    pub rows: Vec<Reconciliation>,
+    /// Providers with recorded spend but no usage API to check it against
+    pub unsupported: Vec<String>,
+    /// Sources that could not be fetched
+    pub errors: Vec<String>,
+}
+
+fn is_openrouter(provider: &ProviderConfig) -> bool {
+    provider.provider_type == "openrouter"
+        || provider.base_url.as_deref().is_some_and(|url| url.contains("openrouter.ai"))
+}
+
+/// Pay-as-you-go Anthropic providers (subscription OAuth isn't in the cost report)
+fn is_anthropic_api(provider: &ProviderConfig) -> bool {
+    provider.provider_type == "anthropic"
+        && provider.auth_type == AuthType::ApiKey
+        && provider.base_url.as_deref().is_none_or(|url| url.contains("api.anthropic.com"))
+}
+
+/// `usage_daily` / `usage_monthly` from OpenRouter's `GET /key` response
+pub fn parse_openrouter_usage(body: &Value, period: Period) -> Result<f64> {
+    let field = match period {
+        Period::Day => "usage_daily",
+        Period::Month => "usage_monthly",
+    };
+    body["data"][field].as_f64().with_context(|| format!("response has no data.{}", field))
+}
+
+/// Sum of one page of an Anthropic cost report; amounts are decimal strings in cents
+pub fn parse_anthropic_cost_page(body: &Value) -> Result<f64> {
+    let buckets = body["data"].as_array().context("response has no data array")?;
+    let mut cents = 0.0;
+    for result in buckets.iter().filter_map(|bucket| bucket["results"].as_array()).flatten() {
+        let amount = result["amount"].as_str().context("cost result has no amount")?;
+        cents += amount.parse::<f64>().with_context(|| format!("invalid amount {:?}", amount))?;
+    }
+    Ok(cents / 100.0)
+}
+
+async fn fetch_openrouter(client: &reqwest::Client, provider: &ProviderConfig, period: Period) -> Result<f64> {
+    let api_key = provider.api_key().context("no api_key configured")?;
+    let base_url = provider.base_url.as_deref().unwrap_or(OPENROUTER_BASE_URL).trim_end_matches('/');
+    let response = client.get(format!("{}/key", base_url)).bearer_auth(api_key).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!("HTTP {}: {}", status, response.text().await.unwrap_or_default());
+    }
+    parse_openrouter_usage(&response.json().await?, period)
+}
+
+async fn fetch_anthropic(client: &reqwest::Client, admin_key: &str, since: DateTime<Utc>) -> Result<f64> {
+    let mut total = 0.0;
+    let mut page: Option<String> = None;
+    loop {
+        let mut query = vec![
+            ("starting_at", since.format("%Y-%m-%dT%H:%M:%SZ").to_string()),
+            ("bucket_width", "1d".to_string()),
+        ];
+        if let Some(page) = page.take() {
+            query.push(("page", page));
+        }
+        let response = client
+            .get(ANTHROPIC_COST_REPORT_URL)
+            .header("x-api-key", admin_key)
+            .header("anthropic-version", "2023-06-01")
+            .query(&query)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("HTTP {}: {}", status, response.text().await.unwrap_or_default());
+        }
+        let body: Value = response.json().await?;
+        total += parse_anthropic_cost_page(&body)?;
+        match body["next_page"].as_str() {
+            Some(next) if body["has_more"].as_bool() == Some(true) => page = Some(next.to_string()),
+            _ => return Ok(total),
+        }
+    }
+}
+
+/// Local cost of `providers`, summed
+fn local_cost(costs: &[ProviderCost], providers: &[String]) -> (f64, u64) {
+    costs
+        .iter()
+        .filter(|cost| providers.contains(&cost.provider))
+        .fold((0.0, 0), |(usd, unpriced), cost| (usd + cost.cost_usd, unpriced + cost.unpriced_requests))
+}
+
+/// Fetch every supported source for `period` and pair it with the usage history
+pub async fn run(config: &AppConfig, period: Period, anthropic_admin_key: Option<&str>) -> Result<Report> {
+    if !config.server.usage.enabled {
+        anyhow::bail!("Usage history is disabled; enable [server.usage] so there is local cost to compare");
+    }
+    let costs = UsageStore::new(&config.server.usage).cost_by_provider(period.start(Utc::now()))?;
+    let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+    let mut report = Report::default();
+    let enabled: Vec<&ProviderConfig> = config.providers.iter().filter(|p| p.is_enabled()).collect();
+
+    for provider in enabled.iter().filter(|p| is_openrouter(p)) {
+        let source = format!("OpenRouter key ({})", provider.name);
+        match fetch_openrouter(&client, provider, period).await {
+            Ok(reported_usd) => {
+                let providers = vec![provider.name.clone()];
+                let (local_usd, unpriced_requests) = local_cost(&costs, &providers);
+                report.rows.push(Reconciliation { source, providers, reported_usd, local_usd, unpriced_requests });
+            }
+            Err(e) => report.errors.push(format!("{}: {:#}", source, e)),
+        }
+    }
+
+    let anthropic: Vec<String> = enabled.iter().filter(|p| is_anthropic_api(p)).map(|p| p.name.clone()).collect();
+    if !anthropic.is_empty() {
+        let source = "Anthropic organization".to_string();
+        match anthropic_admin_key {
+            Some(admin_key) => match fetch_anthropic(&client, admin_key, period.start(Utc::now())).await {
+                Ok(reported_usd) => {
+                    let (local_usd, unpriced_requests) = local_cost(&costs, &anthropic);
+                    report.rows.push(Reconciliation {
+                        source,
+                        providers: anthropic.clone(),
+                        reported_usd,
+                        local_usd,
+                        unpriced_requests,
+                    });
+                }
+                Err(e) => report.errors.push(format!("{}: {:#}", source, e)),
+            },
+            None => report
+                .errors
+                .push(format!("{}: skipped, needs an Admin API key (--anthropic-admin-key or ANTHROPIC_ADMIN_KEY)", source)),
+        }
+    }
+
+    let checked: Vec<&String> = report.rows.iter().flat_map(|row| &row.providers).chain(&anthropic).collect();
+    report.unsupported = costs
+        .iter()
+        .filter(|cost| cost.cost_usd > 0.0 && !checked.contains(&&cost.provider))
+        .map(|cost| cost.provider.clone())
+        .collect();
+    Ok(report)
+}
+
+/// Reported vs local table, with drifting rows marked
+pub fn table(rows: &[Reconciliation], threshold_percent: f64) -> String {
+    let width = rows.iter().map(|row| row.source.len()).max().unwrap_or(0).max(6);
+    let mut out = format!("   {:<width$}  {:>10}  {:>10}  {:>8}\n", "Source", "Reported", "Local", "Drift", width = width);
+    for row in rows {
+        let mark = if row.drifted(threshold_percent) { "⚠️ " } else { "✅" };
+        let drift = row.drift_percent().map(|d| format!("{:+.1}%", d)).unwrap_or_else(|| "-".to_string());
+        out.push_str(&format!(
+            "{} {:<width$}  {:>10}  {:>10}  {:>8}\n",
+            mark,
+            row.source,
+            format!("${:.2}", row.reported_usd),
+            format!("${:.2}", row.local_usd),
+            drift,
+            width = width
+        ));
+        if row.unpriced_requests > 0 {
+            out.push_str(&format!(
+                "   {} request(s) to models without a [pricing] entry are not in the local cost\n",
+                row.unpriced_requests
+            ));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_reconcile_parsing_and_drift() {
+        let key = json!({"data": {"label": "sk-or-v1-abc", "usage": 40.0, "usage_daily": 1.25, "usage_monthly": 12.5}});
+        assert_eq!(parse_openrouter_usage(&key, Period::Day).unwrap(), 1.25);
+        assert_eq!(parse_openrouter_usage(&key, Period::Month).unwrap(), 12.5);
+
+        let page = json!({
+            "data": [
+                {"starting_at": "2025-06-01T00:00:00Z", "results": [{"currency": "USD", "amount": "1050.5"}, {"currency": "USD", "amount": "49.5"}]},
+                {"starting_at": "2025-06-02T00:00:00Z", "results": []}
+            ],
+            "has_more": false
+        });
+        assert!((parse_anthropic_cost_page(&page).unwrap() - 11.0).abs() < 1e-9);
+
+        let row = |reported_usd, local_usd| Reconciliation {
+            source: "OpenRouter key (openrouter)".to_string(),
+            providers: vec!["openrouter".to_string()],
+            reported_usd,
+            local_usd,
+            unpriced_requests: 0,
+        };
+        assert!(!row(10.0, 9.5).drifted(10.0));
+        assert!(row(10.0, 8.0).drifted(10.0));
+        assert!((row(10.0, 8.0).drift_percent().unwrap() + 20.0).abs() < 1e-9);
+        // Tiny amounts don't trip the percentage, spend with nothing reported does
+        assert!(!row(0.02, 0.015).drifted(10.0));
+        assert!(row(0.0, 3.0).drifted(10.0));
+
+        let now = DateTime::parse_from_rfc3339("2025-06-17T15:30:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(Period::Month.start(now).to_rfc3339(), "2025-06-01T00:00:00+00:00");
+        assert_eq!(Period::Day.start(now).to_rfc3339(), "2025-06-17T00:00:00+00:00");
+    }
+}
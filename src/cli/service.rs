@@ -0,0 +1,386 @@
+//! `ccm service`: run CCM as a per-user service that starts at login and restarts
+//! after a crash. On Linux this is a systemd user unit
+//! (`~/.config/systemd/user/claude-code-mux.service`); on macOS a launchd agent
+//! (`~/Library/LaunchAgents/com.claude-code-mux.plist`). Either runs `ccm start` in
+//! the foreground with absolute `--config` and `--state-dir` paths, and appends its
+//! output to the same log file as `ccm start --daemon`. A named instance (`--name work`)
+//! gets its own unit (`claude-code-mux-work.service`) or label (`com.claude-code-mux.work`).
+
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// systemd unit name of an instance
+pub fn systemd_unit_name(instance: Option<&str>) -> String {
+    match instance {
+        Some(name) => format!("claude-code-mux-{}.service", name),
+        None => "claude-code-mux.service".to_string(),
+    }
+}
+
+/// launchd job label of an instance
+pub fn launchd_label(instance: Option<&str>) -> String {
+    match instance {
+        Some(name) => format!("com.claude-code-mux.{}", name),
+        None => "com.claude-code-mux".to_string(),
+    }
+}
+
+/// Unit name of the selected instance
+fn unit_name() -> String {
+    systemd_unit_name(crate::paths::instance())
+}
+
+/// The service manager of this platform
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Manager {
+    Systemd,
+    Launchd,
+}
+
+impl Manager {
+    pub fn current() -> Result<Self> {
+        if cfg!(target_os = "macos") {
+            Ok(Manager::Launchd)
+        } else if cfg!(target_os = "linux") {
+            Ok(Manager::Systemd)
+        } else {
+            bail!("'ccm service' supports systemd (Linux) and launchd (macOS) only")
+        }
+    }
+
+    /// Where the unit or plist is installed
+    pub fn unit_path(self) -> Result<PathBuf> {
+        match self {
+            Manager::Systemd => {
+                let config = dirs::config_dir().context("Failed to get the config directory")?;
+                Ok(config.join("systemd").join("user").join(unit_name()))
+            }
+            Manager::Launchd => {
+                let home = dirs::home_dir().context("Failed to get home directory")?;
+                let plist = format!("{}.plist", launchd_label(crate::paths::instance()));
+                Ok(home.join("Library").join("LaunchAgents").join(plist))
+            }
+        }
+    }
+
+    /// Unit file or plist for `spec`
+    pub fn render(self, spec: &ServiceSpec) -> String {
+        match self {
+            Manager::Systemd => systemd_unit(spec),
+            Manager::Launchd => launchd_plist(spec),
+        }
+    }
+
+    /// Start the service now and at every login
+    pub fn enable(self) -> Result<()> {
+        match self {
+            Manager::Systemd => {
+                systemctl(&["daemon-reload"])?;
+                systemctl(&["enable", &unit_name()])?;
+                // `enable --now` leaves a running unit alone; restart picks up a changed unit
+                systemctl(&["restart", &unit_name()])
+            }
+            Manager::Launchd => {
+                let plist = self.unit_path()?;
+                launchctl(&["enable", &launchd_target()])?;
+                // Unload first so a changed plist is read again (fails if it isn't loaded)
+                let _ = launchctl(&["bootout", &launchd_target()]);
+                launchctl(&["bootstrap", &launchd_domain(), &plist.display().to_string()])
+            }
+        }
+    }
+
+    /// Stop the service and don't start it at login
+    pub fn disable(self) -> Result<()> {
+        match self {
+            Manager::Systemd => systemctl(&["disable", "--now", &unit_name()]),
+            Manager::Launchd => {
+                let _ = launchctl(&["bootout", &launchd_target()]);
+                launchctl(&["disable", &launchd_target()])
+            }
+        }
+    }
+
+    /// Print the service manager's view of the service
+    pub fn print_status(self) -> Result<()> {
+        let mut cmd = match self {
+            Manager::Systemd => {
+                let mut cmd = Command::new("systemctl");
+                cmd.args(["--user", "status", "--no-pager", &unit_name()]);
+                cmd
+            }
+            Manager::Launchd => {
+                let mut cmd = Command::new("launchctl");
+                cmd.args(["print", &launchd_target()]);
+                cmd
+            }
+        };
+        // `systemctl status` exits non-zero for a stopped unit, which is still a valid answer
+        cmd.status().with_context(|| format!("Failed to run {:?}", cmd.get_program()))?;
+        Ok(())
+    }
+}
+
+/// What the service runs
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceSpec {
+    pub exe: PathBuf,
+    pub config_path: PathBuf,
+    /// State directory shared by all instances
+    pub state_dir: PathBuf,
+    pub log_path: PathBuf,
+    /// Named instance (`--name`)
+    pub instance: Option<String>,
+}
+
+impl ServiceSpec {
+    /// This binary with the given config and the current state directory and
+    /// instance, paths absolute
+    pub fn current(config_path: &Path, log_path: &Path) -> Result<Self> {
+        Ok(Self {
+            exe: std::env::current_exe()?,
+            config_path: std::path::absolute(config_path)?,
+            state_dir: std::path::absolute(crate::paths::base_state_dir())?,
+            log_path: std::path::absolute(log_path)?,
+            instance: crate::paths::instance().map(str::to_string),
+        })
+    }
+
+    /// Command line of the service (`--config` is not a global flag, so it goes first)
+    fn args(&self) -> Vec<String> {
+        let mut args: Vec<String> = [
+            self.exe.as_path(),
+            Path::new("--config"),
+            &self.config_path,
+            Path::new("start"),
+            Path::new("--state-dir"),
+            &self.state_dir,
+        ]
+        .iter()
+        .map(|arg| arg.display().to_string())
+        .collect();
+        if let Some(ref name) = self.instance {
+            args.extend(["--name".to_string(), name.clone()]);
+        }
+        args
+    }
+
+    fn description(&self) -> String {
+        match self.instance {
+            Some(ref name) => format!("Claude Code Mux ({})", name),
+            None => "Claude Code Mux".to_string(),
+        }
+    }
+}
+
+/// systemd user unit. SIGTERM (`ccm stop`, `systemctl stop`) counts as a clean
+/// exit, so only crashes are restarted.
+pub fn systemd_unit(spec: &ServiceSpec) -> String {
+    let exec: Vec<String> = spec.args().iter().map(|arg| systemd_quote(arg)).collect();
+    let log = systemd_escape(&spec.log_path.display().to_string());
+    format!(
+        "[Unit]\n\
+         Description={}\n\
+         \n\
+         [Service]\n\
+         Type=simple\n\
+         ExecStart={}\n\
+         Restart=on-failure\n\
+         RestartSec=5s\n\
+         StandardOutput=append:{}\n\
+         StandardError=append:{}\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        spec.description(),
+        exec.join(" "),
+        log,
+        log
+    )
+}
+
+/// launchd agent, started at login and restarted when it exits unsuccessfully
+pub fn launchd_plist(spec: &ServiceSpec) -> String {
+    let args: String = spec
+        .args()
+        .iter()
+        .map(|arg| format!("        <string>{}</string>\n", xml_escape(arg)))
+        .collect();
+    let log = xml_escape(&spec.log_path.display().to_string());
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+{}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>ThrottleInterval</key>
+    <integer>5</integer>
+    <key>StandardOutPath</key>
+    <string>{}</string>
+    <key>StandardErrorPath</key>
+    <string>{}</string>
+</dict>
+</plist>
+"#,
+        launchd_label(spec.instance.as_deref()),
+        args,
+        log,
+        log
+    )
+}
+
+/// Whether a unit or plist is installed
+pub fn installed() -> bool {
+    Manager::current().and_then(Manager::unit_path).is_ok_and(|path| path.exists())
+}
+
+/// Write the unit or plist, then enable and (re)start the service
+pub fn install(manager: Manager, spec: &ServiceSpec) -> Result<PathBuf> {
+    let path = manager.unit_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    if let Some(dir) = spec.log_path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    std::fs::write(&path, manager.render(spec)).with_context(|| format!("Failed to write {}", path.display()))?;
+    manager.enable()?;
+    Ok(path)
+}
+
+/// Stop and disable the service and remove its unit or plist. Returns the removed
+/// path, or None if nothing was installed.
+pub fn uninstall(manager: Manager) -> Result<Option<PathBuf>> {
+    let path = manager.unit_path()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    if let Err(e) = manager.disable() {
+        eprintln!("Warning: {}", e);
+    }
+    std::fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    if manager == Manager::Systemd {
+        systemctl(&["daemon-reload"])?;
+    }
+    Ok(Some(path))
+}
+
+fn systemctl(args: &[&str]) -> Result<()> {
+    let mut full = vec!["--user"];
+    full.extend_from_slice(args);
+    run("systemctl", &full)
+}
+
+fn launchctl(args: &[&str]) -> Result<()> {
+    run("launchctl", args)
+}
+
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        bail!(
+            "'{} {}' failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// launchd domain of the current user's GUI session
+fn launchd_domain() -> String {
+    #[cfg(unix)]
+    let uid = unsafe { nix::libc::getuid() };
+    #[cfg(not(unix))]
+    let uid = 0;
+    format!("gui/{}", uid)
+}
+
+fn launchd_target() -> String {
+    format!("{}/{}", launchd_domain(), launchd_label(crate::paths::instance()))
+}
+
+/// `%` starts a specifier in unit files
+fn systemd_escape(value: &str) -> String {
+    value.replace('%', "%%")
+}
+
+/// One double-quoted `ExecStart` argument
+fn systemd_quote(arg: &str) -> String {
+    format!("\"{}\"", systemd_escape(arg).replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> ServiceSpec {
+        ServiceSpec {
+            exe: PathBuf::from("/opt/ccm/bin/ccm"),
+            config_path: PathBuf::from("/home/me/My Configs/ccm & co.toml"),
+            state_dir: PathBuf::from("/home/me/.claude-code-mux"),
+            log_path: PathBuf::from("/home/me/.claude-code-mux/logs/ccm.log"),
+            instance: None,
+        }
+    }
+
+    #[test]
+    fn test_systemd_unit() {
+        let unit = systemd_unit(&spec());
+        assert!(unit.contains(
+            "ExecStart=\"/opt/ccm/bin/ccm\" \"--config\" \"/home/me/My Configs/ccm & co.toml\" \"start\" \"--state-dir\" \"/home/me/.claude-code-mux\"\n"
+        ));
+        assert!(unit.contains("StandardOutput=append:/home/me/.claude-code-mux/logs/ccm.log\n"));
+        assert!(unit.contains("Restart=on-failure\n"));
+        assert!(unit.contains("WantedBy=default.target\n"));
+        assert!(unit.contains("Description=Claude Code Mux\n"));
+
+        assert_eq!(systemd_quote("50%\"off\""), "\"50%%\\\"off\\\"\"");
+    }
+
+    #[test]
+    fn test_launchd_plist() {
+        let plist = launchd_plist(&spec());
+        assert!(plist.contains("<string>com.claude-code-mux</string>"));
+        assert!(plist.contains(
+            "        <string>/opt/ccm/bin/ccm</string>\n        <string>--config</string>\n        <string>/home/me/My Configs/ccm &amp; co.toml</string>\n        <string>start</string>\n"
+        ));
+        assert!(plist.contains("<key>StandardErrorPath</key>\n    <string>/home/me/.claude-code-mux/logs/ccm.log</string>"));
+        assert!(plist.contains("<key>SuccessfulExit</key>\n        <false/>"));
+    }
+
+    #[test]
+    fn test_named_instance() {
+        let spec = ServiceSpec { instance: Some("work".to_string()), ..spec() };
+        let unit = systemd_unit(&spec);
+        assert!(unit.contains("Description=Claude Code Mux (work)\n"));
+        assert!(unit.contains("\"--state-dir\" \"/home/me/.claude-code-mux\" \"--name\" \"work\"\n"));
+        assert!(launchd_plist(&spec).contains("<string>com.claude-code-mux.work</string>"));
+        assert_eq!(systemd_unit_name(Some("work")), "claude-code-mux-work.service");
+        assert_eq!(systemd_unit_name(None), "claude-code-mux.service");
+    }
+}
@@ -0,0 +1,357 @@
+//! `ccm top`: a live dashboard of the running server, like htop for the router.
+//! Polls `/api/debug/stats` and redraws per-provider request rates, latency,
+//! error rates, token throughput, spend and budgets. Rates are the difference
+//! between two polls, so the first frame shows totals only.
+//!
+//! Drawn with ratatui on the alternate screen in raw mode; `q`, Esc or Ctrl-C quits
+//! and the terminal is restored on every exit path, including errors and panics.
+
+use crate::pid;
+use crossterm::event::{Event, EventStream, KeyCode, KeyEventKind, KeyModifiers};
+use futures::StreamExt;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{LineGauge, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Width of a budget line, label and bar
+const GAUGE_WIDTH: u16 = 60;
+
+/// Provider table columns: header and width
+const COLUMNS: [(&str, u16); 9] = [
+    ("PROVIDER", 18),
+    ("REQUESTS", 8),
+    ("REQ/MIN", 8),
+    ("ERR%", 7),
+    ("LATENCY", 8),
+    ("IN TOK/S", 9),
+    ("OUT TOK/S", 10),
+    ("COST", 9),
+    ("CIRCUITS", 10),
+];
+
+/// One provider's figures, summed over its models
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProviderRow {
+    pub provider: String,
+    /// Requests since server start
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+    /// Failed fraction of the attempts in the health window (None without samples)
+    pub error_rate: Option<f64>,
+    pub avg_latency_ms: Option<u64>,
+    /// Models with an open circuit
+    pub open_circuits: usize,
+}
+
+fn row<'a>(rows: &'a mut BTreeMap<String, ProviderRow>, entry: &Value) -> &'a mut ProviderRow {
+    let provider = entry["provider"].as_str().unwrap_or("?");
+    rows.entry(provider.to_string())
+        .or_insert_with(|| ProviderRow { provider: provider.to_string(), ..Default::default() })
+}
+
+/// Fold the per provider/model entries of `/api/debug/stats` into one row per provider
+pub fn provider_rows(stats: &Value) -> Vec<ProviderRow> {
+    let entries = |field: &str| stats[field].as_array().cloned().unwrap_or_default();
+    let mut rows = BTreeMap::new();
+
+    for cost in entries("costs") {
+        let r = row(&mut rows, &cost);
+        r.requests += cost["requests"].as_u64().unwrap_or(0);
+        r.input_tokens += cost["input_tokens"].as_u64().unwrap_or(0);
+        r.output_tokens += cost["output_tokens"].as_u64().unwrap_or(0);
+        r.cost_usd += cost["cost_usd"].as_f64().unwrap_or(0.0);
+    }
+
+    // Error rate and latency weighted by each model's samples
+    let mut health: BTreeMap<String, (f64, f64, f64, f64)> = BTreeMap::new();
+    for stat in entries("provider_stats") {
+        row(&mut rows, &stat);
+        let samples = stat["samples"].as_f64().unwrap_or(0.0);
+        let successes = samples * stat["success_rate"].as_f64().unwrap_or(0.0);
+        let sums = health.entry(stat["provider"].as_str().unwrap_or("?").to_string()).or_default();
+        sums.0 += samples;
+        sums.1 += samples - successes;
+        if let Some(latency) = stat["avg_latency_ms"].as_f64() {
+            sums.2 += latency * successes;
+            sums.3 += successes;
+        }
+    }
+    for (provider, (samples, failures, latency_sum, successes)) in health {
+        let r = rows.get_mut(&provider).expect("row created above");
+        r.error_rate = (samples > 0.0).then(|| failures / samples);
+        r.avg_latency_ms = (successes > 0.0).then(|| (latency_sum / successes).round() as u64);
+    }
+
+    for circuit in entries("circuits") {
+        if circuit["state"].as_str() == Some("open") {
+            row(&mut rows, &circuit).open_circuits += 1;
+        }
+    }
+    rows.into_values().collect()
+}
+
+/// The table cells for one provider; rates need the previous poll and the seconds since it
+fn provider_cells(r: &ProviderRow, before: Option<&ProviderRow>, elapsed: Option<f64>) -> Vec<String> {
+    let rate = |now: u64, then: fn(&ProviderRow) -> u64, per: f64| match (elapsed, before) {
+        (Some(secs), Some(prev)) => format!("{:.1}", now.saturating_sub(then(prev)) as f64 / secs * per),
+        (Some(secs), None) => format!("{:.1}", now as f64 / secs * per),
+        (None, _) => "-".to_string(),
+    };
+    vec![
+        r.provider.clone(),
+        r.requests.to_string(),
+        rate(r.requests, |p| p.requests, 60.0),
+        r.error_rate.map(|e| format!("{:.1}", e * 100.0)).unwrap_or_else(|| "-".to_string()),
+        r.avg_latency_ms.map(|ms| format!("{}ms", ms)).unwrap_or_else(|| "-".to_string()),
+        rate(r.input_tokens, |p| p.input_tokens, 1.0),
+        rate(r.output_tokens, |p| p.output_tokens, 1.0),
+        format!("${:.2}", r.cost_usd),
+        if r.open_circuits > 0 { format!("⚡ {} open", r.open_circuits) } else { String::new() },
+    ]
+}
+
+/// A budget's spend against its limit, coloured as it nears the limit
+fn budget_gauge(label: &str, spent: f64, limit: Option<f64>) -> Option<LineGauge<'static>> {
+    let limit = limit?;
+    let fraction = if limit > 0.0 { spent / limit } else { 1.0 };
+    let color = if fraction >= 1.0 { Color::Red } else if fraction >= 0.8 { Color::Yellow } else { Color::Green };
+    Some(
+        LineGauge::default()
+            .ratio(fraction.clamp(0.0, 1.0))
+            .label(format!("  {:<6} ${:>8.2} / ${:.2} ({:>3.0}%) ", label, spent, limit, fraction * 100.0))
+            .filled_style(Style::new().fg(color)),
+    )
+}
+
+/// One frame of the dashboard. `stats` is the last poll (None if the server didn't
+/// answer), `previous` the poll before it and the seconds between them.
+pub fn draw(frame: &mut Frame, stats: Option<&Value>, previous: Option<(&Value, f64)>, target: &str, interval: Duration) {
+    let footer = Line::from(format!(" q quit   refreshing every {}s", interval.as_secs())).dim();
+    let Some(stats) = stats else {
+        let [header, footer_area] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let text = vec![
+            Line::from(format!("ccm top - {}", target)).bold(),
+            Line::from(""),
+            Line::from(format!("⚠️  No server answering on {} (retrying every {}s)", target, interval.as_secs())).yellow(),
+        ];
+        frame.render_widget(Paragraph::new(text), header);
+        frame.render_widget(footer, footer_area);
+        return;
+    };
+
+    let rows = provider_rows(stats);
+    let before: BTreeMap<String, ProviderRow> = previous
+        .map(|(stats, _)| provider_rows(stats).into_iter().map(|r| (r.provider.clone(), r)).collect())
+        .unwrap_or_default();
+    let elapsed = previous.map(|(_, secs)| secs).filter(|secs| *secs > 0.0);
+    let budgets = stats["budgets"].as_array().cloned().unwrap_or_default();
+    let recent = stats["recent_routes"].as_array().cloned().unwrap_or_default();
+    let recent = &recent[..recent.len().min(5)];
+
+    let gauges: Vec<(String, Vec<LineGauge>)> = budgets
+        .iter()
+        .map(|budget| {
+            let gauges = [
+                budget_gauge("today", budget["spent_today_usd"].as_f64().unwrap_or(0.0), budget["daily_usd"].as_f64()),
+                budget_gauge("month", budget["spent_this_month_usd"].as_f64().unwrap_or(0.0), budget["monthly_usd"].as_f64()),
+            ];
+            (budget["budget"].as_str().unwrap_or("?").to_string(), gauges.into_iter().flatten().collect())
+        })
+        .collect();
+    let section = |lines: usize| if lines == 0 { 0 } else { lines as u16 + 2 };
+    let budget_lines = gauges.iter().map(|(_, g)| 1 + g.len()).sum();
+    let [header, table, budget_area, recent_area, _, footer_area] = Layout::vertical([
+        Constraint::Length(2),
+        Constraint::Length(rows.len().max(1) as u16 + 1),
+        Constraint::Length(section(budget_lines)),
+        Constraint::Length(section(recent.len())),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let uptime = stats["uptime_secs"].as_u64().unwrap_or(0);
+    let title = format!(
+        "ccm top - {}   up {}h {:02}m   in flight {}   spend ${:.2}",
+        target,
+        uptime / 3600,
+        uptime % 3600 / 60,
+        stats["in_flight"].as_u64().unwrap_or(0),
+        stats["total_cost_usd"].as_f64().unwrap_or(0.0),
+    );
+    frame.render_widget(Line::from(title).bold(), header);
+
+    let right = |i: usize, text: String| if i == 0 { Line::from(text) } else { Line::from(text).right_aligned() };
+    let widths = COLUMNS.map(|(_, width)| Constraint::Length(width));
+    let header_row = Row::new(COLUMNS.iter().enumerate().map(|(i, (name, _))| right(i, name.to_string())))
+        .style(Style::new().add_modifier(Modifier::BOLD | Modifier::REVERSED));
+    let body: Vec<Row> = if rows.is_empty() {
+        vec![Row::new([Line::from("(no requests yet)").dim()])]
+    } else {
+        rows.iter()
+            .map(|r| {
+                let cells = provider_cells(r, before.get(&r.provider), elapsed);
+                let mut cells: Vec<Line> = cells.into_iter().enumerate().map(|(i, cell)| right(i, cell)).collect();
+                if r.open_circuits > 0 {
+                    cells[8] = cells[8].clone().red();
+                }
+                Row::new(cells)
+            })
+            .collect()
+    };
+    frame.render_widget(Table::new(body, widths).header(header_row), table);
+
+    if budget_lines > 0 {
+        let [title, area] = Layout::vertical([Constraint::Length(2), Constraint::Min(0)]).areas(budget_area);
+        frame.render_widget(Paragraph::new(vec![Line::from(""), Line::from("BUDGETS").bold()]), title);
+        let lines = Layout::vertical(vec![Constraint::Length(1); budget_lines]).split(area);
+        let mut line = 0;
+        for (name, gauges) in gauges {
+            frame.render_widget(Line::from(format!("  {}", name)), lines[line]);
+            line += 1;
+            for gauge in gauges {
+                let [bar] = Layout::horizontal([Constraint::Max(GAUGE_WIDTH)]).areas(lines[line]);
+                frame.render_widget(gauge, bar);
+                line += 1;
+            }
+        }
+    }
+
+    if !recent.is_empty() {
+        let mut lines = vec![Line::from(""), Line::from("RECENT").bold()];
+        lines.extend(recent.iter().map(|route| {
+            Line::from(format!(
+                "  {:<12} {} → {}/{}",
+                route["route_type"].as_str().unwrap_or("?"),
+                route["model"].as_str().unwrap_or("?"),
+                route["provider"].as_str().unwrap_or("?"),
+                route["actual_model"].as_str().unwrap_or("?"),
+            ))
+        }));
+        frame.render_widget(Paragraph::new(lines), recent_area);
+    }
+
+    frame.render_widget(footer, footer_area);
+}
+
+/// The terminal in raw mode on the alternate screen, restored when dropped
+struct Screen(DefaultTerminal);
+
+impl Screen {
+    fn enter() -> std::io::Result<Self> {
+        // Undo raw mode if entering the alternate screen fails
+        ratatui::try_init().map(Screen).inspect_err(|_| ratatui::restore())
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = self.0.show_cursor();
+        ratatui::restore();
+    }
+}
+
+/// Redraw every `interval` until `q`, Esc or Ctrl-C
+pub async fn run(host: &str, port: u16, interval: Duration) -> anyhow::Result<()> {
+    let target = format!("{}:{}", host, port);
+    let mut screen = Screen::enter()?;
+    let mut events = EventStream::new();
+    let mut ticker = tokio::time::interval(interval);
+    // The last poll and the one before it, for rates
+    let mut latest: Option<(Value, Instant)> = None;
+    let mut previous: Option<(Value, Instant)> = None;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                match pid::fetch_json(host, port, "/api/debug/stats").await.filter(|stats| stats.is_object()) {
+                    Some(stats) => previous = latest.replace((stats, Instant::now())),
+                    None => (latest, previous) = (None, None),
+                }
+            }
+            event = events.next() => match event {
+                Some(Ok(Event::Key(key))) if key.kind == KeyEventKind::Press => {
+                    let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                    if ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                        return Ok(());
+                    }
+                    continue;
+                }
+                Some(Ok(Event::Resize(..))) => {}
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(e.into()),
+                None => return Ok(()),
+            },
+        }
+
+        let stats = latest.as_ref().map(|(stats, _)| stats);
+        let before = match (&latest, &previous) {
+            (Some((_, now)), Some((stats, then))) => Some((stats, now.duration_since(*then).as_secs_f64())),
+            _ => None,
+        };
+        screen.0.draw(|frame| draw(frame, stats, before, &target, interval))?;
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_top_rows_and_rates() {
+        let stats = |requests: u64, output_tokens: u64| {
+            json!({
+                "uptime_secs": 3720,
+                "in_flight": 2,
+                "total_cost_usd": 1.5,
+                "costs": [
+                    {"provider": "zai", "model": "glm-4.6", "requests": requests, "input_tokens": 1000, "output_tokens": output_tokens, "cost_usd": 1.0},
+                    {"provider": "zai", "model": "glm-4.5-air", "requests": 10, "input_tokens": 0, "output_tokens": 0, "cost_usd": 0.5}
+                ],
+                "provider_stats": [
+                    {"provider": "zai", "model": "glm-4.6", "samples": 30, "success_rate": 0.9, "avg_latency_ms": 1000},
+                    {"provider": "zai", "model": "glm-4.5-air", "samples": 10, "success_rate": 1.0, "avg_latency_ms": 200}
+                ],
+                "circuits": [{"provider": "openrouter", "model": "x", "state": "open", "consecutive_failures": 5}],
+                "budgets": [{"budget": "provider zai", "daily_usd": 2.0, "spent_today_usd": 1.5, "monthly_usd": null, "spent_this_month_usd": 1.5}]
+            })
+        };
+
+        let rows = provider_rows(&stats(20, 500));
+        assert_eq!(rows.len(), 2);
+        let zai = &rows[1];
+        assert_eq!((zai.provider.as_str(), zai.requests, zai.output_tokens), ("zai", 30, 500));
+        assert!((zai.error_rate.unwrap() - 0.075).abs() < 1e-9);
+        // (1000ms * 27 + 200ms * 10) / 37 successes
+        assert_eq!(zai.avg_latency_ms, Some(784));
+        assert_eq!((rows[0].provider.as_str(), rows[0].open_circuits, rows[0].error_rate), ("openrouter", 1, None));
+
+        let first = stats(20, 500);
+        let frame = screen(Some(&stats(26, 800)), Some((&first, 30.0)));
+        let zai_line = frame.iter().find(|line| line.starts_with("zai")).unwrap();
+        let columns: Vec<&str> = zai_line.split_whitespace().collect();
+        // 6 requests in 30s, 300 output tokens in 30s
+        assert_eq!(columns, ["zai", "36", "12.0", "7.5", "784ms", "0.0", "10.0", "$1.50"]);
+        assert!(frame.iter().any(|line| line.starts_with("openrouter") && line.contains("1 open")));
+        assert!(frame.iter().any(|line| line.contains("today  $    1.50 / $2.00 ( 75%)")));
+        assert!(screen(Some(&first), None).iter().any(|line| line.starts_with("zai") && line.contains(" - ")));
+        assert!(screen(None, None).iter().any(|line| line.contains("No server answering on 127.0.0.1:13456")));
+    }
+
+    /// The dashboard drawn on a test terminal, one string per screen line
+    fn screen(stats: Option<&Value>, previous: Option<(&Value, f64)>) -> Vec<String> {
+        let mut terminal = ratatui::Terminal::new(ratatui::backend::TestBackend::new(100, 20)).unwrap();
+        terminal.draw(|frame| draw(frame, stats, previous, "127.0.0.1:13456", Duration::from_secs(2))).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| (0..buffer.area.width).map(|x| buffer[(x, y)].symbol()).collect::<String>())
+            .collect()
+    }
+}
@@ -0,0 +1,455 @@
+//! Startup validation: collect every config problem at once, with the section
+//! (and line, when it can be found in the file) each one comes from.
+
+use super::{route_key, AppConfig, MappingStrategy};
+use crate::models::RouteType;
+use crate::providers::prompt_cache::CACHE_TTLS;
+use crate::providers::registry::PROVIDER_TYPES;
+use crate::providers::AuthType;
+use std::fmt;
+use std::path::Path;
+
+/// One problem found in the configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigProblem {
+    /// Where the problem is, e.g. `[[providers]] "openai"`
+    pub section: String,
+    /// 1-based line of the section header in the config file
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{} (line {}): {}", self.section, line, self.message),
+            None => write!(f, "{}: {}", self.section, self.message),
+        }
+    }
+}
+
+/// Lines (1-based) of every `header` table header in the source, e.g. `[[providers]]`
+fn header_lines(source: &str, header: &str) -> Vec<usize> {
+    source
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            line.trim()
+                .strip_prefix(header)
+                .is_some_and(|rest| rest.trim().is_empty() || rest.trim_start().starts_with('#'))
+        })
+        .map(|(i, _)| i + 1)
+        .collect()
+}
+
+/// Check a loaded config for problems that would stop it from serving requests.
+/// `source` is the config file text, used only to report line numbers.
+pub fn validate(config: &AppConfig, source: Option<&str>) -> Vec<ConfigProblem> {
+    let source = source.unwrap_or_default();
+    let provider_lines = header_lines(source, "[[providers]]");
+    let model_lines = header_lines(source, "[[models]]");
+    let mapping_lines = header_lines(source, "[[models.mappings]]");
+    let mut problems = Vec::new();
+
+    for (i, provider) in config.providers.iter().enumerate() {
+        let mut problem = |message: String| {
+            problems.push(ConfigProblem {
+                section: format!("[[providers]] \"{}\"", provider.name),
+                line: provider_lines.get(i).copied(),
+                message,
+            })
+        };
+
+        if config.providers[..i].iter().any(|p| p.name == provider.name) {
+            problem("duplicate provider name".to_string());
+        }
+        // Disabled providers are never built
+        if !provider.is_enabled() {
+            continue;
+        }
+        if !PROVIDER_TYPES.contains(&provider.provider_type.as_str()) {
+            problem(format!(
+                "unknown provider_type \"{}\" (expected one of: {})",
+                provider.provider_type,
+                PROVIDER_TYPES.join(", ")
+            ));
+        }
+        if let Some(ttl) = provider.cache_ttl.as_deref().filter(|ttl| !CACHE_TTLS.contains(ttl)) {
+            problem(format!("invalid cache_ttl \"{}\" (expected one of: {})", ttl, CACHE_TTLS.join(", ")));
+        }
+        if provider.auth_type == AuthType::ApiKey {
+            match provider.api_key() {
+                // Bedrock falls back to AWS credentials; Ollama needs no key; Copilot uses its login
+                None | Some("") if matches!(provider.provider_type.as_str(), "bedrock" | "ollama" | "copilot") => {}
+                None | Some("") => problem(
+                    "missing api_key (set api_key, or auth_type = \"oauth\" with oauth_provider)".to_string(),
+                ),
+                // Still a reference after env resolution
+                Some(key) if key.starts_with('$') => {
+                    problem(format!("api_key environment variable {} is not set", &key[1..]))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    for (j, model) in config.models.iter().enumerate() {
+        // Mapping headers between this model's header and the next one's
+        let start = model_lines.get(j).copied();
+        let end = model_lines.get(j + 1).copied().unwrap_or(usize::MAX);
+        let lines: Vec<usize> = match start {
+            Some(start) => mapping_lines.iter().copied().filter(|l| *l > start && *l < end).collect(),
+            None => Vec::new(),
+        };
+
+        if model.strategy == MappingStrategy::RoundRobin && !model.ordering.is_priority() {
+            problems.push(ConfigProblem {
+                section: format!("[[models]] \"{}\"", model.name),
+                line: start,
+                message: "strategy = \"round_robin\" needs ordering = \"priority\" (the default)".to_string(),
+            });
+        }
+        if model.max_cost_per_mtok.is_some_and(|max| max <= 0.0) {
+            problems.push(ConfigProblem {
+                section: format!("[[models]] \"{}\"", model.name),
+                line: start,
+                message: "max_cost_per_mtok must be positive".to_string(),
+            });
+        }
+
+        for (k, mapping) in model.mappings.iter().enumerate() {
+            for route in &mapping.routes {
+                if !RouteType::NAMES.iter().any(|name| route_key(name) == route_key(route)) {
+                    problems.push(ConfigProblem {
+                        section: format!("[[models]] \"{}\" mapping #{}", model.name, k + 1),
+                        line: lines.get(k).copied(),
+                        message: format!("unknown route \"{}\" (expected one of: {})", route, RouteType::NAMES.join(", ")),
+                    });
+                }
+            }
+            if !config.providers.iter().any(|p| p.name == mapping.provider) {
+                let known: Vec<&str> = config.providers.iter().map(|p| p.name.as_str()).collect();
+                let known = if known.is_empty() { "none".to_string() } else { known.join(", ") };
+                problems.push(ConfigProblem {
+                    section: format!("[[models]] \"{}\" mapping #{}", model.name, k + 1),
+                    line: lines.get(k).copied(),
+                    message: format!("unknown provider \"{}\" (configured providers: {})", mapping.provider, known),
+                });
+            }
+        }
+    }
+
+    if let Some(auth) = &config.server.auth {
+        let mut problem = |message: String| {
+            problems.push(ConfigProblem {
+                section: "[server.auth]".to_string(),
+                line: header_lines(source, "[server.auth]").first().copied(),
+                message,
+            })
+        };
+        if auth.api_keys.iter().all(|key| key.is_empty()) {
+            problem("no api_keys; every /v1 request would be rejected".to_string());
+        }
+        for key in auth.api_keys.iter().filter(|key| key.starts_with('$')) {
+            problem(format!("api_keys environment variable {} is not set", &key[1..]));
+        }
+    }
+
+    if let Some(admin) = &config.server.admin {
+        let problem = |message: String| ConfigProblem {
+            section: "[server.admin]".to_string(),
+            line: header_lines(source, "[server.admin]").first().copied(),
+            message,
+        };
+        if admin.password.is_empty() {
+            problems.push(problem("empty password; nobody could log in to the admin UI".to_string()));
+        } else if let Some(env_var) = admin.password.strip_prefix('$') {
+            problems.push(problem(format!("password environment variable {} is not set", env_var)));
+        }
+    }
+
+    // An invalid pattern would let that kind of secret through unscanned
+    let rule_lines = header_lines(source, "[[server.guardrails.rules]]");
+    for (i, rule) in config.server.guardrails.rules.iter().enumerate() {
+        if let Err(e) = regex::Regex::new(&rule.pattern) {
+            let e = e.to_string();
+            let reason = e.lines().last().unwrap_or_default().trim_start_matches("error: ");
+            problems.push(ConfigProblem {
+                section: format!("[[server.guardrails.rules]] \"{}\"", rule.name),
+                line: rule_lines.get(i).copied(),
+                message: format!("invalid pattern \"{}\": {}", rule.pattern, reason),
+            });
+        }
+    }
+
+    // A fresh config has no providers yet; /health still reports it
+    if let Some(message) = default_route_problem(config).filter(|_| !config.providers.is_empty()) {
+        problems.push(ConfigProblem {
+            section: "[router] default".to_string(),
+            line: header_lines(source, "[router]").first().copied(),
+            message,
+        });
+    }
+
+    let budget_lines = header_lines(source, "[[budgets]]");
+    for (i, budget) in config.budgets.iter().enumerate() {
+        let mut problem = |message: &str| {
+            problems.push(ConfigProblem {
+                section: format!("[[budgets]] #{}", i + 1),
+                line: budget_lines.get(i).copied(),
+                message: message.to_string(),
+            })
+        };
+        if budget.provider.is_none() && budget.model.is_none() {
+            problem("set provider, model, or both");
+        }
+        if budget.daily_usd.is_none() && budget.monthly_usd.is_none() {
+            problem("set daily_usd, monthly_usd, or both");
+        }
+    }
+
+    let slo_lines = header_lines(source, "[[slos]]");
+    for (i, slo) in config.slos.iter().enumerate() {
+        let mut problem = |message: String| {
+            problems.push(ConfigProblem {
+                section: format!("[[slos]] #{}", i + 1),
+                line: slo_lines.get(i).copied(),
+                message,
+            })
+        };
+        if !RouteType::NAMES.iter().any(|name| route_key(name) == route_key(&slo.route)) {
+            problem(format!("unknown route \"{}\" (expected one of: {})", slo.route, RouteType::NAMES.join(", ")));
+        }
+        if !(slo.percentile > 0.0 && slo.percentile <= 100.0) {
+            problem(format!("percentile {} is not between 0 and 100", slo.percentile));
+        }
+        if slo.max_latency_secs <= 0.0 {
+            problem("max_latency_secs must be positive".to_string());
+        }
+    }
+
+    problems
+}
+
+/// Why router.default can't be routed, if it can't: it needs a `[[models]]` entry with
+/// mappings, a provider that lists it, or `router.fallback_mappings`. Otherwise every
+/// request without a more specific route fails.
+pub fn default_route_problem(config: &AppConfig) -> Option<String> {
+    let default = &config.router.default;
+    if let Some(model) = config.find_model(default) {
+        return model
+            .mappings
+            .is_empty()
+            .then(|| format!("model \"{}\" has no [[models.mappings]]", default));
+    }
+    let enabled = || config.providers.iter().filter(|p| p.is_enabled());
+    // Ollama's installed models are only known at runtime
+    if !config.router.fallback_mappings.is_empty()
+        || enabled().any(|p| p.provider_type == "ollama" || p.models.iter().any(|m| m.eq_ignore_ascii_case(default)))
+    {
+        return None;
+    }
+    let known: Vec<&str> = config.models.iter().map(|m| m.name.as_str()).collect();
+    Some(format!(
+        "\"{}\" is not a [[models]] name and no provider lists it (configured models: {})",
+        default,
+        if known.is_empty() { "none".to_string() } else { known.join(", ") }
+    ))
+}
+
+/// Validate a config loaded from `path`, failing with every problem listed
+pub fn check(config: &AppConfig, path: &Path) -> anyhow::Result<()> {
+    let source = std::fs::read_to_string(path).ok();
+    let problems = validate(config, source.as_deref());
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let list: Vec<String> = problems.iter().map(|p| format!("  • {}", p)).collect();
+    anyhow::bail!(
+        "{} problem{} in {}:\n{}",
+        problems.len(),
+        if problems.len() == 1 { "" } else { "s" },
+        path.display(),
+        list.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"[router]
+default = "sonnet"
+
+[[providers]]
+name = "anthropic"
+provider_type = "anthropic"
+api_key = "$CCM_TEST_UNSET_KEY"
+models = []
+
+[[providers]]
+name = "local"
+provider_type = "lmstudio"
+models = []
+cache_ttl = "2h"
+
+[[providers]]
+name = "old"
+provider_type = "lmstudio"
+models = []
+enabled = false
+
+[[models]]
+name = "sonnet"
+
+[[models.mappings]]
+priority = 1
+provider = "anthropic"
+actual_model = "claude-sonnet-4-5"
+routes = ["background", "thinking"]
+
+[[models.mappings]]
+priority = 2
+provider = "openrouter"
+actual_model = "anthropic/claude-sonnet-4.5"
+
+[[budgets]]
+provider = "anthropic"
+
+[[slos]]
+route = "thinking"
+max_latency_secs = 30.0
+"#;
+
+    #[test]
+    fn test_reports_all_problems() {
+        let config: AppConfig = toml::from_str(CONFIG).unwrap();
+        let problems: Vec<String> = validate(&config, Some(CONFIG)).iter().map(|p| p.to_string()).collect();
+
+        assert_eq!(problems.len(), 8, "{:#?}", problems);
+        assert_eq!(problems[0], "[[providers]] \"anthropic\" (line 4): api_key environment variable CCM_TEST_UNSET_KEY is not set");
+        assert!(problems[1].starts_with("[[providers]] \"local\" (line 10): unknown provider_type \"lmstudio\""));
+        assert_eq!(problems[2], "[[providers]] \"local\" (line 10): invalid cache_ttl \"2h\" (expected one of: 5m, 1h)");
+        assert!(problems[3].starts_with("[[providers]] \"local\" (line 10): missing api_key"));
+        assert!(problems[4].starts_with(
+            "[[models]] \"sonnet\" mapping #1 (line 25): unknown route \"thinking\" (expected one of: web-search,"
+        ));
+        assert_eq!(
+            problems[5],
+            "[[models]] \"sonnet\" mapping #2 (line 31): unknown provider \"openrouter\" (configured providers: anthropic, local, old)"
+        );
+        assert_eq!(problems[6], "[[budgets]] #1 (line 36): set daily_usd, monthly_usd, or both");
+        assert!(problems[7].starts_with("[[slos]] #1 (line 39): unknown route \"thinking\" (expected one of: web-search,"));
+    }
+
+    #[test]
+    fn test_valid_config_without_source() {
+        let config: AppConfig = toml::from_str(
+            r#"
+[router]
+default = "m"
+
+[[providers]]
+name = "p"
+provider_type = "openai"
+api_key = "sk-test"
+models = []
+
+[[models]]
+name = "m"
+mappings = [{ priority = 1, provider = "p", actual_model = "gpt-5" }]
+"#,
+        )
+        .unwrap();
+        assert!(validate(&config, None).is_empty());
+    }
+
+    #[test]
+    fn test_model_ordering_problems() {
+        let config: AppConfig = toml::from_str(
+            r#"
+[router]
+default = "m"
+
+[[providers]]
+name = "p"
+provider_type = "openai"
+api_key = "sk-test"
+models = []
+
+[[models]]
+name = "m"
+strategy = "round_robin"
+ordering = "latency"
+max_cost_per_mtok = 0.0
+mappings = [{ priority = 1, provider = "p", actual_model = "gpt-5" }]
+"#,
+        )
+        .unwrap();
+        let problems: Vec<String> = validate(&config, None).iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            problems,
+            vec![
+                "[[models]] \"m\": strategy = \"round_robin\" needs ordering = \"priority\" (the default)",
+                "[[models]] \"m\": max_cost_per_mtok must be positive",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_guardrail_pattern() {
+        let source = r#"
+[router]
+default = "m"
+
+[server.guardrails]
+enabled = true
+
+[[server.guardrails.rules]]
+name = "internal_token"
+pattern = "itk_[A-Za-z0-9]{32"
+"#;
+        let config: AppConfig = toml::from_str(source).unwrap();
+        let problems: Vec<String> = validate(&config, Some(source)).iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            problems,
+            vec!["[[server.guardrails.rules]] \"internal_token\" (line 8): invalid pattern \"itk_[A-Za-z0-9]{32\": unclosed counted repetition"]
+        );
+    }
+
+    #[test]
+    fn test_default_without_mappings() {
+        let mut config: AppConfig = toml::from_str(
+            r#"
+[router]
+default = "sonet"
+
+[[providers]]
+name = "p"
+provider_type = "openai"
+api_key = "sk-test"
+models = ["gpt-5"]
+
+[[models]]
+name = "sonnet"
+mappings = [{ priority = 1, provider = "p", actual_model = "gpt-5" }]
+
+[[models]]
+name = "empty"
+mappings = []
+"#,
+        )
+        .unwrap();
+        let problems = validate(&config, None);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(
+            problems[0].to_string(),
+            "[router] default: \"sonet\" is not a [[models]] name and no provider lists it (configured models: sonnet, empty)"
+        );
+
+        config.router.default = "empty".to_string();
+        assert_eq!(default_route_problem(&config).unwrap(), "model \"empty\" has no [[models.mappings]]");
+        config.router.default = "GPT-5".to_string();
+        assert_eq!(default_route_problem(&config), None);
+    }
+}
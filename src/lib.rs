@@ -1,11 +1,64 @@
+//! Claude Code Mux as a library: the router that picks a model for each Anthropic
+//! Messages request, and the providers that translate it for OpenAI-compatible,
+//! Gemini, Bedrock, Ollama and other backends.
+//!
+//! # Stable API
+//!
+//! The items re-exported at the crate root are the embedding API and follow semver:
+//! while the version is 0.x, breaking changes to them only come with a minor version
+//! bump and are listed in the changelog. The modules below are public for the `ccm`
+//! binary and may change in any release.
+//!
+//! The exported structs and enums are `#[non_exhaustive]` so fields and variants can
+//! be added in a minor release: build structs with their `new` constructors, from TOML
+//! ([`AppConfig::from_toml`]) or by deserializing JSON, and give matches on enums a
+//! wildcard arm.
+//!
+//! - [`RouterBuilder`] builds a [`Router`] from an [`AppConfig`]
+//! - [`ProviderRegistryBuilder`] builds a [`ProviderRegistry`] from provider configs
+//!   and your own [`AnthropicProvider`] implementations
+//! - [`route_and_dispatch`] routes a request and sends it to the routed model's
+//!   mappings in priority order, failing over until one answers
+//! - [`Router::route`] alone routes without calling a provider
+//!
+//! ```no_run
+//! use claude_code_mux::{route_and_dispatch, AnthropicRequest, ProviderRegistryBuilder, RouterBuilder};
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let router = RouterBuilder::from_file("config.toml")?.build()?;
+//! let registry = ProviderRegistryBuilder::from_config(router.config()).build()?;
+//!
+//! let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+//!     "model": "claude-sonnet-4-5",
+//!     "max_tokens": 1024,
+//!     "messages": [{"role": "user", "content": "Hello"}]
+//! }))?;
+//! let dispatched = route_and_dispatch(&router, &registry, request).await?;
+//! println!("{} via {}/{}", dispatched.decision.route_type, dispatched.provider, dispatched.actual_model);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! See `examples/embed_router.rs` for a complete program.
+
 pub mod auth;
 pub mod cli;
+pub mod embed;
 pub mod message_tracing;
 pub mod models;
+pub mod paths;
 pub mod pid;
 pub mod providers;
 pub mod router;
 pub mod server;
+pub mod shared_state;
+
+pub use cli::{AppConfig, ModelConfig, ModelMapping};
+pub use embed::{route_and_dispatch, DispatchError, Dispatched, ProviderRegistryBuilder, RouterBuilder};
+pub use models::{AnthropicRequest, RouteDecision, RouteType};
+pub use providers::error::ProviderError;
+pub use providers::{AnthropicProvider, ProviderConfig, ProviderRegistry, ProviderResponse, StreamResponse};
+pub use router::Router;
 
 #[cfg(test)]
 mod tests {
@@ -14,4 +67,4 @@ mod tests {
         let result = 2 + 2;
         assert_eq!(result, 4);
     }
-}
\ No newline at end of file
+}
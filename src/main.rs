@@ -1,4 +1,5 @@
 use clap::{Parser, Subcommand};
+use std::io::IsTerminal;
 use std::path::PathBuf;
 use std::process::Command;
 use tracing_subscriber::EnvFilter;
@@ -7,10 +8,12 @@ mod auth;
 mod cli;
 mod message_tracing;
 mod models;
+mod paths;
 mod pid;
 mod providers;
 mod router;
 mod server;
+mod shared_state;
 
 const PROCESS_TRANSITION_GRACE_MS: u64 = 500;
 
@@ -37,14 +40,48 @@ async fn stop_service(pid: u32) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Fail if another instance is listening on the configured port
+async fn ensure_port_free(host: &str, port: u16) -> anyhow::Result<()> {
+    if let Some(body) = pid::probe_health(host, port).await {
+        let owner = body.get("pid").and_then(|p| p.as_u64())
+            .map(|pid| format!(" (PID: {})", pid))
+            .unwrap_or_default();
+        anyhow::bail!(
+            "Port {} is already serving /health{}. Stop that instance or choose another port with --port",
+            port, owner
+        );
+    }
+    Ok(())
+}
+
 async fn start_foreground(config: cli::AppConfig, config_path: PathBuf) -> anyhow::Result<()> {
+    // Hold the instance lock for the lifetime of the server
+    let _lock = match pid::acquire_lock() {
+        Ok(lock) => Some(lock),
+        Err(pid::LockError::Held) => {
+            let holder = pid::read_pid().map(|pid| format!(" (PID: {})", pid)).unwrap_or_default();
+            anyhow::bail!(
+                "Another Claude Code Mux instance is running{}. Use 'ccm stop' first, or pass a different --name or --state-dir",
+                holder
+            );
+        }
+        Err(e) => {
+            eprintln!("Warning: {}", e);
+            None
+        }
+    };
+    ensure_port_free(&config.server.host, config.server.port).await?;
+
     // Write PID file
-    if let Err(e) = pid::write_pid() {
+    if let Err(e) = pid::write_pid(config.server.port, &config_path) {
         eprintln!("Warning: Failed to write PID file: {}", e);
     }
 
     tracing::info!("Starting Claude Code Mux on port {}", config.server.port);
-    println!("🚀 Claude Code Mux v{}", env!("CARGO_PKG_VERSION"));
+    match paths::instance() {
+        Some(name) => println!("🚀 Claude Code Mux v{} (instance: {})", env!("CARGO_PKG_VERSION"), name),
+        None => println!("🚀 Claude Code Mux v{}", env!("CARGO_PKG_VERSION")),
+    }
     println!("📡 Starting server on {}:{}", config.server.host, config.server.port);
     println!();
 
@@ -68,24 +105,183 @@ async fn start_foreground(config: cli::AppConfig, config_path: PathBuf) -> anyho
     result
 }
 
-fn spawn_background_service(
-    port: Option<u16>,
-    config_path: Option<PathBuf>,
-) -> anyhow::Result<()> {
-    let exe_path = std::env::current_exe()?;
-    let mut cmd = Command::new(&exe_path);
-    cmd.arg("start");
+/// Write the statusline script into the state directory (the script reads last_routing.json from it)
+fn install_statusline_script() -> anyhow::Result<PathBuf> {
+    let ccm_dir = paths::state_dir();
+    std::fs::create_dir_all(ccm_dir)?;
+
+    // Point the script at this state directory
+    let script_path = ccm_dir.join("statusline.sh");
+    let script_content = include_str!("../statusline.sh").replace(
+        "${CCM_STATE_DIR:-$HOME/.claude-code-mux}",
+        &format!("${{CCM_STATE_DIR:-{}}}", ccm_dir.display()),
+    );
+    std::fs::write(&script_path, script_content)?;
+
+    // Make executable on Unix
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = std::fs::metadata(&script_path)?.permissions();
+        perms.set_mode(0o755);
+        std::fs::set_permissions(&script_path, perms)?;
+    }
+
+    Ok(script_path)
+}
+
+/// Number of recent routing decisions shown by `ccm status --verbose`
+const STATUS_RECENT_ROUTES: usize = 5;
+
+/// Print live details from a running server's /health?verbose=1 and /api/debug/stats
+async fn print_verbose_status(host: &str, port: u16) {
+    let health = pid::fetch_json(host, port, "/health?verbose=1").await.unwrap_or_default();
+    let stats = pid::fetch_json(host, port, "/api/debug/stats").await.unwrap_or_default();
+
+    if let Some(uptime) = stats["uptime_secs"].as_u64() {
+        println!("   Uptime: {}h {}m {}s", uptime / 3600, uptime % 3600 / 60, uptime % 60);
+    }
+    if let Some(in_flight) = stats["in_flight"].as_u64() {
+        println!("   In-flight requests: {}", in_flight);
+    }
+    if let Some(status) = health["status"].as_str() {
+        let route = &health["default_route"];
+        println!(
+            "   Health: {} (default {} via {})",
+            status,
+            route["model"].as_str().unwrap_or("?"),
+            join_strings(&route["healthy_providers"]),
+        );
+    }
 
-    if let Some(port) = port {
-        cmd.arg("--port").arg(port.to_string());
+    if let Some(providers) = stats["provider_stats"].as_array().filter(|p| !p.is_empty()) {
+        println!();
+        println!("📈 Provider health:");
+        for p in providers {
+            println!(
+                "   • {}/{}: {:.0}% success, {}ms avg ({} samples)",
+                p["provider"].as_str().unwrap_or("?"),
+                p["model"].as_str().unwrap_or("?"),
+                p["success_rate"].as_f64().unwrap_or(0.0) * 100.0,
+                p["avg_latency_ms"].as_u64().unwrap_or(0),
+                p["samples"].as_u64().unwrap_or(0),
+            );
+        }
+    }
+
+    if let Some(circuits) = stats["circuits"].as_array().filter(|c| !c.is_empty()) {
+        println!();
+        println!("⚡ Circuits:");
+        for c in circuits {
+            println!(
+                "   • {}/{}: {} ({} failures)",
+                c["provider"].as_str().unwrap_or("?"),
+                c["model"].as_str().unwrap_or("?"),
+                c["state"].as_str().unwrap_or("?"),
+                c["consecutive_failures"].as_u64().unwrap_or(0),
+            );
+        }
+    }
+
+    if let Some(costs) = stats["costs"].as_array().filter(|c| !c.is_empty()) {
+        println!();
+        println!("💰 Spend since start: ${:.2}", stats["total_cost_usd"].as_f64().unwrap_or(0.0));
+        for c in costs {
+            let unpriced = c["unpriced_requests"].as_u64().unwrap_or(0);
+            let unpriced = if unpriced > 0 { format!(", {} unpriced", unpriced) } else { String::new() };
+            println!(
+                "   • {}/{}: ${:.4} ({} requests, {} in / {} out tokens{})",
+                c["provider"].as_str().unwrap_or("?"),
+                c["model"].as_str().unwrap_or("?"),
+                c["cost_usd"].as_f64().unwrap_or(0.0),
+                c["requests"].as_u64().unwrap_or(0),
+                c["input_tokens"].as_u64().unwrap_or(0),
+                c["output_tokens"].as_u64().unwrap_or(0),
+                unpriced,
+            );
+        }
     }
+
+    if let Some(budgets) = stats["budgets"].as_array().filter(|b| !b.is_empty()) {
+        println!();
+        println!("💸 Budgets:");
+        for b in budgets {
+            let limit = |spent: &str, limit: &str, period: &str| {
+                b[limit].as_f64().map(|l| format!(" {} ${:.2}/${:.2}", period, b[spent].as_f64().unwrap_or(0.0), l))
+            };
+            println!(
+                "   • {}:{}{} (on exceeded: {})",
+                b["budget"].as_str().unwrap_or("?"),
+                limit("spent_today_usd", "daily_usd", "today").unwrap_or_default(),
+                limit("spent_this_month_usd", "monthly_usd", "month").unwrap_or_default(),
+                b["on_exceeded"].as_str().unwrap_or("?"),
+            );
+        }
+    }
+
+    if let Some(tokens) = health["oauth_tokens"].as_array().filter(|t| !t.is_empty()) {
+        println!();
+        println!("🔐 OAuth tokens:");
+        for t in tokens {
+            let warning = t["warning"].as_str().map(|w| format!(" ⚠️  {}", w)).unwrap_or_default();
+            println!(
+                "   • {}: expires {}{}",
+                t["provider_id"].as_str().unwrap_or("?"),
+                t["expires_at"].as_str().unwrap_or("?"),
+                warning,
+            );
+        }
+    }
+
+    if let Some(routes) = stats["recent_routes"].as_array().filter(|r| !r.is_empty()) {
+        println!();
+        println!("🔀 Recent routing decisions:");
+        for r in routes.iter().take(STATUS_RECENT_ROUTES) {
+            println!(
+                "   • {} {} → {}@{} [{}] {}ms",
+                r["timestamp"].as_str().unwrap_or("?"),
+                r["model"].as_str().unwrap_or("?"),
+                r["actual_model"].as_str().unwrap_or("?"),
+                r["provider"].as_str().unwrap_or("?"),
+                r["route_type"].as_str().unwrap_or("?"),
+                r["latency_ms"].as_u64().unwrap_or(0),
+            );
+        }
+    }
+}
+
+fn join_strings(value: &serde_json::Value) -> String {
+    value.as_array()
+        .map(|items| items.iter().filter_map(|i| i.as_str()).collect::<Vec<_>>().join(", "))
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "none".to_string())
+}
+
+/// How long `--daemon` waits for the server to answer /health before giving up on it
+const DAEMON_READY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Start the server as a daemon: a new session with no terminal, running from `/`, with
+/// stdout/stderr appended to the log file. Waits until the child answers /health and
+/// returns its PID, or fails with the child's output if it exits during startup.
+async fn start_daemon(host: &str, port: u16, config_path: Option<PathBuf>) -> anyhow::Result<u32> {
+    let exe_path = std::env::current_exe()?;
+    let mut cmd = Command::new(&exe_path);
+    // The daemon doesn't keep the caller's working directory, so paths must be absolute
+    // (--config is not a global flag, so it goes before the subcommand)
     if let Some(config_path) = config_path {
-        cmd.arg("--config").arg(config_path);
+        cmd.arg("--config").arg(std::path::absolute(config_path)?);
+    }
+    cmd.arg("start");
+    cmd.arg("--state-dir").arg(std::path::absolute(paths::base_state_dir())?);
+    if let Some(name) = paths::instance() {
+        cmd.arg("--name").arg(name);
     }
+    cmd.arg("--port").arg(port.to_string());
 
     #[cfg(unix)]
     {
         use std::os::unix::process::CommandExt;
+        cmd.current_dir("/");
         unsafe {
             cmd.pre_exec(|| {
                 nix::libc::setsid();
@@ -94,11 +290,196 @@ fn spawn_background_service(
         }
     }
 
+    // Keep the server's output for `ccm logs`
+    let log_path = cli::logs::log_path();
+    if let Some(dir) = log_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .map_err(|e| anyhow::anyhow!("Failed to open log file {}: {}", log_path.display(), e))?;
+    let log_start = log.metadata()?.len();
     cmd.stdin(std::process::Stdio::null())
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null());
+        .stdout(log.try_clone()?)
+        .stderr(log);
+
+    let mut child = cmd.spawn()?;
+    let pid = child.id();
+    let deadline = std::time::Instant::now() + DAEMON_READY_TIMEOUT;
+    while std::time::Instant::now() < deadline {
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        if let Some(status) = child.try_wait()? {
+            // Show what the server printed before it exited
+            let output = std::fs::read(&log_path).unwrap_or_default();
+            let output = String::from_utf8_lossy(output.get(log_start as usize..).unwrap_or_default());
+            eprint!("{}", output);
+            anyhow::bail!("The server exited during startup ({}); its output is in {}", status, log_path.display());
+        }
+        let health = pid::probe_health(host, port).await;
+        if health.and_then(|b| b.get("pid").and_then(|p| p.as_u64())) == Some(pid as u64) {
+            return Ok(pid);
+        }
+    }
+    eprintln!(
+        "Warning: PID {} isn't answering on port {} after {}s; check 'ccm logs'",
+        pid, port, DAEMON_READY_TIMEOUT.as_secs()
+    );
+    Ok(pid)
+}
+
+async fn run_doctor(config_path: &std::path::Path) -> anyhow::Result<()> {
+    use cli::doctor::Severity;
+
+    println!("🩺 Checking Claude Code Mux setup");
+    println!();
+    let findings = cli::doctor::run(config_path).await;
+    for finding in &findings {
+        println!("{}", finding);
+    }
+
+    let count = |severity| findings.iter().filter(|f| f.severity == severity).count();
+    let (errors, warnings) = (count(Severity::Error), count(Severity::Warning));
+    println!();
+    if errors > 0 {
+        anyhow::bail!("{} problem{} and {} warning{} found", errors, if errors == 1 { "" } else { "s" }, warnings, if warnings == 1 { "" } else { "s" });
+    }
+    if warnings > 0 {
+        println!("No problems, {} warning{}", warnings, if warnings == 1 { "" } else { "s" });
+    } else {
+        println!("Everything looks good");
+    }
+    Ok(())
+}
+
+async fn run_copilot_login(provider_id: &str, enterprise: Option<&str>) -> anyhow::Result<()> {
+    use auth::copilot::{self, DevicePoll};
+
+    let client = reqwest::Client::new();
+    let token_store = auth::TokenStore::default()?;
+    let device = copilot::request_device_code(&client, enterprise).await?;
+
+    println!("🔐 Open {} and enter the code: {}", device.verification_uri, device.user_code);
+    println!("   Waiting for authorization...");
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(device.expires_in);
+    let mut interval = device.interval.max(1);
+    let github_token = loop {
+        if std::time::Instant::now() >= deadline {
+            anyhow::bail!("The device code expired; run 'ccm copilot-login' again");
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+        match copilot::poll_device_code(&client, &device.device_code, enterprise).await? {
+            DevicePoll::Pending => {}
+            DevicePoll::SlowDown(new_interval) => interval = new_interval.max(interval + 5),
+            DevicePoll::Complete(token) => break token,
+        }
+    };
+
+    let session = copilot::complete_login(&client, &token_store, provider_id, github_token, enterprise).await?;
+    println!("✅ Copilot login saved as '{}' (API: {})", provider_id, session.api_base);
+    println!("   Use it with a provider: provider_type = \"copilot\", name or oauth_provider = \"{}\"", provider_id);
+    Ok(())
+}
+
+async fn run_service_command(action: ServiceAction, config_path: &PathBuf) -> anyhow::Result<()> {
+    use cli::service::{self, Manager, ServiceSpec};
+
+    let manager = Manager::current()?;
+    match action {
+        ServiceAction::Install { print } => {
+            let config = cli::AppConfig::from_file(config_path)?;
+            cli::validate::check(&config, config_path)?;
+            let spec = ServiceSpec::current(config_path, &cli::logs::log_path())?;
+            if print {
+                print!("{}", manager.render(&spec));
+                return Ok(());
+            }
+
+            // The service's server would find the instance lock held
+            if let Ok(pid) = pid::read_pid() {
+                if pid::is_process_running(pid) {
+                    println!("Stopping the running instance (PID: {}) so the service can take over...", pid);
+                    stop_service(pid).await?;
+                }
+                let _ = pid::cleanup_pid();
+            }
+
+            let path = service::install(manager, &spec)?;
+            println!("✅ Installed {}", path.display());
+            println!("📡 Running on port {} and started at every login", config.server.port);
+            println!("📜 Logs: {} ('ccm logs -f' to follow)", spec.log_path.display());
+            if manager == Manager::Systemd {
+                println!("To start it at boot without logging in: loginctl enable-linger $USER");
+            }
+        }
+        ServiceAction::Uninstall => match service::uninstall(manager)? {
+            Some(path) => println!("✅ Removed {}", path.display()),
+            None => println!("The service is not installed"),
+        },
+        ServiceAction::Enable | ServiceAction::Disable | ServiceAction::Status => {
+            if !manager.unit_path()?.exists() {
+                anyhow::bail!("The service is not installed; run 'ccm service install' first");
+            }
+            match action {
+                ServiceAction::Enable => {
+                    manager.enable()?;
+                    println!("✅ Service enabled and started");
+                }
+                ServiceAction::Disable => {
+                    manager.disable()?;
+                    println!("✅ Service stopped and disabled");
+                }
+                _ => manager.print_status()?,
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_config_command(action: ConfigAction, config_path: &PathBuf) -> anyhow::Result<()> {
+    use cli::config_edit;
+
+    match action {
+        ConfigAction::Get { key } => {
+            let content = std::fs::read_to_string(config_path)
+                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", config_path.display(), e))?;
+            let doc: toml_edit::DocumentMut = content.parse()?;
+            println!("{}", config_edit::get(&doc, &key)?);
+        }
+        ConfigAction::Set { key, value, string, reload } => {
+            let problems = config_edit::set_in_file(config_path, &key, config_edit::parse_value(&value, string))?;
+            println!("✅ Set {} in {}", key, config_path.display());
+            for problem in &problems {
+                println!("⚠️  {}", problem);
+            }
 
-    cmd.spawn()?;
+            if reload {
+                let config = cli::AppConfig::from_file(config_path)?;
+                let port = pid::read_pid_info().ok().and_then(|i| i.port).unwrap_or(config.server.port);
+                let host = match config.server.host.as_str() {
+                    "0.0.0.0" | "::" | "" => "127.0.0.1",
+                    host => host,
+                };
+                let mut request = reqwest::Client::new().post(format!("http://{}:{}/api/reload", host, port));
+                if let Some(admin) = &config.server.admin {
+                    request = request.bearer_auth(&admin.password);
+                }
+                let body = request
+                    .send()
+                    .await
+                    .map_err(|e| anyhow::anyhow!("No running instance on port {}: {}", port, e))?
+                    .text()
+                    .await?;
+                if body.contains("Reload failed") {
+                    let reason = body.split("<br/>").nth(1).unwrap_or(&body).trim_end_matches("</div>");
+                    anyhow::bail!("Reload failed: {}", reason);
+                }
+                println!("🔄 Reloaded the running instance on port {}", port);
+            }
+        }
+    }
     Ok(())
 }
 
@@ -110,9 +491,20 @@ struct Cli {
     #[command(subcommand)]
     command: Commands,
 
-    /// Path to configuration file (defaults to ~/.claude-code-mux/config.toml)
+    /// Path to configuration file (defaults to ~/.claude-code-mux/config.toml, or
+    /// ~/.claude-code-mux/config.<name>.toml with --name)
     #[arg(short, long)]
     config: Option<PathBuf>,
+
+    /// Directory for state files: PID, OAuth tokens, traces, statusline info
+    /// (defaults to $CCM_STATE_DIR, then ~/.claude-code-mux or $XDG_STATE_HOME/claude-code-mux)
+    #[arg(long, global = true)]
+    state_dir: Option<PathBuf>,
+
+    /// Named instance, with its own default config, PID file, tokens and state files
+    /// (instances/<name> in the state directory), to run several side by side
+    #[arg(long, global = true, value_parser = paths::parse_instance_name)]
+    name: Option<String>,
 }
 
 #[derive(Subcommand)]
@@ -122,30 +514,157 @@ enum Commands {
         /// Port to listen on
         #[arg(short, long)]
         port: Option<u16>,
-        /// Run in detached/background mode
-        #[arg(short = 'd', long)]
-        detach: bool,
+        /// Run as a background daemon, logging to logs/ccm.log in the state directory
+        #[arg(short = 'd', long, visible_alias = "detach")]
+        daemon: bool,
     },
     /// Stop the router service
     Stop,
     /// Restart the router service
     Restart {
-        /// Run in detached/background mode
-        #[arg(short = 'd', long)]
-        detach: bool,
+        /// Run as a background daemon, logging to logs/ccm.log in the state directory
+        #[arg(short = 'd', long, visible_alias = "detach")]
+        daemon: bool,
     },
     /// Check service status
-    Status,
+    Status {
+        /// Query the running server for uptime, provider health, tokens and recent routes
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Live dashboard of the running server: request rates, latency, errors, tokens and budgets
+    Top {
+        /// Seconds between refreshes
+        #[arg(short, long, default_value_t = 2)]
+        interval: u64,
+    },
     /// Manage models and providers
     Model,
     /// Install statusline script for Claude Code
     InstallStatusline,
+    /// Configure Claude Code (~/.claude/settings.json) to use this router
+    InstallClaudeCode {
+        /// API key to write into settings (default: keep the existing one, or a placeholder)
+        #[arg(long)]
+        api_key: Option<String>,
+        /// Don't install or configure the statusline
+        #[arg(long)]
+        no_statusline: bool,
+        /// Remove the CCM settings instead
+        #[arg(long)]
+        uninstall: bool,
+    },
+    /// Run an eval suite against one or more models through the running router
+    Eval {
+        /// Suite file (TOML) listing prompts and assertions
+        #[arg(long)]
+        suite: PathBuf,
+        /// Model to evaluate (repeatable; default: the suite's models, then router.default)
+        #[arg(short, long)]
+        model: Vec<String>,
+    },
+    /// Compare provider-reported spend (OpenRouter, Anthropic) with the usage history's cost
+    Reconcile {
+        /// Reporting window (UTC)
+        #[arg(long, value_enum, default_value = "month")]
+        period: cli::reconcile::Period,
+        /// Flag sources whose local cost differs from the reported spend by more than this percentage
+        #[arg(long, default_value_t = 10.0)]
+        threshold: f64,
+        /// Anthropic Admin API key (sk-ant-admin...), needed for the organization cost report
+        #[arg(long, env = "ANTHROPIC_ADMIN_KEY", hide_env_values = true)]
+        anthropic_admin_key: Option<String>,
+    },
+    /// Log in to GitHub Copilot (device code flow) for a `copilot` provider
+    CopilotLogin {
+        /// Token store entry: the provider's `oauth_provider`, or its name
+        #[arg(default_value = "copilot")]
+        provider_id: String,
+        /// GitHub Enterprise host (e.g. github.example.com)
+        #[arg(long)]
+        enterprise: Option<String>,
+    },
+    /// Check the config, port, PID file, OAuth tokens, provider reachability and Claude Code setup
+    Doctor,
+    /// Print the log of a server started with --daemon (`logs/ccm.log` in the state directory)
+    Logs {
+        /// Keep printing new lines as they are written
+        #[arg(short, long)]
+        follow: bool,
+        /// Only lines from this far back (30s, 10m, 2h, 1d) or since an RFC 3339 timestamp
+        #[arg(long)]
+        since: Option<String>,
+        /// Only lines at this level or more severe
+        #[arg(short, long, value_enum)]
+        level: Option<cli::logs::Level>,
+        /// Number of lines to print before following (0 for all)
+        #[arg(short = 'n', long, default_value_t = 100)]
+        lines: usize,
+    },
+    /// Read or edit config values by dotted path (e.g. router.default)
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Run the server as a login service (systemd user unit on Linux, launchd agent on macOS)
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum ServiceAction {
+    /// Install the service for the current config and state directory, and start it
+    Install {
+        /// Print the unit file or plist instead of installing it
+        #[arg(long)]
+        print: bool,
+    },
+    /// Stop the service and remove it
+    Uninstall,
+    /// Start the service now and at every login
+    Enable,
+    /// Stop the service and don't start it at login
+    Disable,
+    /// Show the service manager's status for the service
+    Status,
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print a value (strings bare, tables as TOML)
+    Get {
+        /// Dotted path, e.g. router.default or providers.openrouter.enabled
+        key: String,
+    },
+    /// Set a value, keeping the file's comments and formatting
+    Set {
+        /// Dotted path, e.g. router.default or providers.openrouter.enabled
+        key: String,
+        /// TOML value (42, true, ["a", "b"]); anything else is taken as a string
+        value: String,
+        /// Always store the value as a string (e.g. "4.6")
+        #[arg(long)]
+        string: bool,
+        /// Reload the running instance after writing
+        #[arg(long)]
+        reload: bool,
+    },
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
+    // State directory must be fixed before anything touches state files
+    if let Some(ref state_dir) = cli.state_dir {
+        paths::set_state_dir(state_dir.clone());
+    }
+    if let Some(ref name) = cli.name {
+        paths::set_instance(name.clone());
+    }
+
     // Get config path (use default if not specified)
     let config_path = match &cli.config {
         Some(path) => path.clone(),
@@ -153,18 +672,45 @@ async fn main() -> anyhow::Result<()> {
             .unwrap_or_else(|_| PathBuf::from("config/default.toml")),
     };
 
+    // Config edits work on the file itself, so they can repair a config that doesn't load
+    if let Commands::Config { action } = cli.command {
+        return run_config_command(action, &config_path).await;
+    }
+    if let Commands::CopilotLogin { provider_id, enterprise } = cli.command {
+        return run_copilot_login(&provider_id, enterprise.as_deref()).await;
+    }
+    // Diagnoses a config that doesn't load, too
+    if let Commands::Doctor = cli.command {
+        return run_doctor(&config_path).await;
+    }
+    // Disabling or removing the service must work with a broken config
+    if let Commands::Service { action } = cli.command {
+        return run_service_command(action, &config_path).await;
+    }
+    // Reads the state directory only
+    if let Commands::Logs { follow, since, level, lines } = cli.command {
+        return cli::logs::run(follow, since, level, lines).await;
+    }
+
     // Load configuration
     let config = cli::AppConfig::from_file(&config_path)?;
 
     // Initialize tracing: RUST_LOG env var takes precedence, otherwise use config log_level
     let filter = EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| EnvFilter::new(&config.server.log_level));
-    tracing_subscriber::fmt().with_env_filter(filter).init();
+    // No color codes when output goes to a daemon's log file
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_ansi(std::io::stdout().is_terminal())
+        .init();
 
     match cli.command {
-        Commands::Start { port, detach } => {
-            // If detached, spawn as background process
-            if detach {
+        Commands::Start { port, daemon } => {
+            // Report every config problem before touching the port or the PID file
+            cli::validate::check(&config, &config_path)?;
+
+            // Daemon mode: run in the background
+            if daemon {
                 println!("Starting Claude Code Mux in background...");
 
                 // Stop existing service if running
@@ -178,16 +724,15 @@ async fn main() -> anyhow::Result<()> {
                 }
                 let _ = pid::cleanup_pid();
 
-                // Start in background
-                spawn_background_service(port, cli.config)?;
-                tokio::time::sleep(tokio::time::Duration::from_millis(PROCESS_TRANSITION_GRACE_MS)).await;
+                // The background process can't report errors, so check the port here
+                ensure_port_free(&config.server.host, port.unwrap_or(config.server.port)).await?;
 
-                if let Ok(pid) = pid::read_pid() {
-                    println!("✅ Claude Code Mux started in background (PID: {})", pid);
-                } else {
-                    println!("✅ Claude Code Mux started in background");
-                }
-                println!("📡 Running on port {}", port.unwrap_or(config.server.port));
+                // Start in background
+                let port = port.unwrap_or(config.server.port);
+                let pid = start_daemon(&config.server.host, port, cli.config).await?;
+                println!("✅ Claude Code Mux started in background (PID: {})", pid);
+                println!("📡 Running on port {}", port);
+                println!("📜 Logs: {} ('ccm logs -f' to follow)", cli::logs::log_path().display());
                 return Ok(());
             }
 
@@ -231,8 +776,11 @@ async fn main() -> anyhow::Result<()> {
                     let _ = pid::cleanup_pid();
                 }
             }
+            if cli::service::installed() {
+                println!("Note: the login service starts it again at login (launchd also after 'ccm stop'); use 'ccm service disable' to turn it off");
+            }
         }
-        Commands::Restart { detach } => {
+        Commands::Restart { daemon } => {
             // Stop the existing service
             let was_running = match pid::read_pid() {
                 Ok(pid) => {
@@ -253,37 +801,76 @@ async fn main() -> anyhow::Result<()> {
             };
             let _ = pid::cleanup_pid();
 
-            if detach {
+            if daemon {
                 // Background mode
                 println!("Starting service in background...");
-                let port_from_config = Some(config.server.port);
-                spawn_background_service(port_from_config, cli.config)?;
-                tokio::time::sleep(tokio::time::Duration::from_millis(PROCESS_TRANSITION_GRACE_MS)).await;
-
+                let pid = start_daemon(&config.server.host, config.server.port, cli.config).await?;
                 let verb = if was_running { "restarted" } else { "started" };
-                if let Ok(pid) = pid::read_pid() {
-                    println!("✅ Service {} successfully (PID: {})", verb, pid);
-                } else {
-                    println!("✅ Service {} successfully", verb);
-                }
+                println!("✅ Service {} successfully (PID: {})", verb, pid);
             } else {
                 // Foreground mode
                 start_foreground(config, config_path).await?;
             }
         }
-        Commands::Status => {
+        Commands::Top { interval } => {
+            let port = pid::read_pid_info().ok().and_then(|i| i.port).unwrap_or(config.server.port);
+            cli::top::run(&config.server.host, port, std::time::Duration::from_secs(interval.max(1))).await?;
+        }
+        Commands::Status { verbose } => {
             println!("Checking service status...");
-            match pid::read_pid() {
-                Ok(pid) => {
-                    if pid::is_process_running(pid) {
-                        println!("✅ Service is running (PID: {})", pid);
-                    } else {
+            let info = pid::read_pid_info().ok();
+            let port = info.as_ref().and_then(|i| i.port).unwrap_or(config.server.port);
+            let health = pid::probe_health(&config.server.host, port).await;
+            let health_pid = health.as_ref().and_then(|b| b.get("pid")).and_then(|p| p.as_u64());
+
+            match info {
+                Some(info) if pid::is_process_running(info.pid) => {
+                    println!("✅ Service is running (PID: {})", info.pid);
+                    if let Some(name) = paths::instance() {
+                        println!("   Instance: {}", name);
+                    }
+                    println!("   Port: {}", port);
+                    if let Some(ref path) = info.config_path {
+                        println!("   Config: {}", path.display());
+                    }
+                    match health_pid {
+                        _ if health.is_none() => println!("⚠️  Nothing is answering /health on port {}", port),
+                        Some(other) if other != info.pid as u64 => {
+                            println!("⚠️  Port {} is served by another process (PID: {})", port, other)
+                        }
+                        _ => {}
+                    }
+                    let model_warnings = health
+                        .as_ref()
+                        .and_then(|b| b.get("model_warnings"))
+                        .and_then(|w| w.as_array())
+                        .cloned()
+                        .unwrap_or_default();
+                    for warning in model_warnings {
+                        println!(
+                            "⚠️  Model {}/{} looks deprecated: {}",
+                            warning["provider"].as_str().unwrap_or("?"),
+                            warning["model"].as_str().unwrap_or("?"),
+                            warning["detail"].as_str().unwrap_or("")
+                        );
+                    }
+                    if verbose && health.is_some() {
+                        print_verbose_status(&config.server.host, port).await;
+                    }
+                }
+                info => {
+                    if info.is_some() {
                         println!("❌ Service is not running (stale PID file)");
                         let _ = pid::cleanup_pid();
+                    } else {
+                        println!("❌ Service is not running");
+                    }
+                    if health.is_some() {
+                        let owner = health_pid.map(|p| format!(" (PID: {})", p)).unwrap_or_default();
+                        println!("⚠️  Port {} is in use by another process{}", port, owner);
+                    } else if pid::is_locked() {
+                        println!("⚠️  Another instance holds {}", pid::get_lock_file().display());
                     }
-                }
-                Err(_) => {
-                    println!("❌ Service is not running");
                 }
             }
         }
@@ -313,25 +900,7 @@ async fn main() -> anyhow::Result<()> {
             println!("📊 Installing Claude Code Statusline Script");
             println!();
 
-            // Get home directory and create .claude-code-mux directory
-            let home = dirs::home_dir()
-                .ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?;
-            let ccm_dir = home.join(".claude-code-mux");
-            std::fs::create_dir_all(&ccm_dir)?;
-
-            // Write statusline script
-            let script_path = ccm_dir.join("statusline.sh");
-            let script_content = include_str!("../statusline.sh");
-            std::fs::write(&script_path, script_content)?;
-
-            // Make executable on Unix
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                let mut perms = std::fs::metadata(&script_path)?.permissions();
-                perms.set_mode(0o755);
-                std::fs::set_permissions(&script_path, perms)?;
-            }
+            let script_path = install_statusline_script()?;
 
             println!("✅ Statusline script installed to: {}", script_path.display());
             println!();
@@ -348,6 +917,118 @@ async fn main() -> anyhow::Result<()> {
             println!("📊 The statusline will show: model@provider (route-type) HH:MM:SS");
             println!("   Example: minimax-m2@minimax (default) 14:23:45");
         }
+        Commands::InstallClaudeCode { api_key, no_statusline, uninstall } => {
+            use cli::claude_code;
+
+            let settings_path = claude_code::settings_path()?;
+            let mut settings = claude_code::load(&settings_path)?;
+
+            if uninstall {
+                let script_path = paths::state_dir().join("statusline.sh");
+                if !claude_code::uninstall(&mut settings, &script_path)? {
+                    println!("Nothing to remove from {}", settings_path.display());
+                    return Ok(());
+                }
+                let backup = claude_code::save(&settings_path, &settings)?;
+                println!("✅ Removed Claude Code Mux settings from {}", settings_path.display());
+                if let Some(backup) = backup {
+                    println!("   Backup: {}", backup.display());
+                }
+                return Ok(());
+            }
+
+            let script_path = if no_statusline { None } else { Some(install_statusline_script()?) };
+            let host = match config.server.host.as_str() {
+                "0.0.0.0" | "::" | "" => "127.0.0.1",
+                host => host,
+            };
+            let base_url = format!("http://{}:{}", host, config.server.port);
+            let report = claude_code::install(&mut settings, &base_url, api_key.as_deref(), script_path.as_deref())?;
+            let backup = claude_code::save(&settings_path, &settings)?;
+
+            println!("✅ Configured Claude Code in {}", settings_path.display());
+            println!("   ANTHROPIC_BASE_URL = {}", report.base_url);
+            if report.kept_api_key {
+                println!("   ANTHROPIC_API_KEY kept as is");
+            }
+            if let Some(script) = script_path {
+                println!("   Statusline: {}", script.display());
+            }
+            if let Some(backup) = backup {
+                println!("   Backup: {}", backup.display());
+            }
+            println!();
+            println!("Start the router with 'ccm start', then run 'claude'. Undo with 'ccm install-claude-code --uninstall'.");
+        }
+        Commands::Config { .. }
+        | Commands::CopilotLogin { .. }
+        | Commands::Doctor
+        | Commands::Logs { .. }
+        | Commands::Service { .. } => unreachable!("handled before loading the config"),
+        Commands::Eval { suite, model } => {
+            use cli::eval;
+
+            let suite_data = eval::load(&suite)?;
+            let models = if !model.is_empty() {
+                model
+            } else if !suite_data.models.is_empty() {
+                suite_data.models.clone()
+            } else {
+                vec![config.router.default.clone()]
+            };
+
+            let port = pid::read_pid_info().ok().and_then(|i| i.port).unwrap_or(config.server.port);
+            if pid::probe_health(&config.server.host, port).await.is_none() {
+                anyhow::bail!("Nothing is answering on port {}; start the router with 'ccm start' first", port);
+            }
+            let host = match config.server.host.as_str() {
+                "0.0.0.0" | "::" | "" => "127.0.0.1",
+                host => host,
+            };
+
+            let api_key = config.server.auth.as_ref().and_then(|auth| auth.api_keys.first()).map(String::as_str);
+            println!(
+                "🧪 Running {} case(s) from {} against {}",
+                suite_data.cases.len(),
+                suite.display(),
+                models.join(", ")
+            );
+            println!();
+            let results = eval::run(&suite_data, &models, &format!("http://{}:{}", host, port), api_key).await?;
+            println!();
+            print!("{}", eval::scoreboard(&results));
+
+            if results.iter().any(|r| !r.passed()) {
+                std::process::exit(1);
+            }
+        }
+        Commands::Reconcile { period, threshold, anthropic_admin_key } => {
+            use cli::reconcile;
+
+            let report = reconcile::run(&config, period, anthropic_admin_key.as_deref()).await?;
+            if !report.rows.is_empty() {
+                print!("{}", reconcile::table(&report.rows, threshold));
+            }
+            for error in &report.errors {
+                println!("❌ {}", error);
+            }
+            if !report.unsupported.is_empty() {
+                println!("ℹ️  No usage API to check against: {}", report.unsupported.join(", "));
+            }
+            if report.rows.is_empty() && report.errors.is_empty() {
+                println!("Nothing to reconcile: no OpenRouter or Anthropic API-key providers are configured");
+            }
+
+            let drifted = report.rows.iter().filter(|row| row.drifted(threshold)).count();
+            if drifted > 0 {
+                println!();
+                println!(
+                    "⚠️  {} source(s) drifted more than {}%; check the [pricing] entries for their models",
+                    drifted, threshold
+                );
+                std::process::exit(1);
+            }
+        }
     }
 
     Ok(())
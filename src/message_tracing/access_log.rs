@@ -0,0 +1,224 @@
+//! Access log: one JSON line per `/v1` request (route, model, provider, status,
+//! latency, tokens, client key), independent of message tracing. Lines are small
+//! and carry no message content, so it is on by default for auditing.
+//!
+//! Handlers don't pass the entry around: the request runs with it as a task-local,
+//! and code that learns something about the request fills it in with [`note`].
+//! Streaming bodies keep the task-local while they are polled, so usage reported
+//! at the end of a stream lands in the same line.
+
+use super::expand_tilde;
+use crate::cli::AccessLogConfig;
+use futures::Stream;
+use pin_project::{pin_project, pinned_drop};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+tokio::task_local! {
+    static CURRENT: Arc<Mutex<AccessEntry>>;
+}
+
+/// One request
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AccessEntry {
+    pub ts: String,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route: Option<String>,
+    /// Model name requested by the client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actual_model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_tokens: Option<u32>,
+    pub stream: bool,
+    /// First 8 hex digits of the client key's SHA-256, never the key itself
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<String>,
+}
+
+/// Short, stable identifier for a client key
+pub fn key_id(key: &str) -> String {
+    Sha256::digest(key.as_bytes()).iter().take(4).map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Fill in the current request's entry (a no-op outside a logged request)
+pub fn note(update: impl FnOnce(&mut AccessEntry)) {
+    let _ = CURRENT.try_with(|entry| update(&mut entry.lock().unwrap()));
+}
+
+/// Run a request's handler with `entry` as the current entry
+pub async fn track<F: std::future::Future>(entry: Arc<Mutex<AccessEntry>>, handler: F) -> F::Output {
+    CURRENT.scope(entry, handler).await
+}
+
+struct LogFile {
+    file: File,
+    written: u64,
+}
+
+/// Append-only JSONL file, rotated to `<path>.1` when it grows past `max_size_mb`
+pub struct AccessLog {
+    path: PathBuf,
+    max_bytes: u64,
+    file: Option<Mutex<LogFile>>,
+}
+
+fn open(path: &PathBuf) -> std::io::Result<LogFile> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let written = file.metadata()?.len();
+    Ok(LogFile { file, written })
+}
+
+impl AccessLog {
+    pub fn new(config: &AccessLogConfig) -> Self {
+        let path = expand_tilde(&config.path);
+        let max_bytes = config.max_size_mb.saturating_mul(1024 * 1024);
+        if !config.enabled {
+            return Self { path, max_bytes, file: None };
+        }
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        match open(&path) {
+            Ok(file) => {
+                tracing::info!("📒 Access log: {}", path.display());
+                Self { path, max_bytes, file: Some(Mutex::new(file)) }
+            }
+            Err(e) => {
+                tracing::error!("Failed to open access log {}: {}", path.display(), e);
+                Self { path, max_bytes, file: None }
+            }
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    pub fn write(&self, entry: &AccessEntry) {
+        let Some(ref file) = self.file else {
+            return;
+        };
+        let Ok(mut line) = serde_json::to_string(entry) else {
+            return;
+        };
+        line.push('\n');
+
+        let mut log = file.lock().unwrap();
+        if self.max_bytes > 0 && log.written + line.len() as u64 > self.max_bytes {
+            let rotated = PathBuf::from(format!("{}.1", self.path.display()));
+            match std::fs::rename(&self.path, &rotated).and_then(|_| open(&self.path)) {
+                Ok(fresh) => *log = fresh,
+                Err(e) => tracing::warn!("Failed to rotate access log {}: {}", self.path.display(), e),
+            }
+        }
+        if let Err(e) = log.file.write_all(line.as_bytes()) {
+            tracing::warn!("Failed to write access log: {}", e);
+            return;
+        }
+        log.written += line.len() as u64;
+    }
+}
+
+/// Called with a streamed request's finished entry
+type OnEnd = Box<dyn FnOnce(&AccessEntry) + Send>;
+
+/// Response body of a streamed request: keeps the entry current while polled and
+/// hands it to `on_end` (which writes the line) once the stream ends or the client goes away
+#[pin_project(PinnedDrop)]
+pub struct LoggedStream<S> {
+    #[pin]
+    inner: S,
+    entry: Arc<Mutex<AccessEntry>>,
+    started: Instant,
+    on_end: Option<OnEnd>,
+}
+
+impl<S> LoggedStream<S> {
+    pub fn new(
+        inner: S,
+        entry: Arc<Mutex<AccessEntry>>,
+        started: Instant,
+        on_end: impl FnOnce(&AccessEntry) + Send + 'static,
+    ) -> Self {
+        Self { inner, entry, started, on_end: Some(Box::new(on_end)) }
+    }
+}
+
+impl<S: Stream> Stream for LoggedStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        CURRENT.sync_scope(this.entry.clone(), || this.inner.poll_next(cx))
+    }
+}
+
+#[pinned_drop]
+impl<S> PinnedDrop for LoggedStream<S> {
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+        let mut entry = this.entry.lock().unwrap();
+        entry.latency_ms = this.started.elapsed().as_millis() as u64;
+        if let Some(on_end) = this.on_end.take() {
+            on_end(&entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_access_log_stream_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AccessLogConfig {
+            enabled: true,
+            path: dir.path().join("access.jsonl").display().to_string(),
+            max_size_mb: 100,
+        };
+        let log = Arc::new(AccessLog::new(&config));
+        let entry = Arc::new(Mutex::new(AccessEntry {
+            method: "POST".to_string(),
+            path: "/v1/messages".to_string(),
+            client_key: Some(key_id("ccm-laptop-key")),
+            ..Default::default()
+        }));
+
+        track(entry.clone(), async { note(|e| e.route = Some("think".to_string())) }).await;
+        // Usage noted while the body is polled, as at the end of an SSE stream
+        let body = futures::stream::iter(["chunk"]).map(|chunk| {
+            note(|e| e.output_tokens = Some(42));
+            chunk
+        });
+        let chunks: Vec<_> = LoggedStream::new(body, entry, Instant::now(), move |e| log.write(e)).collect().await;
+        assert_eq!(chunks, ["chunk"]);
+        note(|e| e.output_tokens = Some(0)); // outside a request: ignored
+
+        let written = std::fs::read_to_string(dir.path().join("access.jsonl")).unwrap();
+        let line: serde_json::Value = serde_json::from_str(written.trim()).unwrap();
+        assert_eq!(line["route"], "think");
+        assert_eq!(line["output_tokens"], 42);
+        assert_eq!(line["client_key"], key_id("ccm-laptop-key"));
+        assert_eq!(line["client_key"].as_str().unwrap().len(), 8);
+        assert!(line.get("provider").is_none());
+    }
+}
@@ -0,0 +1,408 @@
+//! Dataset recorder for fine-tuning
+//!
+//! Writes sanitized prompt/response pairs to a JSONL file in OpenAI chat
+//! fine-tuning format (`{"messages": [...], "tools": [...]}`). System prompts
+//! are dropped, client `<system-reminder>` blocks are removed, thinking blocks
+//! are skipped and tool results are truncated.
+
+use super::expand_tilde;
+use crate::cli::DatasetConfig;
+use crate::models::{
+    AnthropicRequest, ContentBlock, KnownContentBlock, MessageContent, RouteType,
+};
+use crate::providers::streaming::parse_sse_events;
+use bytes::Bytes;
+use futures::stream::Stream;
+use pin_project::pin_project;
+use serde_json::{json, Value};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+/// Dataset recorder that appends fine-tuning samples to a JSONL file
+pub struct DatasetRecorder {
+    config: DatasetConfig,
+    file: Option<Mutex<File>>,
+}
+
+/// Prompt half of a sample, captured before the request is sent
+#[derive(Debug, Clone)]
+pub struct DatasetSample {
+    messages: Vec<Value>,
+    tools: Option<Vec<Value>>,
+}
+
+impl DatasetRecorder {
+    /// Create a new recorder from config
+    pub fn new(config: DatasetConfig) -> Self {
+        if !config.enabled {
+            return Self { config, file: None };
+        }
+
+        let path = expand_tilde(&config.path);
+
+        if let Some(parent) = path.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                tracing::error!("Failed to create dataset directory: {}", e);
+                return Self { config, file: None };
+            }
+        }
+
+        match OpenOptions::new().create(true).append(true).open(&path) {
+            Ok(file) => {
+                tracing::info!("🗂️  Dataset recording enabled: {}", path.display());
+                Self {
+                    config,
+                    file: Some(Mutex::new(file)),
+                }
+            }
+            Err(e) => {
+                tracing::error!("Failed to open dataset file: {}", e);
+                Self { config, file: None }
+            }
+        }
+    }
+
+    /// Capture the prompt side of a request if recording applies to this route
+    pub fn prepare(&self, request: &AnthropicRequest, route_type: &RouteType) -> Option<DatasetSample> {
+        self.file.as_ref()?;
+
+        if !self.config.routes.is_empty() {
+            let route = route_type.to_string();
+            if !self.config.routes.iter().any(|r| r.eq_ignore_ascii_case(&route)) {
+                return None;
+            }
+        }
+
+        let mut messages = Vec::new();
+        for message in &request.messages {
+            self.convert_message(&message.role, &message.content, &mut messages);
+        }
+
+        if messages.is_empty() {
+            return None;
+        }
+
+        let tools = request.tools.as_ref().map(|tools| {
+            tools
+                .iter()
+                .filter_map(|tool| {
+                    // Server tools (web_search etc.) have no schema to fine-tune on
+                    let name = tool.name.as_ref()?;
+                    Some(json!({
+                        "type": "function",
+                        "function": {
+                            "name": name,
+                            "description": tool.description.clone().unwrap_or_default(),
+                            "parameters": tool.input_schema.clone().unwrap_or_else(|| json!({"type": "object"})),
+                        }
+                    }))
+                })
+                .collect::<Vec<_>>()
+        }).filter(|tools| !tools.is_empty());
+
+        Some(DatasetSample { messages, tools })
+    }
+
+    /// Complete a sample with the assistant response and write it
+    pub fn record(&self, mut sample: DatasetSample, response: &[ContentBlock]) {
+        let Some(ref file_mutex) = self.file else {
+            return;
+        };
+
+        let before = sample.messages.len();
+        self.convert_blocks("assistant", response, &mut sample.messages);
+        if sample.messages.len() == before {
+            // Empty response - nothing to learn from
+            return;
+        }
+
+        let mut line = json!({ "messages": sample.messages });
+        if let Some(tools) = sample.tools {
+            line["tools"] = Value::Array(tools);
+        }
+
+        let Ok(json) = serde_json::to_string(&line) else {
+            return;
+        };
+
+        if let Ok(mut file) = file_mutex.lock() {
+            let _ = writeln!(file, "{}", json);
+        }
+    }
+
+    fn convert_message(&self, role: &str, content: &MessageContent, out: &mut Vec<Value>) {
+        match content {
+            MessageContent::Text(text) => {
+                if !is_system_reminder(text) {
+                    out.push(json!({"role": role, "content": text}));
+                }
+            }
+            MessageContent::Blocks(blocks) => self.convert_blocks(role, blocks, out),
+        }
+    }
+
+    /// Convert Anthropic content blocks to OpenAI chat messages.
+    /// Tool results become separate `tool` messages; tool_use becomes `tool_calls`.
+    fn convert_blocks(&self, role: &str, blocks: &[ContentBlock], out: &mut Vec<Value>) {
+        let mut text_parts = Vec::new();
+        let mut tool_calls = Vec::new();
+
+        for block in blocks {
+            match block {
+                ContentBlock::Known(KnownContentBlock::Text { text, .. }) => {
+                    if !is_system_reminder(text) && !text.trim().is_empty() {
+                        text_parts.push(text.clone());
+                    }
+                }
+                ContentBlock::Known(KnownContentBlock::Image { .. }) => {
+                    text_parts.push("[Image]".to_string());
+                }
+                ContentBlock::Known(KnownContentBlock::ToolUse { id, name, input }) => {
+                    tool_calls.push(json!({
+                        "id": id,
+                        "type": "function",
+                        "function": {
+                            "name": name,
+                            "arguments": input.to_string(),
+                        }
+                    }));
+                }
+                ContentBlock::Known(KnownContentBlock::ToolResult { tool_use_id, content, .. }) => {
+                    out.push(json!({
+                        "role": "tool",
+                        "tool_call_id": tool_use_id,
+                        "content": truncate(&content.to_string(), self.config.max_tool_result_chars),
+                    }));
+                }
+                ContentBlock::Known(KnownContentBlock::Thinking { .. }) | ContentBlock::Unknown(_) => {}
+            }
+        }
+
+        if text_parts.is_empty() && tool_calls.is_empty() {
+            return;
+        }
+
+        let mut message = json!({
+            "role": role,
+            "content": text_parts.join("\n"),
+        });
+        if !tool_calls.is_empty() {
+            message["tool_calls"] = Value::Array(tool_calls);
+        }
+        out.push(message);
+    }
+}
+
+fn is_system_reminder(text: &str) -> bool {
+    text.trim().starts_with("<system-reminder>")
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars).collect();
+    truncated.push_str("\n[truncated]");
+    truncated
+}
+
+/// Stream adapter that rebuilds the assistant response from SSE events and
+/// records the sample once the stream completes. Bytes pass through unchanged.
+#[pin_project]
+pub struct DatasetStream<S> {
+    #[pin]
+    inner: S,
+    recorder: Arc<DatasetRecorder>,
+    sample: Option<DatasetSample>,
+    buffer: String,
+    /// (block type, text or partial json, tool id, tool name)
+    blocks: Vec<(String, String, String, String)>,
+}
+
+impl<S> DatasetStream<S> {
+    pub fn new(stream: S, recorder: Arc<DatasetRecorder>, sample: DatasetSample) -> Self {
+        Self {
+            inner: stream,
+            recorder,
+            sample: Some(sample),
+            buffer: String::new(),
+            blocks: Vec::new(),
+        }
+    }
+}
+
+impl<S, E> Stream for DatasetStream<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        match this.inner.poll_next(cx) {
+            Poll::Ready(Some(Ok(bytes))) => {
+                if let Ok(text) = std::str::from_utf8(&bytes) {
+                    this.buffer.push_str(text);
+                }
+
+                if let Some(end) = this.buffer.rfind("\n\n") {
+                    let complete: String = this.buffer.drain(..end + 2).collect();
+                    for event in parse_sse_events(&complete) {
+                        let Ok(data) = serde_json::from_str::<Value>(&event.data) else {
+                            continue;
+                        };
+                        match data.get("type").and_then(|t| t.as_str()) {
+                            Some("content_block_start") => {
+                                let block = &data["content_block"];
+                                this.blocks.push((
+                                    block["type"].as_str().unwrap_or_default().to_string(),
+                                    block["text"].as_str().unwrap_or_default().to_string(),
+                                    block["id"].as_str().unwrap_or_default().to_string(),
+                                    block["name"].as_str().unwrap_or_default().to_string(),
+                                ));
+                            }
+                            Some("content_block_delta") => {
+                                if let Some(current) = this.blocks.last_mut() {
+                                    let delta = &data["delta"];
+                                    if let Some(text) = delta["text"].as_str() {
+                                        current.1.push_str(text);
+                                    } else if let Some(json) = delta["partial_json"].as_str() {
+                                        current.1.push_str(json);
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+
+                Poll::Ready(Some(Ok(bytes)))
+            }
+            Poll::Ready(None) => {
+                if let Some(sample) = this.sample.take() {
+                    let content: Vec<ContentBlock> = this
+                        .blocks
+                        .drain(..)
+                        .filter_map(|(kind, body, id, name)| match kind.as_str() {
+                            "text" => Some(ContentBlock::text(body, None)),
+                            "tool_use" => {
+                                let input = serde_json::from_str(&body).unwrap_or_else(|_| json!({}));
+                                Some(ContentBlock::tool_use(id, name, input))
+                            }
+                            _ => None,
+                        })
+                        .collect();
+                    this.recorder.record(sample, &content);
+                }
+                Poll::Ready(None)
+            }
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    fn recorder(dir: &tempfile::TempDir, routes: Vec<String>) -> (DatasetRecorder, std::path::PathBuf) {
+        let path = dir.path().join("dataset.jsonl");
+        let config = DatasetConfig {
+            enabled: true,
+            path: path.to_string_lossy().to_string(),
+            routes,
+            max_tool_result_chars: 10,
+        };
+        (DatasetRecorder::new(config), path)
+    }
+
+    fn request() -> AnthropicRequest {
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 100,
+            "system": "You are Claude Code",
+            "tools": [{"name": "Read", "description": "Read a file", "input_schema": {"type": "object"}}],
+            "messages": [
+                {"role": "user", "content": [
+                    {"type": "text", "text": "<system-reminder>ignore me</system-reminder>"},
+                    {"type": "text", "text": "read main.rs"}
+                ]},
+                {"role": "assistant", "content": [
+                    {"type": "thinking", "thinking": "hmm", "signature": "sig"},
+                    {"type": "tool_use", "id": "toolu_1", "name": "Read", "input": {"path": "main.rs"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": "fn main() { println!(\"hi\"); }"}
+                ]}
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_records_sanitized_sample() {
+        let dir = tempfile::tempdir().unwrap();
+        let (recorder, path) = recorder(&dir, vec![]);
+
+        let sample = recorder.prepare(&request(), &RouteType::Default).unwrap();
+        recorder.record(sample, &[ContentBlock::text("Done".to_string(), None)]);
+
+        let line: Value = serde_json::from_str(std::fs::read_to_string(path).unwrap().trim()).unwrap();
+        let messages = line["messages"].as_array().unwrap();
+
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0], json!({"role": "user", "content": "read main.rs"}));
+        assert_eq!(messages[1]["tool_calls"][0]["function"]["name"], "Read");
+        assert_eq!(messages[2]["role"], "tool");
+        assert_eq!(messages[2]["content"], "fn main() \n[truncated]");
+        assert_eq!(messages[3], json!({"role": "assistant", "content": "Done"}));
+        assert_eq!(line["tools"][0]["function"]["name"], "Read");
+        // No system prompt anywhere
+        assert!(!line.to_string().contains("You are Claude Code"));
+    }
+
+    #[test]
+    fn test_route_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let (recorder, _) = recorder(&dir, vec!["think".to_string()]);
+
+        assert!(recorder.prepare(&request(), &RouteType::Default).is_none());
+        assert!(recorder.prepare(&request(), &RouteType::Think).is_some());
+    }
+
+    #[test]
+    fn test_disabled_recorder_prepares_nothing() {
+        let recorder = DatasetRecorder::new(DatasetConfig::default());
+        assert!(recorder.prepare(&request(), &RouteType::Default).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_records_on_completion() {
+        let dir = tempfile::tempdir().unwrap();
+        let (recorder, path) = recorder(&dir, vec![]);
+        let recorder = Arc::new(recorder);
+        let sample = recorder.prepare(&request(), &RouteType::Default).unwrap();
+
+        let sse = concat!(
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"All \"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"good\"}}\n\n",
+        );
+        let chunks: Vec<Result<Bytes, std::io::Error>> = vec![
+            Ok(Bytes::from(&sse[..40])),
+            Ok(Bytes::from(&sse[40..])),
+        ];
+        let stream = DatasetStream::new(futures::stream::iter(chunks), recorder, sample);
+        let passed: Vec<_> = stream.collect().await;
+        assert_eq!(passed.len(), 2);
+
+        let line: Value = serde_json::from_str(std::fs::read_to_string(path).unwrap().trim()).unwrap();
+        let messages = line["messages"].as_array().unwrap();
+        assert_eq!(messages.last().unwrap()["content"], "All good");
+    }
+}
@@ -3,6 +3,7 @@
 //! Logs full request/response messages to a JSONL file for debugging purposes.
 
 use crate::cli::TracingConfig;
+use crate::models::request_hash::hash_request;
 use crate::models::{AnthropicRequest, RouteType};
 use crate::providers::ProviderResponse;
 use chrono::{DateTime, Utc};
@@ -13,6 +14,13 @@ use std::path::PathBuf;
 use std::sync::Mutex;
 use uuid::Uuid;
 
+pub mod access_log;
+pub use access_log::AccessLog;
+pub mod dataset;
+pub use dataset::DatasetRecorder;
+pub mod usage;
+pub use usage::UsageStore;
+
 /// Message tracer that writes to JSONL file
 pub struct MessageTracer {
     config: TracingConfig,
@@ -25,10 +33,14 @@ struct RequestTrace {
     ts: DateTime<Utc>,
     dir: &'static str,
     id: String,
+    /// Same for identical requests, across restarts and releases
+    request_hash: String,
     model: String,
     provider: String,
     route_type: String,
     is_stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    anthropic_version: Option<String>,
     tool_count: usize,
     messages: serde_json::Value,
 }
@@ -43,6 +55,10 @@ struct ResponseTrace {
     stop_reason: String,
     input_tokens: u32,
     output_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_creation_input_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_read_input_tokens: Option<u32>,
     content: serde_json::Value,
 }
 
@@ -125,10 +141,12 @@ impl MessageTracer {
             ts: Utc::now(),
             dir: "req",
             id: id.to_string(),
+            request_hash: hash_request(request).to_string(),
             model: request.model.clone(),
             provider: provider.to_string(),
             route_type: route_type.to_string(),
             is_stream,
+            anthropic_version: request.anthropic_version.clone(),
             tool_count: request.tools.as_ref().map_or(0, |t| t.len()),
             messages,
         };
@@ -155,6 +173,8 @@ impl MessageTracer {
             stop_reason: response.stop_reason.clone().unwrap_or_default(),
             input_tokens: response.usage.input_tokens,
             output_tokens: response.usage.output_tokens,
+            cache_creation_input_tokens: response.usage.cache_creation_input_tokens,
+            cache_read_input_tokens: response.usage.cache_read_input_tokens,
             content: serde_json::to_value(&response.content).unwrap_or_default(),
         };
 
@@ -189,10 +209,10 @@ impl MessageTracer {
 }
 
 /// Expand ~ to home directory
-fn expand_tilde(path: &str) -> PathBuf {
-    if path.starts_with("~/") {
+pub(crate) fn expand_tilde(path: &str) -> PathBuf {
+    if let Some(rest) = path.strip_prefix("~/") {
         if let Some(home) = dirs::home_dir() {
-            return home.join(&path[2..]);
+            return home.join(rest);
         }
     }
     PathBuf::from(path)
@@ -0,0 +1,400 @@
+//! Usage history in SQLite
+//!
+//! One row per completed request (model, provider, route type, tokens, latency,
+//! cost), queried through `/api/usage`. Rows older than `retention_days` are pruned.
+
+use super::expand_tilde;
+use crate::cli::UsageConfig;
+use chrono::{DateTime, Duration, NaiveDate, SecondsFormat, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Prune old rows every this many inserts
+const PRUNE_EVERY: u64 = 1000;
+
+/// One completed request
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageRecord {
+    pub ts: String,
+    /// Model name requested by the client
+    pub model: String,
+    pub provider: String,
+    pub actual_model: String,
+    pub route_type: String,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    pub cache_read_tokens: u32,
+    pub cache_write_tokens: u32,
+    pub latency_ms: u64,
+    pub cost_usd: Option<f64>,
+    pub stream: bool,
+}
+
+/// Filters for `/api/usage`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UsageQuery {
+    /// RFC 3339 timestamp (inclusive)
+    pub since: Option<String>,
+    /// RFC 3339 timestamp (exclusive)
+    pub until: Option<String>,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub route_type: Option<String>,
+    /// Aggregate by `model`, `provider`, `route_type` or `day` instead of listing rows
+    pub group_by: Option<String>,
+    /// Maximum rows/groups returned (default 100)
+    pub limit: Option<u32>,
+}
+
+/// Aggregated usage for one group
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageGroup {
+    pub key: String,
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub cache_write_tokens: u64,
+    pub avg_latency_ms: f64,
+    pub cost_usd: f64,
+}
+
+/// Query result: raw rows or aggregates
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum UsageResult {
+    Rows(Vec<UsageRecord>),
+    Groups(Vec<UsageGroup>),
+}
+
+/// Recorded spend of one provider over a period
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderCost {
+    pub provider: String,
+    pub cost_usd: f64,
+    pub requests: u64,
+    /// Requests to models without a `[pricing]` entry (not in `cost_usd`)
+    pub unpriced_requests: u64,
+}
+
+/// SQLite-backed usage store (no-op when disabled)
+pub struct UsageStore {
+    conn: Option<Mutex<Connection>>,
+    retention_days: u32,
+    inserts: AtomicU64,
+}
+
+/// Timestamp format stored in the `ts` column (sorts lexically)
+fn format_ts(ts: DateTime<Utc>) -> String {
+    ts.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn normalize_ts(raw: &str) -> anyhow::Result<String> {
+    let ts = DateTime::parse_from_rfc3339(raw)
+        .map_err(|e| anyhow::anyhow!("invalid timestamp '{}': {}", raw, e))?;
+    Ok(format_ts(ts.with_timezone(&Utc)))
+}
+
+impl UsageStore {
+    /// Open (or create) the database from config
+    pub fn new(config: &UsageConfig) -> Self {
+        let disabled = Self { conn: None, retention_days: config.retention_days, inserts: AtomicU64::new(0) };
+        if !config.enabled {
+            return disabled;
+        }
+
+        let path = expand_tilde(&config.path);
+        if let Some(parent) = path.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                tracing::error!("Failed to create usage directory: {}", e);
+                return disabled;
+            }
+        }
+
+        match Connection::open(&path).map_err(anyhow::Error::from).and_then(|conn| Self::open(conn, config.retention_days)) {
+            Ok(store) => {
+                tracing::info!("🧾 Usage history enabled: {}", path.display());
+                store
+            }
+            Err(e) => {
+                tracing::error!("Failed to open usage database {}: {}", path.display(), e);
+                disabled
+            }
+        }
+    }
+
+    fn open(conn: Connection, retention_days: u32) -> anyhow::Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS usage (
+                id INTEGER PRIMARY KEY,
+                ts TEXT NOT NULL,
+                model TEXT NOT NULL,
+                provider TEXT NOT NULL,
+                actual_model TEXT NOT NULL,
+                route_type TEXT NOT NULL,
+                input_tokens INTEGER NOT NULL,
+                output_tokens INTEGER NOT NULL,
+                cache_read_tokens INTEGER NOT NULL,
+                cache_write_tokens INTEGER NOT NULL,
+                latency_ms INTEGER NOT NULL,
+                cost_usd REAL,
+                stream INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS usage_ts ON usage(ts);",
+        )?;
+        let store = Self { conn: Some(Mutex::new(conn)), retention_days, inserts: AtomicU64::new(0) };
+        store.prune();
+        Ok(store)
+    }
+
+    /// Append a row (errors are logged, never surfaced to the request)
+    pub fn record(&self, record: &UsageRecord) {
+        let Some(conn) = &self.conn else {
+            return;
+        };
+        let result = conn.lock().unwrap().execute(
+            "INSERT INTO usage (ts, model, provider, actual_model, route_type, input_tokens, output_tokens,
+                cache_read_tokens, cache_write_tokens, latency_ms, cost_usd, stream)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                record.ts,
+                record.model,
+                record.provider,
+                record.actual_model,
+                record.route_type,
+                record.input_tokens,
+                record.output_tokens,
+                record.cache_read_tokens,
+                record.cache_write_tokens,
+                record.latency_ms as i64,
+                record.cost_usd,
+                record.stream,
+            ],
+        );
+        if let Err(e) = result {
+            tracing::warn!("Failed to record usage: {}", e);
+        }
+        if self.inserts.fetch_add(1, Ordering::Relaxed) % PRUNE_EVERY == PRUNE_EVERY - 1 {
+            self.prune();
+        }
+    }
+
+    /// Current time in the `ts` column format
+    pub fn now() -> String {
+        format_ts(Utc::now())
+    }
+
+    /// Delete rows older than the retention window
+    fn prune(&self) {
+        let days = self.retention_days;
+        let Some(conn) = self.conn.as_ref().filter(|_| days > 0) else {
+            return;
+        };
+        let cutoff = format_ts(Utc::now() - Duration::days(days as i64));
+        match conn.lock().unwrap().execute("DELETE FROM usage WHERE ts < ?1", params![cutoff]) {
+            Ok(0) => {}
+            Ok(n) => tracing::debug!("Pruned {} usage rows older than {} days", n, days),
+            Err(e) => tracing::warn!("Failed to prune usage history: {}", e),
+        }
+    }
+
+    /// Priced spend per provider/actual_model/day since the start of the current month
+    pub fn spend_this_month(&self) -> anyhow::Result<Vec<(String, String, NaiveDate, f64)>> {
+        let Some(conn) = &self.conn else {
+            return Ok(Vec::new());
+        };
+        let today = Utc::now().date_naive();
+        let since = format!("{}-01", today.format("%Y-%m"));
+        let conn = conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT provider, actual_model, substr(ts, 1, 10) AS day, SUM(cost_usd) FROM usage
+             WHERE ts >= ?1 AND cost_usd IS NOT NULL GROUP BY provider, actual_model, day",
+        )?;
+        let rows = stmt.query_map(params![since], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, f64>(3)?))
+        })?;
+        let mut spend = Vec::new();
+        for row in rows {
+            let (provider, model, day, cost) = row?;
+            if let Ok(day) = NaiveDate::parse_from_str(&day, "%Y-%m-%d") {
+                spend.push((provider, model, day, cost));
+            }
+        }
+        Ok(spend)
+    }
+
+    /// Priced spend, request count and unpriced request count per provider since `since`
+    pub fn cost_by_provider(&self, since: DateTime<Utc>) -> anyhow::Result<Vec<ProviderCost>> {
+        let Some(conn) = &self.conn else {
+            anyhow::bail!("usage history is disabled (set [server.usage] enabled = true)");
+        };
+        let conn = conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT provider, COALESCE(SUM(cost_usd), 0), COUNT(*), COUNT(*) - COUNT(cost_usd) FROM usage
+             WHERE ts >= ?1 GROUP BY provider ORDER BY provider",
+        )?;
+        let rows = stmt.query_map(params![format_ts(since)], |row| {
+            Ok(ProviderCost {
+                provider: row.get(0)?,
+                cost_usd: row.get(1)?,
+                requests: row.get::<_, i64>(2)? as u64,
+                unpriced_requests: row.get::<_, i64>(3)? as u64,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Rows (newest first) or aggregates matching the query
+    pub fn query(&self, query: &UsageQuery) -> anyhow::Result<UsageResult> {
+        let Some(conn) = &self.conn else {
+            anyhow::bail!("usage history is disabled (set [server.usage] enabled = true)");
+        };
+
+        let mut conditions = Vec::new();
+        let mut values: Vec<String> = Vec::new();
+        if let Some(since) = &query.since {
+            values.push(normalize_ts(since)?);
+            conditions.push(format!("ts >= ?{}", values.len()));
+        }
+        if let Some(until) = &query.until {
+            values.push(normalize_ts(until)?);
+            conditions.push(format!("ts < ?{}", values.len()));
+        }
+        for (column, value) in [("provider", &query.provider), ("model", &query.model), ("route_type", &query.route_type)] {
+            if let Some(value) = value {
+                values.push(value.clone());
+                conditions.push(format!("{} = ?{}", column, values.len()));
+            }
+        }
+        let filter = if conditions.is_empty() { String::new() } else { format!("WHERE {}", conditions.join(" AND ")) };
+        let limit = query.limit.unwrap_or(100);
+        let conn = conn.lock().unwrap();
+
+        let Some(group_by) = &query.group_by else {
+            let sql = format!(
+                "SELECT ts, model, provider, actual_model, route_type, input_tokens, output_tokens, cache_read_tokens,
+                    cache_write_tokens, latency_ms, cost_usd, stream
+                 FROM usage {} ORDER BY ts DESC, id DESC LIMIT {}",
+                filter, limit
+            );
+            let mut stmt = conn.prepare(&sql)?;
+            let rows = stmt.query_map(rusqlite::params_from_iter(values.iter()), |row| {
+                Ok(UsageRecord {
+                    ts: row.get(0)?,
+                    model: row.get(1)?,
+                    provider: row.get(2)?,
+                    actual_model: row.get(3)?,
+                    route_type: row.get(4)?,
+                    input_tokens: row.get(5)?,
+                    output_tokens: row.get(6)?,
+                    cache_read_tokens: row.get(7)?,
+                    cache_write_tokens: row.get(8)?,
+                    latency_ms: row.get::<_, i64>(9)? as u64,
+                    cost_usd: row.get(10)?,
+                    stream: row.get(11)?,
+                })
+            })?;
+            return Ok(UsageResult::Rows(rows.collect::<Result<_, _>>()?));
+        };
+
+        let key = match group_by.as_str() {
+            "model" => "model",
+            "provider" => "provider",
+            "route_type" => "route_type",
+            "day" => "substr(ts, 1, 10)",
+            other => anyhow::bail!("invalid group_by '{}' (expected model, provider, route_type or day)", other),
+        };
+        let sql = format!(
+            "SELECT {key} AS k, COUNT(*), SUM(input_tokens), SUM(output_tokens), SUM(cache_read_tokens),
+                SUM(cache_write_tokens), AVG(latency_ms), COALESCE(SUM(cost_usd), 0)
+             FROM usage {filter} GROUP BY k ORDER BY k LIMIT {limit}"
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let groups = stmt.query_map(rusqlite::params_from_iter(values.iter()), |row| {
+            Ok(UsageGroup {
+                key: row.get(0)?,
+                requests: row.get::<_, i64>(1)? as u64,
+                input_tokens: row.get::<_, i64>(2)? as u64,
+                output_tokens: row.get::<_, i64>(3)? as u64,
+                cache_read_tokens: row.get::<_, i64>(4)? as u64,
+                cache_write_tokens: row.get::<_, i64>(5)? as u64,
+                avg_latency_ms: row.get(6)?,
+                cost_usd: row.get(7)?,
+            })
+        })?;
+        Ok(UsageResult::Groups(groups.collect::<Result<_, _>>()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(ts: &str, model: &str, provider: &str, cost: Option<f64>) -> UsageRecord {
+        UsageRecord {
+            ts: ts.to_string(),
+            model: model.to_string(),
+            provider: provider.to_string(),
+            actual_model: model.to_string(),
+            route_type: "default".to_string(),
+            input_tokens: 100,
+            output_tokens: 50,
+            latency_ms: 200,
+            cost_usd: cost,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_record_and_query() {
+        let store = UsageStore::open(Connection::open_in_memory().unwrap(), 0).unwrap();
+        store.record(&record("2025-01-01T10:00:00.000Z", "sonnet", "anthropic", Some(0.01)));
+        store.record(&record("2025-01-01T11:00:00.000Z", "sonnet", "openrouter", None));
+        store.record(&record("2025-01-02T09:00:00.000Z", "haiku", "anthropic", Some(0.002)));
+
+        let UsageResult::Rows(rows) = store.query(&UsageQuery { provider: Some("anthropic".into()), ..Default::default() }).unwrap() else {
+            panic!("expected rows");
+        };
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].model, "haiku");
+
+        let query = UsageQuery { since: Some("2025-01-01T10:30:00Z".into()), ..Default::default() };
+        let UsageResult::Rows(rows) = store.query(&query).unwrap() else {
+            panic!("expected rows");
+        };
+        assert_eq!(rows.len(), 2);
+
+        let UsageResult::Groups(days) = store.query(&UsageQuery { group_by: Some("day".into()), ..Default::default() }).unwrap() else {
+            panic!("expected groups");
+        };
+        assert_eq!(days.len(), 2);
+        assert_eq!((days[0].key.as_str(), days[0].requests, days[0].input_tokens), ("2025-01-01", 2, 200));
+        assert!((days[0].cost_usd - 0.01).abs() < 1e-9);
+
+        assert!(store.query(&UsageQuery { group_by: Some("ts; DROP TABLE usage".into()), ..Default::default() }).is_err());
+
+        // Old rows are outside the current month
+        assert!(store.spend_this_month().unwrap().is_empty());
+        store.record(&record(&UsageStore::now(), "sonnet", "anthropic", Some(0.5)));
+        store.record(&record(&UsageStore::now(), "sonnet", "anthropic", Some(0.25)));
+        let spend = store.spend_this_month().unwrap();
+        assert_eq!(spend.len(), 1);
+        assert!((spend[0].3 - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_retention_prunes_old_rows() {
+        let conn = Connection::open_in_memory().unwrap();
+        let store = UsageStore::open(conn, 30).unwrap();
+        store.record(&record("2000-01-01T00:00:00.000Z", "sonnet", "anthropic", None));
+        store.record(&record(&UsageStore::now(), "sonnet", "anthropic", None));
+        store.prune();
+
+        let UsageResult::Rows(rows) = store.query(&UsageQuery::default()).unwrap() else {
+            panic!("expected rows");
+        };
+        assert_eq!(rows.len(), 1);
+    }
+}
@@ -1,8 +1,11 @@
+pub mod request_hash;
+
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 /// Anthropic API request format
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[non_exhaustive]
 pub struct AnthropicRequest {
     pub model: String,
     pub messages: Vec<Message>,
@@ -25,6 +28,61 @@ pub struct AnthropicRequest {
     pub system: Option<SystemPrompt>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<Tool>>,
+    /// Client's anthropic-version header (not part of the request body)
+    #[serde(skip)]
+    pub anthropic_version: Option<String>,
+    /// Mapping's `extra_body`, merged into the provider's request JSON (not part of the request body)
+    #[serde(skip)]
+    pub extra_body: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+impl AnthropicRequest {
+    /// A request with only the required fields set
+    #[allow(dead_code)] // Part of the embedding API, not used by the binary
+    pub fn new(model: impl Into<String>, max_tokens: u32, messages: Vec<Message>) -> Self {
+        Self {
+            model: model.into(),
+            messages,
+            max_tokens,
+            thinking: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
+            stop_sequences: None,
+            stream: None,
+            metadata: None,
+            system: None,
+            tools: None,
+            anthropic_version: None,
+            extra_body: None,
+        }
+    }
+}
+
+/// Anthropic API version used when the client doesn't send one
+pub const DEFAULT_ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Validate an anthropic-version header value.
+/// Versions are dates (YYYY-MM-DD); unknown newer dates are accepted so clients can adopt them.
+pub fn validate_anthropic_version(version: &str) -> Result<(), String> {
+    let is_date_shaped = version.len() == 10
+        && version.chars().enumerate().all(|(i, c)| match i {
+            4 | 7 => c == '-',
+            _ => c.is_ascii_digit(),
+        });
+
+    if !is_date_shaped || chrono::NaiveDate::parse_from_str(version, "%Y-%m-%d").is_err() {
+        return Err(format!(
+            "Invalid anthropic-version '{}': expected a date like {}",
+            version, DEFAULT_ANTHROPIC_VERSION
+        ));
+    }
+
+    if version < "2023-01-01" {
+        return Err(format!("Unsupported anthropic-version '{}'", version));
+    }
+
+    Ok(())
 }
 
 /// Message in the conversation
@@ -68,20 +126,21 @@ pub enum ToolResultContent {
     Blocks(Vec<ToolResultBlock>),
 }
 
-impl ToolResultContent {
-    /// Convert to string (for OpenAI compatibility)
-    pub fn to_string(&self) -> String {
+impl std::fmt::Display for ToolResultContent {
+    /// Render as plain text (for OpenAI compatibility)
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ToolResultContent::Text(s) => s.clone(),
+            ToolResultContent::Text(s) => f.write_str(s),
             ToolResultContent::Blocks(blocks) => {
-                blocks.iter()
-                    .filter_map(|block| match block {
-                        ToolResultBlock::Known(KnownToolResultBlock::Text { text }) => Some(text.clone()),
-                        ToolResultBlock::Known(KnownToolResultBlock::Image { .. }) => Some("[Image]".to_string()),
-                        ToolResultBlock::Unknown(_) => Some("[Unknown]".to_string()),
+                let text = blocks.iter()
+                    .map(|block| match block {
+                        ToolResultBlock::Known(KnownToolResultBlock::Text { text }) => text.clone(),
+                        ToolResultBlock::Known(KnownToolResultBlock::Image { .. }) => "[Image]".to_string(),
+                        ToolResultBlock::Unknown(_) => "[Unknown]".to_string(),
                     })
                     .collect::<Vec<_>>()
-                    .join("\n")
+                    .join("\n");
+                f.write_str(&text)
             }
         }
     }
@@ -223,6 +282,8 @@ pub struct Tool {
     pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub input_schema: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<serde_json::Value>,
 }
 
 /// Thinking/reasoning configuration for Plan Mode
@@ -250,6 +311,9 @@ pub struct CountTokensRequest {
     pub system: Option<SystemPrompt>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<Tool>>,
+    /// Client's anthropic-version header (not part of the request body)
+    #[serde(skip)]
+    pub anthropic_version: Option<String>,
 }
 
 /// Response for token counting
@@ -260,6 +324,7 @@ pub struct CountTokensResponse {
 
 /// Router decision result
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct RouteDecision {
     pub model_name: String,
     pub route_type: RouteType,
@@ -268,22 +333,68 @@ pub struct RouteDecision {
 
 /// Type of routing decision
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum RouteType {
     WebSearch,
+    Suggest,
+    LongContext,
     PromptRule,
     Think,
     Background,
     Default,
+    /// Routing skipped via X-CCM-Bypass header
+    Bypass,
+}
+
+impl RouteType {
+    /// Every route type's name, as displayed
+    pub const NAMES: &'static [&'static str] =
+        &["web-search", "suggest", "long-context", "prompt-rule", "think", "background", "default", "bypass"];
 }
 
 impl std::fmt::Display for RouteType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             RouteType::WebSearch => write!(f, "web-search"),
+            RouteType::Suggest => write!(f, "suggest"),
+            RouteType::LongContext => write!(f, "long-context"),
             RouteType::PromptRule => write!(f, "prompt-rule"),
             RouteType::Think => write!(f, "think"),
             RouteType::Background => write!(f, "background"),
             RouteType::Default => write!(f, "default"),
+            RouteType::Bypass => write!(f, "bypass"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_anthropic_version() {
+        assert!(validate_anthropic_version("2023-06-01").is_ok());
+        assert!(validate_anthropic_version("2023-01-01").is_ok());
+        // Future versions pass through
+        assert!(validate_anthropic_version("2026-03-15").is_ok());
+
+        assert!(validate_anthropic_version("2022-12-31").is_err());
+        assert!(validate_anthropic_version("2023-13-01").is_err());
+        assert!(validate_anthropic_version("2023-6-1").is_err());
+        assert!(validate_anthropic_version("latest").is_err());
+    }
+
+    #[test]
+    fn test_anthropic_version_not_serialized() {
+        let mut request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 10,
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+        request.anthropic_version = Some("2023-06-01".to_string());
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert!(json.get("anthropic_version").is_none());
+    }
+}
@@ -0,0 +1,171 @@
+//! Canonical request hashing, shared by everything that needs to recognize the
+//! same request again (conversation fingerprints, caching, dedup, replay).
+//!
+//! Only what determines the conversation is hashed: model, system prompt, messages
+//! and tools. Delivery details (`stream`, `metadata`, the anthropic-version header)
+//! and `cache_control` markers are left out, object keys are sorted, and a plain
+//! string is hashed like the single text block it stands for. The digest is SHA-256,
+//! so hashes are stable across processes and releases; changing the canonical form
+//! requires bumping [`VERSION`].
+
+use super::{AnthropicRequest, Message, MessageContent, SystemPrompt};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// Prefix of every hashed document; bump when the canonical form changes
+const VERSION: &str = "ccm-request-v1";
+
+/// SHA-256 of a request's canonical form
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestHash([u8; 32]);
+
+impl fmt::Display for RequestHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+/// Hash of the model, system prompt, messages and tools of `request`
+pub fn hash_request(request: &AnthropicRequest) -> RequestHash {
+    let system = match &request.system {
+        Some(SystemPrompt::Text(text)) => json!([{"type": "text", "text": text}]),
+        Some(SystemPrompt::Blocks(blocks)) => blocks_value(serde_json::to_value(blocks).unwrap_or_default()),
+        None => json!([]),
+    };
+    let tools = match &request.tools {
+        Some(tools) => blocks_value(serde_json::to_value(tools).unwrap_or_default()),
+        None => json!([]),
+    };
+    digest(&json!({
+        "model": request.model,
+        "system": system,
+        "messages": messages_value(&request.messages),
+        "tools": tools,
+    }))
+}
+
+/// Hash of a conversation's messages alone, for relating requests across models
+pub fn hash_messages(messages: &[Message]) -> RequestHash {
+    digest(&json!({ "messages": messages_value(messages) }))
+}
+
+fn messages_value(messages: &[Message]) -> Value {
+    messages
+        .iter()
+        .map(|message| {
+            let content = match &message.content {
+                MessageContent::Text(text) => json!([{"type": "text", "text": text}]),
+                MessageContent::Blocks(blocks) => blocks_value(serde_json::to_value(blocks).unwrap_or_default()),
+            };
+            json!({"role": message.role, "content": content})
+        })
+        .collect()
+}
+
+/// Drop `cache_control` from a list of blocks, including tool result blocks
+fn blocks_value(mut blocks: Value) -> Value {
+    for block in blocks.as_array_mut().into_iter().flatten() {
+        let Some(block) = block.as_object_mut() else {
+            continue;
+        };
+        block.remove("cache_control");
+        if let Some(content) = block.get_mut("content").filter(|c| c.is_array()) {
+            *content = blocks_value(content.take());
+        }
+    }
+    blocks
+}
+
+fn digest(document: &Value) -> RequestHash {
+    let mut canonical = format!("{}\n", VERSION);
+    write_canonical(document, &mut canonical);
+    RequestHash(Sha256::digest(canonical.as_bytes()).into())
+}
+
+/// Compact JSON with object keys sorted (serde_json keeps insertion order here)
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(value, out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(value: Value) -> AnthropicRequest {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_request_hash_is_stable() {
+        let request = request(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "system": "You are terse.",
+            "messages": [
+                {"role": "user", "content": "List the files"},
+                {"role": "assistant", "content": [{"type": "tool_use", "id": "t1", "name": "ls", "input": {"path": ".", "all": true}}]},
+                {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "t1", "content": "a.rs\nb.rs"}]}
+            ],
+            "tools": [{"name": "ls", "description": "List files", "input_schema": {"type": "object"}}]
+        }));
+        // Pinned: a change here invalidates every stored hash, so bump VERSION instead
+        assert_eq!(
+            hash_request(&request).to_string(),
+            "9e6db810ffea056bc9b00978759bd528bf734677ce2f469aaebe60060cfce601"
+        );
+    }
+
+    #[test]
+    fn test_request_hash_ignores_volatile_fields() {
+        let base = hash_request(&request(json!({
+            "model": "m",
+            "max_tokens": 10,
+            "system": "Be brief.",
+            "messages": [{"role": "user", "content": "hi"}],
+            "tools": [{"name": "t", "input_schema": {"type": "object", "properties": {}}}]
+        })));
+
+        let mut variant = request(json!({
+            "model": "m",
+            "max_tokens": 10,
+            "stream": true,
+            "metadata": {"user_id": "session-1"},
+            "system": [{"type": "text", "text": "Be brief.", "cache_control": {"type": "ephemeral"}}],
+            "messages": [{"role": "user", "content": [{"type": "text", "text": "hi", "cache_control": {"type": "ephemeral"}}]}],
+            "tools": [{"input_schema": {"properties": {}, "type": "object"}, "name": "t", "cache_control": {"type": "ephemeral"}}]
+        }));
+        variant.anthropic_version = Some("2023-06-01".to_string());
+        assert_eq!(hash_request(&variant), base);
+
+        // Content changes the hash
+        variant.model = "other".to_string();
+        assert_ne!(hash_request(&variant), base);
+    }
+}
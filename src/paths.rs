@@ -0,0 +1,130 @@
+//! Location of CCM's state files (PID file, OAuth tokens, traces, statusline info).
+//!
+//! Resolution order:
+//! 1. `--state-dir` flag (see [`set_state_dir`])
+//! 2. `CCM_STATE_DIR` environment variable
+//! 3. `~/.claude-code-mux` if it already exists (keeps existing installs where they are)
+//! 4. `$XDG_STATE_HOME/claude-code-mux` if `XDG_STATE_HOME` is set
+//! 5. `~/.claude-code-mux`
+//!
+//! A named instance (`--name work`, see [`set_instance`]) keeps its state files in
+//! `instances/<name>` under that directory, so several instances can run side by side.
+
+use once_cell::sync::OnceCell;
+use std::path::{Path, PathBuf};
+
+/// Environment variable overriding the state directory
+pub const STATE_DIR_ENV: &str = "CCM_STATE_DIR";
+
+const LEGACY_DIR: &str = ".claude-code-mux";
+
+static BASE_STATE_DIR: OnceCell<PathBuf> = OnceCell::new();
+static INSTANCE: OnceCell<String> = OnceCell::new();
+static STATE_DIR: OnceCell<PathBuf> = OnceCell::new();
+
+/// Set the state directory from the command line. Must be called before any state file is used.
+pub fn set_state_dir(path: PathBuf) {
+    if BASE_STATE_DIR.set(path).is_err() {
+        tracing::warn!("State directory already initialized, ignoring --state-dir");
+    }
+}
+
+/// Select a named instance from the command line. Must be called before any state file is used.
+pub fn set_instance(name: String) {
+    if INSTANCE.set(name).is_err() {
+        tracing::warn!("Instance already selected, ignoring --name");
+    }
+}
+
+/// Name of the selected instance (None for the default instance)
+pub fn instance() -> Option<&'static str> {
+    INSTANCE.get().map(String::as_str)
+}
+
+/// Check an instance name: letters, digits, `-` and `_`, as it ends up in file,
+/// systemd unit and launchd label names
+pub fn parse_instance_name(name: &str) -> Result<String, String> {
+    let valid_chars = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if name.is_empty() || name.len() > 64 || !valid_chars || name.starts_with('-') {
+        return Err("use up to 64 letters, digits, '-' and '_', not starting with '-'".to_string());
+    }
+    Ok(name.to_string())
+}
+
+/// State directory shared by all instances (`--state-dir`, `CCM_STATE_DIR`, ...)
+pub fn base_state_dir() -> &'static Path {
+    BASE_STATE_DIR.get_or_init(|| {
+        let env = |name: &str| std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+        resolve_state_dir(env(STATE_DIR_ENV), dirs::home_dir(), env("XDG_STATE_HOME"))
+    })
+}
+
+/// Directory holding the selected instance's state files (not created here)
+pub fn state_dir() -> &'static Path {
+    STATE_DIR.get_or_init(|| instance_dir(base_state_dir(), instance()))
+}
+
+/// Path of a file inside the state directory
+pub fn state_file(name: &str) -> PathBuf {
+    state_dir().join(name)
+}
+
+fn instance_dir(base: &Path, instance: Option<&str>) -> PathBuf {
+    match instance {
+        Some(name) => base.join("instances").join(name),
+        None => base.to_path_buf(),
+    }
+}
+
+fn resolve_state_dir(explicit: Option<PathBuf>, home: Option<PathBuf>, xdg_state_home: Option<PathBuf>) -> PathBuf {
+    if let Some(dir) = explicit {
+        return dir;
+    }
+
+    let legacy = home.unwrap_or_else(|| PathBuf::from(".")).join(LEGACY_DIR);
+    match xdg_state_home {
+        Some(xdg) if !legacy.exists() => xdg.join("claude-code-mux"),
+        _ => legacy,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_state_dir() {
+        let home = tempfile::tempdir().unwrap();
+        let home_path = home.path().to_path_buf();
+        let xdg = PathBuf::from("/xdg/state");
+
+        // Explicit override always wins
+        assert_eq!(
+            resolve_state_dir(Some("/custom".into()), Some(home_path.clone()), Some(xdg.clone())),
+            PathBuf::from("/custom")
+        );
+
+        // Fresh install with XDG_STATE_HOME uses it
+        assert_eq!(
+            resolve_state_dir(None, Some(home_path.clone()), Some(xdg.clone())),
+            xdg.join("claude-code-mux")
+        );
+        assert_eq!(resolve_state_dir(None, Some(home_path.clone()), None), home_path.join(LEGACY_DIR));
+
+        // Existing ~/.claude-code-mux is kept
+        std::fs::create_dir(home_path.join(LEGACY_DIR)).unwrap();
+        assert_eq!(resolve_state_dir(None, Some(home_path.clone()), Some(xdg)), home_path.join(LEGACY_DIR));
+    }
+
+    #[test]
+    fn test_instances() {
+        let base = Path::new("/state");
+        assert_eq!(instance_dir(base, None), PathBuf::from("/state"));
+        assert_eq!(instance_dir(base, Some("work")), PathBuf::from("/state/instances/work"));
+
+        assert_eq!(parse_instance_name("work_2-b").as_deref(), Ok("work_2-b"));
+        for bad in ["", "-work", "my work", "../work", "work.old"] {
+            assert!(parse_instance_name(bad).is_err(), "{:?}", bad);
+        }
+    }
+}
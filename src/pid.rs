@@ -1,15 +1,51 @@
-use std::fs;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, ErrorKind};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long to wait for an existing instance to answer /health
+const HEALTH_PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Contents of the PID file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PidInfo {
+    pub pid: u32,
+    /// Port the instance listens on (absent in legacy PID files)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Config file the instance was started with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_path: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+}
+
+impl PidInfo {
+    /// Parse PID file contents: JSON, or the legacy bare PID
+    fn parse(content: &str) -> io::Result<Self> {
+        let content = content.trim();
+        if let Ok(pid) = content.parse::<u32>() {
+            return Ok(Self { pid, port: None, config_path: None, started_at: None });
+        }
+        serde_json::from_str(content).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    }
+}
 
 /// Get the PID file path
 pub fn get_pid_file() -> PathBuf {
-    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
-    home.join(".claude-code-mux").join("ccm.pid")
+    crate::paths::state_file("ccm.pid")
 }
 
-/// Write the current process PID to the PID file
-pub fn write_pid() -> io::Result<()> {
+/// Get the lock file path. Kept separate from the PID file so it is never
+/// deleted while another process may be waiting on it.
+pub fn get_lock_file() -> PathBuf {
+    crate::paths::state_file("ccm.lock")
+}
+
+/// Write the current process PID, port and config path to the PID file
+pub fn write_pid(port: u16, config_path: &Path) -> io::Result<()> {
     let pid_file = get_pid_file();
 
     // Create parent directory if it doesn't exist
@@ -17,18 +53,27 @@ pub fn write_pid() -> io::Result<()> {
         fs::create_dir_all(parent)?;
     }
 
-    let pid = std::process::id();
-    fs::write(&pid_file, pid.to_string())?;
-    tracing::info!("PID {} written to {:?}", pid, pid_file);
+    let info = PidInfo {
+        pid: std::process::id(),
+        port: Some(port),
+        config_path: Some(config_path.to_path_buf()),
+        started_at: Some(Utc::now()),
+    };
+    let json = serde_json::to_string_pretty(&info)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    fs::write(&pid_file, json)?;
+    tracing::info!("PID {} written to {:?}", info.pid, pid_file);
     Ok(())
 }
 
+/// Read the full PID file
+pub fn read_pid_info() -> io::Result<PidInfo> {
+    PidInfo::parse(&fs::read_to_string(get_pid_file())?)
+}
+
 /// Read the PID from the PID file
 pub fn read_pid() -> io::Result<u32> {
-    let pid_file = get_pid_file();
-    let pid_str = fs::read_to_string(&pid_file)?;
-    pid_str.trim().parse::<u32>()
-        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+    read_pid_info().map(|info| info.pid)
 }
 
 /// Remove the PID file
@@ -41,16 +86,96 @@ pub fn cleanup_pid() -> io::Result<()> {
     Ok(())
 }
 
+/// Exclusive instance lock, released when dropped (or when the process exits)
+pub struct PidLock {
+    #[cfg(unix)]
+    _lock: nix::fcntl::Flock<File>,
+    #[cfg(not(unix))]
+    _file: File,
+}
+
+/// Why the instance lock could not be taken
+#[derive(Debug)]
+pub enum LockError {
+    /// Another process holds the lock
+    Held,
+    Io(io::Error),
+}
+
+impl std::fmt::Display for LockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LockError::Held => write!(f, "another instance holds {}", get_lock_file().display()),
+            LockError::Io(e) => write!(f, "failed to lock {}: {}", get_lock_file().display(), e),
+        }
+    }
+}
+
+impl std::error::Error for LockError {}
+
+/// Take the exclusive instance lock without blocking
+pub fn acquire_lock() -> Result<PidLock, LockError> {
+    let path = get_lock_file();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(LockError::Io)?;
+    }
+    lock_file(&path)
+}
+
+#[cfg(unix)]
+fn lock_file(path: &Path) -> Result<PidLock, LockError> {
+    use nix::errno::Errno;
+    use nix::fcntl::{Flock, FlockArg};
+
+    let file = OpenOptions::new().create(true).truncate(false).write(true).open(path).map_err(LockError::Io)?;
+    match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+        Ok(lock) => Ok(PidLock { _lock: lock }),
+        Err((_, Errno::EWOULDBLOCK)) => Err(LockError::Held),
+        Err((_, errno)) => Err(LockError::Io(errno.into())),
+    }
+}
+
+#[cfg(not(unix))]
+fn lock_file(path: &Path) -> Result<PidLock, LockError> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    // share_mode(0) denies other opens while the handle is held
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    match OpenOptions::new().create(true).truncate(false).write(true).share_mode(0).open(path) {
+        Ok(file) => Ok(PidLock { _file: file }),
+        Err(e) if e.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => Err(LockError::Held),
+        Err(e) => Err(LockError::Io(e)),
+    }
+}
+
+/// Check whether a running instance holds the lock
+pub fn is_locked() -> bool {
+    matches!(acquire_lock(), Err(LockError::Held))
+}
+
+/// Query /health on host:port. Returns the body if anything answered.
+pub async fn probe_health(host: &str, port: u16) -> Option<serde_json::Value> {
+    fetch_json(host, port, "/health").await
+}
+
+/// GET a path from a local instance. Returns the body (Null if not JSON) if anything answered.
+pub async fn fetch_json(host: &str, port: u16, path: &str) -> Option<serde_json::Value> {
+    let host = match host {
+        "0.0.0.0" | "::" | "" => "127.0.0.1",
+        other => other,
+    };
+    let client = reqwest::Client::builder().timeout(HEALTH_PROBE_TIMEOUT).build().ok()?;
+    let response = client.get(format!("http://{}:{}{}", host, port, path)).send().await.ok()?;
+    Some(response.json().await.unwrap_or(serde_json::Value::Null))
+}
+
 /// Check if a process is running
 #[cfg(unix)]
 pub fn is_process_running(pid: u32) -> bool {
     use nix::sys::signal::{kill, Signal};
     use nix::unistd::Pid;
 
-    match kill(Pid::from_raw(pid as i32), Signal::SIGCONT) {
-        Ok(_) => true,
-        Err(_) => false,
-    }
+    kill(Pid::from_raw(pid as i32), Signal::SIGCONT).is_ok()
 }
 
 #[cfg(windows)]
@@ -68,3 +193,36 @@ pub fn is_process_running(pid: u32) -> bool {
         })
         .unwrap_or(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_legacy_pid_file() {
+        let info = PidInfo::parse("1234\n").unwrap();
+        assert_eq!(info.pid, 1234);
+        assert_eq!(info.port, None);
+    }
+
+    #[test]
+    fn test_parse_json_pid_file() {
+        let info = PidInfo::parse(r#"{"pid": 42, "port": 13456, "config_path": "/etc/ccm.toml"}"#).unwrap();
+        assert_eq!(info.pid, 42);
+        assert_eq!(info.port, Some(13456));
+        assert_eq!(info.config_path, Some(PathBuf::from("/etc/ccm.toml")));
+        assert!(PidInfo::parse("not a pid").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_lock_is_exclusive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ccm.lock");
+
+        let first = lock_file(&path).unwrap();
+        assert!(matches!(lock_file(&path), Err(LockError::Held)));
+        drop(first);
+        assert!(lock_file(&path).is_ok());
+    }
+}
@@ -1,11 +1,16 @@
-use super::{AnthropicProvider, ProviderResponse, StreamResponse, error::ProviderError};
-use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse, MessageContent, ContentBlock, KnownContentBlock};
+use super::{merge_extra_body, AnthropicProvider, ProviderResponse, StreamResponse, error::ProviderError};
+use super::prompt_cache::{budget_breakpoints, EXTENDED_CACHE_TTL_BETA, MAX_BREAKPOINTS};
+use super::timeout;
+use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse, MessageContent, ContentBlock, KnownContentBlock, DEFAULT_ANTHROPIC_VERSION};
 use crate::auth::{TokenStore, OAuthClient, OAuthConfig};
 use async_trait::async_trait;
 use reqwest::Client;
 use std::collections::HashMap;
 use secrecy::ExposeSecret;
 
+/// anthropic-beta flags sent with Claude Pro/Max OAuth tokens
+const OAUTH_BETAS: &str = "oauth-2025-04-20,claude-code-20250219,interleaved-thinking-2025-05-14,fine-grained-tool-streaming-2025-05-14";
+
 /// Headers to forward from Anthropic responses (rate limits, etc.)
 const ANTHROPIC_FORWARD_HEADERS: &[&str] = &[
     "anthropic-ratelimit-requests-limit",
@@ -63,7 +68,7 @@ fn looks_like_anthropic_signature(sig: &str) -> bool {
 
 /// Proactive: strip thinking blocks that don't look like they came from Anthropic.
 /// Keeps unsigned blocks and blocks with valid-looking Anthropic signatures.
-fn strip_non_anthropic_thinking(request: &mut AnthropicRequest) {
+pub(super) fn strip_non_anthropic_thinking(request: &mut AnthropicRequest) {
     let mut stripped_count = 0;
 
     for message in &mut request.messages {
@@ -131,7 +136,7 @@ fn remove_empty_messages(request: &mut AnthropicRequest) {
 /// Sanitize tool_use.id and tool_use_id fields to match Anthropic's pattern requirement.
 /// Anthropic requires tool IDs to match: ^[a-zA-Z0-9_-]+
 /// Non-Anthropic providers may generate IDs with invalid characters.
-fn sanitize_tool_use_ids(request: &mut AnthropicRequest, is_anthropic_target: bool) {
+pub(super) fn sanitize_tool_use_ids(request: &mut AnthropicRequest, is_anthropic_target: bool) {
     if !is_anthropic_target {
         return;
     }
@@ -200,6 +205,8 @@ pub struct AnthropicCompatibleProvider {
     oauth_provider: Option<String>,
     /// Token store for OAuth authentication
     token_store: Option<TokenStore>,
+    /// TTL set on every prompt cache breakpoint ("5m" or "1h")
+    cache_ttl: Option<String>,
 }
 
 impl AnthropicCompatibleProvider {
@@ -232,7 +239,26 @@ impl AnthropicCompatibleProvider {
             custom_headers,
             oauth_provider,
             token_store,
+            cache_ttl: None,
+        }
+    }
+
+    /// Set the TTL of every prompt cache breakpoint ("1h" enables the extended TTL beta)
+    pub fn with_cache_ttl(mut self, cache_ttl: Option<String>) -> Self {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    /// anthropic-beta header value: Claude Code betas for OAuth, extended cache TTL when configured
+    fn beta_header(&self) -> Option<String> {
+        let mut betas = Vec::new();
+        if self.is_oauth() {
+            betas.push(OAUTH_BETAS);
+        }
+        if self.cache_ttl.as_deref() == Some("1h") {
+            betas.push(EXTENDED_CACHE_TTL_BETA);
         }
+        (!betas.is_empty()).then(|| betas.join(","))
     }
 
     /// Get authentication header value (API key or OAuth Bearer token)
@@ -340,48 +366,55 @@ impl AnthropicCompatibleProvider {
     async fn try_send_message(&self, url: &str, auth_value: &str, request: &AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
         let mut req_builder = self.client
             .post(url)
-            .header("anthropic-version", "2023-06-01")
+            .header("anthropic-version", request.anthropic_version.as_deref().unwrap_or(DEFAULT_ANTHROPIC_VERSION))
             .header("Content-Type", "application/json");
 
         // Set auth header based on OAuth vs API key
         if self.is_oauth() {
-            req_builder = req_builder
-                .header("Authorization", format!("Bearer {}", auth_value))
-                .header("anthropic-beta", "oauth-2025-04-20,claude-code-20250219,interleaved-thinking-2025-05-14,fine-grained-tool-streaming-2025-05-14");
+            req_builder = req_builder.header("Authorization", format!("Bearer {}", auth_value));
         } else {
             req_builder = req_builder.header("x-api-key", auth_value);
         }
+        if let Some(betas) = self.beta_header() {
+            req_builder = req_builder.header("anthropic-beta", betas);
+        }
 
         // Add custom headers
         for (key, value) in &self.custom_headers {
             req_builder = req_builder.header(key, value);
         }
 
-        let response = req_builder.json(request).send().await?;
+        let mut body = serde_json::to_value(request)?;
+        merge_extra_body(&mut body, request.extra_body.as_ref());
+        let response = timeout::response_headers(req_builder.json(&body).send()).await?;
 
         if !response.status().is_success() {
             let status = response.status().as_u16();
+            let headers = response.headers().clone();
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
 
             if status == 401 && self.is_oauth() {
                 tracing::warn!("🔄 Received 401, OAuth token may be invalid or expired");
             }
 
-            return Err(ProviderError::ApiError {
+            return Err(ProviderError::from_response(
                 status,
-                message: format!("{} API error: {}", self.name, error_text),
-            });
+                &headers,
+                format!("{} API error: {}", self.name, error_text),
+            ));
         }
 
+        let rate_limits = super::quota::rate_limit_headers(response.headers());
         let response_text = response.text().await?;
         tracing::debug!("{} provider response body: {}", self.name, response_text);
 
-        let provider_response: ProviderResponse = serde_json::from_str(&response_text)
+        let mut provider_response: ProviderResponse = serde_json::from_str(&response_text)
             .map_err(|e| {
                 tracing::error!("Failed to parse {} response: {}", self.name, e);
                 tracing::error!("Response body was: {}", response_text);
                 e
             })?;
+        provider_response.rate_limits = rate_limits;
 
         Ok(provider_response)
     }
@@ -390,35 +423,40 @@ impl AnthropicCompatibleProvider {
     async fn try_send_stream_request(&self, url: &str, auth_value: &str, request: &AnthropicRequest) -> Result<reqwest::Response, ProviderError> {
         let mut req_builder = self.client
             .post(url)
-            .header("anthropic-version", "2023-06-01")
+            .header("anthropic-version", request.anthropic_version.as_deref().unwrap_or(DEFAULT_ANTHROPIC_VERSION))
             .header("Content-Type", "application/json");
 
         if self.is_oauth() {
-            req_builder = req_builder
-                .header("Authorization", format!("Bearer {}", auth_value))
-                .header("anthropic-beta", "oauth-2025-04-20,claude-code-20250219,interleaved-thinking-2025-05-14,fine-grained-tool-streaming-2025-05-14");
+            req_builder = req_builder.header("Authorization", format!("Bearer {}", auth_value));
         } else {
             req_builder = req_builder.header("x-api-key", auth_value);
         }
+        if let Some(betas) = self.beta_header() {
+            req_builder = req_builder.header("anthropic-beta", betas);
+        }
 
         for (key, value) in &self.custom_headers {
             req_builder = req_builder.header(key, value);
         }
 
-        let response = req_builder.json(request).send().await?;
+        let mut body = serde_json::to_value(request)?;
+        merge_extra_body(&mut body, request.extra_body.as_ref());
+        let response = req_builder.json(&body).send().await?;
 
         if !response.status().is_success() {
             let status = response.status().as_u16();
+            let headers = response.headers().clone();
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
 
             if status == 401 && self.is_oauth() {
                 tracing::warn!("🔄 Received 401 on streaming, OAuth token may be invalid or expired");
             }
 
-            return Err(ProviderError::ApiError {
+            return Err(ProviderError::from_response(
                 status,
-                message: format!("{} API error: {}", self.name, error_text),
-            });
+                &headers,
+                format!("{} API error: {}", self.name, error_text),
+            ));
         }
 
         Ok(response)
@@ -437,6 +475,10 @@ impl AnthropicProvider for AnthropicCompatibleProvider {
         if is_anthropic {
             strip_non_anthropic_thinking(&mut request);
         }
+        let dropped = budget_breakpoints(&mut request, self.cache_ttl.as_deref());
+        if dropped > 0 {
+            tracing::debug!("✂️ Dropped {} cache breakpoint(s) over the limit of {}", dropped, MAX_BREAKPOINTS);
+        }
 
         // Get authentication header value (API key or OAuth token)
         let auth_value = self.get_auth_header().await?;
@@ -467,14 +509,14 @@ impl AnthropicProvider for AnthropicCompatibleProvider {
 
             let mut req_builder = self.client
                 .post(&url)
-                .header("anthropic-version", "2023-06-01")
+                .header("anthropic-version", request.anthropic_version.as_deref().unwrap_or(DEFAULT_ANTHROPIC_VERSION))
                 .header("Content-Type", "application/json");
 
             // Set auth header
             if self.is_oauth() {
                 req_builder = req_builder
                     .header("Authorization", format!("Bearer {}", auth_value))
-                    .header("anthropic-beta", "oauth-2025-04-20,claude-code-20250219,interleaved-thinking-2025-05-14,fine-grained-tool-streaming-2025-05-14");
+                    .header("anthropic-beta", OAUTH_BETAS);
             } else {
                 req_builder = req_builder.header("x-api-key", auth_value);
             }
@@ -486,11 +528,9 @@ impl AnthropicProvider for AnthropicCompatibleProvider {
 
             if !response.status().is_success() {
                 let status = response.status().as_u16();
+                let headers = response.headers().clone();
                 let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                return Err(ProviderError::ApiError {
-                    status,
-                    message: error_text,
-                });
+                return Err(ProviderError::from_response(status, &headers, error_text));
             }
 
             let count_response: CountTokensResponse = response.json().await?;
@@ -557,6 +597,10 @@ impl AnthropicProvider for AnthropicCompatibleProvider {
         if is_anthropic {
             strip_non_anthropic_thinking(&mut request);
         }
+        let dropped = budget_breakpoints(&mut request, self.cache_ttl.as_deref());
+        if dropped > 0 {
+            tracing::debug!("✂️ Dropped {} cache breakpoint(s) over the limit of {}", dropped, MAX_BREAKPOINTS);
+        }
 
         // Get authentication header value
         let auth_value = self.get_auth_header().await?;
@@ -579,19 +623,54 @@ impl AnthropicProvider for AnthropicCompatibleProvider {
             HashMap::new()
         };
 
+        let rate_limits = super::quota::rate_limit_headers(response.headers());
+
         // Wrap stream with logging to capture cache statistics
         use crate::providers::streaming::LoggingSseStream;
-        let byte_stream = response.bytes_stream().map_err(|e| ProviderError::HttpError(e));
+        let byte_stream = response.bytes_stream().map_err(ProviderError::HttpError);
         let logging_stream = LoggingSseStream::new(byte_stream, self.name.clone(), request.model.clone());
 
         // Return stream with headers for forwarding
         Ok(StreamResponse {
             stream: Box::pin(logging_stream),
             headers,
+            rate_limits,
         })
     }
 
     fn supports_model(&self, model: &str) -> bool {
         self.models.iter().any(|m| m.eq_ignore_ascii_case(model))
     }
+
+    fn supports_prompt_cache(&self) -> bool {
+        true
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<String>>, ProviderError> {
+        let auth_value = self.get_auth_header().await?;
+        let mut req_builder = self.client
+            .get(format!("{}/v1/models?limit=1000", self.base_url))
+            .header("anthropic-version", DEFAULT_ANTHROPIC_VERSION);
+
+        if self.is_oauth() {
+            req_builder = req_builder
+                .header("Authorization", format!("Bearer {}", auth_value))
+                .header("anthropic-beta", "oauth-2025-04-20");
+        } else {
+            req_builder = req_builder.header("x-api-key", auth_value);
+        }
+        for (key, value) in &self.custom_headers {
+            req_builder = req_builder.header(key, value);
+        }
+
+        let response = req_builder.send().await?;
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let headers = response.headers().clone();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(ProviderError::from_response(status, &headers, error_text));
+        }
+        let body: serde_json::Value = response.json().await?;
+        Ok(Some(super::model_ids(&body)))
+    }
 }
@@ -0,0 +1,301 @@
+//! AWS Bedrock: Anthropic models through the Bedrock runtime `invoke` and
+//! `invoke-with-response-stream` endpoints, signed with SigV4 from standard
+//! AWS credentials (or a Bedrock API key).
+
+use super::anthropic_compatible::{sanitize_tool_use_ids, strip_non_anthropic_thinking};
+use super::aws::{self, AwsCredentials, EventStreamDecoder, SigningRequest};
+use super::prompt_cache::{budget_breakpoints, EXTENDED_CACHE_TTL_BETA, MAX_BREAKPOINTS};
+use super::streaming::LoggingSseStream;
+use super::timeout;
+use super::{merge_extra_body, AnthropicProvider, ProviderError, ProviderResponse, StreamResponse};
+use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse};
+use async_trait::async_trait;
+use base64::Engine as _;
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use reqwest::Client;
+use std::collections::HashMap;
+
+/// `anthropic_version` Bedrock expects in the request body
+const BEDROCK_ANTHROPIC_VERSION: &str = "bedrock-2023-05-31";
+
+/// Region used when neither the config nor the AWS environment sets one
+const DEFAULT_REGION: &str = "us-east-1";
+
+/// Environment variable holding a Bedrock API key
+const BEARER_TOKEN_ENV: &str = "AWS_BEARER_TOKEN_BEDROCK";
+
+pub struct BedrockProvider {
+    pub name: String,
+    /// Bedrock API key, sent as a bearer token instead of signing
+    api_key: Option<String>,
+    region: String,
+    base_url: String,
+    models: Vec<String>,
+    client: Client,
+    cache_ttl: Option<String>,
+}
+
+impl BedrockProvider {
+    /// `region` defaults to `AWS_REGION`/`AWS_DEFAULT_REGION`/`~/.aws/config`, then us-east-1
+    pub fn new(
+        name: String,
+        api_key: Option<String>,
+        region: Option<String>,
+        base_url: Option<String>,
+        models: Vec<String>,
+    ) -> Self {
+        let region = region
+            .or_else(aws::default_region)
+            .unwrap_or_else(|| DEFAULT_REGION.to_string());
+        let base_url = base_url.unwrap_or_else(|| format!("https://bedrock-runtime.{}.amazonaws.com", region));
+        let api_key = api_key
+            .filter(|key| !key.is_empty())
+            .or_else(|| std::env::var(BEARER_TOKEN_ENV).ok().filter(|key| !key.is_empty()));
+
+        Self {
+            name,
+            api_key,
+            region,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            models,
+            client: Client::new(),
+            cache_ttl: None,
+        }
+    }
+
+    /// Set the TTL applied to every prompt cache breakpoint ("5m" or "1h")
+    pub fn with_cache_ttl(mut self, cache_ttl: Option<String>) -> Self {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    /// Bedrock takes the Anthropic body minus `model`/`stream`/`metadata`,
+    /// with the version and betas in the body instead of headers
+    fn body(&self, mut request: AnthropicRequest) -> Result<Vec<u8>, ProviderError> {
+        sanitize_tool_use_ids(&mut request, true);
+        strip_non_anthropic_thinking(&mut request);
+        let dropped = budget_breakpoints(&mut request, self.cache_ttl.as_deref());
+        if dropped > 0 {
+            tracing::debug!("✂️ Dropped {} cache breakpoint(s) over the limit of {}", dropped, MAX_BREAKPOINTS);
+        }
+
+        let mut body = serde_json::to_value(&request)?;
+        let fields = body.as_object_mut().expect("requests serialize to objects");
+        fields.remove("model");
+        fields.remove("stream");
+        fields.remove("metadata");
+        fields.insert("anthropic_version".to_string(), BEDROCK_ANTHROPIC_VERSION.into());
+        if self.cache_ttl.as_deref() == Some("1h") {
+            fields.insert("anthropic_beta".to_string(), serde_json::json!([EXTENDED_CACHE_TTL_BETA]));
+        }
+        merge_extra_body(&mut body, request.extra_body.as_ref());
+        Ok(serde_json::to_vec(&body)?)
+    }
+
+    /// POST to `/model/{model}/{action}`, signed unless an API key is configured
+    async fn invoke(&self, model: &str, action: &str, body: Vec<u8>, accept: &str) -> Result<reqwest::Response, ProviderError> {
+        let path = format!("/model/{}/{}", aws::uri_encode(model), action);
+        let url = reqwest::Url::parse(&format!("{}{}", self.base_url, path))
+            .map_err(|e| ProviderError::ConfigError(format!("Invalid Bedrock URL for {}: {}", self.name, e)))?;
+
+        let mut req_builder = self.client
+            .post(url.clone())
+            .header("content-type", "application/json")
+            .header("accept", accept);
+
+        if let Some(api_key) = &self.api_key {
+            req_builder = req_builder.header("Authorization", format!("Bearer {}", api_key));
+        } else {
+            let credentials = AwsCredentials::load().ok_or_else(|| {
+                ProviderError::AuthError(
+                    "No AWS credentials found: set AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY, AWS_PROFILE or a Bedrock api_key".to_string(),
+                )
+            })?;
+            let host = match (url.host_str(), url.port()) {
+                (Some(host), Some(port)) => format!("{}:{}", host, port),
+                (Some(host), None) => host.to_string(),
+                (None, _) => return Err(ProviderError::ConfigError(format!("Bedrock base_url for {} has no host", self.name))),
+            };
+            let signing_request = SigningRequest {
+                method: "POST",
+                host: &host,
+                path: url.path(),
+                headers: &[("content-type", "application/json"), ("accept", accept)],
+                payload: &body,
+            };
+            for (name, value) in aws::sign(&signing_request, &credentials, &self.region, "bedrock", chrono::Utc::now()) {
+                req_builder = req_builder.header(name, value);
+            }
+        }
+
+        let response = timeout::response_headers(req_builder.body(body).send()).await?;
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let headers = response.headers().clone();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(ProviderError::from_response(
+                status,
+                &headers,
+                format!("{} API error: {}", self.name, error_text),
+            ));
+        }
+
+        Ok(response)
+    }
+}
+
+/// HTTP status for a Bedrock stream exception
+fn exception_status(exception_type: &str) -> u16 {
+    match exception_type {
+        "throttlingException" => 429,
+        "validationException" => 400,
+        "serviceUnavailableException" => 503,
+        "modelTimeoutException" => 504,
+        _ => 500,
+    }
+}
+
+/// Convert event stream messages to Anthropic SSE. Each `chunk` event carries
+/// one base64-encoded Anthropic stream event.
+fn events_to_sse(decoder: &mut EventStreamDecoder, bytes: &[u8]) -> Result<String, ProviderError> {
+    let messages = decoder
+        .push(bytes)
+        .map_err(|e| ProviderError::ApiError { status: 502, message: format!("Bedrock stream: {}", e) })?;
+
+    let mut output = String::new();
+    for message in messages {
+        if message.header(":message-type") == Some("exception") {
+            let exception_type = message.header(":exception-type").unwrap_or("unknown");
+            let text = String::from_utf8_lossy(&message.payload);
+            tracing::error!("❌ Bedrock stream {}: {}", exception_type, text);
+            return Err(ProviderError::from_status(
+                exception_status(exception_type),
+                None,
+                format!("Bedrock {}: {}", exception_type, text),
+            ));
+        }
+        if message.header(":event-type") != Some("chunk") {
+            continue;
+        }
+
+        let chunk: serde_json::Value = serde_json::from_slice(&message.payload)?;
+        let Some(encoded) = chunk["bytes"].as_str() else {
+            continue;
+        };
+        let event = base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|e| ProviderError::ApiError { status: 502, message: format!("Bedrock stream chunk: {}", e) })?;
+        let event: serde_json::Value = serde_json::from_slice(&event)?;
+        let event_type = event["type"].as_str().unwrap_or("message").to_string();
+        output.push_str(&format!("event: {}\ndata: {}\n\n", event_type, event));
+    }
+    Ok(output)
+}
+
+#[async_trait]
+impl AnthropicProvider for BedrockProvider {
+    async fn send_message(&self, request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+        let model = request.model.clone();
+        let body = self.body(request)?;
+        let response = self.invoke(&model, "invoke", body, "application/json").await?;
+
+        let response_text = response.text().await?;
+        tracing::debug!("{} provider response body: {}", self.name, response_text);
+        Ok(serde_json::from_str(&response_text)?)
+    }
+
+    async fn send_message_stream(&self, request: AnthropicRequest) -> Result<StreamResponse, ProviderError> {
+        let model = request.model.clone();
+        let body = self.body(request)?;
+        let response = self
+            .invoke(&model, "invoke-with-response-stream", body, "application/vnd.amazon.eventstream")
+            .await?;
+
+        let mut decoder = EventStreamDecoder::new();
+        let sse_stream = response
+            .bytes_stream()
+            .map(move |result| {
+                let bytes = result.map_err(ProviderError::HttpError)?;
+                events_to_sse(&mut decoder, &bytes).map(Bytes::from)
+            })
+            .try_filter(|bytes| futures::future::ready(!bytes.is_empty()));
+
+        Ok(StreamResponse {
+            stream: Box::pin(LoggingSseStream::new(sse_stream, self.name.clone(), model)),
+            headers: HashMap::new(),
+            rate_limits: HashMap::new(),
+        })
+    }
+
+    async fn count_tokens(&self, request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+        // Estimated locally
+        let request = AnthropicRequest {
+            model: request.model,
+            messages: request.messages,
+            max_tokens: 1,
+            system: request.system,
+            tools: request.tools,
+            anthropic_version: None,
+            extra_body: None,
+            thinking: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
+            stop_sequences: None,
+            stream: None,
+            metadata: None,
+        };
+        Ok(CountTokensResponse {
+            input_tokens: crate::router::estimate_input_tokens(&request),
+        })
+    }
+
+    fn supports_model(&self, model: &str) -> bool {
+        self.models.iter().any(|m| m.eq_ignore_ascii_case(model))
+    }
+
+    fn supports_prompt_cache(&self) -> bool {
+        true
+    }
+
+    /// Bedrock rejects `url` image sources
+    fn accepts_image_urls(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_body_moves_version_into_payload() {
+        let provider = BedrockProvider::new(
+            "bedrock".to_string(),
+            Some("key".to_string()),
+            Some("eu-west-1".to_string()),
+            None,
+            vec![],
+        )
+        .with_cache_ttl(Some("1h".to_string()));
+        assert_eq!(provider.base_url, "https://bedrock-runtime.eu-west-1.amazonaws.com");
+
+        let request: AnthropicRequest = serde_json::from_value(json!({
+            "model": "anthropic.claude-sonnet-4-5-20250929-v1:0",
+            "max_tokens": 1024,
+            "stream": true,
+            "metadata": {"user_id": "u1"},
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+
+        let body: serde_json::Value = serde_json::from_slice(&provider.body(request).unwrap()).unwrap();
+        assert_eq!(body["anthropic_version"], BEDROCK_ANTHROPIC_VERSION);
+        assert_eq!(body["anthropic_beta"], json!([EXTENDED_CACHE_TTL_BETA]));
+        assert!(body.get("model").is_none() && body.get("stream").is_none() && body.get("metadata").is_none());
+        assert_eq!(body["max_tokens"], 1024);
+    }
+}
@@ -0,0 +1,311 @@
+//! Daily/monthly spend budgets per provider or model.
+//!
+//! Spend comes from the `[pricing]` table, so only priced models count. A budget
+//! is checked before a request is sent; the request that crosses it still
+//! completes, later ones fall over to other mappings or are rejected.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+/// What to do with requests to a mapping whose budget is spent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetAction {
+    /// Skip the mapping and try the next one
+    #[default]
+    Fallback,
+    /// Fail the request
+    Reject,
+}
+
+/// A spend limit (`[[budgets]]`) on a provider, a model, or a model on one provider
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BudgetConfig {
+    /// Provider name
+    pub provider: Option<String>,
+    /// Upstream model name (`actual_model`)
+    pub model: Option<String>,
+    /// Limit in USD per UTC day
+    pub daily_usd: Option<f64>,
+    /// Limit in USD per UTC calendar month
+    pub monthly_usd: Option<f64>,
+    #[serde(default)]
+    pub on_exceeded: BudgetAction,
+}
+
+impl BudgetConfig {
+    pub fn applies_to(&self, provider: &str, model: &str) -> bool {
+        self.provider.as_deref().is_none_or(|p| p == provider) && self.model.as_deref().is_none_or(|m| m == model)
+    }
+
+    /// e.g. `anthropic/claude-opus-4-1`, `anthropic`, `claude-opus-4-1`
+    pub fn label(&self) -> String {
+        match (&self.provider, &self.model) {
+            (Some(provider), Some(model)) => format!("{}/{}", provider, model),
+            (Some(name), None) | (None, Some(name)) => name.clone(),
+            (None, None) => "*".to_string(),
+        }
+    }
+}
+
+/// Spend of one provider/model in the current day and month
+#[derive(Debug, Clone)]
+struct Spend {
+    day: NaiveDate,
+    day_usd: f64,
+    /// First day of the month
+    month: NaiveDate,
+    month_usd: f64,
+}
+
+impl Spend {
+    fn roll(&mut self, today: NaiveDate) {
+        if self.day != today {
+            self.day = today;
+            self.day_usd = 0.0;
+        }
+        if self.month != month_start(today) {
+            self.month = month_start(today);
+            self.month_usd = 0.0;
+        }
+    }
+}
+
+fn month_start(day: NaiveDate) -> NaiveDate {
+    day.with_day(1).expect("day 1 exists in every month")
+}
+
+fn next_month(day: NaiveDate) -> NaiveDate {
+    let (year, month) = if day.month() == 12 { (day.year() + 1, 1) } else { (day.year(), day.month() + 1) };
+    NaiveDate::from_ymd_opt(year, month, 1).expect("day 1 exists in every month")
+}
+
+/// A budget that has been used up
+#[derive(Debug, Clone)]
+pub struct BudgetExceeded {
+    pub budget: BudgetConfig,
+    /// "daily" or "monthly"
+    pub period: &'static str,
+    pub spent_usd: f64,
+    pub limit_usd: f64,
+    /// When the period resets
+    pub resets_at: DateTime<Utc>,
+}
+
+impl std::fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} budget for {} exhausted (${:.2} of ${:.2}, resets {})",
+            self.period,
+            self.budget.label(),
+            self.spent_usd,
+            self.limit_usd,
+            self.resets_at.format("%Y-%m-%d %H:%M UTC")
+        )
+    }
+}
+
+/// Current spend against one budget, for `/api/debug/stats`
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetStatus {
+    pub budget: String,
+    pub daily_usd: Option<f64>,
+    pub spent_today_usd: f64,
+    pub monthly_usd: Option<f64>,
+    pub spent_this_month_usd: f64,
+    pub on_exceeded: BudgetAction,
+}
+
+/// One provider/model's spend in the current period, as exchanged with other instances
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpendRecord {
+    pub provider: String,
+    pub model: String,
+    /// Day `day_usd` was spent on; `month_usd` is for its month
+    pub day: NaiveDate,
+    pub day_usd: f64,
+    pub month_usd: f64,
+}
+
+/// Per provider/model spend in the current UTC day and month
+#[derive(Default)]
+pub struct BudgetTracker {
+    spend: DashMap<(String, String), Spend>,
+    /// Spend of other instances sharing state, replaced on each sync
+    remote: DashMap<(String, String), Spend>,
+}
+
+impl BudgetTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the cost of a completed request
+    pub fn record(&self, provider: &str, model: &str, cost: f64) {
+        self.record_at(provider, model, cost, Utc::now().date_naive());
+    }
+
+    /// Add spend on `day` (used to seed from the usage history at startup)
+    pub fn record_at(&self, provider: &str, model: &str, cost: f64, day: NaiveDate) {
+        let today = Utc::now().date_naive();
+        if month_start(day) != month_start(today) {
+            return;
+        }
+        let mut spend = self.spend.entry((provider.to_string(), model.to_string())).or_insert_with(|| Spend {
+            day: today,
+            day_usd: 0.0,
+            month: month_start(today),
+            month_usd: 0.0,
+        });
+        spend.roll(today);
+        if day == today {
+            spend.day_usd += cost;
+        }
+        spend.month_usd += cost;
+    }
+
+    /// This instance's spend, for other instances sharing state
+    pub fn local_spend(&self) -> Vec<SpendRecord> {
+        let today = Utc::now().date_naive();
+        self.spend
+            .iter_mut()
+            .map(|mut entry| {
+                entry.roll(today);
+                SpendRecord {
+                    provider: entry.key().0.clone(),
+                    model: entry.key().1.clone(),
+                    day: entry.day,
+                    day_usd: entry.day_usd,
+                    month_usd: entry.month_usd,
+                }
+            })
+            .collect()
+    }
+
+    /// Replace other instances' spend (several records for one provider/model add up)
+    pub fn set_remote(&self, records: Vec<SpendRecord>) {
+        let today = Utc::now().date_naive();
+        self.remote.clear();
+        for record in records {
+            let mut spend = Spend { day: record.day, day_usd: record.day_usd, month: month_start(record.day), month_usd: record.month_usd };
+            spend.roll(today);
+            let mut total = self.remote.entry((record.provider, record.model)).or_insert_with(|| Spend {
+                day: today,
+                day_usd: 0.0,
+                month: month_start(today),
+                month_usd: 0.0,
+            });
+            total.day_usd += spend.day_usd;
+            total.month_usd += spend.month_usd;
+        }
+    }
+
+    /// Spend counted against a budget today and this month, on every instance
+    fn spent(&self, budget: &BudgetConfig, today: NaiveDate) -> (f64, f64) {
+        self.spend
+            .iter_mut()
+            .chain(self.remote.iter_mut())
+            .filter(|entry| budget.applies_to(&entry.key().0, &entry.key().1))
+            .fold((0.0, 0.0), |(day, month), mut entry| {
+                entry.roll(today);
+                (day + entry.day_usd, month + entry.month_usd)
+            })
+    }
+
+    /// The first exhausted budget covering this provider/model, if any
+    pub fn check(&self, budgets: &[BudgetConfig], provider: &str, model: &str) -> Option<BudgetExceeded> {
+        let now = Utc::now();
+        let today = now.date_naive();
+        let midnight = |day: NaiveDate| Utc.from_utc_datetime(&day.and_hms_opt(0, 0, 0).expect("midnight is valid"));
+
+        budgets.iter().filter(|b| b.applies_to(provider, model)).find_map(|budget| {
+            let (day, month) = self.spent(budget, today);
+            let exceeded = |period, spent_usd, limit_usd, resets_at| BudgetExceeded {
+                budget: budget.clone(),
+                period,
+                spent_usd,
+                limit_usd,
+                resets_at,
+            };
+            match (budget.daily_usd, budget.monthly_usd) {
+                (_, Some(limit)) if month >= limit => Some(exceeded("monthly", month, limit, midnight(next_month(today)))),
+                (Some(limit), _) if day >= limit => Some(exceeded("daily", day, limit, midnight(today + Duration::days(1)))),
+                _ => None,
+            }
+        })
+    }
+
+    pub fn statuses(&self, budgets: &[BudgetConfig]) -> Vec<BudgetStatus> {
+        let today = Utc::now().date_naive();
+        budgets
+            .iter()
+            .map(|budget| {
+                let (day, month) = self.spent(budget, today);
+                BudgetStatus {
+                    budget: budget.label(),
+                    daily_usd: budget.daily_usd,
+                    spent_today_usd: day,
+                    monthly_usd: budget.monthly_usd,
+                    spent_this_month_usd: month,
+                    on_exceeded: budget.on_exceeded,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(provider: Option<&str>, model: Option<&str>, daily: Option<f64>, monthly: Option<f64>) -> BudgetConfig {
+        BudgetConfig {
+            provider: provider.map(String::from),
+            model: model.map(String::from),
+            daily_usd: daily,
+            monthly_usd: monthly,
+            on_exceeded: BudgetAction::Fallback,
+        }
+    }
+
+    #[test]
+    fn test_budget_checks() {
+        let budgets = vec![
+            budget(Some("anthropic"), None, Some(5.0), None),
+            budget(None, Some("gpt-5"), None, Some(20.0)),
+        ];
+        let tracker = BudgetTracker::new();
+        tracker.record("anthropic", "claude-sonnet-4-5", 3.0);
+        tracker.record("anthropic", "claude-opus-4-1", 1.5);
+        assert!(tracker.check(&budgets, "anthropic", "claude-opus-4-1").is_none());
+
+        tracker.record("anthropic", "claude-opus-4-1", 1.0);
+        let exceeded = tracker.check(&budgets, "anthropic", "claude-haiku-4-5").unwrap();
+        assert_eq!((exceeded.period, exceeded.budget.label().as_str()), ("daily", "anthropic"));
+        assert!((exceeded.spent_usd - 5.5).abs() < 1e-9);
+        assert!(tracker.check(&budgets, "openrouter", "claude-opus-4-1").is_none());
+
+        // Earlier days of this month only count towards the monthly limit
+        let today = Utc::now().date_naive();
+        if today.day() > 1 {
+            tracker.record_at("openai", "gpt-5", 25.0, today - Duration::days(1));
+            assert_eq!(tracker.check(&budgets, "openai", "gpt-5").unwrap().period, "monthly");
+            assert_eq!(tracker.statuses(&budgets)[1].spent_today_usd, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_period_boundaries() {
+        let dec = NaiveDate::from_ymd_opt(2025, 12, 17).unwrap();
+        assert_eq!(next_month(dec), NaiveDate::from_ymd_opt(2026, 1, 1).unwrap());
+        assert_eq!(month_start(dec), NaiveDate::from_ymd_opt(2025, 12, 1).unwrap());
+
+        let mut spend = Spend { day: dec, day_usd: 4.0, month: month_start(dec), month_usd: 9.0 };
+        spend.roll(dec + Duration::days(1));
+        assert_eq!((spend.day_usd, spend.month_usd), (0.0, 9.0));
+        spend.roll(next_month(dec));
+        assert_eq!(spend.month_usd, 0.0);
+    }
+}
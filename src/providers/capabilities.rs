@@ -0,0 +1,415 @@
+use crate::models::{AnthropicRequest, ContentBlock, KnownContentBlock, MessageContent, SystemPrompt};
+use super::error::ProviderError;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+/// How long a learned capability is applied before the feature is tried again
+const RECORD_TTL_DAYS: i64 = 7;
+
+/// Request features that some upstreams reject
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// `cache_control` markers on system/message blocks
+    CacheControl,
+    /// Extended thinking (`thinking` param and thinking blocks)
+    Thinking,
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Capability::CacheControl => write!(f, "cache_control"),
+            Capability::Thinking => write!(f, "thinking"),
+        }
+    }
+}
+
+/// What we learned about a single provider/model pair
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CapabilityRecord {
+    /// Features the upstream rejected
+    #[serde(default)]
+    pub unsupported: Vec<Capability>,
+    /// When the last feature was learned; the record expires `RECORD_TTL_DAYS` later
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detected_at: Option<DateTime<Utc>>,
+    /// Error message that triggered the last detection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+impl CapabilityRecord {
+    /// Learned within the TTL. Records without a detection time (older files) have expired.
+    fn is_current(&self, now: DateTime<Utc>) -> bool {
+        self.detected_at
+            .is_some_and(|at| now - at < chrono::Duration::days(RECORD_TTL_DAYS))
+    }
+}
+
+/// Persistent cache of per provider/model capabilities.
+///
+/// When an upstream rejects a request because of a feature it doesn't support
+/// (e.g. "cache_control" on an Anthropic-compatible endpoint), the failure is
+/// recorded here and the matching transformer is applied to every later request
+/// for that provider/model, so we don't keep paying for the same 400.
+#[derive(Debug, Clone)]
+pub struct CapabilityCache {
+    /// Path to cache file (None = in-memory only)
+    file_path: Option<PathBuf>,
+    /// provider/model -> learned capabilities
+    records: Arc<RwLock<HashMap<String, CapabilityRecord>>>,
+}
+
+impl CapabilityCache {
+    /// Load cache from file. A missing or unreadable file starts an empty cache.
+    pub fn load(file_path: PathBuf) -> Self {
+        let records: HashMap<String, CapabilityRecord> = match std::fs::read_to_string(&file_path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                tracing::warn!("⚠️  Ignoring unreadable capability cache {}: {}", file_path.display(), e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        let now = Utc::now();
+        let records = records.into_iter().filter(|(_, record)| record.is_current(now)).collect();
+
+        Self {
+            file_path: Some(file_path),
+            records: Arc::new(RwLock::new(records)),
+        }
+    }
+
+    /// Create an in-memory cache (nothing is persisted)
+    pub fn in_memory() -> Self {
+        Self {
+            file_path: None,
+            records: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Get default cache path
+    /// <state dir>/capabilities.json
+    pub fn default_path() -> Option<PathBuf> {
+        Some(crate::paths::state_file("capabilities.json"))
+    }
+
+    fn key(provider: &str, model: &str) -> String {
+        format!("{}/{}", provider, model)
+    }
+
+    /// Check whether a feature is known to be rejected by provider/model
+    #[cfg(test)]
+    pub fn is_unsupported(&self, provider: &str, model: &str, capability: Capability) -> bool {
+        let records = self.records.read().unwrap();
+        records
+            .get(&Self::key(provider, model))
+            .filter(|r| r.is_current(Utc::now()))
+            .map(|r| r.unsupported.contains(&capability))
+            .unwrap_or(false)
+    }
+
+    /// Record that provider/model rejects a feature. Returns true if this is new information.
+    pub fn mark_unsupported(&self, provider: &str, model: &str, capability: Capability, reason: &str) -> bool {
+        {
+            let mut records = self.records.write().unwrap();
+            let record = records.entry(Self::key(provider, model)).or_default();
+            if !record.is_current(Utc::now()) {
+                *record = CapabilityRecord::default();
+            }
+            if record.unsupported.contains(&capability) {
+                return false;
+            }
+            record.unsupported.push(capability);
+            record.detected_at = Some(Utc::now());
+            record.last_error = Some(reason.chars().take(500).collect());
+        }
+
+        self.persist();
+        true
+    }
+
+    /// Inspect a provider failure and remember any unsupported feature it reveals.
+    /// Returns the newly learned capability, if any.
+    pub fn record_failure(&self, provider: &str, model: &str, error: &ProviderError) -> Option<Capability> {
+        let (capability, message) = match error {
+            ProviderError::UnsupportedFeature { feature, message, .. } => (*feature, message),
+            ProviderError::ApiError { status, message } => (detect_unsupported(*status, message)?, message),
+            _ => return None,
+        };
+
+        if self.mark_unsupported(provider, model, capability, message) {
+            tracing::warn!("🧩 Learned that {}/{} rejects {}, will strip it from future requests", provider, model, capability);
+            Some(capability)
+        } else {
+            None
+        }
+    }
+
+    /// Apply transformers for every feature known to be rejected by provider/model.
+    /// Returns the capabilities that were stripped.
+    pub fn apply(&self, provider: &str, model: &str, request: &mut AnthropicRequest) -> Vec<Capability> {
+        let unsupported = {
+            let records = self.records.read().unwrap();
+            match records.get(&Self::key(provider, model)) {
+                Some(record) if record.is_current(Utc::now()) => record.unsupported.clone(),
+                _ => return Vec::new(),
+            }
+        };
+
+        for capability in &unsupported {
+            match capability {
+                Capability::CacheControl => strip_cache_control(request),
+                Capability::Thinking => strip_thinking(request),
+            }
+        }
+
+        if !unsupported.is_empty() {
+            tracing::debug!("🧩 Applied learned transformers for {}/{}: {:?}", provider, model, unsupported);
+        }
+        unsupported
+    }
+
+    /// Snapshot of all current learned records (for the admin API)
+    pub fn list(&self) -> HashMap<String, CapabilityRecord> {
+        let now = Utc::now();
+        self.records
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, record)| record.is_current(now))
+            .map(|(key, record)| (key.clone(), record.clone()))
+            .collect()
+    }
+
+    /// Forget everything learned so far
+    pub fn clear(&self) {
+        self.records.write().unwrap().clear();
+        self.persist();
+    }
+
+    fn persist(&self) {
+        let Some(ref path) = self.file_path else {
+            return;
+        };
+
+        let json = {
+            let records = self.records.read().unwrap();
+            match serde_json::to_string_pretty(&*records) {
+                Ok(json) => json,
+                Err(e) => {
+                    tracing::debug!("Failed to serialize capability cache: {}", e);
+                    return;
+                }
+            }
+        };
+
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        if let Err(e) = std::fs::write(path, json) {
+            tracing::debug!("Failed to write capability cache: {}", e);
+        }
+    }
+}
+
+/// Wording upstreams use when they don't know a field or feature at all
+const UNSUPPORTED_PATTERNS: &[&str] = &[
+    "not supported",
+    "unsupported",
+    "does not support",
+    "extra inputs are not permitted",
+    "unknown field",
+    "unrecognized",
+    "unexpected field",
+    "not allowed",
+];
+
+/// Wording of ordinary validation errors about a supported feature (too many
+/// cache breakpoints, a thinking budget above max_tokens, bad signatures)
+const VALIDATION_PATTERNS: &[&str] = &[
+    "maximum",
+    "at most",
+    "budget",
+    "max_tokens",
+    "greater than",
+    "less than",
+    "signature",
+];
+
+/// Map an upstream error to the feature it says is unsupported.
+/// Only client errors count - 5xx and rate limits say nothing about capabilities -
+/// and only when the message says the feature itself is unknown or unsupported.
+pub(crate) fn detect_unsupported(status: u16, message: &str) -> Option<Capability> {
+    if status != 400 && status != 422 {
+        return None;
+    }
+
+    let message = message.to_lowercase();
+    if !UNSUPPORTED_PATTERNS.iter().any(|p| message.contains(p))
+        || VALIDATION_PATTERNS.iter().any(|p| message.contains(p))
+    {
+        return None;
+    }
+    if message.contains("cache_control") {
+        Some(Capability::CacheControl)
+    } else if message.contains("thinking") {
+        Some(Capability::Thinking)
+    } else {
+        None
+    }
+}
+
+/// Remove all cache_control markers from system prompt and messages
+fn strip_cache_control(request: &mut AnthropicRequest) {
+    if let Some(SystemPrompt::Blocks(blocks)) = &mut request.system {
+        for block in blocks.iter_mut() {
+            block.cache_control = None;
+        }
+    }
+
+    for message in &mut request.messages {
+        if let MessageContent::Blocks(blocks) = &mut message.content {
+            for block in blocks.iter_mut() {
+                match block {
+                    ContentBlock::Known(KnownContentBlock::Text { cache_control, .. })
+                    | ContentBlock::Known(KnownContentBlock::ToolResult { cache_control, .. }) => {
+                        *cache_control = None;
+                    }
+                    ContentBlock::Unknown(value) => {
+                        if let Some(obj) = value.as_object_mut() {
+                            obj.remove("cache_control");
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Disable extended thinking and drop thinking blocks from history
+fn strip_thinking(request: &mut AnthropicRequest) {
+    request.thinking = None;
+
+    for message in &mut request.messages {
+        if let MessageContent::Blocks(blocks) = &mut message.content {
+            blocks.retain(|b| !matches!(b, ContentBlock::Known(KnownContentBlock::Thinking { .. })));
+        }
+    }
+
+    request.messages.retain(|msg| match &msg.content {
+        MessageContent::Text(t) => !t.is_empty(),
+        MessageContent::Blocks(b) => !b.is_empty(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_with_cache_control() -> AnthropicRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "glm-4.6",
+            "max_tokens": 100,
+            "thinking": {"type": "enabled", "budget_tokens": 1024},
+            "system": [{"type": "text", "text": "sys", "cache_control": {"type": "ephemeral"}}],
+            "messages": [
+                {"role": "user", "content": [{"type": "text", "text": "hi", "cache_control": {"type": "ephemeral"}}]},
+                {"role": "assistant", "content": [{"type": "thinking", "thinking": "hmm", "signature": "x"}]},
+                {"role": "user", "content": "again"}
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_detect_unsupported() {
+        assert_eq!(
+            detect_unsupported(400, "Extra inputs are not permitted: messages.0.content.0.cache_control"),
+            Some(Capability::CacheControl)
+        );
+        assert_eq!(detect_unsupported(400, "thinking is not supported by this model"), Some(Capability::Thinking));
+        assert_eq!(detect_unsupported(400, "Invalid signature in thinking block"), None);
+        assert_eq!(detect_unsupported(500, "cache_control exploded"), None);
+        assert_eq!(detect_unsupported(400, "max_tokens too large"), None);
+
+        // Validation errors about features the model does support
+        assert_eq!(
+            detect_unsupported(400, "A maximum of 4 blocks with cache_control may be provided. Found 5."),
+            None
+        );
+        assert_eq!(
+            detect_unsupported(400, "`max_tokens` must be greater than `thinking.budget_tokens`"),
+            None
+        );
+        assert_eq!(detect_unsupported(400, "cache_control.ttl: invalid value"), None);
+    }
+
+    #[test]
+    fn test_learned_records_expire() {
+        let cache = CapabilityCache::in_memory();
+        cache.mark_unsupported("zai", "glm-4.6", Capability::Thinking, "thinking is not supported");
+        cache.records.write().unwrap().get_mut("zai/glm-4.6").unwrap().detected_at =
+            Some(Utc::now() - chrono::Duration::days(RECORD_TTL_DAYS + 1));
+
+        assert!(!cache.is_unsupported("zai", "glm-4.6", Capability::Thinking));
+        assert!(cache.apply("zai", "glm-4.6", &mut request_with_cache_control()).is_empty());
+        assert!(cache.list().is_empty());
+
+        // Learning it again starts a fresh record
+        assert!(cache.mark_unsupported("zai", "glm-4.6", Capability::Thinking, "thinking is not supported"));
+        assert!(cache.is_unsupported("zai", "glm-4.6", Capability::Thinking));
+    }
+
+    #[test]
+    fn test_record_failure_and_apply() {
+        let cache = CapabilityCache::in_memory();
+        let error = ProviderError::ApiError {
+            status: 400,
+            message: "unknown field cache_control".to_string(),
+        };
+
+        assert_eq!(cache.record_failure("zai", "glm-4.6", &error), Some(Capability::CacheControl));
+        // Second identical failure is not new information
+        assert_eq!(cache.record_failure("zai", "glm-4.6", &error), None);
+        assert!(cache.is_unsupported("zai", "glm-4.6", Capability::CacheControl));
+        assert!(!cache.is_unsupported("zai", "glm-4.5", Capability::CacheControl));
+
+        let mut request = request_with_cache_control();
+        let applied = cache.apply("zai", "glm-4.6", &mut request);
+        assert_eq!(applied, vec![Capability::CacheControl]);
+
+        let json = serde_json::to_string(&request).unwrap();
+        assert!(!json.contains("cache_control"));
+        // Thinking is untouched
+        assert!(request.thinking.is_some());
+    }
+
+    #[test]
+    fn test_strip_thinking_removes_empty_messages() {
+        let mut request = request_with_cache_control();
+        strip_thinking(&mut request);
+
+        assert!(request.thinking.is_none());
+        assert_eq!(request.messages.len(), 2);
+    }
+
+    #[test]
+    fn test_persists_across_loads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capabilities.json");
+
+        let cache = CapabilityCache::load(path.clone());
+        cache.mark_unsupported("minimax", "MiniMax-M2", Capability::Thinking, "thinking not allowed");
+
+        let reloaded = CapabilityCache::load(path);
+        assert!(reloaded.is_unsupported("minimax", "MiniMax-M2", Capability::Thinking));
+
+        reloaded.clear();
+        assert!(reloaded.list().is_empty());
+    }
+}
@@ -0,0 +1,288 @@
+use super::error::ProviderError;
+use crate::cli::{CircuitBreakerConfig, ModelMapping};
+use crate::models::AnthropicRequest;
+use dashmap::DashMap;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Circuit state for a provider/model mapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Healthy - requests flow normally
+    Closed,
+    /// Failing - mapping is tried last until the cooldown elapses
+    Open,
+    /// Cooldown elapsed - next request or probe decides whether to close
+    HalfOpen,
+}
+
+#[derive(Debug, Clone)]
+struct CircuitEntry {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    last_error: Option<String>,
+}
+
+impl Default for CircuitEntry {
+    fn default() -> Self {
+        Self {
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            opened_at: None,
+            last_error: None,
+        }
+    }
+}
+
+/// Snapshot of a circuit for status endpoints
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitStatus {
+    pub provider: String,
+    pub model: String,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// Per provider/model circuit breaker.
+///
+/// After `failure_threshold` consecutive failures a mapping's circuit opens and
+/// the dispatcher tries it last, so a sustained outage of the priority-1
+/// provider doesn't add a failed round-trip to every request. Once the cooldown
+/// elapses the circuit goes half-open: a background probe (or the next real
+/// request) decides whether to close it again and fail back to the primary.
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    entries: DashMap<(String, String), CircuitEntry>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            entries: DashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &CircuitBreakerConfig {
+        &self.config
+    }
+
+    fn key(provider: &str, model: &str) -> (String, String) {
+        (provider.to_string(), model.to_string())
+    }
+
+    fn cooldown(&self) -> Duration {
+        Duration::from_secs(self.config.open_duration_secs)
+    }
+
+    /// Current state, moving Open -> HalfOpen once the cooldown has elapsed
+    pub fn state(&self, provider: &str, model: &str) -> CircuitState {
+        let Some(mut entry) = self.entries.get_mut(&Self::key(provider, model)) else {
+            return CircuitState::Closed;
+        };
+
+        if entry.state == CircuitState::Open
+            && entry.opened_at.map(|t| t.elapsed() >= self.cooldown()).unwrap_or(true)
+        {
+            entry.state = CircuitState::HalfOpen;
+        }
+        entry.state
+    }
+
+    /// Move mappings with an open circuit to the end (stable, keeps priority order otherwise).
+    /// Open mappings are still tried as a last resort.
+    pub fn deprioritize_open(&self, mappings: &mut [ModelMapping]) {
+        if !self.config.enabled {
+            return;
+        }
+
+        let before: Vec<String> = mappings.iter().map(|m| m.provider.clone()).collect();
+        mappings.sort_by_key(|m| self.state(&m.provider, &m.actual_model) == CircuitState::Open);
+
+        if let Some(first) = before.first() {
+            if mappings[0].provider != *first {
+                tracing::info!("⚡ Circuit open for {}, trying {} first", first, mappings[0].provider);
+            }
+        }
+    }
+
+    /// Record a successful request. Returns true if the circuit was open/half-open (recovery).
+    pub fn record_success(&self, provider: &str, model: &str) -> bool {
+        let Some(mut entry) = self.entries.get_mut(&Self::key(provider, model)) else {
+            return false;
+        };
+
+        let recovered = entry.state != CircuitState::Closed;
+        *entry = CircuitEntry::default();
+
+        if recovered {
+            tracing::info!("🔁 {}/{} recovered, failed back to primary", provider, model);
+        }
+        recovered
+    }
+
+    /// Record a failed request. Only failures that indicate an unhealthy upstream count.
+    pub fn record_failure(&self, provider: &str, model: &str, error: &ProviderError) {
+        if !self.config.enabled || !is_breaker_failure(error) {
+            return;
+        }
+
+        let mut entry = self.entries.entry(Self::key(provider, model)).or_default();
+        entry.consecutive_failures += 1;
+        entry.last_error = Some(error.to_string().chars().take(200).collect());
+
+        let should_open = match entry.state {
+            // A failed half-open attempt re-opens immediately
+            CircuitState::HalfOpen => true,
+            CircuitState::Closed => entry.consecutive_failures >= self.config.failure_threshold,
+            CircuitState::Open => false,
+        };
+
+        if should_open {
+            if entry.state == CircuitState::Closed {
+                tracing::warn!(
+                    "⚡ Circuit opened for {}/{} after {} consecutive failures",
+                    provider, model, entry.consecutive_failures
+                );
+            }
+            entry.state = CircuitState::Open;
+            entry.opened_at = Some(Instant::now());
+        }
+    }
+
+    /// Circuits whose cooldown has elapsed and should be probed now
+    pub fn due_probes(&self) -> Vec<(String, String)> {
+        let keys: Vec<(String, String)> = self.entries.iter().map(|e| e.key().clone()).collect();
+        keys.into_iter()
+            .filter(|(provider, model)| self.state(provider, model) == CircuitState::HalfOpen)
+            .collect()
+    }
+
+    /// Snapshot of all non-closed circuits
+    pub fn statuses(&self) -> Vec<CircuitStatus> {
+        let keys: Vec<(String, String)> = self.entries.iter().map(|e| e.key().clone()).collect();
+        let mut statuses: Vec<CircuitStatus> = keys
+            .into_iter()
+            .filter_map(|(provider, model)| {
+                let state = self.state(&provider, &model);
+                let entry = self.entries.get(&(provider.clone(), model.clone()))?;
+                Some(CircuitStatus {
+                    provider,
+                    model,
+                    state,
+                    consecutive_failures: entry.consecutive_failures,
+                    last_error: entry.last_error.clone(),
+                })
+            })
+            .collect();
+        statuses.sort_by(|a, b| (&a.provider, &a.model).cmp(&(&b.provider, &b.model)));
+        statuses
+    }
+}
+
+/// Client errors (bad request, unsupported feature) say nothing about provider health
+fn is_breaker_failure(error: &ProviderError) -> bool {
+    error.is_provider_fault()
+}
+
+/// Minimal request used for half-open probes
+pub fn probe_request(model: &str) -> AnthropicRequest {
+    serde_json::from_value(serde_json::json!({
+        "model": model,
+        "max_tokens": 1,
+        "messages": [{"role": "user", "content": "ping"}]
+    }))
+    .expect("probe request is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(open_duration_secs: u64) -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerConfig {
+            enabled: true,
+            failure_threshold: 2,
+            open_duration_secs,
+            probe_interval_secs: 0,
+        })
+    }
+
+    fn server_error() -> ProviderError {
+        ProviderError::ApiError { status: 503, message: "overloaded".to_string() }
+    }
+
+    fn mapping(priority: u32, provider: &str) -> ModelMapping {
+        ModelMapping {
+            priority,
+            provider: provider.to_string(),
+            actual_model: "m".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_opens_after_threshold() {
+        let cb = breaker(60);
+        cb.record_failure("a", "m", &server_error());
+        assert_eq!(cb.state("a", "m"), CircuitState::Closed);
+        cb.record_failure("a", "m", &server_error());
+        assert_eq!(cb.state("a", "m"), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_client_errors_do_not_trip() {
+        let cb = breaker(60);
+        let bad_request = ProviderError::ApiError { status: 400, message: "bad".to_string() };
+        cb.record_failure("a", "m", &bad_request);
+        cb.record_failure("a", "m", &bad_request);
+        assert_eq!(cb.state("a", "m"), CircuitState::Closed);
+    }
+
+    #[test]
+    fn test_open_mappings_tried_last() {
+        let cb = breaker(60);
+        cb.record_failure("primary", "m", &server_error());
+        cb.record_failure("primary", "m", &server_error());
+
+        let mut mappings = vec![mapping(1, "primary"), mapping(2, "secondary"), mapping(3, "tertiary")];
+        cb.deprioritize_open(&mut mappings);
+        let order: Vec<&str> = mappings.iter().map(|m| m.provider.as_str()).collect();
+        assert_eq!(order, vec!["secondary", "tertiary", "primary"]);
+    }
+
+    #[test]
+    fn test_half_open_then_failback() {
+        let cb = breaker(0);
+        cb.record_failure("primary", "m", &server_error());
+        cb.record_failure("primary", "m", &server_error());
+
+        // Zero cooldown: immediately half-open and due for a probe
+        assert_eq!(cb.state("primary", "m"), CircuitState::HalfOpen);
+        assert_eq!(cb.due_probes(), vec![("primary".to_string(), "m".to_string())]);
+
+        // Half-open mappings keep their priority position
+        let mut mappings = vec![mapping(1, "primary"), mapping(2, "secondary")];
+        cb.deprioritize_open(&mut mappings);
+        assert_eq!(mappings[0].provider, "primary");
+
+        assert!(cb.record_success("primary", "m"));
+        assert_eq!(cb.state("primary", "m"), CircuitState::Closed);
+        assert!(!cb.record_success("primary", "m"));
+    }
+
+    #[test]
+    fn test_half_open_failure_reopens() {
+        let cb = breaker(60);
+        cb.record_failure("a", "m", &server_error());
+        cb.record_failure("a", "m", &server_error());
+        cb.entries.get_mut(&CircuitBreaker::key("a", "m")).unwrap().state = CircuitState::HalfOpen;
+
+        cb.record_failure("a", "m", &server_error());
+        assert_eq!(cb.state("a", "m"), CircuitState::Open);
+    }
+}
@@ -0,0 +1,117 @@
+//! GitHub Copilot: OpenAI-compatible chat completions on the Copilot API,
+//! authorized with a session token exchanged from the stored GitHub login.
+
+use super::openai::OpenAIProvider;
+use super::{AnthropicProvider, ProviderError, ProviderResponse, StreamResponse};
+use crate::auth::copilot::{self, EDITOR_HEADERS};
+use crate::auth::TokenStore;
+use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use secrecy::ExposeSecret;
+use std::sync::Arc;
+
+/// Refresh the session token this long before it expires
+const SESSION_REFRESH_MARGIN_SECS: i64 = 300;
+
+pub struct CopilotProvider {
+    pub name: String,
+    /// Token store entry holding the GitHub login
+    provider_id: String,
+    models: Vec<String>,
+    token_store: Option<TokenStore>,
+    client: Client,
+    /// OpenAI-compatible client for the current session token, rebuilt when it expires
+    session: tokio::sync::Mutex<Option<(DateTime<Utc>, Arc<OpenAIProvider>)>>,
+}
+
+impl CopilotProvider {
+    /// `provider_id` is the token store entry written by `ccm copilot-login`
+    pub fn new(name: String, provider_id: String, models: Vec<String>, token_store: Option<TokenStore>) -> Self {
+        Self {
+            name,
+            provider_id,
+            models,
+            token_store,
+            client: Client::new(),
+            session: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Client for the current session, exchanging the GitHub token for a new
+    /// session token when needed
+    async fn api(&self) -> Result<Arc<OpenAIProvider>, ProviderError> {
+        let mut session = self.session.lock().await;
+        if let Some((expires_at, api)) = session.as_ref() {
+            if Utc::now() + chrono::Duration::seconds(SESSION_REFRESH_MARGIN_SECS) < *expires_at {
+                return Ok(api.clone());
+            }
+        }
+
+        let token_store = self.token_store.as_ref().ok_or_else(|| {
+            ProviderError::AuthError("Copilot provider configured but TokenStore not available".to_string())
+        })?;
+        let new_session = copilot::refresh_session(&self.client, token_store, &self.provider_id)
+            .await
+            .map_err(|e| ProviderError::AuthError(format!("{:#}", e)))?;
+        tracing::debug!("🔄 {}: new Copilot session token (expires {})", self.name, new_session.expires_at);
+
+        let mut headers: Vec<(String, String)> =
+            EDITOR_HEADERS.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        headers.push(("Openai-Intent".to_string(), "conversation-edits".to_string()));
+        let api = Arc::new(OpenAIProvider::with_headers(
+            self.name.clone(),
+            new_session.token.expose_secret().to_string(),
+            new_session.api_base,
+            self.models.clone(),
+            headers,
+            None,
+            None,
+        ));
+        *session = Some((new_session.expires_at, api.clone()));
+        Ok(api)
+    }
+}
+
+#[async_trait]
+impl AnthropicProvider for CopilotProvider {
+    async fn send_message(&self, request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+        self.api().await?.send_message(request).await
+    }
+
+    async fn send_message_stream(&self, request: AnthropicRequest) -> Result<StreamResponse, ProviderError> {
+        self.api().await?.send_message_stream(request).await
+    }
+
+    async fn count_tokens(&self, request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+        // Estimated locally
+        let request = AnthropicRequest {
+            model: request.model,
+            messages: request.messages,
+            max_tokens: 1,
+            system: request.system,
+            tools: request.tools,
+            anthropic_version: None,
+            extra_body: None,
+            thinking: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
+            stop_sequences: None,
+            stream: None,
+            metadata: None,
+        };
+        Ok(CountTokensResponse {
+            input_tokens: crate::router::estimate_input_tokens(&request),
+        })
+    }
+
+    fn supports_model(&self, model: &str) -> bool {
+        self.models.iter().any(|m| m.eq_ignore_ascii_case(model))
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<String>>, ProviderError> {
+        self.api().await?.list_models().await
+    }
+}
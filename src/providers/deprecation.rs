@@ -0,0 +1,134 @@
+use super::error::ProviderError;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::Serialize;
+use tracing::{info, warn};
+
+/// Upstream error messages are truncated to this many characters in warnings
+const MAX_DETAIL_CHARS: usize = 200;
+
+/// Where a deprecation warning came from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningSource {
+    /// The provider's model list no longer includes the model
+    ModelList,
+    /// A request was answered with model not found
+    ModelNotFound,
+}
+
+/// A configured `actual_model` that the provider appears to no longer serve
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelWarning {
+    pub provider: String,
+    pub model: String,
+    pub source: WarningSource,
+    pub detail: String,
+    pub since: DateTime<Utc>,
+}
+
+/// Persistent warnings about retired or unknown models, kept until the model is
+/// listed again or serves a request
+#[derive(Default)]
+pub struct ModelWarnings {
+    warnings: DashMap<(String, String), ModelWarning>,
+}
+
+impl ModelWarnings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn raise(&self, provider: &str, model: &str, source: WarningSource, detail: String) {
+        let key = (provider.to_string(), model.to_string());
+        let since = match self.warnings.get(&key) {
+            Some(existing) => existing.since,
+            None => {
+                warn!("⚠️ Model {}/{} looks deprecated: {}", provider, model, detail);
+                Utc::now()
+            }
+        };
+        self.warnings.insert(
+            key,
+            ModelWarning { provider: provider.to_string(), model: model.to_string(), source, detail, since },
+        );
+    }
+
+    fn clear(&self, provider: &str, model: &str) {
+        if self.warnings.remove(&(provider.to_string(), model.to_string())).is_some() {
+            info!("✅ Model {}/{} is available again", provider, model);
+        }
+    }
+
+    /// Raise a warning when a request failed because the model doesn't exist
+    pub fn record_error(&self, provider: &str, model: &str, error: &ProviderError) {
+        if error.is_model_not_found() {
+            let detail: String = error.to_string().chars().take(MAX_DETAIL_CHARS).collect();
+            self.raise(provider, model, WarningSource::ModelNotFound, detail);
+        }
+    }
+
+    /// The model served a request, so it's still there
+    pub fn record_success(&self, provider: &str, model: &str) {
+        if !self.warnings.is_empty() {
+            self.clear(provider, model);
+        }
+    }
+
+    /// Compare a configured model against the provider's current model list
+    pub fn check_listed(&self, provider: &str, model: &str, listed: &[String]) {
+        if listed.iter().any(|m| m.eq_ignore_ascii_case(model)) {
+            self.clear(provider, model);
+        } else {
+            let detail = format!("not in {}'s model list", provider);
+            self.raise(provider, model, WarningSource::ModelList, detail);
+        }
+    }
+
+    /// Current warnings, sorted by provider/model
+    pub fn list(&self) -> Vec<ModelWarning> {
+        let mut warnings: Vec<ModelWarning> = self.warnings.iter().map(|w| w.value().clone()).collect();
+        warnings.sort_by(|a, b| (&a.provider, &a.model).cmp(&(&b.provider, &b.model)));
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_not_found_warning() {
+        let warnings = ModelWarnings::new();
+        warnings.record_error("openai", "gpt-4", &ProviderError::from_status(500, None, "upstream down".to_string()));
+        assert!(warnings.list().is_empty());
+
+        let not_found = ProviderError::from_status(404, None, "The model `gpt-4` does not exist".to_string());
+        warnings.record_error("openai", "gpt-4", &not_found);
+        let since = warnings.list()[0].since;
+        warnings.record_error("openai", "gpt-4", &not_found);
+        let list = warnings.list();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].source, WarningSource::ModelNotFound);
+        assert_eq!(list[0].since, since);
+
+        warnings.record_success("openai", "gpt-4");
+        assert!(warnings.list().is_empty());
+    }
+
+    #[test]
+    fn test_model_list_check() {
+        let warnings = ModelWarnings::new();
+        let listed = vec!["GLM-4.6".to_string(), "glm-4.5-air".to_string()];
+        warnings.check_listed("zai", "glm-4.6", &listed);
+        warnings.check_listed("zai", "glm-4.5", &listed);
+
+        let list = warnings.list();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].model, "glm-4.5");
+        assert_eq!(list[0].source, WarningSource::ModelList);
+
+        warnings.check_listed("zai", "glm-4.5", &["glm-4.5".to_string()]);
+        assert!(warnings.list().is_empty());
+    }
+}
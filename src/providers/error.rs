@@ -1,7 +1,22 @@
+use super::capabilities::{detect_unsupported, Capability};
+use reqwest::header::HeaderMap;
+use std::time::Duration;
 use thiserror::Error;
 
+/// Upstream messages that mean the prompt doesn't fit the model's context window
+const CONTEXT_TOO_LARGE_PATTERNS: &[&str] = &[
+    "prompt is too long",
+    "context_length_exceeded",
+    "context length",
+    "context window",
+    "maximum context",
+    "input is too long",
+    "too many tokens",
+];
+
 /// Provider-specific errors
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum ProviderError {
     #[error("HTTP request failed: {0}")]
     HttpError(#[from] reqwest::Error),
@@ -12,12 +27,202 @@ pub enum ProviderError {
     #[error("Model not supported by provider: {0}")]
     ModelNotSupported(String),
 
+    /// Upstream error that doesn't fit a more specific variant
     #[error("Provider API error: {status} - {message}")]
     ApiError { status: u16, message: String },
 
+    /// 429 from upstream
+    #[error("Rate limited: {status} - {message}")]
+    RateLimited {
+        status: u16,
+        retry_after: Option<Duration>,
+        message: String,
+    },
+
+    /// Upstream is temporarily out of capacity (503/529, `overloaded_error`)
+    #[error("Provider overloaded: {status} - {message}")]
+    Overloaded { status: u16, message: String },
+
+    /// Prompt exceeds the model's context window
+    #[error("Context too large: {status} - {message}")]
+    ContextTooLarge { status: u16, message: String },
+
+    /// Upstream rejected our credentials (expired OAuth token, revoked key)
+    #[error("Authentication rejected by provider: {status} - {message}")]
+    AuthExpired { status: u16, message: String },
+
+    /// Request used a feature this provider/model rejects
+    #[error("Unsupported feature {feature}: {status} - {message}")]
+    UnsupportedFeature {
+        status: u16,
+        feature: Capability,
+        message: String,
+    },
+
+    /// A `[server.timeouts]` limit ran out during `phase`
+    #[error("Timed out after {}ms waiting for {phase}", .after.as_millis())]
+    Timeout { phase: &'static str, after: Duration },
+
     #[error("Configuration error: {0}")]
     ConfigError(String),
 
     #[error("Authentication error: {0}")]
     AuthError(String),
 }
+
+impl ProviderError {
+    /// Classify an upstream error response by status, headers and body
+    pub fn from_response(status: u16, headers: &HeaderMap, message: String) -> Self {
+        Self::from_status(status, retry_after(headers), message)
+    }
+
+    /// Classify an upstream error when only the status and body are known
+    pub fn from_status(status: u16, retry_after: Option<Duration>, message: String) -> Self {
+        let lower = message.to_lowercase();
+        match status {
+            429 => ProviderError::RateLimited { status, retry_after, message },
+            503 | 529 => ProviderError::Overloaded { status, message },
+            401 | 403 => ProviderError::AuthExpired { status, message },
+            413 => ProviderError::ContextTooLarge { status, message },
+            _ if lower.contains("overloaded_error") => ProviderError::Overloaded { status, message },
+            400 | 422 if CONTEXT_TOO_LARGE_PATTERNS.iter().any(|p| lower.contains(p)) => {
+                ProviderError::ContextTooLarge { status, message }
+            }
+            // Other client errors are the request's fault, unless they name a feature the provider lacks
+            _ => match detect_unsupported(status, &message) {
+                Some(feature) => ProviderError::UnsupportedFeature { status, feature, message },
+                None => ProviderError::ApiError { status, message },
+            },
+        }
+    }
+
+    /// How long the upstream asked us to wait before retrying
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ProviderError::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// The provider itself is unhealthy or unusable, as opposed to the request being bad
+    pub fn is_provider_fault(&self) -> bool {
+        match self {
+            ProviderError::RateLimited { .. }
+            | ProviderError::Overloaded { .. }
+            | ProviderError::AuthExpired { .. }
+            | ProviderError::HttpError(_)
+            | ProviderError::Timeout { .. }
+            | ProviderError::AuthError(_) => true,
+            ProviderError::ApiError { status, .. } => *status >= 500 || matches!(status, 401 | 403 | 408 | 429),
+            _ => false,
+        }
+    }
+
+    /// Upstream says the requested model doesn't exist (retired or misspelled)
+    pub fn is_model_not_found(&self) -> bool {
+        let ProviderError::ApiError { status, message } = self else {
+            return false;
+        };
+        let lower = message.to_lowercase();
+        lower.contains("model_not_found")
+            || (lower.contains("model")
+                && (*status == 404 || lower.contains("does not exist") || lower.contains("not found")))
+    }
+
+    /// Status and Anthropic error type to return to the client when this is the final error
+    pub fn client_error(&self) -> (u16, &'static str) {
+        match self {
+            ProviderError::RateLimited { .. } => (429, "rate_limit_error"),
+            ProviderError::Overloaded { .. } => (529, "overloaded_error"),
+            ProviderError::ContextTooLarge { .. } | ProviderError::UnsupportedFeature { .. } => {
+                (400, "invalid_request_error")
+            }
+            ProviderError::ApiError { status, .. } if (400..500).contains(status) && *status != 408 => {
+                (400, "invalid_request_error")
+            }
+            ProviderError::Timeout { .. } => (504, "timeout_error"),
+            _ => (502, "api_error"),
+        }
+    }
+}
+
+/// Parse `retry-after-ms` or `retry-after` (seconds or HTTP date)
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        return Some(Duration::from_millis(ms.max(0.0) as u64));
+    }
+    let value = header("retry-after")?;
+    if let Ok(secs) = value.parse::<f64>() {
+        return Some(Duration::from_millis((secs.max(0.0) * 1000.0) as u64));
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().ok().or(Some(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classifies_upstream_errors() {
+        let rate_limited = ProviderError::from_status(429, Some(Duration::from_secs(3)), "slow down".to_string());
+        assert!(matches!(rate_limited, ProviderError::RateLimited { .. }));
+        assert_eq!(rate_limited.retry_after(), Some(Duration::from_secs(3)));
+        assert_eq!(rate_limited.client_error(), (429, "rate_limit_error"));
+
+        let overloaded = ProviderError::from_status(500, None, r#"{"type":"error","error":{"type":"overloaded_error"}}"#.to_string());
+        assert!(matches!(overloaded, ProviderError::Overloaded { .. }));
+        assert_eq!(overloaded.client_error(), (529, "overloaded_error"));
+
+        let too_long = ProviderError::from_status(400, None, "prompt is too long: 210000 tokens > 200000 maximum".to_string());
+        assert!(matches!(too_long, ProviderError::ContextTooLarge { .. }));
+        assert!(!too_long.is_provider_fault());
+
+        let auth = ProviderError::from_status(401, None, "token expired".to_string());
+        assert!(matches!(auth, ProviderError::AuthExpired { .. }));
+        assert!(auth.is_provider_fault());
+
+        let feature = ProviderError::from_status(400, None, "unknown field cache_control".to_string());
+        assert!(matches!(feature, ProviderError::UnsupportedFeature { feature: Capability::CacheControl, .. }));
+
+        // Validation errors about supported features are plain client errors
+        for message in [
+            "A maximum of 4 blocks with cache_control may be provided. Found 5.",
+            "`max_tokens` must be greater than `thinking.budget_tokens`",
+        ] {
+            let invalid = ProviderError::from_status(400, None, message.to_string());
+            assert!(matches!(invalid, ProviderError::ApiError { status: 400, .. }));
+            assert_eq!(invalid.client_error(), (400, "invalid_request_error"));
+            assert!(!invalid.is_provider_fault());
+        }
+
+        // Signature errors stay generic so the Anthropic provider can retry them itself
+        let signature = ProviderError::from_status(400, None, "Invalid signature in thinking block".to_string());
+        assert!(matches!(signature, ProviderError::ApiError { status: 400, .. }));
+        assert_eq!(signature.client_error(), (400, "invalid_request_error"));
+        assert!(!signature.is_model_not_found());
+
+        let retired = ProviderError::from_status(404, None, r#"{"error":{"message":"The model `gpt-4-0314` has been deprecated","code":"model_not_found"}}"#.to_string());
+        assert!(retired.is_model_not_found());
+        assert!(ProviderError::from_status(400, None, "model: claude-2.1 does not exist".to_string()).is_model_not_found());
+        assert!(!ProviderError::from_status(404, None, "Not Found".to_string()).is_model_not_found());
+    }
+
+    #[test]
+    fn test_retry_after_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert("retry-after", "7".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+
+        headers.insert("retry-after-ms", "1500".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_millis(1500)));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+    }
+}
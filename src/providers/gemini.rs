@@ -1,10 +1,15 @@
-use super::{AnthropicProvider, ProviderError, ProviderResponse, StreamResponse, Usage};
+use super::{merge_extra_body, AnthropicProvider, ProviderError, ProviderResponse, StreamResponse, Usage};
 use crate::auth::{OAuthClient, OAuthConfig, TokenStore};
 use crate::models::{AnthropicRequest, ContentBlock, KnownContentBlock, MessageContent, SystemPrompt};
+use super::streaming::{LoggingSseStream, SseStream};
+use super::timeout;
 use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{Stream, StreamExt, TryStreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use secrecy::ExposeSecret;
 
 /// Google Gemini provider supporting three authentication methods:
@@ -12,7 +17,6 @@ use secrecy::ExposeSecret;
 /// 2. API Key (Google AI Studio) - Uses public Gemini API
 /// 3. Vertex AI (Google Cloud) - Uses Vertex AI API
 pub struct GeminiProvider {
-    #[allow(dead_code)]
     pub name: String,
     pub api_key: Option<String>,
     pub base_url: String,
@@ -57,6 +61,7 @@ fn clean_json_schema(value: &mut serde_json::Value) {
 }
 
 impl GeminiProvider {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         name: String,
         api_key: Option<String>,
@@ -176,6 +181,8 @@ impl GeminiProvider {
 
         // Transform messages
         let mut contents = Vec::new();
+        // functionResponse parts are matched by name, tool_result blocks only carry the id
+        let mut tool_names: HashMap<&str, &str> = HashMap::new();
         for msg in &request.messages {
             let role = match msg.role.as_str() {
                 "user" => "user",
@@ -209,8 +216,37 @@ impl GeminiProvider {
                                             data: data.clone(),
                                         },
                                     });
+                                } else if let Some(url) = &source.url {
+                                    parts.push(GeminiPart::FileData {
+                                        file_data: GeminiFileData {
+                                            mime_type: source.media_type.clone(),
+                                            file_uri: url.clone(),
+                                        },
+                                    });
                                 }
                             }
+                            ContentBlock::Known(KnownContentBlock::ToolUse { id, name, input }) => {
+                                tool_names.insert(id, name);
+                                parts.push(GeminiPart::FunctionCall {
+                                    function_call: GeminiFunctionCall {
+                                        name: name.clone(),
+                                        args: input.clone(),
+                                    },
+                                });
+                            }
+                            ContentBlock::Known(KnownContentBlock::ToolResult { tool_use_id, content, is_error, .. }) => {
+                                let Some(name) = tool_names.get(tool_use_id.as_str()) else {
+                                    tracing::warn!("⚠️ Dropping tool_result {} with no matching tool_use", tool_use_id);
+                                    continue;
+                                };
+                                let key = if *is_error { "error" } else { "result" };
+                                parts.push(GeminiPart::FunctionResponse {
+                                    function_response: GeminiFunctionResponse {
+                                        name: name.to_string(),
+                                        response: serde_json::json!({ key: content.to_string() }),
+                                    },
+                                });
+                            }
                             ContentBlock::Known(KnownContentBlock::Thinking { raw }) => {
                                 // Gemini doesn't have thinking blocks, convert to text
                                 if let Some(thinking) = raw.get("thinking").and_then(|v| v.as_str()) {
@@ -220,7 +256,7 @@ impl GeminiProvider {
                                 }
                             }
                             _ => {
-                                // Skip tool use/result and unknown for now
+                                // Skip unknown blocks
                             }
                         }
                     }
@@ -250,7 +286,7 @@ impl GeminiProvider {
                 let mut function_declarations = Vec::new();
 
                 for tool in anthropic_tools {
-                    let tool_name = tool.name.as_ref().map(|s| s.as_str()).unwrap_or("");
+                    let tool_name = tool.name.as_deref().unwrap_or("");
 
                     match tool_name {
                         "WebSearch" => {
@@ -316,36 +352,26 @@ impl GeminiProvider {
                 message: "No candidates in response".to_string(),
             })?;
 
-        let content = candidate
+        let content: Vec<ContentBlock> = candidate
             .content
             .parts
             .iter()
-            .map(|part| match part {
-                GeminiPart::Text { text } => ContentBlock::text(text.clone(), None),
-                _ => ContentBlock::text(String::new(), None),
+            .filter_map(|part| match part {
+                GeminiPart::Text { text } if !text.is_empty() => Some(ContentBlock::text(text.clone(), None)),
+                GeminiPart::FunctionCall { function_call } => Some(ContentBlock::tool_use(
+                    tool_use_id(),
+                    function_call.name.clone(),
+                    function_call.input(),
+                )),
+                _ => None,
             })
             .collect();
 
-        let stop_reason = match candidate.finish_reason.as_deref() {
-            Some("STOP") => Some("end_turn".to_string()),
-            Some("MAX_TOKENS") => Some("max_tokens".to_string()),
-            _ => None,
-        };
-
-        let usage = Usage {
-            input_tokens: response
-                .usage_metadata
-                .as_ref()
-                .and_then(|u| u.prompt_token_count)
-                .unwrap_or(0) as u32,
-            output_tokens: response
-                .usage_metadata
-                .as_ref()
-                .and_then(|u| u.candidates_token_count)
-                .unwrap_or(0) as u32,
-            cache_creation_input_tokens: None,
-            cache_read_input_tokens: None,
-        };
+        let has_tool_use = content
+            .iter()
+            .any(|b| matches!(b, ContentBlock::Known(KnownContentBlock::ToolUse { .. })));
+        let stop_reason = stop_reason(candidate.finish_reason.as_deref(), has_tool_use);
+        let usage = gemini_usage(response.usage_metadata.as_ref());
 
         Ok(ProviderResponse {
             id: format!("gemini-{}", chrono::Utc::now().timestamp_millis()),
@@ -356,10 +382,22 @@ impl GeminiProvider {
             stop_reason,
             stop_sequence: None,
             usage,
+            rate_limits: HashMap::new(),
         })
     }
 
 
+    /// Convert a Gemini SSE stream (plain or Code Assist wrapped) to Anthropic SSE
+    fn transform_stream(&self, response: reqwest::Response, model: String) -> StreamResponse {
+        let transformed_stream = transform_sse_stream(response.bytes_stream(), self.name.clone(), model.clone());
+
+        StreamResponse {
+            stream: Box::pin(LoggingSseStream::new(transformed_stream, self.name.clone(), model)),
+            headers: HashMap::new(), // Gemini doesn't have rate limit headers to forward
+            rate_limits: HashMap::new(),
+        }
+    }
+
     /// Handle 429 rate limit errors with automatic retry
     async fn handle_rate_limit_retry<F, Fut>(
         &self,
@@ -373,7 +411,7 @@ impl GeminiProvider {
         let mut retries = 0;
         
         loop {
-            let response = request_fn().await?;
+            let response = timeout::response_headers(request_fn()).await?;
             
             // Check if it's a 429 error
             if response.status().as_u16() == 429 {
@@ -389,17 +427,11 @@ impl GeminiProvider {
                         continue;
                     } else {
                         tracing::error!("❌ Rate limit retries exhausted after {} attempts", max_retries);
-                        return Err(ProviderError::ApiError {
-                            status: 429,
-                            message: error_text,
-                        });
+                        return Err(ProviderError::from_status(429, Some(delay), error_text));
                     }
                 } else {
                     // No retry delay found, return error
-                    return Err(ProviderError::ApiError {
-                        status: 429,
-                        message: error_text,
-                    });
+                    return Err(ProviderError::from_status(429, None, error_text));
                 }
             }
             
@@ -408,6 +440,55 @@ impl GeminiProvider {
     }
 }
 
+/// Translate a Gemini `streamGenerateContent?alt=sse` byte stream to Anthropic SSE
+pub(super) fn transform_sse_stream<S>(bytes: S, provider_name: String, model: String) -> impl Stream<Item = Result<Bytes, ProviderError>> + Send
+where
+    S: Stream<Item = Result<Bytes, reqwest::Error>> + Send + 'static,
+{
+    let state = Arc::new(Mutex::new(GeminiStreamState::new(model)));
+    let state_for_cleanup = state.clone();
+
+    SseStream::new(bytes)
+        .map(move |result| {
+            let sse_event = result.map_err(ProviderError::HttpError)?;
+            if sse_event.data.trim().is_empty() {
+                return Ok(Bytes::new());
+            }
+
+            if let Ok(error_response) = serde_json::from_str::<GeminiErrorResponse>(&sse_event.data) {
+                let error = error_response.error;
+                tracing::error!("❌ {} upstream error ({}): {}", provider_name, error.code, error.message);
+                return Err(ProviderError::from_status(
+                    error.code,
+                    None,
+                    format!("{}: {}", provider_name, error.message),
+                ));
+            }
+
+            // Code Assist wraps each chunk in {"response": ...}
+            let chunk = serde_json::from_str::<CodeAssistResponse>(&sse_event.data)
+                .map(|wrapped| wrapped.response)
+                .or_else(|_| serde_json::from_str::<GeminiResponse>(&sse_event.data));
+            match chunk {
+                Ok(chunk) => Ok(Bytes::from(state.lock().unwrap().transform_chunk(chunk))),
+                Err(e) => {
+                    tracing::warn!("❌ {} failed to parse chunk: {} - Data: {}", provider_name, e, sse_event.data);
+                    Ok(Bytes::new())
+                }
+            }
+        })
+        // Close the message if the stream ends without a finishReason
+        .chain(futures::stream::once(async move {
+            let mut state = state_for_cleanup.lock().unwrap();
+            if state.message_started && !state.stream_ended {
+                tracing::warn!("⚠️ Gemini stream ended without finishReason - sending end events");
+                return Ok(Bytes::from(state.finish("end_turn")));
+            }
+            Ok(Bytes::new())
+        }))
+        .try_filter(|bytes| futures::future::ready(!bytes.is_empty()))
+}
+
 #[async_trait]
 impl AnthropicProvider for GeminiProvider {
     async fn send_message(
@@ -457,6 +538,8 @@ impl AnthropicProvider for GeminiProvider {
                     session_id: None, // Optional
                 },
             };
+            let mut code_assist_request = serde_json::to_value(&code_assist_request)?;
+            merge_extra_body(&mut code_assist_request["request"], request.extra_body.as_ref());
 
             // Code Assist API endpoint: https://cloudcode-pa.googleapis.com/v1internal:generateContent
             let url = format!("{}:generateContent", self.base_url);
@@ -496,6 +579,7 @@ impl AnthropicProvider for GeminiProvider {
 
             if !response.status().is_success() {
                 let status = response.status().as_u16();
+                let headers = response.headers().clone();
                 let error_text = response
                     .text()
                     .await
@@ -513,17 +597,11 @@ impl AnthropicProvider for GeminiProvider {
                         format!("Model '{}' not found. Original error: {}", model_name, error_text)
                     };
                     tracing::warn!("⚠️ Model not found (404): {}", user_friendly_msg);
-                    return Err(ProviderError::ApiError {
-                        status,
-                        message: user_friendly_msg,
-                    });
+                    return Err(ProviderError::from_response(status, &headers, user_friendly_msg));
                 }
 
                 tracing::error!("Code Assist API error ({}): {}", status, error_text);
-                return Err(ProviderError::ApiError {
-                    status,
-                    message: error_text,
-                });
+                return Err(ProviderError::from_response(status, &headers, error_text));
             }
 
             // Parse Code Assist response
@@ -531,7 +609,8 @@ impl AnthropicProvider for GeminiProvider {
             self.transform_response(code_assist_response.response, model)
         } else {
             // Use public Gemini API or Vertex AI
-            let gemini_request = self.transform_request(&request)?;
+            let mut gemini_request = serde_json::to_value(self.transform_request(&request)?)?;
+            merge_extra_body(&mut gemini_request, request.extra_body.as_ref());
 
             // Build URL
             let url = if self.is_vertex_ai() {
@@ -544,13 +623,8 @@ impl AnthropicProvider for GeminiProvider {
                     model
                 )
             } else if self.api_key.is_some() {
-                // API Key endpoint (key in query parameter)
-                format!(
-                    "{}/models/{}:generateContent?key={}",
-                    self.base_url,
-                    model,
-                    self.api_key.as_ref().unwrap()
-                )
+                // API Key endpoint (key in the x-goog-api-key header, so it stays out of logged URLs)
+                format!("{}/models/{}:generateContent", self.base_url, model)
             } else {
                 return Err(ProviderError::ConfigError(
                     "Gemini provider requires either api_key, OAuth, or Vertex AI configuration".to_string()
@@ -560,6 +634,7 @@ impl AnthropicProvider for GeminiProvider {
             // Clone necessary data for the retry closure
             let client = self.client.clone();
             let custom_headers = self.custom_headers.clone();
+            let api_key = self.api_key.clone().filter(|_| !self.is_vertex_ai());
             let gemini_request = gemini_request.clone();
             let url = url.clone();
 
@@ -567,6 +642,9 @@ impl AnthropicProvider for GeminiProvider {
             let response = self.handle_rate_limit_retry(
                 move || {
                     let mut req_builder = client.post(&url).header("Content-Type", "application/json");
+                    if let Some(api_key) = &api_key {
+                        req_builder = req_builder.header("x-goog-api-key", api_key);
+                    }
 
                     // Add custom headers
                     for (key, value) in &custom_headers {
@@ -581,15 +659,13 @@ impl AnthropicProvider for GeminiProvider {
 
             if !response.status().is_success() {
                 let status = response.status().as_u16();
+                let headers = response.headers().clone();
                 let error_text = response
                     .text()
                     .await
                     .unwrap_or_else(|_| "Unknown error".to_string());
                 tracing::error!("Gemini API error ({}): {}", status, error_text);
-                return Err(ProviderError::ApiError {
-                    status,
-                    message: error_text,
-                });
+                return Err(ProviderError::from_response(status, &headers, error_text));
             }
 
             let gemini_response: GeminiResponse = response.json().await?;
@@ -601,8 +677,6 @@ impl AnthropicProvider for GeminiProvider {
         &self,
         request: AnthropicRequest,
     ) -> Result<StreamResponse, ProviderError> {
-        use futures::TryStreamExt;
-
         let model = request.model.clone();
 
         // Check if using OAuth (Code Assist API)
@@ -646,6 +720,8 @@ impl AnthropicProvider for GeminiProvider {
                     session_id: None, // Optional
                 },
             };
+            let mut code_assist_request = serde_json::to_value(&code_assist_request)?;
+            merge_extra_body(&mut code_assist_request["request"], request.extra_body.as_ref());
 
             // Code Assist API streaming endpoint with alt=sse parameter
             let url = format!("{}:streamGenerateContent?alt=sse", self.base_url);
@@ -668,27 +744,20 @@ impl AnthropicProvider for GeminiProvider {
 
             if !response.status().is_success() {
                 let status = response.status().as_u16();
+                let headers = response.headers().clone();
                 let error_text = response
                     .text()
                     .await
                     .unwrap_or_else(|_| "Unknown error".to_string());
                 tracing::error!("Code Assist API streaming error ({}): {}", status, error_text);
-                return Err(ProviderError::ApiError {
-                    status,
-                    message: error_text,
-                });
+                return Err(ProviderError::from_response(status, &headers, error_text));
             }
 
-            // Return the streaming response
-            // The Gemini API returns SSE format, just pass through the stream
-            let stream = response.bytes_stream().map_err(|e| ProviderError::HttpError(e));
-            Ok(StreamResponse {
-                stream: Box::pin(stream),
-                headers: HashMap::new(), // Gemini doesn't have rate limit headers to forward
-            })
+            Ok(self.transform_stream(response, model))
         } else {
             // Use public Gemini API or Vertex AI streaming
-            let gemini_request = self.transform_request(&request)?;
+            let mut gemini_request = serde_json::to_value(self.transform_request(&request)?)?;
+            merge_extra_body(&mut gemini_request, request.extra_body.as_ref());
 
             // Build URL
             let url = if self.is_vertex_ai() {
@@ -701,13 +770,8 @@ impl AnthropicProvider for GeminiProvider {
                     model
                 )
             } else if self.api_key.is_some() {
-                // API Key streaming endpoint
-                format!(
-                    "{}/models/{}:streamGenerateContent?key={}&alt=sse",
-                    self.base_url,
-                    model,
-                    self.api_key.as_ref().unwrap()
-                )
+                // API Key streaming endpoint (key sent as a header)
+                format!("{}/models/{}:streamGenerateContent?alt=sse", self.base_url, model)
             } else {
                 return Err(ProviderError::ConfigError(
                     "Gemini provider requires either api_key, OAuth, or Vertex AI configuration".to_string()
@@ -718,6 +782,9 @@ impl AnthropicProvider for GeminiProvider {
 
             // Build request
             let mut req_builder = self.client.post(&url).header("Content-Type", "application/json");
+            if let Some(api_key) = self.api_key.as_ref().filter(|_| !self.is_vertex_ai()) {
+                req_builder = req_builder.header("x-goog-api-key", api_key);
+            }
 
             // Add custom headers
             for (key, value) in &self.custom_headers {
@@ -729,23 +796,16 @@ impl AnthropicProvider for GeminiProvider {
 
             if !response.status().is_success() {
                 let status = response.status().as_u16();
+                let headers = response.headers().clone();
                 let error_text = response
                     .text()
                     .await
                     .unwrap_or_else(|_| "Unknown error".to_string());
                 tracing::error!("Gemini API streaming error ({}): {}", status, error_text);
-                return Err(ProviderError::ApiError {
-                    status,
-                    message: error_text,
-                });
+                return Err(ProviderError::from_response(status, &headers, error_text));
             }
 
-            // Return the streaming response
-            let stream = response.bytes_stream().map_err(|e| ProviderError::HttpError(e));
-            Ok(StreamResponse {
-                stream: Box::pin(stream),
-                headers: HashMap::new(), // Gemini doesn't have rate limit headers to forward
-            })
+            Ok(self.transform_stream(response, model))
         }
     }
 
@@ -762,6 +822,11 @@ impl AnthropicProvider for GeminiProvider {
     fn supports_model(&self, model: &str) -> bool {
         self.models.iter().any(|m| m.eq_ignore_ascii_case(model))
     }
+
+    /// `file_data` only takes Cloud Storage and Files API URIs
+    fn accepts_image_urls(&self) -> bool {
+        false
+    }
 }
 
 // Gemini API structures
@@ -778,9 +843,11 @@ struct GeminiRequest {
     tools: Option<Vec<GeminiTool>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 struct GeminiContent {
+    #[serde(default)]
     role: String,
+    #[serde(default)]
     parts: Vec<GeminiPart>,
 }
 
@@ -789,6 +856,17 @@ struct GeminiContent {
 enum GeminiPart {
     Text { text: String },
     InlineData { inline_data: GeminiInlineData },
+    FileData { file_data: GeminiFileData },
+    FunctionCall {
+        #[serde(rename = "functionCall")]
+        function_call: GeminiFunctionCall,
+    },
+    FunctionResponse {
+        #[serde(rename = "functionResponse")]
+        function_response: GeminiFunctionResponse,
+    },
+    /// Parts we don't translate (executableCode, codeExecutionResult, ...)
+    Other(serde_json::Value),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -798,6 +876,38 @@ struct GeminiInlineData {
     data: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiFileData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mime_type: Option<String>,
+    file_uri: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GeminiFunctionCall {
+    name: String,
+    #[serde(default)]
+    args: serde_json::Value,
+}
+
+impl GeminiFunctionCall {
+    /// tool_use input; calls to functions without parameters may omit `args`
+    fn input(&self) -> serde_json::Value {
+        if self.args.is_null() {
+            serde_json::json!({})
+        } else {
+            self.args.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GeminiFunctionResponse {
+    name: String,
+    response: serde_json::Value,
+}
+
 #[derive(Debug, Clone, Serialize)]
 struct GeminiSystemInstruction {
     parts: Vec<GeminiPart>,
@@ -855,6 +965,8 @@ struct UrlContextTool {}
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct GeminiResponse {
+    /// Absent on the usage-only chunk at the end of a stream
+    #[serde(default)]
     candidates: Vec<GeminiCandidate>,
     #[serde(skip_serializing_if = "Option::is_none")]
     usage_metadata: Option<GeminiUsageMetadata>,
@@ -863,6 +975,8 @@ struct GeminiResponse {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct GeminiCandidate {
+    /// Absent when the candidate was blocked
+    #[serde(default)]
     content: GeminiContent,
     #[serde(skip_serializing_if = "Option::is_none")]
     finish_reason: Option<String>,
@@ -875,6 +989,173 @@ struct GeminiUsageMetadata {
     prompt_token_count: Option<i32>,
     candidates_token_count: Option<i32>,
     total_token_count: Option<i32>,
+    /// Prompt tokens served from context cache (included in prompt_token_count)
+    cached_content_token_count: Option<i32>,
+}
+
+fn gemini_usage(metadata: Option<&GeminiUsageMetadata>) -> Usage {
+    Usage::from_prompt_total(
+        metadata.and_then(|u| u.prompt_token_count).unwrap_or(0) as u32,
+        metadata.and_then(|u| u.candidates_token_count).unwrap_or(0) as u32,
+        metadata.and_then(|u| u.cached_content_token_count).map(|n| n as u32),
+    )
+}
+
+/// Map a Gemini finishReason to an Anthropic stop_reason
+fn stop_reason(finish_reason: Option<&str>, has_tool_use: bool) -> Option<String> {
+    let reason = match finish_reason? {
+        _ if has_tool_use => "tool_use",
+        "MAX_TOKENS" => "max_tokens",
+        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" => "refusal",
+        _ => "end_turn",
+    };
+    Some(reason.to_string())
+}
+
+/// Gemini doesn't give function calls ids, so tool_use blocks get fresh ones
+fn tool_use_id() -> String {
+    format!("toolu_{}", uuid::Uuid::new_v4().simple())
+}
+
+fn sse(event: &str, data: serde_json::Value) -> String {
+    format!("event: {}\ndata: {}\n\n", event, data)
+}
+
+/// Tracks an in-progress stream so Gemini chunks (whole parts, no block
+/// boundaries) can be emitted as Anthropic content blocks
+#[derive(Debug)]
+struct GeminiStreamState {
+    message_id: String,
+    model: String,
+    message_started: bool,
+    /// Index of the open text block, if any
+    text_block: Option<u32>,
+    next_block_index: u32,
+    had_tool_use: bool,
+    /// Latest usage (each chunk carries running totals)
+    usage: Usage,
+    stream_ended: bool,
+}
+
+impl GeminiStreamState {
+    fn new(model: String) -> Self {
+        Self {
+            message_id: format!("msg_{}", uuid::Uuid::new_v4().simple()),
+            model,
+            message_started: false,
+            text_block: None,
+            next_block_index: 0,
+            had_tool_use: false,
+            usage: gemini_usage(None),
+            stream_ended: false,
+        }
+    }
+
+    fn transform_chunk(&mut self, chunk: GeminiResponse) -> String {
+        let mut output = String::new();
+        if self.stream_ended {
+            return output;
+        }
+        if chunk.usage_metadata.is_some() {
+            self.usage = gemini_usage(chunk.usage_metadata.as_ref());
+        }
+
+        if !self.message_started {
+            self.message_started = true;
+            output.push_str(&sse("message_start", serde_json::json!({
+                "type": "message_start",
+                "message": {
+                    "id": self.message_id,
+                    "type": "message",
+                    "role": "assistant",
+                    "content": [],
+                    "model": self.model,
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": {"input_tokens": self.usage.input_tokens, "output_tokens": 0}
+                }
+            })));
+        }
+
+        let Some(candidate) = chunk.candidates.into_iter().next() else {
+            return output;
+        };
+        for part in candidate.content.parts {
+            match part {
+                GeminiPart::Text { text } if !text.is_empty() => {
+                    let index = match self.text_block {
+                        Some(index) => index,
+                        None => {
+                            let index = self.next_block_index;
+                            self.next_block_index += 1;
+                            self.text_block = Some(index);
+                            output.push_str(&sse("content_block_start", serde_json::json!({
+                                "type": "content_block_start",
+                                "index": index,
+                                "content_block": {"type": "text", "text": ""}
+                            })));
+                            index
+                        }
+                    };
+                    output.push_str(&sse("content_block_delta", serde_json::json!({
+                        "type": "content_block_delta",
+                        "index": index,
+                        "delta": {"type": "text_delta", "text": text}
+                    })));
+                }
+                GeminiPart::FunctionCall { function_call } => {
+                    // Function calls arrive whole, so each is a complete tool_use block
+                    self.close_text_block(&mut output);
+                    let index = self.next_block_index;
+                    self.next_block_index += 1;
+                    self.had_tool_use = true;
+                    output.push_str(&sse("content_block_start", serde_json::json!({
+                        "type": "content_block_start",
+                        "index": index,
+                        "content_block": {"type": "tool_use", "id": tool_use_id(), "name": function_call.name, "input": {}}
+                    })));
+                    output.push_str(&sse("content_block_delta", serde_json::json!({
+                        "type": "content_block_delta",
+                        "index": index,
+                        "delta": {"type": "input_json_delta", "partial_json": function_call.input().to_string()}
+                    })));
+                    output.push_str(&sse("content_block_stop", serde_json::json!({
+                        "type": "content_block_stop",
+                        "index": index
+                    })));
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(reason) = stop_reason(candidate.finish_reason.as_deref(), self.had_tool_use) {
+            output.push_str(&self.finish(&reason));
+        }
+        output
+    }
+
+    fn close_text_block(&mut self, output: &mut String) {
+        if let Some(index) = self.text_block.take() {
+            output.push_str(&sse("content_block_stop", serde_json::json!({
+                "type": "content_block_stop",
+                "index": index
+            })));
+        }
+    }
+
+    /// Close any open block and end the message
+    fn finish(&mut self, stop_reason: &str) -> String {
+        let mut output = String::new();
+        self.close_text_block(&mut output);
+        output.push_str(&sse("message_delta", serde_json::json!({
+            "type": "message_delta",
+            "delta": {"stop_reason": stop_reason, "stop_sequence": null},
+            "usage": self.usage
+        })));
+        output.push_str(&sse("message_stop", serde_json::json!({"type": "message_stop"})));
+        self.stream_ended = true;
+        output
+    }
 }
 
 // Code Assist API structures (for OAuth)
@@ -953,7 +1234,7 @@ fn parse_retry_delay(duration: &str) -> Option<std::time::Duration> {
     if let Some(ms_str) = duration.strip_suffix("ms") {
         ms_str.parse::<f64>().ok().map(|ms| std::time::Duration::from_millis(ms as u64))
     } else if let Some(s_str) = duration.strip_suffix("s") {
-        s_str.parse::<f64>().ok().map(|s| std::time::Duration::from_secs_f64(s))
+        s_str.parse::<f64>().ok().map(std::time::Duration::from_secs_f64)
     } else {
         None
     }
@@ -993,3 +1274,128 @@ fn extract_retry_delay(error_text: &str) -> Option<std::time::Duration> {
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn provider() -> GeminiProvider {
+        GeminiProvider::new(
+            "gemini".to_string(),
+            Some("key".to_string()),
+            None,
+            vec!["gemini-2.5-pro".to_string()],
+            HashMap::new(),
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_transform_request_tools_and_images() {
+        let request: AnthropicRequest = serde_json::from_value(json!({
+            "model": "gemini-2.5-pro",
+            "max_tokens": 1024,
+            "tools": [{"name": "Read", "description": "Read a file", "input_schema": {"type": "object", "$schema": "x"}}],
+            "messages": [
+                {"role": "user", "content": [
+                    {"type": "text", "text": "what's in main.rs?"},
+                    {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBOR"}}
+                ]},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "toolu_1", "name": "Read", "input": {"path": "main.rs"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": "fn main() {}"}
+                ]}
+            ]
+        }))
+        .unwrap();
+
+        let json = serde_json::to_value(provider().transform_request(&request).unwrap()).unwrap();
+        let contents = &json["contents"];
+        assert_eq!(contents[0]["parts"][1]["inline_data"]["mimeType"], "image/png");
+        assert_eq!(contents[1]["role"], "model");
+        assert_eq!(contents[1]["parts"][0]["functionCall"], json!({"name": "Read", "args": {"path": "main.rs"}}));
+        assert_eq!(
+            contents[2]["parts"][0]["functionResponse"],
+            json!({"name": "Read", "response": {"result": "fn main() {}"}})
+        );
+        assert_eq!(json["tools"][0]["functionDeclarations"][0]["parameters"], json!({"type": "object"}));
+    }
+
+    #[test]
+    fn test_transform_response_function_call() {
+        let response: GeminiResponse = serde_json::from_value(json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [
+                    {"text": "Reading it."},
+                    {"functionCall": {"name": "Read", "args": {"path": "main.rs"}}}
+                ]},
+                "finishReason": "STOP"
+            }],
+            "usageMetadata": {"promptTokenCount": 100, "candidatesTokenCount": 20, "cachedContentTokenCount": 40}
+        }))
+        .unwrap();
+
+        let response = provider().transform_response(response, "gemini-2.5-pro".to_string()).unwrap();
+        assert_eq!(response.stop_reason.as_deref(), Some("tool_use"));
+        assert_eq!(response.content[0].as_text(), Some("Reading it."));
+        let ContentBlock::Known(KnownContentBlock::ToolUse { id, name, input }) = &response.content[1] else {
+            panic!("expected tool_use, got {:?}", response.content[1]);
+        };
+        assert!(id.starts_with("toolu_"));
+        assert_eq!((name.as_str(), input), ("Read", &json!({"path": "main.rs"})));
+        assert_eq!((response.usage.input_tokens, response.usage.cache_read_input_tokens), (60, Some(40)));
+    }
+
+    #[test]
+    fn test_stream_chunks_to_anthropic_sse() {
+        let mut state = GeminiStreamState::new("gemini-2.5-pro".to_string());
+        let chunk = |value: serde_json::Value| -> GeminiResponse {
+            // Code Assist chunks are wrapped in {"response": ...}
+            serde_json::from_value::<CodeAssistResponse>(json!({"response": value})).unwrap().response
+        };
+
+        let mut output = state.transform_chunk(chunk(json!({
+            "candidates": [{"content": {"role": "model", "parts": [{"text": "Hel"}]}}]
+        })));
+        output.push_str(&state.transform_chunk(chunk(json!({
+            "candidates": [{"content": {"role": "model", "parts": [{"text": "lo"}]}}]
+        }))));
+        output.push_str(&state.transform_chunk(chunk(json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [{"functionCall": {"name": "Bash", "args": {"command": "ls"}}}]},
+                "finishReason": "STOP"
+            }],
+            "usageMetadata": {"promptTokenCount": 50, "candidatesTokenCount": 7}
+        }))));
+
+        let events = crate::providers::streaming::parse_sse_events(&output);
+        let types: Vec<_> = events.iter().map(|e| e.event.as_deref().unwrap()).collect();
+        assert_eq!(
+            types,
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop"
+            ]
+        );
+        let data = |i: usize| serde_json::from_str::<serde_json::Value>(&events[i].data).unwrap();
+        assert_eq!(data(5)["content_block"]["name"], "Bash");
+        assert_eq!(data(6)["delta"]["partial_json"], r#"{"command":"ls"}"#);
+        assert_eq!(data(8)["delta"]["stop_reason"], "tool_use");
+        assert_eq!(data(8)["usage"]["output_tokens"], 7);
+        assert!(state.stream_ended);
+    }
+}
@@ -0,0 +1,366 @@
+use crate::cli::{ModelMapping, TieBreak};
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Number of recent outcomes kept per provider/model
+const HEALTH_WINDOW: usize = 50;
+
+/// Round-robin weight floor, so a failing mapping still gets the odd request and can recover
+const MIN_ROTATION_WEIGHT: f64 = 0.1;
+
+/// Outcomes older than this don't count for `ordering = "latency"`, so a mapping
+/// that stopped getting requests is measured again instead of keeping a stale average
+const LATENCY_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// Below this recent success rate a mapping is tried after the others under `ordering = "latency"`
+const MIN_LATENCY_SUCCESS_RATE: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy)]
+struct Outcome {
+    success: bool,
+    latency_ms: u64,
+    at: Instant,
+}
+
+/// Rolling health snapshot for a provider/model mapping
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthStats {
+    pub provider: String,
+    pub model: String,
+    /// Outcomes in the rolling window
+    pub samples: usize,
+    /// Fraction of successful attempts in the window (0.0 - 1.0)
+    pub success_rate: f64,
+    /// Mean latency of successful attempts (time to response / first byte)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_latency_ms: Option<u64>,
+    /// Error events received inside successful streams since startup
+    #[serde(skip_serializing_if = "is_zero")]
+    pub stream_errors: u64,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+/// Tracks recent success rate and latency per provider/model.
+///
+/// Used to break priority ties between mappings (`router.tie_break = "success_rate"`),
+/// to weight `strategy = "round_robin"` rotation and for `ordering = "latency"`.
+pub struct HealthTracker {
+    entries: DashMap<(String, String), VecDeque<Outcome>>,
+    /// Smooth weighted round-robin counters per (model, provider, actual_model)
+    rotation: DashMap<(String, String, String), f64>,
+    stream_errors: DashMap<(String, String), u64>,
+}
+
+impl Default for HealthTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HealthTracker {
+    pub fn new() -> Self {
+        Self {
+            entries: DashMap::new(),
+            rotation: DashMap::new(),
+            stream_errors: DashMap::new(),
+        }
+    }
+
+    /// Record the outcome of one upstream attempt
+    pub fn record(&self, provider: &str, model: &str, success: bool, latency: Duration) {
+        let mut window = self
+            .entries
+            .entry((provider.to_string(), model.to_string()))
+            .or_default();
+        if window.len() >= HEALTH_WINDOW {
+            window.pop_front();
+        }
+        window.push_back(Outcome {
+            success,
+            latency_ms: latency.as_millis() as u64,
+            at: Instant::now(),
+        });
+    }
+
+    /// Record an error event inside a stream that had already started successfully
+    /// (counted as a failed attempt too)
+    pub fn record_stream_error(&self, provider: &str, model: &str, latency: Duration) {
+        self.record(provider, model, false, latency);
+        *self.stream_errors.entry((provider.to_string(), model.to_string())).or_default() += 1;
+    }
+
+    /// Rolling stats for a provider/model (None if never tried)
+    pub fn stats(&self, provider: &str, model: &str) -> Option<HealthStats> {
+        let window = self.entries.get(&(provider.to_string(), model.to_string()))?;
+        Some(self.summarize(provider, model, &window))
+    }
+
+    fn summarize(&self, provider: &str, model: &str, window: &VecDeque<Outcome>) -> HealthStats {
+        let successes: Vec<u64> = window.iter().filter(|o| o.success).map(|o| o.latency_ms).collect();
+        let success_rate = if window.is_empty() {
+            1.0
+        } else {
+            successes.len() as f64 / window.len() as f64
+        };
+        let avg_latency_ms = if successes.is_empty() {
+            None
+        } else {
+            Some(successes.iter().sum::<u64>() / successes.len() as u64)
+        };
+
+        HealthStats {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            samples: window.len(),
+            success_rate,
+            avg_latency_ms,
+            stream_errors: self
+                .stream_errors
+                .get(&(provider.to_string(), model.to_string()))
+                .map_or(0, |n| *n),
+        }
+    }
+
+    /// Snapshot of all tracked provider/models
+    pub fn all_stats(&self) -> Vec<HealthStats> {
+        let mut stats: Vec<HealthStats> = self
+            .entries
+            .iter()
+            .map(|e| self.summarize(&e.key().0, &e.key().1, e.value()))
+            .collect();
+        stats.sort_by(|a, b| (&a.provider, &a.model).cmp(&(&b.provider, &b.model)));
+        stats
+    }
+
+    /// Order mappings by priority. Ties keep config order, unless `tie_break` is
+    /// `success_rate`: then higher recent success rate wins, then lower latency.
+    /// Mappings without history count as fully healthy so they get tried.
+    pub fn order(&self, mappings: &mut [ModelMapping], tie_break: TieBreak) {
+        match tie_break {
+            TieBreak::Config => mappings.sort_by_key(|m| m.priority),
+            TieBreak::SuccessRate => {
+                let keyed: Vec<(f64, u64)> = mappings
+                    .iter()
+                    .map(|m| match self.stats(&m.provider, &m.actual_model) {
+                        Some(s) => (s.success_rate, s.avg_latency_ms.unwrap_or(0)),
+                        None => (1.0, 0),
+                    })
+                    .collect();
+                let mut indexed: Vec<(usize, ModelMapping)> = mappings.iter().cloned().enumerate().collect();
+                indexed.sort_by(|(ia, a), (ib, b)| {
+                    a.priority
+                        .cmp(&b.priority)
+                        .then(keyed[*ib].0.total_cmp(&keyed[*ia].0))
+                        .then(keyed[*ia].1.cmp(&keyed[*ib].1))
+                        .then(ia.cmp(ib))
+                });
+                for (slot, (_, mapping)) in mappings.iter_mut().zip(indexed) {
+                    *slot = mapping;
+                }
+            }
+        }
+    }
+
+    /// Order mappings fastest first (`ordering = "latency"`), by mean latency of
+    /// successful attempts in the last 15 minutes. Mappings without recent attempts
+    /// come first, so they get measured; mappings failing half their recent attempts
+    /// or more come last. Priority only breaks ties.
+    pub fn order_by_latency(&self, mappings: &mut [ModelMapping]) {
+        self.order_by_latency_at(mappings, Instant::now());
+    }
+
+    fn order_by_latency_at(&self, mappings: &mut [ModelMapping], now: Instant) {
+        let keyed: Vec<(bool, u64)> = mappings
+            .iter()
+            .map(|m| {
+                let Some(window) = self.entries.get(&(m.provider.clone(), m.actual_model.clone())) else {
+                    return (false, 0);
+                };
+                let recent: Vec<&Outcome> =
+                    window.iter().filter(|o| now.saturating_duration_since(o.at) <= LATENCY_WINDOW).collect();
+                let latencies: Vec<u64> = recent.iter().filter(|o| o.success).map(|o| o.latency_ms).collect();
+                if recent.is_empty() {
+                    return (false, 0);
+                }
+                let failing = (latencies.len() as f64 / recent.len() as f64) < MIN_LATENCY_SUCCESS_RATE;
+                let avg = latencies.iter().sum::<u64>().checked_div(latencies.len() as u64).unwrap_or(u64::MAX);
+                (failing, avg)
+            })
+            .collect();
+        let mut indexed: Vec<(usize, ModelMapping)> = mappings.iter().cloned().enumerate().collect();
+        indexed.sort_by(|(ia, a), (ib, b)| keyed[*ia].cmp(&keyed[*ib]).then(a.priority.cmp(&b.priority)).then(ia.cmp(ib)));
+        for (slot, (_, mapping)) in mappings.iter_mut().zip(indexed) {
+            *slot = mapping;
+        }
+    }
+
+    /// Cycle each group of equal-priority mappings per request (`strategy = "round_robin"`).
+    /// Mappings are weighted by recent success rate, so unhealthy ones come up less often;
+    /// the rest of the group follows as fallbacks. Expects mappings sorted by priority.
+    pub fn rotate(&self, model: &str, mappings: &mut [ModelMapping]) {
+        let mut start = 0;
+        while start < mappings.len() {
+            let priority = mappings[start].priority;
+            let end = start + mappings[start..].iter().take_while(|m| m.priority == priority).count();
+            self.rotate_group(model, &mut mappings[start..end]);
+            start = end;
+        }
+    }
+
+    /// Smooth weighted round-robin: add each weight to its counter, pick the largest,
+    /// and take the total back off the pick
+    fn rotate_group(&self, model: &str, group: &mut [ModelMapping]) {
+        if group.len() < 2 {
+            return;
+        }
+        let key = |m: &ModelMapping| (model.to_string(), m.provider.clone(), m.actual_model.clone());
+        let weights: Vec<f64> = group
+            .iter()
+            .map(|m| {
+                let rate = self.stats(&m.provider, &m.actual_model).map_or(1.0, |s| s.success_rate);
+                rate.max(MIN_ROTATION_WEIGHT)
+            })
+            .collect();
+        let total: f64 = weights.iter().sum();
+
+        let mut counters: Vec<f64> = group
+            .iter()
+            .zip(&weights)
+            .map(|(m, weight)| {
+                let mut counter = self.rotation.entry(key(m)).or_insert(0.0);
+                *counter += weight;
+                *counter
+            })
+            .collect();
+        let picked = (0..group.len())
+            .reduce(|best, i| if counters[i] > counters[best] { i } else { best })
+            .unwrap_or(0);
+        if let Some(mut counter) = self.rotation.get_mut(&key(&group[picked])) {
+            *counter -= total;
+        }
+        counters[picked] -= total;
+
+        // The pick first, then the rest in the order they're next in line
+        let mut indexed: Vec<(usize, ModelMapping)> = group.iter().cloned().enumerate().collect();
+        indexed.sort_by(|(a, _), (b, _)| {
+            (*a != picked)
+                .cmp(&(*b != picked))
+                .then(counters[*b].total_cmp(&counters[*a]))
+                .then(a.cmp(b))
+        });
+        for (slot, (_, mapping)) in group.iter_mut().zip(indexed) {
+            *slot = mapping;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(priority: u32, provider: &str) -> ModelMapping {
+        ModelMapping {
+            priority,
+            provider: provider.to_string(),
+            actual_model: "m".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn providers(mappings: &[ModelMapping]) -> Vec<&str> {
+        mappings.iter().map(|m| m.provider.as_str()).collect()
+    }
+
+    #[test]
+    fn test_ties_keep_config_order() {
+        let health = HealthTracker::new();
+        health.record("a", "m", false, Duration::from_millis(100));
+
+        let mut mappings = vec![mapping(2, "c"), mapping(1, "a"), mapping(1, "b")];
+        health.order(&mut mappings, TieBreak::Config);
+        assert_eq!(providers(&mappings), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_success_rate_tie_break() {
+        let health = HealthTracker::new();
+        health.record("a", "m", true, Duration::from_millis(100));
+        health.record("a", "m", false, Duration::from_millis(100));
+        health.record("b", "m", true, Duration::from_millis(900));
+        health.record("c", "m", true, Duration::from_millis(200));
+
+        // b and c are both 100%, c is faster; a is 50%; d never tried counts as healthy
+        let mut mappings = vec![mapping(1, "a"), mapping(1, "b"), mapping(1, "c"), mapping(2, "d")];
+        health.order(&mut mappings, TieBreak::SuccessRate);
+        assert_eq!(providers(&mappings), vec!["c", "b", "a", "d"]);
+
+        // Priority always wins over health
+        let mut mappings = vec![mapping(2, "c"), mapping(1, "a")];
+        health.order(&mut mappings, TieBreak::SuccessRate);
+        assert_eq!(providers(&mappings), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn test_latency_ordering() {
+        let health = HealthTracker::new();
+        for (provider, ms) in [("a", 900), ("b", 300), ("c", 600)] {
+            health.record(provider, "m", true, Duration::from_millis(ms));
+        }
+        // c is fast when it works, but fails most attempts
+        health.record("c", "m", false, Duration::from_millis(100));
+        health.record("c", "m", false, Duration::from_millis(100));
+
+        // Untried d first (to be measured), then fastest first, failing c last; priority ignored
+        let mut mappings = vec![mapping(1, "a"), mapping(1, "c"), mapping(2, "b"), mapping(3, "d")];
+        health.order_by_latency(&mut mappings);
+        assert_eq!(providers(&mappings), vec!["d", "b", "a", "c"]);
+
+        // Once the attempts are old, everything is measured again in priority order
+        let later = Instant::now() + LATENCY_WINDOW + Duration::from_secs(1);
+        let mut mappings = vec![mapping(2, "b"), mapping(1, "c"), mapping(1, "a")];
+        health.order_by_latency_at(&mut mappings, later);
+        assert_eq!(providers(&mappings), vec!["c", "a", "b"]);
+    }
+
+    #[test]
+    fn test_window_is_bounded() {
+        let health = HealthTracker::new();
+        for _ in 0..HEALTH_WINDOW {
+            health.record("a", "m", false, Duration::from_millis(10));
+        }
+        health.record("a", "m", true, Duration::from_millis(10));
+
+        let stats = health.stats("a", "m").unwrap();
+        assert_eq!(stats.samples, HEALTH_WINDOW);
+        assert!((stats.success_rate - 1.0 / HEALTH_WINDOW as f64).abs() < f64::EPSILON);
+        assert_eq!(stats.avg_latency_ms, Some(10));
+    }
+
+    #[test]
+    fn test_round_robin_rotation() {
+        let health = HealthTracker::new();
+        let mappings = vec![mapping(1, "a"), mapping(1, "b"), mapping(1, "c"), mapping(2, "d")];
+        let first = |health: &HealthTracker| {
+            let mut ordered = mappings.clone();
+            health.rotate("model", &mut ordered);
+            assert_eq!(ordered[3].provider, "d");
+            ordered[0].provider.clone()
+        };
+
+        let picks: Vec<String> = (0..6).map(|_| first(&health)).collect();
+        assert_eq!(picks, ["a", "b", "c", "a", "b", "c"]);
+
+        // b keeps failing: it's picked far less often but not starved
+        for _ in 0..10 {
+            health.record("b", "m", false, Duration::from_millis(100));
+        }
+        let picks: Vec<String> = (0..42).map(|_| first(&health)).collect();
+        let count = |p: &str| picks.iter().filter(|x| *x == p).count();
+        assert_eq!((count("a"), count("b"), count("c")), (20, 2, 20));
+    }
+}
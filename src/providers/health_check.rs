@@ -0,0 +1,353 @@
+//! Active provider health checks (`[server.health_checks]`).
+//!
+//! Request outcomes only say something about a provider while it gets traffic. A
+//! health check probes each enabled provider on an interval instead: a model list
+//! request where the provider has one, or a one-token completion. A provider whose
+//! last `down_after` probes failed counts as down, and the dispatcher skips it
+//! while another mapping's provider is up. One successful probe brings it back.
+
+use crate::cli::{AppConfig, ModelMapping};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::ProviderConfig;
+
+/// Probe outcomes kept per provider for the success rate and latency
+const PROBE_WINDOW: usize = 20;
+
+/// `[server.health_checks]`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct HealthCheckConfig {
+    /// Seconds between probes of each provider
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    /// How providers are probed
+    #[serde(default)]
+    pub probe: ProbeMethod,
+    /// Consecutive failed probes before a provider counts as down
+    #[serde(default = "default_down_after")]
+    pub down_after: u32,
+    /// How long a probe may take before it counts as failed
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_interval_secs() -> u64 {
+    60
+}
+
+fn default_down_after() -> u32 {
+    2
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+impl HealthCheckConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs.max(1))
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs.max(1))
+    }
+}
+
+/// How a provider is probed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeMethod {
+    /// List models, falling back to a one-token completion where the provider
+    /// can't list them
+    #[default]
+    Auto,
+    /// List models only; providers that can't list them aren't probed
+    Models,
+    /// One-token completion (billed like any request)
+    Completion,
+}
+
+/// Model a completion probe asks for: the provider's first mapped `actual_model`,
+/// then the first of its `models`
+pub fn probe_model(config: &AppConfig, provider: &ProviderConfig) -> Option<String> {
+    config
+        .models
+        .iter()
+        .flat_map(|m| &m.mappings)
+        .find(|m| m.provider == provider.name && !m.actual_model.contains('{'))
+        .map(|m| &m.actual_model)
+        .or(provider.models.first())
+        .cloned()
+}
+
+/// Provider health as seen by the probes, for `/api/providers/health`
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderHealthStatus {
+    pub provider: String,
+    /// `up`, `down`, or `unknown` before the first probe
+    pub status: &'static str,
+    /// Probes in the rolling window
+    pub samples: usize,
+    /// Fraction of successful probes in the window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success_rate: Option<f64>,
+    /// Mean latency of successful probes in the window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_latency_ms: Option<u64>,
+    pub consecutive_failures: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_probe_at: Option<DateTime<Utc>>,
+    /// `models` or `completion`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_method: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ProbeOutcome {
+    success: bool,
+    latency_ms: u64,
+}
+
+struct ProbeEntry {
+    outcomes: VecDeque<ProbeOutcome>,
+    consecutive_failures: u32,
+    down: bool,
+    last_attempt: Instant,
+    last_probe_at: Option<DateTime<Utc>>,
+    last_method: Option<&'static str>,
+    last_error: Option<String>,
+}
+
+/// Probe schedule and results per provider
+#[derive(Default)]
+pub struct HealthChecks {
+    entries: DashMap<String, ProbeEntry>,
+}
+
+impl HealthChecks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a probe of `provider` is due; if so, it counts as started now
+    pub fn start_if_due(&self, provider: &str, interval: Duration) -> bool {
+        self.start_if_due_at(provider, interval, Instant::now())
+    }
+
+    fn start_if_due_at(&self, provider: &str, interval: Duration, now: Instant) -> bool {
+        match self.entries.entry(provider.to_string()) {
+            dashmap::mapref::entry::Entry::Occupied(mut entry) => {
+                if now.duration_since(entry.get().last_attempt) < interval {
+                    return false;
+                }
+                entry.get_mut().last_attempt = now;
+                true
+            }
+            dashmap::mapref::entry::Entry::Vacant(entry) => {
+                entry.insert(ProbeEntry {
+                    outcomes: VecDeque::new(),
+                    consecutive_failures: 0,
+                    down: false,
+                    last_attempt: now,
+                    last_probe_at: None,
+                    last_method: None,
+                    last_error: None,
+                });
+                true
+            }
+        }
+    }
+
+    /// Record a probe's outcome. Returns `Some(true)` when the provider just went
+    /// down, `Some(false)` when it just came back.
+    pub fn record(
+        &self,
+        provider: &str,
+        method: &'static str,
+        result: Result<Duration, String>,
+        down_after: u32,
+    ) -> Option<bool> {
+        let mut entry = self.entries.get_mut(provider)?;
+        if entry.outcomes.len() >= PROBE_WINDOW {
+            entry.outcomes.pop_front();
+        }
+        entry.last_probe_at = Some(Utc::now());
+        entry.last_method = Some(method);
+        let was_down = entry.down;
+        match result {
+            Ok(took) => {
+                entry.outcomes.push_back(ProbeOutcome { success: true, latency_ms: took.as_millis() as u64 });
+                entry.consecutive_failures = 0;
+                entry.last_error = None;
+                entry.down = false;
+            }
+            Err(e) => {
+                entry.outcomes.push_back(ProbeOutcome { success: false, latency_ms: 0 });
+                entry.consecutive_failures += 1;
+                entry.last_error = Some(e.chars().take(200).collect());
+                entry.down = entry.consecutive_failures >= down_after.max(1);
+            }
+        }
+        (entry.down != was_down).then_some(entry.down)
+    }
+
+    /// Whether the probes say `provider` is down
+    pub fn is_down(&self, provider: &str) -> bool {
+        self.entries.get(provider).is_some_and(|e| e.down)
+    }
+
+    /// Drop mappings to providers that are down, unless that would leave none:
+    /// then they are all tried, in order, as usual
+    pub fn skip_down(&self, mappings: &mut Vec<ModelMapping>) {
+        if mappings.iter().all(|m| self.is_down(&m.provider)) {
+            return;
+        }
+        let down: Vec<&str> = mappings.iter().filter(|m| self.is_down(&m.provider)).map(|m| m.provider.as_str()).collect();
+        if down.is_empty() {
+            return;
+        }
+        tracing::info!("🩺 Skipping providers failing health checks: {}", down.join(", "));
+        mappings.retain(|m| !self.is_down(&m.provider));
+    }
+
+    /// Status of each named provider (`unknown` until it has been probed)
+    pub fn statuses<'a>(&self, providers: impl IntoIterator<Item = &'a str>) -> Vec<ProviderHealthStatus> {
+        providers
+            .into_iter()
+            .map(|provider| {
+                let Some(entry) = self.entries.get(provider).filter(|e| e.last_probe_at.is_some()) else {
+                    return ProviderHealthStatus {
+                        provider: provider.to_string(),
+                        status: "unknown",
+                        samples: 0,
+                        success_rate: None,
+                        avg_latency_ms: None,
+                        consecutive_failures: 0,
+                        last_probe_at: None,
+                        last_method: None,
+                        last_error: None,
+                    };
+                };
+                let latencies: Vec<u64> = entry.outcomes.iter().filter(|o| o.success).map(|o| o.latency_ms).collect();
+                let samples = entry.outcomes.len();
+                ProviderHealthStatus {
+                    provider: provider.to_string(),
+                    status: if entry.down { "down" } else { "up" },
+                    samples,
+                    success_rate: (samples > 0).then(|| latencies.len() as f64 / samples as f64),
+                    avg_latency_ms: (!latencies.is_empty()).then(|| latencies.iter().sum::<u64>() / latencies.len() as u64),
+                    consecutive_failures: entry.consecutive_failures,
+                    last_probe_at: entry.last_probe_at,
+                    last_method: entry.last_method,
+                    last_error: entry.last_error.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(provider: &str) -> ModelMapping {
+        ModelMapping {
+            priority: 1,
+            provider: provider.to_string(),
+            actual_model: "m".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_probe_model() {
+        let config: AppConfig = toml::from_str(
+            r#"
+[router]
+default = "fast"
+
+[[providers]]
+name = "openrouter"
+provider_type = "openrouter"
+api_key = "sk-test"
+models = ["z-ai/glm-4.6"]
+
+[[providers]]
+name = "ollama"
+provider_type = "ollama"
+models = ["llama3.2"]
+
+[[providers]]
+name = "spare"
+provider_type = "openai"
+api_key = "sk-test"
+models = []
+
+[[models]]
+name = "fast"
+mappings = [
+    { priority = 1, provider = "openrouter", actual_model = "{requested}" },
+    { priority = 2, provider = "openrouter", actual_model = "openai/gpt-5-mini" },
+]
+"#,
+        )
+        .unwrap();
+        let models: Vec<Option<String>> = config.providers.iter().map(|p| probe_model(&config, p)).collect();
+        assert_eq!(models, vec![Some("openai/gpt-5-mini".to_string()), Some("llama3.2".to_string()), None]);
+    }
+
+    #[test]
+    fn test_down_after_consecutive_failures() {
+        let checks = HealthChecks::new();
+        let interval = Duration::from_secs(60);
+        let start = Instant::now();
+
+        assert!(checks.start_if_due_at("a", interval, start));
+        assert!(!checks.start_if_due_at("a", interval, start + Duration::from_secs(30)));
+        assert!(checks.start_if_due_at("a", interval, start + Duration::from_secs(60)));
+        checks.start_if_due_at("b", interval, start);
+
+        assert_eq!(checks.record("a", "models", Ok(Duration::from_millis(120)), 2), None);
+        assert_eq!(checks.record("a", "models", Err("connection refused".to_string()), 2), None);
+        assert!(!checks.is_down("a"));
+        assert_eq!(checks.record("a", "models", Err("connection refused".to_string()), 2), Some(true));
+        assert!(checks.is_down("a"));
+
+        let statuses = checks.statuses(["a", "b", "c"]);
+        assert_eq!(statuses[0].status, "down");
+        assert_eq!(statuses[0].samples, 3);
+        assert_eq!(statuses[0].consecutive_failures, 2);
+        assert_eq!(statuses[0].avg_latency_ms, Some(120));
+        assert_eq!(statuses[0].last_error.as_deref(), Some("connection refused"));
+        assert_eq!(statuses[1].status, "unknown");
+        assert_eq!(statuses[2].status, "unknown");
+
+        // One success brings it back
+        assert_eq!(checks.record("a", "completion", Ok(Duration::from_millis(300)), 2), Some(false));
+        assert!(!checks.is_down("a"));
+    }
+
+    #[test]
+    fn test_skip_down_keeps_last_resort() {
+        let checks = HealthChecks::new();
+        for provider in ["a", "b"] {
+            checks.start_if_due(provider, Duration::from_secs(60));
+            checks.record(provider, "models", Err("timeout".to_string()), 1);
+        }
+
+        let mut mappings = vec![mapping("a"), mapping("c"), mapping("b")];
+        checks.skip_down(&mut mappings);
+        assert_eq!(mappings.iter().map(|m| m.provider.as_str()).collect::<Vec<_>>(), vec!["c"]);
+
+        // All down: nothing is skipped
+        let mut mappings = vec![mapping("a"), mapping("b")];
+        checks.skip_down(&mut mappings);
+        assert_eq!(mappings.len(), 2);
+    }
+}
@@ -1,14 +1,36 @@
+pub mod aws;
+pub mod bedrock;
+pub mod budget;
+pub mod capabilities;
+pub mod circuit_breaker;
+pub mod copilot;
+pub mod deprecation;
 pub mod error;
+pub mod health;
+pub mod health_check;
+pub mod quota;
+pub mod pricing;
+pub mod prompt_cache;
+pub mod prompt_tools;
+pub mod quality;
 pub mod openai;
 pub mod anthropic_compatible;
 pub mod gemini;
+pub mod ollama;
 pub mod registry;
+pub mod retry;
+pub mod slo;
 pub mod streaming;
+pub mod timeout;
+pub mod warm_pool;
+#[cfg(test)]
+mod stream_fixtures;
 
 use async_trait::async_trait;
 use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse, ContentBlock, KnownContentBlock};
 use error::ProviderError;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use secrecy::{ExposeSecret, SecretString};
 use bytes::Bytes;
 use futures::stream::Stream;
 use std::pin::Pin;
@@ -16,6 +38,7 @@ use std::collections::HashMap;
 
 /// Provider response that maintains Anthropic API compatibility
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct ProviderResponse {
     pub id: String,
     pub r#type: String,
@@ -25,6 +48,27 @@ pub struct ProviderResponse {
     pub stop_reason: Option<String>,
     pub stop_sequence: Option<String>,
     pub usage: Usage,
+    /// Upstream rate limit headers (not sent to clients)
+    #[serde(skip)]
+    pub rate_limits: HashMap<String, String>,
+}
+
+impl ProviderResponse {
+    /// An assistant message with no stop reason or rate limit headers
+    #[allow(dead_code)] // Part of the embedding API, not used by the binary
+    pub fn new(id: impl Into<String>, model: impl Into<String>, content: Vec<ContentBlock>, usage: Usage) -> Self {
+        Self {
+            id: id.into(),
+            r#type: "message".to_string(),
+            role: "assistant".to_string(),
+            content,
+            model: model.into(),
+            stop_reason: None,
+            stop_sequence: None,
+            usage,
+            rate_limits: HashMap::new(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,12 +81,58 @@ pub struct Usage {
     pub cache_read_input_tokens: Option<u32>,
 }
 
+impl Usage {
+    /// Usage from providers whose prompt token count includes cached tokens (OpenAI, Gemini).
+    /// Anthropic's `input_tokens` excludes cache reads, so cached tokens are split out.
+    pub fn from_prompt_total(prompt_tokens: u32, output_tokens: u32, cached_tokens: Option<u32>) -> Self {
+        let cached = cached_tokens.unwrap_or(0).min(prompt_tokens);
+        Self {
+            input_tokens: prompt_tokens - cached,
+            output_tokens,
+            cache_creation_input_tokens: None,
+            cache_read_input_tokens: (cached > 0).then_some(cached),
+        }
+    }
+
+    /// All prompt tokens: uncached input plus cache writes and reads
+    pub fn total_input_tokens(&self) -> u32 {
+        self.input_tokens
+            + self.cache_creation_input_tokens.unwrap_or(0)
+            + self.cache_read_input_tokens.unwrap_or(0)
+    }
+
+    /// Short cache summary for log lines, e.g. " cache:85%" (empty when caching wasn't used)
+    pub fn cache_info(&self) -> String {
+        let read = self.cache_read_input_tokens.unwrap_or(0);
+        if read == 0 && self.cache_creation_input_tokens.unwrap_or(0) == 0 {
+            return String::new();
+        }
+        let pct = (read as u64 * 100).checked_div(self.total_input_tokens() as u64).unwrap_or(0);
+        format!(" cache:{}%", pct)
+    }
+}
+
 /// Response from streaming request, includes headers for passthrough
+#[non_exhaustive]
 pub struct StreamResponse {
     /// The byte stream (SSE format)
     pub stream: Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>>,
     /// Headers to forward (e.g., Anthropic rate limit headers)
     pub headers: HashMap<String, String>,
+    /// Upstream rate limit headers, for quota tracking
+    pub rate_limits: HashMap<String, String>,
+}
+
+impl StreamResponse {
+    /// A stream with no headers to forward
+    #[allow(dead_code)] // Part of the embedding API, not used by the binary
+    pub fn new(stream: Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>>) -> Self {
+        Self {
+            stream,
+            headers: HashMap::new(),
+            rate_limits: HashMap::new(),
+        }
+    }
 }
 
 /// Main provider trait - all providers must implement this
@@ -66,26 +156,78 @@ pub trait AnthropicProvider: Send + Sync {
 
     /// Check if provider supports a specific model
     fn supports_model(&self, model: &str) -> bool;
+
+    /// Provider honours Anthropic `cache_control` prompt caching
+    fn supports_prompt_cache(&self) -> bool {
+        false
+    }
+
+    /// Provider fetches `url` image sources itself; when false, the server inlines
+    /// http(s) image URLs as base64 before sending
+    fn accepts_image_urls(&self) -> bool {
+        true
+    }
+
+    /// Model IDs the provider currently serves, from its model list endpoint.
+    /// `None` when the provider has no such endpoint.
+    async fn list_models(&self) -> Result<Option<Vec<String>>, ProviderError> {
+        Ok(None)
+    }
+
+    /// Load `model` or keep it loaded for at least `keep_alive` (`keep_warm`).
+    /// The default sends a one-token generation.
+    async fn keep_warm(&self, model: &str, _keep_alive: std::time::Duration) -> Result<(), ProviderError> {
+        self.send_message(circuit_breaker::probe_request(model)).await.map(|_| ())
+    }
+
+    /// Whether `model` is loaded in memory, for local servers that report it.
+    /// `None` when the provider can't tell.
+    async fn is_loaded(&self, _model: &str) -> Result<Option<bool>, ProviderError> {
+        Ok(None)
+    }
+}
+
+/// Model IDs from an OpenAI/Anthropic-style model list (`{"data": [{"id": ...}]}`)
+pub fn model_ids(body: &serde_json::Value) -> Vec<String> {
+    body["data"]
+        .as_array()
+        .map(|models| models.iter().filter_map(|m| m["id"].as_str().map(String::from)).collect())
+        .unwrap_or_default()
+}
+
+/// Merge a mapping's `extra_body` into an outbound request body. Objects merge key
+/// by key, so `{"generationConfig": {"topK": 40}}` keeps the other generation
+/// settings; any other value replaces the provider's.
+pub fn merge_extra_body(body: &mut serde_json::Value, extra: Option<&serde_json::Map<String, serde_json::Value>>) {
+    let (Some(fields), Some(extra)) = (body.as_object_mut(), extra) else {
+        return;
+    };
+    for (key, value) in extra {
+        match fields.get_mut(key) {
+            Some(existing @ serde_json::Value::Object(_)) if value.is_object() => {
+                merge_extra_body(existing, value.as_object())
+            }
+            _ => {
+                fields.insert(key.clone(), value.clone());
+            }
+        }
+    }
 }
 
 /// Authentication type for providers
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum AuthType {
     /// API key authentication
+    #[default]
     ApiKey,
     /// OAuth 2.0 authentication
     OAuth,
 }
 
-impl Default for AuthType {
-    fn default() -> Self {
-        AuthType::ApiKey
-    }
-}
-
 /// Provider configuration from TOML
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct ProviderConfig {
     pub name: String,
     pub provider_type: String,
@@ -94,9 +236,14 @@ pub struct ProviderConfig {
     #[serde(default)]
     pub auth_type: AuthType,
 
-    /// API key (required for auth_type = "apikey")
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub api_key: Option<String>,
+    /// API key (required for auth_type = "apikey"); redacted in Debug output
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_api_key",
+        deserialize_with = "deserialize_api_key"
+    )]
+    pub api_key: Option<SecretString>,
 
     /// OAuth provider ID (required for auth_type = "oauth")
     /// References a token stored in TokenStore
@@ -107,7 +254,7 @@ pub struct ProviderConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub project_id: Option<String>,
 
-    /// Location/Region (for Vertex AI provider)
+    /// Location/Region (for Vertex AI and Bedrock providers)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub location: Option<String>,
 
@@ -118,20 +265,75 @@ pub struct ProviderConfig {
 
     pub headers: Option<HashMap<String, String>>,
 
+    /// Data-residency region where requests are processed (e.g. "us", "eu", "cn", "local")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+
+    /// Retry transient upstream errors against this provider before failing over
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryConfig>,
+
+    /// Prompt cache TTL set on every cache breakpoint ("5m" or "1h"; Anthropic-compatible only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_ttl: Option<String>,
+
+    /// Keep local models loaded with periodic warm-ups (Ollama, llama.cpp, ...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_warm: Option<warm_pool::KeepWarmConfig>,
+
     pub models: Vec<String>,
     pub enabled: Option<bool>,
 }
 
+/// Serialize the API key as written (config files and masked API responses)
+fn serialize_api_key<S: Serializer>(api_key: &Option<SecretString>, serializer: S) -> Result<S::Ok, S::Error> {
+    match api_key {
+        Some(key) => serializer.serialize_str(key.expose_secret()),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn deserialize_api_key<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<SecretString>, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.map(SecretString::new))
+}
+
 impl ProviderConfig {
+    /// An enabled provider with no credentials, models or options set
+    #[allow(dead_code)] // Part of the embedding API, not used by the binary
+    pub fn new(name: impl Into<String>, provider_type: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            provider_type: provider_type.into(),
+            auth_type: AuthType::default(),
+            api_key: None,
+            oauth_provider: None,
+            project_id: None,
+            location: None,
+            base_url: None,
+            headers: None,
+            region: None,
+            retry: None,
+            cache_ttl: None,
+            keep_warm: None,
+            models: Vec::new(),
+            enabled: None,
+        }
+    }
+
     pub fn is_enabled(&self) -> bool {
         self.enabled.unwrap_or(true)
     }
 
+    /// The configured API key (or its unresolved `$VAR` reference)
+    pub fn api_key(&self) -> Option<&str> {
+        self.api_key.as_ref().map(|key| key.expose_secret().as_str())
+    }
+
     /// Get the API key or OAuth provider ID
     #[allow(dead_code)]
     pub fn get_auth_credential(&self) -> Option<String> {
         match self.auth_type {
-            AuthType::ApiKey => self.api_key.clone(),
+            AuthType::ApiKey => self.api_key().map(String::from),
             AuthType::OAuth => self.oauth_provider.clone(),
         }
     }
@@ -141,3 +343,44 @@ impl ProviderConfig {
 pub use openai::OpenAIProvider;
 pub use anthropic_compatible::AnthropicCompatibleProvider;
 pub use registry::ProviderRegistry;
+pub use retry::RetryConfig;
+pub use capabilities::CapabilityCache;
+pub use health::HealthTracker;
+pub use health_check::HealthChecks;
+pub use quota::QuotaTracker;
+pub use quality::QualityTracker;
+pub use pricing::CostTracker;
+pub use budget::BudgetTracker;
+pub use slo::SloTracker;
+pub use warm_pool::WarmPool;
+pub use circuit_breaker::CircuitBreaker;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_extra_body() {
+        let mut body = json!({
+            "model": "m",
+            "temperature": 0.2,
+            "generationConfig": {"maxOutputTokens": 100, "temperature": 0.2}
+        });
+        let extra = json!({
+            "temperature": 0.7,
+            "provider": {"order": ["groq"], "allow_fallbacks": false},
+            "generationConfig": {"topK": 40}
+        });
+        merge_extra_body(&mut body, extra.as_object());
+        assert_eq!(
+            body,
+            json!({
+                "model": "m",
+                "temperature": 0.7,
+                "generationConfig": {"maxOutputTokens": 100, "temperature": 0.2, "topK": 40},
+                "provider": {"order": ["groq"], "allow_fallbacks": false}
+            })
+        );
+    }
+}
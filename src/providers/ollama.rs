@@ -0,0 +1,181 @@
+//! Ollama: local models through Ollama's OpenAI-compatible endpoint, with the
+//! installed models discovered from `/api/tags`. `keep_warm` uses the native API:
+//! a prompt-less `/api/generate` loads a model, `/api/ps` lists the loaded ones.
+
+use super::openai::OpenAIProvider;
+use super::{AnthropicProvider, ProviderError, ProviderResponse, StreamResponse};
+use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse};
+use async_trait::async_trait;
+use reqwest::Client;
+use std::sync::{Arc, RwLock};
+
+/// Where a local Ollama listens by default
+pub const DEFAULT_OLLAMA_BASE_URL: &str = "http://localhost:11434";
+
+/// Ollama ignores the key, but OpenAI clients have to send one
+const PLACEHOLDER_API_KEY: &str = "ollama";
+
+pub struct OllamaProvider {
+    pub name: String,
+    base_url: String,
+    models: Vec<String>,
+    /// Installed models from the last `/api/tags` call
+    installed: Arc<RwLock<Vec<String>>>,
+    client: Client,
+    /// Chat requests (and their SSE streams) go through the OpenAI-compatible API
+    openai: OpenAIProvider,
+}
+
+impl OllamaProvider {
+    /// `base_url` is the Ollama server root (default `http://localhost:11434`, or `OLLAMA_HOST`).
+    /// Installed models are discovered in the background when a runtime is available.
+    pub fn new(name: String, api_key: Option<String>, base_url: Option<String>, models: Vec<String>) -> Self {
+        let base_url = base_url
+            .or_else(|| std::env::var("OLLAMA_HOST").ok().filter(|host| !host.is_empty()).map(|host| host_url(&host)))
+            .unwrap_or_else(|| DEFAULT_OLLAMA_BASE_URL.to_string());
+        let base_url = base_url.trim_end_matches('/').trim_end_matches("/v1").to_string();
+        let api_key = api_key.filter(|key| !key.is_empty()).unwrap_or_else(|| PLACEHOLDER_API_KEY.to_string());
+
+        let provider = Self {
+            openai: OpenAIProvider::with_headers(
+                name.clone(),
+                api_key,
+                format!("{}/v1", base_url),
+                models.clone(),
+                vec![],
+                None,
+                None,
+            ),
+            name,
+            base_url,
+            models,
+            installed: Arc::new(RwLock::new(Vec::new())),
+            client: Client::new(),
+        };
+
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let (name, client, url, installed) =
+                (provider.name.clone(), provider.client.clone(), provider.tags_url(), provider.installed.clone());
+            handle.spawn(async move {
+                match fetch_tags(&client, &url).await {
+                    Ok(models) => {
+                        tracing::info!("🦙 {}: {} local model(s) installed: {}", name, models.len(), models.join(", "));
+                        *installed.write().unwrap() = models;
+                    }
+                    Err(e) => tracing::warn!("🦙 {}: couldn't list local models at {}: {}", name, url, e),
+                }
+            });
+        }
+        provider
+    }
+
+    fn tags_url(&self) -> String {
+        self.native_url("tags")
+    }
+
+    fn native_url(&self, path: &str) -> String {
+        format!("{}/api/{}", self.base_url, path)
+    }
+}
+
+/// `OLLAMA_HOST` may be a bare `host:port`
+fn host_url(host: &str) -> String {
+    if host.contains("://") {
+        host.to_string()
+    } else {
+        format!("http://{}", host)
+    }
+}
+
+/// Model names from an `/api/tags` response (`{"models": [{"name": "llama3.2:latest", ...}]}`)
+fn tag_names(body: &serde_json::Value) -> Vec<String> {
+    body["models"]
+        .as_array()
+        .map(|models| models.iter().filter_map(|m| m["name"].as_str().map(String::from)).collect())
+        .unwrap_or_default()
+}
+
+async fn fetch_tags(client: &Client, url: &str) -> Result<Vec<String>, ProviderError> {
+    Ok(tag_names(&json_body(client.get(url).send().await?).await?))
+}
+
+async fn json_body(response: reqwest::Response) -> Result<serde_json::Value, ProviderError> {
+    if !response.status().is_success() {
+        let status = response.status().as_u16();
+        let headers = response.headers().clone();
+        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+        return Err(ProviderError::from_response(status, &headers, error_text));
+    }
+    Ok(response.json().await?)
+}
+
+/// Ollama resolves a model without a tag to `:latest`
+fn same_model(a: &str, b: &str) -> bool {
+    let untagged = |name: &str| name.strip_suffix(":latest").unwrap_or(name).to_ascii_lowercase();
+    untagged(a) == untagged(b)
+}
+
+#[async_trait]
+impl AnthropicProvider for OllamaProvider {
+    async fn send_message(&self, request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+        self.openai.send_message(request).await
+    }
+
+    async fn send_message_stream(&self, request: AnthropicRequest) -> Result<StreamResponse, ProviderError> {
+        self.openai.send_message_stream(request).await
+    }
+
+    async fn count_tokens(&self, request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+        self.openai.count_tokens(request).await
+    }
+
+    fn supports_model(&self, model: &str) -> bool {
+        self.models.iter().any(|m| same_model(m, model))
+            || self.installed.read().unwrap().iter().any(|m| same_model(m, model))
+    }
+
+    /// Ollama only reads base64 data URLs from `image_url` parts
+    fn accepts_image_urls(&self) -> bool {
+        false
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<String>>, ProviderError> {
+        let models = fetch_tags(&self.client, &self.tags_url()).await?;
+        *self.installed.write().unwrap() = models.clone();
+        Ok(Some(models))
+    }
+
+    /// Load without generating anything, and keep the model loaded for `keep_alive`
+    async fn keep_warm(&self, model: &str, keep_alive: std::time::Duration) -> Result<(), ProviderError> {
+        let body = serde_json::json!({"model": model, "keep_alive": format!("{}s", keep_alive.as_secs())});
+        json_body(self.client.post(self.native_url("generate")).json(&body).send().await?).await.map(|_| ())
+    }
+
+    async fn is_loaded(&self, model: &str) -> Result<Option<bool>, ProviderError> {
+        let running = json_body(self.client.get(self.native_url("ps")).send().await?).await?;
+        Ok(Some(tag_names(&running).iter().any(|m| same_model(m, model))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tags_and_model_matching() {
+        let body = json!({"models": [
+            {"name": "qwen2.5-coder:7b", "model": "qwen2.5-coder:7b", "size": 4683087332u64},
+            {"name": "llama3.2:latest", "model": "llama3.2:latest"}
+        ]});
+        let provider = OllamaProvider::new("ollama".to_string(), None, Some("http://gpu-box:11434/v1/".to_string()), vec![]);
+        assert_eq!(provider.tags_url(), "http://gpu-box:11434/api/tags");
+        assert!(!provider.supports_model("llama3.2"));
+
+        *provider.installed.write().unwrap() = tag_names(&body);
+        assert!(provider.supports_model("llama3.2"));
+        assert!(provider.supports_model("qwen2.5-coder:7b"));
+        assert!(!provider.supports_model("qwen2.5-coder"));
+        assert_eq!(host_url("0.0.0.0:11434"), "http://0.0.0.0:11434");
+    }
+}
@@ -1,4 +1,5 @@
-use super::{AnthropicProvider, ProviderResponse, StreamResponse, ContentBlock, KnownContentBlock, Usage, error::ProviderError};
+use super::{merge_extra_body, AnthropicProvider, ProviderResponse, StreamResponse, ContentBlock, KnownContentBlock, Usage, error::ProviderError};
+use super::timeout;
 use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse, MessageContent};
 use crate::auth::{OAuthClient, OAuthConfig, TokenStore};
 use async_trait::async_trait;
@@ -6,6 +7,8 @@ use serde::{Deserialize, Serialize};
 use reqwest::Client;
 use std::collections::HashMap;
 use bytes::Bytes;
+use futures::Stream;
+use std::pin::Pin;
 use base64::{Engine as _, engine::general_purpose};
 use secrecy::ExposeSecret;
 
@@ -40,6 +43,9 @@ struct OpenAIRequest {
     tools: Option<Vec<OpenAITool>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tool_choice: Option<serde_json::Value>,
+    /// DeepSeek thinking mode (`{"type": "enabled"}`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<serde_json::Value>,
 }
 
 /// OpenAI Responses API request format (for Codex models)
@@ -136,6 +142,9 @@ struct OpenAIMessage {
     content: Option<OpenAIContent>,
     #[serde(skip_serializing_if = "Option::is_none")]
     reasoning: Option<String>,
+    /// DeepSeek's name for `reasoning`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reasoning_content: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tool_calls: Option<Vec<OpenAIToolCall>>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -166,6 +175,25 @@ struct OpenAIUsage {
     completion_tokens: u32,
     #[serde(default)]
     total_tokens: u32,
+    #[serde(default)]
+    prompt_tokens_details: Option<OpenAITokenDetails>,
+    /// DeepSeek reports cache hits separately
+    #[serde(default)]
+    prompt_cache_hit_tokens: Option<u32>,
+}
+
+impl OpenAIUsage {
+    fn to_usage(&self) -> Usage {
+        let cached = self.prompt_tokens_details.as_ref().and_then(|d| d.cached_tokens).or(self.prompt_cache_hit_tokens);
+        Usage::from_prompt_total(self.prompt_tokens, self.completion_tokens, cached)
+    }
+}
+
+/// Breakdown of prompt tokens (`prompt_tokens_details` / `input_tokens_details`)
+#[derive(Debug, Deserialize)]
+struct OpenAITokenDetails {
+    #[serde(default)]
+    cached_tokens: Option<u32>,
 }
 
 /// OpenAI Responses API response format (for Codex models)
@@ -201,6 +229,8 @@ struct ResponsesContentBlock {
 struct ResponsesUsage {
     input_tokens: u32,
     output_tokens: u32,
+    #[serde(default)]
+    input_tokens_details: Option<OpenAITokenDetails>,
 }
 
 /// OpenAI Streaming Chunk (for SSE transformation)
@@ -226,6 +256,17 @@ struct OpenAIStreamUsage {
     completion_tokens: u32,
     #[serde(default)]
     total_tokens: u32,
+    #[serde(default)]
+    prompt_tokens_details: Option<OpenAITokenDetails>,
+    #[serde(default)]
+    prompt_cache_hit_tokens: Option<u32>,
+}
+
+impl OpenAIStreamUsage {
+    fn to_usage(&self) -> Usage {
+        let cached = self.prompt_tokens_details.as_ref().and_then(|d| d.cached_tokens).or(self.prompt_cache_hit_tokens);
+        Usage::from_prompt_total(self.prompt_tokens, self.completion_tokens, cached)
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -246,6 +287,8 @@ struct OpenAIStreamDelta {
     #[serde(default)]
     reasoning: Option<String>, // For GLM/Cerebras models
     #[serde(default)]
+    reasoning_content: Option<String>, // For DeepSeek
+    #[serde(default)]
     role: Option<String>,
     #[serde(default)]
     tool_calls: Option<Vec<serde_json::Value>>,
@@ -286,12 +329,324 @@ struct StreamTransformState {
     /// Tool call indices that have had content_block_start emitted
     /// Maps OpenAI tool_call index → Anthropic content_block index
     tool_blocks: std::collections::HashMap<u32, u32>,
+    /// Tool blocks (by content block index) that have received argument bytes
+    tool_args_seen: std::collections::HashSet<u32>,
     /// Next available content block index
     next_block_index: u32,
     /// Has finish_reason been received?
     stream_ended: bool,
     /// Did this response include any tool calls? (for correct stop_reason)
     had_tool_calls: bool,
+    /// stop_reason from finish_reason, held until the usage-only chunk that
+    /// follows it with `stream_options.include_usage`
+    pending_stop_reason: Option<&'static str>,
+}
+
+impl StreamTransformState {
+    /// Tool block indices in block order (so closing events are deterministic)
+    fn tool_block_indices(&self) -> Vec<u32> {
+        let mut indices: Vec<u32> = self.tool_blocks.values().copied().collect();
+        indices.sort_unstable();
+        indices
+    }
+
+    /// content_block_stop for every open tool block. A call that ended without any
+    /// argument bytes gets `{}` first, since clients can't parse empty input.
+    fn close_tool_blocks(&mut self) -> String {
+        let mut output = String::new();
+        for block_index in self.tool_block_indices() {
+            if !self.tool_args_seen.contains(&block_index) {
+                tracing::debug!("🔧 Tool block {} ended without arguments, sending {{}}", block_index);
+                output.push_str(&sse_event("content_block_delta", &empty_input_delta(block_index)));
+            }
+            output.push_str(&sse_event("content_block_stop", &serde_json::json!({
+                "type": "content_block_stop",
+                "index": block_index
+            })));
+        }
+        self.tool_blocks.clear();
+        self.tool_args_seen.clear();
+        output
+    }
+
+    /// message_delta + message_stop for the pending stop_reason
+    fn end_message(&mut self, usage: Option<&OpenAIStreamUsage>) -> String {
+        let stop_reason = self.pending_stop_reason.take().unwrap_or("end_turn");
+        self.stream_ended = true;
+        let usage = usage.map(|u| u.to_usage()).unwrap_or_else(|| Usage::from_prompt_total(0, 0, None));
+        let mut message_delta = serde_json::json!({
+            "type": "message_delta",
+            "delta": {
+                "stop_reason": stop_reason,
+                "stop_sequence": null
+            },
+            "usage": {
+                "input_tokens": usage.input_tokens,
+                "output_tokens": usage.output_tokens
+            }
+        });
+        if let Some(cached) = usage.cache_read_input_tokens {
+            message_delta["usage"]["cache_read_input_tokens"] = serde_json::json!(cached);
+        }
+        let message_stop = serde_json::json!({
+            "type": "message_stop"
+        });
+        tracing::debug!("✅ Sent message_stop event, stream_ended=true, output_tokens={}", usage.output_tokens);
+        format!(
+            "event: message_delta\ndata: {}\n\nevent: message_stop\ndata: {}\n\n",
+            message_delta, message_stop
+        )
+    }
+}
+
+/// State for Responses API → Anthropic SSE transformation
+///
+/// Responses events address output items by `output_index`; each item becomes one
+/// Anthropic content block (reasoning → thinking, message → text, function_call → tool_use),
+/// opened on its first delta and closed by `response.output_item.done`.
+#[derive(Debug, Default)]
+struct ResponsesStreamState {
+    message_id: String,
+    model: String,
+    /// Upstream response ID, for cancellation
+    response_id: Option<String>,
+    message_started: bool,
+    /// Open blocks: output_index → Anthropic content block index
+    open_blocks: std::collections::BTreeMap<u64, u32>,
+    /// Open tool_use blocks that haven't received argument bytes yet
+    tool_blocks_without_args: std::collections::HashSet<u32>,
+    next_block_index: u32,
+    had_tool_calls: bool,
+    stream_ended: bool,
+}
+
+fn sse_event(name: &str, data: &serde_json::Value) -> String {
+    format!("event: {}\ndata: {}\n\n", name, data)
+}
+
+/// `{}` as the whole input of a tool_use block whose call had no arguments
+fn empty_input_delta(index: u32) -> serde_json::Value {
+    serde_json::json!({
+        "type": "content_block_delta",
+        "index": index,
+        "delta": {"type": "input_json_delta", "partial_json": "{}"}
+    })
+}
+
+impl ResponsesStreamState {
+    fn new(message_id: String, model: String) -> Self {
+        Self { message_id, model, ..Default::default() }
+    }
+
+    fn start_message(&mut self, output: &mut String) {
+        if self.message_started {
+            return;
+        }
+        self.message_started = true;
+        output.push_str(&sse_event("message_start", &serde_json::json!({
+            "type": "message_start",
+            "message": {
+                "id": self.message_id,
+                "type": "message",
+                "role": "assistant",
+                "content": [],
+                "model": self.model,
+                "stop_reason": null,
+                "stop_sequence": null,
+                "usage": {"input_tokens": 0, "output_tokens": 0}
+            }
+        })));
+    }
+
+    /// Block index for an output item, starting the block if needed
+    fn block(&mut self, output_index: u64, content_block: serde_json::Value, output: &mut String) -> u32 {
+        if let Some(index) = self.open_blocks.get(&output_index) {
+            return *index;
+        }
+        let index = self.next_block_index;
+        self.next_block_index += 1;
+        self.open_blocks.insert(output_index, index);
+        output.push_str(&sse_event("content_block_start", &serde_json::json!({
+            "type": "content_block_start",
+            "index": index,
+            "content_block": content_block
+        })));
+        index
+    }
+
+    fn close_block(&mut self, output_index: u64, output: &mut String) {
+        if let Some(index) = self.open_blocks.remove(&output_index) {
+            if self.tool_blocks_without_args.remove(&index) {
+                output.push_str(&sse_event("content_block_delta", &empty_input_delta(index)));
+            }
+            output.push_str(&sse_event("content_block_stop", &serde_json::json!({
+                "type": "content_block_stop",
+                "index": index
+            })));
+        }
+    }
+
+    fn delta(&mut self, output_index: u64, content_block: serde_json::Value, delta: serde_json::Value, output: &mut String) {
+        let index = self.block(output_index, content_block, output);
+        output.push_str(&sse_event("content_block_delta", &serde_json::json!({
+            "type": "content_block_delta",
+            "index": index,
+            "delta": delta
+        })));
+    }
+
+    /// Close open blocks and end the message
+    fn finish(&mut self, stop_reason: &str, usage: Usage) -> String {
+        let mut output = String::new();
+        self.start_message(&mut output);
+        for output_index in self.open_blocks.keys().copied().collect::<Vec<_>>() {
+            self.close_block(output_index, &mut output);
+        }
+        let stop_reason = if self.had_tool_calls { "tool_use" } else { stop_reason };
+        let mut message_delta = serde_json::json!({
+            "type": "message_delta",
+            "delta": {"stop_reason": stop_reason, "stop_sequence": null},
+            "usage": {"input_tokens": usage.input_tokens, "output_tokens": usage.output_tokens}
+        });
+        if let Some(cached) = usage.cache_read_input_tokens {
+            message_delta["usage"]["cache_read_input_tokens"] = serde_json::json!(cached);
+        }
+        output.push_str(&sse_event("message_delta", &message_delta));
+        output.push_str(&sse_event("message_stop", &serde_json::json!({"type": "message_stop"})));
+        self.stream_ended = true;
+        output
+    }
+
+    fn transform_event(&mut self, event: &serde_json::Value) -> Result<String, ProviderError> {
+        let mut output = String::new();
+        let output_index = event["output_index"].as_u64().unwrap_or(0);
+        let text = |field: &str| event[field].as_str().unwrap_or_default().to_string();
+
+        match event["type"].as_str().unwrap_or_default() {
+            "response.created" => {
+                if let Some(model) = event["response"]["model"].as_str() {
+                    self.model = model.to_string();
+                }
+                self.response_id = event["response"]["id"].as_str().map(String::from);
+                self.start_message(&mut output);
+            }
+            "response.output_item.added" if event["item"]["type"] == "function_call" => {
+                self.start_message(&mut output);
+                self.had_tool_calls = true;
+                let item = &event["item"];
+                let id = item["call_id"].as_str().or(item["id"].as_str()).unwrap_or("call_0");
+                let name = item["name"].as_str().unwrap_or("unknown");
+                let index = self.block(output_index, serde_json::json!({"type": "tool_use", "id": id, "name": name, "input": {}}), &mut output);
+                self.tool_blocks_without_args.insert(index);
+            }
+            "response.output_text.delta" => {
+                self.start_message(&mut output);
+                self.delta(
+                    output_index,
+                    serde_json::json!({"type": "text", "text": ""}),
+                    serde_json::json!({"type": "text_delta", "text": text("delta")}),
+                    &mut output,
+                );
+            }
+            "response.reasoning_summary_text.delta" | "response.reasoning_text.delta" => {
+                self.start_message(&mut output);
+                self.delta(
+                    output_index,
+                    serde_json::json!({"type": "thinking", "thinking": ""}),
+                    serde_json::json!({"type": "thinking_delta", "thinking": text("delta")}),
+                    &mut output,
+                );
+            }
+            "response.function_call_arguments.delta" => {
+                self.start_message(&mut output);
+                if let Some(index) = self.open_blocks.get(&output_index).filter(|_| !text("delta").is_empty()) {
+                    self.tool_blocks_without_args.remove(index);
+                }
+                self.delta(
+                    output_index,
+                    serde_json::json!({"type": "tool_use", "id": "call_0", "name": "unknown", "input": {}}),
+                    serde_json::json!({"type": "input_json_delta", "partial_json": text("delta")}),
+                    &mut output,
+                );
+            }
+            "response.output_item.done" => self.close_block(output_index, &mut output),
+            "response.completed" | "response.incomplete" => {
+                let response = &event["response"];
+                let stop_reason = match response["incomplete_details"]["reason"].as_str() {
+                    Some("max_output_tokens") => "max_tokens",
+                    Some("content_filter") => "refusal",
+                    _ => "end_turn",
+                };
+                let usage = &response["usage"];
+                let usage = Usage::from_prompt_total(
+                    usage["input_tokens"].as_u64().unwrap_or(0) as u32,
+                    usage["output_tokens"].as_u64().unwrap_or(0) as u32,
+                    usage["input_tokens_details"]["cached_tokens"].as_u64().map(|n| n as u32),
+                );
+                output.push_str(&self.finish(stop_reason, usage));
+            }
+            "response.failed" | "error" => {
+                self.stream_ended = true;
+                let error = if event["type"] == "error" { event } else { &event["response"]["error"] };
+                let message = error["message"].as_str().unwrap_or("unknown error");
+                let status = match error["code"].as_str() {
+                    Some("rate_limit_exceeded") => 429,
+                    Some("server_is_overloaded") => 529,
+                    Some("context_length_exceeded" | "invalid_prompt") => 400,
+                    _ => 500,
+                };
+                return Err(ProviderError::from_status(status, None, message.to_string()));
+            }
+            _ => {}
+        }
+        Ok(output)
+    }
+}
+
+/// How to cancel a Responses API generation: the endpoint it was created at and
+/// the headers it was created with
+pub(super) struct ResponsesCancel {
+    client: Client,
+    url: String,
+    headers: reqwest::header::HeaderMap,
+}
+
+/// Held by a Responses stream; when the stream is dropped before the response
+/// finished (the client aborted), asks the backend to cancel the generation so
+/// it stops counting against usage limits
+struct ResponsesCancelGuard {
+    cancel: ResponsesCancel,
+    state: std::sync::Arc<std::sync::Mutex<ResponsesStreamState>>,
+    provider_name: String,
+}
+
+impl Drop for ResponsesCancelGuard {
+    fn drop(&mut self) {
+        let response_id = match self.state.lock() {
+            Ok(state) if !state.stream_ended => state.response_id.clone(),
+            _ => None,
+        };
+        let (Some(response_id), Ok(handle)) = (response_id, tokio::runtime::Handle::try_current()) else {
+            return;
+        };
+
+        tracing::info!("🛑 {} stream dropped before completion, cancelling response {}", self.provider_name, response_id);
+        let request = self.cancel.client
+            .post(format!("{}/{}/cancel", self.cancel.url, response_id))
+            .headers(std::mem::take(&mut self.cancel.headers));
+        let provider_name = std::mem::take(&mut self.provider_name);
+        handle.spawn(async move {
+            match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    tracing::debug!("🛑 {} cancelled response {}", provider_name, response_id);
+                }
+                Ok(response) => {
+                    tracing::debug!("🛑 {} couldn't cancel response {}: HTTP {}", provider_name, response_id, response.status());
+                }
+                Err(e) => tracing::debug!("🛑 {} couldn't cancel response {}: {}", provider_name, response_id, e),
+            }
+        });
+    }
 }
 
 /// OpenAI provider implementation
@@ -306,6 +661,29 @@ pub struct OpenAIProvider {
     oauth_provider: Option<String>,
     /// Token store for OAuth authentication
     token_store: Option<TokenStore>,
+    /// Rewrite tool call IDs to the 9-character alphanumeric form Mistral requires
+    short_tool_call_ids: bool,
+    /// DeepSeek thinking: request it with `thinking`, send `reasoning_content` back
+    reasoning_content: bool,
+}
+
+/// Length of a Mistral tool call ID
+const SHORT_TOOL_CALL_ID_LEN: usize = 9;
+
+/// Mistral accepts only `[a-zA-Z0-9]{9}` tool call IDs. Other IDs (e.g. Anthropic's
+/// `toolu_...`) are replaced by a hash of themselves, so a tool call and its result
+/// still match across turns; IDs already in that form are kept.
+fn short_tool_call_id(id: &str) -> String {
+    if id.len() == SHORT_TOOL_CALL_ID_LEN && id.bytes().all(|b| b.is_ascii_alphanumeric()) {
+        return id.to_string();
+    }
+    const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+    use sha2::Digest;
+    sha2::Sha256::digest(id.as_bytes())
+        .iter()
+        .take(SHORT_TOOL_CALL_ID_LEN)
+        .map(|b| ALPHABET[*b as usize % ALPHABET.len()] as char)
+        .collect()
 }
 
 impl OpenAIProvider {
@@ -324,8 +702,8 @@ impl OpenAIProvider {
                 // Next line should be data: {...}
                 if i + 1 < lines.len() {
                     let data_line = lines[i + 1];
-                    if data_line.starts_with("data: ") {
-                        let json_str = &data_line[6..];  // Skip "data: "
+                    if let Some(json_str) = data_line.strip_prefix("data: ") {
+                        // Skip "data: "
                         if let Ok(json) = serde_json::from_str::<serde_json::Value>(json_str) {
                             // Extract both reasoning and message from response.output array
                             // Note: Codex models have reasoning at output[0], message at output[1]
@@ -451,6 +829,53 @@ impl OpenAIProvider {
             custom_headers,
             oauth_provider,
             token_store,
+            short_tool_call_ids: false,
+            reasoning_content: false,
+        }
+    }
+
+    /// Mistral AI (`https://api.mistral.ai/v1` unless `base_url` is set)
+    pub fn mistral(
+        name: String,
+        api_key: String,
+        base_url: Option<String>,
+        models: Vec<String>,
+        custom_headers: Vec<(String, String)>,
+    ) -> Self {
+        Self {
+            short_tool_call_ids: true,
+            ..Self::with_headers(
+                name,
+                api_key,
+                base_url.unwrap_or_else(|| "https://api.mistral.ai/v1".to_string()),
+                models,
+                custom_headers,
+                None,
+                None,
+            )
+        }
+    }
+
+    /// DeepSeek (`https://api.deepseek.com` unless `base_url` is set), with Anthropic
+    /// thinking mapped to DeepSeek's thinking mode and `reasoning_content`
+    pub fn deepseek(
+        name: String,
+        api_key: String,
+        base_url: Option<String>,
+        models: Vec<String>,
+        custom_headers: Vec<(String, String)>,
+    ) -> Self {
+        Self {
+            reasoning_content: true,
+            ..Self::with_headers(
+                name,
+                api_key,
+                base_url.unwrap_or_else(|| "https://api.deepseek.com".to_string()),
+                models,
+                custom_headers,
+                None,
+                None,
+            )
         }
     }
 
@@ -563,13 +988,23 @@ impl OpenAIProvider {
                 role: "system".to_string(),
                 content: Some(OpenAIContent::String(system_text)),
                 reasoning: None,
+                reasoning_content: None,
                 tool_calls: None,
                 tool_call_id: None,
             });
         }
 
+        // The current turn starts at the last user message that isn't only tool results
+        let turn_start = request.messages.iter()
+            .rposition(|msg| msg.role == "user" && match &msg.content {
+                MessageContent::Text(_) => true,
+                MessageContent::Blocks(blocks) => blocks.iter()
+                    .any(|block| !matches!(block, ContentBlock::Known(KnownContentBlock::ToolResult { .. }))),
+            })
+            .unwrap_or(0);
+
         // Transform messages
-        for msg in &request.messages {
+        for (i, msg) in request.messages.iter().enumerate() {
             match &msg.content {
                 MessageContent::Text(text) => {
                     // Simple text message
@@ -577,6 +1012,7 @@ impl OpenAIProvider {
                         role: msg.role.clone(),
                         content: Some(OpenAIContent::String(text.clone())),
                         reasoning: None,
+                        reasoning_content: None,
                         tool_calls: None,
                         tool_call_id: None,
                     });
@@ -589,7 +1025,7 @@ impl OpenAIProvider {
                                 let result_content = if *is_error {
                                     // Prefix error content so models know not to retry
                                     tracing::debug!("🚨 Tool result is_error=true for {}, prefixing content", tool_use_id);
-                                    format!("[SYSTEM: Tools are disabled during warmup. Do NOT call any tools. Wait for the next user message before attempting any tool use.]\n{}", content.to_string())
+                                    format!("[SYSTEM: Tools are disabled during warmup. Do NOT call any tools. Wait for the next user message before attempting any tool use.]\n{}", content)
                                 } else {
                                     content.to_string()
                                 };
@@ -631,11 +1067,9 @@ impl OpenAIProvider {
                                 // Convert Anthropic image format to OpenAI format
                                 let url = if source.r#type == "base64" {
                                     // data:image/{media_type};base64,{data}
-                                    let media_type = source.media_type.as_ref()
-                                        .map(|s| s.as_str())
+                                    let media_type = source.media_type.as_deref()
                                         .unwrap_or("image/png");
-                                    let data = source.data.as_ref()
-                                        .map(|s| s.as_str())
+                                    let data = source.data.as_deref()
                                         .unwrap_or("");
                                     format!("data:{};base64,{}", media_type, data)
                                 } else if let Some(url) = &source.url {
@@ -684,11 +1118,26 @@ impl OpenAIProvider {
                             role: "tool".to_string(),
                             content: Some(OpenAIContent::String(result_content)),
                             reasoning: None,
+                            reasoning_content: None,
                             tool_calls: None,
                             tool_call_id: Some(tool_use_id),
                         });
                     }
 
+                    // DeepSeek needs the reasoning behind tool calls made earlier in the
+                    // current turn sent back; reasoning from earlier turns is left out
+                    let reasoning_content = if self.reasoning_content && msg.role == "assistant" && i > turn_start {
+                        let thinking: Vec<&str> = blocks.iter()
+                            .filter_map(|block| match block {
+                                ContentBlock::Known(KnownContentBlock::Thinking { raw }) => raw["thinking"].as_str(),
+                                _ => None,
+                            })
+                            .collect();
+                        (!thinking.is_empty()).then(|| thinking.join("\n"))
+                    } else {
+                        None
+                    };
+
                     // Then add main message with content and/or tool_calls
                     if !content_parts.is_empty() || !tool_calls.is_empty() {
                         let content = if content_parts.is_empty() {
@@ -708,6 +1157,7 @@ impl OpenAIProvider {
                             role: msg.role.clone(),
                             content,
                             reasoning: None,
+                            reasoning_content,
                             tool_calls: if tool_calls.is_empty() { None } else { Some(tool_calls) },
                             tool_call_id: None,
                         });
@@ -740,6 +1190,17 @@ impl OpenAIProvider {
             None
         };
 
+        if self.short_tool_call_ids {
+            for message in &mut openai_messages {
+                for tool_call in message.tool_calls.iter_mut().flatten() {
+                    tool_call.id = short_tool_call_id(&tool_call.id);
+                }
+                if let Some(id) = &mut message.tool_call_id {
+                    *id = short_tool_call_id(id);
+                }
+            }
+        }
+
         Ok(OpenAIRequest {
             model: request.model.clone(),
             messages: openai_messages,
@@ -751,6 +1212,11 @@ impl OpenAIProvider {
             stream_options,
             tools,
             tool_choice: None, // TODO: Add tool_choice support if needed
+            thinking: if self.reasoning_content && request.thinking.as_ref().is_some_and(|t| t.r#type == "enabled") {
+                Some(serde_json::json!({"type": "enabled"}))
+            } else {
+                None
+            },
         })
     }
 
@@ -771,7 +1237,7 @@ impl OpenAIProvider {
         let mut content_blocks = Vec::new();
 
         // Add reasoning as thinking block (unsigned — no signature field).
-        if let Some(reasoning) = choice.message.reasoning {
+        if let Some(reasoning) = choice.message.reasoning.or(choice.message.reasoning_content) {
             if !reasoning.is_empty() {
                 content_blocks.push(ContentBlock::thinking(serde_json::json!({
                     "thinking": reasoning
@@ -847,12 +1313,8 @@ impl OpenAIProvider {
             model: response.model,
             stop_reason,
             stop_sequence: None,
-            usage: Usage {
-                input_tokens: response.usage.prompt_tokens,
-                output_tokens: response.usage.completion_tokens,
-                cache_creation_input_tokens: None,
-                cache_read_input_tokens: None,
-            },
+            usage: response.usage.to_usage(),
+            rate_limits: HashMap::new(),
         }
     }
 
@@ -879,12 +1341,12 @@ impl OpenAIProvider {
             model: response.model,
             stop_reason: Some("end_turn".to_string()),
             stop_sequence: None,
-            usage: Usage {
-                input_tokens: response.usage.input_tokens,
-                output_tokens: response.usage.output_tokens,
-                cache_creation_input_tokens: None,
-                cache_read_input_tokens: None,
-            },
+            usage: Usage::from_prompt_total(
+                response.usage.input_tokens,
+                response.usage.output_tokens,
+                response.usage.input_tokens_details.as_ref().and_then(|d| d.cached_tokens),
+            ),
+            rate_limits: HashMap::new(),
         }
     }
 
@@ -939,10 +1401,18 @@ impl OpenAIProvider {
             output.push_str(&format!("event: message_start\ndata: {}\n\n", message_start));
         }
 
+        // Usage-only chunk after finish_reason ends the message
+        if state.pending_stop_reason.is_some() {
+            if chunk.usage.is_some() {
+                output.push_str(&state.end_message(chunk.usage.as_ref()));
+            }
+            return output;
+        }
+
         // Process delta content
         for choice in &chunk.choices {
             // Handle reasoning content as thinking blocks (separate from text content)
-            if let Some(reasoning) = choice.delta.reasoning.as_ref() {
+            if let Some(reasoning) = choice.delta.reasoning.as_ref().or(choice.delta.reasoning_content.as_ref()) {
                 if !reasoning.is_empty() {
                     // Emit thinking block start if not already open
                     if !state.thinking_block_open {
@@ -1104,6 +1574,7 @@ impl OpenAIProvider {
                                     state.next_block_index += 1;
                                     idx
                                 });
+                            state.tool_args_seen.insert(block_index);
 
                             let input_delta = serde_json::json!({
                                 "type": "content_block_delta",
@@ -1126,11 +1597,9 @@ impl OpenAIProvider {
             //   1. content_block_stop (for thinking block if open)
             //   2. content_block_stop (for text block if open)
             //   3. content_block_stop (for each open tool block)
-            //   4. message_delta (with stop_reason mapped from finish_reason)
+            //   4. message_delta (with stop_reason mapped from finish_reason, once usage is known)
             //   5. message_stop (signals end of message)
             if let Some(reason) = &choice.finish_reason {
-                state.stream_ended = true;
-
                 // Close thinking block if still open
                 if state.thinking_block_open {
                     let block_stop = serde_json::json!({
@@ -1138,6 +1607,7 @@ impl OpenAIProvider {
                         "index": state.thinking_block_index
                     });
                     output.push_str(&format!("event: content_block_stop\ndata: {}\n\n", block_stop));
+                    state.thinking_block_open = false;
                 }
 
                 // Close text block if still open
@@ -1147,18 +1617,12 @@ impl OpenAIProvider {
                         "index": state.text_block_index
                     });
                     output.push_str(&format!("event: content_block_stop\ndata: {}\n\n", block_stop));
+                    state.text_block_open = false;
                 }
 
                 // Close all open tool blocks
-                for (_, block_index) in &state.tool_blocks {
-                    let block_stop = serde_json::json!({
-                        "type": "content_block_stop",
-                        "index": block_index
-                    });
-                    output.push_str(&format!("event: content_block_stop\ndata: {}\n\n", block_stop));
-                }
+                output.push_str(&state.close_tool_blocks());
 
-                // Emit message_delta with stop reason
                 // Mapping: OpenAI finish_reason → Anthropic stop_reason
                 // IMPORTANT: If this response included any tool calls, force stop_reason="tool_use"
                 // even if provider sent finish_reason="stop" (some providers do this incorrectly)
@@ -1175,30 +1639,14 @@ impl OpenAIProvider {
                         _ => "end_turn"
                     }
                 };
-                // Extract token counts from usage if available (requires stream_options.include_usage)
-                let (input_tokens, output_tokens) = chunk.usage.as_ref()
-                    .map(|u| (u.prompt_tokens, u.completion_tokens))
-                    .unwrap_or((0, 0));
-                let message_delta = serde_json::json!({
-                    "type": "message_delta",
-                    "delta": {
-                        "stop_reason": stop_reason,
-                        "stop_sequence": null
-                    },
-                    "usage": {
-                        "input_tokens": input_tokens,
-                        "output_tokens": output_tokens
-                    }
-                });
-                output.push_str(&format!("event: message_delta\ndata: {}\n\n", message_delta));
+                state.pending_stop_reason = Some(stop_reason);
 
-                // Emit message_stop
-                let message_stop = serde_json::json!({
-                    "type": "message_stop"
-                });
-                output.push_str(&format!("event: message_stop\ndata: {}\n\n", message_stop));
-                tracing::debug!("✅ Sent message_stop event, stream_ended=true, output_tokens={}", output_tokens);
-                tracing::debug!("📤 Termination sequence:\n{}", output);
+                // Usage may come with finish_reason (Cerebras, DeepSeek) or in a following
+                // usage-only chunk (OpenAI with include_usage), in which case we wait for it
+                if chunk.usage.is_some() {
+                    output.push_str(&state.end_message(chunk.usage.as_ref()));
+                    tracing::debug!("📤 Termination sequence:\n{}", output);
+                }
             }
         }
 
@@ -1209,89 +1657,307 @@ impl OpenAIProvider {
 
         output
     }
-}
-
-#[async_trait]
-impl AnthropicProvider for OpenAIProvider {
-    async fn send_message(&self, request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
-        // Get authentication token (API key or OAuth)
-        let auth_value = self.get_auth_header().await?;
-
-        // Determine base URL: OAuth uses ChatGPT backend, API key uses configured base_url
-        let base_url = if self.is_oauth() {
-            "https://chatgpt.com/backend-api"
-        } else {
-            &self.base_url
-        };
 
-        // Check if we should use Responses API endpoint:
-        // - OAuth: Always use /codex/responses for all models
-        // - API Key: Only use /responses for models containing "codex"
-        let use_responses_api = if self.is_oauth() {
-            true  // OAuth always uses Codex endpoint
-        } else {
-            Self::is_codex_model(&request.model)  // API Key only for codex models
-        };
+    /// Translate a Responses API SSE byte stream to Anthropic SSE. With `cancel`, an
+    /// unfinished response is cancelled upstream when the stream is dropped.
+    pub(super) fn transform_responses_stream<S>(
+        bytes: S,
+        provider_name: String,
+        model: String,
+        cancel: Option<ResponsesCancel>,
+    ) -> impl Stream<Item = Result<Bytes, ProviderError>> + Send
+    where
+        S: Stream<Item = Result<Bytes, reqwest::Error>> + Send + 'static,
+    {
+        use futures::stream::{StreamExt, TryStreamExt};
+        use crate::providers::streaming::SseStream;
+        use std::sync::{Arc, Mutex};
 
-        if use_responses_api {
-            // Use /v1/responses endpoint for Codex models
-            let responses_request = self.transform_to_responses_request(&request)?;
+        let message_id = format!("msg_{}", uuid::Uuid::new_v4());
+        let state = Arc::new(Mutex::new(ResponsesStreamState::new(message_id, model)));
+        let state_for_cleanup = state.clone();
+        let cancel_guard = cancel.map(|cancel| ResponsesCancelGuard {
+            cancel,
+            state: state.clone(),
+            provider_name: provider_name.clone(),
+        });
 
-            // OAuth (ChatGPT Codex) uses /codex/responses, API Key uses /responses
-            let endpoint = if self.is_oauth() {
-                "/codex/responses"
-            } else {
-                "/responses"
-            };
-            let url = format!("{}{}", base_url, endpoint);
+        SseStream::new(bytes)
+            .map(move |result| {
+                // Owned by the stream, so it drops when the client goes away
+                let _ = &cancel_guard;
+                let sse_event = result.map_err(ProviderError::HttpError)?;
+                let mut state = state.lock().unwrap();
+                if state.stream_ended || sse_event.data.trim().is_empty() || sse_event.data.trim() == "[DONE]" {
+                    return Ok(Bytes::new());
+                }
+                match serde_json::from_str::<serde_json::Value>(&sse_event.data) {
+                    Ok(event) => state.transform_event(&event).map(Bytes::from).map_err(|e| {
+                        tracing::error!("❌ {} upstream error: {}", provider_name, e);
+                        e
+                    }),
+                    Err(e) => {
+                        tracing::warn!("❌ {} failed to parse event: {} - Data: {}", provider_name, e, sse_event.data);
+                        Ok(Bytes::new())
+                    }
+                }
+            })
+            // Close the message if the stream ends without response.completed
+            .chain(futures::stream::once(async move {
+                let mut state = state_for_cleanup.lock().unwrap();
+                if state.message_started && !state.stream_ended {
+                    tracing::warn!("⚠️ Responses stream ended without response.completed - sending end events");
+                    return Ok(Bytes::from(state.finish("end_turn", Usage::from_prompt_total(0, 0, None))));
+                }
+                Ok(Bytes::new())
+            }))
+            .try_filter(|bytes| futures::future::ready(!bytes.is_empty()))
+    }
 
-            tracing::debug!("Using {} endpoint for Codex model: {}", endpoint, request.model);
+    /// Translate a Chat Completions SSE byte stream to Anthropic SSE, closing the
+    /// message if the stream ends without a finish_reason
+    pub(super) fn transform_chat_stream<S>(bytes: S, provider_name: String) -> impl Stream<Item = Result<Bytes, ProviderError>> + Send
+    where
+        S: Stream<Item = Result<Bytes, reqwest::Error>> + Send + 'static,
+    {
+        use futures::stream::{StreamExt, TryStreamExt};
+        use crate::providers::streaming::SseStream;
+        use std::sync::{Arc, Mutex};
 
-            let mut req_builder = self.client
-                .post(&url)
-                .header("Authorization", format!("Bearer {}", auth_value))
-                .header("Content-Type", "application/json")
-                .header("accept", "text/event-stream");
+        let message_id = format!("msg_{}", uuid::Uuid::new_v4());
 
-            // For OAuth (ChatGPT Codex), add Codex-specific headers
-            if self.is_oauth() {
-                if let Some(account_id) = Self::extract_account_id(&auth_value) {
-                    req_builder = req_builder
-                        .header("chatgpt-account-id", account_id)
-                        .header("OpenAI-Beta", "responses=experimental")
-                        .header("originator", "codex_cli_rs")
-                        // Browser-like headers to avoid Cloudflare bot detection
-                        .header("User-Agent", "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36")
-                        .header("Origin", "https://chatgpt.com")
-                        .header("Referer", "https://chatgpt.com/")
-                        .header("sec-ch-ua", "\"Google Chrome\";v=\"131\", \"Chromium\";v=\"131\", \"Not_A Brand\";v=\"24\"")
-                        .header("sec-ch-ua-mobile", "?0")
-                        .header("sec-ch-ua-platform", "\"macOS\"")
-                        .header("sec-fetch-dest", "empty")
-                        .header("sec-fetch-mode", "cors")
-                        .header("sec-fetch-site", "same-origin");
-                    tracing::debug!("🔐 Using OAuth Bearer token for ChatGPT Codex on {}", self.name);
-                }
-            }
+        // Streaming State Management
+        // ===========================
+        // Using Arc<Mutex<StreamTransformState>> to track state across async chunks.
+        // The state tracks: message_started, text_block_open, tool_blocks, stream_ended
+        let state = Arc::new(Mutex::new(StreamTransformState::default()));
+        let state_for_cleanup = state.clone();
 
-            // Add custom headers
-            for (key, value) in &self.custom_headers {
-                req_builder = req_builder.header(key, value);
-            }
+        // Convert response bytes stream to SSE events
+        let sse_stream = SseStream::new(bytes);
 
-            let response = req_builder
-                .json(&responses_request)
-                .send()
-                .await?;
+        // Transform OpenAI SSE events to Anthropic format
+        let transformed_stream = sse_stream.then(move |result| {
+            let message_id = message_id.clone();
+            let state = state.clone();
+            let provider_name = provider_name.clone();
 
-            if !response.status().is_success() {
-                let status = response.status().as_u16();
-                let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                tracing::error!("Responses API error ({}): {}", status, error_text);
-                return Err(ProviderError::ApiError {
-                    status,
-                    message: error_text,
+            async move {
+                match result {
+                    Ok(sse_event) => {
+                        // If stream already ended, don't process any more chunks
+                        if state.lock().unwrap().stream_ended {
+                            tracing::debug!("⏹️ Stream already ended, skipping chunk");
+                            return Ok(Bytes::new());
+                        }
+
+                        tracing::debug!("📦 Received SSE chunk: {}", sse_event.data);
+
+                        // Skip empty data
+                        if sse_event.data.trim().is_empty() {
+                            tracing::debug!("⏭️ Skipping empty SSE event");
+                            return Ok(Bytes::new());
+                        }
+
+                        if sse_event.data.trim() == "[DONE]" {
+                            tracing::debug!("✅ Stream finished with [DONE]");
+                            let mut state = state.lock().unwrap();
+                            if state.pending_stop_reason.is_some() {
+                                return Ok(Bytes::from(state.end_message(None)));
+                            }
+                            return Ok(Bytes::new());
+                        }
+
+                        // Check for error response first (some providers return HTTP 200 with error in body)
+                        if let Ok(error_response) = serde_json::from_str::<OpenAIStreamError>(&sse_event.data) {
+                            let status = error_response.status_code.unwrap_or(500);
+                            let error_type = error_response.error.r#type.as_deref().unwrap_or("unknown");
+                            tracing::error!(
+                                "❌ {} upstream error ({}): {} [type={}]",
+                                provider_name, status, error_response.error.message, error_type
+                            );
+                            return Err(ProviderError::from_status(
+                                status,
+                                None,
+                                format!("{}: {}", provider_name, error_response.error.message),
+                            ));
+                        }
+
+                        // Parse OpenAI chunk
+                        match serde_json::from_str::<OpenAIStreamChunk>(&sse_event.data) {
+                            Ok(chunk) => {
+                                tracing::debug!("✨ Transforming chunk with {} choices", chunk.choices.len());
+
+                                // Transform to Anthropic format (raw SSE bytes)
+                                let sse_output = Self::transform_openai_chunk_to_anthropic_sse(
+                                    &chunk,
+                                    &message_id,
+                                    &mut state.lock().unwrap()
+                                );
+
+                                if !sse_output.is_empty() {
+                                    tracing::debug!("SSE: {} bytes", sse_output.len());
+                                } else {
+                                    tracing::debug!("SSE: empty output (will be filtered)");
+                                }
+
+                                // Return as raw bytes (already SSE-formatted)
+                                Ok(Bytes::from(sse_output))
+                            }
+                            Err(e) => {
+                                tracing::warn!(
+                                    "❌ {} failed to parse chunk: {} - Data: {}",
+                                    provider_name, e, sse_event.data
+                                );
+                                Ok(Bytes::new())
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        tracing::error!("💥 Stream error: {}", e);
+                        Err(ProviderError::HttpError(e))
+                    }
+                }
+            }
+        })
+        .try_filter(|bytes| futures::future::ready(!bytes.is_empty()));
+
+        // Add stream finalization to ensure proper termination
+        // Some providers close streams without sending finish_reason
+        transformed_stream.chain(futures::stream::once(async move {
+            let mut state = state_for_cleanup.lock().unwrap();
+            tracing::debug!("🏁 Stream finalization: message_started={}, stream_ended={}",
+                state.message_started, state.stream_ended);
+
+            // finish_reason arrived but neither usage nor [DONE] followed
+            if state.pending_stop_reason.is_some() {
+                return Ok(Bytes::from(state.end_message(None)));
+            }
+
+            // Only send end events if stream didn't end properly
+            if state.message_started && !state.stream_ended {
+                tracing::warn!("⚠️ Stream ended without finish_reason - sending end events");
+
+                let mut output = String::new();
+
+                // Close text block if open
+                if state.text_block_open {
+                    let block_stop = serde_json::json!({
+                        "type": "content_block_stop",
+                        "index": state.text_block_index
+                    });
+                    output.push_str(&format!("event: content_block_stop\ndata: {}\n\n", block_stop));
+                }
+
+                // Close all tool blocks
+                output.push_str(&state.close_tool_blocks());
+
+                // Send message_delta with end_turn (we don't know the real stop_reason)
+                let message_delta = serde_json::json!({
+                    "type": "message_delta",
+                    "delta": {
+                        "stop_reason": "end_turn",
+                        "stop_sequence": null
+                    },
+                    "usage": {
+                        "output_tokens": 0
+                    }
+                });
+                output.push_str(&format!("event: message_delta\ndata: {}\n\n", message_delta));
+
+                // Send message_stop
+                let message_stop = serde_json::json!({
+                    "type": "message_stop"
                 });
+                output.push_str(&format!("event: message_stop\ndata: {}\n\n", message_stop));
+
+                Ok(Bytes::from(output))
+            } else {
+                tracing::debug!("🏁 Stream properly ended, no finalization needed");
+                Ok(Bytes::new())
+            }
+        }))
+        .try_filter(|bytes| futures::future::ready(!bytes.is_empty()))
+    }
+}
+
+#[async_trait]
+impl AnthropicProvider for OpenAIProvider {
+    async fn send_message(&self, request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+        // Get authentication token (API key or OAuth)
+        let auth_value = self.get_auth_header().await?;
+
+        // Determine base URL: OAuth uses ChatGPT backend, API key uses configured base_url
+        let base_url = if self.is_oauth() {
+            "https://chatgpt.com/backend-api"
+        } else {
+            &self.base_url
+        };
+
+        // Check if we should use Responses API endpoint:
+        // - OAuth: Always use /codex/responses for all models
+        // - API Key: Only use /responses for models containing "codex"
+        let use_responses_api = if self.is_oauth() {
+            true  // OAuth always uses Codex endpoint
+        } else {
+            Self::is_codex_model(&request.model)  // API Key only for codex models
+        };
+
+        if use_responses_api {
+            // Use /v1/responses endpoint for Codex models
+            let mut responses_request = serde_json::to_value(self.transform_to_responses_request(&request)?)?;
+            merge_extra_body(&mut responses_request, request.extra_body.as_ref());
+
+            // OAuth (ChatGPT Codex) uses /codex/responses, API Key uses /responses
+            let endpoint = if self.is_oauth() {
+                "/codex/responses"
+            } else {
+                "/responses"
+            };
+            let url = format!("{}{}", base_url, endpoint);
+
+            tracing::debug!("Using {} endpoint for Codex model: {}", endpoint, request.model);
+
+            let mut req_builder = self.client
+                .post(&url)
+                .header("Authorization", format!("Bearer {}", auth_value))
+                .header("Content-Type", "application/json")
+                .header("accept", "text/event-stream");
+
+            // For OAuth (ChatGPT Codex), add Codex-specific headers
+            if self.is_oauth() {
+                if let Some(account_id) = Self::extract_account_id(&auth_value) {
+                    req_builder = req_builder
+                        .header("chatgpt-account-id", account_id)
+                        .header("OpenAI-Beta", "responses=experimental")
+                        .header("originator", "codex_cli_rs")
+                        // Browser-like headers to avoid Cloudflare bot detection
+                        .header("User-Agent", "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/131.0.0.0 Safari/537.36")
+                        .header("Origin", "https://chatgpt.com")
+                        .header("Referer", "https://chatgpt.com/")
+                        .header("sec-ch-ua", "\"Google Chrome\";v=\"131\", \"Chromium\";v=\"131\", \"Not_A Brand\";v=\"24\"")
+                        .header("sec-ch-ua-mobile", "?0")
+                        .header("sec-ch-ua-platform", "\"macOS\"")
+                        .header("sec-fetch-dest", "empty")
+                        .header("sec-fetch-mode", "cors")
+                        .header("sec-fetch-site", "same-origin");
+                    tracing::debug!("🔐 Using OAuth Bearer token for ChatGPT Codex on {}", self.name);
+                }
+            }
+
+            // Add custom headers
+            for (key, value) in &self.custom_headers {
+                req_builder = req_builder.header(key, value);
+            }
+
+            let response = timeout::response_headers(req_builder.json(&responses_request).send()).await?;
+
+            if !response.status().is_success() {
+                let status = response.status().as_u16();
+                let headers = response.headers().clone();
+                let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+                tracing::error!("Responses API error ({}): {}", status, error_text);
+                return Err(ProviderError::from_response(status, &headers, error_text));
             }
 
             let response_text = response.text().await?;
@@ -1317,10 +1983,12 @@ impl AnthropicProvider for OpenAIProvider {
                     cache_creation_input_tokens: None,
                     cache_read_input_tokens: None,
                 },
+                rate_limits: HashMap::new(),
             })
         } else {
             // Use standard /v1/chat/completions endpoint for non-Codex models
-            let openai_request = self.transform_request(&request)?;
+            let mut openai_request = serde_json::to_value(self.transform_request(&request)?)?;
+            merge_extra_body(&mut openai_request, request.extra_body.as_ref());
             let url = format!("{}/chat/completions", base_url);
 
             let mut req_builder = self.client
@@ -1352,20 +2020,17 @@ impl AnthropicProvider for OpenAIProvider {
                 req_builder = req_builder.header(key, value);
             }
 
-            let response = req_builder
-                .json(&openai_request)
-                .send()
-                .await?;
+            let response = timeout::response_headers(req_builder.json(&openai_request).send()).await?;
 
             if !response.status().is_success() {
                 let status = response.status().as_u16();
+                let headers = response.headers().clone();
                 let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-                return Err(ProviderError::ApiError {
-                    status,
-                    message: error_text,
-                });
+                return Err(ProviderError::from_response(status, &headers, error_text));
             }
 
+            let rate_limits = super::quota::rate_limit_headers(response.headers());
+
             // Get response body as text for debugging
             let response_text = response.text().await?;
             tracing::debug!("OpenAI provider response body: {}", response_text);
@@ -1378,7 +2043,9 @@ impl AnthropicProvider for OpenAIProvider {
                     e
                 })?;
 
-            Ok(self.transform_response(openai_response))
+            let mut provider_response = self.transform_response(openai_response);
+            provider_response.rate_limits = rate_limits;
+            Ok(provider_response)
         }
     }
 
@@ -1434,8 +2101,6 @@ impl AnthropicProvider for OpenAIProvider {
         &self,
         request: AnthropicRequest,
     ) -> Result<StreamResponse, ProviderError> {
-        use futures::stream::TryStreamExt;
-
         // Get authentication token (API key or OAuth)
         let auth_value = self.get_auth_header().await?;
 
@@ -1449,20 +2114,21 @@ impl AnthropicProvider for OpenAIProvider {
         // Check if this is a Codex model
         let is_codex = Self::is_codex_model(&request.model);
 
-        let (url, request_body) = if is_codex {
+        let (url, mut request_body) = if is_codex {
             // Use /v1/responses endpoint for Codex models
             tracing::debug!("Using /v1/responses endpoint for Codex model (streaming): {}", request.model);
             let responses_request = self.transform_to_responses_request(&request)?;
             let body = serde_json::to_value(&responses_request)
-                .map_err(|e| ProviderError::SerializationError(e))?;
+                .map_err(ProviderError::SerializationError)?;
             (format!("{}/responses", base_url), body)
         } else {
             // Use standard /v1/chat/completions endpoint
             let openai_request = self.transform_request(&request)?;
             let body = serde_json::to_value(&openai_request)
-                .map_err(|e| ProviderError::SerializationError(e))?;
+                .map_err(ProviderError::SerializationError)?;
             (format!("{}/chat/completions", base_url), body)
         };
+        merge_extra_body(&mut request_body, request.extra_body.as_ref());
 
         // Send streaming request
         let mut req_builder = self.client
@@ -1493,194 +2159,71 @@ impl AnthropicProvider for OpenAIProvider {
             req_builder = req_builder.header(key, value);
         }
 
-        let response = req_builder
+        let http_request = req_builder
             .json(&request_body)
-            .send()
-            .await?;
+            .build()?;
+        let cancel = is_codex.then(|| ResponsesCancel {
+            client: self.client.clone(),
+            url: url.clone(),
+            headers: http_request.headers().clone(),
+        });
+        let response = self.client.execute(http_request).await?;
 
         // Check for errors
         if !response.status().is_success() {
             let status = response.status().as_u16();
+            let headers = response.headers().clone();
             let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(ProviderError::ApiError {
-                status,
-                message: error_text,
-            });
+            return Err(ProviderError::from_response(status, &headers, error_text));
         }
 
-        // Transform OpenAI SSE format to Anthropic SSE format
-        use futures::stream::StreamExt;
-        use crate::providers::streaming::SseStream;
-        use std::sync::{Arc, Mutex};
-
-        let message_id = format!("msg_{}", uuid::Uuid::new_v4());
+        let rate_limits = super::quota::rate_limit_headers(response.headers());
 
-        // Streaming State Management
-        // ===========================
-        // Using Arc<Mutex<StreamTransformState>> to track state across async chunks.
-        // The state tracks: message_started, text_block_open, tool_blocks, stream_ended
-        let state = Arc::new(Mutex::new(StreamTransformState::default()));
-        let state_for_cleanup = state.clone();
-
-        // Convert response bytes stream to SSE events
-        let sse_stream = SseStream::new(response.bytes_stream());
-
-        // Capture provider/model names for logging
-        let provider_name = self.name.clone();
+        // Transform OpenAI SSE format to Anthropic SSE format
         let model_name = request.model.clone();
-
-        // Transform OpenAI SSE events to Anthropic format
-        let transformed_stream = sse_stream.then(move |result| {
-            let message_id = message_id.clone();
-            let state = state.clone();
-            let provider_name = provider_name.clone();
-
-            async move {
-                match result {
-                    Ok(sse_event) => {
-                        // If stream already ended, don't process any more chunks
-                        if state.lock().unwrap().stream_ended {
-                            tracing::debug!("⏹️ Stream already ended, skipping chunk");
-                            return Ok(Bytes::new());
-                        }
-
-                        tracing::debug!("📦 Received SSE chunk: {}", sse_event.data);
-
-                        // Skip empty data
-                        if sse_event.data.trim().is_empty() {
-                            tracing::debug!("⏭️ Skipping empty SSE event");
-                            return Ok(Bytes::new());
-                        }
-
-                        if sse_event.data.trim() == "[DONE]" {
-                            tracing::debug!("✅ Stream finished with [DONE]");
-                            return Ok(Bytes::new());
-                        }
-
-                        // Check for error response first (some providers return HTTP 200 with error in body)
-                        if let Ok(error_response) = serde_json::from_str::<OpenAIStreamError>(&sse_event.data) {
-                            let status = error_response.status_code.unwrap_or(500);
-                            let error_type = error_response.error.r#type.as_deref().unwrap_or("unknown");
-                            tracing::error!(
-                                "❌ {} upstream error ({}): {} [type={}]",
-                                provider_name, status, error_response.error.message, error_type
-                            );
-                            return Err(ProviderError::ApiError {
-                                status,
-                                message: format!("{}: {}", provider_name, error_response.error.message),
-                            });
-                        }
-
-                        // Parse OpenAI chunk
-                        match serde_json::from_str::<OpenAIStreamChunk>(&sse_event.data) {
-                            Ok(chunk) => {
-                                tracing::debug!("✨ Transforming chunk with {} choices", chunk.choices.len());
-
-                                // Transform to Anthropic format (raw SSE bytes)
-                                let sse_output = Self::transform_openai_chunk_to_anthropic_sse(
-                                    &chunk,
-                                    &message_id,
-                                    &mut *state.lock().unwrap()
-                                );
-
-                                if !sse_output.is_empty() {
-                                    tracing::debug!("SSE: {} bytes", sse_output.len());
-                                } else {
-                                    tracing::debug!("SSE: empty output (will be filtered)");
-                                }
-
-                                // Return as raw bytes (already SSE-formatted)
-                                Ok(Bytes::from(sse_output))
-                            }
-                            Err(e) => {
-                                tracing::warn!(
-                                    "❌ {} failed to parse chunk: {} - Data: {}",
-                                    provider_name, e, sse_event.data
-                                );
-                                Ok(Bytes::new())
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!("💥 Stream error: {}", e);
-                        Err(ProviderError::HttpError(e))
-                    }
-                }
-            }
-        })
-        .try_filter(|bytes| futures::future::ready(!bytes.is_empty()));
-
-        // Add stream finalization to ensure proper termination
-        // Some providers close streams without sending finish_reason
-        let finalized_stream = transformed_stream.chain(futures::stream::once(async move {
-            let state = state_for_cleanup.lock().unwrap();
-            tracing::debug!("🏁 Stream finalization: message_started={}, stream_ended={}",
-                state.message_started, state.stream_ended);
-
-            // Only send end events if stream didn't end properly
-            if state.message_started && !state.stream_ended {
-                tracing::warn!("⚠️ Stream ended without finish_reason - sending end events");
-
-                let mut output = String::new();
-
-                // Close text block if open
-                if state.text_block_open {
-                    let block_stop = serde_json::json!({
-                        "type": "content_block_stop",
-                        "index": state.text_block_index
-                    });
-                    output.push_str(&format!("event: content_block_stop\ndata: {}\n\n", block_stop));
-                }
-
-                // Close all tool blocks
-                for (_, block_index) in &state.tool_blocks {
-                    let block_stop = serde_json::json!({
-                        "type": "content_block_stop",
-                        "index": block_index
-                    });
-                    output.push_str(&format!("event: content_block_stop\ndata: {}\n\n", block_stop));
-                }
-
-                // Send message_delta with end_turn (we don't know the real stop_reason)
-                let message_delta = serde_json::json!({
-                    "type": "message_delta",
-                    "delta": {
-                        "stop_reason": "end_turn",
-                        "stop_sequence": null
-                    },
-                    "usage": {
-                        "output_tokens": 0
-                    }
-                });
-                output.push_str(&format!("event: message_delta\ndata: {}\n\n", message_delta));
-
-                // Send message_stop
-                let message_stop = serde_json::json!({
-                    "type": "message_stop"
-                });
-                output.push_str(&format!("event: message_stop\ndata: {}\n\n", message_stop));
-
-                Ok(Bytes::from(output))
-            } else {
-                tracing::debug!("🏁 Stream properly ended, no finalization needed");
-                Ok(Bytes::new())
-            }
-        }))
-        .try_filter(|bytes| futures::future::ready(!bytes.is_empty()));
-
+        let transformed_stream: Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>> = if is_codex {
+            Box::pin(Self::transform_responses_stream(response.bytes_stream(), self.name.clone(), model_name.clone(), cancel))
+        } else {
+            Box::pin(Self::transform_chat_stream(response.bytes_stream(), self.name.clone()))
+        };
         // Wrap with logging stream to capture token stats
         use crate::providers::streaming::LoggingSseStream;
-        let logging_stream = LoggingSseStream::new(finalized_stream, self.name.clone(), model_name);
+        let logging_stream = LoggingSseStream::new(transformed_stream, self.name.clone(), model_name);
 
         Ok(StreamResponse {
             stream: Box::pin(logging_stream),
-            headers: HashMap::new(), // OpenAI doesn't have rate limit headers to forward
+            headers: HashMap::new(), // OpenAI rate limit headers aren't forwarded to Anthropic clients
+            rate_limits,
         })
     }
 
     fn supports_model(&self, model: &str) -> bool {
         self.models.iter().any(|m| m.eq_ignore_ascii_case(model))
     }
+
+    async fn list_models(&self) -> Result<Option<Vec<String>>, ProviderError> {
+        // The ChatGPT Codex backend has no model list
+        if self.is_oauth() {
+            return Ok(None);
+        }
+
+        let mut req_builder = self.client
+            .get(format!("{}/models", self.base_url))
+            .header("Authorization", format!("Bearer {}", self.get_auth_header().await?));
+        for (key, value) in &self.custom_headers {
+            req_builder = req_builder.header(key, value);
+        }
+
+        let response = req_builder.send().await?;
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let headers = response.headers().clone();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(ProviderError::from_response(status, &headers, error_text));
+        }
+        let body: serde_json::Value = response.json().await?;
+        Ok(Some(super::model_ids(&body)))
+    }
 }
 
 #[cfg(test)]
@@ -1773,10 +2316,63 @@ mod tests {
                 "content":""
             },"finish_reason":"tool_calls"}]
         }"#, id, &mut state);
+        assert!(out.contains("content_block_stop"), "should close the blocks");
+        assert!(!out.contains("message_stop"), "should wait for the usage chunk");
+
+        // 5. Usage-only chunk (stream_options.include_usage)
+        let out = transform_chunk(r#"{
+            "id":"gen-1","model":"kimi","choices":[],
+            "usage":{"prompt_tokens":100,"completion_tokens":12}
+        }"#, id, &mut state);
         assert!(out.contains("tool_use"), "stop_reason should be tool_use");
+        assert!(out.contains(r#""output_tokens":12"#), "should report usage");
         assert!(out.contains("message_stop"), "should end the stream");
     }
 
+    /// A tool call that ends without argument bytes gets `{}` input before its block stops
+    #[test]
+    fn test_tool_call_without_arguments_gets_empty_object() {
+        let mut state = StreamTransformState::default();
+        transform_chunk(r#"{
+            "id":"gen-1","model":"m","choices":[{"index":0,"delta":{
+                "tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"Now","arguments":""}}]
+            },"finish_reason":null}]
+        }"#, "msg_test", &mut state);
+        let out = transform_chunk(
+            r#"{"id":"gen-1","model":"m","choices":[{"index":0,"delta":{},"finish_reason":"tool_calls"}]}"#,
+            "msg_test",
+            &mut state,
+        );
+        let empty = r#""partial_json":"{}""#;
+        assert!(out.contains(empty), "{}", out);
+        assert!(out.find(empty) < out.find("content_block_stop"));
+
+        // Responses API: the function_call item is done without argument deltas
+        let mut state = ResponsesStreamState::new("msg_test".to_string(), "m".to_string());
+        let mut out = String::new();
+        for event in [
+            serde_json::json!({"type": "response.output_item.added", "output_index": 0,
+                "item": {"type": "function_call", "call_id": "call_1", "name": "Now"}}),
+            serde_json::json!({"type": "response.output_item.done", "output_index": 0}),
+        ] {
+            out.push_str(&state.transform_event(&event).unwrap());
+        }
+        assert!(out.contains(empty), "{}", out);
+
+        // ...but not when arguments arrived
+        let mut state = ResponsesStreamState::new("msg_test".to_string(), "m".to_string());
+        let mut out = String::new();
+        for event in [
+            serde_json::json!({"type": "response.output_item.added", "output_index": 0,
+                "item": {"type": "function_call", "call_id": "call_1", "name": "Now"}}),
+            serde_json::json!({"type": "response.function_call_arguments.delta", "output_index": 0, "delta": "{\"tz\":\"UTC\"}"}),
+            serde_json::json!({"type": "response.output_item.done", "output_index": 0}),
+        ] {
+            out.push_str(&state.transform_event(&event).unwrap());
+        }
+        assert!(!out.contains(empty), "{}", out);
+    }
+
     /// When text comes first (normal case), text gets index 0 and tool gets index 1.
     #[test]
     fn test_text_before_tool_call_normal_ordering() {
@@ -1822,4 +2418,144 @@ mod tests {
         assert!(out.contains("\"type\":\"thinking\""), "should be a thinking content block");
         assert!(out.contains("thinking_delta"), "should use thinking_delta type");
     }
+
+    /// Cached prompt tokens are split out of input_tokens, matching Anthropic's accounting
+    #[test]
+    fn test_usage_splits_cached_tokens() {
+        let usage: OpenAIUsage = serde_json::from_str(
+            r#"{"prompt_tokens":1000,"completion_tokens":20,"total_tokens":1020,"prompt_tokens_details":{"cached_tokens":800}}"#,
+        ).unwrap();
+        let usage = usage.to_usage();
+        assert_eq!(usage.input_tokens, 200);
+        assert_eq!(usage.cache_read_input_tokens, Some(800));
+        assert_eq!(usage.total_input_tokens(), 1000);
+
+        // DeepSeek reports hits at the top level
+        let usage: OpenAIUsage = serde_json::from_str(
+            r#"{"prompt_tokens":500,"completion_tokens":5,"prompt_cache_hit_tokens":100,"prompt_cache_miss_tokens":400}"#,
+        ).unwrap();
+        assert_eq!(usage.to_usage().cache_read_input_tokens, Some(100));
+    }
+
+    #[test]
+    fn test_final_chunk_reports_cached_tokens() {
+        let mut state = StreamTransformState::default();
+        let out = transform_chunk(r#"{
+            "id":"gen-1","model":"gpt","choices":[{"index":0,"delta":{},"finish_reason":"stop"}],
+            "usage":{"prompt_tokens":100,"completion_tokens":3,"prompt_tokens_details":{"cached_tokens":64}}
+        }"#, "msg_test", &mut state);
+        assert!(out.contains(r#""cache_read_input_tokens":64"#));
+        assert!(out.contains(r#""input_tokens":36"#));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_responses_stream_cancels_upstream() {
+        use futures::StreamExt;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/responses", listener.local_addr().unwrap());
+        let cancel = ResponsesCancel {
+            client: Client::new(),
+            url,
+            headers: [(reqwest::header::AUTHORIZATION, "Bearer key".parse().unwrap())].into_iter().collect(),
+        };
+
+        let created = "event: response.created\ndata: {\"type\":\"response.created\",\"response\":{\"id\":\"resp_1\",\"model\":\"gpt-5-codex\"}}\n\n";
+        let upstream = futures::stream::iter(vec![Ok::<_, reqwest::Error>(Bytes::from(created))]).chain(futures::stream::pending());
+        let mut stream = Box::pin(OpenAIProvider::transform_responses_stream(
+            upstream, "codex".to_string(), "gpt-5-codex".to_string(), Some(cancel),
+        ));
+        assert!(stream.next().await.unwrap().is_ok());
+        drop(stream);
+
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = vec![0; 1024];
+        let n = socket.read(&mut request).await.unwrap();
+        let request = String::from_utf8_lossy(&request[..n]).to_lowercase();
+        assert!(request.starts_with("post /responses/resp_1/cancel "));
+        assert!(request.contains("authorization: bearer key"));
+        socket.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n").await.unwrap();
+    }
+
+    #[test]
+    fn test_mistral_shortens_tool_call_ids() {
+        let provider = OpenAIProvider::mistral("mistral".to_string(), "key".to_string(), None, vec![], vec![]);
+        assert_eq!(provider.base_url, "https://api.mistral.ai/v1");
+
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "codestral-latest",
+            "max_tokens": 1024,
+            "messages": [
+                {"role": "user", "content": "list files"},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "toolu_01A09q90qw90lq917835lq9", "name": "Bash", "input": {"command": "ls"}},
+                    {"type": "tool_use", "id": "D681PevKs", "name": "Bash", "input": {"command": "pwd"}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_01A09q90qw90lq917835lq9", "content": "src"},
+                    {"type": "tool_result", "tool_use_id": "D681PevKs", "content": "/"}
+                ]}
+            ]
+        })).unwrap();
+
+        let json = serde_json::to_value(provider.transform_request(&request).unwrap()).unwrap();
+        let call_ids: Vec<&str> = json["messages"][1]["tool_calls"].as_array().unwrap()
+            .iter().map(|c| c["id"].as_str().unwrap()).collect();
+        assert_eq!(call_ids[1], "D681PevKs");
+        assert!(call_ids[0].len() == 9 && call_ids[0].chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_eq!(json["messages"][2]["tool_call_id"], call_ids[0]);
+        assert_eq!(json["messages"][3]["tool_call_id"], call_ids[1]);
+    }
+
+    #[test]
+    fn test_deepseek_thinking_and_reasoning_content() {
+        let provider = OpenAIProvider::deepseek("deepseek".to_string(), "key".to_string(), None, vec![], vec![]);
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "deepseek-chat",
+            "max_tokens": 1024,
+            "thinking": {"type": "enabled", "budget_tokens": 4096},
+            "messages": [
+                {"role": "user", "content": "hi"},
+                {"role": "assistant", "content": [
+                    {"type": "thinking", "thinking": "greeting"},
+                    {"type": "text", "text": "Hello"}
+                ]},
+                {"role": "user", "content": "list files"},
+                {"role": "assistant", "content": [
+                    {"type": "thinking", "thinking": "use ls"},
+                    {"type": "tool_use", "id": "call_1", "name": "Bash", "input": {"command": "ls"}}
+                ]},
+                {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "call_1", "content": "src"}]}
+            ]
+        })).unwrap();
+
+        let json = serde_json::to_value(provider.transform_request(&request).unwrap()).unwrap();
+        assert_eq!(json["thinking"], serde_json::json!({"type": "enabled"}));
+        assert!(json["messages"][1].get("reasoning_content").is_none(), "earlier turns drop reasoning");
+        assert_eq!(json["messages"][3]["reasoning_content"], "use ls");
+
+        // Non-streaming and streaming reasoning_content become thinking blocks
+        let response: OpenAIResponse = serde_json::from_value(serde_json::json!({
+            "id": "r1", "model": "deepseek-chat",
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": "42", "reasoning_content": "compute"}, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 5, "completion_tokens": 3, "total_tokens": 8}
+        })).unwrap();
+        let content = serde_json::to_value(provider.transform_response(response).content).unwrap();
+        assert_eq!(content[0]["type"], "thinking");
+        assert_eq!(content[0]["thinking"], "compute");
+
+        let mut state = StreamTransformState::default();
+        let out = transform_chunk(r#"{
+            "id":"gen-1","model":"deepseek-chat","choices":[{"index":0,"delta":{
+                "content":null,"reasoning_content":"step one"
+            },"finish_reason":null}]
+        }"#, "msg_test", &mut state);
+        assert!(out.contains("thinking_delta") && out.contains("step one"));
+
+        // Without the DeepSeek preset no thinking parameter is sent
+        let openai = OpenAIProvider::with_headers("openai".to_string(), "key".to_string(), "http://x".to_string(), vec![], vec![], None, None);
+        let json = serde_json::to_value(openai.transform_request(&request).unwrap()).unwrap();
+        assert!(json.get("thinking").is_none() && json["messages"][3].get("reasoning_content").is_none());
+    }
 }
@@ -0,0 +1,271 @@
+use super::Usage;
+use crate::cli::ModelMapping;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+/// Price of a model in USD per million tokens (`[pricing."provider/model"]`)
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    /// Cache read price (default: input price)
+    pub cache_read: Option<f64>,
+    /// Cache write price (default: input price)
+    pub cache_write: Option<f64>,
+}
+
+impl ModelPrice {
+    /// Cost of a request in USD
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        let per_token = |tokens: Option<u32>, price: f64| tokens.unwrap_or(0) as f64 * price;
+        (per_token(Some(usage.input_tokens), self.input)
+            + per_token(Some(usage.output_tokens), self.output)
+            + per_token(usage.cache_read_input_tokens, self.cache_read.unwrap_or(self.input))
+            + per_token(usage.cache_creation_input_tokens, self.cache_write.unwrap_or(self.input)))
+            / 1_000_000.0
+    }
+
+    /// USD per million tokens at 3 input tokens per output token, for comparing
+    /// models (`ordering = "cost"`, `max_cost_per_mtok`)
+    pub fn blended_per_mtok(&self) -> f64 {
+        (3.0 * self.input + self.output) / 4.0
+    }
+}
+
+/// Order mappings cheapest first by blended price (`ordering = "cost"`). Unpriced
+/// mappings come last; priority breaks ties.
+pub fn order_by_cost(mappings: &mut [ModelMapping], price: impl Fn(&ModelMapping) -> Option<ModelPrice>) {
+    let keyed: Vec<Option<f64>> = mappings.iter().map(|m| price(m).map(|p| p.blended_per_mtok())).collect();
+    let mut indexed: Vec<(usize, ModelMapping)> = mappings.iter().cloned().enumerate().collect();
+    indexed.sort_by(|(ia, a), (ib, b)| {
+        let (ka, kb) = (keyed[*ia], keyed[*ib]);
+        ka.is_none()
+            .cmp(&kb.is_none())
+            .then(ka.unwrap_or(0.0).total_cmp(&kb.unwrap_or(0.0)))
+            .then(a.priority.cmp(&b.priority))
+            .then(ia.cmp(ib))
+    });
+    for (slot, (_, mapping)) in mappings.iter_mut().zip(indexed) {
+        *slot = mapping;
+    }
+}
+
+/// Price table entries from either supported format: `[pricing]` shape (USD per
+/// million tokens) or LiteLLM's (`input_cost_per_token`, ..., USD per token).
+/// Entries with neither (e.g. LiteLLM's `sample_spec`, image models) are skipped.
+pub fn parse_price_table(body: &Value) -> BTreeMap<String, ModelPrice> {
+    let Some(entries) = body.as_object() else {
+        return BTreeMap::new();
+    };
+    entries
+        .iter()
+        .filter_map(|(key, entry)| {
+            if let Ok(price) = serde_json::from_value::<ModelPrice>(entry.clone()) {
+                return Some((key.clone(), price));
+            }
+            let per_million = |field: &str| entry[field].as_f64().map(|p| p * 1_000_000.0);
+            let price = ModelPrice {
+                input: per_million("input_cost_per_token")?,
+                output: per_million("output_cost_per_token")?,
+                cache_read: per_million("cache_read_input_token_cost"),
+                cache_write: per_million("cache_creation_input_token_cost"),
+            };
+            Some((key.clone(), price))
+        })
+        .collect()
+}
+
+/// Prices fetched from `[server.pricing_source]`, consulted after `[pricing]`.
+/// The last fetched table is cached on disk so prices survive restarts while offline.
+pub struct RemotePricing {
+    prices: RwLock<BTreeMap<String, ModelPrice>>,
+    cache_path: PathBuf,
+}
+
+impl RemotePricing {
+    /// Start with the cached table, if there is one
+    pub fn load(cache_path: PathBuf) -> Self {
+        let prices = std::fs::read_to_string(&cache_path)
+            .ok()
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default();
+        Self { prices: RwLock::new(prices), cache_path }
+    }
+
+    /// Same lookup order as `AppConfig::price_for`
+    pub fn price_for(&self, provider: &str, model: &str) -> Option<ModelPrice> {
+        let prices = self.prices.read().unwrap();
+        prices.get(&format!("{}/{}", provider, model)).or_else(|| prices.get(model)).cloned()
+    }
+
+    /// Number of known prices
+    pub fn count(&self) -> usize {
+        self.prices.read().unwrap().len()
+    }
+
+    /// Fetch `url` and replace the table; returns the number of prices. An empty
+    /// or unparseable table is an error and keeps the previous one.
+    pub async fn refresh(&self, client: &reqwest::Client, url: &str) -> anyhow::Result<usize> {
+        let response = client.get(url).send().await?.error_for_status()?;
+        let prices = parse_price_table(&response.json().await?);
+        if prices.is_empty() {
+            anyhow::bail!("no prices found in {}", url);
+        }
+        if let Err(e) = std::fs::write(&self.cache_path, serde_json::to_string(&prices)?) {
+            tracing::debug!("Couldn't cache prices at {}: {}", self.cache_path.display(), e);
+        }
+        let count = prices.len();
+        *self.prices.write().unwrap() = prices;
+        Ok(count)
+    }
+}
+
+/// Cost suffix for metrics log lines, e.g. " $0.0123" (empty when unpriced)
+pub fn cost_info(cost: Option<f64>) -> String {
+    cost.map(|c| format!(" ${:.4}", c)).unwrap_or_default()
+}
+
+/// Accumulated usage and spend for a provider/model since startup
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CostTotals {
+    pub provider: String,
+    pub model: String,
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub cache_write_tokens: u64,
+    /// Spend on requests with a configured price
+    pub cost_usd: f64,
+    /// Requests with no configured price (not included in cost_usd)
+    pub unpriced_requests: u64,
+}
+
+/// Per provider/model usage and cost totals
+#[derive(Default)]
+pub struct CostTracker {
+    totals: DashMap<(String, String), CostTotals>,
+}
+
+impl CostTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a completed request's usage; returns its cost when the model is priced
+    pub fn record(&self, provider: &str, model: &str, usage: &Usage, price: Option<&ModelPrice>) -> Option<f64> {
+        let cost = price.map(|p| p.cost(usage));
+        let mut totals = self.totals.entry((provider.to_string(), model.to_string())).or_insert_with(|| CostTotals {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            ..Default::default()
+        });
+        totals.requests += 1;
+        totals.input_tokens += usage.input_tokens as u64;
+        totals.output_tokens += usage.output_tokens as u64;
+        totals.cache_read_tokens += usage.cache_read_input_tokens.unwrap_or(0) as u64;
+        totals.cache_write_tokens += usage.cache_creation_input_tokens.unwrap_or(0) as u64;
+        match cost {
+            Some(cost) => totals.cost_usd += cost,
+            None => totals.unpriced_requests += 1,
+        }
+        cost
+    }
+
+    /// Totals per provider/model, sorted by provider/model
+    pub fn all_totals(&self) -> Vec<CostTotals> {
+        let mut totals: Vec<CostTotals> = self.totals.iter().map(|t| t.value().clone()).collect();
+        totals.sort_by(|a, b| (&a.provider, &a.model).cmp(&(&b.provider, &b.model)));
+        totals
+    }
+
+    /// Total spend across all provider/models
+    pub fn total_cost(&self) -> f64 {
+        self.totals.iter().map(|t| t.cost_usd).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(input: u32, output: u32, read: Option<u32>, write: Option<u32>) -> Usage {
+        Usage { input_tokens: input, output_tokens: output, cache_creation_input_tokens: write, cache_read_input_tokens: read }
+    }
+
+    #[test]
+    fn test_cost_and_totals() {
+        let price = ModelPrice { input: 3.0, output: 15.0, cache_read: Some(0.3), cache_write: None };
+        let cost = price.cost(&usage(1_000, 2_000, Some(10_000), Some(1_000)));
+        // 1k in @3 + 2k out @15 + 10k read @0.3 + 1k write @3 (input price)
+        assert!((cost - 0.039).abs() < 1e-9);
+
+        let tracker = CostTracker::new();
+        tracker.record("anthropic", "sonnet", &usage(1_000, 2_000, Some(10_000), Some(1_000)), Some(&price));
+        assert_eq!(tracker.record("ollama", "qwen", &usage(500, 100, None, None), None), None);
+
+        let totals = tracker.all_totals();
+        assert_eq!(totals[0].cache_read_tokens, 10_000);
+        assert_eq!(totals[1].unpriced_requests, 1);
+        assert!((tracker.total_cost() - 0.039).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_order_by_cost() {
+        let mapping = |priority: u32, provider: &str| ModelMapping {
+            priority,
+            provider: provider.to_string(),
+            actual_model: "m".to_string(),
+            ..Default::default()
+        };
+        let price = |m: &ModelMapping| {
+            let (input, output) = match m.provider.as_str() {
+                "premium" => (3.0, 15.0),
+                "cheap" => (0.6, 2.2),
+                "cheap-too" => (1.0, 1.0),
+                _ => return None,
+            };
+            Some(ModelPrice { input, output, cache_read: None, cache_write: None })
+        };
+        assert_eq!(ModelPrice { input: 0.6, output: 2.2, cache_read: None, cache_write: None }.blended_per_mtok(), 1.0);
+
+        // Cheapest first whatever the priority, equal prices by priority, unpriced last
+        let mut mappings = vec![mapping(1, "premium"), mapping(2, "local"), mapping(3, "cheap-too"), mapping(2, "cheap")];
+        order_by_cost(&mut mappings, price);
+        let providers: Vec<&str> = mappings.iter().map(|m| m.provider.as_str()).collect();
+        assert_eq!(providers, vec!["cheap", "cheap-too", "premium", "local"]);
+    }
+
+    #[test]
+    fn test_parse_price_table() {
+        let table = parse_price_table(&serde_json::json!({
+            "sample_spec": {"input_cost_per_token": "varies", "mode": "chat"},
+            "glm-4.6": {"input": 0.6, "output": 2.2},
+            "anthropic/claude-sonnet-4-5": {
+                "input_cost_per_token": 3e-6,
+                "output_cost_per_token": 1.5e-5,
+                "cache_read_input_token_cost": 3e-7,
+                "litellm_provider": "anthropic"
+            },
+            "dall-e-3": {"output_cost_per_image": 0.04}
+        }));
+        assert_eq!(table.len(), 2);
+        assert_eq!(table["glm-4.6"], ModelPrice { input: 0.6, output: 2.2, cache_read: None, cache_write: None });
+        let sonnet = &table["anthropic/claude-sonnet-4-5"];
+        assert!((sonnet.input - 3.0).abs() < 1e-9 && (sonnet.output - 15.0).abs() < 1e-9);
+        assert!((sonnet.cache_read.unwrap() - 0.3).abs() < 1e-9);
+        assert_eq!(sonnet.cache_write, None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("pricing.json");
+        std::fs::write(&cache, serde_json::to_string(&table).unwrap()).unwrap();
+        let remote = RemotePricing::load(cache);
+        assert_eq!(remote.price_for("zai", "glm-4.6").unwrap().output, 2.2);
+        assert!(remote.price_for("openrouter", "claude-sonnet-4-5").is_none());
+        assert!(remote.price_for("anthropic", "claude-sonnet-4-5").is_some());
+    }
+}
@@ -0,0 +1,149 @@
+//! Anthropic prompt cache breakpoints: keep requests within the 4-breakpoint limit
+//! and apply the configured cache TTL.
+
+use crate::models::{AnthropicRequest, ContentBlock, KnownContentBlock, MessageContent, SystemPrompt};
+use serde_json::{Map, Value};
+
+/// Anthropic rejects requests with more `cache_control` breakpoints than this
+pub const MAX_BREAKPOINTS: usize = 4;
+
+/// Beta flag for the 1 hour cache TTL
+pub const EXTENDED_CACHE_TTL_BETA: &str = "extended-cache-ttl-2025-04-11";
+
+/// Cache TTLs Anthropic accepts
+pub const CACHE_TTLS: &[&str] = &["5m", "1h"];
+
+/// A place a `cache_control` marker can live
+enum Slot<'a> {
+    Field(&'a mut Option<Value>),
+    /// Raw JSON object of an unknown content block
+    Raw(&'a mut Map<String, Value>),
+}
+
+impl Slot<'_> {
+    fn is_set(&self) -> bool {
+        match self {
+            Slot::Field(field) => field.is_some(),
+            Slot::Raw(obj) => obj.contains_key("cache_control"),
+        }
+    }
+
+    fn clear(&mut self) {
+        match self {
+            Slot::Field(field) => **field = None,
+            Slot::Raw(obj) => {
+                obj.remove("cache_control");
+            }
+        }
+    }
+
+    fn set_ttl(&mut self, ttl: &str) {
+        let marker = match self {
+            Slot::Field(field) => field.as_mut(),
+            Slot::Raw(obj) => obj.get_mut("cache_control"),
+        };
+        if let Some(marker) = marker.and_then(|m| m.as_object_mut()) {
+            marker.insert("ttl".to_string(), Value::from(ttl));
+        }
+    }
+}
+
+/// Breakpoints in prompt order (tools, system, messages), so later ones cache larger prefixes
+fn breakpoints(request: &mut AnthropicRequest) -> Vec<Slot<'_>> {
+    let mut slots = Vec::new();
+    for tool in request.tools.iter_mut().flatten() {
+        slots.push(Slot::Field(&mut tool.cache_control));
+    }
+    if let Some(SystemPrompt::Blocks(blocks)) = &mut request.system {
+        for block in blocks.iter_mut() {
+            slots.push(Slot::Field(&mut block.cache_control));
+        }
+    }
+    for message in &mut request.messages {
+        let MessageContent::Blocks(blocks) = &mut message.content else {
+            continue;
+        };
+        for block in blocks.iter_mut() {
+            match block {
+                ContentBlock::Known(KnownContentBlock::Text { cache_control, .. })
+                | ContentBlock::Known(KnownContentBlock::ToolResult { cache_control, .. }) => {
+                    slots.push(Slot::Field(cache_control));
+                }
+                ContentBlock::Unknown(Value::Object(obj)) => slots.push(Slot::Raw(obj)),
+                _ => {}
+            }
+        }
+    }
+    slots.retain(|slot| slot.is_set());
+    slots
+}
+
+/// Drop all but the last `MAX_BREAKPOINTS` breakpoints (the ones covering the largest
+/// prefixes) and set `ttl` on the rest. Returns the number of breakpoints dropped.
+pub fn budget_breakpoints(request: &mut AnthropicRequest, ttl: Option<&str>) -> usize {
+    let mut slots = breakpoints(request);
+    let excess = slots.len().saturating_sub(MAX_BREAKPOINTS);
+    for slot in &mut slots[..excess] {
+        slot.clear();
+    }
+    if let Some(ttl) = ttl {
+        for slot in &mut slots[excess..] {
+            slot.set_ttl(ttl);
+        }
+    }
+    excess
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request() -> AnthropicRequest {
+        let marker = json!({"type": "ephemeral"});
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "tools": [{"name": "Bash", "input_schema": {}, "cache_control": marker}],
+            "system": [
+                {"type": "text", "text": "You are Claude Code", "cache_control": marker},
+                {"type": "text", "text": "CLAUDE.md", "cache_control": marker},
+            ],
+            "messages": [
+                {"role": "user", "content": [{"type": "text", "text": "hi", "cache_control": marker}]},
+                {"role": "assistant", "content": "hello"},
+                {"role": "user", "content": [
+                    {"type": "document", "source": {"type": "text", "data": "doc"}, "cache_control": marker},
+                    {"type": "text", "text": "summarize"},
+                ]},
+            ],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_keeps_last_four_breakpoints() {
+        let mut request = request();
+        assert_eq!(budget_breakpoints(&mut request, None), 1);
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert!(json["tools"][0].get("cache_control").is_none());
+        assert!(json["system"][0]["cache_control"].is_object());
+        assert_eq!(json["messages"][2]["content"][0]["cache_control"], json!({"type": "ephemeral"}));
+        assert_eq!(breakpoints(&mut request).len(), MAX_BREAKPOINTS);
+
+        // Already within budget
+        assert_eq!(budget_breakpoints(&mut request, None), 0);
+    }
+
+    #[test]
+    fn test_applies_ttl() {
+        let mut request = request();
+        budget_breakpoints(&mut request, Some("1h"));
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["system"][1]["cache_control"], json!({"type": "ephemeral", "ttl": "1h"}));
+        assert_eq!(json["messages"][2]["content"][0]["cache_control"]["ttl"], "1h");
+        assert!(json["messages"][2]["content"][1].get("cache_control").is_none());
+    }
+}
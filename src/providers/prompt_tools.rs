@@ -0,0 +1,214 @@
+//! Prompt-embedded tools for backends with no function calling.
+//!
+//! Tool definitions are rendered into the system prompt and earlier tool calls and
+//! results are replayed as tagged text. The model is asked to answer with
+//! `<tool_call>{"name": ..., "input": {...}}</tool_call>` blocks, which are turned back
+//! into `tool_use` content blocks so Claude Code sees a normal tool call.
+
+use super::ProviderResponse;
+use crate::models::{AnthropicRequest, ContentBlock, KnownContentBlock, MessageContent, SystemBlock, SystemPrompt};
+use serde_json::Value;
+
+const CALL_OPEN: &str = "<tool_call>";
+const CALL_CLOSE: &str = "</tool_call>";
+
+/// Move the request's tools into the system prompt and flatten tool_use / tool_result
+/// blocks into text. Returns false if the request has no client tools.
+pub fn embed_tools(request: &mut AnthropicRequest) -> bool {
+    // Server tools (web_search etc.) carry a type and can't be emulated
+    let tools: Vec<_> = request
+        .tools
+        .take()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|t| t.r#type.is_none() && t.name.is_some())
+        .collect();
+    if tools.is_empty() {
+        return false;
+    }
+
+    let mut instructions = String::from(
+        "You can call tools. To call a tool, reply with one or more blocks of exactly this form \
+         and write nothing after them:\n\
+         <tool_call>\n{\"name\": \"TOOL_NAME\", \"input\": {ARGUMENTS}}\n</tool_call>\n\
+         The results will be sent back in <tool_result> blocks. Only call the tools listed below.\n\n\
+         # Available tools\n",
+    );
+    for tool in &tools {
+        instructions.push_str(&format!("\n## {}\n", tool.name.as_deref().unwrap_or_default()));
+        if let Some(ref description) = tool.description {
+            instructions.push_str(description.trim());
+            instructions.push('\n');
+        }
+        if let Some(ref schema) = tool.input_schema {
+            instructions.push_str(&format!("Input schema: {}\n", schema));
+        }
+    }
+
+    request.system = Some(match request.system.take() {
+        None => SystemPrompt::Text(instructions),
+        Some(SystemPrompt::Text(text)) => SystemPrompt::Text(format!("{}\n\n{}", text, instructions)),
+        Some(SystemPrompt::Blocks(mut blocks)) => {
+            blocks.push(SystemBlock { r#type: "text".to_string(), text: instructions, cache_control: None });
+            SystemPrompt::Blocks(blocks)
+        }
+    });
+
+    for message in &mut request.messages {
+        let MessageContent::Blocks(blocks) = &mut message.content else {
+            continue;
+        };
+        for block in blocks.iter_mut() {
+            let text = match block {
+                ContentBlock::Known(KnownContentBlock::ToolUse { name, input, .. }) => {
+                    format!("{}\n{}\n{}", CALL_OPEN, serde_json::json!({"name": name, "input": input}), CALL_CLOSE)
+                }
+                ContentBlock::Known(KnownContentBlock::ToolResult { tool_use_id, content, is_error, .. }) => {
+                    let error = if *is_error { " error=\"true\"" } else { "" };
+                    format!("<tool_result id=\"{}\"{}>\n{}\n</tool_result>", tool_use_id, error, content)
+                }
+                _ => continue,
+            };
+            *block = ContentBlock::text(text, None);
+        }
+    }
+    true
+}
+
+/// Turn `<tool_call>` blocks in the response text into tool_use blocks
+pub fn extract_tool_calls(response: &mut ProviderResponse) {
+    let mut content = Vec::with_capacity(response.content.len());
+    let mut found = false;
+
+    for block in response.content.drain(..) {
+        let Some(text) = block.as_text().filter(|t| t.contains(CALL_OPEN)) else {
+            content.push(block);
+            continue;
+        };
+
+        let mut rest = text;
+        let mut calls = Vec::new();
+        let mut prose = String::new();
+        while let Some(start) = rest.find(CALL_OPEN) {
+            prose.push_str(&rest[..start]);
+            let body = &rest[start + CALL_OPEN.len()..];
+            let (call, after) = match body.find(CALL_CLOSE) {
+                Some(end) => (&body[..end], &body[end + CALL_CLOSE.len()..]),
+                // Model stopped before closing the tag
+                None => (body, ""),
+            };
+            match parse_call(call) {
+                Some((name, input)) => calls.push(ContentBlock::tool_use(tool_use_id(), name, input)),
+                None => prose.push_str(&rest[start..rest.len() - after.len()]),
+            }
+            rest = after;
+        }
+        prose.push_str(rest);
+
+        if !prose.trim().is_empty() {
+            content.push(ContentBlock::text(prose.trim().to_string(), None));
+        }
+        found |= !calls.is_empty();
+        content.extend(calls);
+    }
+
+    response.content = content;
+    if found {
+        response.stop_reason = Some("tool_use".to_string());
+    }
+}
+
+/// Parse `{"name": ..., "input": {...}}`, tolerating a surrounding code fence
+fn parse_call(call: &str) -> Option<(String, Value)> {
+    let json = call
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+    let value: Value = serde_json::from_str(json).ok()?;
+    let name = value.get("name")?.as_str()?.to_string();
+    let input = match value.get("input").or_else(|| value.get("arguments")) {
+        Some(Value::Object(map)) => Value::Object(map.clone()),
+        _ => Value::Object(Default::default()),
+    };
+    Some((name, input))
+}
+
+fn tool_use_id() -> String {
+    format!("toolu_{}", uuid::Uuid::new_v4().simple())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::Usage;
+
+    #[test]
+    fn test_embed_tools_flattens_history() {
+        let mut request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "local",
+            "max_tokens": 1024,
+            "system": "You are Claude Code",
+            "tools": [
+                {"name": "Read", "description": "Read a file", "input_schema": {"type": "object"}},
+                {"type": "web_search_20250305", "name": "web_search"}
+            ],
+            "messages": [
+                {"role": "user", "content": "open main.rs"},
+                {"role": "assistant", "content": [{"type": "tool_use", "id": "toolu_1", "name": "Read", "input": {"path": "main.rs"}}]},
+                {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "toolu_1", "content": "fn main() {}"}]}
+            ]
+        }))
+        .unwrap();
+
+        assert!(embed_tools(&mut request));
+        assert!(request.tools.is_none());
+        let Some(SystemPrompt::Text(system)) = &request.system else { panic!("expected text system prompt") };
+        assert!(system.starts_with("You are Claude Code"));
+        assert!(system.contains("## Read"));
+        assert!(!system.contains("web_search"));
+
+        let json = serde_json::to_string(&request.messages).unwrap();
+        assert!(!json.contains("tool_use\""));
+        assert!(json.contains("<tool_call>"));
+        assert!(json.contains("<tool_result id=\\\"toolu_1\\\">"));
+    }
+
+    #[test]
+    fn test_extract_tool_calls() {
+        let mut response = ProviderResponse {
+            id: "msg_1".to_string(),
+            r#type: "message".to_string(),
+            role: "assistant".to_string(),
+            content: vec![ContentBlock::text(
+                "Let me look.\n<tool_call>\n{\"name\": \"Read\", \"input\": {\"path\": \"main.rs\"}}\n</tool_call>".to_string(),
+                None,
+            )],
+            model: "local".to_string(),
+            stop_reason: Some("end_turn".to_string()),
+            stop_sequence: None,
+            usage: Usage { input_tokens: 1, output_tokens: 1, cache_creation_input_tokens: None, cache_read_input_tokens: None },
+            rate_limits: Default::default(),
+        };
+
+        extract_tool_calls(&mut response);
+        assert_eq!(response.stop_reason.as_deref(), Some("tool_use"));
+        assert_eq!(response.content.len(), 2);
+        assert_eq!(response.content[0].as_text(), Some("Let me look."));
+        match &response.content[1] {
+            ContentBlock::Known(KnownContentBlock::ToolUse { name, input, .. }) => {
+                assert_eq!(name, "Read");
+                assert_eq!(input["path"], "main.rs");
+            }
+            other => panic!("expected tool_use, got {:?}", other),
+        }
+
+        // Malformed calls are left as text
+        response.content = vec![ContentBlock::text("<tool_call>not json</tool_call>".to_string(), None)];
+        response.stop_reason = Some("end_turn".to_string());
+        extract_tool_calls(&mut response);
+        assert_eq!(response.stop_reason.as_deref(), Some("end_turn"));
+        assert_eq!(response.content[0].as_text(), Some("<tool_call>not json</tool_call>"));
+    }
+}
@@ -0,0 +1,258 @@
+use super::ProviderResponse;
+use crate::models::request_hash::{hash_messages, RequestHash};
+use crate::models::{ContentBlock, KnownContentBlock, Message, MessageContent};
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Signals older than this don't count toward the rates
+const QUALITY_WINDOW: Duration = Duration::from_secs(3600);
+
+/// Events kept per provider/model
+const MAX_EVENTS: usize = 1000;
+
+/// The same conversation sent again within this long counts as a client retry
+const CLIENT_RETRY_WINDOW: Duration = Duration::from_secs(60);
+
+/// How long to remember which mapping served a conversation state
+/// (the next turn can follow a long tool run)
+const SERVED_TTL: Duration = Duration::from_secs(600);
+
+/// Tool results Claude Code sends back when a tool call couldn't be used as given
+const TOOL_ERROR_MARKERS: &[&str] = &["InputValidationError", "Invalid tool parameters", "No such tool available"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Signal {
+    /// A completed response, with what was wrong with it
+    Response { empty: bool, malformed_tool_call: bool },
+    /// The client re-sent the conversation this mapping answered
+    ClientRetry,
+    /// The client rejected a tool call from this mapping's previous answer
+    ToolCallRejected,
+}
+
+/// Soft quality metrics for a provider/model over the last hour
+#[derive(Debug, Clone, Serialize)]
+pub struct QualityStats {
+    pub provider: String,
+    pub model: String,
+    /// Responses inspected in the window
+    pub responses: usize,
+    pub empty_responses: usize,
+    /// Tool calls with malformed arguments, seen in responses or rejected by the client
+    pub malformed_tool_calls: usize,
+    /// Conversations this mapping answered that the client sent again within 60s
+    pub client_retries: usize,
+    /// (empty + malformed tool calls + client retries) / responses
+    pub problem_rate: f64,
+}
+
+struct Served {
+    provider: String,
+    model: String,
+    at: Instant,
+}
+
+/// Fingerprints of a request's conversation, used to relate requests to each other
+#[derive(Debug, Clone, Copy)]
+pub struct Conversation {
+    /// All messages: equal for a client retry
+    full: RequestHash,
+    /// Messages before the previous assistant turn: equal to the previous request's `full`
+    previous: Option<RequestHash>,
+    /// The last user message reports a rejected tool call
+    tool_call_rejected: bool,
+}
+
+impl Conversation {
+    pub fn new(messages: &[Message]) -> Self {
+        let previous = (messages.len() >= 3).then(|| hash_messages(&messages[..messages.len() - 2]));
+        let tool_call_rejected = messages.last().is_some_and(reports_tool_error);
+        Self { full: hash_messages(messages), previous, tool_call_rejected }
+    }
+}
+
+fn reports_tool_error(message: &Message) -> bool {
+    let MessageContent::Blocks(blocks) = &message.content else {
+        return false;
+    };
+    blocks.iter().any(|block| match block {
+        ContentBlock::Known(KnownContentBlock::ToolResult { is_error: true, content, .. }) => {
+            let text = content.to_string();
+            TOOL_ERROR_MARKERS.iter().any(|m| text.contains(m))
+        }
+        _ => false,
+    })
+}
+
+/// Tracks soft quality signals per provider/model: empty responses, malformed
+/// tool calls and client-side retries. Hard failures are the HealthTracker's job.
+#[derive(Default)]
+pub struct QualityTracker {
+    events: DashMap<(String, String), VecDeque<(Instant, Signal)>>,
+    /// Conversation fingerprint -> mapping that answered it
+    served: DashMap<RequestHash, Served>,
+}
+
+impl QualityTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(&self, provider: &str, model: &str, signal: Signal) {
+        let mut events = self.events.entry((provider.to_string(), model.to_string())).or_default();
+        let now = Instant::now();
+        while events.front().is_some_and(|(at, _)| now.duration_since(*at) > QUALITY_WINDOW) || events.len() >= MAX_EVENTS {
+            events.pop_front();
+        }
+        events.push_back((now, signal));
+    }
+
+    /// Attribute an incoming request's retry or tool rejection to the mapping that
+    /// served the earlier request
+    pub fn observe_request(&self, conversation: &Conversation) {
+        let now = Instant::now();
+        if let Some(served) = self.served.get(&conversation.full) {
+            if now.duration_since(served.at) <= CLIENT_RETRY_WINDOW {
+                self.push(&served.provider, &served.model, Signal::ClientRetry);
+            }
+        }
+        if conversation.tool_call_rejected {
+            if let Some(served) = conversation.previous.and_then(|h| self.served.get(&h)) {
+                self.push(&served.provider, &served.model, Signal::ToolCallRejected);
+            }
+        }
+    }
+
+    /// Remember which mapping answered a conversation
+    pub fn record_served(&self, conversation: &Conversation, provider: &str, model: &str) {
+        let now = Instant::now();
+        if self.served.len() > MAX_EVENTS {
+            self.served.retain(|_, s| now.duration_since(s.at) <= SERVED_TTL);
+        }
+        self.served.insert(
+            conversation.full,
+            Served { provider: provider.to_string(), model: model.to_string(), at: now },
+        );
+    }
+
+    /// Inspect a complete response for empty content and malformed tool calls
+    pub fn record_response(&self, provider: &str, model: &str, response: &ProviderResponse) {
+        let empty = response.content.iter().all(|block| match block {
+            ContentBlock::Known(KnownContentBlock::Text { text, .. }) => text.trim().is_empty(),
+            ContentBlock::Known(KnownContentBlock::Thinking { .. }) => true,
+            _ => false,
+        });
+        let malformed_tool_call = response.content.iter().any(|block| match block {
+            ContentBlock::Known(KnownContentBlock::ToolUse { input, .. }) => !input.is_object(),
+            // Prompt-embedded tool call that couldn't be parsed
+            ContentBlock::Known(KnownContentBlock::Text { text, .. }) => text.contains("<tool_call>"),
+            _ => false,
+        });
+        self.push(provider, model, Signal::Response { empty, malformed_tool_call });
+    }
+
+    /// Snapshot of all tracked provider/models
+    pub fn all_stats(&self) -> Vec<QualityStats> {
+        let now = Instant::now();
+        let mut stats: Vec<QualityStats> = self
+            .events
+            .iter()
+            .map(|entry| {
+                let recent: Vec<Signal> = entry
+                    .value()
+                    .iter()
+                    .filter(|(at, _)| now.duration_since(*at) <= QUALITY_WINDOW)
+                    .map(|(_, signal)| *signal)
+                    .collect();
+                let count = |f: fn(&Signal) -> bool| recent.iter().filter(|s| f(s)).count();
+                let responses = count(|s| matches!(s, Signal::Response { .. }));
+                let empty_responses = count(|s| matches!(s, Signal::Response { empty: true, .. }));
+                let malformed_tool_calls = count(|s| {
+                    matches!(s, Signal::Response { malformed_tool_call: true, .. } | Signal::ToolCallRejected)
+                });
+                let client_retries = count(|s| *s == Signal::ClientRetry);
+                let problems = empty_responses + malformed_tool_calls + client_retries;
+
+                QualityStats {
+                    provider: entry.key().0.clone(),
+                    model: entry.key().1.clone(),
+                    responses,
+                    empty_responses,
+                    malformed_tool_calls,
+                    client_retries,
+                    problem_rate: if responses == 0 { 0.0 } else { problems as f64 / responses as f64 },
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| (&a.provider, &a.model).cmp(&(&b.provider, &b.model)));
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::Usage;
+
+    fn messages(value: serde_json::Value) -> Vec<Message> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn response(content: serde_json::Value) -> ProviderResponse {
+        ProviderResponse {
+            id: "msg_1".to_string(),
+            r#type: "message".to_string(),
+            role: "assistant".to_string(),
+            content: serde_json::from_value(content).unwrap(),
+            model: "m".to_string(),
+            stop_reason: None,
+            stop_sequence: None,
+            usage: Usage { input_tokens: 1, output_tokens: 1, cache_creation_input_tokens: None, cache_read_input_tokens: None },
+            rate_limits: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_response_signals() {
+        let quality = QualityTracker::new();
+        quality.record_response("p", "m", &response(serde_json::json!([{"type": "text", "text": "hello"}])));
+        quality.record_response("p", "m", &response(serde_json::json!([{"type": "text", "text": "  "}])));
+        quality.record_response("p", "m", &response(serde_json::json!([
+            {"type": "tool_use", "id": "t1", "name": "Bash", "input": "{\"command\": "}
+        ])));
+
+        let stats = &quality.all_stats()[0];
+        assert_eq!(stats.responses, 3);
+        assert_eq!(stats.empty_responses, 1);
+        assert_eq!(stats.malformed_tool_calls, 1);
+        assert!((stats.problem_rate - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_client_retry_and_tool_rejection() {
+        let quality = QualityTracker::new();
+        let first = messages(serde_json::json!([{"role": "user", "content": "run the tests"}]));
+        let conversation = Conversation::new(&first);
+        quality.observe_request(&conversation);
+        quality.record_served(&conversation, "p", "m");
+
+        // Same conversation again: a client retry
+        quality.observe_request(&Conversation::new(&first));
+
+        // Next turn rejects the tool call p/m made
+        let next = messages(serde_json::json!([
+            {"role": "user", "content": "run the tests"},
+            {"role": "assistant", "content": [{"type": "tool_use", "id": "t1", "name": "Bash", "input": {}}]},
+            {"role": "user", "content": [{"type": "tool_result", "tool_use_id": "t1", "is_error": true,
+                "content": "<tool_use_error>InputValidationError: Bash failed due to the following issue: The required parameter `command` is missing</tool_use_error>"}]}
+        ]));
+        quality.observe_request(&Conversation::new(&next));
+
+        let stats = &quality.all_stats()[0];
+        assert_eq!(stats.client_retries, 1);
+        assert_eq!(stats.malformed_tool_calls, 1);
+        assert_eq!(stats.responses, 0);
+    }
+}
@@ -0,0 +1,400 @@
+use super::error::ProviderError;
+use crate::cli::{ModelMapping, QuotaConfig};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How long a low-quota observation counts when the provider sent no reset time
+const DEFAULT_RESET_SECS: i64 = 60;
+
+/// Response headers carrying rate limit state
+const RATE_LIMIT_HEADER_PREFIXES: &[&str] = &["anthropic-ratelimit-", "x-ratelimit-"];
+
+/// Collect rate limit headers (Anthropic `anthropic-ratelimit-*`, OpenAI-style `x-ratelimit-*`)
+pub fn rate_limit_headers(headers: &reqwest::header::HeaderMap) -> HashMap<String, String> {
+    headers
+        .iter()
+        .filter(|(name, _)| RATE_LIMIT_HEADER_PREFIXES.iter().any(|p| name.as_str().starts_with(p)))
+        .filter_map(|(name, value)| Some((name.as_str().to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+/// Remaining quota for one limit (requests, tokens, ...)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaLimit {
+    pub name: String,
+    pub remaining: u64,
+    pub limit: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reset_at: Option<DateTime<Utc>>,
+}
+
+impl QuotaLimit {
+    fn fraction(&self) -> f64 {
+        if self.limit == 0 {
+            1.0
+        } else {
+            self.remaining as f64 / self.limit as f64
+        }
+    }
+}
+
+/// Last observed quota for a provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaStatus {
+    pub provider: String,
+    pub limits: Vec<QuotaLimit>,
+    pub observed_at: DateTime<Utc>,
+}
+
+impl QuotaStatus {
+    /// The most constrained limit
+    fn tightest(&self) -> Option<&QuotaLimit> {
+        self.limits.iter().min_by(|a, b| a.fraction().total_cmp(&b.fraction()))
+    }
+
+    /// Remaining fraction of the most constrained limit (1.0 if nothing is known)
+    pub fn remaining_fraction(&self) -> f64 {
+        self.tightest().map(QuotaLimit::fraction).unwrap_or(1.0)
+    }
+
+    /// When the most constrained limit refills
+    fn reset_at(&self) -> DateTime<Utc> {
+        self.tightest()
+            .and_then(|l| l.reset_at)
+            .unwrap_or(self.observed_at + chrono::Duration::seconds(DEFAULT_RESET_SECS))
+    }
+}
+
+/// Parse rate limit headers into per-limit remaining quota.
+///
+/// Anthropic: `anthropic-ratelimit-{requests,tokens,input-tokens,output-tokens}-{limit,remaining,reset}`
+/// with RFC 3339 reset times. OpenAI: `x-ratelimit-{limit,remaining,reset}-{requests,tokens}` with
+/// duration resets like `6m0s`. Limits with no `remaining` value are skipped.
+pub fn parse_limits(headers: &HashMap<String, String>, now: DateTime<Utc>) -> Vec<QuotaLimit> {
+    let mut limits: Vec<QuotaLimit> = Vec::new();
+
+    for (name, value) in headers {
+        let (limit_name, field) = if let Some(rest) = name.strip_prefix("anthropic-ratelimit-") {
+            match rest.rsplit_once('-') {
+                Some((limit_name, field)) => (limit_name.to_string(), field),
+                None => continue,
+            }
+        } else if let Some(rest) = name.strip_prefix("x-ratelimit-") {
+            match rest.split_once('-') {
+                Some((field, limit_name)) => (limit_name.to_string(), field),
+                // OpenRouter-style x-ratelimit-remaining without a resource suffix
+                None => ("requests".to_string(), rest),
+            }
+        } else {
+            continue;
+        };
+
+        let index = match limits.iter().position(|l| l.name == limit_name) {
+            Some(i) => i,
+            None => {
+                limits.push(QuotaLimit { name: limit_name, remaining: u64::MAX, limit: 0, reset_at: None });
+                limits.len() - 1
+            }
+        };
+        let entry = &mut limits[index];
+        let value = value.trim();
+        match field {
+            "remaining" => entry.remaining = value.parse().unwrap_or(u64::MAX),
+            "limit" => entry.limit = value.parse().unwrap_or(0),
+            "reset" => entry.reset_at = parse_reset(value, now),
+            _ => {}
+        }
+    }
+
+    limits.retain(|l| l.remaining != u64::MAX);
+    limits.sort_by(|a, b| a.name.cmp(&b.name));
+    limits
+}
+
+/// Reset times are RFC 3339 timestamps, epoch milliseconds/seconds, or durations ("1s", "6m0s", "20ms")
+fn parse_reset(value: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    if let Ok(ts) = DateTime::parse_from_rfc3339(value) {
+        return Some(ts.with_timezone(&Utc));
+    }
+    if let Ok(n) = value.parse::<i64>() {
+        return match n {
+            n if n > 1_000_000_000_000 => DateTime::from_timestamp_millis(n),
+            n if n > 1_000_000_000 => DateTime::from_timestamp(n, 0),
+            n => Some(now + chrono::Duration::seconds(n)),
+        };
+    }
+    parse_duration_ms(value).map(|ms| now + chrono::Duration::milliseconds(ms))
+}
+
+/// Go-style durations as sent by OpenAI: "1h2m3.5s", "20ms"
+fn parse_duration_ms(value: &str) -> Option<i64> {
+    let mut total = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let split = rest.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+        let number: f64 = rest[..split].parse().ok()?;
+        rest = &rest[split..];
+        let unit_len = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        let multiplier = match &rest[..unit_len] {
+            "h" => 3_600_000.0,
+            "m" => 60_000.0,
+            "s" => 1_000.0,
+            "ms" => 1.0,
+            _ => return None,
+        };
+        total += number * multiplier;
+        rest = &rest[unit_len..];
+    }
+    Some(total as i64)
+}
+
+/// Per-provider quota tracker.
+///
+/// Providers report remaining quota in response headers. When a provider's most
+/// constrained limit drops below `spillover_below` of its size, its mappings are
+/// tried after the others until the limit resets, so new requests spill over to
+/// the next mapping before the provider starts answering 429. A 429 with
+/// Retry-After puts the provider in the same state until the window passes.
+pub struct QuotaTracker {
+    config: QuotaConfig,
+    entries: DashMap<String, QuotaStatus>,
+    /// Provider -> end of the Retry-After window from its last 429
+    cooldowns: DashMap<String, DateTime<Utc>>,
+}
+
+impl QuotaTracker {
+    pub fn new(config: QuotaConfig) -> Self {
+        Self {
+            config,
+            entries: DashMap::new(),
+            cooldowns: DashMap::new(),
+        }
+    }
+
+    /// Record the Retry-After window of a 429
+    pub fn record_rate_limit(&self, provider: &str, error: &ProviderError) {
+        if !self.config.enabled {
+            return;
+        }
+        let Some(retry_after) = error.retry_after() else {
+            return;
+        };
+        let Ok(retry_after) = chrono::Duration::from_std(retry_after) else {
+            return;
+        };
+
+        let until = Utc::now() + retry_after;
+        let mut entry = self.cooldowns.entry(provider.to_string()).or_insert(until);
+        if until > *entry {
+            *entry = until;
+        }
+        tracing::warn!("⏳ {} rate limited, trying its mappings last until {}", provider, entry.format("%H:%M:%S"));
+    }
+
+    /// End of the provider's Retry-After window, if it hasn't passed yet
+    pub fn cooldown_until(&self, provider: &str) -> Option<DateTime<Utc>> {
+        self.cooldowns.get(provider).map(|until| *until).filter(|until| *until > Utc::now())
+    }
+
+    /// Record rate limit headers from a successful response
+    pub fn observe(&self, provider: &str, headers: &HashMap<String, String>) {
+        if !self.config.enabled || headers.is_empty() {
+            return;
+        }
+
+        let now = Utc::now();
+        let limits = parse_limits(headers, now);
+        if limits.is_empty() {
+            return;
+        }
+
+        let status = QuotaStatus { provider: provider.to_string(), limits, observed_at: now };
+        let was_low = self.is_exhausted(provider);
+        let low = status.remaining_fraction() < self.config.spillover_below;
+        if low && !was_low {
+            tracing::warn!(
+                "🪫 {} quota nearly exhausted ({:.1}% left), spilling over to other mappings until {}",
+                provider,
+                status.remaining_fraction() * 100.0,
+                status.reset_at().format("%H:%M:%S")
+            );
+        }
+        self.entries.insert(provider.to_string(), status);
+    }
+
+    /// Provider is below the spillover threshold and its limit hasn't reset yet,
+    /// or is inside a Retry-After window
+    pub fn is_exhausted(&self, provider: &str) -> bool {
+        if self.cooldown_until(provider).is_some() {
+            return true;
+        }
+        let Some(status) = self.entries.get(provider) else {
+            return false;
+        };
+        status.remaining_fraction() < self.config.spillover_below && status.reset_at() > Utc::now()
+    }
+
+    /// Move mappings whose provider is nearly out of quota to the end (stable)
+    pub fn deprioritize_exhausted(&self, mappings: &mut [ModelMapping]) {
+        if !self.config.enabled || (self.entries.is_empty() && self.cooldowns.is_empty()) {
+            return;
+        }
+        mappings.sort_by_key(|m| self.is_exhausted(&m.provider));
+    }
+
+    /// Retry-After windows that haven't passed yet
+    pub fn cooldowns(&self) -> Vec<(String, DateTime<Utc>)> {
+        let now = Utc::now();
+        self.cooldowns.iter().filter(|e| *e.value() > now).map(|e| (e.key().clone(), *e.value())).collect()
+    }
+
+    /// Adopt quota observed by other instances sharing the same accounts: newer
+    /// observations replace ours, and the later of two Retry-After windows wins
+    pub fn merge(&self, statuses: Vec<QuotaStatus>, cooldowns: Vec<(String, DateTime<Utc>)>) {
+        if !self.config.enabled {
+            return;
+        }
+        for status in statuses {
+            let newer = self.entries.get(&status.provider).is_none_or(|current| status.observed_at > current.observed_at);
+            if newer {
+                self.entries.insert(status.provider.clone(), status);
+            }
+        }
+        for (provider, until) in cooldowns {
+            let mut entry = self.cooldowns.entry(provider).or_insert(until);
+            if until > *entry {
+                *entry = until;
+            }
+        }
+    }
+
+    /// Snapshot of all observed providers
+    pub fn statuses(&self) -> Vec<QuotaStatus> {
+        let mut statuses: Vec<QuotaStatus> = self.entries.iter().map(|e| e.value().clone()).collect();
+        statuses.sort_by(|a, b| a.provider.cmp(&b.provider));
+        statuses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn tracker() -> QuotaTracker {
+        QuotaTracker::new(QuotaConfig { enabled: true, spillover_below: 0.05, ..Default::default() })
+    }
+
+    fn mapping(priority: u32, provider: &str) -> ModelMapping {
+        ModelMapping {
+            priority,
+            provider: provider.to_string(),
+            actual_model: "m".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_anthropic_headers() {
+        let now = Utc::now();
+        let limits = parse_limits(&headers(&[
+            ("anthropic-ratelimit-requests-limit", "50"),
+            ("anthropic-ratelimit-requests-remaining", "49"),
+            ("anthropic-ratelimit-requests-reset", "2030-01-01T00:00:00Z"),
+            ("anthropic-ratelimit-input-tokens-limit", "40000"),
+            ("anthropic-ratelimit-input-tokens-remaining", "1000"),
+        ]), now);
+
+        assert_eq!(limits.len(), 2);
+        assert_eq!(limits[0].name, "input-tokens");
+        assert_eq!(limits[0].remaining, 1000);
+        assert_eq!(limits[1].name, "requests");
+        assert!(limits[1].reset_at.is_some());
+    }
+
+    #[test]
+    fn test_parse_openai_headers() {
+        let now = Utc::now();
+        let limits = parse_limits(&headers(&[
+            ("x-ratelimit-limit-requests", "500"),
+            ("x-ratelimit-remaining-requests", "499"),
+            ("x-ratelimit-reset-requests", "6m0s"),
+            ("x-ratelimit-limit-tokens", "30000"),
+            ("x-ratelimit-remaining-tokens", "29000"),
+        ]), now);
+
+        let requests = limits.iter().find(|l| l.name == "requests").unwrap();
+        assert_eq!(requests.reset_at, Some(now + chrono::Duration::minutes(6)));
+        assert_eq!(limits.iter().find(|l| l.name == "tokens").unwrap().limit, 30000);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration_ms("1s"), Some(1000));
+        assert_eq!(parse_duration_ms("6m0s"), Some(360_000));
+        assert_eq!(parse_duration_ms("1h2m3.5s"), Some(3_723_500));
+        assert_eq!(parse_duration_ms("20ms"), Some(20));
+        assert_eq!(parse_duration_ms("soon"), None);
+    }
+
+    #[test]
+    fn test_spills_over_before_exhaustion() {
+        let quota = tracker();
+        quota.observe("primary", &headers(&[
+            ("anthropic-ratelimit-tokens-limit", "100000"),
+            ("anthropic-ratelimit-tokens-remaining", "2000"),
+            ("anthropic-ratelimit-tokens-reset", "2099-01-01T00:00:00Z"),
+        ]));
+        assert!(quota.is_exhausted("primary"));
+
+        let mut mappings = vec![mapping(1, "primary"), mapping(2, "secondary")];
+        quota.deprioritize_exhausted(&mut mappings);
+        assert_eq!(mappings[0].provider, "secondary");
+
+        // Quota refilled
+        quota.observe("primary", &headers(&[
+            ("anthropic-ratelimit-tokens-limit", "100000"),
+            ("anthropic-ratelimit-tokens-remaining", "90000"),
+        ]));
+        assert!(!quota.is_exhausted("primary"));
+    }
+
+    #[test]
+    fn test_past_reset_is_not_exhausted() {
+        let quota = tracker();
+        quota.observe("p", &headers(&[
+            ("x-ratelimit-limit-requests", "100"),
+            ("x-ratelimit-remaining-requests", "0"),
+            ("x-ratelimit-reset-requests", "2001-01-01T00:00:00Z"),
+        ]));
+        assert!(!quota.is_exhausted("p"));
+    }
+
+    #[test]
+    fn test_retry_after_cooldown() {
+        let quota = tracker();
+        quota.record_rate_limit("p", &ProviderError::from_status(500, None, "boom".to_string()));
+        assert!(!quota.is_exhausted("p"));
+
+        let limited = ProviderError::from_status(429, Some(std::time::Duration::from_secs(30)), "slow down".to_string());
+        quota.record_rate_limit("p", &limited);
+        assert!(quota.is_exhausted("p"));
+        assert!(quota.cooldown_until("p").unwrap() > Utc::now() + chrono::Duration::seconds(25));
+
+        let mut mappings = vec![mapping(1, "p"), mapping(2, "q")];
+        quota.deprioritize_exhausted(&mut mappings);
+        assert_eq!(mappings[0].provider, "q");
+
+        // A shorter window doesn't cut the existing one
+        quota.record_rate_limit("p", &ProviderError::from_status(429, Some(std::time::Duration::ZERO), String::new()));
+        assert!(quota.is_exhausted("p"));
+
+        quota.record_rate_limit("r", &ProviderError::from_status(429, Some(std::time::Duration::ZERO), String::new()));
+        assert!(!quota.is_exhausted("r"));
+    }
+}
@@ -1,7 +1,12 @@
 use super::{AnthropicProvider, ProviderConfig, OpenAIProvider, AnthropicCompatibleProvider, error::ProviderError};
+use super::bedrock::BedrockProvider;
+use super::copilot::CopilotProvider;
 use super::gemini::GeminiProvider;
+use super::ollama::OllamaProvider;
+use super::retry::RetryingProvider;
+use super::timeout::TimeoutProvider;
 use crate::auth::TokenStore;
-use crate::cli::ModelConfig;
+use crate::cli::{ModelConfig, TimeoutConfig};
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -11,6 +16,13 @@ const DEFAULT_OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
 /// GitHub repository URL (used in HTTP-Referer headers)
 const REPO_URL: &str = "https://github.com/elidickinson/claude-code-mux";
 
+/// Accepted `provider_type` values, including deprecated aliases
+/// (keep in sync with `from_configs_with_models`)
+pub const PROVIDER_TYPES: &[&str] = &[
+    "openai", "openrouter", "anthropic", "z.ai", "minimax", "zenmux", "kimi-coding", "gemini", "vertex-ai", "bedrock",
+    "ollama", "mistral", "deepseek", "copilot", "deepinfra", "novita", "baseten", "together", "fireworks", "groq", "nebius", "cerebras", "moonshot",
+];
+
 /// Provider registry that manages all configured providers
 pub struct ProviderRegistry {
     /// Map of provider name -> provider instance
@@ -31,11 +43,16 @@ impl ProviderRegistry {
     /// Load providers from configuration
     #[allow(dead_code)]
     pub fn from_configs(configs: &[ProviderConfig], token_store: Option<TokenStore>) -> Result<Self, ProviderError> {
-        Self::from_configs_with_models(configs, token_store, &[])
+        Self::from_configs_with_models(configs, token_store, &[], &TimeoutConfig::default())
     }
 
-    /// Load providers from configuration with model mappings
-    pub fn from_configs_with_models(configs: &[ProviderConfig], token_store: Option<TokenStore>, models: &[ModelConfig]) -> Result<Self, ProviderError> {
+    /// Load providers from configuration with model mappings, each attempt bounded by `timeouts`
+    pub fn from_configs_with_models(
+        configs: &[ProviderConfig],
+        token_store: Option<TokenStore>,
+        models: &[ModelConfig],
+        timeouts: &TimeoutConfig,
+    ) -> Result<Self, ProviderError> {
         let mut registry = Self::new();
 
         for config in configs {
@@ -46,8 +63,13 @@ impl ProviderRegistry {
 
             // Get API key - required for API key auth, skipped for OAuth
             let api_key = match &config.auth_type {
+                // Bedrock signs with AWS credentials unless given a Bedrock API key;
+                // a local Ollama needs no key; Copilot uses its stored GitHub login
+                super::AuthType::ApiKey if matches!(config.provider_type.as_str(), "bedrock" | "ollama" | "copilot") => {
+                    config.api_key().unwrap_or_default().to_string()
+                }
                 super::AuthType::ApiKey => {
-                    config.api_key.clone().ok_or_else(|| {
+                    config.api_key().map(String::from).ok_or_else(|| {
                         ProviderError::ConfigError(
                             format!("Provider '{}' requires api_key for ApiKey auth", config.name)
                         )
@@ -99,6 +121,24 @@ impl ProviderRegistry {
                     token_store.clone(),
                 )),
 
+                // Mistral AI (OpenAI-compatible with short tool call IDs)
+                "mistral" => Box::new(OpenAIProvider::mistral(
+                    config.name.clone(),
+                    api_key,
+                    config.base_url.clone(),
+                    config.models.clone(),
+                    config.headers.clone().unwrap_or_default().into_iter().collect(),
+                )),
+
+                // DeepSeek (OpenAI-compatible with thinking mode / reasoning_content)
+                "deepseek" => Box::new(OpenAIProvider::deepseek(
+                    config.name.clone(),
+                    api_key,
+                    config.base_url.clone(),
+                    config.models.clone(),
+                    config.headers.clone().unwrap_or_default().into_iter().collect(),
+                )),
+
                 // Deprecated aliases for OpenAI-compatible providers
                 // These will be removed in a future version
                 // NOTE: Preset URLs/headers here must match OPENAI_PRESETS in admin.html
@@ -179,27 +219,27 @@ impl ProviderRegistry {
                     config.models.clone(),
                     config.oauth_provider.clone(),
                     token_store.clone(),
-                )),
+                ).with_cache_ttl(config.cache_ttl.clone())),
                 "z.ai" => Box::new(AnthropicCompatibleProvider::zai(
                     api_key,
                     config.models.clone(),
                     token_store.clone(),
-                )),
+                ).with_cache_ttl(config.cache_ttl.clone())),
                 "minimax" => Box::new(AnthropicCompatibleProvider::minimax(
                     api_key,
                     config.models.clone(),
                     token_store.clone(),
-                )),
+                ).with_cache_ttl(config.cache_ttl.clone())),
                 "zenmux" => Box::new(AnthropicCompatibleProvider::zenmux(
                     api_key,
                     config.models.clone(),
                     token_store.clone(),
-                )),
+                ).with_cache_ttl(config.cache_ttl.clone())),
                 "kimi-coding" => Box::new(AnthropicCompatibleProvider::kimi_coding(
                     api_key,
                     config.models.clone(),
                     token_store.clone(),
-                )),
+                ).with_cache_ttl(config.cache_ttl.clone())),
 
                 // Google Gemini (supports OAuth, API Key, Vertex AI)
                 "gemini" => {
@@ -238,6 +278,31 @@ impl ProviderRegistry {
                     ))
                 }
 
+                // AWS Bedrock (Anthropic models, SigV4 or Bedrock API key)
+                "bedrock" => Box::new(BedrockProvider::new(
+                    config.name.clone(),
+                    Some(api_key),
+                    config.location.clone(),
+                    config.base_url.clone(),
+                    config.models.clone(),
+                ).with_cache_ttl(config.cache_ttl.clone())),
+
+                // Local Ollama (OpenAI-compatible API, models discovered from /api/tags)
+                "ollama" => Box::new(OllamaProvider::new(
+                    config.name.clone(),
+                    Some(api_key),
+                    config.base_url.clone(),
+                    config.models.clone(),
+                )),
+
+                // GitHub Copilot (login stored by `ccm copilot-login`)
+                "copilot" => Box::new(CopilotProvider::new(
+                    config.name.clone(),
+                    config.oauth_provider.clone().unwrap_or_else(|| config.name.clone()),
+                    config.models.clone(),
+                    token_store.clone(),
+                )),
+
                 other => {
                     return Err(ProviderError::ConfigError(
                         format!("Unknown provider type: {}", other)
@@ -249,6 +314,13 @@ impl ProviderRegistry {
             // Model mappings are now defined in [[models]] section
             // We only register the provider by name
 
+            // Timeouts apply per try, so retries each get the full limits
+            let provider: Box<dyn AnthropicProvider> = Box::new(TimeoutProvider::new(config.name.clone(), provider, timeouts.clone()));
+            let provider: Box<dyn AnthropicProvider> = match config.retry.clone() {
+                Some(retry) if retry.attempts > 1 => Box::new(RetryingProvider::new(config.name.clone(), provider, retry)),
+                _ => provider,
+            };
+
             // Add provider to registry
             registry.providers.insert(config.name.clone(), Arc::new(provider));
         }
@@ -264,6 +336,12 @@ impl ProviderRegistry {
         Ok(registry)
     }
 
+    /// Add a provider instance under `name`, replacing any provider of that name
+    #[allow(dead_code)] // Used by the embedding API (`embed`), not the binary
+    pub fn register(&mut self, name: impl Into<String>, provider: Box<dyn AnthropicProvider>) {
+        self.providers.insert(name.into(), Arc::new(provider));
+    }
+
     /// Get a provider by name
     pub fn get_provider(&self, name: &str) -> Option<Arc<Box<dyn AnthropicProvider>>> {
         self.providers.get(name).cloned()
@@ -271,21 +349,25 @@ impl ProviderRegistry {
 
     /// Get a provider for a specific model
     pub fn get_provider_for_model(&self, model: &str) -> Result<Arc<Box<dyn AnthropicProvider>>, ProviderError> {
+        self.provider_name_for_model(model)
+            .and_then(|name| self.providers.get(&name).cloned())
+            .ok_or_else(|| ProviderError::ModelNotSupported(model.to_string()))
+    }
+
+    /// Name of the provider that serves a model
+    pub fn provider_name_for_model(&self, model: &str) -> Option<String> {
         // First, check if we have a direct model → provider mapping
         if let Some(provider_name) = self.model_to_provider.get(model) {
-            if let Some(provider) = self.providers.get(provider_name) {
-                return Ok(provider.clone());
+            if self.providers.contains_key(provider_name) {
+                return Some(provider_name.clone());
             }
         }
 
         // If no direct mapping, search through all providers
-        for provider in self.providers.values() {
-            if provider.supports_model(model) {
-                return Ok(provider.clone());
-            }
-        }
-
-        Err(ProviderError::ModelNotSupported(model.to_string()))
+        self.providers
+            .iter()
+            .find(|(_, provider)| provider.supports_model(model))
+            .map(|(name, _)| name.clone())
     }
 
     /// List all available models
@@ -308,6 +390,7 @@ impl Default for ProviderRegistry {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use secrecy::SecretString;
 
     #[test]
     fn test_empty_registry() {
@@ -323,6 +406,32 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_all_provider_types_build() {
+        use crate::providers::AuthType;
+
+        for provider_type in PROVIDER_TYPES {
+            let config = ProviderConfig {
+                name: provider_type.to_string(),
+                provider_type: provider_type.to_string(),
+                auth_type: AuthType::ApiKey,
+                api_key: Some(SecretString::new("test-key".to_string())),
+                base_url: None,
+                models: vec![],
+                enabled: Some(true),
+                oauth_provider: None,
+                project_id: None,
+                location: None,
+                headers: None,
+                region: None,
+                retry: None,
+                cache_ttl: None,
+                keep_warm: None,
+            };
+            assert!(ProviderRegistry::from_configs(&[config], None).is_ok(), "{} should build", provider_type);
+        }
+    }
+
     #[test]
     fn test_model_counting_with_configs() {
         use crate::providers::{ProviderConfig, AuthType};
@@ -332,7 +441,7 @@ mod tests {
                 name: "provider-a".to_string(),
                 provider_type: "anthropic".to_string(),
                 auth_type: AuthType::ApiKey,
-                api_key: Some("test-key-1".to_string()),
+                api_key: Some(SecretString::new("test-key-1".to_string())),
                 base_url: None,
                 models: vec![],
                 enabled: Some(true),
@@ -340,12 +449,16 @@ mod tests {
                 project_id: None,
                 location: None,
                 headers: None,
+                region: None,
+                retry: None,
+                cache_ttl: None,
+                keep_warm: None,
             },
             ProviderConfig {
                 name: "provider-b".to_string(),
                 provider_type: "anthropic".to_string(),
                 auth_type: AuthType::ApiKey,
-                api_key: Some("test-key-2".to_string()),
+                api_key: Some(SecretString::new("test-key-2".to_string())),
                 base_url: None,
                 models: vec![],
                 enabled: Some(true),
@@ -353,6 +466,10 @@ mod tests {
                 project_id: None,
                 location: None,
                 headers: None,
+                region: None,
+                retry: None,
+                cache_ttl: None,
+                keep_warm: None,
             },
         ];
 
@@ -365,8 +482,20 @@ mod tests {
                         provider: "provider-a".to_string(),
                         actual_model: "actual-model-1".to_string(),
                         inject_continuation_prompt: false,
+                        continuation: None,
+                        force_non_streaming: false,
+                        force_streaming: false,
+                        prompt_tools: false,
+                        inject_identity: false,
+                        identity_details: None,
+                        routes: vec![],
+                        extra_body: None,
                     }
                 ],
+                residency: None,
+                strategy: Default::default(),
+                ordering: Default::default(),
+                max_cost_per_mtok: None,
             },
             crate::cli::ModelConfig {
                 name: "model-2".to_string(),
@@ -376,8 +505,20 @@ mod tests {
                         provider: "provider-b".to_string(),
                         actual_model: "actual-model-2".to_string(),
                         inject_continuation_prompt: false,
+                        continuation: None,
+                        force_non_streaming: false,
+                        force_streaming: false,
+                        prompt_tools: false,
+                        inject_identity: false,
+                        identity_details: None,
+                        routes: vec![],
+                        extra_body: None,
                     }
                 ],
+                residency: None,
+                strategy: Default::default(),
+                ordering: Default::default(),
+                max_cost_per_mtok: None,
             },
         ];
 
@@ -385,7 +526,8 @@ mod tests {
         let registry = ProviderRegistry::from_configs_with_models(
             &providers,
             None,  // token_store
-            &models
+            &models,
+            &TimeoutConfig::default(),
         ).unwrap();
 
         assert_eq!(registry.list_models().len(), 2);
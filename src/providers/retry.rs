@@ -0,0 +1,242 @@
+use super::error::ProviderError;
+use super::{AnthropicProvider, ProviderResponse, StreamResponse};
+use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse};
+use async_trait::async_trait;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::Duration;
+use tracing::warn;
+
+/// Per-provider retry policy for transient upstream errors (`[providers.retry]`)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RetryConfig {
+    /// Total tries against this provider, including the first (1 = no retries)
+    #[serde(default = "default_attempts")]
+    pub attempts: u32,
+    /// Delay before the first retry; doubles on each further retry
+    #[serde(default = "default_backoff_ms")]
+    pub backoff_ms: u64,
+    /// Upper bound for a single delay
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// Random spread applied to each delay, as a fraction (0.2 = ±20%)
+    #[serde(default = "default_jitter")]
+    pub jitter: f64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            attempts: default_attempts(),
+            backoff_ms: default_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+            jitter: default_jitter(),
+        }
+    }
+}
+
+fn default_attempts() -> u32 {
+    3
+}
+
+fn default_backoff_ms() -> u64 {
+    500
+}
+
+fn default_max_backoff_ms() -> u64 {
+    8000
+}
+
+fn default_jitter() -> f64 {
+    0.2
+}
+
+impl RetryConfig {
+    /// Delay before retry number `retry` (1-based), without jitter
+    fn base_delay(&self, retry: u32) -> Duration {
+        let factor = 2u64.saturating_pow(retry.saturating_sub(1));
+        Duration::from_millis(self.backoff_ms.saturating_mul(factor).min(self.max_backoff_ms))
+    }
+
+    fn delay(&self, retry: u32) -> Duration {
+        let base = self.base_delay(retry);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return base;
+        }
+        base.mul_f64(rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter))
+    }
+}
+
+/// Transient failures worth retrying against the same provider: 5xx/529 and
+/// connection-level errors. Rate limits are left to the failover loop.
+fn is_transient(error: &ProviderError) -> bool {
+    match error {
+        ProviderError::Overloaded { .. } => true,
+        ProviderError::ApiError { status, .. } => *status >= 500,
+        ProviderError::HttpError(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+        _ => false,
+    }
+}
+
+/// Wraps a provider and retries transient errors with exponential backoff before
+/// the error reaches the failover loop
+pub struct RetryingProvider {
+    name: String,
+    inner: Box<dyn AnthropicProvider>,
+    config: RetryConfig,
+}
+
+impl RetryingProvider {
+    pub fn new(name: String, inner: Box<dyn AnthropicProvider>, config: RetryConfig) -> Self {
+        Self { name, inner, config }
+    }
+
+    async fn with_retries<T, F, Fut>(&self, what: &str, mut call: F) -> Result<T, ProviderError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ProviderError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match call().await {
+                Err(e) if attempt < self.config.attempts && is_transient(&e) => {
+                    let delay = self.config.delay(attempt);
+                    warn!(
+                        "🔁 {} {} failed ({}), retry {}/{} in {}ms",
+                        self.name,
+                        what,
+                        e,
+                        attempt,
+                        self.config.attempts - 1,
+                        delay.as_millis()
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl AnthropicProvider for RetryingProvider {
+    async fn send_message(&self, request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+        self.with_retries("request", || self.inner.send_message(request.clone())).await
+    }
+
+    /// Only opening the stream is retried; errors after the first byte are passed through
+    async fn send_message_stream(&self, request: AnthropicRequest) -> Result<StreamResponse, ProviderError> {
+        self.with_retries("stream", || self.inner.send_message_stream(request.clone())).await
+    }
+
+    async fn count_tokens(&self, request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+        self.with_retries("count_tokens", || self.inner.count_tokens(request.clone())).await
+    }
+
+    fn supports_model(&self, model: &str) -> bool {
+        self.inner.supports_model(model)
+    }
+
+    fn supports_prompt_cache(&self) -> bool {
+        self.inner.supports_prompt_cache()
+    }
+
+    fn accepts_image_urls(&self) -> bool {
+        self.inner.accepts_image_urls()
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<String>>, ProviderError> {
+        self.inner.list_models().await
+    }
+
+    async fn keep_warm(&self, model: &str, keep_alive: std::time::Duration) -> Result<(), ProviderError> {
+        self.inner.keep_warm(model, keep_alive).await
+    }
+
+    async fn is_loaded(&self, model: &str) -> Result<Option<bool>, ProviderError> {
+        self.inner.is_loaded(model).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    /// Fails with the given status until `failures` calls have been made
+    struct Flaky {
+        calls: Arc<AtomicU32>,
+        failures: u32,
+        status: u16,
+    }
+
+    #[async_trait]
+    impl AnthropicProvider for Flaky {
+        async fn send_message(&self, _request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+            unimplemented!()
+        }
+
+        async fn send_message_stream(&self, _request: AnthropicRequest) -> Result<StreamResponse, ProviderError> {
+            unimplemented!()
+        }
+
+        async fn count_tokens(&self, _request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            if call <= self.failures {
+                return Err(ProviderError::from_status(self.status, None, "upstream hiccup".to_string()));
+            }
+            Ok(CountTokensResponse { input_tokens: 42 })
+        }
+
+        fn supports_model(&self, _model: &str) -> bool {
+            true
+        }
+    }
+
+    fn retrying(failures: u32, status: u16) -> (RetryingProvider, Arc<AtomicU32>) {
+        let calls = Arc::new(AtomicU32::new(0));
+        let inner = Flaky { calls: calls.clone(), failures, status };
+        let config = RetryConfig { attempts: 3, backoff_ms: 1, max_backoff_ms: 5, jitter: 0.5 };
+        (RetryingProvider::new("flaky".to_string(), Box::new(inner), config), calls)
+    }
+
+    fn count_request() -> CountTokensRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "m",
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_errors() {
+        let (provider, calls) = retrying(2, 529);
+        assert_eq!(provider.count_tokens(count_request()).await.unwrap().input_tokens, 42);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // Gives up after the configured attempts
+        let (provider, calls) = retrying(5, 500);
+        assert!(provider.count_tokens(count_request()).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // Client errors and rate limits go straight to failover
+        for status in [400, 429] {
+            let (provider, calls) = retrying(1, status);
+            assert!(provider.count_tokens(count_request()).await.is_err());
+            assert_eq!(calls.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let config = RetryConfig { attempts: 10, backoff_ms: 500, max_backoff_ms: 3000, jitter: 0.0 };
+        assert_eq!(config.delay(1), Duration::from_millis(500));
+        assert_eq!(config.delay(2), Duration::from_millis(1000));
+        assert_eq!(config.delay(4), Duration::from_millis(3000));
+        assert_eq!(config.delay(40), Duration::from_millis(3000));
+    }
+}
@@ -0,0 +1,279 @@
+//! Latency SLOs per route type (`[[slos]]`), e.g. think p90 under 30s.
+//!
+//! A turn's latency runs from the request arriving to the last byte of the
+//! response, so streamed answers count in full. Only successful turns count.
+//! Percentiles are computed over a rolling time window; an SLO is evaluated once
+//! it has `min_samples` turns in its window, and an alert is raised when it
+//! starts or stops being met.
+
+use crate::cli::route_key;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Latency samples kept per route, whatever the window
+const MAX_SAMPLES: usize = 10_000;
+
+/// A latency objective for one route type
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SloConfig {
+    /// Route type (`think`, `background`, `default`, ...)
+    pub route: String,
+    /// Percentile to hold under the limit (e.g. 90 for p90)
+    #[serde(default = "default_percentile")]
+    pub percentile: f64,
+    /// Latency limit for a whole turn, in seconds
+    pub max_latency_secs: f64,
+    /// Rolling window the percentile is computed over
+    #[serde(default = "default_window_minutes")]
+    pub window_minutes: u64,
+    /// Turns needed in the window before the SLO is evaluated
+    #[serde(default = "default_min_samples")]
+    pub min_samples: usize,
+    /// URL to POST a JSON alert to when the SLO starts or stops being met
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+}
+
+fn default_percentile() -> f64 {
+    90.0
+}
+
+fn default_window_minutes() -> u64 {
+    60
+}
+
+fn default_min_samples() -> usize {
+    20
+}
+
+impl SloConfig {
+    /// e.g. `think p90 < 30s`
+    pub fn label(&self) -> String {
+        format!("{} p{} < {}s", self.route, self.percentile, self.max_latency_secs)
+    }
+
+    fn window(&self) -> Duration {
+        Duration::from_secs(self.window_minutes.saturating_mul(60))
+    }
+
+    fn max_latency_ms(&self) -> u64 {
+        (self.max_latency_secs * 1000.0) as u64
+    }
+}
+
+/// Where an SLO stands over its current window
+#[derive(Debug, Clone, Serialize)]
+pub struct SloStatus {
+    pub slo: String,
+    pub route: String,
+    pub percentile: f64,
+    pub max_latency_ms: u64,
+    /// Turns in the window
+    pub samples: usize,
+    /// Latency at the percentile (None without samples)
+    pub observed_ms: Option<u64>,
+    /// Fraction of turns in the window within the limit (None without samples)
+    pub compliance: Option<f64>,
+    /// Whether the percentile is within the limit (None until `min_samples` turns)
+    pub met: Option<bool>,
+    /// Times the SLO has gone from met to violated since startup
+    pub violations: u64,
+}
+
+/// An SLO that started or stopped being met
+#[derive(Debug, Clone)]
+pub struct SloAlert {
+    pub slo: SloConfig,
+    pub violated: bool,
+    pub observed_ms: u64,
+    pub samples: usize,
+}
+
+impl std::fmt::Display for SloAlert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: p{} is {:.2}s over the last {} turns in {}m",
+            self.slo.label(),
+            self.slo.percentile,
+            self.observed_ms as f64 / 1000.0,
+            self.samples,
+            self.slo.window_minutes
+        )
+    }
+}
+
+/// Per-SLO state kept across evaluations
+#[derive(Debug, Default, Clone, Copy)]
+struct SloState {
+    violated: bool,
+    violations: u64,
+}
+
+/// Rolling turn latencies per route type
+#[derive(Default)]
+pub struct SloTracker {
+    /// (when, latency_ms) by route key, oldest first
+    samples: DashMap<String, VecDeque<(Instant, u64)>>,
+    /// Keyed by label, so state carries over a reload that keeps the SLO
+    states: DashMap<String, SloState>,
+}
+
+impl SloTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a successful turn on `route` and return the alerts it triggers.
+    /// Routes without an SLO aren't tracked.
+    pub fn record(&self, slos: &[SloConfig], route: &str, latency_ms: u64) -> Vec<SloAlert> {
+        self.record_at(slos, route, latency_ms, Instant::now())
+    }
+
+    fn record_at(&self, slos: &[SloConfig], route: &str, latency_ms: u64, now: Instant) -> Vec<SloAlert> {
+        let key = route_key(route);
+        let slos: Vec<&SloConfig> = slos.iter().filter(|s| route_key(&s.route) == key).collect();
+        let Some(longest) = slos.iter().map(|s| s.window()).max() else {
+            return Vec::new();
+        };
+
+        let mut samples = self.samples.entry(key).or_default();
+        samples.push_back((now, latency_ms));
+        while samples.len() > MAX_SAMPLES || samples.front().is_some_and(|(at, _)| now.duration_since(*at) > longest) {
+            samples.pop_front();
+        }
+
+        let mut alerts = Vec::new();
+        for slo in slos {
+            let window = in_window(&samples, slo, now);
+            if window.len() < slo.min_samples.max(1) {
+                continue;
+            }
+            let observed_ms = percentile(&window, slo.percentile);
+            let violated = observed_ms > slo.max_latency_ms();
+            let mut state = self.states.entry(slo.label()).or_default();
+            if state.violated != violated {
+                state.violated = violated;
+                if violated {
+                    state.violations += 1;
+                }
+                alerts.push(SloAlert { slo: slo.clone(), violated, observed_ms, samples: window.len() });
+            }
+        }
+        alerts
+    }
+
+    pub fn statuses(&self, slos: &[SloConfig]) -> Vec<SloStatus> {
+        self.statuses_at(slos, Instant::now())
+    }
+
+    fn statuses_at(&self, slos: &[SloConfig], now: Instant) -> Vec<SloStatus> {
+        slos.iter()
+            .map(|slo| {
+                let window = self
+                    .samples
+                    .get(&route_key(&slo.route))
+                    .map(|samples| in_window(&samples, slo, now))
+                    .unwrap_or_default();
+                let max_latency_ms = slo.max_latency_ms();
+                let observed_ms = (!window.is_empty()).then(|| percentile(&window, slo.percentile));
+                let within = window.iter().filter(|ms| **ms <= max_latency_ms).count();
+                SloStatus {
+                    slo: slo.label(),
+                    route: slo.route.clone(),
+                    percentile: slo.percentile,
+                    max_latency_ms,
+                    samples: window.len(),
+                    observed_ms,
+                    compliance: (!window.is_empty()).then(|| within as f64 / window.len() as f64),
+                    met: observed_ms.filter(|_| window.len() >= slo.min_samples.max(1)).map(|ms| ms <= max_latency_ms),
+                    violations: self.states.get(&slo.label()).map_or(0, |s| s.violations),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Latencies recorded within the SLO's window
+fn in_window(samples: &VecDeque<(Instant, u64)>, slo: &SloConfig, now: Instant) -> Vec<u64> {
+    samples
+        .iter()
+        .filter(|(at, _)| now.duration_since(*at) <= slo.window())
+        .map(|(_, ms)| *ms)
+        .collect()
+}
+
+/// Nearest-rank percentile of a non-empty sample
+fn percentile(values: &[u64], percentile: f64) -> u64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slo(route: &str, percentile: f64, max_latency_secs: f64) -> SloConfig {
+        SloConfig {
+            route: route.to_string(),
+            percentile,
+            max_latency_secs,
+            window_minutes: 10,
+            min_samples: 5,
+            webhook: None,
+        }
+    }
+
+    #[test]
+    fn test_percentile() {
+        let values: Vec<u64> = (1..=10).collect();
+        assert_eq!(percentile(&values, 50.0), 5);
+        assert_eq!(percentile(&values, 90.0), 9);
+        assert_eq!(percentile(&values, 99.0), 10);
+        assert_eq!(percentile(&[7], 90.0), 7);
+    }
+
+    #[test]
+    fn test_slo_alerts_on_transitions() {
+        let tracker = SloTracker::new();
+        let slos = vec![slo("think", 90.0, 2.0), slo("background", 90.0, 1.0)];
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // Not evaluated before min_samples
+        for i in 0..4 {
+            assert!(tracker.record_at(&slos, "think", 5_000, at(i)).is_empty());
+        }
+        let alerts = tracker.record_at(&slos, "think", 5_000, at(4));
+        assert_eq!(alerts.len(), 1);
+        assert!(alerts[0].violated);
+        assert_eq!(alerts[0].slo.route, "think");
+        assert_eq!(alerts[0].observed_ms, 5_000);
+
+        // Still violated: no new alert. Routes without an SLO aren't tracked.
+        assert!(tracker.record_at(&slos, "think", 5_000, at(5)).is_empty());
+        assert!(tracker.record_at(&slos, "default", 60_000, at(5)).is_empty());
+
+        // Old samples leave the window; fast turns bring the SLO back
+        let later = 5 + 10 * 60 + 1;
+        let mut recovered = Vec::new();
+        for i in 0..5 {
+            recovered.extend(tracker.record_at(&slos, "think", 500, at(later + i)));
+        }
+        assert_eq!(recovered.len(), 1);
+        assert!(!recovered[0].violated);
+
+        let statuses = tracker.statuses_at(&slos, at(later + 5));
+        assert_eq!(statuses[0].samples, 5);
+        assert_eq!(statuses[0].observed_ms, Some(500));
+        assert_eq!(statuses[0].compliance, Some(1.0));
+        assert_eq!(statuses[0].met, Some(true));
+        assert_eq!(statuses[0].violations, 1);
+        assert_eq!(statuses[1].samples, 0);
+        assert_eq!(statuses[1].met, None);
+    }
+}
@@ -0,0 +1,173 @@
+//! Snapshot tests for the streaming transformers.
+//!
+//! Each `tests/fixtures/streams/<format>/<name>.sse` file is a recorded upstream SSE
+//! body (`openai` = Chat Completions, `responses` = OpenAI Responses API, `gemini` =
+//! `streamGenerateContent?alt=sse`). It is fed through that format's transformer, and
+//! the resulting Anthropic events are compared with `<name>.snap`. Run with
+//! `UPDATE_STREAM_FIXTURES=1` to (re)write snapshots, then review the diff.
+
+use super::{gemini, openai::OpenAIProvider, streaming::parse_sse_events, ProviderError};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use std::path::{Path, PathBuf};
+
+const FIXTURE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/streams");
+
+/// Model name passed to transformers that need one
+const FIXTURE_MODEL: &str = "fixture-model";
+
+/// Run one upstream body, split into `chunks`, through the transformer for `format`
+async fn transform(format: &str, chunks: Vec<Bytes>) -> String {
+    let upstream = futures::stream::iter(chunks.into_iter().map(Ok::<_, reqwest::Error>));
+    let provider = format!("fixture-{}", format);
+    let stream: std::pin::Pin<Box<dyn Stream<Item = Result<Bytes, ProviderError>> + Send>> = match format {
+        "openai" => Box::pin(OpenAIProvider::transform_chat_stream(upstream, provider)),
+        "responses" => Box::pin(OpenAIProvider::transform_responses_stream(upstream, provider, FIXTURE_MODEL.to_string(), None)),
+        "gemini" => Box::pin(gemini::transform_sse_stream(upstream, provider, FIXTURE_MODEL.to_string())),
+        other => panic!("unknown fixture format '{}' (expected openai, responses or gemini)", other),
+    };
+
+    let mut output = String::new();
+    let mut results = stream;
+    while let Some(result) = results.next().await {
+        match result {
+            Ok(bytes) => output.push_str(&String::from_utf8_lossy(&bytes)),
+            // Errors end the stream for the client; record them in the snapshot
+            Err(e) => {
+                output.push_str(&format!("event: stream_error\ndata: {}\n\n", serde_json::json!(e.to_string())));
+                break;
+            }
+        }
+    }
+    output
+}
+
+/// One line per Anthropic event (`<event> <data>`), with generated IDs replaced
+fn snapshot(output: &str) -> String {
+    let ids = regex::Regex::new(r"\b(msg|toolu)_[0-9a-f-]{32,36}\b").unwrap();
+    parse_sse_events(output)
+        .iter()
+        .map(|event| {
+            let data = ids.replace_all(&event.data, "${1}_[id]");
+            format!("{} {}\n", event.event.as_deref().unwrap_or("message"), data)
+        })
+        .collect()
+}
+
+/// Check the events form a valid Anthropic message stream
+fn check_sequence(snapshot: &str) -> Result<(), String> {
+    let mut events = Vec::new();
+    for line in snapshot.lines() {
+        let (name, data) = line.split_once(' ').unwrap_or((line, "null"));
+        let data: serde_json::Value = serde_json::from_str(data).map_err(|e| format!("invalid JSON in `{}`: {}", line, e))?;
+        events.push((name, data));
+    }
+    if events.last().is_some_and(|(name, _)| *name == "stream_error") {
+        events.pop();
+    } else if events.last().map(|(name, _)| *name) != Some("message_stop") {
+        return Err("stream doesn't end with message_stop".to_string());
+    }
+    if events.first().map(|(name, _)| *name) != Some("message_start") {
+        return Err("stream doesn't start with message_start".to_string());
+    }
+
+    let mut open = std::collections::BTreeSet::new();
+    let mut next_index = 0;
+    for (name, data) in &events {
+        let index = data["index"].as_u64();
+        match *name {
+            "content_block_start" => {
+                if index != Some(next_index) {
+                    return Err(format!("block started at index {:?}, expected {}", index, next_index));
+                }
+                next_index += 1;
+                open.insert(index);
+            }
+            "content_block_delta" if !open.contains(&index) => {
+                return Err(format!("delta for block {:?}, which isn't open", index));
+            }
+            "content_block_stop" if !open.remove(&index) => {
+                return Err(format!("stop for block {:?}, which isn't open", index));
+            }
+            "message_delta" | "message_stop" if !open.is_empty() => {
+                return Err(format!("{} with blocks still open: {:?}", name, open));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn fixtures() -> Vec<(String, PathBuf)> {
+    let mut fixtures = Vec::new();
+    for format in std::fs::read_dir(FIXTURE_DIR).expect("fixture directory").flatten() {
+        if !format.path().is_dir() {
+            continue;
+        }
+        let name = format.file_name().to_string_lossy().to_string();
+        for file in std::fs::read_dir(format.path()).unwrap().flatten() {
+            if file.path().extension().is_some_and(|ext| ext == "sse") {
+                fixtures.push((name.clone(), file.path()));
+            }
+        }
+    }
+    fixtures.sort();
+    fixtures
+}
+
+fn relative(path: &Path) -> String {
+    path.strip_prefix(env!("CARGO_MANIFEST_DIR")).unwrap_or(path).display().to_string()
+}
+
+#[tokio::test]
+async fn test_stream_fixtures() {
+    let update = std::env::var_os("UPDATE_STREAM_FIXTURES").is_some();
+    let fixtures = fixtures();
+    assert!(!fixtures.is_empty(), "no fixtures in {}", FIXTURE_DIR);
+
+    let mut failures = Vec::new();
+    for (format, path) in fixtures {
+        let raw = std::fs::read_to_string(&path).unwrap();
+
+        // Line by line, so events arrive split across chunks, and all at once
+        let lines = raw.split_inclusive('\n').map(|line| Bytes::from(line.to_string())).collect();
+        let actual = snapshot(&transform(&format, lines).await);
+        let whole = snapshot(&transform(&format, vec![Bytes::from(raw)]).await);
+        if actual != whole {
+            failures.push(format!("{}: output depends on how the stream is chunked", relative(&path)));
+            continue;
+        }
+        if let Err(e) = check_sequence(&actual) {
+            failures.push(format!("{}: invalid Anthropic stream: {}", relative(&path), e));
+        }
+
+        let snap_path = path.with_extension("snap");
+        if update {
+            std::fs::write(&snap_path, &actual).unwrap();
+            continue;
+        }
+        match std::fs::read_to_string(&snap_path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!(
+                "{} doesn't match {}\n--- expected\n{}--- actual\n{}",
+                relative(&path), relative(&snap_path), expected, actual
+            )),
+            Err(_) => failures.push(format!(
+                "{} has no snapshot (run with UPDATE_STREAM_FIXTURES=1 to create it)",
+                relative(&path)
+            )),
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
+
+#[test]
+fn test_check_sequence_rejects_unclosed_block() {
+    let events = concat!(
+        "message_start {\"type\":\"message_start\"}\n",
+        "content_block_start {\"index\":0}\n",
+        "message_delta {}\n",
+        "message_stop {}\n",
+    );
+    assert!(check_sequence(events).unwrap_err().contains("still open"));
+}
@@ -4,6 +4,7 @@ use pin_project::pin_project;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use serde_json::Value;
+use super::Usage;
 
 /// SSE event from provider
 #[derive(Debug, Clone)]
@@ -14,7 +15,6 @@ pub struct SseEvent {
 
 impl SseEvent {
     /// Format as SSE output for client
-    #[allow(dead_code)]
     pub fn to_sse_string(&self) -> String {
         let mut output = String::new();
 
@@ -65,6 +65,223 @@ pub fn parse_sse_events(input: &str) -> Vec<SseEvent> {
     events
 }
 
+/// Build the Anthropic SSE event sequence for a complete (non-streaming) response.
+///
+/// Used when a mapping has `force_non_streaming`: the upstream call is made
+/// non-streaming and the client still receives a valid message stream
+/// (message_start, start/deltas/stop per block, message_delta, message_stop).
+/// Text and thinking are split into deltas of `chunk_chars` characters (0 = one delta).
+pub fn synthesize_sse_events(response: &super::ProviderResponse, chunk_chars: usize) -> Vec<SseEvent> {
+    use crate::models::{ContentBlock, KnownContentBlock};
+    use serde_json::json;
+
+    fn event(name: &str, data: Value) -> SseEvent {
+        SseEvent { event: Some(name.to_string()), data: data.to_string() }
+    }
+
+    let mut start_usage = json!({
+        "input_tokens": response.usage.input_tokens,
+        "output_tokens": 0,
+    });
+    if let Some(n) = response.usage.cache_creation_input_tokens {
+        start_usage["cache_creation_input_tokens"] = json!(n);
+    }
+    if let Some(n) = response.usage.cache_read_input_tokens {
+        start_usage["cache_read_input_tokens"] = json!(n);
+    }
+
+    let mut events = vec![
+        event("message_start", json!({
+            "type": "message_start",
+            "message": {
+                "id": response.id,
+                "type": "message",
+                "role": response.role,
+                "content": [],
+                "model": response.model,
+                "stop_reason": null,
+                "stop_sequence": null,
+                "usage": start_usage,
+            }
+        })),
+        event("ping", json!({"type": "ping"})),
+    ];
+
+    for (index, block) in response.content.iter().enumerate() {
+        let (start, deltas): (Value, Vec<Value>) = match block {
+            ContentBlock::Known(KnownContentBlock::Text { text, .. }) => (
+                json!({"type": "text", "text": ""}),
+                chunk_text(text, chunk_chars)
+                    .map(|chunk| json!({"type": "text_delta", "text": chunk}))
+                    .collect(),
+            ),
+            ContentBlock::Known(KnownContentBlock::ToolUse { id, name, input }) => (
+                json!({"type": "tool_use", "id": id, "name": name, "input": {}}),
+                vec![json!({"type": "input_json_delta", "partial_json": input.to_string()})],
+            ),
+            ContentBlock::Known(KnownContentBlock::Thinking { raw }) => {
+                let thinking = raw.get("thinking").and_then(|t| t.as_str()).unwrap_or("");
+                let mut deltas: Vec<Value> = chunk_text(thinking, chunk_chars)
+                    .map(|chunk| json!({"type": "thinking_delta", "thinking": chunk}))
+                    .collect();
+                if let Some(signature) = raw.get("signature") {
+                    deltas.push(json!({"type": "signature_delta", "signature": signature}));
+                }
+                (json!({"type": "thinking", "thinking": ""}), deltas)
+            }
+            // Anything else (redacted_thinking, server tool results, ...) is sent whole in the start event
+            other => (serde_json::to_value(other).unwrap_or(Value::Null), Vec::new()),
+        };
+
+        events.push(event("content_block_start", json!({
+            "type": "content_block_start",
+            "index": index,
+            "content_block": start,
+        })));
+        for delta in deltas {
+            events.push(event("content_block_delta", json!({
+                "type": "content_block_delta",
+                "index": index,
+                "delta": delta,
+            })));
+        }
+        events.push(event("content_block_stop", json!({"type": "content_block_stop", "index": index})));
+    }
+
+    events.push(event("message_delta", json!({
+        "type": "message_delta",
+        "delta": {
+            "stop_reason": response.stop_reason,
+            "stop_sequence": response.stop_sequence,
+        },
+        "usage": {"output_tokens": response.usage.output_tokens},
+    })));
+    events.push(event("message_stop", json!({"type": "message_stop"})));
+    events
+}
+
+/// Split text into pieces of at most `chunk_chars` characters (always at least one piece)
+fn chunk_text(text: &str, chunk_chars: usize) -> impl Iterator<Item = &str> {
+    let mut rest = Some(text);
+    std::iter::from_fn(move || {
+        let current = rest?;
+        let split = match current.char_indices().nth(chunk_chars) {
+            Some((idx, _)) if chunk_chars > 0 => idx,
+            _ => current.len(),
+        };
+        let (chunk, tail) = current.split_at(split);
+        rest = (!tail.is_empty()).then_some(tail);
+        Some(chunk)
+    })
+}
+
+/// Rebuild a complete response from an Anthropic SSE stream (the inverse of
+/// [`synthesize_sse_events`]).
+///
+/// Used when a mapping has `force_streaming`: the upstream call is made streaming
+/// and a non-streaming client still receives one JSON message. An `error` event
+/// inside the stream fails the whole call, as a non-streaming request would.
+pub async fn collect_stream_response<S>(stream: S) -> Result<super::ProviderResponse, super::ProviderError>
+where
+    S: Stream<Item = Result<Bytes, super::ProviderError>>,
+{
+    use futures::StreamExt;
+    use serde_json::json;
+
+    let mut stream = std::pin::pin!(stream);
+    let mut buffer = String::new();
+    let mut message = json!({"id": "", "type": "message", "role": "assistant", "model": "", "content": []});
+    let mut usage = Usage { input_tokens: 0, output_tokens: 0, cache_creation_input_tokens: None, cache_read_input_tokens: None };
+    let mut blocks: Vec<Value> = Vec::new();
+    // Partial tool input JSON per block
+    let mut partial_json: Vec<String> = Vec::new();
+
+    loop {
+        let chunk = stream.next().await.transpose()?;
+        match &chunk {
+            Some(bytes) => buffer.push_str(&String::from_utf8_lossy(bytes)),
+            None => buffer.push_str("\n\n"),
+        }
+        let Some(end) = buffer.rfind("\n\n") else {
+            continue;
+        };
+        let complete: String = buffer.drain(..end + 2).collect();
+        for event in parse_sse_events(&complete) {
+            let Ok(data) = serde_json::from_str::<Value>(&event.data) else {
+                continue;
+            };
+            match data.get("type").and_then(|t| t.as_str()) {
+                Some("message_start") => {
+                    let start = &data["message"];
+                    for field in ["id", "role", "model"] {
+                        if let Some(value) = start.get(field).filter(|v| v.is_string()) {
+                            message[field] = value.clone();
+                        }
+                    }
+                    merge_usage(&mut usage, &start["usage"]);
+                }
+                Some("content_block_start") => {
+                    blocks.push(data["content_block"].clone());
+                    partial_json.push(String::new());
+                }
+                Some("content_block_delta") => {
+                    let (Some(block), Some(json)) = (blocks.last_mut(), partial_json.last_mut()) else {
+                        continue;
+                    };
+                    let delta = &data["delta"];
+                    let append = |block: &mut Value, field: &str, text: &str| {
+                        let current = block[field].as_str().unwrap_or_default();
+                        block[field] = Value::String(format!("{}{}", current, text));
+                    };
+                    match delta["type"].as_str() {
+                        Some("text_delta") => append(block, "text", delta["text"].as_str().unwrap_or_default()),
+                        Some("thinking_delta") => append(block, "thinking", delta["thinking"].as_str().unwrap_or_default()),
+                        Some("signature_delta") => block["signature"] = delta["signature"].clone(),
+                        Some("input_json_delta") => json.push_str(delta["partial_json"].as_str().unwrap_or_default()),
+                        Some("citations_delta") => {
+                            if !block["citations"].is_array() {
+                                block["citations"] = json!([]);
+                            }
+                            if let Some(citations) = block["citations"].as_array_mut() {
+                                citations.push(delta["citation"].clone());
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Some("message_delta") => {
+                    message["stop_reason"] = data["delta"]["stop_reason"].clone();
+                    message["stop_sequence"] = data["delta"]["stop_sequence"].clone();
+                    merge_usage(&mut usage, &data["usage"]);
+                }
+                Some("error") => {
+                    let error = StreamErrorEvent::from_data(&data);
+                    return Err(super::ProviderError::from_status(
+                        error.status(),
+                        None,
+                        format!("{}: {}", error.error_type, error.message),
+                    ));
+                }
+                _ => {}
+            }
+        }
+        if chunk.is_none() {
+            break;
+        }
+    }
+
+    for (block, json) in blocks.iter_mut().zip(partial_json) {
+        if !json.is_empty() {
+            block["input"] = serde_json::from_str(&json)?;
+        } else if block["type"] == "tool_use" && !block["input"].is_object() {
+            block["input"] = json!({});
+        }
+    }
+    message["content"] = Value::Array(blocks);
+    message["usage"] = serde_json::to_value(&usage)?;
+    Ok(serde_json::from_value(message)?)
+}
+
 /// Stream adapter that converts a reqwest Response stream into SSE events
 #[pin_project]
 pub struct SseStream<S> {
@@ -227,11 +444,9 @@ where
                                     }
                                     *this.logged_message_start = true;
                                 }
-                                Some("content_block_delta") => {
+                                Some("content_block_delta") if this.first_token_time.is_none() => {
                                     // Mark first token arrival
-                                    if this.first_token_time.is_none() {
-                                        *this.first_token_time = Some(std::time::Instant::now());
-                                    }
+                                    *this.first_token_time = Some(std::time::Instant::now());
                                 }
                                 Some("message_delta") => {
                                     // Track tokens (output_tokens always, input_tokens for OpenAI providers)
@@ -244,6 +459,11 @@ where
                                                     *this.input_tokens = input;
                                                 }
                                             }
+                                            if let Some(cached) = usage.get("cache_read_input_tokens").and_then(|v| v.as_u64()) {
+                                                if *this.cache_read == 0 {
+                                                    *this.cache_read = cached;
+                                                }
+                                            }
                                         }
                                     }
                                 }
@@ -281,11 +501,7 @@ where
 
                 // Build cache info string if caching was used
                 let cache_info = if *this.cache_creation > 0 || *this.cache_read > 0 {
-                    let cache_pct = if total_input > 0 {
-                        (*this.cache_read * 100) / total_input
-                    } else {
-                        0
-                    };
+                    let cache_pct = (*this.cache_read * 100).checked_div(total_input).unwrap_or(0);
                     format!(" cache:{}%", cache_pct)
                 } else {
                     String::new()
@@ -321,6 +537,273 @@ where
     }
 }
 
+/// Called once with the final usage of a stream
+type UsageCallback = Box<dyn FnOnce(&Usage) + Send>;
+
+/// Stream adapter that reads usage from `message_start` / `message_delta` events and
+/// hands the final usage to `on_complete` once the stream ends. Bytes pass through unchanged.
+#[pin_project]
+pub struct UsageStream<S> {
+    #[pin]
+    inner: S,
+    buffer: String,
+    usage: Usage,
+    on_complete: Option<UsageCallback>,
+}
+
+impl<S> UsageStream<S> {
+    pub fn new(stream: S, on_complete: impl FnOnce(&Usage) + Send + 'static) -> Self {
+        Self {
+            inner: stream,
+            buffer: String::new(),
+            usage: Usage { input_tokens: 0, output_tokens: 0, cache_creation_input_tokens: None, cache_read_input_tokens: None },
+            on_complete: Some(Box::new(on_complete)),
+        }
+    }
+}
+
+/// Fold an SSE usage object into the running usage (later non-zero values win)
+fn merge_usage(usage: &mut Usage, value: &Value) {
+    let field = |name: &str| value.get(name).and_then(|v| v.as_u64()).map(|v| v as u32).filter(|v| *v > 0);
+    if let Some(input) = field("input_tokens") {
+        usage.input_tokens = input;
+    }
+    if let Some(output) = field("output_tokens") {
+        usage.output_tokens = output;
+    }
+    if let Some(read) = field("cache_read_input_tokens") {
+        usage.cache_read_input_tokens = Some(read);
+    }
+    if let Some(write) = field("cache_creation_input_tokens") {
+        usage.cache_creation_input_tokens = Some(write);
+    }
+}
+
+impl<S, E> Stream for UsageStream<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        match this.inner.poll_next(cx) {
+            Poll::Ready(Some(Ok(bytes))) => {
+                if let Ok(text) = std::str::from_utf8(&bytes) {
+                    this.buffer.push_str(text);
+                }
+
+                if let Some(end) = this.buffer.rfind("\n\n") {
+                    let complete: String = this.buffer.drain(..end + 2).collect();
+                    for event in parse_sse_events(&complete) {
+                        let Ok(data) = serde_json::from_str::<Value>(&event.data) else {
+                            continue;
+                        };
+                        match data.get("type").and_then(|t| t.as_str()) {
+                            Some("message_start") => merge_usage(this.usage, &data["message"]["usage"]),
+                            Some("message_delta") => merge_usage(this.usage, &data["usage"]),
+                            _ => {}
+                        }
+                    }
+                }
+
+                Poll::Ready(Some(Ok(bytes)))
+            }
+            Poll::Ready(None) => {
+                if let Some(on_complete) = this.on_complete.take() {
+                    on_complete(this.usage);
+                }
+                Poll::Ready(None)
+            }
+            other => other,
+        }
+    }
+}
+
+/// An `error` event sent inside an otherwise successful (HTTP 200) stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamErrorEvent {
+    /// Anthropic error type, e.g. "overloaded_error"
+    pub error_type: String,
+    pub message: String,
+}
+
+impl StreamErrorEvent {
+    fn from_data(data: &Value) -> Self {
+        let error = &data["error"];
+        Self {
+            error_type: error["type"].as_str().unwrap_or("api_error").to_string(),
+            message: error["message"].as_str().unwrap_or("unknown error").to_string(),
+        }
+    }
+
+    /// HTTP status the error has when returned as a response
+    pub fn status(&self) -> u16 {
+        match self.error_type.as_str() {
+            "invalid_request_error" => 400,
+            "authentication_error" => 401,
+            "permission_error" => 403,
+            "not_found_error" => 404,
+            "request_too_large" => 413,
+            "rate_limit_error" => 429,
+            "overloaded_error" => 529,
+            _ => 500,
+        }
+    }
+}
+
+/// Called with the first mid-stream error event
+type StreamErrorCallback = Box<dyn FnOnce(&StreamErrorEvent) + Send>;
+
+/// Stream adapter that watches for `error` events inside a stream and reports the
+/// first to `on_error`. With `convert`, the error event is replaced by a text block
+/// explaining it and a clean `message_stop`, and anything after it is dropped;
+/// otherwise it passes through. Events are re-emitted whole.
+#[pin_project]
+pub struct StreamErrorWatch<S> {
+    #[pin]
+    inner: S,
+    buffer: Vec<u8>,
+    convert: bool,
+    on_error: Option<StreamErrorCallback>,
+    message_started: bool,
+    /// Index of a content block that was started but not stopped
+    open_block: Option<u64>,
+    next_block_index: u64,
+    /// The error was converted; drop the rest of the stream
+    converted: bool,
+    finished: bool,
+}
+
+impl<S> StreamErrorWatch<S> {
+    pub fn new(stream: S, convert: bool, on_error: impl FnOnce(&StreamErrorEvent) + Send + 'static) -> Self {
+        Self {
+            inner: stream,
+            buffer: Vec::new(),
+            convert,
+            on_error: Some(Box::new(on_error)),
+            message_started: false,
+            open_block: None,
+            next_block_index: 0,
+            converted: false,
+            finished: false,
+        }
+    }
+}
+
+/// Events that end a message cleanly after a mid-stream error, closing any open block
+fn error_stop_events(error: &StreamErrorEvent, message_started: bool, open_block: Option<u64>, index: u64) -> String {
+    let event = |name: &str, data: Value| SseEvent { event: Some(name.to_string()), data: data.to_string() }.to_sse_string();
+    let mut output = String::new();
+    if !message_started {
+        output.push_str(&event("message_start", serde_json::json!({
+            "type": "message_start",
+            "message": {
+                "id": "msg_stream_error", "type": "message", "role": "assistant", "content": [],
+                "model": "unknown", "stop_reason": null, "stop_sequence": null,
+                "usage": {"input_tokens": 0, "output_tokens": 0}
+            }
+        })));
+    }
+    if let Some(open) = open_block {
+        output.push_str(&event("content_block_stop", serde_json::json!({"type": "content_block_stop", "index": open})));
+    }
+    let text = format!(
+        "\n\n[Response interrupted: the upstream provider sent {}: {}]",
+        error.error_type, error.message
+    );
+    output.push_str(&event("content_block_start", serde_json::json!({
+        "type": "content_block_start", "index": index, "content_block": {"type": "text", "text": ""}
+    })));
+    output.push_str(&event("content_block_delta", serde_json::json!({
+        "type": "content_block_delta", "index": index, "delta": {"type": "text_delta", "text": text}
+    })));
+    output.push_str(&event("content_block_stop", serde_json::json!({"type": "content_block_stop", "index": index})));
+    output.push_str(&event("message_delta", serde_json::json!({
+        "type": "message_delta",
+        "delta": {"stop_reason": "end_turn", "stop_sequence": null},
+        "usage": {"output_tokens": 0}
+    })));
+    output.push_str(&event("message_stop", serde_json::json!({"type": "message_stop"})));
+    output
+}
+
+impl<S, E> Stream for StreamErrorWatch<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        loop {
+            if *this.finished {
+                return Poll::Ready(None);
+            }
+            match this.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(bytes))) => {
+                    if *this.converted {
+                        continue;
+                    }
+                    this.buffer.extend_from_slice(&bytes);
+                    let Some(end) = this.buffer.windows(2).rposition(|w| w == b"\n\n") else {
+                        continue;
+                    };
+                    let complete: Vec<u8> = this.buffer.drain(..end + 2).collect();
+                    let complete = String::from_utf8_lossy(&complete);
+
+                    let mut output = String::new();
+                    for raw in complete.split_inclusive("\n\n") {
+                        let data = parse_sse_events(raw)
+                            .first()
+                            .and_then(|e| serde_json::from_str::<Value>(&e.data).ok())
+                            .unwrap_or(Value::Null);
+                        match data.get("type").and_then(|t| t.as_str()) {
+                            Some("message_start") => *this.message_started = true,
+                            Some("content_block_start") => {
+                                let index = data["index"].as_u64().unwrap_or(*this.next_block_index);
+                                *this.open_block = Some(index);
+                                *this.next_block_index = index + 1;
+                            }
+                            Some("content_block_stop") => *this.open_block = None,
+                            Some("error") => {
+                                let error = StreamErrorEvent::from_data(&data);
+                                if let Some(on_error) = this.on_error.take() {
+                                    on_error(&error);
+                                }
+                                if *this.convert {
+                                    output.push_str(&error_stop_events(
+                                        &error,
+                                        *this.message_started,
+                                        *this.open_block,
+                                        *this.next_block_index,
+                                    ));
+                                    *this.converted = true;
+                                    this.buffer.clear();
+                                    break;
+                                }
+                            }
+                            _ => {}
+                        }
+                        output.push_str(raw);
+                    }
+                    return Poll::Ready(Some(Ok(Bytes::from(output))));
+                }
+                Poll::Ready(None) => {
+                    *this.finished = true;
+                    if this.buffer.is_empty() || *this.converted {
+                        return Poll::Ready(None);
+                    }
+                    return Poll::Ready(Some(Ok(Bytes::from(std::mem::take(this.buffer)))));
+                }
+                other => return other,
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -354,4 +837,162 @@ mod tests {
         assert!(events[0].event.is_none());
         assert_eq!(events[0].data, "plain data");
     }
+
+    #[test]
+    fn test_synthesize_sse_events_round_trip() {
+        let response: crate::providers::ProviderResponse = serde_json::from_value(serde_json::json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-sonnet-4-5",
+            "content": [
+                {"type": "thinking", "thinking": "hmm", "signature": "sig"},
+                {"type": "text", "text": "Hello"},
+                {"type": "tool_use", "id": "tu_1", "name": "Read", "input": {"path": "a.rs"}}
+            ],
+            "stop_reason": "tool_use",
+            "stop_sequence": null,
+            "usage": {"input_tokens": 10, "output_tokens": 5, "cache_read_input_tokens": 3}
+        }))
+        .unwrap();
+
+        let sse: String = synthesize_sse_events(&response, 0).iter().map(|e| e.to_sse_string()).collect();
+        let events = parse_sse_events(&sse);
+        let names: Vec<&str> = events.iter().map(|e| e.event.as_deref().unwrap()).collect();
+        assert_eq!(names.first(), Some(&"message_start"));
+        assert_eq!(names.last(), Some(&"message_stop"));
+        assert_eq!(names.iter().filter(|n| **n == "content_block_start").count(), 3);
+
+        let data: Vec<Value> = events.iter().map(|e| serde_json::from_str(&e.data).unwrap()).collect();
+        assert_eq!(data[0]["message"]["usage"]["cache_read_input_tokens"], 3);
+        let deltas: Vec<&Value> = data.iter().filter(|d| d["type"] == "content_block_delta").map(|d| &d["delta"]).collect();
+        assert_eq!(deltas[0]["thinking"], "hmm");
+        assert_eq!(deltas[1]["signature"], "sig");
+        assert_eq!(deltas[2]["text"], "Hello");
+        let tool_input: Value = serde_json::from_str(deltas[3]["partial_json"].as_str().unwrap()).unwrap();
+        assert_eq!(tool_input["path"], "a.rs");
+
+        let message_delta = data.iter().find(|d| d["type"] == "message_delta").unwrap();
+        assert_eq!(message_delta["delta"]["stop_reason"], "tool_use");
+        assert_eq!(message_delta["usage"]["output_tokens"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_collect_stream_response() {
+        let response: crate::providers::ProviderResponse = serde_json::from_value(serde_json::json!({
+            "id": "msg_1", "type": "message", "role": "assistant", "model": "glm-4.6",
+            "content": [
+                {"type": "thinking", "thinking": "let me look", "signature": "sig"},
+                {"type": "text", "text": "Reading the file now"},
+                {"type": "tool_use", "id": "tu_1", "name": "Read", "input": {"path": "a.rs"}}
+            ],
+            "stop_reason": "tool_use", "stop_sequence": null,
+            "usage": {"input_tokens": 10, "output_tokens": 5, "cache_read_input_tokens": 3}
+        }))
+        .unwrap();
+        let sse: String = synthesize_sse_events(&response, 4).iter().map(|e| e.to_sse_string()).collect();
+        // Split mid-event to exercise buffering
+        let chunks = vec![Ok(Bytes::from(sse[..101].to_string())), Ok(Bytes::from(sse[101..].to_string()))];
+
+        let collected = collect_stream_response(futures::stream::iter(chunks)).await.unwrap();
+        assert_eq!(serde_json::to_value(&collected).unwrap(), serde_json::to_value(&response).unwrap());
+
+        let error = concat!(
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{}}\n\n",
+            "event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n",
+        );
+        let result = collect_stream_response(futures::stream::iter(vec![Ok(Bytes::from(error))])).await;
+        assert!(matches!(result, Err(crate::providers::ProviderError::Overloaded { status: 529, .. })));
+    }
+
+    #[test]
+    fn test_chunk_text() {
+        assert_eq!(chunk_text("héllo wörld", 4).collect::<Vec<_>>(), vec!["héll", "o wö", "rld"]);
+        assert_eq!(chunk_text("abc", 0).collect::<Vec<_>>(), vec!["abc"]);
+        assert_eq!(chunk_text("", 4).collect::<Vec<_>>(), vec![""]);
+    }
+
+    #[test]
+    fn test_synthesize_chunks_text_deltas() {
+        let response: crate::providers::ProviderResponse = serde_json::from_value(serde_json::json!({
+            "id": "msg_1", "type": "message", "role": "assistant", "model": "m",
+            "content": [{"type": "text", "text": "abcdefghij"}],
+            "stop_reason": "end_turn", "stop_sequence": null,
+            "usage": {"input_tokens": 1, "output_tokens": 1}
+        }))
+        .unwrap();
+
+        let deltas: Vec<String> = synthesize_sse_events(&response, 4)
+            .iter()
+            .filter(|e| e.event.as_deref() == Some("content_block_delta"))
+            .map(|e| serde_json::from_str::<Value>(&e.data).unwrap()["delta"]["text"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(deltas, vec!["abcd", "efgh", "ij"]);
+    }
+
+    #[tokio::test]
+    async fn test_usage_stream_reports_final_usage() {
+        use futures::StreamExt;
+        use std::sync::{Arc, Mutex};
+
+        let events = concat!(
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":1000,\"output_tokens\":1,\"cache_read_input_tokens\":500}}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"delta\":{\"text\":\"hi\"}}\n\n",
+            "event: message_delta\ndata: {\"type\":\"message_delta\",\"usage\":{\"output_tokens\":1000}}\n\n",
+        );
+        let inner = futures::stream::iter(vec![Ok::<_, std::io::Error>(Bytes::from(events))]);
+        let seen = Arc::new(Mutex::new(None));
+        let seen_in_callback = seen.clone();
+        let stream = UsageStream::new(inner, move |usage: &Usage| {
+            *seen_in_callback.lock().unwrap() = Some(usage.clone());
+        });
+        let chunks: Vec<_> = stream.collect().await;
+
+        assert_eq!(chunks.len(), 1);
+        let usage = seen.lock().unwrap().clone().unwrap();
+        assert_eq!((usage.input_tokens, usage.output_tokens, usage.cache_read_input_tokens), (1000, 1000, Some(500)));
+    }
+
+    #[tokio::test]
+    async fn test_stream_error_watch_converts_error_event() {
+        use futures::StreamExt;
+        use std::sync::{Arc, Mutex};
+
+        let input = concat!(
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{}}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n",
+            "event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n",
+            "event: ping\ndata: {\"type\":\"ping\"}\n\n",
+        );
+        let run = |convert: bool| async move {
+            // Split mid-event to exercise buffering
+            let inner = futures::stream::iter(vec![
+                Ok::<_, std::io::Error>(Bytes::from(&input[..70])),
+                Ok(Bytes::from(&input[70..])),
+            ]);
+            let seen = Arc::new(Mutex::new(None));
+            let seen_in_callback = seen.clone();
+            let stream = StreamErrorWatch::new(inner, convert, move |e: &StreamErrorEvent| {
+                *seen_in_callback.lock().unwrap() = Some(e.clone());
+            });
+            let output: Vec<u8> = stream.map(|b| b.unwrap().to_vec()).concat().await;
+            let error = seen.lock().unwrap().clone();
+            (String::from_utf8(output).unwrap(), error)
+        };
+
+        let (output, error) = run(false).await;
+        assert_eq!(output, input);
+        assert_eq!(error.unwrap().status(), 529);
+
+        let (output, _) = run(true).await;
+        let events: Vec<String> = parse_sse_events(&output).into_iter().filter_map(|e| e.event).collect();
+        assert_eq!(
+            events,
+            ["message_start", "content_block_start", "content_block_delta", "content_block_stop",
+             "content_block_start", "content_block_delta", "content_block_stop", "message_delta", "message_stop"]
+        );
+        assert!(output.contains("overloaded_error: Overloaded"));
+        assert!(output.contains("\"index\":1"));
+    }
 }
@@ -0,0 +1,296 @@
+use super::error::ProviderError;
+use super::{AnthropicProvider, ProviderResponse, StreamResponse};
+use crate::cli::TimeoutConfig;
+use crate::models::{AnthropicRequest, CountTokensRequest, CountTokensResponse};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::{self, StreamExt};
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::warn;
+
+tokio::task_local! {
+    /// `connect_timeout_ms` for the non-streaming attempt running on this task
+    static HEADERS_LIMIT: Option<Duration>;
+}
+
+/// Await a non-streaming request's `.send()`, failing with [`ProviderError::Timeout`]
+/// if the response headers take longer than `connect_timeout_ms`. A no-op outside a
+/// [`TimeoutProvider`] attempt, so streaming and token count calls are unaffected.
+pub(crate) async fn response_headers<T, E: Into<ProviderError>>(
+    send: impl Future<Output = Result<T, E>>,
+) -> Result<T, ProviderError> {
+    let limit = HEADERS_LIMIT.try_with(|limit| *limit).ok().flatten();
+    within(limit, "response headers", async { send.await.map_err(Into::into) }).await
+}
+
+/// `Some(duration)` for a configured limit, `None` for 0 (disabled)
+fn limit(ms: u64) -> Option<Duration> {
+    (ms > 0).then(|| Duration::from_millis(ms))
+}
+
+/// Run `call`, failing with [`ProviderError::Timeout`] if it takes longer than `limit`
+async fn within<T>(
+    limit: Option<Duration>,
+    phase: &'static str,
+    call: impl Future<Output = Result<T, ProviderError>>,
+) -> Result<T, ProviderError> {
+    match limit {
+        Some(after) => tokio::time::timeout(after, call).await.unwrap_or(Err(ProviderError::Timeout { phase, after })),
+        None => call.await,
+    }
+}
+
+/// Like [`within`], for a deadline set at the start of the attempt (and its total limit)
+async fn by<T>(
+    deadline: Option<(Instant, Duration)>,
+    phase: &'static str,
+    call: impl Future<Output = Result<T, ProviderError>>,
+) -> Result<T, ProviderError> {
+    match deadline {
+        Some((deadline, after)) => {
+            tokio::time::timeout_at(deadline, call).await.unwrap_or(Err(ProviderError::Timeout { phase, after }))
+        }
+        None => call.await,
+    }
+}
+
+/// Wraps a provider and bounds each attempt (`[server.timeouts]`), so a provider
+/// that accepts the connection but never answers is failed over instead of hanging.
+///
+/// - `connect_timeout_ms`: until the response headers arrive; providers apply it to
+///   non-streaming requests through [`response_headers`]
+/// - `first_token_timeout_ms`: from there until the first content delta
+/// - `api_timeout_ms`: the whole attempt; a stream past it ends with an error event
+/// - `count_tokens_timeout_ms`: a token count
+pub struct TimeoutProvider {
+    name: String,
+    inner: Box<dyn AnthropicProvider>,
+    config: TimeoutConfig,
+}
+
+impl TimeoutProvider {
+    pub fn new(name: String, inner: Box<dyn AnthropicProvider>, config: TimeoutConfig) -> Self {
+        Self { name, inner, config }
+    }
+
+    fn log(&self, error: ProviderError) -> ProviderError {
+        if matches!(error, ProviderError::Timeout { .. }) {
+            warn!("⏱️ {}: {}", self.name, error);
+        }
+        error
+    }
+}
+
+/// Anthropic `error` event for a stream cut off by the total timeout
+fn timeout_event(error: &ProviderError) -> Bytes {
+    let data = serde_json::json!({
+        "type": "error",
+        "error": {"type": "timeout_error", "message": error.to_string()},
+    });
+    Bytes::from(format!("event: error\ndata: {}\n\n", data))
+}
+
+#[async_trait]
+impl AnthropicProvider for TimeoutProvider {
+    async fn send_message(&self, request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+        let attempt = HEADERS_LIMIT.scope(limit(self.config.connect_timeout_ms), self.inner.send_message(request));
+        within(limit(self.config.api_timeout_ms), "response", attempt)
+            .await
+            .map_err(|e| self.log(e))
+    }
+
+    /// Nothing has reached the client until the first content delta, so stalls up
+    /// to then fail the attempt; the chunks read while waiting are replayed
+    async fn send_message_stream(&self, request: AnthropicRequest) -> Result<StreamResponse, ProviderError> {
+        let deadline = limit(self.config.api_timeout_ms).map(|after| (Instant::now() + after, after));
+
+        let opened = within(limit(self.config.connect_timeout_ms), "response headers", self.inner.send_message_stream(request));
+        let mut response = by(deadline, "response headers", opened).await.map_err(|e| self.log(e))?;
+
+        let first_token = async {
+            let mut head = Vec::new();
+            let mut seen = String::new();
+            while let Some(chunk) = response.stream.next().await {
+                let chunk = chunk?;
+                seen.push_str(&String::from_utf8_lossy(&chunk));
+                head.push(chunk);
+                if seen.contains("content_block_delta") {
+                    break;
+                }
+            }
+            Ok(head)
+        };
+        let first_token = within(limit(self.config.first_token_timeout_ms), "first token", first_token);
+        let head = by(deadline, "first token", first_token).await.map_err(|e| self.log(e))?;
+
+        let name = self.name.clone();
+        let rest = stream::unfold(Some(response.stream), move |rest| {
+            let name = name.clone();
+            async move {
+                let mut rest = rest?;
+                let Some((deadline, after)) = deadline else {
+                    return rest.next().await.map(|chunk| (chunk, Some(rest)));
+                };
+                match tokio::time::timeout_at(deadline, rest.next()).await {
+                    Ok(chunk) => chunk.map(|chunk| (chunk, Some(rest))),
+                    Err(_) => {
+                        let error = ProviderError::Timeout { phase: "end of stream", after };
+                        warn!("⏱️ {}: {}", name, error);
+                        Some((Ok(timeout_event(&error)), None))
+                    }
+                }
+            }
+        });
+        response.stream = Box::pin(stream::iter(head.into_iter().map(Ok)).chain(rest));
+        Ok(response)
+    }
+
+    async fn count_tokens(&self, request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+        within(limit(self.config.count_tokens_timeout_ms), "token count", self.inner.count_tokens(request))
+            .await
+            .map_err(|e| self.log(e))
+    }
+
+    fn supports_model(&self, model: &str) -> bool {
+        self.inner.supports_model(model)
+    }
+
+    fn supports_prompt_cache(&self) -> bool {
+        self.inner.supports_prompt_cache()
+    }
+
+    fn accepts_image_urls(&self) -> bool {
+        self.inner.accepts_image_urls()
+    }
+
+    async fn list_models(&self) -> Result<Option<Vec<String>>, ProviderError> {
+        self.inner.list_models().await
+    }
+
+    async fn keep_warm(&self, model: &str, keep_alive: std::time::Duration) -> Result<(), ProviderError> {
+        self.inner.keep_warm(model, keep_alive).await
+    }
+
+    async fn is_loaded(&self, model: &str) -> Result<Option<bool>, ProviderError> {
+        self.inner.is_loaded(model).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Answers with its SSE events after `headers_after`, then one event per `event_gap`
+    struct Slow {
+        headers_after: Duration,
+        event_gap: Duration,
+    }
+
+    const EVENTS: [&str; 3] = [
+        "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{}}\n\n",
+        "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"hi\"}}\n\n",
+        "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+    ];
+
+    #[async_trait]
+    impl AnthropicProvider for Slow {
+        async fn send_message(&self, _request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+            response_headers(async {
+                tokio::time::sleep(self.headers_after).await;
+                Ok::<_, ProviderError>(())
+            })
+            .await?;
+            tokio::time::sleep(self.event_gap).await;
+            Err(ProviderError::ConfigError("answered".to_string()))
+        }
+
+        async fn send_message_stream(&self, _request: AnthropicRequest) -> Result<StreamResponse, ProviderError> {
+            tokio::time::sleep(self.headers_after).await;
+            let gap = self.event_gap;
+            let events = stream::iter(EVENTS).then(move |event| async move {
+                tokio::time::sleep(gap).await;
+                Ok(Bytes::from(event))
+            });
+            Ok(StreamResponse { stream: Box::pin(events), headers: HashMap::new(), rate_limits: HashMap::new() })
+        }
+
+        async fn count_tokens(&self, _request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+            tokio::time::sleep(self.headers_after).await;
+            Ok(CountTokensResponse { input_tokens: 1 })
+        }
+
+        fn supports_model(&self, _model: &str) -> bool {
+            true
+        }
+    }
+
+    fn provider(headers_after_ms: u64, event_gap_ms: u64, config: TimeoutConfig) -> TimeoutProvider {
+        let inner = Slow { headers_after: Duration::from_millis(headers_after_ms), event_gap: Duration::from_millis(event_gap_ms) };
+        TimeoutProvider::new("slow".to_string(), Box::new(inner), config)
+    }
+
+    fn request() -> AnthropicRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "m",
+            "max_tokens": 10,
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap()
+    }
+
+    async fn body(response: StreamResponse) -> String {
+        let chunks: Vec<Bytes> = response.stream.map(|chunk| chunk.unwrap()).collect().await;
+        chunks.iter().map(|c| String::from_utf8_lossy(c).to_string()).collect()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stream_timeouts() {
+        let config = TimeoutConfig {
+            api_timeout_ms: 10_000,
+            connect_timeout_ms: 1_000,
+            first_token_timeout_ms: 2_000,
+            count_tokens_timeout_ms: 2_000,
+        };
+
+        let error = provider(5_000, 0, config.clone()).send_message_stream(request()).await.err().unwrap();
+        assert!(matches!(error, ProviderError::Timeout { phase: "response headers", .. }));
+        assert_eq!(error.client_error(), (504, "timeout_error"));
+
+        // A non-streaming request stalled before its headers fails at the connect limit,
+        // and one that answers in time is then bounded by the total
+        let error = provider(5_000, 0, config.clone()).send_message(request()).await.err().unwrap();
+        assert!(matches!(error, ProviderError::Timeout { phase: "response headers", .. }));
+        let error = provider(500, 20_000, config.clone()).send_message(request()).await.err().unwrap();
+        assert!(matches!(error, ProviderError::Timeout { phase: "response", .. }));
+        let error = provider(500, 0, config.clone()).send_message(request()).await.err().unwrap();
+        assert!(matches!(error, ProviderError::ConfigError(_)));
+
+        let error = provider(0, 1_500, config.clone()).send_message_stream(request()).await.err().unwrap();
+        assert!(matches!(error, ProviderError::Timeout { phase: "first token", .. }));
+
+        // Chunks read while waiting for the first token are replayed
+        let response = provider(500, 500, config.clone()).send_message_stream(request()).await.unwrap();
+        assert_eq!(body(response).await, EVENTS.concat());
+
+        let count = CountTokensRequest {
+            model: "m".to_string(),
+            messages: vec![],
+            system: None,
+            tools: None,
+            anthropic_version: None,
+        };
+        let error = provider(5_000, 0, config.clone()).count_tokens(count.clone()).await.err().unwrap();
+        assert!(matches!(error, ProviderError::Timeout { phase: "token count", .. }));
+        assert!(provider(500, 0, config.clone()).count_tokens(count).await.is_ok());
+
+        // Past the total, the stream ends with an error event
+        let response = provider(0, 900, TimeoutConfig { api_timeout_ms: 2_500, ..config }).send_message_stream(request()).await.unwrap();
+        let output = body(response).await;
+        assert!(output.starts_with(&EVENTS[..2].concat()));
+        assert!(output.contains("timeout_error"));
+        assert!(!output.contains("message_stop"));
+    }
+}
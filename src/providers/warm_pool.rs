@@ -0,0 +1,237 @@
+//! Keep local models loaded (`keep_warm` on a provider).
+//!
+//! Local servers such as Ollama unload an idle model after a few minutes, and the
+//! next request pays the load again (often 10-30s for a large model). A provider
+//! with `keep_warm` gets a cheap warm-up per model on an interval: Ollama's native
+//! preload with a `keep_alive` that outlasts the interval, a one-token generation
+//! for other servers (llama.cpp, LM Studio, vLLM behind `openai`).
+
+use crate::cli::AppConfig;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use super::ProviderConfig;
+
+/// `keep_warm` on a provider
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct KeepWarmConfig {
+    /// Models to keep loaded (default: the provider's mapped `actual_model`s, router
+    /// targets it serves directly, then its `models`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<String>,
+    /// Seconds between warm-ups
+    #[serde(default = "default_keep_warm_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_keep_warm_interval_secs() -> u64 {
+    240
+}
+
+impl KeepWarmConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs.max(1))
+    }
+
+    /// How long the server should keep a model loaded after a warm-up: two
+    /// intervals, so one failed warm-up doesn't unload it
+    pub fn keep_alive(&self) -> Duration {
+        self.interval() * 2
+    }
+}
+
+/// Models a provider's `keep_warm` covers. `serves` tells whether the provider
+/// serves a model name directly (e.g. `background = "qwen2.5-coder:7b"` with Ollama).
+pub fn keep_warm_models(config: &AppConfig, provider: &ProviderConfig, serves: impl Fn(&str) -> bool) -> Vec<String> {
+    let Some(keep_warm) = &provider.keep_warm else {
+        return Vec::new();
+    };
+    if !keep_warm.models.is_empty() {
+        return keep_warm.models.clone();
+    }
+    let mapped = config
+        .models
+        .iter()
+        .flat_map(|m| &m.mappings)
+        .filter(|m| m.provider == provider.name && !m.actual_model.contains('{'))
+        .map(|m| &m.actual_model);
+    let router = &config.router;
+    let targets = [
+        &router.background,
+        &router.think,
+        &router.websearch,
+        &router.websearch_summarizer,
+        &router.suggest,
+        &router.long_context,
+    ];
+    let direct = targets
+        .into_iter()
+        .flatten()
+        .chain(std::iter::once(&router.default))
+        .chain(router.prompt_rules.iter().map(|rule| &rule.model))
+        .filter(|target| config.find_model(target).is_none() && serves(target));
+
+    let mut models: Vec<String> = Vec::new();
+    for model in mapped.chain(direct).chain(&provider.models) {
+        if !models.contains(model) {
+            models.push(model.clone());
+        }
+    }
+    models
+}
+
+/// Load state of one kept-warm model, for `/api/providers`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WarmStatus {
+    pub model: String,
+    /// Whether the model is loaded, as reported by the server (or the last
+    /// warm-up's outcome when it doesn't say); None before the first warm-up
+    pub loaded: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_warmed_at: Option<DateTime<Utc>>,
+    /// Duration of the last successful warm-up (long ones mean the model was loaded from cold)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_warm_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+struct WarmEntry {
+    status: WarmStatus,
+    last_attempt: Instant,
+}
+
+/// Warm-up schedule and load state per provider/model
+#[derive(Default)]
+pub struct WarmPool {
+    entries: DashMap<(String, String), WarmEntry>,
+}
+
+impl WarmPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a warm-up is due; if so, it counts as started now
+    pub fn start_if_due(&self, provider: &str, model: &str, interval: Duration) -> bool {
+        self.start_if_due_at(provider, model, interval, Instant::now())
+    }
+
+    fn start_if_due_at(&self, provider: &str, model: &str, interval: Duration, now: Instant) -> bool {
+        match self.entries.entry((provider.to_string(), model.to_string())) {
+            dashmap::mapref::entry::Entry::Occupied(mut entry) => {
+                if now.duration_since(entry.get().last_attempt) < interval {
+                    return false;
+                }
+                entry.get_mut().last_attempt = now;
+                true
+            }
+            dashmap::mapref::entry::Entry::Vacant(entry) => {
+                let status = WarmStatus { model: model.to_string(), ..Default::default() };
+                entry.insert(WarmEntry { status, last_attempt: now });
+                true
+            }
+        }
+    }
+
+    /// Record a warm-up's outcome and the load state reported afterwards. Returns
+    /// the previous status, so callers can log transitions.
+    pub fn record(&self, provider: &str, model: &str, result: Result<Duration, String>, loaded: Option<bool>) -> WarmStatus {
+        let mut entry = self.entries.entry((provider.to_string(), model.to_string())).or_insert_with(|| WarmEntry {
+            status: WarmStatus { model: model.to_string(), ..Default::default() },
+            last_attempt: Instant::now(),
+        });
+        let previous = entry.status.clone();
+        let status = &mut entry.status;
+        status.loaded = loaded.or(Some(result.is_ok()));
+        match result {
+            Ok(took) => {
+                status.last_warmed_at = Some(Utc::now());
+                status.last_warm_ms = Some(took.as_millis() as u64);
+                status.last_error = None;
+            }
+            Err(e) => status.last_error = Some(e),
+        }
+        previous
+    }
+
+    /// Load state of a provider's kept-warm models
+    pub fn statuses(&self, provider: &str) -> Vec<WarmStatus> {
+        let mut statuses: Vec<WarmStatus> =
+            self.entries.iter().filter(|e| e.key().0 == provider).map(|e| e.status.clone()).collect();
+        statuses.sort_by(|a, b| a.model.cmp(&b.model));
+        statuses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keep_warm_models() {
+        let config: AppConfig = toml::from_str(
+            r#"
+[router]
+default = "local"
+background = "qwen2.5-coder:7b"
+think = "gpt-5"
+
+[[providers]]
+name = "ollama"
+provider_type = "ollama"
+models = ["llama3.2", "phi4"]
+keep_warm = { interval_secs = 120 }
+
+[[providers]]
+name = "cloud"
+provider_type = "openai"
+api_key = "sk-test"
+models = []
+
+[[models]]
+name = "local"
+mappings = [
+    { priority = 1, provider = "ollama", actual_model = "llama3.2" },
+    { priority = 2, provider = "ollama", actual_model = "{requested}" },
+    { priority = 3, provider = "cloud", actual_model = "gpt-5" },
+]
+"#,
+        )
+        .unwrap();
+        // Mapped models, then direct router targets the provider serves, then `models`;
+        // templates skipped, no duplicates
+        let installed = |model: &str| model.starts_with("qwen") || model.starts_with("llama");
+        assert_eq!(
+            keep_warm_models(&config, &config.providers[0], installed),
+            vec!["llama3.2", "qwen2.5-coder:7b", "phi4"]
+        );
+        assert!(keep_warm_models(&config, &config.providers[1], |_| true).is_empty());
+        assert_eq!(config.providers[0].keep_warm.as_ref().unwrap().keep_alive(), Duration::from_secs(240));
+    }
+
+    #[test]
+    fn test_warm_pool_schedule_and_status() {
+        let pool = WarmPool::new();
+        let interval = Duration::from_secs(60);
+        let start = Instant::now();
+
+        assert!(pool.start_if_due_at("ollama", "llama3.2", interval, start));
+        assert!(!pool.start_if_due_at("ollama", "llama3.2", interval, start + Duration::from_secs(30)));
+        assert!(pool.start_if_due_at("ollama", "llama3.2", interval, start + Duration::from_secs(60)));
+
+        let previous = pool.record("ollama", "llama3.2", Ok(Duration::from_millis(18_000)), Some(true));
+        assert_eq!(previous.loaded, None);
+        let previous = pool.record("ollama", "llama3.2", Err("connection refused".to_string()), None);
+        assert_eq!(previous.loaded, Some(true));
+
+        let statuses = pool.statuses("ollama");
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].loaded, Some(false));
+        assert_eq!(statuses[0].last_warm_ms, Some(18_000));
+        assert_eq!(statuses[0].last_error.as_deref(), Some("connection refused"));
+        assert!(pool.statuses("other").is_empty());
+    }
+}
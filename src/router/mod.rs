@@ -1,36 +1,269 @@
-use crate::cli::AppConfig;
-use crate::models::{AnthropicRequest, MessageContent, RouteDecision, RouteType, SystemPrompt};
+pub mod pin;
+pub mod session;
+
+use crate::cli::{AppConfig, PromptRule, RuleLifetime, StripScope, ThinkLevel};
+use crate::models::{AnthropicRequest, MessageContent, RouteDecision, RouteType, SystemPrompt, ThinkingConfig};
 use anyhow::Result;
 use once_cell::sync::Lazy;
 use regex::Regex;
+use serde::Serialize;
+use pin::PinFile;
+use session::{RuleFiring, SessionStore};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 use tracing::{debug, info};
 
 /// Regex to detect capture group references ($1, $name, ${1}, ${name})
 static CAPTURE_REF_PATTERN: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"\$(?:\d+|[a-zA-Z_]\w*|\{[^}]+\})").unwrap());
 
+/// Subagent model tag in the system prompt
+static SUBAGENT_TAG_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<CCM-SUBAGENT-MODEL>(.*?)</CCM-SUBAGENT-MODEL>").expect("Invalid regex pattern"));
+
+/// Default pattern for Claude Code prompt-suggestion/autocomplete system prompts
+const DEFAULT_SUGGEST_PATTERN: &str =
+    r"(?i)(prompt suggestion|suggest(?:ed)? (?:the |a )?next (?:prompt|message|input)|autocomplete)";
+
+/// Default max_tokens ceiling for suggestion requests
+const DEFAULT_SUGGEST_MAX_TOKENS: u32 = 512;
+
+/// Default estimated input size for the long-context route
+const DEFAULT_LONG_CONTEXT_THRESHOLD: u32 = 60_000;
+
+/// Default for `router.websearch_summarizer_threshold` (estimated tokens)
+const DEFAULT_WEBSEARCH_SUMMARIZER_THRESHOLD: u32 = 8_000;
+
+/// Smallest thinking budget the Anthropic API accepts
+const MIN_THINKING_BUDGET: u32 = 1_024;
+
 /// Check if a string contains capture group references
 fn contains_capture_reference(s: &str) -> bool {
     s.contains('$') && CAPTURE_REF_PATTERN.is_match(s)
 }
 
+/// Rough input token count (~4 characters per token) over the system prompt,
+/// message text, tool calls/results and tool definitions
+pub fn estimate_input_tokens(request: &AnthropicRequest) -> u32 {
+    use crate::models::{ContentBlock, KnownContentBlock};
+
+    let mut chars = match &request.system {
+        Some(SystemPrompt::Text(text)) => text.len(),
+        Some(SystemPrompt::Blocks(blocks)) => blocks.iter().map(|b| b.text.len()).sum(),
+        None => 0,
+    };
+
+    for msg in &request.messages {
+        chars += match &msg.content {
+            MessageContent::Text(text) => text.len(),
+            MessageContent::Blocks(blocks) => blocks
+                .iter()
+                .map(|block| match block {
+                    ContentBlock::Known(KnownContentBlock::Text { text, .. }) => text.len(),
+                    ContentBlock::Known(KnownContentBlock::ToolUse { input, .. }) => input.to_string().len(),
+                    ContentBlock::Known(KnownContentBlock::ToolResult { content, .. }) => content.to_string().len(),
+                    ContentBlock::Known(KnownContentBlock::Thinking { raw }) => {
+                        raw.get("thinking").and_then(|v| v.as_str()).map(str::len).unwrap_or(0)
+                    }
+                    // Images are billed by size, not by their base64 payload
+                    ContentBlock::Known(KnownContentBlock::Image { .. }) => 0,
+                    ContentBlock::Unknown(value) => value.to_string().len(),
+                })
+                .sum(),
+        };
+    }
+
+    if let Some(ref tools) = request.tools {
+        chars += tools.iter().map(|t| serde_json::to_string(t).map(|s| s.len()).unwrap_or(0)).sum::<usize>();
+    }
+
+    (chars / 4) as u32
+}
+
+/// Rough token count (~4 chars/token) of the `web_search_tool_result` blocks in a request
+pub fn web_search_result_tokens(request: &AnthropicRequest) -> u32 {
+    use crate::models::ContentBlock;
+
+    let chars: usize = request
+        .messages
+        .iter()
+        .filter_map(|msg| match &msg.content {
+            MessageContent::Blocks(blocks) => Some(blocks),
+            MessageContent::Text(_) => None,
+        })
+        .flatten()
+        .filter_map(|block| match block {
+            ContentBlock::Unknown(value) if value["type"] == "web_search_tool_result" => Some(value.to_string().len()),
+            _ => None,
+        })
+        .sum();
+    (chars / 4) as u32
+}
+
 /// Compiled prompt rule with pre-compiled regex
 #[derive(Clone)]
 pub struct CompiledPromptRule {
-    pub regex: Regex,
+    /// Prompt pattern (None for metadata-only rules)
+    pub regex: Option<Regex>,
+    /// Metadata field path -> pattern (all must match)
+    pub metadata: Vec<(String, Regex)>,
     pub model: String,
     pub strip_match: bool,
+    pub strip_scope: StripScope,
+    pub lifetime: RuleLifetime,
+    /// Stable identity for session tracking (survives reordering and reloads)
+    pub key: String,
     /// True if model contains capture group references ($1, $name, etc.)
     pub is_dynamic: bool,
 }
 
+impl CompiledPromptRule {
+    /// Compile a configured rule, checking all regexes
+    pub fn compile(rule: &PromptRule) -> std::result::Result<Self, String> {
+        if rule.pattern.is_empty() && rule.metadata.is_empty() {
+            return Err(format!(
+                "prompt_rule for model '{}' has neither pattern nor metadata",
+                rule.model
+            ));
+        }
+
+        let regex = if rule.pattern.is_empty() {
+            None
+        } else {
+            let regex = Regex::new(&rule.pattern)
+                .map_err(|e| format!("Invalid prompt_rule pattern '{}': {}", rule.pattern, e))?;
+            Some(regex)
+        };
+
+        let mut metadata = Vec::new();
+        for (key, pattern) in &rule.metadata {
+            let regex = Regex::new(pattern).map_err(|e| {
+                format!("Invalid prompt_rule metadata pattern '{}' for '{}': {}", pattern, key, e)
+            })?;
+            metadata.push((key.clone(), regex));
+        }
+        // Stable order for logging/matched text
+        metadata.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let metadata_key: Vec<String> = metadata.iter().map(|(k, r)| format!("{}={}", k, r.as_str())).collect();
+        let key = format!("{}|{}|{}", rule.pattern, metadata_key.join(","), rule.model);
+
+        Ok(CompiledPromptRule {
+            regex,
+            metadata,
+            model: rule.model.clone(),
+            strip_match: rule.strip_match,
+            strip_scope: rule.strip_scope,
+            lifetime: rule.lifetime,
+            key,
+            is_dynamic: contains_capture_reference(&rule.model),
+        })
+    }
+}
+
+/// Validate a prompt rule before it is saved (admin API)
+pub fn validate_prompt_rule(rule: &PromptRule) -> std::result::Result<(), String> {
+    if rule.model.trim().is_empty() {
+        return Err("prompt_rule model must not be empty".to_string());
+    }
+    CompiledPromptRule::compile(rule).map(|_| ())
+}
+
+/// One routing step evaluated for a request (X-CCM-Explain)
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteStep {
+    pub step: &'static str,
+    pub matched: bool,
+    pub reason: String,
+}
+
+/// Collects route steps only when an explanation was requested
+struct Explainer {
+    steps: Option<Vec<RouteStep>>,
+}
+
+impl Explainer {
+    fn disabled() -> Self {
+        Self { steps: None }
+    }
+
+    fn enabled() -> Self {
+        Self { steps: Some(Vec::new()) }
+    }
+
+    /// Record a step; the reason is only built when explaining
+    fn step(&mut self, step: &'static str, matched: bool, reason: impl FnOnce() -> String) {
+        if let Some(ref mut steps) = self.steps {
+            steps.push(RouteStep { step, matched, reason: reason() });
+        }
+    }
+}
+
+/// A change to the request or session state decided by routing, applied by [`Router::apply`]
+#[derive(Debug, Clone)]
+pub enum RouteMutation {
+    /// Auto-mapping replaced the model name
+    SetModel(String),
+    /// Remove the CCM-SUBAGENT-MODEL tag from the system prompt
+    RemoveSubagentTag,
+    /// Strip a prompt rule's matched phrase from user messages
+    StripMatch { regex: Regex, scope: StripScope },
+    /// Remember a once/sticky prompt rule firing for the session
+    RecordFiring { session: String, rule: String, firing: RuleFiring },
+    /// Enable thinking with at least this budget (think keyword)
+    RaiseThinkingBudget(u32),
+}
+
+/// Routing decision plus the mutations needed to carry it out
+#[derive(Debug, Clone)]
+pub struct RoutePlan {
+    pub decision: RouteDecision,
+    pub mutations: Vec<RouteMutation>,
+}
+
+/// A matched prompt rule, before any mutation is applied
+struct PromptRuleMatch {
+    model: String,
+    matched: String,
+    mutations: Vec<RouteMutation>,
+}
+
+/// Client profile: header matchers plus a router built from the overridden config
+#[derive(Clone)]
+struct ClientRouter {
+    name: String,
+    user_agent: Option<Regex>,
+    x_app: Option<Regex>,
+    router: Router,
+}
+
+impl ClientRouter {
+    fn matches(&self, user_agent: Option<&str>, x_app: Option<&str>) -> bool {
+        let check = |regex: &Option<Regex>, value: Option<&str>| match regex {
+            Some(regex) => value.map(|v| regex.is_match(v)).unwrap_or(false),
+            None => true,
+        };
+        check(&self.user_agent, user_agent) && check(&self.x_app, x_app)
+    }
+}
+
 /// Router for intelligently selecting models based on request characteristics
 #[derive(Clone)]
 pub struct Router {
     config: AppConfig,
+    clients: Vec<ClientRouter>,
+    /// Set on routers built for a client profile
+    client_name: Option<String>,
     auto_map_regex: Option<Regex>,
     background_regex: Option<Regex>,
+    suggest_regex: Regex,
     prompt_rules: Vec<CompiledPromptRule>,
+    /// Think keyword levels (`router.think_keywords`), empty when disabled
+    think_levels: Vec<(Regex, ThinkLevel)>,
+    /// Prompt rule firings per session (shared with client routers and across reloads)
+    sessions: Arc<SessionStore>,
+    /// `override.toml` pins (shared with client routers)
+    pin_file: Arc<PinFile>,
 }
 
 impl Router {
@@ -41,21 +274,21 @@ impl Router {
             .router
             .auto_map_regex
             .as_ref()
-            .and_then(|pattern| {
+            .map(|pattern| {
                 if pattern.is_empty() {
                     // Empty string: use default Claude pattern
-                    Some(Regex::new(r"^claude-").expect("Invalid default Claude regex"))
+                    Regex::new(r"^claude-").expect("Invalid default Claude regex")
                 } else {
                     // Custom pattern provided
                     match Regex::new(pattern) {
-                        Ok(regex) => Some(regex),
+                        Ok(regex) => regex,
                         Err(e) => {
                             eprintln!(
                                 "Warning: Invalid auto_map_regex pattern '{}': {}",
                                 pattern, e
                             );
                             eprintln!("Falling back to default Claude pattern");
-                            Some(Regex::new(r"^claude-").expect("Invalid default Claude regex"))
+                            Regex::new(r"^claude-").expect("Invalid default Claude regex")
                         }
                     }
                 }
@@ -70,26 +303,22 @@ impl Router {
             .router
             .background_regex
             .as_ref()
-            .and_then(|pattern| {
+            .map(|pattern| {
                 if pattern.is_empty() {
                     // Empty string: use default claude-haiku pattern
-                    Some(
-                        Regex::new(r"(?i)claude.*haiku").expect("Invalid default background regex"),
-                    )
+                    Regex::new(r"(?i)claude.*haiku").expect("Invalid default background regex")
                 } else {
                     // Custom pattern provided
                     match Regex::new(pattern) {
-                        Ok(regex) => Some(regex),
+                        Ok(regex) => regex,
                         Err(e) => {
                             eprintln!(
                                 "Warning: Invalid background_regex pattern '{}': {}",
                                 pattern, e
                             );
                             eprintln!("Falling back to default claude-haiku pattern");
-                            Some(
-                                Regex::new(r"(?i)claude.*haiku")
-                                    .expect("Invalid default background regex"),
-                            )
+                            Regex::new(r"(?i)claude.*haiku")
+                                    .expect("Invalid default background regex")
                         }
                     }
                 }
@@ -99,29 +328,26 @@ impl Router {
                 Some(Regex::new(r"(?i)claude.*haiku").expect("Invalid default background regex"))
             });
 
+        // Compile suggestion regex
+        let suggest_regex = match config.router.suggest_regex.as_deref() {
+            Some(pattern) if !pattern.is_empty() => Regex::new(pattern).unwrap_or_else(|e| {
+                eprintln!("Warning: Invalid suggest_regex pattern '{}': {}", pattern, e);
+                eprintln!("Falling back to default suggestion pattern");
+                Regex::new(DEFAULT_SUGGEST_PATTERN).expect("Invalid default suggest regex")
+            }),
+            _ => Regex::new(DEFAULT_SUGGEST_PATTERN).expect("Invalid default suggest regex"),
+        };
+
         // Compile prompt rules
         let prompt_rules: Vec<CompiledPromptRule> = config
             .router
             .prompt_rules
             .iter()
-            .filter_map(|rule| {
-                match Regex::new(&rule.pattern) {
-                    Ok(regex) => {
-                        let is_dynamic = contains_capture_reference(&rule.model);
-                        Some(CompiledPromptRule {
-                            regex,
-                            model: rule.model.clone(),
-                            strip_match: rule.strip_match,
-                            is_dynamic,
-                        })
-                    }
-                    Err(e) => {
-                        eprintln!(
-                            "Warning: Invalid prompt_rule pattern '{}': {}. Skipping.",
-                            rule.pattern, e
-                        );
-                        None
-                    }
+            .filter_map(|rule| match CompiledPromptRule::compile(rule) {
+                Ok(compiled) => Some(compiled),
+                Err(e) => {
+                    eprintln!("Warning: {}. Skipping.", e);
+                    None
                 }
             })
             .collect();
@@ -130,106 +356,388 @@ impl Router {
             info!("📝 Loaded {} prompt routing rules", prompt_rules.len());
         }
 
-        Self {
+        // Compile think keyword levels
+        let think_keywords = &config.router.think_keywords;
+        let think_levels: Vec<(Regex, ThinkLevel)> = think_keywords
+            .levels
+            .iter()
+            .filter(|_| think_keywords.enabled)
+            .filter_map(|level| match Regex::new(&level.pattern) {
+                Ok(regex) => Some((regex, level.clone())),
+                Err(e) => {
+                    eprintln!("Warning: Invalid think_keywords pattern '{}': {}. Skipping.", level.pattern, e);
+                    None
+                }
+            })
+            .collect();
+
+        let clients = Self::build_client_routers(&config);
+
+        let router = Self {
             config,
+            clients,
+            client_name: None,
             auto_map_regex,
             background_regex,
+            suggest_regex,
             prompt_rules,
+            think_levels,
+            sessions: Arc::new(SessionStore::new()),
+            pin_file: Arc::new(PinFile::new(crate::paths::state_file(pin::PIN_FILE))),
+        };
+
+        // Client profiles track sessions in the same store, and follow the same pins
+        let (sessions, pin_file) = (router.sessions.clone(), router.pin_file.clone());
+        router.with_sessions(sessions).with_pin_file(pin_file)
+    }
+
+    /// Share a session store, e.g. the previous router's on config reload
+    pub fn with_sessions(mut self, sessions: Arc<SessionStore>) -> Self {
+        for client in &mut self.clients {
+            client.router.sessions = sessions.clone();
+        }
+        self.sessions = sessions;
+        self
+    }
+
+    /// Read pins from another file (the state directory's `override.toml` by default)
+    pub fn with_pin_file(mut self, pin_file: Arc<PinFile>) -> Self {
+        for client in &mut self.clients {
+            client.router.pin_file = pin_file.clone();
+        }
+        self.pin_file = pin_file;
+        self
+    }
+
+    /// Configuration the router was built from
+    #[allow(dead_code)] // Used by the embedding API (`embed`), not the binary
+    pub fn config(&self) -> &AppConfig {
+        &self.config
+    }
+
+    /// Session store used for once/sticky prompt rules
+    pub fn sessions(&self) -> Arc<SessionStore> {
+        self.sessions.clone()
+    }
+
+    /// Build a router per client profile, with that client's overrides applied
+    fn build_client_routers(config: &AppConfig) -> Vec<ClientRouter> {
+        let compile = |name: &str, field: &str, pattern: &Option<String>| -> Result<Option<Regex>, ()> {
+            match pattern.as_deref() {
+                None | Some("") => Ok(None),
+                Some(p) => Regex::new(p).map(Some).map_err(|e| {
+                    eprintln!("Warning: Invalid {} pattern '{}' for client '{}': {}. Skipping.", field, p, name, e);
+                }),
+            }
+        };
+
+        let mut routers = Vec::new();
+        for client in &config.router.clients {
+            let (Ok(user_agent), Ok(x_app)) = (
+                compile(&client.name, "user_agent", &client.user_agent),
+                compile(&client.name, "x_app", &client.x_app),
+            ) else {
+                continue;
+            };
+            if user_agent.is_none() && x_app.is_none() {
+                eprintln!("Warning: client '{}' has neither user_agent nor x_app. Skipping.", client.name);
+                continue;
+            }
+
+            let mut client_config = config.clone();
+            let router = &mut client_config.router;
+            router.clients.clear();
+            if let Some(ref default) = client.default {
+                router.default = default.clone();
+            }
+            for (target, value) in [
+                (&mut router.background, &client.background),
+                (&mut router.think, &client.think),
+                (&mut router.websearch, &client.websearch),
+                (&mut router.suggest, &client.suggest),
+                (&mut router.long_context, &client.long_context),
+            ] {
+                if value.is_some() {
+                    *target = value.clone();
+                }
+            }
+            let mut prompt_rules = client.prompt_rules.clone();
+            prompt_rules.append(&mut router.prompt_rules);
+            router.prompt_rules = prompt_rules;
+
+            let mut client_router = Router::new(client_config);
+            client_router.client_name = Some(client.name.clone());
+            routers.push(ClientRouter {
+                name: client.name.clone(),
+                user_agent,
+                x_app,
+                router: client_router,
+            });
         }
+
+        if !routers.is_empty() {
+            info!("🧭 Loaded {} client routing profiles", routers.len());
+        }
+        routers
+    }
+
+    /// Select the router for a client based on its User-Agent / x-app headers.
+    /// Falls back to the global router when no client profile matches.
+    pub fn for_client(&self, user_agent: Option<&str>, x_app: Option<&str>) -> &Router {
+        match self.clients.iter().find(|c| c.matches(user_agent, x_app)) {
+            Some(client) => {
+                debug!("🧭 Using routing profile for client '{}'", client.name);
+                &client.router
+            }
+            None => self,
+        }
+    }
+
+    /// Hard pin: skip auto-mapping and all route overrides, using the requested
+    /// model's own mapping. Errors if the model has no mapping.
+    pub fn bypass(&self, request: &AnthropicRequest) -> Result<RouteDecision> {
+        let model_config = self
+            .config
+            .models
+            .iter()
+            .find(|m| m.name.eq_ignore_ascii_case(&request.model))
+            .ok_or_else(|| anyhow::anyhow!("X-CCM-Bypass: no model mapping found for '{}'", request.model))?;
+
+        debug!("📌 Routing bypassed, pinned to {}", model_config.name);
+        Ok(RouteDecision {
+            model_name: model_config.name.clone(),
+            route_type: RouteType::Bypass,
+            matched_prompt: None,
+        })
     }
 
     /// Route an incoming request to the appropriate model
     ///
     /// Priority order (highest to lowest):
     /// 1. WebSearch - tool-based detection (web_search tool present)
-    /// 2. Background - model name regex match (e.g., haiku) - checked early to save costs
-    /// 3. Subagent - CCM-SUBAGENT-MODEL tag in system prompt
-    /// 4. Prompt Rules - regex pattern matching on user prompt (after background for cost savings)
-    /// 5. Think - Plan Mode / reasoning enabled
-    /// 6. Default - auto-mapped or original model name
+    /// 2. Suggest - tiny suggestion/autocomplete requests (system prompt + shape)
+    /// 3. LongContext - estimated input tokens above long_context_threshold
+    /// 4. Background - model name regex match (e.g., haiku) - checked early to save costs
+    /// 5. Subagent - CCM-SUBAGENT-MODEL tag in system prompt
+    /// 6. Prompt Rules - regex pattern matching on user prompt (after background for cost savings)
+    /// 7. Think - Plan Mode / reasoning enabled
+    /// 8. Default - auto-mapped or original model name
     pub fn route(&self, request: &mut AnthropicRequest) -> Result<RouteDecision> {
-        // Save original model for background task detection
-        let original_model = request.model.clone();
+        let plan = self.plan(request)?;
+        self.apply(&plan, request);
+        Ok(plan.decision)
+    }
+
+    /// Route a request and record every step evaluated (for X-CCM-Explain)
+    pub fn route_explained(&self, request: &mut AnthropicRequest) -> Result<(RouteDecision, Vec<RouteStep>)> {
+        let (plan, steps) = self.plan_explained(request)?;
+        self.apply(&plan, request);
+        Ok((plan.decision, steps))
+    }
+
+    /// Decide where a request goes without changing it or any session state.
+    /// The returned mutations are carried out by [`Router::apply`].
+    pub fn plan(&self, request: &AnthropicRequest) -> Result<RoutePlan> {
+        self.plan_with(request, &mut Explainer::disabled())
+    }
+
+    /// [`Router::plan`], also returning every step evaluated
+    pub fn plan_explained(&self, request: &AnthropicRequest) -> Result<(RoutePlan, Vec<RouteStep>)> {
+        let mut explainer = Explainer::enabled();
+        let plan = self.plan_with(request, &mut explainer)?;
+        Ok((plan, explainer.steps.unwrap_or_default()))
+    }
+
+    /// Carry out a plan's mutations on the request and session store
+    pub fn apply(&self, plan: &RoutePlan, request: &mut AnthropicRequest) {
+        for mutation in &plan.mutations {
+            match mutation {
+                RouteMutation::SetModel(model) => request.model = model.clone(),
+                RouteMutation::RemoveSubagentTag => Self::remove_subagent_tag(request),
+                RouteMutation::StripMatch { regex, scope: StripScope::Turn } => {
+                    self.strip_match_from_turn_starting_message(request, regex)
+                }
+                RouteMutation::StripMatch { regex, scope: StripScope::All } => {
+                    self.strip_match_from_all_user_messages(request, regex)
+                }
+                RouteMutation::RecordFiring { session, rule, firing } => {
+                    self.sessions.record(session, rule, firing.clone())
+                }
+                RouteMutation::RaiseThinkingBudget(budget) => Self::raise_thinking_budget(request, *budget),
+            }
+        }
+    }
+
+    fn plan_with(&self, request: &AnthropicRequest, explain: &mut Explainer) -> Result<RoutePlan> {
+        if let Some(ref client) = self.client_name {
+            explain.step("client", true, || format!("client profile '{}'", client));
+        }
+
+        // Temporary pins from override.toml win over router.default/think/background
+        let pin = self.pin_file.current();
+        if let Some(ref pin) = pin {
+            explain.step("pin", true, || format!("override.toml pins {}", pin.summary()));
+        }
+        let pinned = |field: fn(&pin::RoutePin) -> &Option<String>| pin.as_deref().and_then(|pin| field(pin).clone());
+        let default_model = pinned(|pin| &pin.default).unwrap_or_else(|| self.config.router.default.clone());
+        let background_model = pinned(|pin| &pin.background).or_else(|| self.config.router.background.clone());
+        let think_model = pinned(|pin| &pin.think).or_else(|| self.config.router.think.clone());
+
+        let mut mutations = Vec::new();
+        let decide = |model_name: String, route_type: RouteType, matched_prompt: Option<String>, mutations: Vec<RouteMutation>| {
+            Ok(RoutePlan {
+                decision: RouteDecision { model_name, route_type, matched_prompt },
+                mutations,
+            })
+        };
+
+        // Original model for background task detection
+        let original_model = &request.model;
 
         // 0. Auto-mapping (model name transformation FIRST)
         // Transform model name if it matches auto_map_regex
-        if let Some(ref regex) = self.auto_map_regex {
-            if regex.is_match(&request.model) {
-                let old = request.model.clone();
-                request.model = self.config.router.default.clone();
-                debug!("🔀 Auto-mapped model '{}' → '{}'", old, request.model);
+        let model = match self.auto_map_regex {
+            Some(ref regex) if regex.is_match(original_model) => {
+                let mapped = default_model.clone();
+                debug!("🔀 Auto-mapped model '{}' → '{}'", original_model, mapped);
+                explain.step("auto_map", true, || format!("'{}' matches auto_map_regex, mapped to '{}'", original_model, mapped));
+                mutations.push(RouteMutation::SetModel(mapped.clone()));
+                mapped
             }
+            Some(_) => {
+                explain.step("auto_map", false, || format!("'{}' doesn't match auto_map_regex", original_model));
+                original_model.clone()
+            }
+            None => {
+                explain.step("auto_map", false, || "auto_map_regex not configured".to_string());
+                original_model.clone()
+            }
+        };
+
+        // 0b. Web search results (before websearch: the follow-up still offers the web_search tool)
+        if let Some(ref summarizer) = self.config.router.websearch_summarizer {
+            let threshold = self
+                .config
+                .router
+                .websearch_summarizer_threshold
+                .unwrap_or(DEFAULT_WEBSEARCH_SUMMARIZER_THRESHOLD);
+            let tokens = web_search_result_tokens(request);
+            if tokens > threshold {
+                debug!("📰 Routing to websearch summarizer (~{} tokens of search results)", tokens);
+                explain.step("websearch_summarizer", true, || format!("~{} tokens of web search results > {}", tokens, threshold));
+                return decide(summarizer.clone(), RouteType::WebSearch, None, mutations);
+            }
+            explain.step("websearch_summarizer", false, || format!("~{} tokens of web search results <= {}", tokens, threshold));
         }
 
         // 1. WebSearch (HIGHEST PRIORITY - tool-based detection)
         if let Some(ref websearch_model) = self.config.router.websearch {
             if self.has_web_search_tool(request) {
                 debug!("🔍 Routing to websearch model (web_search tool detected)");
-                return Ok(RouteDecision {
-                    model_name: websearch_model.clone(),
-                    route_type: RouteType::WebSearch,
-                    matched_prompt: None,
-                });
+                explain.step("websearch", true, || "web_search tool present".to_string());
+                return decide(websearch_model.clone(), RouteType::WebSearch, None, mutations);
+            }
+            explain.step("websearch", false, || "no web_search tool".to_string());
+        } else {
+            explain.step("websearch", false, || "router.websearch not configured".to_string());
+        }
+
+        // 1b. Suggestion/autocomplete micro-requests (before background, which would
+        // otherwise catch them by their haiku model name)
+        if let Some(ref suggest_model) = self.config.router.suggest {
+            if self.is_suggestion_request(request) {
+                debug!("💡 Routing to suggest model (suggestion request detected)");
+                explain.step("suggest", true, || "suggestion request (shape and system prompt)".to_string());
+                return decide(suggest_model.clone(), RouteType::Suggest, None, mutations);
+            }
+            explain.step("suggest", false, || "not a suggestion request".to_string());
+        } else {
+            explain.step("suggest", false, || "router.suggest not configured".to_string());
+        }
+
+        // 1c. Long context (before background: a large haiku request still needs a big window)
+        if let Some(ref long_context_model) = self.config.router.long_context {
+            let threshold = self.config.router.long_context_threshold.unwrap_or(DEFAULT_LONG_CONTEXT_THRESHOLD);
+            let tokens = estimate_input_tokens(request);
+            if tokens > threshold {
+                debug!("📚 Routing to long-context model (~{} input tokens)", tokens);
+                explain.step("long_context", true, || format!("~{} input tokens > {}", tokens, threshold));
+                return decide(long_context_model.clone(), RouteType::LongContext, None, mutations);
             }
+            explain.step("long_context", false, || format!("~{} input tokens <= {}", tokens, threshold));
+        } else {
+            explain.step("long_context", false, || "router.long_context not configured".to_string());
         }
 
         // 2. Background tasks (check against ORIGINAL model name, before auto-mapping)
         // Checked early to prevent expensive models being used for background tasks
-        if let Some(ref background_model) = self.config.router.background {
-            if self.is_background_task(&original_model) {
+        if let Some(background_model) = background_model {
+            if self.is_background_task(original_model) {
                 debug!("🔄 Routing to background model");
-                return Ok(RouteDecision {
-                    model_name: background_model.clone(),
-                    route_type: RouteType::Background,
-                    matched_prompt: None,
-                });
+                explain.step("background", true, || format!("'{}' matches background_regex", original_model));
+                return decide(background_model, RouteType::Background, None, mutations);
             }
+            explain.step("background", false, || format!("'{}' doesn't match background_regex", original_model));
+        } else {
+            explain.step("background", false, || "router.background not configured".to_string());
         }
 
-        // 3. Subagent Model (system prompt tag)
-        if let Some(model) = self.extract_subagent_model(request) {
+        // 3. Subagent Model (system prompt tag, removed when applied)
+        if let Some(subagent_model) = self.subagent_model(request) {
             debug!(
                 "🤖 Routing to subagent model (CCM-SUBAGENT-MODEL tag): {}",
-                model
+                subagent_model
             );
-            return Ok(RouteDecision {
-                model_name: model,
-                route_type: RouteType::Default,
-                matched_prompt: None,
-            });
+            explain.step("subagent", true, || format!("CCM-SUBAGENT-MODEL tag: {}", subagent_model));
+            mutations.push(RouteMutation::RemoveSubagentTag);
+            return decide(subagent_model, RouteType::Default, None, mutations);
         }
+        explain.step("subagent", false, || "no CCM-SUBAGENT-MODEL tag".to_string());
 
         // 4. Prompt Rules (pattern matching on user prompt)
         // NOTE: Checked AFTER background to ensure background tasks use cheaper models
-        if let Some((model, matched_text)) = self.match_prompt_rule(request) {
-            debug!("📝 Routing to model via prompt rule match: {}", model);
-            return Ok(RouteDecision {
-                model_name: model,
-                route_type: RouteType::PromptRule,
-                matched_prompt: Some(matched_text),
-            });
+        if let Some(rule_match) = self.match_prompt_rule(request) {
+            debug!("📝 Routing to model via prompt rule match: {}", rule_match.model);
+            explain.step("prompt_rules", true, || format!("matched '{}' → {}", rule_match.matched, rule_match.model));
+            mutations.extend(rule_match.mutations);
+            return decide(rule_match.model, RouteType::PromptRule, Some(rule_match.matched), mutations);
         }
-
-        // 5. Think mode (Plan Mode / Reasoning)
-        if let Some(ref think_model) = self.config.router.think {
+        explain.step("prompt_rules", false, || format!("none of {} rules matched", self.prompt_rules.len()));
+
+        // 5. Think mode (Plan Mode / Reasoning, or a think keyword in the prompt)
+        let keyword = self.match_think_keyword(request);
+        if let Some((level, matched)) = &keyword {
+            debug!("🧠 Think keyword '{}': thinking budget {}", matched, level.budget_tokens);
+            mutations.push(RouteMutation::RaiseThinkingBudget(level.budget_tokens));
+            if let Some(model) = &level.model {
+                explain.step("think", true, || format!("keyword '{}' → {} thinking tokens, {}", matched, level.budget_tokens, model));
+                return decide(model.clone(), RouteType::Think, Some(matched.clone()), mutations);
+            }
+        }
+        if let Some(think_model) = think_model {
+            if let Some((level, matched)) = keyword {
+                explain.step("think", true, || format!("keyword '{}' → {} thinking tokens", matched, level.budget_tokens));
+                return decide(think_model, RouteType::Think, Some(matched), mutations);
+            }
             if self.is_plan_mode(request) {
                 debug!("🧠 Routing to think model (Plan Mode detected)");
-                return Ok(RouteDecision {
-                    model_name: think_model.clone(),
-                    route_type: RouteType::Think,
-                    matched_prompt: None,
-                });
+                explain.step("think", true, || "thinking enabled".to_string());
+                return decide(think_model, RouteType::Think, None, mutations);
             }
+            explain.step("think", false, || "thinking not enabled".to_string());
+        } else if let Some((level, matched)) = keyword {
+            explain.step("think", false, || {
+                format!("keyword '{}' → {} thinking tokens; router.think not configured", matched, level.budget_tokens)
+            });
+        } else {
+            explain.step("think", false, || "router.think not configured".to_string());
         }
 
         // 6. Default fallback
         // Use the transformed model name (from auto-mapping) or original if no mapping
-        debug!("✅ Using model: {}", request.model);
-        Ok(RouteDecision {
-            model_name: request.model.clone(),
-            route_type: RouteType::Default,
-            matched_prompt: None,
-        })
+        debug!("✅ Using model: {}", model);
+        explain.step("default", true, || format!("using model '{}'", model));
+        decide(model, RouteType::Default, None, mutations)
     }
 
     /// Check if request has web_search tool (tool-based detection)
@@ -247,6 +755,48 @@ impl Router {
         }
     }
 
+    /// Detect suggestion/autocomplete requests by shape and system prompt signature:
+    /// small max_tokens, no tools, no thinking, and a system prompt matching suggest_regex
+    fn is_suggestion_request(&self, request: &AnthropicRequest) -> bool {
+        let max_tokens = self.config.router.suggest_max_tokens.unwrap_or(DEFAULT_SUGGEST_MAX_TOKENS);
+        if request.max_tokens > max_tokens {
+            return false;
+        }
+        if request.tools.as_ref().map(|t| !t.is_empty()).unwrap_or(false) || self.is_plan_mode(request) {
+            return false;
+        }
+
+        match &request.system {
+            Some(SystemPrompt::Text(text)) => self.suggest_regex.is_match(text),
+            Some(SystemPrompt::Blocks(blocks)) => blocks.iter().any(|b| self.suggest_regex.is_match(&b.text)),
+            None => false,
+        }
+    }
+
+    /// First think keyword level matching the turn-starting user message, with the matched text
+    fn match_think_keyword(&self, request: &AnthropicRequest) -> Option<(ThinkLevel, String)> {
+        if self.think_levels.is_empty() {
+            return None;
+        }
+        let content = self.extract_turn_starting_user_message(request)?;
+        self.think_levels
+            .iter()
+            .find_map(|(regex, level)| regex.find(&content).map(|m| (level.clone(), m.as_str().to_string())))
+    }
+
+    /// Enable thinking with at least `budget` tokens. The budget stays below max_tokens,
+    /// as the API requires; requests too small for the minimum budget are left alone.
+    fn raise_thinking_budget(request: &mut AnthropicRequest, budget: u32) {
+        let budget = budget.min(request.max_tokens.saturating_sub(1));
+        if budget < MIN_THINKING_BUDGET {
+            return;
+        }
+        let current = request.thinking.as_ref().filter(|t| t.r#type == "enabled").and_then(|t| t.budget_tokens);
+        if current.is_none_or(|current| current < budget) {
+            request.thinking = Some(ThinkingConfig { r#type: "enabled".to_string(), budget_tokens: Some(budget) });
+        }
+    }
+
     /// Check if request is Plan Mode by detecting thinking field
     fn is_plan_mode(&self, request: &AnthropicRequest) -> bool {
         request
@@ -267,13 +817,13 @@ impl Router {
     }
 
     /// Match prompt rules against the turn-starting user message content
-    /// Returns (model_name, matched_text) if a rule matches, None otherwise
-    /// Strips the matched phrase from the prompt if strip_match is true
+    /// Returns the model, matched text and planned mutations if a rule matches
+    /// Plans stripping the matched phrase from the prompt if strip_match is true
     /// For dynamic rules (model contains $refs), expands capture groups in the model name
     ///
     /// NOTE: We check the turn-starting message (not just the last user message) so that
     /// prompt phrases like "OPUS" persist for the entire turn, even through tool calls.
-    fn match_prompt_rule(&self, request: &mut AnthropicRequest) -> Option<(String, String)> {
+    fn match_prompt_rule(&self, request: &AnthropicRequest) -> Option<PromptRuleMatch> {
         if self.prompt_rules.is_empty() {
             return None;
         }
@@ -312,11 +862,42 @@ impl Router {
         }
 
         // Extract turn-starting user message content (persists through tool calls)
-        let user_content = self.extract_turn_starting_user_message(request)?;
+        let user_content = self.extract_turn_starting_user_message(request);
+
+        // Session identity for once/sticky rules (Claude Code sets user_id per session)
+        let session = Self::metadata_value(request, "user_id");
+        let turn = {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            user_content.hash(&mut hasher);
+            hasher.finish()
+        };
 
         // Check each rule in order (first match wins)
         for rule in &self.prompt_rules {
-            if let Some(captures) = rule.regex.captures(&user_content) {
+            if !Self::metadata_matches(rule, request) {
+                continue;
+            }
+
+            // Metadata-only rule: no prompt condition
+            let Some(ref regex) = rule.regex else {
+                let matched_text = rule
+                    .metadata
+                    .iter()
+                    .map(|(key, _)| format!("metadata.{}", key))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let Some(mutations) = self.session_allows(rule, session.as_deref(), turn, &rule.model, &matched_text) else {
+                    continue;
+                };
+                debug!("📝 Metadata rule matched: {} → model='{}'", matched_text, rule.model);
+                return Some(PromptRuleMatch { model: rule.model.clone(), matched: matched_text, mutations });
+            };
+
+            let Some(ref user_content) = user_content else {
+                continue;
+            };
+
+            if let Some(captures) = regex.captures(user_content) {
                 let matched_text = captures
                     .get(0)
                     .map(|m| m.as_str().to_string())
@@ -329,25 +910,89 @@ impl Router {
                     rule.model.clone()
                 };
 
+                let Some(mut mutations) = self.session_allows(rule, session.as_deref(), turn, &model_name, &matched_text) else {
+                    continue;
+                };
+
                 debug!(
                     "📝 Prompt rule matched: pattern='{}' → model='{}' (strip_match={})",
-                    rule.regex.as_str(),
+                    regex.as_str(),
                     model_name,
                     rule.strip_match
                 );
 
-                // Strip the matched phrase from the turn-starting message if requested
+                // Strip the matched phrase from the turn-starting message (or all of them) if requested
                 if rule.strip_match {
-                    self.strip_match_from_turn_starting_message(request, &rule.regex);
+                    mutations.push(RouteMutation::StripMatch { regex: regex.clone(), scope: rule.strip_scope });
                 }
 
-                return Some((model_name, matched_text));
+                return Some(PromptRuleMatch { model: model_name, matched: matched_text, mutations });
+            }
+        }
+
+        // Sticky rules keep applying for the rest of the session
+        let session = session?;
+        for rule in self.prompt_rules.iter().filter(|r| r.lifetime == RuleLifetime::Sticky) {
+            if let Some(firing) = self.sessions.firing(&session, &rule.key) {
+                debug!("📌 Sticky prompt rule still active for session → model='{}'", firing.model);
+                return Some(PromptRuleMatch { model: firing.model, matched: firing.matched, mutations: Vec::new() });
             }
         }
 
         None
     }
 
+    /// Apply a rule's session lifetime to a match. Returns None when a once-per-session
+    /// rule already fired in an earlier turn, otherwise the mutations recording a first firing.
+    fn session_allows(&self, rule: &CompiledPromptRule, session: Option<&str>, turn: u64, model: &str, matched: &str) -> Option<Vec<RouteMutation>> {
+        let Some(session) = session.filter(|_| rule.lifetime != RuleLifetime::Turn) else {
+            return Some(Vec::new());
+        };
+
+        match self.sessions.firing(session, &rule.key) {
+            Some(firing) if rule.lifetime == RuleLifetime::Once && firing.turn != turn => {
+                debug!("📝 Prompt rule '{}' already fired this session, ignoring", matched);
+                None
+            }
+            Some(_) => Some(Vec::new()),
+            None => Some(vec![RouteMutation::RecordFiring {
+                session: session.to_string(),
+                rule: rule.key.clone(),
+                firing: RuleFiring {
+                    turn,
+                    model: model.to_string(),
+                    matched: matched.to_string(),
+                },
+            }]),
+        }
+    }
+
+    /// Check that every metadata pattern of a rule matches the request metadata
+    fn metadata_matches(rule: &CompiledPromptRule, request: &AnthropicRequest) -> bool {
+        rule.metadata.iter().all(|(key, regex)| {
+            Self::metadata_value(request, key)
+                .map(|value| regex.is_match(&value))
+                .unwrap_or(false)
+        })
+    }
+
+    /// Look up a metadata field by dotted path (e.g. "user_id", "tags.team").
+    /// Non-string values are matched against their JSON representation.
+    fn metadata_value(request: &AnthropicRequest, path: &str) -> Option<String> {
+        let metadata = request.metadata.as_ref()?;
+        let mut parts = path.split('.');
+        let mut value = metadata.get(parts.next()?)?;
+        for part in parts {
+            value = value.get(part)?;
+        }
+
+        match value {
+            serde_json::Value::String(s) => Some(s.clone()),
+            serde_json::Value::Null => None,
+            other => Some(other.to_string()),
+        }
+    }
+
     /// Expand capture group references in a model template string
     /// Supports $1, $name, ${1}, ${name} syntax via regex crate's Captures::expand
     fn expand_model_template(template: &str, captures: &regex::Captures) -> String {
@@ -544,6 +1189,36 @@ impl Router {
         self.strip_match_from_last_user_message(request, regex);
     }
 
+    /// Strip the matched phrase from every user text message in the history
+    fn strip_match_from_all_user_messages(&self, request: &mut AnthropicRequest, regex: &Regex) {
+        let mut stripped_count = 0;
+        for msg in request.messages.iter_mut().filter(|m| m.role == "user") {
+            match &mut msg.content {
+                MessageContent::Text(text) => {
+                    let stripped = regex.replace_all(text, "").to_string();
+                    if stripped != *text {
+                        *text = stripped;
+                        stripped_count += 1;
+                    }
+                }
+                MessageContent::Blocks(blocks) => {
+                    for block in blocks.iter_mut() {
+                        if let Some(text) = block.as_text_mut() {
+                            let stripped = regex.replace_all(text, "").to_string();
+                            if stripped != *text {
+                                *text = stripped;
+                                stripped_count += 1;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        if stripped_count > 0 {
+            debug!("🔪 Stripped matched phrase from {} user message(s)", stripped_count);
+        }
+    }
+
     /// Strip the matched phrase from the last user message (fallback for edge cases)
     fn strip_match_from_last_user_message(&self, request: &mut AnthropicRequest, regex: &Regex) {
         // Find the last user message (mutable)
@@ -575,53 +1250,46 @@ impl Router {
     }
 
     /// Extract subagent model from system prompt tag
-    /// Checks for <CCM-SUBAGENT-MODEL>model-name</CCM-SUBAGENT-MODEL> in system[1].text
-    /// and removes the tag after extraction.
+    /// Checks for <CCM-SUBAGENT-MODEL>model-name</CCM-SUBAGENT-MODEL> in system[1].text.
+    /// The tag is removed by the RemoveSubagentTag mutation.
     ///
     /// First attempts to resolve the tag value as a model name in the models config.
     /// Falls back to treating it as a direct provider model name (deprecated behavior).
-    fn extract_subagent_model(&self, request: &mut AnthropicRequest) -> Option<String> {
+    fn subagent_model(&self, request: &AnthropicRequest) -> Option<String> {
         // Check if system exists and is Blocks type with at least 2 blocks
-        let system = request.system.as_mut()?;
-
-        if let SystemPrompt::Blocks(blocks) = system {
-            if blocks.len() < 2 {
-                return None;
-            }
-
-            // Check second block (index 1) for tag
-            let second_block = &mut blocks[1];
-            if !second_block.text.contains("<CCM-SUBAGENT-MODEL>") {
-                return None;
-            }
+        let Some(SystemPrompt::Blocks(blocks)) = &request.system else {
+            return None;
+        };
 
-            // Extract model name using regex
-            let re = Regex::new(r"<CCM-SUBAGENT-MODEL>(.*?)</CCM-SUBAGENT-MODEL>")
-                .expect("Invalid regex pattern");
+        // Check second block (index 1) for tag
+        let second_block = blocks.get(1)?;
+        if !second_block.text.contains("<CCM-SUBAGENT-MODEL>") {
+            return None;
+        }
 
-            if let Some(captures) = re.captures(&second_block.text) {
-                if let Some(model_match) = captures.get(1) {
-                    let tag_value = model_match.as_str().to_string();
+        // Extract model name using regex
+        let tag_value = SUBAGENT_TAG_PATTERN.captures(&second_block.text)?.get(1)?.as_str().to_string();
 
-                    // Remove the tag from the text
-                    second_block.text = re.replace_all(&second_block.text, "").to_string();
+        // First, try to find a model with this name in the models config (case-insensitive)
+        if let Some(model) = self.config.models.iter().find(|m| m.name.eq_ignore_ascii_case(&tag_value)) {
+            // Found a configured model with this name (use the configured case)
+            return Some(model.name.clone());
+        }
 
-                    // First, try to find a model with this name in the models config (case-insensitive)
-                    if let Some(_model) = self.config.models.iter().find(|m| m.name.eq_ignore_ascii_case(&tag_value)) {
-                        // Found a configured model with this name (use the configured case)
-                        return Some(_model.name.clone());
-                    }
+        // DEPRECATED: Fall back to treating the tag value as a direct provider model name
+        // This behavior is deprecated and should not be relied upon.
+        // Please configure a named model in the [models] section instead.
+        debug!("⚠️  CCM-SUBAGENT-MODEL tag '{}' not found in models config, using as direct provider model name (deprecated)", tag_value);
+        Some(tag_value)
+    }
 
-                    // DEPRECATED: Fall back to treating the tag value as a direct provider model name
-                    // This behavior is deprecated and should not be relied upon.
-                    // Please configure a named model in the [models] section instead.
-                    debug!("⚠️  CCM-SUBAGENT-MODEL tag '{}' not found in models config, using as direct provider model name (deprecated)", tag_value);
-                    return Some(tag_value);
-                }
+    /// Remove the CCM-SUBAGENT-MODEL tag from system[1].text
+    fn remove_subagent_tag(request: &mut AnthropicRequest) {
+        if let Some(SystemPrompt::Blocks(blocks)) = request.system.as_mut() {
+            if let Some(second_block) = blocks.get_mut(1) {
+                second_block.text = SUBAGENT_TAG_PATTERN.replace_all(&second_block.text, "").to_string();
             }
         }
-
-        None
     }
 }
 
@@ -642,9 +1310,13 @@ mod tests {
                 auto_map_regex: None,   // Use default Claude pattern
                 background_regex: None, // Use default claude-haiku pattern
                 prompt_rules: vec![],   // No prompt rules by default
+                ..Default::default()
             },
             providers: vec![],
             models: vec![],
+            pricing: Default::default(),
+            budgets: Vec::new(),
+            slos: Vec::new(),
         }
     }
 
@@ -665,6 +1337,8 @@ mod tests {
             metadata: None,
             system: None,
             tools: None,
+            anthropic_version: None,
+            extra_body: None,
         }
     }
 
@@ -684,6 +1358,66 @@ mod tests {
         assert_eq!(decision.model_name, "think.model");
     }
 
+    #[test]
+    fn test_think_keywords_raise_budget() {
+        let mut config = create_test_config();
+        config.router.think_keywords.enabled = true;
+        let router = Router::new(config.clone());
+
+        let route = |router: &Router, text: &str, max_tokens: u32| {
+            let mut request = create_simple_request(text);
+            request.max_tokens = max_tokens;
+            let decision = router.route(&mut request).unwrap();
+            (decision, request.thinking.and_then(|t| t.budget_tokens))
+        };
+
+        let (decision, budget) = route(&router, "Please ultrathink about this design", 64_000);
+        assert_eq!(decision.route_type, RouteType::Think);
+        assert_eq!(decision.model_name, "think.model");
+        assert_eq!(decision.matched_prompt.as_deref(), Some("ultrathink"));
+        assert_eq!(budget, Some(31_999));
+
+        assert_eq!(route(&router, "Think hard before you answer", 64_000).1, Some(10_000));
+        assert_eq!(route(&router, "think", 64_000).1, Some(4_000));
+        // The budget stays below max_tokens
+        assert_eq!(route(&router, "ultrathink", 8_000).1, Some(7_999));
+        let (decision, budget) = route(&router, "Rethinking the plan", 64_000);
+        assert_eq!(decision.route_type, RouteType::Default);
+        assert_eq!(budget, None);
+
+        // A level's own model wins over router.think
+        config.router.think_keywords.levels[0].model = Some("opus.model".to_string());
+        let router = Router::new(config);
+        assert_eq!(route(&router, "ultrathink", 64_000).0.model_name, "opus.model");
+        assert_eq!(route(&router, "think hard", 64_000).0.model_name, "think.model");
+    }
+
+    #[test]
+    fn test_override_file_pins_routes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(pin::PIN_FILE);
+        let mut config = create_test_config();
+        config.router.auto_map_regex = Some(String::new());
+        let router = Router::new(config).with_pin_file(Arc::new(PinFile::new(path.clone())));
+
+        let mut thinking = create_simple_request("Plan the migration");
+        thinking.thinking = Some(ThinkingConfig { r#type: "enabled".to_string(), budget_tokens: Some(10_000) });
+        assert_eq!(router.route(&mut create_simple_request("Hi")).unwrap().model_name, "default.model");
+
+        std::fs::write(&path, "default = \"pinned.model\"\nthink = \"pinned.model\"\n").unwrap();
+        let mut request = create_simple_request("Hi");
+        assert_eq!(router.route(&mut request).unwrap().model_name, "pinned.model");
+        assert_eq!(request.model, "pinned.model");
+        let decision = router.route(&mut thinking.clone()).unwrap();
+        assert_eq!((decision.route_type, decision.model_name.as_str()), (RouteType::Think, "pinned.model"));
+        let (_, steps) = router.route_explained(&mut create_simple_request("Hi")).unwrap();
+        assert_eq!(steps[0].reason, "override.toml pins default=pinned.model, think=pinned.model");
+
+        // Deleting the file unpins without a reload
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(router.route(&mut thinking).unwrap().model_name, "think.model");
+    }
+
     #[test]
     fn test_background_task_detection() {
         let config = create_test_config();
@@ -741,6 +1475,7 @@ mod tests {
                 "type": "object",
                 "properties": {}
             })),
+            cache_control: None,
         }]);
 
         let decision = router.route(&mut request).unwrap();
@@ -764,6 +1499,7 @@ mod tests {
             name: None,
             description: None,
             input_schema: None,
+            cache_control: None,
         }]);
 
         let decision = router.route(&mut request).unwrap();
@@ -771,6 +1507,77 @@ mod tests {
         assert_eq!(decision.model_name, "websearch.model");
     }
 
+    #[test]
+    fn test_websearch_summarizer_routing() {
+        let mut config = create_test_config();
+        config.router.websearch_summarizer = Some("summarizer.model".to_string());
+        config.router.websearch_summarizer_threshold = Some(100);
+        let router = Router::new(config);
+
+        let web_search_tool = crate::models::Tool {
+            r#type: Some("web_search_20250305".to_string()),
+            name: Some("web_search".to_string()),
+            description: None,
+            input_schema: None,
+            cache_control: None,
+        };
+        let results = |page: &str| {
+            crate::models::ContentBlock::Unknown(serde_json::json!({
+                "type": "web_search_tool_result",
+                "tool_use_id": "srvtoolu_1",
+                "content": [{"type": "web_search_result", "url": "https://example.com", "title": "Example", "encrypted_content": page}]
+            }))
+        };
+        let follow_up = |page: &str| {
+            let mut request = create_simple_request("Search the web for latest news");
+            request.tools = Some(vec![web_search_tool.clone()]);
+            request.messages.push(Message { role: "assistant".to_string(), content: MessageContent::Blocks(vec![results(page)]) });
+            request
+        };
+
+        // Small results stay on the websearch model
+        let decision = router.route(&mut follow_up("short")).unwrap();
+        assert_eq!(decision.model_name, "websearch.model");
+
+        let mut request = follow_up(&"x".repeat(2_000));
+        assert!(web_search_result_tokens(&request) > 500);
+        let decision = router.route(&mut request).unwrap();
+        assert_eq!(decision.route_type, RouteType::WebSearch);
+        assert_eq!(decision.model_name, "summarizer.model");
+    }
+
+    #[test]
+    fn test_long_context_routing() {
+        let mut config = create_test_config();
+        config.router.long_context = Some("long.model".to_string());
+        config.router.long_context_threshold = Some(1000);
+        let router = Router::new(config);
+
+        // ~1250 tokens of history goes to the long-context model, even for a haiku request
+        let mut request = create_simple_request(&"word ".repeat(1000));
+        request.model = "claude-3-5-haiku-20241022".to_string();
+        let decision = router.route(&mut request).unwrap();
+        assert_eq!(decision.route_type, RouteType::LongContext);
+        assert_eq!(decision.model_name, "long.model");
+
+        // Small requests are unaffected
+        let mut request = create_simple_request("short prompt");
+        let decision = router.route(&mut request).unwrap();
+        assert_eq!(decision.route_type, RouteType::Default);
+
+        // Tool definitions count toward the estimate
+        let mut request = create_simple_request("short prompt");
+        request.tools = Some(vec![crate::models::Tool {
+            r#type: None,
+            name: Some("Read".to_string()),
+            description: Some("x".repeat(5000)),
+            input_schema: None,
+            cache_control: None,
+        }]);
+        assert!(estimate_input_tokens(&request) > 1000);
+        assert_eq!(router.route(&mut request).unwrap().route_type, RouteType::LongContext);
+    }
+
     #[test]
     fn test_auto_map_claude_models() {
         let config = create_test_config();
@@ -822,6 +1629,7 @@ mod tests {
             pattern: "(?i)commit.*changes".to_string(),
             model: "fast-model".to_string(),
             strip_match: false,
+            ..Default::default()
         }];
         let router = Router::new(config);
 
@@ -839,6 +1647,7 @@ mod tests {
             pattern: r"\[fast\]".to_string(),
             model: "fast-model".to_string(),
             strip_match: true,
+            ..Default::default()
         }];
         let router = Router::new(config);
 
@@ -864,6 +1673,7 @@ mod tests {
             pattern: r"\[fast\]".to_string(),
             model: "fast-model".to_string(),
             strip_match: false,
+            ..Default::default()
         }];
         let router = Router::new(config);
 
@@ -888,6 +1698,7 @@ mod tests {
             pattern: r"(?i)CCM-MODEL:([a-zA-Z0-9._-]+)".to_string(),
             model: "$1".to_string(),
             strip_match: true,
+            ..Default::default()
         }];
         let router = Router::new(config);
 
@@ -913,6 +1724,7 @@ mod tests {
             pattern: r"(?i)USE-MODEL:(?P<model>[a-zA-Z0-9._-]+)".to_string(),
             model: "$model".to_string(),
             strip_match: true,
+            ..Default::default()
         }];
         let router = Router::new(config);
 
@@ -930,6 +1742,7 @@ mod tests {
             pattern: r"@(\w+)-mode".to_string(),
             model: "provider-$1".to_string(),
             strip_match: false,
+            ..Default::default()
         }];
         let router = Router::new(config);
 
@@ -948,6 +1761,7 @@ mod tests {
             pattern: r"\[static\]".to_string(),
             model: "static-model".to_string(), // No $ references
             strip_match: true,
+            ..Default::default()
         }];
         let router = Router::new(config);
 
@@ -979,6 +1793,7 @@ mod tests {
             pattern: r"(?i)OPUS".to_string(),
             model: "opus-model".to_string(),
             strip_match: false,
+            ..Default::default()
         }];
         let router = Router::new(config);
 
@@ -1028,6 +1843,8 @@ mod tests {
             metadata: None,
             system: None,
             tools: None,
+            anthropic_version: None,
+            extra_body: None,
         };
 
         let decision = router.route(&mut request).unwrap();
@@ -1047,6 +1864,7 @@ mod tests {
             pattern: r"(?i)OPUS".to_string(),
             model: "opus-model".to_string(),
             strip_match: false,
+            ..Default::default()
         }];
         let router = Router::new(config);
 
@@ -1082,6 +1900,8 @@ mod tests {
             metadata: None,
             system: None,
             tools: None,
+            anthropic_version: None,
+            extra_body: None,
         };
 
         let decision = router.route(&mut request).unwrap();
@@ -1102,6 +1922,7 @@ mod tests {
             pattern: r"\[OPUS\]".to_string(),
             model: "opus-model".to_string(),
             strip_match: true,
+            ..Default::default()
         }];
         let router = Router::new(config);
 
@@ -1147,6 +1968,8 @@ mod tests {
             metadata: None,
             system: None,
             tools: None,
+            anthropic_version: None,
+            extra_body: None,
         };
 
         let decision = router.route(&mut request).unwrap();
@@ -1161,4 +1984,382 @@ mod tests {
             panic!("Expected text content in first message");
         }
     }
+
+    #[test]
+    fn test_prompt_rule_strip_scope_all() {
+        use crate::cli::PromptRule;
+
+        let rule = |strip_scope| PromptRule {
+            pattern: r"\bOPUS\b".to_string(),
+            model: "opus-model".to_string(),
+            strip_match: true,
+            strip_scope,
+            ..Default::default()
+        };
+        let history = |router: &Router| {
+            let mut request = create_simple_request("OPUS plan the refactor");
+            request.messages.push(Message {
+                role: "assistant".to_string(),
+                content: MessageContent::Text("Here is the plan".to_string()),
+            });
+            request.messages.push(Message {
+                role: "user".to_string(),
+                content: MessageContent::Text("OPUS now implement it".to_string()),
+            });
+            let decision = router.route(&mut request).unwrap();
+            assert_eq!(decision.model_name, "opus-model");
+            request
+        };
+        let text = |request: &AnthropicRequest, i: usize| match &request.messages[i].content {
+            MessageContent::Text(text) => text.clone(),
+            _ => panic!("Expected text content"),
+        };
+
+        let mut config = create_test_config();
+        config.router.prompt_rules = vec![rule(StripScope::Turn)];
+        let request = history(&Router::new(config));
+        assert!(text(&request, 0).contains("OPUS"), "earlier turns are untouched by default");
+        assert!(!text(&request, 2).contains("OPUS"));
+
+        let mut config = create_test_config();
+        config.router.prompt_rules = vec![rule(StripScope::All)];
+        let request = history(&Router::new(config));
+        assert!(!text(&request, 0).contains("OPUS"));
+        assert!(!text(&request, 2).contains("OPUS"));
+        assert_eq!(text(&request, 1), "Here is the plan");
+    }
+
+    #[test]
+    fn test_prompt_rule_lifetime_once_and_sticky() {
+        use crate::cli::PromptRule;
+
+        let mut config = create_test_config();
+        config.router.prompt_rules = vec![
+            PromptRule {
+                pattern: r"\[fast\]".to_string(),
+                model: "fast-model".to_string(),
+                lifetime: RuleLifetime::Once,
+                ..Default::default()
+            },
+            PromptRule {
+                pattern: r"\[opus\]".to_string(),
+                model: "opus-model".to_string(),
+                lifetime: RuleLifetime::Sticky,
+                ..Default::default()
+            },
+        ];
+        let router = Router::new(config);
+        let route = |text: &str, session: &str| {
+            let mut request = create_simple_request(text);
+            request.metadata = Some([("user_id".to_string(), serde_json::json!(session))].into());
+            router.route(&mut request).unwrap()
+        };
+
+        // Once: the triggering turn (including its tool round-trips) uses the rule...
+        assert_eq!(route("[fast] fix typo", "s1").model_name, "fast-model");
+        assert_eq!(route("[fast] fix typo", "s1").model_name, "fast-model");
+        // ...but a later turn in the same session doesn't
+        assert_ne!(route("[fast] another one", "s1").model_name, "fast-model");
+        // Other sessions are unaffected
+        assert_eq!(route("[fast] another one", "s2").model_name, "fast-model");
+
+        // Sticky: applies to later requests without the tag
+        assert_ne!(route("plain request", "s3").route_type, RouteType::PromptRule);
+        assert_eq!(route("[opus] design this", "s3").model_name, "opus-model");
+        let decision = route("now implement it", "s3");
+        assert_eq!(decision.route_type, RouteType::PromptRule);
+        assert_eq!(decision.model_name, "opus-model");
+
+        // Sessions survive a rebuilt router that shares the store
+        let rebuilt = Router::new(create_test_config()).with_sessions(router.sessions());
+        assert!(rebuilt.sessions().session_count() >= 3);
+    }
+
+    #[test]
+    fn test_plan_is_pure_and_apply_matches_route() {
+        use crate::cli::PromptRule;
+        use crate::models::SystemBlock;
+
+        let mut config = create_test_config();
+        config.router.prompt_rules = vec![PromptRule {
+            pattern: r"\[opus\]".to_string(),
+            model: "opus-model".to_string(),
+            strip_match: true,
+            lifetime: RuleLifetime::Sticky,
+            ..Default::default()
+        }];
+        let router = Router::new(config);
+
+        let mut tagged = create_simple_request("[opus] design this");
+        tagged.metadata = Some([("user_id".to_string(), serde_json::json!("s1"))].into());
+        let mut subagent = create_simple_request("review this");
+        subagent.system = Some(SystemPrompt::Blocks(vec![
+            SystemBlock { r#type: "text".to_string(), text: "You are Claude Code".to_string(), cache_control: None },
+            SystemBlock { r#type: "text".to_string(), text: "<CCM-SUBAGENT-MODEL>glm-4.6</CCM-SUBAGENT-MODEL>Review".to_string(), cache_control: None },
+        ]));
+
+        for request in [tagged, subagent] {
+            // Planning leaves the request and session store untouched
+            let before = serde_json::to_value(&request).unwrap();
+            let sessions = router.sessions().session_count();
+            let plan = router.plan(&request).unwrap();
+            assert_eq!(serde_json::to_value(&request).unwrap(), before);
+            assert_eq!(router.sessions().session_count(), sessions);
+            assert!(!plan.mutations.is_empty());
+
+            // plan + apply gives the same decision and request as route() on a fresh router
+            let mut planned = request.clone();
+            router.apply(&plan, &mut planned);
+            let mut routed = request.clone();
+            let decision = Router::new(router.config.clone()).route(&mut routed).unwrap();
+            assert_eq!(plan.decision.model_name, decision.model_name);
+            assert_eq!(plan.decision.route_type, decision.route_type);
+            assert_eq!(serde_json::to_value(&planned).unwrap(), serde_json::to_value(&routed).unwrap());
+            assert_ne!(serde_json::to_value(&planned).unwrap(), before);
+        }
+
+        // The sticky firing was recorded by apply
+        assert_eq!(router.sessions().session_count(), 1);
+    }
+
+    #[test]
+    fn test_metadata_rule_matches_user_id() {
+        use crate::cli::PromptRule;
+        let mut config = create_test_config();
+        config.router.prompt_rules = vec![PromptRule {
+            model: "teammate-model".to_string(),
+            metadata: [("user_id".to_string(), "^user_alice".to_string())].into(),
+            ..Default::default()
+        }];
+        let router = Router::new(config);
+
+        let mut request = create_simple_request("hello");
+        request.metadata = Some([("user_id".to_string(), serde_json::json!("user_alice_session_1"))].into());
+        let decision = router.route(&mut request).unwrap();
+        assert_eq!(decision.route_type, RouteType::PromptRule);
+        assert_eq!(decision.model_name, "teammate-model");
+        assert_eq!(decision.matched_prompt.as_deref(), Some("metadata.user_id"));
+
+        // Different user falls through to default routing
+        let mut request = create_simple_request("hello");
+        request.metadata = Some([("user_id".to_string(), serde_json::json!("user_bob"))].into());
+        let decision = router.route(&mut request).unwrap();
+        assert_eq!(decision.route_type, RouteType::Default);
+
+        // No metadata at all
+        let mut request = create_simple_request("hello");
+        let decision = router.route(&mut request).unwrap();
+        assert_eq!(decision.route_type, RouteType::Default);
+    }
+
+    #[test]
+    fn test_metadata_rule_nested_tag_with_pattern() {
+        use crate::cli::PromptRule;
+        let mut config = create_test_config();
+        config.router.prompt_rules = vec![PromptRule {
+            pattern: "(?i)deploy".to_string(),
+            model: "infra-model".to_string(),
+            metadata: [("tags.team".to_string(), "^infra$".to_string())].into(),
+            ..Default::default()
+        }];
+        let router = Router::new(config);
+
+        let infra_metadata = || Some([("tags".to_string(), serde_json::json!({"team": "infra"}))].into());
+
+        // Both metadata and prompt must match
+        let mut request = create_simple_request("please deploy this");
+        request.metadata = infra_metadata();
+        let decision = router.route(&mut request).unwrap();
+        assert_eq!(decision.model_name, "infra-model");
+
+        let mut request = create_simple_request("write docs");
+        request.metadata = infra_metadata();
+        let decision = router.route(&mut request).unwrap();
+        assert_eq!(decision.route_type, RouteType::Default);
+
+        let mut request = create_simple_request("please deploy this");
+        let decision = router.route(&mut request).unwrap();
+        assert_eq!(decision.route_type, RouteType::Default);
+    }
+
+    #[test]
+    fn test_suggest_detection() {
+        let mut config = create_test_config();
+        config.router.suggest = Some("suggest.model".to_string());
+        let router = Router::new(config);
+
+        let suggestion = || {
+            let mut request = create_simple_request("what next?");
+            request.model = "claude-haiku-4-5".to_string();
+            request.max_tokens = 64;
+            request.system = Some(SystemPrompt::Text(
+                "You generate a prompt suggestion for what the user might type next.".to_string(),
+            ));
+            request
+        };
+
+        // Takes precedence over background (haiku) routing
+        let decision = router.route(&mut suggestion()).unwrap();
+        assert_eq!(decision.route_type, RouteType::Suggest);
+        assert_eq!(decision.model_name, "suggest.model");
+
+        // Too large to be a suggestion
+        let mut request = suggestion();
+        request.max_tokens = 4096;
+        assert_eq!(router.route(&mut request).unwrap().route_type, RouteType::Background);
+
+        // Regular system prompt
+        let mut request = suggestion();
+        request.system = Some(SystemPrompt::Text("You are a coding assistant.".to_string()));
+        assert_eq!(router.route(&mut request).unwrap().route_type, RouteType::Background);
+    }
+
+    #[test]
+    fn test_suggest_not_configured() {
+        let router = Router::new(create_test_config());
+        let mut request = create_simple_request("hi");
+        request.max_tokens = 32;
+        request.system = Some(SystemPrompt::Text("autocomplete the input".to_string()));
+        assert_ne!(router.route(&mut request).unwrap().route_type, RouteType::Suggest);
+    }
+
+    #[test]
+    fn test_client_overrides() {
+        use crate::cli::{ClientRoute, PromptRule};
+        let mut config = create_test_config();
+        config.router.clients = vec![ClientRoute {
+            name: "aider".to_string(),
+            user_agent: Some("(?i)aider".to_string()),
+            default: Some("aider.default".to_string()),
+            think: Some("aider.think".to_string()),
+            prompt_rules: vec![PromptRule {
+                pattern: "(?i)refactor".to_string(),
+                model: "aider.refactor".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }];
+        let router = Router::new(config);
+
+        // Claude Code keeps the global defaults
+        let claude = router.for_client(Some("claude-cli/2.0.0 (external, cli)"), Some("cli"));
+        let mut request = create_simple_request("hello");
+        request.model = "glm-4.6".to_string();
+        assert_eq!(claude.route(&mut request).unwrap().model_name, "glm-4.6");
+        let mut request = create_simple_request("hello");
+        assert_eq!(claude.route(&mut request).unwrap().model_name, "default.model");
+
+        // Aider gets its own default (via auto-map), think model and prompt rules
+        let aider = router.for_client(Some("Aider/0.80"), None);
+        let mut request = create_simple_request("hello");
+        assert_eq!(aider.route(&mut request).unwrap().model_name, "aider.default");
+
+        let mut request = create_simple_request("hello");
+        request.thinking = Some(ThinkingConfig { r#type: "enabled".to_string(), budget_tokens: None });
+        assert_eq!(aider.route(&mut request).unwrap().model_name, "aider.think");
+
+        let mut request = create_simple_request("please refactor this");
+        assert_eq!(aider.route(&mut request).unwrap().model_name, "aider.refactor");
+
+        // Background isn't overridden, so the global one applies
+        let mut request = create_simple_request("hello");
+        request.model = "claude-3-5-haiku".to_string();
+        assert_eq!(aider.route(&mut request).unwrap().model_name, "background.model");
+    }
+
+    #[test]
+    fn test_client_requires_all_matchers() {
+        use crate::cli::ClientRoute;
+        let mut config = create_test_config();
+        config.router.clients = vec![ClientRoute {
+            name: "script".to_string(),
+            user_agent: Some("python".to_string()),
+            x_app: Some("^batch$".to_string()),
+            default: Some("script.default".to_string()),
+            ..Default::default()
+        }];
+        let router = Router::new(config);
+
+        let mut request = create_simple_request("hello");
+        assert_eq!(
+            router.for_client(Some("python-httpx"), Some("batch")).route(&mut request).unwrap().model_name,
+            "script.default"
+        );
+        let mut request = create_simple_request("hello");
+        assert_eq!(
+            router.for_client(Some("python-httpx"), None).route(&mut request).unwrap().model_name,
+            "default.model"
+        );
+    }
+
+    #[test]
+    fn test_bypass_pins_requested_model() {
+        use crate::cli::ModelConfig;
+        let mut config = create_test_config();
+        config.models = vec![ModelConfig {
+            name: "glm-4.6".to_string(),
+            mappings: vec![],
+            residency: None,
+            strategy: Default::default(),
+            ordering: Default::default(),
+            max_cost_per_mtok: None,
+        }];
+        let router = Router::new(config);
+
+        // Would normally be routed to think.model
+        let mut request = create_simple_request("hello");
+        request.model = "GLM-4.6".to_string();
+        request.thinking = Some(ThinkingConfig { r#type: "enabled".to_string(), budget_tokens: None });
+        let decision = router.bypass(&request).unwrap();
+        assert_eq!(decision.model_name, "glm-4.6");
+        assert_eq!(decision.route_type, RouteType::Bypass);
+
+        // Claude model names are not auto-mapped and have no mapping
+        let request = create_simple_request("hello");
+        assert!(router.bypass(&request).is_err());
+    }
+
+    #[test]
+    fn test_validate_prompt_rule() {
+        use crate::cli::PromptRule;
+        let rule = |pattern: &str, model: &str| PromptRule {
+            pattern: pattern.to_string(),
+            model: model.to_string(),
+            ..Default::default()
+        };
+
+        assert!(validate_prompt_rule(&rule("(?i)commit", "fast-model")).is_ok());
+        assert!(validate_prompt_rule(&rule("(unclosed", "fast-model")).unwrap_err().contains("Invalid prompt_rule pattern"));
+        assert!(validate_prompt_rule(&rule("commit", " ")).is_err());
+        assert!(validate_prompt_rule(&rule("", "fast-model")).unwrap_err().contains("neither pattern nor metadata"));
+
+        let mut metadata_rule = rule("", "team-model");
+        metadata_rule.metadata.insert("user_id".to_string(), "[bad".to_string());
+        assert!(validate_prompt_rule(&metadata_rule).is_err());
+    }
+
+    #[test]
+    fn test_route_explained() {
+        let router = Router::new(create_test_config());
+
+        let mut request = create_simple_request("hello");
+        request.model = "claude-3-5-haiku".to_string();
+        let (decision, steps) = router.route_explained(&mut request).unwrap();
+        assert_eq!(decision.route_type, RouteType::Background);
+
+        let summary: Vec<(&str, bool)> = steps.iter().map(|s| (s.step, s.matched)).collect();
+        assert_eq!(
+            summary,
+            vec![("auto_map", true), ("websearch", false), ("suggest", false), ("long_context", false), ("background", true)]
+        );
+        assert!(steps[0].reason.contains("mapped to 'default.model'"));
+        assert_eq!(steps[1].reason, "no web_search tool");
+        assert_eq!(steps[3].reason, "router.long_context not configured");
+        assert!(steps[4].reason.contains("claude-3-5-haiku"));
+
+        // Same decision without explanation
+        let mut request = create_simple_request("hello");
+        request.model = "claude-3-5-haiku".to_string();
+        assert_eq!(router.route(&mut request).unwrap().model_name, decision.model_name);
+    }
 }
@@ -0,0 +1,117 @@
+//! `override.toml` in the state directory: temporary pins for `router.default`,
+//! `think` and `background`, an escape hatch during provider incidents. The file
+//! is checked on every request (one `stat`), so creating, editing or deleting it
+//! takes effect without a reload.
+
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use tracing::{info, warn};
+
+/// Name of the pinning file in the state directory
+pub const PIN_FILE: &str = "override.toml";
+
+/// Models pinned by `override.toml`; unset fields fall back to the config
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoutePin {
+    pub default: Option<String>,
+    pub think: Option<String>,
+    pub background: Option<String>,
+}
+
+impl RoutePin {
+    /// `default=glm-4.6, think=glm-4.6` for logs and X-CCM-Explain
+    pub fn summary(&self) -> String {
+        [("default", &self.default), ("think", &self.think), ("background", &self.background)]
+            .iter()
+            .filter_map(|(field, model)| model.as_ref().map(|model| format!("{}={}", field, model)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// File identity last seen: modification time and size
+type Stamp = Option<(SystemTime, u64)>;
+
+/// The pinning file, re-read only when it changes
+pub struct PinFile {
+    path: PathBuf,
+    cached: RwLock<(Stamp, Option<Arc<RoutePin>>)>,
+}
+
+impl PinFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, cached: RwLock::new((None, None)) }
+    }
+
+    /// No pins: an empty path never exists
+    #[allow(dead_code)] // Used by the embedding API (`embed`), not the binary
+    pub fn disabled() -> Self {
+        Self::new(PathBuf::new())
+    }
+
+    /// Pins currently in effect
+    pub fn current(&self) -> Option<Arc<RoutePin>> {
+        let stamp: Stamp = std::fs::metadata(&self.path)
+            .ok()
+            .and_then(|meta| Some((meta.modified().ok()?, meta.len())));
+        {
+            let cached = self.cached.read().unwrap();
+            if cached.0 == stamp {
+                return cached.1.clone();
+            }
+        }
+
+        let pin = stamp.and_then(|_| self.load()).map(Arc::new);
+        let mut cached = self.cached.write().unwrap();
+        match (&cached.1, &pin) {
+            (_, Some(pin)) => warn!("📌 Routing pinned by {}: {}", self.path.display(), pin.summary()),
+            (Some(_), None) => info!("📌 {} removed, routing unpinned", self.path.display()),
+            (None, None) => {}
+        }
+        *cached = (stamp, pin.clone());
+        pin
+    }
+
+    fn load(&self) -> Option<RoutePin> {
+        let parsed = std::fs::read_to_string(&self.path)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| toml::from_str::<RoutePin>(&content).map_err(anyhow::Error::from));
+        match parsed {
+            Ok(pin) if pin == RoutePin::default() => None,
+            Ok(pin) => Some(pin),
+            Err(e) => {
+                warn!("📌 Ignoring {}: {}", self.path.display(), e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pin_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(PIN_FILE);
+        let pins = PinFile::new(path.clone());
+        assert_eq!(pins.current(), None);
+
+        std::fs::write(&path, "default = \"glm-4.6\"\nthink = \"glm-4.6\"\n").unwrap();
+        let pin = pins.current().unwrap();
+        assert_eq!(pin.default.as_deref(), Some("glm-4.6"));
+        assert_eq!(pin.background, None);
+        assert_eq!(pin.summary(), "default=glm-4.6, think=glm-4.6");
+
+        // A typo must not silently pin nothing, nor half of what was meant
+        std::fs::write(&path, "defualt = \"glm-4.6\"\n").unwrap();
+        assert_eq!(pins.current(), None);
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(pins.current(), None);
+    }
+}
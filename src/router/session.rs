@@ -0,0 +1,136 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Sessions idle for longer than this are forgotten
+const SESSION_IDLE_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Upper bound on tracked sessions; the least recently seen are dropped first
+const MAX_SESSIONS: usize = 10_000;
+
+/// A prompt rule that fired in a session
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleFiring {
+    /// Hash of the turn-starting message that triggered the rule
+    pub turn: u64,
+    /// Model the rule resolved to
+    pub model: String,
+    /// Text that matched
+    pub matched: String,
+}
+
+#[derive(Debug)]
+struct SessionEntry {
+    last_seen: Instant,
+    /// rule key -> first firing
+    rules: HashMap<String, RuleFiring>,
+    /// provider/model targets whose prompt cache was primed
+    warmed: HashSet<String>,
+}
+
+/// Per-session routing memory for prompt rules with `lifetime = "once"` / `"sticky"`
+/// and prompt cache warm-up.
+///
+/// Sessions are keyed by `metadata.user_id`, which Claude Code sets per session.
+/// State is in-memory only and survives config reloads, not restarts.
+#[derive(Debug, Default)]
+pub struct SessionStore {
+    sessions: Mutex<HashMap<String, SessionEntry>>,
+}
+
+impl SessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// First firing of a rule in a session, if any
+    pub fn firing(&self, session: &str, rule: &str) -> Option<RuleFiring> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let entry = sessions.get_mut(session)?;
+        if entry.last_seen.elapsed() > SESSION_IDLE_TTL {
+            sessions.remove(session);
+            return None;
+        }
+        entry.last_seen = Instant::now();
+        entry.rules.get(rule).cloned()
+    }
+
+    /// Remember that a rule fired; later firings in the same session are ignored
+    pub fn record(&self, session: &str, rule: &str, firing: RuleFiring) {
+        let mut sessions = self.sessions.lock().unwrap();
+        Self::entry(&mut sessions, session).rules.entry(rule.to_string()).or_insert(firing);
+    }
+
+    /// Mark a provider/model as primed for a session. Returns false if it already was.
+    pub fn mark_warmed(&self, session: &str, target: &str) -> bool {
+        let mut sessions = self.sessions.lock().unwrap();
+        Self::entry(&mut sessions, session).warmed.insert(target.to_string())
+    }
+
+    fn entry<'a>(sessions: &'a mut HashMap<String, SessionEntry>, session: &str) -> &'a mut SessionEntry {
+        if !sessions.contains_key(session) && sessions.len() >= MAX_SESSIONS {
+            Self::evict(sessions);
+        }
+        let entry = sessions.entry(session.to_string()).or_insert_with(|| SessionEntry {
+            last_seen: Instant::now(),
+            rules: HashMap::new(),
+            warmed: HashSet::new(),
+        });
+        entry.last_seen = Instant::now();
+        entry
+    }
+
+    /// Number of tracked sessions
+    #[allow(dead_code)]
+    pub fn session_count(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    /// Drop expired sessions, then the oldest until there is room
+    fn evict(sessions: &mut HashMap<String, SessionEntry>) {
+        sessions.retain(|_, entry| entry.last_seen.elapsed() <= SESSION_IDLE_TTL);
+        while sessions.len() >= MAX_SESSIONS {
+            let Some(oldest) = sessions
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_seen)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            sessions.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn firing(turn: u64) -> RuleFiring {
+        RuleFiring { turn, model: "fast".to_string(), matched: "[fast]".to_string() }
+    }
+
+    #[test]
+    fn test_first_firing_wins() {
+        let store = SessionStore::new();
+        assert_eq!(store.firing("s1", "rule"), None);
+
+        store.record("s1", "rule", firing(1));
+        store.record("s1", "rule", firing(2));
+        assert_eq!(store.firing("s1", "rule").unwrap().turn, 1);
+
+        // Other sessions and rules are independent
+        assert_eq!(store.firing("s2", "rule"), None);
+        assert_eq!(store.firing("s1", "other"), None);
+        assert_eq!(store.session_count(), 1);
+    }
+
+    #[test]
+    fn test_warm_once_per_target() {
+        let store = SessionStore::new();
+        assert!(store.mark_warmed("s1", "anthropic/claude-sonnet"));
+        assert!(!store.mark_warmed("s1", "anthropic/claude-sonnet"));
+        assert!(store.mark_warmed("s1", "zai/glm-4.6"));
+        assert!(store.mark_warmed("s2", "anthropic/claude-sonnet"));
+    }
+}
@@ -0,0 +1,206 @@
+//! Admin UI and admin API access (`[server.admin]`): a password entered once on
+//! `/login` for a session cookie, or sent as `Authorization: Bearer` by scripts.
+//! Separate from the `[server.auth]` client keys, which only open `/v1/*`.
+
+use super::{AppError, AppState};
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, Method};
+use axum::middleware::Next;
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::Form;
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+pub const SESSION_COOKIE: &str = "ccm_admin";
+
+/// Slows down password guessing
+const FAILED_LOGIN_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+fn digest(secret: &str) -> Vec<u8> {
+    Sha256::digest(secret.as_bytes()).to_vec()
+}
+
+/// Compare digests so the time taken doesn't reveal how much of the password matched
+fn password_matches(password: &str, presented: &str) -> bool {
+    // An unset `$VAR` is reported by validation, never accepted
+    !password.is_empty() && !password.starts_with('$') && digest(password) == digest(presented)
+}
+
+struct Session {
+    expires_at: DateTime<Utc>,
+    /// Password the session was opened with; changing it logs everyone out
+    password_digest: Vec<u8>,
+}
+
+/// Logged-in admin sessions, in memory (a restart logs everyone out)
+#[derive(Default)]
+pub struct AdminSessions {
+    sessions: DashMap<String, Session>,
+}
+
+impl AdminSessions {
+    /// Open a session and return its id
+    pub fn create(&self, password: &str, hours: u64) -> String {
+        let now = Utc::now();
+        self.sessions.retain(|_, session| session.expires_at > now);
+        let id = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+        let session = Session { expires_at: now + Duration::hours(hours as i64), password_digest: digest(password) };
+        self.sessions.insert(id.clone(), session);
+        id
+    }
+
+    /// Whether `id` is an unexpired session opened with the current password
+    pub fn is_valid(&self, id: &str, password: &str) -> bool {
+        self.sessions
+            .get(id)
+            .is_some_and(|session| session.expires_at > Utc::now() && session.password_digest == digest(password))
+    }
+
+    pub fn remove(&self, id: &str) {
+        self.sessions.remove(id);
+    }
+}
+
+/// Value of the session cookie, if the browser sent one
+fn session_id(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value)
+}
+
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    value.strip_prefix("Bearer ").or_else(|| value.strip_prefix("bearer ")).map(str::trim)
+}
+
+/// Let logged-in admins and scripts with the password through (a no-op without `[server.admin]`).
+/// Browsers are sent to `/login`, API calls get 401.
+pub async fn require_admin(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let inner = state.snapshot();
+    let Some(admin) = &inner.config.server.admin else {
+        return next.run(request).await;
+    };
+    let headers = request.headers();
+    let authorized = bearer(headers).is_some_and(|presented| password_matches(&admin.password, presented))
+        || session_id(headers).is_some_and(|id| state.admin_sessions.is_valid(id, &admin.password));
+    if authorized {
+        return next.run(request).await;
+    }
+
+    if request.method() == Method::GET && request.uri().path() == "/" {
+        return Redirect::to("/login").into_response();
+    }
+    tracing::warn!("🔒 Rejected {} {}: admin login required", request.method(), request.uri().path());
+    AppError::Unauthorized(
+        "admin login required; log in at /login or send the [server.admin] password as Authorization: Bearer".to_string(),
+    )
+    .into_response()
+}
+
+fn login_form(failed: bool) -> Html<String> {
+    let error = if failed { r#"<p class="error">Wrong password</p>"# } else { "" };
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Claude Code Mux - Login</title>
+<style>
+body {{ font-family: system-ui, sans-serif; background: #0f0f10; color: #e5e5e5; display: flex; align-items: center; justify-content: center; height: 100vh; margin: 0; }}
+form {{ background: #1a1a1d; padding: 2rem; border-radius: 1rem; border: 1px solid #2e2e33; width: 18rem; }}
+input {{ width: 100%; box-sizing: border-box; padding: .6rem; margin: .8rem 0; border-radius: .5rem; border: 1px solid #3a3a40; background: #111; color: inherit; }}
+button {{ width: 100%; padding: .6rem; border: 0; border-radius: .5rem; background: #d97757; color: white; font-weight: 600; cursor: pointer; }}
+.error {{ color: #f87171; margin: 0; }}
+</style>
+</head>
+<body>
+<form method="post" action="/login">
+<h2>Claude Code Mux</h2>
+{error}
+<input type="password" name="password" placeholder="Admin password" autofocus required>
+<button type="submit">Log in</button>
+</form>
+</body>
+</html>"#
+    ))
+}
+
+pub async fn login_page(State(state): State<Arc<AppState>>) -> Response {
+    if state.snapshot().config.server.admin.is_none() {
+        return Redirect::to("/").into_response();
+    }
+    login_form(false).into_response()
+}
+
+#[derive(serde::Deserialize)]
+pub struct LoginForm {
+    password: String,
+}
+
+pub async fn login(State(state): State<Arc<AppState>>, Form(form): Form<LoginForm>) -> Response {
+    let inner = state.snapshot();
+    let Some(admin) = &inner.config.server.admin else {
+        return Redirect::to("/").into_response();
+    };
+    if !password_matches(&admin.password, &form.password) {
+        tracing::warn!("🔒 Failed admin login");
+        tokio::time::sleep(FAILED_LOGIN_DELAY).await;
+        return login_form(true).into_response();
+    }
+
+    let id = state.admin_sessions.create(&admin.password, admin.session_hours);
+    let cookie = format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Strict; Max-Age={}",
+        SESSION_COOKIE,
+        id,
+        admin.session_hours * 3600
+    );
+    ([(header::SET_COOKIE, cookie)], Redirect::to("/")).into_response()
+}
+
+pub async fn logout(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    if let Some(id) = session_id(&headers) {
+        state.admin_sessions.remove(id);
+    }
+    let cookie = format!("{}=; Path=/; HttpOnly; SameSite=Strict; Max-Age=0", SESSION_COOKIE);
+    ([(header::SET_COOKIE, cookie)], Redirect::to("/login")).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admin_sessions() {
+        let sessions = AdminSessions::default();
+        let id = sessions.create("hunter2-but-longer", 12);
+        assert!(sessions.is_valid(&id, "hunter2-but-longer"));
+        assert!(!sessions.is_valid("forged", "hunter2-but-longer"));
+        // A new password logs existing sessions out
+        assert!(!sessions.is_valid(&id, "rotated-password"));
+        let expired = sessions.create("hunter2-but-longer", 0);
+        assert!(!sessions.is_valid(&expired, "hunter2-but-longer"));
+        sessions.remove(&id);
+        assert!(!sessions.is_valid(&id, "hunter2-but-longer"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, "theme=dark; ccm_admin=abc123".parse().unwrap());
+        headers.insert(header::AUTHORIZATION, "Bearer hunter2-but-longer".parse().unwrap());
+        assert_eq!(session_id(&headers), Some("abc123"));
+        assert_eq!(bearer(&headers), Some("hunter2-but-longer"));
+        assert_eq!(session_id(&HeaderMap::new()), None);
+
+        assert!(password_matches("hunter2-but-longer", "hunter2-but-longer"));
+        assert!(!password_matches("hunter2-but-longer", "hunter2"));
+        assert!(!password_matches("$CCM_ADMIN_PASSWORD", "$CCM_ADMIN_PASSWORD"));
+        assert!(!password_matches("", ""));
+    }
+}
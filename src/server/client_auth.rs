@@ -0,0 +1,73 @@
+//! Client API keys for `/v1/*` (`[server.auth]`). Keys come from the live config,
+//! so adding or revoking one takes effect on reload.
+
+use super::{AppError, AppState};
+use axum::extract::{Request, State};
+use axum::http::HeaderMap;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+/// Key from `x-api-key` or `Authorization: Bearer` (what Anthropic and OpenAI clients send)
+pub(super) fn presented_key(headers: &HeaderMap) -> Option<&str> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    header("x-api-key")
+        .or_else(|| header("authorization").and_then(|v| v.strip_prefix("Bearer ").or_else(|| v.strip_prefix("bearer "))))
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+}
+
+/// Compare digests so the time taken doesn't reveal how much of a key matched
+fn key_matches(keys: &[String], presented: &str) -> bool {
+    let presented = Sha256::digest(presented.as_bytes());
+    keys.iter()
+        // Unset `$VAR` references are reported by validation, never accepted
+        .filter(|key| !key.is_empty() && !key.starts_with('$'))
+        .fold(false, |found, key| found | (Sha256::digest(key.as_bytes()) == presented))
+}
+
+/// Reject requests without a configured key with 401 (a no-op without `[server.auth]`)
+pub async fn require_api_key(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let inner = state.snapshot();
+    let Some(auth) = &inner.config.server.auth else {
+        return next.run(request).await;
+    };
+    match presented_key(request.headers()) {
+        Some(key) if key_matches(&auth.api_keys, key) => next.run(request).await,
+        presented => {
+            let reason = if presented.is_some() { "invalid API key" } else { "missing API key" };
+            tracing::warn!("🔒 Rejected {} {}: {}", request.method(), request.uri().path(), reason);
+            AppError::Unauthorized(format!(
+                "{}; send one of the [server.auth] api_keys as x-api-key or Authorization: Bearer",
+                reason
+            ))
+            .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_keys() {
+        let keys = vec!["ccm-laptop-key".to_string(), "$CCM_UNSET".to_string(), String::new()];
+        let headers = |name: &'static str, value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(name, value.parse().unwrap());
+            headers
+        };
+
+        assert_eq!(presented_key(&headers("x-api-key", "ccm-laptop-key")), Some("ccm-laptop-key"));
+        assert_eq!(presented_key(&headers("authorization", "Bearer ccm-laptop-key")), Some("ccm-laptop-key"));
+        assert_eq!(presented_key(&headers("authorization", "Basic abc")), None);
+        assert_eq!(presented_key(&HeaderMap::new()), None);
+
+        assert!(key_matches(&keys, "ccm-laptop-key"));
+        assert!(!key_matches(&keys, "ccm-laptop"));
+        assert!(!key_matches(&keys, "$CCM_UNSET"));
+        assert!(!key_matches(&keys, ""));
+    }
+}
@@ -0,0 +1,66 @@
+//! Reload the configuration when its file changes on disk (`server.watch_config`),
+//! with the same validation and atomic swap as `/api/reload`.
+
+use super::{config_source_hash, reload_state, AppState};
+use notify::{RecursiveMode, Watcher};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/// Quiet period after the last change before reloading (editors often save in several writes)
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+pub async fn run(state: Arc<AppState>) {
+    let path = match std::fs::canonicalize(&state.config_path) {
+        Ok(path) => path,
+        Err(e) => {
+            warn!("⚠️ Not watching {} for changes: {}", state.config_path.display(), e);
+            return;
+        }
+    };
+    let (Some(dir), Some(file_name)) = (path.parent(), path.file_name().map(|n| n.to_os_string())) else {
+        return;
+    };
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if !event.kind.is_access() && event.paths.iter().any(|p| p.file_name() == Some(file_name.as_os_str())) {
+            let _ = tx.send(());
+        }
+    });
+    // Watch the directory: editors that save by renaming a temp file over the
+    // config would otherwise drop a watch on the file itself
+    let _watcher = match watcher.and_then(|mut w| w.watch(dir, RecursiveMode::NonRecursive).map(|_| w)) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            warn!("⚠️ Not watching {} for changes: {}", path.display(), e);
+            return;
+        }
+    };
+    info!("👀 Reloading when {} changes", path.display());
+
+    // Contents last reloaded from, so an invalid file is reported once, not on every touch
+    let mut last_tried = state.snapshot().source_hash.clone();
+    while rx.recv().await.is_some() {
+        while let Ok(Some(())) = tokio::time::timeout(DEBOUNCE, rx.recv()).await {}
+
+        // Mid-rename, or a save that didn't change anything (or came from the admin UI, already loaded)
+        let Some(hash) = config_source_hash(&path) else {
+            continue;
+        };
+        if Some(&hash) == last_tried.as_ref() || Some(&hash) == state.snapshot().source_hash.as_ref() {
+            debug!("Config file touched without changes, not reloading");
+            continue;
+        }
+        last_tried = Some(hash);
+
+        info!("🔄 {} changed on disk, reloading", path.display());
+        if let Err(e) = reload_state(&state) {
+            warn!("⚠️ Keeping the previous configuration: {}", e);
+        }
+    }
+}
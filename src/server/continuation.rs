@@ -0,0 +1,117 @@
+//! Continuation prompt injection for models that stop after tool calls instead of
+//! carrying on with multi-step tasks.
+
+use crate::cli::{ContinuationConfig, ContinuationPlacement};
+use crate::models::{ContentBlock, Message, MessageContent};
+
+/// Reminder used when a mapping doesn't set its own text
+pub const DEFAULT_CONTINUATION_TEXT: &str = "<system-reminder>If you have an active todo list, remember to mark items complete and continue to the next. Do not mention this reminder.</system-reminder>";
+
+/// Message has tool results but no text content
+/// (indicates model should continue after tool execution)
+fn is_tool_result_turn(msg: &Message) -> bool {
+    let has_tool_results = match &msg.content {
+        MessageContent::Blocks(blocks) => blocks.iter().any(|b| b.is_tool_result()),
+        _ => false,
+    };
+
+    let has_text = match &msg.content {
+        MessageContent::Text(text) => !text.trim().is_empty(),
+        MessageContent::Blocks(blocks) => {
+            blocks.iter().any(|b| b.as_text().map(|t| !t.trim().is_empty()).unwrap_or(false))
+        }
+    };
+
+    msg.role == "user" && has_tool_results && !has_text
+}
+
+/// Number of trailing tool-result-only user turns (every other message, counting back)
+fn consecutive_tool_rounds(messages: &[Message]) -> usize {
+    messages.iter().rev().step_by(2).take_while(|m| is_tool_result_turn(m)).count()
+}
+
+/// Add the continuation prompt to the last message if it meets the trigger.
+/// Returns true if it was injected.
+pub fn inject(messages: &mut [Message], config: &ContinuationConfig) -> bool {
+    if consecutive_tool_rounds(messages) < config.min_tool_rounds.max(1) {
+        return false;
+    }
+    let Some(msg) = messages.last_mut() else {
+        return false;
+    };
+
+    let text = config.text.as_deref().unwrap_or(DEFAULT_CONTINUATION_TEXT);
+    let reminder = ContentBlock::text(text.to_string(), None);
+    match &mut msg.content {
+        MessageContent::Text(original) => {
+            let original = ContentBlock::text(std::mem::take(original), None);
+            msg.content = MessageContent::Blocks(match config.placement {
+                ContinuationPlacement::Prepend => vec![reminder, original],
+                ContinuationPlacement::Append => vec![original, reminder],
+            });
+        }
+        MessageContent::Blocks(blocks) => match config.placement {
+            ContinuationPlacement::Prepend => blocks.insert(0, reminder),
+            ContinuationPlacement::Append => blocks.push(reminder),
+        },
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn conversation(tool_rounds: usize) -> Vec<Message> {
+        let mut messages = vec![serde_json::from_value(json!({"role": "user", "content": "fix the tests"})).unwrap()];
+        for i in 0..tool_rounds {
+            messages.push(
+                serde_json::from_value(json!({"role": "assistant", "content": [
+                    {"type": "tool_use", "id": format!("t{}", i), "name": "Bash", "input": {}}
+                ]}))
+                .unwrap(),
+            );
+            messages.push(
+                serde_json::from_value(json!({"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": format!("t{}", i), "content": "ok"}
+                ]}))
+                .unwrap(),
+            );
+        }
+        messages
+    }
+
+    fn last_blocks(messages: &[Message]) -> serde_json::Value {
+        serde_json::to_value(&messages.last().unwrap().content).unwrap()
+    }
+
+    #[test]
+    fn test_default_prepends_after_one_round() {
+        let mut messages = conversation(0);
+        assert!(!inject(&mut messages, &ContinuationConfig::default()));
+
+        let mut messages = conversation(1);
+        assert!(inject(&mut messages, &ContinuationConfig::default()));
+        let blocks = last_blocks(&messages);
+        assert_eq!(blocks[0]["text"], DEFAULT_CONTINUATION_TEXT);
+        assert_eq!(blocks[1]["type"], "tool_result");
+    }
+
+    #[test]
+    fn test_min_rounds_text_and_append() {
+        let config = ContinuationConfig {
+            text: Some("Keep going.".to_string()),
+            min_tool_rounds: 3,
+            placement: ContinuationPlacement::Append,
+        };
+        let mut messages = conversation(2);
+        assert!(!inject(&mut messages, &config));
+
+        let mut messages = conversation(3);
+        assert!(inject(&mut messages, &config));
+        let blocks = last_blocks(&messages);
+        assert_eq!(blocks[0]["type"], "tool_result");
+        assert_eq!(blocks[1]["text"], "Keep going.");
+    }
+}
@@ -0,0 +1,257 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::providers::circuit_breaker::CircuitState;
+
+use super::{resolve_model_config, AppState};
+
+/// OAuth tokens expiring within this window are reported as warnings
+const TOKEN_EXPIRY_WARNING_MINUTES: i64 = 30;
+
+/// Number of routing decisions kept for /api/debug/stats
+const RECENT_ROUTES_WINDOW: usize = 20;
+
+/// Outcome of the last config load/reload
+#[derive(Debug, Clone, Serialize)]
+pub struct ReloadStatus {
+    pub loaded_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error_at: Option<DateTime<Utc>>,
+}
+
+impl ReloadStatus {
+    pub fn new() -> Self {
+        Self {
+            loaded_at: Utc::now(),
+            last_error: None,
+            last_error_at: None,
+        }
+    }
+}
+
+/// Counts requests currently waiting on an upstream provider
+#[derive(Default)]
+pub struct InFlight(AtomicUsize);
+
+impl InFlight {
+    /// Count a request until the returned guard is dropped
+    pub fn enter(&self) -> InFlightGuard<'_> {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard(&self.0)
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub struct InFlightGuard<'a>(&'a AtomicUsize);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A request that was served, as reported by /api/debug/stats
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentRoute {
+    pub timestamp: DateTime<Utc>,
+    pub model: String,
+    pub route_type: String,
+    pub provider: String,
+    pub actual_model: String,
+    pub latency_ms: u64,
+}
+
+/// Most recent routing decisions, newest first
+#[derive(Default)]
+pub struct RecentRoutes(Mutex<VecDeque<RecentRoute>>);
+
+impl RecentRoutes {
+    pub fn record(&self, model: &str, route_type: &crate::models::RouteType, provider: &str, actual_model: &str, latency_ms: u64) {
+        let mut routes = self.0.lock().unwrap();
+        routes.push_front(RecentRoute {
+            timestamp: Utc::now(),
+            model: model.to_string(),
+            route_type: route_type.to_string(),
+            provider: provider.to_string(),
+            actual_model: actual_model.to_string(),
+            latency_ms,
+        });
+        routes.truncate(RECENT_ROUTES_WINDOW);
+    }
+
+    pub fn list(&self) -> Vec<RecentRoute> {
+        self.0.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HealthQuery {
+    verbose: Option<String>,
+}
+
+/// Health check endpoint.
+///
+/// Returns 503 when no provider for router.default is usable (all circuits open,
+/// or nothing to route to), so it can serve as a Docker/k8s readiness probe.
+/// `?verbose=1` adds provider health, OAuth expiry warnings, reload status and queue depth.
+pub async fn health_check(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HealthQuery>,
+) -> Response {
+    let inner = state.snapshot();
+    let default_model = &inner.config.router.default;
+
+    // Which providers could serve router.default right now
+    let (default_providers, healthy_providers): (Vec<String>, Vec<String>) =
+        match resolve_model_config(&inner, default_model, None) {
+            Some(model_config) => {
+                let providers: Vec<String> = model_config.mappings.iter().map(|m| m.provider.clone()).collect();
+                let healthy = model_config
+                    .mappings
+                    .iter()
+                    .filter(|m| inner.provider_registry.get_provider(&m.provider).is_some())
+                    .filter(|m| {
+                        let actual_model = m.resolve_actual_model(default_model);
+                        state.circuit_breaker.state(&m.provider, &actual_model) != CircuitState::Open
+                    })
+                    .filter(|m| inner.config.server.health_checks.is_none() || !state.health_checks.is_down(&m.provider))
+                    .map(|m| m.provider.clone())
+                    .collect();
+                (providers, healthy)
+            }
+            None if inner.provider_registry.get_provider_for_model(default_model).is_ok() => {
+                (vec!["direct".to_string()], vec!["direct".to_string()])
+            }
+            None => (Vec::new(), Vec::new()),
+        };
+
+    let healthy = !healthy_providers.is_empty();
+    let status = if healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+
+    let mut body = serde_json::json!({
+        "status": if healthy { "ok" } else { "unhealthy" },
+        "service": "claude-code-mux",
+        "pid": std::process::id(),
+    });
+
+    // Nothing at all to route to is a config mistake, not an outage: say so
+    if default_providers.is_empty() {
+        let problem = crate::cli::validate::default_route_problem(&inner.config)
+            .unwrap_or_else(|| format!("no provider serves \"{}\"", default_model));
+        body["misconfigured"] = serde_json::json!(format!("router.default: {}", problem));
+    }
+
+    // Persistent, so clients polling /health notice retired models without reading logs
+    let model_warnings = state.model_warnings.list();
+    if !model_warnings.is_empty() {
+        body["model_warnings"] = serde_json::json!(model_warnings);
+    }
+
+    let verbose = matches!(query.verbose.as_deref(), Some("1" | "true" | "yes"));
+    if verbose {
+        let now = Utc::now();
+        let warn_before = now + chrono::Duration::minutes(TOKEN_EXPIRY_WARNING_MINUTES);
+        let mut tokens: Vec<serde_json::Value> = state
+            .token_store
+            .all()
+            .into_values()
+            .map(|token| {
+                let warning = if token.expires_at <= now {
+                    Some("expired (will refresh on next use)")
+                } else if token.expires_at <= warn_before {
+                    Some("expires soon")
+                } else {
+                    None
+                };
+                serde_json::json!({
+                    "provider_id": token.provider_id,
+                    "expires_at": token.expires_at,
+                    "warning": warning,
+                })
+            })
+            .collect();
+        tokens.sort_by(|a, b| a["provider_id"].as_str().cmp(&b["provider_id"].as_str()));
+
+        let extra = serde_json::json!({
+            "default_route": {
+                "model": default_model,
+                "providers": default_providers,
+                "healthy_providers": healthy_providers,
+            },
+            "circuits": state.circuit_breaker.statuses(),
+            "provider_stats": state.health.all_stats(),
+            "quotas": state.quota.statuses(),
+            "oauth_tokens": tokens,
+            "config": &*state.reload_status.read().unwrap(),
+            "queue": {
+                "in_flight": state.in_flight.count(),
+            },
+        });
+        if let (Some(obj), Some(extra)) = (body.as_object_mut(), extra.as_object()) {
+            obj.extend(extra.clone());
+        }
+    }
+
+    (status, Json(body)).into_response()
+}
+
+/// Runtime statistics for `ccm status --verbose`
+pub async fn debug_stats(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(serde_json::json!({
+        "pid": std::process::id(),
+        "started_at": state.started_at,
+        "uptime_secs": (Utc::now() - state.started_at).num_seconds(),
+        "in_flight": state.in_flight.count(),
+        "provider_stats": state.health.all_stats(),
+        "circuits": state.circuit_breaker.statuses(),
+        "quotas": state.quota.statuses(),
+        "costs": state.costs.all_totals(),
+        "total_cost_usd": state.costs.total_cost(),
+        "budgets": state.budgets.statuses(&state.snapshot().config.budgets),
+        "slos": state.slo.statuses(&state.snapshot().config.slos),
+        "recent_routes": state.recent_routes.list(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_flight_guard() {
+        let in_flight = InFlight::default();
+        {
+            let _a = in_flight.enter();
+            let _b = in_flight.enter();
+            assert_eq!(in_flight.count(), 2);
+        }
+        assert_eq!(in_flight.count(), 0);
+    }
+
+    #[test]
+    fn test_recent_routes_newest_first_and_bounded() {
+        let routes = RecentRoutes::default();
+        for i in 0..RECENT_ROUTES_WINDOW + 5 {
+            routes.record(&format!("m{}", i), &crate::models::RouteType::Default, "p", "actual", i as u64);
+        }
+
+        let list = routes.list();
+        assert_eq!(list.len(), RECENT_ROUTES_WINDOW);
+        assert_eq!(list[0].model, format!("m{}", RECENT_ROUTES_WINDOW + 4));
+        assert_eq!(list[0].route_type, "default");
+    }
+}
@@ -0,0 +1,213 @@
+use crate::models::AnthropicRequest;
+use crate::providers::error::ProviderError;
+use crate::providers::{AnthropicProvider, StreamResponse};
+use bytes::Bytes;
+use futures::stream::{self, StreamExt};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// SSE event that carries the first generated token
+const FIRST_TOKEN_EVENT: &[u8] = b"content_block_delta";
+
+/// One side of a hedged request
+pub struct Attempt<'a> {
+    pub label: String,
+    pub provider: &'a Arc<Box<dyn AnthropicProvider>>,
+    pub request: AnthropicRequest,
+}
+
+/// Outcome of racing the primary mapping against the hedge
+pub struct Raced {
+    /// 0 = primary, 1 = hedge
+    pub index: usize,
+    pub result: Result<StreamResponse, ProviderError>,
+    pub elapsed: Duration,
+    /// The other attempt failed before the race was decided
+    pub other_failure: Option<(usize, ProviderError, Duration)>,
+    /// The hedge was started
+    pub hedged: bool,
+}
+
+/// Open a stream and wait for its first token. Events before it are buffered and
+/// replayed, so the returned stream is unchanged from the client's point of view.
+pub async fn first_token(
+    provider: &Arc<Box<dyn AnthropicProvider>>,
+    request: AnthropicRequest,
+) -> Result<StreamResponse, ProviderError> {
+    let mut response = provider.send_message_stream(request).await?;
+    let mut buffered: Vec<Result<Bytes, ProviderError>> = Vec::new();
+    let mut tail: Vec<u8> = Vec::new();
+
+    while let Some(chunk) = response.stream.next().await {
+        let chunk = chunk?;
+        // Keep the end of the previous chunk so an event name split across chunks is still found
+        tail.extend_from_slice(&chunk);
+        let found = tail.windows(FIRST_TOKEN_EVENT.len()).any(|w| w == FIRST_TOKEN_EVENT);
+        let stale = tail.len().saturating_sub(FIRST_TOKEN_EVENT.len());
+        tail.drain(..stale);
+        buffered.push(Ok(chunk));
+        if found {
+            break;
+        }
+    }
+
+    response.stream = Box::pin(stream::iter(buffered).chain(response.stream));
+    Ok(response)
+}
+
+/// Start the primary; if it hasn't produced a token after `delay`, build the hedge and start it
+/// as well, then serve whichever streams first. A hedge that can't be built (`None`) leaves the
+/// primary to finish alone. The loser is dropped, which cancels its upstream request.
+pub async fn race<'a>(
+    primary: Attempt<'a>,
+    hedge: impl Future<Output = Option<Attempt<'a>>>,
+    delay: Duration,
+) -> Raced {
+    let start = Instant::now();
+    let primary_label = primary.label;
+    let first = first_token(primary.provider, primary.request);
+    tokio::pin!(first);
+
+    tokio::select! {
+        result = &mut first => {
+            return Raced { index: 0, result, elapsed: start.elapsed(), other_failure: None, hedged: false };
+        }
+        _ = tokio::time::sleep(delay) => {}
+    }
+
+    // The primary keeps going while the hedge request is built
+    let hedge = tokio::select! {
+        result = &mut first => {
+            return Raced { index: 0, result, elapsed: start.elapsed(), other_failure: None, hedged: false };
+        }
+        hedge = hedge => hedge,
+    };
+    let Some(hedge) = hedge else {
+        let result = first.await;
+        return Raced { index: 0, result, elapsed: start.elapsed(), other_failure: None, hedged: false };
+    };
+
+    info!(
+        "🏁 No token from {} after {}ms, hedging with {}",
+        primary_label,
+        delay.as_millis(),
+        hedge.label
+    );
+    let hedge_start = Instant::now();
+    let second = first_token(hedge.provider, hedge.request);
+    tokio::pin!(second);
+
+    let (index, result, elapsed) = tokio::select! {
+        result = &mut first => (0, result, start.elapsed()),
+        result = &mut second => (1, result, hedge_start.elapsed()),
+    };
+
+    if let Err(e) = result {
+        // The other attempt is still the best remaining option
+        let other_failure = Some((index, e, elapsed));
+        let (index, result, elapsed) = if index == 0 {
+            (1, second.await, hedge_start.elapsed())
+        } else {
+            (0, first.await, start.elapsed())
+        };
+        return Raced { index, result, elapsed, other_failure, hedged: true };
+    }
+
+    let (winner, loser) = if index == 0 { (&primary_label, &hedge.label) } else { (&hedge.label, &primary_label) };
+    info!("🏁 {} streamed first, cancelling {}", winner, loser);
+    Raced { index, result, elapsed, other_failure: None, hedged: true }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CountTokensRequest, CountTokensResponse};
+    use crate::providers::ProviderResponse;
+    use async_trait::async_trait;
+
+    /// Streams a message_start immediately and a text delta after `token_delay`
+    struct SlowProvider {
+        token_delay: Duration,
+    }
+
+    #[async_trait]
+    impl AnthropicProvider for SlowProvider {
+        async fn send_message(&self, _request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+            unimplemented!()
+        }
+
+        async fn send_message_stream(&self, _request: AnthropicRequest) -> Result<StreamResponse, ProviderError> {
+            let delay = self.token_delay;
+            let events = stream::iter(vec![Bytes::from_static(b"event: message_start\ndata: {}\n\n")])
+                .chain(stream::once(async move {
+                    tokio::time::sleep(delay).await;
+                    Bytes::from_static(b"event: content_block_delta\ndata: {}\n\n")
+                }))
+                .map(Ok);
+            Ok(StreamResponse {
+                stream: Box::pin(events),
+                headers: Default::default(),
+                rate_limits: Default::default(),
+            })
+        }
+
+        async fn count_tokens(&self, _request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+            unimplemented!()
+        }
+
+        fn supports_model(&self, _model: &str) -> bool {
+            true
+        }
+    }
+
+    fn request() -> AnthropicRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "m",
+            "max_tokens": 16,
+            "stream": true,
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap()
+    }
+
+    fn provider(token_delay_ms: u64) -> Arc<Box<dyn AnthropicProvider>> {
+        Arc::new(Box::new(SlowProvider { token_delay: Duration::from_millis(token_delay_ms) }))
+    }
+
+    fn attempt<'a>(label: &str, provider: &'a Arc<Box<dyn AnthropicProvider>>) -> Attempt<'a> {
+        Attempt { label: label.to_string(), provider, request: request() }
+    }
+
+    #[tokio::test]
+    async fn test_fast_primary_is_not_hedged() {
+        let (fast, slow) = (provider(5), provider(5));
+        let raced = race(attempt("a", &fast), async { Some(attempt("b", &slow)) }, Duration::from_millis(500)).await;
+        assert_eq!(raced.index, 0);
+        assert!(!raced.hedged);
+
+        // Buffered events are replayed
+        let chunks: Vec<_> = raced.result.unwrap().stream.collect().await;
+        assert_eq!(chunks.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_slow_primary_loses_to_hedge() {
+        let (slow, fast) = (provider(2000), provider(5));
+        let raced = race(attempt("a", &slow), async { Some(attempt("b", &fast)) }, Duration::from_millis(20)).await;
+        assert_eq!(raced.index, 1);
+        assert!(raced.hedged);
+        assert!(raced.other_failure.is_none());
+        assert!(raced.result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_unbuildable_hedge_leaves_primary() {
+        let slow = provider(100);
+        let raced = race(attempt("a", &slow), async { None }, Duration::from_millis(20)).await;
+        assert_eq!(raced.index, 0);
+        assert!(!raced.hedged);
+        assert!(raced.result.is_ok());
+    }
+}
@@ -0,0 +1,79 @@
+//! Identity note telling the serving model who it really is, so fallback models
+//! behind a Claude Code system prompt don't introduce themselves as Claude.
+
+use crate::cli::ModelMapping;
+use crate::models::{SystemBlock, SystemPrompt};
+
+/// Note naming the provider/model, plus the mapping's `identity_details`
+fn note(mapping: &ModelMapping) -> String {
+    let mut note = format!(
+        "Note: this conversation is served by the model \"{}\" from the provider \"{}\" through a proxy, \
+         even if the prompt above describes you as Claude. If asked which model you are, say that.",
+        mapping.actual_model, mapping.provider
+    );
+    if let Some(details) = mapping.identity_details.as_deref().map(str::trim).filter(|d| !d.is_empty()) {
+        note.push(' ');
+        note.push_str(details);
+    }
+    note
+}
+
+/// Append the identity note to the end of the system prompt, after any cache
+/// breakpoints so cached prefixes stay valid. Returns true if it was added.
+pub fn inject(system: &mut Option<SystemPrompt>, mapping: &ModelMapping) -> bool {
+    if !mapping.inject_identity {
+        return false;
+    }
+
+    let note = note(mapping);
+    match system {
+        Some(SystemPrompt::Text(text)) => {
+            text.push_str("\n\n");
+            text.push_str(&note);
+        }
+        Some(SystemPrompt::Blocks(blocks)) => blocks.push(SystemBlock {
+            r#type: "text".to_string(),
+            text: note,
+            cache_control: None,
+        }),
+        None => *system = Some(SystemPrompt::Text(note)),
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_appends_note_after_cached_blocks() {
+        let mut mapping = ModelMapping {
+            priority: 1,
+            provider: "openrouter".to_string(),
+            actual_model: "z-ai/glm-4.6".to_string(),
+            ..Default::default()
+        };
+        let mut system: Option<SystemPrompt> = Some(
+            serde_json::from_value(json!([
+                {"type": "text", "text": "You are Claude Code", "cache_control": {"type": "ephemeral"}}
+            ]))
+            .unwrap(),
+        );
+        assert!(!inject(&mut system, &mapping));
+
+        mapping.inject_identity = true;
+        mapping.identity_details = Some("Knowledge cutoff: 2025-04. No image input.".to_string());
+        assert!(inject(&mut system, &mapping));
+        let json = serde_json::to_value(&system).unwrap();
+        assert!(json[0]["cache_control"].is_object());
+        let text = json[1]["text"].as_str().unwrap();
+        assert!(text.contains("\"z-ai/glm-4.6\" from the provider \"openrouter\""));
+        assert!(text.ends_with("Knowledge cutoff: 2025-04. No image input."));
+        assert!(json[1].get("cache_control").is_none());
+
+        let mut system = None;
+        assert!(inject(&mut system, &mapping));
+        assert!(matches!(system, Some(SystemPrompt::Text(text)) if text.starts_with("Note:")));
+    }
+}
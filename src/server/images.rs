@@ -0,0 +1,228 @@
+//! Fetch image URLs for providers that only accept inline (base64) image data,
+//! so `image_url` parts and `url` image sources work with every backend.
+
+use super::AppError;
+use crate::models::{
+    AnthropicRequest, ContentBlock, ImageSource, KnownContentBlock, KnownToolResultBlock, MessageContent,
+    ToolResultBlock, ToolResultContent,
+};
+use crate::providers::AnthropicProvider;
+use base64::{engine::general_purpose, Engine as _};
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::debug;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Larger than any provider accepts for a single image
+const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+/// Replace http(s) image URLs in `request` with their base64 data when `provider`
+/// can't fetch them itself. A URL that can't be fetched fails the request.
+pub async fn inline_for(provider: &dyn AnthropicProvider, request: &mut AnthropicRequest) -> Result<(), AppError> {
+    InlinedImages::new().inline_for(provider, request).await
+}
+
+/// Images fetched for one request, so trying it on several mappings downloads each URL once
+#[derive(Default)]
+pub struct InlinedImages {
+    client: Option<reqwest::Client>,
+    /// Media type and base64 data by URL, or why the URL couldn't be fetched
+    fetched: HashMap<String, Result<(String, String), String>>,
+}
+
+impl InlinedImages {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// [`inline_for`], reusing images this request already fetched
+    pub async fn inline_for(&mut self, provider: &dyn AnthropicProvider, request: &mut AnthropicRequest) -> Result<(), AppError> {
+        if provider.accepts_image_urls() {
+            return Ok(());
+        }
+        for source in remote_sources(request) {
+            let url = source.url.take().unwrap_or_default();
+            if !self.fetched.contains_key(&url) {
+                let client = self.client()?;
+                let fetched = fetch(&client, &url).await.map(|(media_type, data)| {
+                    debug!("🖼️ Inlined {} ({}, {} bytes)", url, media_type, data.len());
+                    (media_type, general_purpose::STANDARD.encode(&data))
+                });
+                self.fetched.insert(url.clone(), fetched);
+            }
+            let (media_type, data) = self.fetched[&url]
+                .clone()
+                .map_err(|e| AppError::InvalidRequest(format!("Failed to fetch image {}: {}", url, e)))?;
+            source.r#type = "base64".to_string();
+            source.media_type = Some(media_type);
+            source.data = Some(data);
+        }
+        Ok(())
+    }
+
+    fn client(&mut self) -> Result<reqwest::Client, AppError> {
+        if let Some(client) = &self.client {
+            return Ok(client.clone());
+        }
+        let client = reqwest::Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            .build()
+            .map_err(|e| AppError::ProviderError(format!("Failed to build HTTP client: {}", e)))?;
+        self.client = Some(client.clone());
+        Ok(client)
+    }
+}
+
+/// Image sources with an http(s) URL, in messages and tool results
+fn remote_sources(request: &mut AnthropicRequest) -> impl Iterator<Item = &mut ImageSource> {
+    request
+        .messages
+        .iter_mut()
+        .filter_map(|message| match &mut message.content {
+            MessageContent::Blocks(blocks) => Some(blocks),
+            MessageContent::Text(_) => None,
+        })
+        .flatten()
+        .flat_map(|block| {
+            let sources: Vec<&mut ImageSource> = match block {
+                ContentBlock::Known(KnownContentBlock::Image { source }) => vec![source],
+                ContentBlock::Known(KnownContentBlock::ToolResult { content: ToolResultContent::Blocks(blocks), .. }) => {
+                    blocks
+                        .iter_mut()
+                        .filter_map(|block| match block {
+                            ToolResultBlock::Known(KnownToolResultBlock::Image { source }) => Some(source),
+                            _ => None,
+                        })
+                        .collect()
+                }
+                _ => Vec::new(),
+            };
+            sources
+        })
+        .filter(|source| {
+            source.r#type == "url"
+                && source.url.as_deref().is_some_and(|url| url.starts_with("http://") || url.starts_with("https://"))
+        })
+}
+
+/// Download an image, returning its media type and bytes
+async fn fetch(client: &reqwest::Client, url: &str) -> Result<(String, Vec<u8>), String> {
+    let response = client.get(url).send().await.map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_ascii_lowercase());
+
+    let mut data = Vec::new();
+    let mut body = response.bytes_stream();
+    while let Some(chunk) = body.next().await {
+        data.extend_from_slice(&chunk.map_err(|e| e.to_string())?);
+        if data.len() > MAX_IMAGE_BYTES {
+            return Err(format!("larger than {} MB", MAX_IMAGE_BYTES / (1024 * 1024)));
+        }
+    }
+
+    let media_type = content_type
+        .filter(|t| t.starts_with("image/"))
+        .or_else(|| sniff(&data).map(String::from))
+        .ok_or("not an image")?;
+    Ok((media_type, data))
+}
+
+/// Media type from the file signature, for servers that don't send an image content type
+fn sniff(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CountTokensRequest, CountTokensResponse};
+    use crate::providers::{error::ProviderError, ProviderResponse, StreamResponse};
+    use async_trait::async_trait;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    struct InlineOnly;
+
+    #[async_trait]
+    impl AnthropicProvider for InlineOnly {
+        async fn send_message(&self, _request: AnthropicRequest) -> Result<ProviderResponse, ProviderError> {
+            unimplemented!()
+        }
+
+        async fn send_message_stream(&self, _request: AnthropicRequest) -> Result<StreamResponse, ProviderError> {
+            unimplemented!()
+        }
+
+        async fn count_tokens(&self, _request: CountTokensRequest) -> Result<CountTokensResponse, ProviderError> {
+            unimplemented!()
+        }
+
+        fn supports_model(&self, _model: &str) -> bool {
+            true
+        }
+
+        fn accepts_image_urls(&self) -> bool {
+            false
+        }
+    }
+
+    #[tokio::test]
+    async fn test_inline_image_urls() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/cat.png", listener.local_addr().unwrap());
+        let png = b"\x89PNG\r\n\x1a\nrest";
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 1024];
+            let _ = socket.read(&mut request).await.unwrap();
+            // No content type: the media type comes from the file signature
+            let head = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n", png.len());
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(png).await.unwrap();
+        });
+
+        let mut request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "m",
+            "max_tokens": 10,
+            "messages": [{"role": "user", "content": [
+                {"type": "text", "text": "What is this?"},
+                {"type": "image", "source": {"type": "url", "url": url}},
+                {"type": "image", "source": {"type": "url", "url": "gs://bucket/dog.png"}}
+            ]}]
+        }))
+        .unwrap();
+        let mut fallback_request = request.clone();
+        let mut images = InlinedImages::new();
+        images.inline_for(&InlineOnly, &mut request).await.unwrap();
+
+        let blocks = serde_json::to_value(&request.messages[0].content).unwrap();
+        assert_eq!(
+            blocks[1]["source"],
+            serde_json::json!({"type": "base64", "media_type": "image/png", "data": general_purpose::STANDARD.encode(png)})
+        );
+        // Only http(s) URLs are fetched
+        assert_eq!(blocks[2]["source"], serde_json::json!({"type": "url", "url": "gs://bucket/dog.png"}));
+
+        // The server answered once: a second mapping reuses the fetched image
+        images.inline_for(&InlineOnly, &mut fallback_request).await.unwrap();
+        assert_eq!(serde_json::to_value(&fallback_request.messages[0].content).unwrap(), blocks);
+    }
+}
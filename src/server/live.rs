@@ -0,0 +1,209 @@
+//! Live progress of streaming responses (output tokens/s and elapsed time), served
+//! from `GET /api/live` so the statusline can show generation speed mid-response.
+
+use crate::models::RouteType;
+use crate::providers::streaming::parse_sse_events;
+use bytes::Bytes;
+use futures::Stream;
+use pin_project::{pin_project, pinned_drop};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+/// Rough characters per output token, for streams that only report usage at the end
+const CHARS_PER_TOKEN: usize = 4;
+
+struct Progress {
+    model: String,
+    provider: String,
+    route_type: RouteType,
+    started: Instant,
+    /// When the first content delta arrived (speed is measured from here)
+    first_token: Option<Instant>,
+    output_chars: usize,
+    /// Output tokens reported by the upstream, once known
+    reported_tokens: Option<u32>,
+}
+
+impl Progress {
+    fn status(&self, active: bool) -> LiveStatus {
+        let output_tokens = self.reported_tokens.unwrap_or((self.output_chars / CHARS_PER_TOKEN) as u32);
+        let generating = self.first_token.map(|t| t.elapsed().as_secs_f64()).unwrap_or(0.0);
+        LiveStatus {
+            model: self.model.clone(),
+            provider: self.provider.clone(),
+            route_type: self.route_type.to_string(),
+            active,
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            output_tokens,
+            estimated: self.reported_tokens.is_none(),
+            tokens_per_sec: if generating > 0.0 { (output_tokens as f64 / generating * 10.0).round() / 10.0 } else { 0.0 },
+        }
+    }
+}
+
+/// One stream's progress, as returned by `/api/live`
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveStatus {
+    pub model: String,
+    pub provider: String,
+    pub route_type: String,
+    /// Still streaming (false for the last finished stream)
+    pub active: bool,
+    pub elapsed_ms: u64,
+    pub output_tokens: u32,
+    /// Counted from the streamed text (~4 characters per token) rather than reported usage
+    pub estimated: bool,
+    /// Output tokens per second since the first token
+    pub tokens_per_sec: f64,
+}
+
+/// Streams in progress, plus the one that finished last
+#[derive(Default)]
+pub struct LiveStreams {
+    next_id: AtomicU64,
+    streams: Mutex<HashMap<u64, Progress>>,
+    last: Mutex<Option<LiveStatus>>,
+}
+
+impl LiveStreams {
+    /// Track a response stream until it ends or the client disconnects
+    pub fn watch<S>(self: &Arc<Self>, stream: S, model: &str, provider: &str, route_type: RouteType) -> LiveStream<S> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.streams.lock().unwrap().insert(
+            id,
+            Progress {
+                model: model.to_string(),
+                provider: provider.to_string(),
+                route_type,
+                started: Instant::now(),
+                first_token: None,
+                output_chars: 0,
+                reported_tokens: None,
+            },
+        );
+        LiveStream { inner: stream, streams: self.clone(), id, buffer: String::new() }
+    }
+
+    /// Active streams, oldest first
+    pub fn active(&self) -> Vec<LiveStatus> {
+        let streams = self.streams.lock().unwrap();
+        let mut active: Vec<(&u64, &Progress)> = streams.iter().collect();
+        active.sort_by_key(|(id, _)| **id);
+        active.into_iter().map(|(_, progress)| progress.status(true)).collect()
+    }
+
+    pub fn last(&self) -> Option<LiveStatus> {
+        self.last.lock().unwrap().clone()
+    }
+
+    fn update(&self, id: u64, events: &str) {
+        let mut streams = self.streams.lock().unwrap();
+        let Some(progress) = streams.get_mut(&id) else {
+            return;
+        };
+        for event in parse_sse_events(events) {
+            let Ok(data) = serde_json::from_str::<Value>(&event.data) else {
+                continue;
+            };
+            match data["type"].as_str() {
+                Some("content_block_delta") => {
+                    let delta = &data["delta"];
+                    let chars = ["text", "thinking", "partial_json"]
+                        .iter()
+                        .filter_map(|field| delta[*field].as_str())
+                        .map(str::len)
+                        .sum::<usize>();
+                    if chars > 0 {
+                        progress.first_token.get_or_insert_with(Instant::now);
+                        progress.output_chars += chars;
+                    }
+                }
+                Some("message_delta") => {
+                    if let Some(tokens) = data["usage"]["output_tokens"].as_u64().filter(|t| *t > 0) {
+                        progress.reported_tokens = Some(tokens as u32);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn finish(&self, id: u64) {
+        if let Some(progress) = self.streams.lock().unwrap().remove(&id) {
+            *self.last.lock().unwrap() = Some(progress.status(false));
+        }
+    }
+}
+
+/// Stream adapter feeding `LiveStreams`; bytes pass through unchanged
+#[pin_project(PinnedDrop)]
+pub struct LiveStream<S> {
+    #[pin]
+    inner: S,
+    streams: Arc<LiveStreams>,
+    id: u64,
+    buffer: String,
+}
+
+impl<S, E> Stream for LiveStream<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let poll = this.inner.poll_next(cx);
+        if let Poll::Ready(Some(Ok(bytes))) = &poll {
+            if let Ok(text) = std::str::from_utf8(bytes) {
+                this.buffer.push_str(text);
+            }
+            if let Some(end) = this.buffer.rfind("\n\n") {
+                let complete: String = this.buffer.drain(..end + 2).collect();
+                this.streams.update(*this.id, &complete);
+            }
+        }
+        poll
+    }
+}
+
+#[pinned_drop]
+impl<S> PinnedDrop for LiveStream<S> {
+    fn drop(self: Pin<&mut Self>) {
+        self.streams.finish(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_live_progress() {
+        let streams = Arc::new(LiveStreams::default());
+        let chunks = vec![
+            Ok::<_, std::io::Error>(Bytes::from("event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hello there, ")),
+            Ok(Bytes::from("world\"}}\n\n")),
+        ];
+        let mut stream = streams.watch(futures::stream::iter(chunks), "glm-4.6", "zai", RouteType::Default);
+
+        stream.next().await;
+        assert_eq!(streams.active()[0].output_tokens, 0);
+        stream.next().await;
+        let status = &streams.active()[0];
+        assert_eq!((status.output_tokens, status.estimated, status.active), (4, true, true));
+
+        streams.update(0, "event: message_delta\ndata: {\"type\":\"message_delta\",\"usage\":{\"output_tokens\":3}}\n\n");
+        drop(stream);
+        assert!(streams.active().is_empty());
+        let last = streams.last().unwrap();
+        assert_eq!((last.model.as_str(), last.output_tokens, last.estimated, last.active), ("glm-4.6", 3, false, false));
+    }
+}
@@ -0,0 +1,43 @@
+//! `GET /metrics`: SLO compliance in the Prometheus text format, one series per
+//! `[[slos]]` entry labelled by route and percentile.
+
+use axum::{extract::State, http::header, response::IntoResponse};
+use std::fmt::Write;
+use std::sync::Arc;
+
+use crate::providers::slo::SloStatus;
+
+use super::AppState;
+
+pub async fn metrics(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let statuses = state.slo.statuses(&state.snapshot().config.slos);
+    let mut out = String::new();
+    let mut series = |name: &str, help: &str, kind: &str, value: &dyn Fn(&SloStatus) -> Option<f64>| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for status in &statuses {
+            if let Some(value) = value(status) {
+                let _ = writeln!(
+                    out,
+                    "{}{{route=\"{}\",percentile=\"{}\"}} {}",
+                    name, status.route, status.percentile, value
+                );
+            }
+        }
+    };
+
+    series("ccm_slo_target_seconds", "Latency limit of the SLO", "gauge", &|s| Some(s.max_latency_ms as f64 / 1000.0));
+    series("ccm_slo_latency_seconds", "Turn latency at the SLO's percentile over its window", "gauge", &|s| {
+        s.observed_ms.map(|ms| ms as f64 / 1000.0)
+    });
+    series("ccm_slo_compliance_ratio", "Fraction of turns in the window within the latency limit", "gauge", &|s| s.compliance);
+    series("ccm_slo_met", "1 if the SLO is met, 0 if violated (absent until min_samples turns)", "gauge", &|s| {
+        s.met.map(|met| if met { 1.0 } else { 0.0 })
+    });
+    series("ccm_slo_samples", "Turns in the SLO's window", "gauge", &|s| Some(s.samples as f64));
+    series("ccm_slo_violations_total", "Times the SLO went from met to violated since startup", "counter", &|s| {
+        Some(s.violations as f64)
+    });
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], out)
+}
@@ -1,33 +1,71 @@
 mod openai_compat;
+mod responses_compat;
+mod continuation;
+mod identity;
+mod health;
 mod oauth_handlers;
-
-use crate::cli::AppConfig;
-use crate::models::{AnthropicRequest, RouteType};
-use crate::router::Router;
-use crate::providers::ProviderRegistry;
+mod prompt_rule_handlers;
+mod warmup;
+mod hedge;
+pub(crate) mod guardrails;
+mod secrets;
+mod live;
+mod client_auth;
+mod admin_auth;
+mod model_list;
+mod config_watch;
+pub(crate) mod images;
+mod routing_info;
+mod token_count;
+mod route_test;
+mod metrics;
+
+use crate::cli::{AppConfig, CountTokensMode, MappingOrdering, MappingStrategy, ModelConfig, ModelMapping, SyntheticStreamConfig};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use crate::models::{AnthropicRequest, RouteDecision, RouteType};
+use crate::router::{RouteStep, Router};
+use crate::providers::{AnthropicProvider, BudgetTracker, CapabilityCache, CircuitBreaker, HealthChecks, HealthTracker, ProviderRegistry, ProviderResponse, CostTracker, QualityTracker, QuotaTracker, SloTracker, WarmPool};
+use crate::providers::budget::{BudgetAction, BudgetExceeded};
+use crate::providers::deprecation::ModelWarnings;
+use crate::providers::pricing::{self, cost_info, ModelPrice, RemotePricing};
+use crate::providers::error::ProviderError;
+use crate::providers::prompt_tools;
+use crate::providers::health_check::{self, ProbeMethod};
+use crate::providers::warm_pool;
+use crate::shared_state::SharedState;
+use crate::providers::quality::Conversation;
+use crate::providers::streaming::{collect_stream_response, synthesize_sse_events, StreamErrorEvent, StreamErrorWatch, UsageStream};
+use crate::providers::Usage;
 use crate::auth::TokenStore;
-use crate::message_tracing::MessageTracer;
+use crate::message_tracing::{access_log, AccessLog, DatasetRecorder, MessageTracer, UsageStore};
+use crate::message_tracing::usage::{UsageQuery, UsageRecord};
+use crate::message_tracing::dataset::DatasetStream;
+use guardrails::SecretScanner;
+use health::{InFlight, RecentRoutes, ReloadStatus};
 use axum::{
     body::Body,
-    extract::State,
+    extract::{Query, State},
     http::{HeaderMap, StatusCode},
     response::{
         Html, IntoResponse, Response,
     },
-    routing::{get, post},
+    routing::{delete, get, post},
     Json, Router as AxumRouter,
 };
 use std::sync::Arc;
 use tokio::net::TcpListener;
-use tracing::{debug, error, info};
-use futures::stream::TryStreamExt;
-use chrono::Local;
+use tracing::{debug, error, info, warn};
+use futures::stream::{StreamExt, TryStreamExt};
 
 /// Reloadable components - rebuilt on config reload
 pub struct ReloadableState {
     pub config: AppConfig,
     pub router: Router,
     pub provider_registry: Arc<ProviderRegistry>,
+    pub guardrails: SecretScanner,
+    /// SHA-256 of the config file this state was built from (None if it couldn't be read)
+    pub source_hash: Option<String>,
 }
 
 /// Application state shared across handlers
@@ -39,6 +77,45 @@ pub struct AppState {
     pub token_store: TokenStore,
     pub config_path: std::path::PathBuf,
     pub message_tracer: Arc<MessageTracer>,
+    pub dataset_recorder: Arc<DatasetRecorder>,
+    pub usage_store: Arc<UsageStore>,
+    pub access_log: Arc<AccessLog>,
+    /// Learned per provider/model quirks (persisted across restarts)
+    pub capabilities: CapabilityCache,
+    /// Per provider/model circuit breaker (survives reloads)
+    pub circuit_breaker: Arc<CircuitBreaker>,
+    /// Rolling success rate / latency per provider/model (survives reloads)
+    pub health: Arc<HealthTracker>,
+    /// Background provider probes (`[server.health_checks]`)
+    pub health_checks: HealthChecks,
+    /// Remaining quota per provider from rate limit headers (survives reloads)
+    pub quota: Arc<QuotaTracker>,
+    /// Soft quality signals per provider/model (survives reloads)
+    pub quality: QualityTracker,
+    /// Configured models that look retired upstream (survives reloads)
+    pub model_warnings: ModelWarnings,
+    /// Token usage and spend per provider/model since startup (survives reloads)
+    pub costs: Arc<CostTracker>,
+    pub budgets: BudgetTracker,
+    /// Rolling turn latencies against `[[slos]]` (survives reloads)
+    pub slo: SloTracker,
+    /// Load state of `keep_warm` models (survives reloads)
+    pub warm_pool: WarmPool,
+    /// Outcome of the last config reload (for /health)
+    pub reload_status: std::sync::RwLock<ReloadStatus>,
+    /// Requests currently waiting on an upstream provider
+    pub in_flight: InFlight,
+    /// Last few served requests (for /api/debug/stats)
+    pub recent_routes: RecentRoutes,
+    /// Output progress of streaming responses (for the statusline)
+    pub live: Arc<live::LiveStreams>,
+    /// Recent routing decisions for the statusline (`last_routing.json`)
+    pub routing_info: routing_info::RoutingInfo,
+    /// Prices from `[server.pricing_source]` (config `[pricing]` wins)
+    pub remote_pricing: Arc<RemotePricing>,
+    /// Logged-in admin UI sessions (`[server.admin]`)
+    pub admin_sessions: admin_auth::AdminSessions,
+    pub started_at: chrono::DateTime<chrono::Utc>,
 }
 
 impl AppState {
@@ -48,49 +125,84 @@ impl AppState {
     }
 }
 
-const RECENT_REQUESTS_WINDOW: usize = 20;
+/// Add a completed request to the cost totals and usage history; returns its cost when priced
+fn record_usage(
+    state: &AppState,
+    model: &str,
+    mapping: &ModelMapping,
+    route_type: &RouteType,
+    usage: &Usage,
+    latency_ms: u64,
+    stream: bool,
+) -> Option<f64> {
+    let price = mapping_price(state, &state.snapshot(), mapping);
+    let cost = state.costs.record(&mapping.provider, &mapping.actual_model, usage, price.as_ref());
+    if let Some(cost) = cost {
+        state.budgets.record(&mapping.provider, &mapping.actual_model, cost);
+    }
+    access_log::note(|entry| {
+        entry.provider = Some(mapping.provider.clone());
+        entry.actual_model = Some(mapping.actual_model.clone());
+        entry.input_tokens = Some(usage.input_tokens);
+        entry.output_tokens = Some(usage.output_tokens);
+    });
+    state.usage_store.record(&UsageRecord {
+        ts: UsageStore::now(),
+        model: model.to_string(),
+        provider: mapping.provider.clone(),
+        actual_model: mapping.actual_model.clone(),
+        route_type: route_type.to_string(),
+        input_tokens: usage.input_tokens,
+        output_tokens: usage.output_tokens,
+        cache_read_tokens: usage.cache_read_input_tokens.unwrap_or(0),
+        cache_write_tokens: usage.cache_creation_input_tokens.unwrap_or(0),
+        latency_ms,
+        cost_usd: cost,
+        stream,
+    });
+    cost
+}
 
-/// Write routing information to file for statusline script
-fn write_routing_info(model: &str, provider: &str, route_type: &RouteType) {
-    if let Some(home) = dirs::home_dir() {
-        let file_path = home.join(".claude-code-mux/last_routing.json");
+/// Non-streaming call to a mapping. `force_streaming` mappings are called streaming
+/// upstream and the stream is collected into one response.
+pub(crate) async fn send_non_streaming(
+    provider: &dyn AnthropicProvider,
+    mapping: &ModelMapping,
+    mut request: AnthropicRequest,
+) -> Result<ProviderResponse, ProviderError> {
+    // Prompt-embedded tools are parsed from the complete response either way
+    if !mapping.force_streaming || mapping.prompt_tools {
+        return provider.send_message(request).await;
+    }
+    debug!("🌊 Collecting upstream stream for {}@{}", mapping.actual_model, mapping.provider);
+    request.stream = Some(true);
+    let stream_response = provider.send_message_stream(request).await?;
+    let mut response = collect_stream_response(stream_response.stream).await?;
+    response.rate_limits = stream_response.rate_limits;
+    Ok(response)
+}
 
-        // Read existing recent requests history
-        let mut recent: Vec<String> = Vec::new();
-        if let Ok(existing_content) = std::fs::read_to_string(&file_path) {
-            if let Ok(existing) = serde_json::from_str::<serde_json::Value>(&existing_content) {
-                if let Some(items) = existing.get("recent").and_then(|t| t.as_array()) {
-                    for item in items {
-                        if let Some(entry) = item.as_str() {
-                            recent.push(entry.to_string());
-                        }
-                    }
-                }
-            }
-        }
+/// SSE response built from a complete provider response (for `force_non_streaming` mappings).
+/// Deltas are paced so the client renders progressively.
+fn synthesized_stream_response(response: &ProviderResponse, pacing: &SyntheticStreamConfig) -> Response {
+    let events = synthesize_sse_events(response, pacing.chunk_chars);
+    let deltas = events.iter().filter(|e| e.event.as_deref() == Some("content_block_delta")).count();
+    let delay = pacing.delay_per_delta(deltas);
 
-        // Add current model/provider to recent
-        let current_entry = format!("{}@{}", model, provider);
-        recent.insert(0, current_entry);
-        recent.truncate(RECENT_REQUESTS_WINDOW);
-
-        // Create routing info
-        let routing_info = serde_json::json!({
-            "model": model,
-            "provider": provider,
-            "route_type": route_type.to_string(),
-            "timestamp": Local::now().format("%H:%M:%S").to_string(),
-            "recent": recent
-        });
-
-        if let Ok(json) = serde_json::to_string(&routing_info) {
-            if let Err(e) = std::fs::write(file_path, json) {
-                tracing::debug!("Failed to write routing info: {}", e);
-            }
-        } else {
-            tracing::debug!("Failed to serialize routing info");
+    let body_stream = futures::stream::iter(events).then(move |event| async move {
+        if !delay.is_zero() && event.event.as_deref() == Some("content_block_delta") {
+            tokio::time::sleep(delay).await;
         }
-    }
+        Ok::<_, std::io::Error>(bytes::Bytes::from(event.to_sse_string()))
+    });
+
+    Response::builder()
+        .status(200)
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .header("Connection", "keep-alive")
+        .body(Body::from_stream(body_stream))
+        .unwrap()
 }
 
 /// Start the HTTP server
@@ -108,7 +220,7 @@ pub async fn start_server(config: AppConfig, config_path: std::path::PathBuf) ->
 
     // Initialize provider registry from config (with token store and model mappings)
     let provider_registry = Arc::new(
-        ProviderRegistry::from_configs_with_models(&config.providers, Some(token_store.clone()), &config.models)
+        ProviderRegistry::from_configs_with_models(&config.providers, Some(token_store.clone()), &config.models, &config.server.timeouts)
             .map_err(|e| anyhow::anyhow!("Failed to initialize provider registry: {}", e))?
     );
 
@@ -120,11 +232,37 @@ pub async fn start_server(config: AppConfig, config_path: std::path::PathBuf) ->
     // Initialize message tracer
     let message_tracer = Arc::new(MessageTracer::new(config.server.tracing.clone()));
 
+    // Initialize dataset recorder
+    let dataset_recorder = Arc::new(DatasetRecorder::new(config.server.dataset.clone()));
+
+    // Initialize usage history
+    let usage_store = Arc::new(UsageStore::new(&config.server.usage));
+    let access_log = Arc::new(AccessLog::new(&config.server.access_log));
+
+    // Count this month's recorded spend towards budgets
+    let budgets = BudgetTracker::new();
+    match usage_store.spend_this_month() {
+        Ok(spend) => {
+            for (provider, model, day, cost) in spend {
+                budgets.record_at(&provider, &model, cost, day);
+            }
+        }
+        Err(e) => warn!("Failed to load this month's spend from usage history: {}", e),
+    }
+
+    // Load learned provider capabilities
+    let capabilities = match CapabilityCache::default_path() {
+        Some(path) => CapabilityCache::load(path),
+        None => CapabilityCache::in_memory(),
+    };
+
     // Build reloadable state
     let reloadable = Arc::new(ReloadableState {
         config: config.clone(),
         router,
         provider_registry,
+        guardrails: SecretScanner::new(&config.server.guardrails),
+        source_hash: config_source_hash(&config_path),
     });
 
     let state = Arc::new(AppState {
@@ -132,26 +270,116 @@ pub async fn start_server(config: AppConfig, config_path: std::path::PathBuf) ->
         token_store,
         config_path,
         message_tracer,
+        dataset_recorder,
+        usage_store,
+        access_log,
+        capabilities,
+        circuit_breaker: Arc::new(CircuitBreaker::new(config.server.circuit_breaker.clone())),
+        health: Arc::new(HealthTracker::new()),
+        health_checks: HealthChecks::new(),
+        quota: Arc::new(QuotaTracker::new(config.server.quota.clone())),
+        quality: QualityTracker::new(),
+        model_warnings: ModelWarnings::new(),
+        costs: Arc::new(CostTracker::new()),
+        budgets,
+        slo: SloTracker::new(),
+        warm_pool: WarmPool::new(),
+        reload_status: std::sync::RwLock::new(ReloadStatus::new()),
+        in_flight: InFlight::default(),
+        recent_routes: RecentRoutes::default(),
+        live: Arc::new(live::LiveStreams::default()),
+        routing_info: routing_info::RoutingInfo::start(crate::paths::state_file("last_routing.json")),
+        remote_pricing: Arc::new(RemotePricing::load(crate::paths::state_file("pricing-cache.json"))),
+        admin_sessions: admin_auth::AdminSessions::default(),
+        started_at: chrono::Utc::now(),
     });
 
+    // Probe half-open circuits so we fail back to the primary provider once it recovers
+    let breaker_config = state.circuit_breaker.config();
+    if breaker_config.enabled && breaker_config.probe_interval_secs > 0 {
+        tokio::spawn(run_failback_probes(state.clone()));
+    }
+
+    // Reload when the config file is edited by hand
+    if config.server.watch_config {
+        tokio::spawn(config_watch::run(state.clone()));
+    }
+
+    // Check provider model lists for configured models that were retired
+    if config.server.model_check_interval_hours > 0 {
+        tokio::spawn(run_model_checks(state.clone(), config.server.model_check_interval_hours));
+    }
+
+    // Keep local models loaded; runs regardless, since a reload can add `keep_warm`
+    tokio::spawn(run_keep_warm(state.clone()));
+
+    // Probe providers; runs regardless, since a reload can add `[server.health_checks]`
+    tokio::spawn(run_health_checks(state.clone()));
+
+    // Keep remote prices current
+    if let Some(source) = &config.server.pricing_source {
+        tokio::spawn(run_pricing_refresh(state.clone(), source.url.clone(), source.refresh_interval_hours));
+    }
+
+    // Exchange rate limits, spend and OAuth tokens with other instances
+    if let Some(shared_config) = &config.server.shared_state {
+        match SharedState::open(shared_config) {
+            Ok(shared) => {
+                info!("🔗 Sharing state as '{}' through {}", shared.instance(), shared_config.path);
+                tokio::spawn(run_shared_state_sync(state.clone(), Arc::new(shared), shared_config.sync_interval_secs));
+            }
+            Err(e) => error!("Failed to open shared state {}: {}", shared_config.path, e),
+        }
+    }
+
     // Build router
     let app = AxumRouter::new()
-        .route("/", get(serve_admin))
-        .route("/v1/messages", post(handle_messages))
-        .route("/v1/messages/count_tokens", post(handle_count_tokens))
-        .route("/v1/chat/completions", post(handle_openai_chat_completions))
-        .route("/health", get(health_check))
-        .route("/api/config/json", get(get_config_json))
-        .route("/api/config/json", post(update_config_json))
-        .route("/api/reload", post(reload_config))
-        // OAuth endpoints
-        .route("/api/oauth/authorize", post(oauth_handlers::oauth_authorize))
-        .route("/api/oauth/exchange", post(oauth_handlers::oauth_exchange))
+        .merge(
+            AxumRouter::new()
+                .route("/", get(serve_admin))
+                .route("/api/config/json", get(get_config_json))
+                .route("/api/config/json", post(update_config_json))
+                .route("/api/config/effective", get(get_effective_config))
+                .route("/api/reload", post(reload_config))
+                .route("/api/capabilities/clear", post(clear_capabilities))
+                .route("/api/providers/:name/api_key", post(set_provider_api_key))
+                .route("/api/prompt-rules", post(prompt_rule_handlers::upsert_prompt_rule))
+                .route("/api/prompt-rules/:index", delete(prompt_rule_handlers::delete_prompt_rule))
+                .route("/api/route-test", post(route_test::route_test))
+                // OAuth endpoints
+                .route("/api/oauth/authorize", post(oauth_handlers::oauth_authorize))
+                .route("/api/oauth/exchange", post(oauth_handlers::oauth_exchange))
+                .route("/api/oauth/tokens", get(oauth_handlers::oauth_list_tokens))
+                .route("/api/oauth/tokens/delete", post(oauth_handlers::oauth_delete_token))
+                .route("/api/oauth/tokens/refresh", post(oauth_handlers::oauth_refresh_token))
+                .route_layer(axum::middleware::from_fn_with_state(state.clone(), admin_auth::require_admin)),
+        )
+        .route("/login", get(admin_auth::login_page))
+        .route("/login", post(admin_auth::login))
+        .route("/logout", post(admin_auth::logout))
+        .merge(
+            AxumRouter::new()
+                .route("/v1/messages", post(handle_messages))
+                .route("/v1/messages/count_tokens", post(handle_count_tokens))
+                .route("/v1/chat/completions", post(handle_openai_chat_completions))
+                .route("/v1/responses", post(handle_responses))
+                .route("/v1/models", get(list_models))
+                .route_layer(axum::middleware::from_fn_with_state(state.clone(), client_auth::require_api_key))
+                .route_layer(axum::middleware::from_fn_with_state(state.clone(), log_access)),
+        )
+        .route("/health", get(health::health_check))
+        .route("/api/capabilities", get(list_capabilities))
+        .route("/api/circuit-breaker", get(list_circuits))
+        .route("/api/providers", get(list_providers))
+        .route("/api/providers/health", get(provider_health))
+        .route("/api/debug/stats", get(health::debug_stats))
+        .route("/metrics", get(metrics::metrics))
+        .route("/api/live", get(live_status))
+        .route("/api/usage", get(query_usage))
+        .route("/api/prompt-rules", get(prompt_rule_handlers::list_prompt_rules))
+        // OAuth callbacks stay open: providers redirect the browser there
         .route("/api/oauth/callback", get(oauth_handlers::oauth_callback))
-        .route("/auth/callback", get(oauth_handlers::oauth_callback))  // OpenAI Codex uses this path
-        .route("/api/oauth/tokens", get(oauth_handlers::oauth_list_tokens))
-        .route("/api/oauth/tokens/delete", post(oauth_handlers::oauth_delete_token))
-        .route("/api/oauth/tokens/refresh", post(oauth_handlers::oauth_refresh_token));
+        .route("/auth/callback", get(oauth_handlers::oauth_callback));  // OpenAI Codex uses this path
 
     // Clone state before moving it
     let oauth_state = state.clone();
@@ -192,22 +420,304 @@ pub async fn start_server(config: AppConfig, config_path: std::path::PathBuf) ->
     Ok(())
 }
 
+/// Write an access log line and record SLO latency per `/v1` request: right away for
+/// JSON responses, when the body ends for streams (so usage reported at the end is
+/// included and the turn's latency covers the whole stream)
+async fn log_access(State(state): State<Arc<AppState>>, request: axum::extract::Request, next: axum::middleware::Next) -> Response {
+    if !state.access_log.is_enabled() && state.snapshot().config.slos.is_empty() {
+        return next.run(request).await;
+    }
+    let started = std::time::Instant::now();
+    let entry = Arc::new(std::sync::Mutex::new(access_log::AccessEntry {
+        ts: UsageStore::now(),
+        method: request.method().to_string(),
+        path: request.uri().path().to_string(),
+        client_key: client_auth::presented_key(request.headers()).map(access_log::key_id),
+        ..Default::default()
+    }));
+
+    let response = access_log::track(entry.clone(), next.run(request)).await;
+    entry.lock().unwrap().status = response.status().as_u16();
+    let is_stream = response
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if !is_stream {
+        let mut entry = entry.lock().unwrap();
+        entry.latency_ms = started.elapsed().as_millis() as u64;
+        finish_turn(&state, &entry);
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = access_log::LoggedStream::new(body.into_data_stream(), entry, started, move |entry| finish_turn(&state, entry));
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+/// Log a finished request and count a successful turn towards its route's SLOs
+fn finish_turn(state: &AppState, entry: &access_log::AccessEntry) {
+    state.access_log.write(entry);
+    let Some(route) = entry.route.as_deref().filter(|_| (200..300).contains(&entry.status)) else {
+        return;
+    };
+    for alert in state.slo.record(&state.snapshot().config.slos, route, entry.latency_ms) {
+        if alert.violated {
+            warn!("🐢 SLO violated: {}", alert);
+        } else {
+            info!("✅ SLO met again: {}", alert);
+        }
+        if let Some(url) = alert.slo.webhook.clone() {
+            let body = serde_json::json!({
+                "text": format!("{} {}", if alert.violated { "SLO violated:" } else { "SLO met again:" }, alert),
+                "slo": alert.slo.label(),
+                "route": alert.slo.route,
+                "state": if alert.violated { "violated" } else { "met" },
+                "percentile": alert.slo.percentile,
+                "observed_ms": alert.observed_ms,
+                "max_latency_ms": (alert.slo.max_latency_secs * 1000.0) as u64,
+                "samples": alert.samples,
+            });
+            tokio::spawn(async move {
+                if let Err(e) = reqwest::Client::new().post(&url).json(&body).send().await {
+                    warn!("Failed to send SLO alert to {}: {}", url, e);
+                }
+            });
+        }
+    }
+}
+
+/// Periodically probe half-open circuits with a minimal request
+async fn run_failback_probes(state: Arc<AppState>) {
+    let interval_secs = state.circuit_breaker.config().probe_interval_secs;
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        interval.tick().await;
+
+        for (provider_name, model) in state.circuit_breaker.due_probes() {
+            let inner = state.snapshot();
+            let Some(provider) = inner.provider_registry.get_provider(&provider_name) else {
+                continue;
+            };
+
+            debug!("🩺 Probing half-open circuit {}/{}", provider_name, model);
+            let probe = crate::providers::circuit_breaker::probe_request(&model);
+            match provider.send_message(probe).await {
+                Ok(_) => {
+                    state.circuit_breaker.record_success(&provider_name, &model);
+                }
+                Err(e) => {
+                    debug!("🩺 Probe for {}/{} failed: {}", provider_name, model, e);
+                    state.circuit_breaker.record_failure(&provider_name, &model, &e);
+                    state.model_warnings.record_error(&provider_name, &model, &e);
+                }
+            }
+        }
+    }
+}
+
+/// How often the `keep_warm` schedule is checked
+const KEEP_WARM_TICK: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Warm up each `keep_warm` model once its interval has passed. Warm-ups run as
+/// their own tasks, so a model loading from cold doesn't hold up the others.
+async fn run_keep_warm(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(KEEP_WARM_TICK);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        interval.tick().await;
+
+        let inner = state.snapshot();
+        for provider_config in inner.config.providers.iter().filter(|p| p.is_enabled()) {
+            let Some(keep_warm) = &provider_config.keep_warm else {
+                continue;
+            };
+            let Some(provider) = inner.provider_registry.get_provider(&provider_config.name) else {
+                continue;
+            };
+            for model in warm_pool::keep_warm_models(&inner.config, provider_config, |m| provider.supports_model(m)) {
+                if !state.warm_pool.start_if_due(&provider_config.name, &model, keep_warm.interval()) {
+                    continue;
+                }
+                let (state, provider, name, keep_alive) =
+                    (state.clone(), provider.clone(), provider_config.name.clone(), keep_warm.keep_alive());
+                tokio::spawn(async move {
+                    let start = std::time::Instant::now();
+                    let result = provider.keep_warm(&model, keep_alive).await.map(|_| start.elapsed()).map_err(|e| e.to_string());
+                    let loaded = provider.is_loaded(&model).await.ok().flatten();
+                    let previous = state.warm_pool.record(&name, &model, result.clone(), loaded);
+                    match result {
+                        Ok(took) if previous.loaded != Some(true) => {
+                            info!("🔥 {}/{} warm ({}ms)", name, model, took.as_millis())
+                        }
+                        Ok(took) => debug!("🔥 {}/{} kept warm ({}ms)", name, model, took.as_millis()),
+                        Err(e) if previous.last_error.is_none() => warn!("🔥 Keeping {}/{} warm failed: {}", name, model, e),
+                        Err(e) => debug!("🔥 Keeping {}/{} warm failed again: {}", name, model, e),
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// How often the health check schedule is checked
+const HEALTH_CHECK_TICK: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Probe each enabled provider once its interval has passed, each in its own task
+async fn run_health_checks(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(HEALTH_CHECK_TICK);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        interval.tick().await;
+
+        let inner = state.snapshot();
+        let Some(checks) = inner.config.server.health_checks.clone() else {
+            continue;
+        };
+        for provider_config in inner.config.providers.iter().filter(|p| p.is_enabled()) {
+            let Some(provider) = inner.provider_registry.get_provider(&provider_config.name) else {
+                continue;
+            };
+            if !state.health_checks.start_if_due(&provider_config.name, checks.interval()) {
+                continue;
+            }
+            let model = health_check::probe_model(&inner.config, provider_config);
+            let (state, checks, name) = (state.clone(), checks.clone(), provider_config.name.clone());
+            tokio::spawn(async move {
+                let start = std::time::Instant::now();
+                let probe = tokio::time::timeout(checks.timeout(), probe_provider(provider.as_ref().as_ref(), checks.probe, model)).await;
+                let (method, result) = match probe {
+                    Ok(Some((method, result))) => (method, result.map(|_| start.elapsed()).map_err(|e| e.to_string())),
+                    // Nothing to probe with
+                    Ok(None) => return,
+                    Err(_) => ("timeout", Err(format!("no answer within {}s", checks.timeout().as_secs()))),
+                };
+                match state.health_checks.record(&name, method, result.clone(), checks.down_after) {
+                    Some(true) => warn!("🩺 {} is down: {}", name, result.err().unwrap_or_default()),
+                    Some(false) => info!("🩺 {} is back up", name),
+                    None => {
+                        if let Err(e) = result {
+                            debug!("🩺 Health check of {} failed: {}", name, e);
+                        }
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// One health check probe: the method used and its outcome, or None when the
+/// method doesn't apply to this provider
+async fn probe_provider(
+    provider: &dyn AnthropicProvider,
+    method: ProbeMethod,
+    model: Option<String>,
+) -> Option<(&'static str, Result<(), ProviderError>)> {
+    if method != ProbeMethod::Completion {
+        match provider.list_models().await {
+            Ok(Some(_)) => return Some(("models", Ok(()))),
+            Ok(None) if method == ProbeMethod::Models => return None,
+            Err(e) if method == ProbeMethod::Models || e.is_provider_fault() => return Some(("models", Err(e))),
+            // Can't list models (or no models endpoint): fall back to a completion
+            _ => {}
+        }
+    }
+    let probe = crate::providers::circuit_breaker::probe_request(&model?);
+    Some(("completion", provider.send_message(probe).await.map(|_| ())))
+}
+
+/// Periodically push this instance's rate limits, spend and tokens to the shared
+/// database and pull the other instances'
+async fn run_shared_state_sync(state: Arc<AppState>, shared: Arc<SharedState>, interval_secs: u64) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs.max(1)));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        interval.tick().await;
+
+        let (state, shared) = (state.clone(), shared.clone());
+        let result = tokio::task::spawn_blocking(move || shared.sync(&state.quota, &state.budgets, &state.token_store)).await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("🔗 Shared state sync failed: {:#}", e),
+            Err(e) => warn!("🔗 Shared state sync panicked: {}", e),
+        }
+    }
+}
+
+/// Fetch the remote price table now and every `interval_hours` (0 = only at startup)
+async fn run_pricing_refresh(state: Arc<AppState>, url: String, interval_hours: u64) {
+    let client = match reqwest::Client::builder().timeout(std::time::Duration::from_secs(30)).build() {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to build pricing client: {}", e);
+            return;
+        }
+    };
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_hours.max(1) * 3600));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        interval.tick().await;
+
+        match state.remote_pricing.refresh(&client, &url).await {
+            Ok(count) => info!("💲 Loaded {} prices from {}", count, url),
+            Err(e) => warn!("💲 Price refresh failed, keeping {} known prices: {:#}", state.remote_pricing.count(), e),
+        }
+        if interval_hours == 0 {
+            return;
+        }
+    }
+}
+
+/// Periodically compare configured actual_models against each provider's model list
+async fn run_model_checks(state: Arc<AppState>, interval_hours: u64) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_hours * 3600));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        interval.tick().await;
+
+        let inner = state.snapshot();
+        let mut by_provider: std::collections::BTreeMap<&str, Vec<&str>> = std::collections::BTreeMap::new();
+        for mapping in inner.config.models.iter().flat_map(|m| &m.mappings) {
+            // Templated models depend on the request
+            if !mapping.actual_model.contains('{') {
+                by_provider.entry(&mapping.provider).or_default().push(&mapping.actual_model);
+            }
+        }
+
+        for (provider_name, models) in by_provider {
+            let Some(provider) = inner.provider_registry.get_provider(provider_name) else {
+                continue;
+            };
+            match provider.list_models().await {
+                Ok(Some(listed)) if !listed.is_empty() => {
+                    for model in models {
+                        state.model_warnings.check_listed(provider_name, model, &listed);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => debug!("📋 Couldn't list models for {}: {}", provider_name, e),
+            }
+        }
+    }
+}
+
 /// Serve Admin UI
 async fn serve_admin() -> impl IntoResponse {
     Html(include_str!("admin.html"))
 }
 
-/// Health check endpoint
-async fn health_check() -> impl IntoResponse {
-    Json(serde_json::json!({
-        "status": "ok",
-        "service": "claude-code-mux"
-    }))
-}
-
-/// Get full configuration as JSON (for admin UI)
+/// Get full configuration as JSON (for admin UI), with secrets masked
 async fn get_config_json(State(state): State<Arc<AppState>>) -> impl IntoResponse {
     let inner = state.snapshot();
+    let mut providers = serde_json::to_value(&inner.config.providers).unwrap_or_default();
+    secrets::mask_secrets(&mut providers);
     Json(serde_json::json!({
         "server": {
             "host": inner.config.server.host,
@@ -218,15 +728,143 @@ async fn get_config_json(State(state): State<Arc<AppState>>) -> impl IntoRespons
             "background": inner.config.router.background,
             "think": inner.config.router.think,
             "websearch": inner.config.router.websearch,
+            "websearch_summarizer": inner.config.router.websearch_summarizer,
+            "websearch_summarizer_threshold": inner.config.router.websearch_summarizer_threshold,
+            "suggest": inner.config.router.suggest,
+            "long_context": inner.config.router.long_context,
+            "long_context_threshold": inner.config.router.long_context_threshold,
             "auto_map_regex": inner.config.router.auto_map_regex,
             "background_regex": inner.config.router.background_regex,
             "prompt_rules": inner.config.router.prompt_rules,
+            "tie_break": inner.config.router.tie_break,
         },
-        "providers": inner.config.providers,
+        "providers": providers,
         "models": inner.config.models,
     }))
 }
 
+/// List provider/model capabilities learned from upstream failures
+async fn list_capabilities(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(serde_json::json!({
+        "capabilities": state.capabilities.list(),
+    }))
+}
+
+/// Per-request usage history, filtered and optionally grouped (`?group_by=day`)
+async fn query_usage(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<UsageQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let usage = state.usage_store.query(&query).map_err(|e| AppError::InvalidRequest(e.to_string()))?;
+    Ok(Json(serde_json::json!({ "usage": usage })))
+}
+
+/// Forget learned capabilities (e.g. after an upstream adds support)
+async fn clear_capabilities(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    state.capabilities.clear();
+    info!("🧩 Cleared learned provider capabilities");
+    Json(serde_json::json!({
+        "status": "success",
+        "message": "Capability cache cleared"
+    }))
+}
+
+/// Streams in progress with their output speed, and the last finished one
+async fn live_status(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(serde_json::json!({
+        "streams": state.live.active(),
+        "last": state.live.last(),
+    }))
+}
+
+/// List provider/model circuits that have recorded failures
+async fn list_circuits(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(serde_json::json!({
+        "circuits": state.circuit_breaker.statuses(),
+    }))
+}
+
+/// Configured providers with rolling health and soft quality signals per model
+async fn list_providers(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let inner = state.snapshot();
+    let health = state.health.all_stats();
+    let quality = state.quality.all_stats();
+
+    let providers: Vec<serde_json::Value> = inner
+        .config
+        .providers
+        .iter()
+        .map(|p| {
+            serde_json::json!({
+                "name": p.name,
+                "provider_type": p.provider_type,
+                "enabled": p.is_enabled(),
+                "region": p.region,
+                "health": health.iter().filter(|h| h.provider == p.name).collect::<Vec<_>>(),
+                "quality": quality.iter().filter(|q| q.provider == p.name).collect::<Vec<_>>(),
+                "warm": state.warm_pool.statuses(&p.name),
+            })
+        })
+        .collect();
+    Json(serde_json::json!({ "providers": providers }))
+}
+
+/// Provider health from the background probes (`[server.health_checks]`)
+async fn provider_health(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let inner = state.snapshot();
+    let names = inner.config.providers.iter().filter(|p| p.is_enabled()).map(|p| p.name.as_str());
+    Json(serde_json::json!({
+        "enabled": inner.config.server.health_checks.is_some(),
+        "health_checks": inner.config.server.health_checks,
+        "providers": state.health_checks.statuses(names),
+    }))
+}
+
+/// Models clients can request. Anthropic list format when the client sends
+/// `anthropic-version` (Anthropic SDKs do), OpenAI format otherwise.
+async fn list_models(State(state): State<Arc<AppState>>, headers: HeaderMap) -> impl IntoResponse {
+    let models = model_list::list_models(&state.snapshot().config);
+    if headers.contains_key("anthropic-version") {
+        Json(model_list::anthropic_list(&models, state.started_at))
+    } else {
+        Json(model_list::openai_list(&models, state.started_at))
+    }
+}
+
+/// New API key for a provider
+#[derive(Debug, serde::Deserialize)]
+struct ApiKeyUpdate {
+    api_key: String,
+}
+
+/// Re-enter a provider's API key (config reads only return it masked). Writes
+/// the key (or a `$ENV_VAR` reference) to the config file and reloads.
+async fn set_provider_api_key(
+    State(state): State<Arc<AppState>>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+    Json(update): Json<ApiKeyUpdate>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = update.api_key.trim();
+    if api_key.is_empty() || secrets::is_masked(api_key) {
+        return Err(AppError::InvalidRequest("api_key must be the full key".to_string()));
+    }
+    if !state.snapshot().config.providers.iter().any(|p| p.name == name) {
+        return Err(AppError::InvalidRequest(format!("No provider named '{}'", name)));
+    }
+
+    let path = format!("providers.\"{}\".api_key", name);
+    crate::cli::config_edit::set_in_file(&state.config_path, &path, api_key.into())
+        .map_err(|e| AppError::ParseError(format!("{:#}", e)))?;
+    info!("🔑 API key for provider '{}' updated via admin API", name);
+    reload_state(&state).map_err(AppError::ParseError)?;
+
+    Ok(Json(serde_json::json!({
+        "status": "success",
+        "provider": name,
+        "api_key": secrets::mask_secret(api_key),
+    })))
+}
+
 /// Remove null values from JSON (TOML doesn't support null)
 fn remove_null_values(value: &mut serde_json::Value) {
     match value {
@@ -263,8 +901,20 @@ async fn update_config_json(
     let mut config: toml::Value = toml::from_str(&config_str)
         .map_err(|e| AppError::ParseError(format!("Failed to parse config: {}", e)))?;
 
+    // get_config_json serves resolved values; keep the file's `${VAR}` references
+    // for the ones that weren't changed
+    let raw = serde_json::to_value(&config)
+        .map_err(|e| AppError::ParseError(format!("Failed to read config: {}", e)))?;
+    crate::cli::env::restore_references(&mut new_config, &raw, &|name| std::env::var(name).ok());
+
     // Update providers section
-    if let Some(providers) = new_config.get("providers") {
+    if let Some(providers) = new_config.get_mut("providers") {
+        // Masked secrets (from get_config_json) keep the values in the file
+        let current = config.get("providers").map(serde_json::to_value).transpose()
+            .map_err(|e| AppError::ParseError(format!("Failed to read providers: {}", e)))?
+            .unwrap_or_default();
+        secrets::restore_provider_secrets(providers, &current).map_err(AppError::InvalidRequest)?;
+
         // Convert from serde_json::Value to toml::Value
         let providers_toml: toml::Value = serde_json::from_str(&providers.to_string())
             .map_err(|e| AppError::ParseError(format!("Failed to convert providers: {}", e)))?;
@@ -311,8 +961,11 @@ async fn update_config_json(
             update_field(router_table, "think", router.get("think"));
             update_field(router_table, "websearch", router.get("websearch"));
             update_field(router_table, "background", router.get("background"));
+            update_field(router_table, "suggest", router.get("suggest"));
+            update_field(router_table, "long_context", router.get("long_context"));
             update_field(router_table, "auto_map_regex", router.get("auto_map_regex"));
             update_field(router_table, "background_regex", router.get("background_regex"));
+            update_field(router_table, "tie_break", router.get("tie_break"));
         }
     }
 
@@ -335,51 +988,95 @@ async fn update_config_json(
 async fn reload_config(State(state): State<Arc<AppState>>) -> Response {
     info!("🔄 Configuration reload requested via UI");
 
-    // 1. Read and parse new config (all sync, no locks held)
-    let config_str = match std::fs::read_to_string(&state.config_path) {
-        Ok(s) => s,
-        Err(e) => {
-            error!("Failed to read config: {}", e);
-            return Html(format!("<div class='px-4 py-3 rounded-xl bg-red-500/20 border border-red-500/50 text-foreground text-sm'><strong>❌ Reload failed</strong><br/>Failed to read config: {}</div>", e)).into_response();
-        }
-    };
+    match reload_state(&state) {
+        Ok(()) => Html("<div class='px-4 py-3 rounded-xl bg-green-500/20 border border-green-500/50 text-foreground text-sm'><strong>✅ Configuration reloaded</strong><br/>New settings are now active.</div>").into_response(),
+        Err(e) => Html(format!("<div class='px-4 py-3 rounded-xl bg-red-500/20 border border-red-500/50 text-foreground text-sm'><strong>❌ Reload failed</strong><br/>{}</div>", e)).into_response(),
+    }
+}
+
+/// SHA-256 (hex) of the config file's contents
+fn config_source_hash(path: &std::path::Path) -> Option<String> {
+    use sha2::Digest;
+    let contents = std::fs::read(path).ok()?;
+    Some(sha2::Sha256::digest(&contents).iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Get the configuration currently in use, which differs from the file on disk
+/// until the next reload. Secrets are masked.
+async fn get_effective_config(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let inner = state.snapshot();
+    let mut config = serde_json::to_value(&inner.config).unwrap_or_default();
+    secrets::mask_secrets(&mut config);
 
-    let new_config: AppConfig = match toml::from_str(&config_str) {
-        Ok(c) => c,
+    let disk_hash = config_source_hash(&state.config_path);
+    let reload_status = state.reload_status.read().unwrap();
+    Json(serde_json::json!({
+        "config": config,
+        "config_path": state.config_path,
+        "source_hash": inner.source_hash,
+        "changed_on_disk": disk_hash != inner.source_hash,
+        "loaded_at": reload_status.loaded_at,
+        "last_error": reload_status.last_error,
+        "last_error_at": reload_status.last_error_at,
+    }))
+}
+
+/// Re-read the config file and atomically swap in a new router and provider registry
+fn reload_state(state: &AppState) -> Result<(), String> {
+    let result = rebuild_state(state);
+
+    let mut status = state.reload_status.write().unwrap();
+    match &result {
+        Ok(()) => *status = ReloadStatus::new(),
         Err(e) => {
-            error!("Failed to parse config: {}", e);
-            return Html(format!("<div class='px-4 py-3 rounded-xl bg-red-500/20 border border-red-500/50 text-foreground text-sm'><strong>❌ Reload failed</strong><br/>Failed to parse config: {}</div>", e)).into_response();
+            status.last_error = Some(e.clone());
+            status.last_error_at = Some(chrono::Utc::now());
         }
-    };
+    }
+    result
+}
 
-    // 2. Build new router (compiles regexes)
-    let new_router = Router::new(new_config.clone());
+fn rebuild_state(state: &AppState) -> Result<(), String> {
+    // 1. Read and parse new config, including CCM_* env overrides (all sync, no locks held)
+    let source_hash = config_source_hash(&state.config_path);
+    let new_config = AppConfig::from_file(&state.config_path).map_err(|e| {
+        error!("Failed to load config: {:#}", e);
+        format!("Failed to load config: {:#}", e)
+    })?;
+    crate::cli::validate::check(&new_config, &state.config_path).map_err(|e| {
+        error!("Invalid config: {:#}", e);
+        format!("Invalid config: {:#}", e)
+    })?;
+
+    // 2. Build new router (compiles regexes), keeping prompt rule session state
+    let new_router = Router::new(new_config.clone()).with_sessions(state.snapshot().router.sessions());
 
     // 3. Build new provider registry (reuse existing token_store)
-    let new_registry = match ProviderRegistry::from_configs_with_models(
+    let new_registry = ProviderRegistry::from_configs_with_models(
         &new_config.providers,
         Some(state.token_store.clone()),
         &new_config.models,
-    ) {
-        Ok(r) => Arc::new(r),
-        Err(e) => {
-            error!("Failed to init providers: {}", e);
-            return Html(format!("<div class='px-4 py-3 rounded-xl bg-red-500/20 border border-red-500/50 text-foreground text-sm'><strong>❌ Reload failed</strong><br/>Failed to init providers: {}</div>", e)).into_response();
-        }
-    };
+        &new_config.server.timeouts,
+    )
+    .map_err(|e| {
+        error!("Failed to init providers: {}", e);
+        format!("Failed to init providers: {}", e)
+    })?;
 
     // 4. Create new reloadable state
     let new_inner = Arc::new(ReloadableState {
+        guardrails: SecretScanner::new(&new_config.server.guardrails),
         config: new_config,
         router: new_router,
-        provider_registry: new_registry,
+        provider_registry: Arc::new(new_registry),
+        source_hash,
     });
 
     // 5. Atomic swap (write lock held for microseconds)
     *state.inner.write().unwrap() = new_inner;
 
     info!("✅ Configuration reloaded successfully");
-    Html("<div class='px-4 py-3 rounded-xl bg-green-500/20 border border-green-500/50 text-foreground text-sm'><strong>✅ Configuration reloaded</strong><br/>New settings are now active.</div>").into_response()
+    Ok(())
 }
 
 /// Handle /v1/chat/completions requests (OpenAI-compatible endpoint)
@@ -391,61 +1088,44 @@ async fn handle_openai_chat_completions(
     headers: HeaderMap,
     Json(openai_request): Json<openai_compat::OpenAIRequest>,
 ) -> Result<Response, AppError> {
+    if openai_request.stream == Some(true) {
+        return stream_openai_chat_completions(state, headers, openai_request).await;
+    }
+
     let model = openai_request.model.clone();
     let start_time = std::time::Instant::now();
 
+    let _in_flight = state.in_flight.enter();
+
     // Get snapshot of reloadable state
     let inner = state.snapshot();
 
-    // Streaming is not supported for /v1/chat/completions
-    if openai_request.stream == Some(true) {
-        return Err(AppError::ParseError(
-            "Streaming is not supported for /v1/chat/completions. Use /v1/messages instead.".to_string()
-        ));
-    }
-
     // 1. Transform OpenAI request to Anthropic format
     let mut anthropic_request = openai_compat::transform_openai_to_anthropic(openai_request)
         .map_err(|e| AppError::ParseError(format!("Failed to transform OpenAI request: {}", e)))?;
 
     // 2. Route the request (may modify system prompt to remove CCM-SUBAGENT-MODEL tag)
-    let decision = inner
-        .router
-        .route(&mut anthropic_request)
-        .map_err(|e| AppError::RoutingError(e.to_string()))?;
+    let (decision, explain_steps) = route_request(&inner, &headers, &mut anthropic_request)?;
 
     // 3. Try model mappings with fallback (1:N mapping)
-    if let Some(model_config) = inner.config.models.iter().find(|m| m.name.eq_ignore_ascii_case(&decision.model_name)) {
-
-        // Check for X-Provider header to override priority
-        let forced_provider = headers
-            .get("x-provider")
-            .and_then(|v| v.to_str().ok())
-            .filter(|s| !s.is_empty())
-            .map(|s| s.to_string());
+    // X-Provider header overrides priority (and direct lookup for unmapped models)
+    let forced_provider = forced_provider(&headers);
 
+    if let Some(model_config) = resolve_model_config(&inner, &decision.model_name, forced_provider.as_deref()) {
         if let Some(ref provider_name) = forced_provider {
             info!("🎯 Using forced provider from X-Provider header: {}", provider_name);
         }
 
-        // Sort mappings by priority (or filter by forced provider)
-        let mut sorted_mappings = model_config.mappings.clone();
-
-        if let Some(ref provider_name) = forced_provider {
-            // Filter to only the specified provider
-            sorted_mappings.retain(|m| m.provider == *provider_name);
-            if sorted_mappings.is_empty() {
-                return Err(AppError::RoutingError(format!(
-                    "Provider '{}' not found in mappings for model '{}'",
-                    provider_name, decision.model_name
-                )));
-            }
-        } else {
-            // Use priority ordering
-            sorted_mappings.sort_by_key(|m| m.priority);
-        }
+        let sorted_mappings = mapping_chain(
+            &state, &inner, &model_config, &decision, &model, forced_provider.as_deref(), true,
+        )?;
 
         // Try each mapping in priority order (or just the forced one)
+        let mut last_error: Option<ProviderError> = None;
+        // Why the last mapping that couldn't take the request was skipped (e.g. blocked by guardrails)
+        let mut skipped: Option<AppError> = None;
+        // Images are fetched once, for the first mapping whose provider needs them inline
+        let mut inlined_images = images::InlinedImages::new();
         for (idx, mapping) in sorted_mappings.iter().enumerate() {
             // Try to get provider from registry
             if let Some(provider) = inner.provider_registry.get_provider(&mapping.provider) {
@@ -479,34 +1159,46 @@ async fn handle_openai_chat_completions(
                     retry_info
                 );
 
-                // Update model to actual model name
-                anthropic_request.model = mapping.actual_model.clone();
-
-                // Inject continuation prompt if configured (skip for background tasks)
-                if mapping.inject_continuation_prompt && decision.route_type != RouteType::Background {
-                    if let Some(last_msg) = anthropic_request.messages.last_mut() {
-                        if should_inject_continuation(last_msg) {
-                            info!("💉 Injecting continuation prompt for model: {}", mapping.actual_model);
-                            inject_continuation_text(last_msg);
-                        }
+                let provider_request = mapping_request(
+                    &state, &inner, &anthropic_request, mapping, decision.route_type, &mut inlined_images,
+                )
+                .await;
+                let provider_request = match provider_request {
+                    Ok(request) => request,
+                    Err(e) => {
+                        info!("⚠️ Skipping {}/{}: {}", mapping.provider, mapping.actual_model, e);
+                        skipped = Some(e);
+                        continue;
                     }
-                }
+                };
 
                 // Write routing info immediately on first attempt
                 if idx == 0 {
-                    write_routing_info(&mapping.actual_model, &mapping.provider, &decision.route_type);
+                    state.routing_info.record(&mapping.actual_model, &mapping.provider, &decision.route_type);
                 }
 
-                match provider.send_message(anthropic_request.clone()).await {
-                    Ok(anthropic_response) => {
+                let attempt_start = std::time::Instant::now();
+                match send_non_streaming(provider.as_ref().as_ref(), mapping, provider_request).await {
+                    Ok(mut anthropic_response) => {
+                        state.circuit_breaker.record_success(&mapping.provider, &mapping.actual_model);
+                        state.model_warnings.record_success(&mapping.provider, &mapping.actual_model);
+                        state.health.record(&mapping.provider, &mapping.actual_model, true, attempt_start.elapsed());
+                        state.quota.observe(&mapping.provider, &anthropic_response.rate_limits);
+                        if mapping.prompt_tools {
+                            prompt_tools::extract_tool_calls(&mut anthropic_response);
+                        }
+                        state.quality.record_response(&mapping.provider, &mapping.actual_model, &anthropic_response);
+
                         // Calculate and log metrics
                         let latency_ms = start_time.elapsed().as_millis() as u64;
                         let tok_s = (anthropic_response.usage.output_tokens as f32 * 1000.0) / latency_ms as f32;
-                        info!("📊 {}@{} {}ms {:.0}t/s {}tok", mapping.actual_model, mapping.provider, latency_ms, tok_s, anthropic_response.usage.output_tokens);
+                        let cost = record_usage(&state, &model, mapping, &decision.route_type, &anthropic_response.usage, latency_ms, false);
+                        info!("📊 {}@{} {}ms {:.0}t/s {}tok{}{}", mapping.actual_model, mapping.provider, latency_ms, tok_s, anthropic_response.usage.output_tokens, anthropic_response.usage.cache_info(), cost_info(cost));
+                        state.recent_routes.record(&model, &decision.route_type, &mapping.provider, &mapping.actual_model, latency_ms);
 
                         // Write routing info on fallback success (idx==0 already wrote above)
                         if idx > 0 {
-                            write_routing_info(&mapping.actual_model, &mapping.provider, &decision.route_type);
+                            state.routing_info.record(&mapping.actual_model, &mapping.provider, &decision.route_type);
                         }
 
                         // Transform Anthropic response to OpenAI format
@@ -515,10 +1207,16 @@ async fn handle_openai_chat_completions(
                             model.clone(),
                         );
 
-                        return Ok(Json(openai_response).into_response());
+                        return Ok(json_response(&openai_response, &explain_steps, &decision, &mapping.provider, &mapping.actual_model));
                     }
                     Err(e) => {
+                        state.capabilities.record_failure(&mapping.provider, &mapping.actual_model, &e);
+                        state.circuit_breaker.record_failure(&mapping.provider, &mapping.actual_model, &e);
+                        state.model_warnings.record_error(&mapping.provider, &mapping.actual_model, &e);
+                        state.quota.record_rate_limit(&mapping.provider, &e);
+                        state.health.record(&mapping.provider, &mapping.actual_model, false, attempt_start.elapsed());
                         info!("⚠️ Provider {} failed: {}, trying next fallback", mapping.provider, e);
+                        last_error = Some(e);
                         continue;
                     }
                 }
@@ -529,22 +1227,29 @@ async fn handle_openai_chat_completions(
         }
 
         error!("❌ All provider mappings failed for model: {}", decision.model_name);
-        return Err(AppError::ProviderError(format!(
-            "All {} provider mappings failed for model: {}",
-            sorted_mappings.len(),
-            decision.model_name
-        )));
+        // No provider was called: report why the request couldn't be sent
+        if let (None, Some(skipped)) = (&last_error, skipped) {
+            return Err(skipped);
+        }
+        Err(AppError::upstream(
+            format!("All {} provider mappings failed for model: {}", sorted_mappings.len(), decision.model_name),
+            last_error,
+        ))
     } else {
         // No model mapping found, try direct provider registry lookup (backward compatibility)
-        if let Ok(provider) = inner.provider_registry.get_provider_for_model(&decision.model_name) {
+        if let Some((provider_name, provider)) = direct_provider(&inner, forced_provider.as_deref(), &decision.model_name)? {
+            check_route_provider(&inner, &decision, &provider_name)?;
+            check_residency(&inner, &decision, &provider_name)?;
             info!("📦 Using provider from registry (direct lookup): {}", decision.model_name);
 
             // Update model to routed model
             anthropic_request.model = decision.model_name.clone();
+            guard_secrets(&inner, &provider_name, &mut anthropic_request)?;
+            images::inline_for(provider.as_ref().as_ref(), &mut anthropic_request).await?;
 
             let anthropic_response = provider.send_message(anthropic_request)
                 .await
-                .map_err(|e| AppError::ProviderError(e.to_string()))?;
+                .map_err(|e| AppError::upstream(format!("{} failed", decision.model_name), Some(e)))?;
 
             // Transform to OpenAI format
             let openai_response = openai_compat::transform_anthropic_to_openai(
@@ -552,58 +1257,555 @@ async fn handle_openai_chat_completions(
                 model,
             );
 
-            return Ok(Json(openai_response).into_response());
+            return Ok(json_response(&openai_response, &explain_steps, &decision, "direct", &decision.model_name));
         }
 
         error!("❌ No model mapping or provider found for model: {}", decision.model_name);
-        return Err(AppError::ProviderError(format!(
+        Err(AppError::ProviderError(format!(
             "No model mapping or provider found for model: {}",
             decision.model_name
-        )));
+        )))
+    }
+}
+
+/// Streaming /v1/chat/completions requests. Served by `handle_messages`, with its
+/// SSE stream converted to chat completion chunks.
+async fn stream_openai_chat_completions(
+    state: Arc<AppState>,
+    headers: HeaderMap,
+    openai_request: openai_compat::OpenAIRequest,
+) -> Result<Response, AppError> {
+    let model = openai_request.model.clone();
+    let include_usage = openai_request
+        .stream_options
+        .as_ref()
+        .and_then(|o| o["include_usage"].as_bool())
+        .unwrap_or(false);
+    let anthropic_request = openai_compat::transform_openai_to_anthropic(openai_request)
+        .map_err(|e| AppError::ParseError(format!("Failed to transform OpenAI request: {}", e)))?;
+    let request_json = serde_json::to_value(&anthropic_request)
+        .map_err(|e| AppError::ParseError(format!("Failed to serialize request: {}", e)))?;
+
+    let response = handle_messages(State(state), headers, Json(request_json)).await?;
+    let (mut parts, body) = response.into_parts();
+    parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+
+    let mut converter = openai_compat::ChatCompletionStream::new(model, include_usage);
+    let chunks = body.into_data_stream().map(move |chunk| chunk.map(|bytes| converter.push(&bytes)));
+    Ok(Response::from_parts(parts, Body::from_stream(chunks)))
+}
+
+/// Handle /v1/responses requests (OpenAI Responses API, e.g. Codex CLI).
+/// Served by `handle_messages`, with its output converted back to Responses format.
+async fn handle_responses(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<responses_compat::ResponsesRequest>,
+) -> Result<Response, AppError> {
+    let anthropic_request = responses_compat::transform_request(request)
+        .map_err(|e| AppError::ParseError(format!("Failed to transform Responses request: {}", e)))?;
+    let model = anthropic_request.model.clone();
+    let request_json = serde_json::to_value(&anthropic_request)
+        .map_err(|e| AppError::ParseError(format!("Failed to serialize request: {}", e)))?;
+
+    let response = handle_messages(State(state), headers, Json(request_json)).await?;
+    let (mut parts, body) = response.into_parts();
+    parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+
+    let is_stream = parts
+        .headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if is_stream {
+        let mut converter = responses_compat::ResponsesStream::new(model);
+        let events = body.into_data_stream().map(move |chunk| chunk.map(|bytes| converter.push(&bytes)));
+        return Ok(Response::from_parts(parts, Body::from_stream(events)));
+    }
+
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(|e| AppError::ParseError(format!("Failed to read response: {}", e)))?;
+    let anthropic_response: ProviderResponse = serde_json::from_slice(&bytes)
+        .map_err(|e| AppError::ParseError(format!("Failed to parse response: {}", e)))?;
+    let body = serde_json::to_vec(&responses_compat::transform_response(anthropic_response, &model)).unwrap_or_default();
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+/// Find the mappings for a routed model: its [[models]] entry, or
+/// router.fallback_mappings when neither that nor a direct provider lookup matches.
+/// An unmapped model with a forced provider always goes to direct lookup.
+fn resolve_model_config<'a>(inner: &'a ReloadableState, model_name: &str, forced_provider: Option<&str>) -> Option<Cow<'a, ModelConfig>> {
+    if let Some(model_config) = inner.config.find_model(model_name) {
+        return Some(Cow::Borrowed(model_config));
+    }
+    if forced_provider.is_some() || inner.provider_registry.get_provider_for_model(model_name).is_ok() {
+        return None;
     }
+
+    let fallback = inner.config.fallback_model_config(model_name)?;
+    info!("🪂 No mapping for {}, using fallback_mappings", model_name);
+    Some(Cow::Owned(fallback))
+}
+
+/// Provider named by the X-Provider header, if any
+fn forced_provider(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("x-provider")
+        .and_then(|v| v.to_str().ok())
+        .filter(|s| !s.is_empty())  // Ignore empty strings
+        .map(|s| s.to_string())
 }
 
-/// Check if message has tool results but no text content
-/// (indicates model should continue after tool execution)
-fn should_inject_continuation(msg: &crate::models::Message) -> bool {
-    use crate::models::MessageContent;
-    let has_tool_results = match &msg.content {
-        MessageContent::Blocks(blocks) => blocks.iter().any(|b| b.is_tool_result()),
-        _ => false,
+/// A provider together with its configured name
+type NamedProvider = (String, Arc<Box<dyn AnthropicProvider>>);
+
+/// Provider for a model without mappings: the forced provider if one was named,
+/// otherwise whichever provider lists the model. Unknown forced providers are an error.
+fn direct_provider(
+    inner: &ReloadableState,
+    forced_provider: Option<&str>,
+    model_name: &str,
+) -> Result<Option<NamedProvider>, AppError> {
+    let Some(provider_name) = forced_provider else {
+        return Ok(inner
+            .provider_registry
+            .provider_name_for_model(model_name)
+            .and_then(|name| inner.provider_registry.get_provider(&name).map(|provider| (name, provider))));
     };
 
-    let has_text = match &msg.content {
-        MessageContent::Text(text) => !text.trim().is_empty(),
-        MessageContent::Blocks(blocks) => {
-            blocks.iter().any(|b| b.as_text().map(|t| !t.trim().is_empty()).unwrap_or(false))
+    match inner.provider_registry.get_provider(provider_name) {
+        Some(provider) => {
+            info!("🎯 Using forced provider from X-Provider header: {} (direct lookup)", provider_name);
+            Ok(Some((provider_name.to_string(), provider)))
+        }
+        None => {
+            let mut available = inner.provider_registry.list_providers();
+            available.sort();
+            Err(AppError::RoutingError(format!(
+                "Provider '{}' not found; available providers: {}",
+                provider_name,
+                available.join(", ")
+            )))
         }
+    }
+}
+
+/// Mappings to try for a routed request, in order: the forced provider's, or all of
+/// them ordered by priority (or latency), health, quota and circuit state; then the route,
+/// residency and budget filters. `rotate` advances round-robin counters (dry runs don't).
+fn mapping_chain(
+    state: &AppState,
+    inner: &ReloadableState,
+    model_config: &ModelConfig,
+    decision: &RouteDecision,
+    requested_model: &str,
+    forced_provider: Option<&str>,
+    rotate: bool,
+) -> Result<Vec<ModelMapping>, AppError> {
+    let mut sorted_mappings = model_config.mappings.clone();
+    for mapping in &mut sorted_mappings {
+        mapping.actual_model = mapping.resolve_actual_model(requested_model);
+    }
+
+    if let Some(provider_name) = forced_provider {
+        // Filter to only the specified provider
+        sorted_mappings.retain(|m| m.provider == provider_name);
+        if sorted_mappings.is_empty() {
+            return Err(AppError::RoutingError(format!(
+                "Provider '{}' not found in mappings for model '{}'; available: {}",
+                provider_name,
+                decision.model_name,
+                model_config.mappings.iter().map(|m| m.provider.as_str()).collect::<Vec<_>>().join(", ")
+            )));
+        }
+    } else {
+        // Use the model's ordering, trying mappings with an open circuit last
+        match model_config.ordering {
+            MappingOrdering::Priority => {
+                state.health.order(&mut sorted_mappings, inner.config.router.tie_break);
+                if rotate && model_config.strategy == MappingStrategy::RoundRobin {
+                    state.health.rotate(&model_config.name, &mut sorted_mappings);
+                }
+            }
+            MappingOrdering::Latency => state.health.order_by_latency(&mut sorted_mappings),
+            MappingOrdering::Cost => pricing::order_by_cost(&mut sorted_mappings, |m| mapping_price(state, inner, m)),
+        }
+        state.quota.deprioritize_exhausted(&mut sorted_mappings);
+        state.circuit_breaker.deprioritize_open(&mut sorted_mappings);
+        if inner.config.server.health_checks.is_some() {
+            state.health_checks.skip_down(&mut sorted_mappings);
+        }
+    }
+    filter_route_providers(inner, decision, &mut sorted_mappings)?;
+    filter_residency(inner, model_config, &mut sorted_mappings)?;
+    filter_max_cost(state, inner, model_config, decision, &mut sorted_mappings)?;
+    filter_budgets(state, inner, model_config, &mut sorted_mappings)?;
+    Ok(sorted_mappings)
+}
+
+/// Drop mappings to providers the route type may not use (`router.route_providers`),
+/// and mappings whose own `routes` leave out the route type. Fails if no mapping is left.
+fn filter_route_providers(
+    inner: &ReloadableState,
+    decision: &RouteDecision,
+    mappings: &mut Vec<ModelMapping>,
+) -> Result<(), AppError> {
+    let route = decision.route_type.to_string();
+    let filter = inner.config.router.provider_filter(&route);
+    let permitted = |m: &ModelMapping| m.serves_route(&route) && filter.is_none_or(|f| f.permits(&m.provider));
+
+    let before: Vec<String> = mappings.iter().map(|m| m.provider.clone()).collect();
+    let dropped: Vec<&str> = mappings.iter().filter(|m| !permitted(m)).map(|m| m.provider.as_str()).collect();
+    if !dropped.is_empty() {
+        info!("🚧 Skipping providers not allowed for {} route: {}", decision.route_type, dropped.join(", "));
+    }
+    mappings.retain(permitted);
+    if mappings.is_empty() {
+        return Err(AppError::RoutingError(format!(
+            "No provider allowed for {} route among mappings for model '{}' ({})",
+            decision.route_type,
+            decision.model_name,
+            before.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Drop mappings to providers outside the model's residency policy, logging each violation.
+/// Fails if no mapping is left.
+fn filter_residency(inner: &ReloadableState, model_config: &ModelConfig, mappings: &mut Vec<ModelMapping>) -> Result<(), AppError> {
+    let policy = inner.config.residency_for(Some(model_config));
+    if policy.is_empty() {
+        return Ok(());
+    }
+
+    let before: Vec<String> = mappings.iter().map(|m| m.provider.clone()).collect();
+    mappings.retain(|m| {
+        let permitted = inner.config.residency_permits(policy, &m.provider);
+        if !permitted {
+            warn!(
+                "🌍 Residency: skipping {} (region {}) for model {}; allowed: {}",
+                m.provider,
+                inner.config.provider_region(&m.provider).unwrap_or("untagged"),
+                model_config.name,
+                policy.join(", ")
+            );
+        }
+        permitted
+    });
+    if mappings.is_empty() {
+        return Err(AppError::RoutingError(format!(
+            "No provider for model '{}' satisfies residency policy [{}] (mapped: {})",
+            model_config.name,
+            policy.join(", "),
+            before.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Price of a mapping: `[pricing]`, then the fetched price table
+fn mapping_price(state: &AppState, inner: &ReloadableState, mapping: &ModelMapping) -> Option<ModelPrice> {
+    inner
+        .config
+        .price_for(&mapping.provider, &mapping.actual_model)
+        .cloned()
+        .or_else(|| state.remote_pricing.price_for(&mapping.provider, &mapping.actual_model))
+}
+
+/// On background routes, drop mappings priced above the model's `max_cost_per_mtok`.
+/// Unpriced mappings stay. Fails if no mapping is left.
+fn filter_max_cost(
+    state: &AppState,
+    inner: &ReloadableState,
+    model_config: &ModelConfig,
+    decision: &RouteDecision,
+    mappings: &mut Vec<ModelMapping>,
+) -> Result<(), AppError> {
+    let Some(max_cost) = model_config.max_cost_per_mtok.filter(|_| decision.route_type == RouteType::Background) else {
+        return Ok(());
     };
 
-    // Inject if message has tool results but no text
-    has_tool_results && !has_text
+    let before: Vec<String> = mappings.iter().map(|m| m.provider.clone()).collect();
+    mappings.retain(|m| {
+        let Some(cost) = mapping_price(state, inner, m).map(|p| p.blended_per_mtok()) else {
+            return true;
+        };
+        if cost > max_cost {
+            info!(
+                "💲 Skipping {}/{} for background route: ${:.2}/Mtok is over max_cost_per_mtok ${:.2}",
+                m.provider, m.actual_model, cost, max_cost
+            );
+        }
+        cost <= max_cost
+    });
+    if mappings.is_empty() {
+        return Err(AppError::RoutingError(format!(
+            "No mapping for model '{}' costs at most ${}/Mtok for background requests (mapped: {})",
+            model_config.name,
+            max_cost,
+            before.join(", ")
+        )));
+    }
+    Ok(())
 }
 
-/// Inject continuation text into the last user message
-/// Prepends a text block to the existing message content (doesn't create a new message)
-fn inject_continuation_text(msg: &mut crate::models::Message) {
-    use crate::models::{MessageContent, ContentBlock};
+/// Drop mappings whose spend budget is used up, or reject the request when the
+/// budget says so (or no mapping is left)
+fn filter_budgets(
+    state: &AppState,
+    inner: &ReloadableState,
+    model_config: &ModelConfig,
+    mappings: &mut Vec<ModelMapping>,
+) -> Result<(), AppError> {
+    let budgets = &inner.config.budgets;
+    if budgets.is_empty() {
+        return Ok(());
+    }
 
-    let continuation = "<system-reminder>If you have an active todo list, remember to mark items complete and continue to the next. Do not mention this reminder.</system-reminder>";
+    let mut last_exceeded = None;
+    let mut kept = Vec::with_capacity(mappings.len());
+    for mapping in mappings.drain(..) {
+        match state.budgets.check(budgets, &mapping.provider, &mapping.actual_model) {
+            None => kept.push(mapping),
+            Some(exceeded) if exceeded.budget.on_exceeded == BudgetAction::Reject => {
+                warn!("💸 Rejecting request for model {}: {}", model_config.name, exceeded);
+                return Err(AppError::BudgetExceeded(Box::new(exceeded)));
+            }
+            Some(exceeded) => {
+                warn!("💸 Skipping {}@{}: {}", mapping.actual_model, mapping.provider, exceeded);
+                last_exceeded = Some(exceeded);
+            }
+        }
+    }
+    *mappings = kept;
+
+    match last_exceeded {
+        Some(exceeded) if mappings.is_empty() => Err(AppError::BudgetExceeded(Box::new(exceeded))),
+        _ => Ok(()),
+    }
+}
 
-    match &mut msg.content {
-        MessageContent::Text(text) => {
-            // Convert to Blocks and prepend continuation
-            let original_text = text.clone();
-            msg.content = MessageContent::Blocks(vec![
-                ContentBlock::text(continuation.to_string(), None),
-                ContentBlock::text(original_text, None),
-            ]);
+/// How long to wait before retrying a rate-limited mapping: its Retry-After,
+/// if within server.quota.max_retry_wait_ms
+fn rate_limit_wait(inner: &ReloadableState, error: &ProviderError) -> Option<std::time::Duration> {
+    let cap = std::time::Duration::from_millis(inner.config.server.quota.max_retry_wait_ms);
+    error.retry_after().filter(|wait| !cap.is_zero() && *wait <= cap)
+}
+
+/// Reject a direct-lookup provider the route type may not use
+fn check_route_provider(inner: &ReloadableState, decision: &RouteDecision, provider: &str) -> Result<(), AppError> {
+    match inner.config.router.provider_filter(&decision.route_type.to_string()) {
+        Some(filter) if !filter.permits(provider) => Err(AppError::RoutingError(format!(
+            "Provider '{}' is not allowed for {} route (model '{}')",
+            provider, decision.route_type, decision.model_name
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Reject a direct-lookup provider outside the global residency policy
+fn check_residency(inner: &ReloadableState, decision: &RouteDecision, provider: &str) -> Result<(), AppError> {
+    let policy = inner.config.residency_for(inner.config.find_model(&decision.model_name));
+    if inner.config.residency_permits(policy, provider) {
+        return Ok(());
+    }
+    let region = inner.config.provider_region(provider).unwrap_or("untagged");
+    warn!("🌍 Residency: refusing {} (region {}) for model {}; allowed: {}", provider, region, decision.model_name, policy.join(", "));
+    Err(AppError::RoutingError(format!(
+        "Provider '{}' (region {}) violates residency policy [{}] for model '{}'",
+        provider,
+        region,
+        policy.join(", "),
+        decision.model_name
+    )))
+}
+
+/// Route a request, honouring the X-CCM-Bypass hard pin.
+/// Returns the evaluated steps too when the client sent X-CCM-Explain.
+fn route_request(
+    inner: &ReloadableState,
+    headers: &HeaderMap,
+    request: &mut AnthropicRequest,
+) -> Result<(RouteDecision, Option<Vec<RouteStep>>), AppError> {
+    let bypass = header_flag(headers, "x-ccm-bypass");
+    let explain = header_flag(headers, "x-ccm-explain");
+    let requested_model = request.model.clone();
+
+    let (user_agent, x_app) = client_headers(headers);
+    let router = inner.router.for_client(user_agent, x_app);
+    let result = match (bypass, explain) {
+        (true, false) => router.bypass(request).map(|d| (d, None)),
+        (true, true) => router.bypass(request).map(|d| {
+            let step = RouteStep { step: "bypass", matched: true, reason: "X-CCM-Bypass header set".to_string() };
+            (d, Some(vec![step]))
+        }),
+        (false, false) => router.route(request).map(|d| (d, None)),
+        (false, true) => router.route_explained(request).map(|(d, steps)| (d, Some(steps))),
+    };
+    let (decision, steps) = result.map_err(|e| AppError::RoutingError(e.to_string()))?;
+    access_log::note(|entry| {
+        entry.route = Some(decision.route_type.to_string());
+        entry.model = Some(requested_model);
+        entry.stream = request.stream == Some(true);
+    });
+
+    if let Some(ref steps) = steps {
+        for step in steps {
+            info!("🔎 {:<12} {} {}", step.step, if step.matched { "✓" } else { "·" }, step.reason);
         }
-        MessageContent::Blocks(blocks) => {
-            // Prepend continuation text to existing blocks
-            blocks.insert(0, ContentBlock::text(continuation.to_string(), None));
+    }
+    Ok((decision, steps))
+}
+
+/// Check a boolean request header ("true", "1", "yes")
+fn header_flag(headers: &HeaderMap, name: &str) -> bool {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "true" | "1" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Serialize a non-streaming response, attaching the routing explanation
+/// as a `ccm_explain` extension field when one was requested
+fn json_response<T: serde::Serialize>(
+    body: &T,
+    steps: &Option<Vec<RouteStep>>,
+    decision: &RouteDecision,
+    provider: &str,
+    actual_model: &str,
+) -> Response {
+    let Some(steps) = steps else {
+        return Json(body).into_response();
+    };
+
+    let mut value = serde_json::to_value(body).unwrap_or_default();
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert(
+            "ccm_explain".to_string(),
+            serde_json::json!({
+                "model": decision.model_name,
+                "route_type": decision.route_type.to_string(),
+                "provider": provider,
+                "actual_model": actual_model,
+                "steps": steps,
+            }),
+        );
+    }
+    Json(value).into_response()
+}
+
+/// Extract client identification headers (User-Agent, x-app) for per-client routing
+fn client_headers(headers: &HeaderMap) -> (Option<&str>, Option<&str>) {
+    let get = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    (get("user-agent"), get("x-app"))
+}
+
+/// Read and validate the client's anthropic-version header
+fn client_anthropic_version(headers: &HeaderMap) -> Result<Option<String>, AppError> {
+    let Some(value) = headers.get("anthropic-version") else {
+        return Ok(None);
+    };
+
+    let version = value
+        .to_str()
+        .map_err(|_| AppError::InvalidRequest("anthropic-version header is not valid ASCII".to_string()))?
+        .trim();
+
+    crate::models::validate_anthropic_version(version).map_err(AppError::InvalidRequest)?;
+    Ok(Some(version.to_string()))
+}
+
+/// Run the guardrail secret scanner for a provider
+fn guard_secrets(inner: &ReloadableState, provider: &str, request: &mut AnthropicRequest) -> Result<(), AppError> {
+    inner.guardrails.apply(provider, request).map_err(|blocked| {
+        AppError::InvalidRequest(format!(
+            "Request blocked by guardrails: possible secrets detected ({}) in content headed to provider '{}'",
+            blocked.rules.join(", "),
+            provider
+        ))
+    })
+}
+
+/// Run the guardrail secret scanner over a count_tokens request's system prompt and messages
+fn guard_count_secrets(
+    inner: &ReloadableState,
+    provider: &str,
+    request: &mut crate::models::CountTokensRequest,
+) -> Result<(), AppError> {
+    let mut scanned = AnthropicRequest {
+        model: request.model.clone(),
+        messages: std::mem::take(&mut request.messages),
+        max_tokens: 1,
+        system: request.system.take(),
+        tools: None,
+        anthropic_version: None,
+        extra_body: None,
+        thinking: None,
+        temperature: None,
+        top_p: None,
+        top_k: None,
+        stop_sequences: None,
+        stream: None,
+        metadata: None,
+    };
+    let result = guard_secrets(inner, provider, &mut scanned);
+    request.messages = scanned.messages;
+    request.system = scanned.system;
+    result
+}
+
+/// Point a routed request at a mapping: its actual model and `extra_body`, plus the
+/// mapping's continuation prompt and identity note
+pub(crate) fn apply_mapping(request: &mut AnthropicRequest, mapping: &ModelMapping, route_type: RouteType) {
+    request.model = mapping.actual_model.clone();
+    request.extra_body = mapping.extra_body.clone();
+
+    // Inject continuation prompt if configured (skip for background/suggest/bypass)
+    if let Some(config) = mapping.continuation() {
+        if !matches!(route_type, RouteType::Background | RouteType::Suggest | RouteType::Bypass)
+            && continuation::inject(&mut request.messages, &config)
+        {
+            info!("💉 Injecting continuation prompt for model: {}", mapping.actual_model);
         }
     }
+
+    if identity::inject(&mut request.system, mapping) {
+        debug!("🪪 Added identity note for {}/{}", mapping.provider, mapping.actual_model);
+    }
+}
+
+/// Build the upstream request for one mapping from the routed request: actual model,
+/// continuation prompt, identity note, capability stripping and inlined images
+async fn mapping_request(
+    state: &AppState,
+    inner: &ReloadableState,
+    routed: &AnthropicRequest,
+    mapping: &ModelMapping,
+    route_type: RouteType,
+    inlined_images: &mut images::InlinedImages,
+) -> Result<AnthropicRequest, AppError> {
+    let mut anthropic_request = routed.clone();
+    apply_mapping(&mut anthropic_request, mapping, route_type);
+
+    // Strip features this provider/model is known to reject
+    state.capabilities.apply(&mapping.provider, &mapping.actual_model, &mut anthropic_request);
+
+    // Redact secrets, or refuse to send them, before the prompt leaves for the provider
+    guard_secrets(inner, &mapping.provider, &mut anthropic_request)?;
+
+    // Describe tools in the prompt for models without function calling
+    if mapping.prompt_tools && prompt_tools::embed_tools(&mut anthropic_request) {
+        debug!("🧰 Embedded tools in the system prompt for {}", mapping.actual_model);
+    }
+
+    // Fetch image URLs for providers that only take base64 data
+    if let Some(provider) = inner.provider_registry.get_provider(&mapping.provider) {
+        inlined_images.inline_for(provider.as_ref().as_ref(), &mut anthropic_request).await?;
+    }
+    Ok(anthropic_request)
 }
 
 /// Handle /v1/messages requests (both streaming and non-streaming)
@@ -618,12 +1820,17 @@ async fn handle_messages(
         .unwrap_or("unknown");
     let start_time = std::time::Instant::now();
 
+    let _in_flight = state.in_flight.enter();
+
     // Get snapshot of reloadable state
     let inner = state.snapshot();
 
     // Generate trace ID for correlating request/response
     let trace_id = state.message_tracer.new_trace_id();
 
+    // Client's API version is passed through to Anthropic-compatible upstreams
+    let anthropic_version = client_anthropic_version(&headers)?;
+
     // DEBUG: Log request body for debugging
     if let Ok(json_str) = serde_json::to_string_pretty(&request_json) {
         tracing::debug!("📥 Incoming request body:\n{}", json_str);
@@ -641,76 +1848,83 @@ async fn handle_messages(
             AppError::ParseError(format!("Invalid request format: {}", e))
         })?;
 
+    // Relate this request to earlier ones (client retries, rejected tool calls)
+    let conversation = Conversation::new(&request_for_routing.messages);
+    state.quality.observe_request(&conversation);
+
     // 2. Route the request (may modify system prompt to remove CCM-SUBAGENT-MODEL tag)
-    let decision = inner
-        .router
-        .route(&mut request_for_routing)
-        .map_err(|e| AppError::RoutingError(e.to_string()))?;
+    let (decision, explain_steps) = route_request(&inner, &headers, &mut request_for_routing)?;
 
     // 3. Try model mappings with fallback (1:N mapping)
-    if let Some(model_config) = inner.config.models.iter().find(|m| m.name.eq_ignore_ascii_case(&decision.model_name)) {
-
-        // Check for X-Provider header to override priority
-        let forced_provider = headers
-            .get("x-provider")
-            .and_then(|v| v.to_str().ok())
-            .filter(|s| !s.is_empty())  // Ignore empty strings
-            .map(|s| s.to_string());
+    // X-Provider header overrides priority (and direct lookup for unmapped models)
+    let forced_provider = forced_provider(&headers);
 
+    if let Some(model_config) = resolve_model_config(&inner, &decision.model_name, forced_provider.as_deref()) {
         if let Some(ref provider_name) = forced_provider {
             info!("🎯 Using forced provider from X-Provider header: {}", provider_name);
         }
 
-        // Sort mappings by priority (or filter by forced provider)
-        let mut sorted_mappings = model_config.mappings.clone();
+        let sorted_mappings = mapping_chain(
+            &state, &inner, &model_config, &decision, model, forced_provider.as_deref(), true,
+        )?;
 
-        if let Some(ref provider_name) = forced_provider {
-            // Filter to only the specified provider
-            sorted_mappings.retain(|m| m.provider == *provider_name);
-            if sorted_mappings.is_empty() {
-                return Err(AppError::RoutingError(format!(
-                    "Provider '{}' not found in mappings for model '{}'",
-                    provider_name, decision.model_name
-                )));
-            }
-        } else {
-            // Use priority ordering
-            sorted_mappings.sort_by_key(|m| m.priority);
-        }
+        // Parse request as Anthropic format, with the routing modifications
+        // (system prompt, messages, think keyword budget)
+        let mut routed_request: AnthropicRequest = serde_json::from_value(request_json.clone())
+            .map_err(|e| AppError::ParseError(format!("Invalid request format: {}", e)))?;
+        routed_request.anthropic_version = anthropic_version.clone();
+        routed_request.system = request_for_routing.system.clone();
+        routed_request.messages = request_for_routing.messages.clone();
+        routed_request.thinking = request_for_routing.thinking.clone();
 
         // Try each mapping in priority order (or just the forced one)
-        for (idx, mapping) in sorted_mappings.iter().enumerate() {
+        let mut last_error: Option<ProviderError> = None;
+        // Why the last mapping that couldn't take the request was skipped (e.g. blocked by guardrails)
+        let mut skipped: Option<AppError> = None;
+        // Images are fetched once, for the first mapping whose provider needs them inline
+        let mut inlined_images = images::InlinedImages::new();
+        let mut hedged_through = 0;
+        let mut attempts: VecDeque<usize> = (0..sorted_mappings.len()).collect();
+        let mut waited_for_rate_limit = false;
+        while let Some(idx) = attempts.pop_front() {
+            let mapping = &sorted_mappings[idx];
+            // Already raced as the hedge of the first mapping
+            if idx > 0 && idx <= hedged_through {
+                continue;
+            }
+
             // Try to get provider from registry
             if let Some(provider) = inner.provider_registry.get_provider(&mapping.provider) {
                 // Trust the model mapping configuration - no need to validate
-
-                // Parse request as Anthropic format
-                let mut anthropic_request: AnthropicRequest = serde_json::from_value(request_json.clone())
-                    .map_err(|e| AppError::ParseError(format!("Invalid request format: {}", e)))?;
+                let anthropic_request = mapping_request(
+                    &state, &inner, &routed_request, mapping, decision.route_type, &mut inlined_images,
+                )
+                .await;
+                let mut anthropic_request = match anthropic_request {
+                    Ok(request) => request,
+                    Err(e) => {
+                        info!("⚠️ Skipping {}/{}: {}", mapping.provider, mapping.actual_model, e);
+                        skipped = Some(e);
+                        continue;
+                    }
+                };
 
                 // Save original model name for response
-                let original_model = anthropic_request.model.clone();
+                let original_model = model.to_string();
 
-                // Update model to actual model name
-                anthropic_request.model = mapping.actual_model.clone();
-
-                // Apply routing modifications (system prompt, messages)
-                anthropic_request.system = request_for_routing.system.clone();
-                anthropic_request.messages = request_for_routing.messages.clone();
-
-                // Inject continuation prompt if configured (skip for background tasks)
-                if mapping.inject_continuation_prompt && decision.route_type != RouteType::Background {
-                    if let Some(last_msg) = anthropic_request.messages.last_mut() {
-                        if should_inject_continuation(last_msg) {
-                            info!("💉 Injecting continuation prompt for model: {}", mapping.actual_model);
-                            inject_continuation_text(last_msg);
-                        }
-                    }
-                }
+                // Prime the prompt cache on the session's first request to this provider/model
+                warmup::warm_prompt_cache(&inner, &provider, mapping, &anthropic_request).await;
 
                 // Check if streaming is requested
                 let is_streaming = anthropic_request.stream == Some(true);
 
+                // Providers with unreliable streaming (or prompt-embedded tools, which are parsed from the
+                // complete response) are called non-streaming and the SSE stream is synthesized
+                let synthesize_stream = is_streaming && (mapping.force_non_streaming || mapping.prompt_tools);
+                if synthesize_stream {
+                    anthropic_request.stream = Some(false);
+                }
+
                 // Build retry indicator (only show if not first attempt)
                 let retry_info = if idx > 0 {
                     format!(" [{}/{}]", idx + 1, sorted_mappings.len())
@@ -753,18 +1967,84 @@ async fn handle_messages(
                     is_streaming,
                 );
 
+                // Capture prompt for dataset recording (if enabled for this route)
+                let dataset_sample = state.dataset_recorder.prepare(&anthropic_request, &decision.route_type);
+
                 // Write routing info immediately on first attempt
                 if idx == 0 {
-                    write_routing_info(&mapping.actual_model, &mapping.provider, &decision.route_type);
+                    state.routing_info.record(&mapping.actual_model, &mapping.provider, &decision.route_type);
                 }
 
-                if is_streaming {
+                if is_streaming && !synthesize_stream {
                     // Streaming request
-                    match provider.send_message_stream(anthropic_request).await {
+                    let attempt_start = std::time::Instant::now();
+                    let hedge = if idx == 0 && forced_provider.is_none() && inner.config.server.hedging.applies_to(&decision.route_type.to_string()) {
+                        sorted_mappings.get(1).filter(|m| !m.force_non_streaming && !m.prompt_tools).and_then(|m| {
+                            Some((m, inner.provider_registry.get_provider(&m.provider)?))
+                        })
+                    } else {
+                        None
+                    };
+
+                    let (mapping, result, elapsed) = match hedge {
+                        Some((hedge_mapping, hedge_provider)) => {
+                            // Built only if the hedge fires; a mapping that can't take the request isn't hedged with
+                            let hedge_label = format!("{}/{}", hedge_mapping.provider, hedge_mapping.actual_model);
+                            let hedge_attempt = async {
+                                let hedge_request = mapping_request(
+                                    &state, &inner, &routed_request, hedge_mapping, decision.route_type, &mut inlined_images,
+                                )
+                                .await;
+                                match hedge_request {
+                                    Ok(request) => Some(hedge::Attempt { label: hedge_label, provider: &hedge_provider, request }),
+                                    Err(e) => {
+                                        info!("⚠️ Not hedging with {}: {}", hedge_label, e);
+                                        None
+                                    }
+                                }
+                            };
+                            let raced = hedge::race(
+                                hedge::Attempt { label: format!("{}/{}", mapping.provider, mapping.actual_model), provider: &provider, request: anthropic_request },
+                                hedge_attempt,
+                                std::time::Duration::from_millis(inner.config.server.hedging.delay_ms),
+                            )
+                            .await;
+                            if raced.hedged {
+                                hedged_through = 1;
+                            }
+                            if let Some((index, e, elapsed)) = raced.other_failure {
+                                let failed = &sorted_mappings[index];
+                                state.message_tracer.trace_error(&trace_id, &e.to_string());
+                                state.capabilities.record_failure(&failed.provider, &failed.actual_model, &e);
+                                state.circuit_breaker.record_failure(&failed.provider, &failed.actual_model, &e);
+                                state.model_warnings.record_error(&failed.provider, &failed.actual_model, &e);
+                                state.quota.record_rate_limit(&failed.provider, &e);
+                                state.health.record(&failed.provider, &failed.actual_model, false, elapsed);
+                                info!("⚠️ Provider {} streaming failed: {}", failed.provider, e);
+                            }
+                            (&sorted_mappings[raced.index], raced.result, raced.elapsed)
+                        }
+                        None => {
+                            let result = provider.send_message_stream(anthropic_request).await;
+                            (mapping, result, attempt_start.elapsed())
+                        }
+                    };
+
+                    match result {
                         Ok(stream_response) => {
-                            // Write routing info on fallback success (idx==0 already wrote above)
-                            if idx > 0 {
-                                write_routing_info(&mapping.actual_model, &mapping.provider, &decision.route_type);
+                            state.circuit_breaker.record_success(&mapping.provider, &mapping.actual_model);
+                            state.model_warnings.record_success(&mapping.provider, &mapping.actual_model);
+                            state.health.record(&mapping.provider, &mapping.actual_model, true, elapsed);
+                            state.quota.observe(&mapping.provider, &stream_response.rate_limits);
+                            state.quality.record_served(&conversation, &mapping.provider, &mapping.actual_model);
+                            state.recent_routes.record(
+                                model, &decision.route_type, &mapping.provider, &mapping.actual_model,
+                                start_time.elapsed().as_millis() as u64,
+                            );
+
+                            // Write routing info on fallback or hedge success (the first mapping already wrote above)
+                            if !std::ptr::eq(mapping, &sorted_mappings[0]) {
+                                state.routing_info.record(&mapping.actual_model, &mapping.provider, &decision.route_type);
                             }
 
                             // Convert provider stream to HTTP response
@@ -772,10 +2052,48 @@ async fn handle_messages(
                             // We pass them through as-is without wrapping
                             let body_stream = stream_response.stream.map_err(|e| {
                                 error!("Stream error: {}", e);
-                                std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+                                std::io::Error::other(e.to_string())
+                            });
+                            let (error_state, error_mapping, error_trace_id) = (state.clone(), mapping.clone(), trace_id.clone());
+                            let body_stream = StreamErrorWatch::new(
+                                body_stream,
+                                inner.config.server.convert_stream_errors,
+                                move |event: &StreamErrorEvent| {
+                                    warn!(
+                                        "⚠️ {}@{} sent {} mid-stream: {}",
+                                        error_mapping.actual_model, error_mapping.provider, event.error_type, event.message
+                                    );
+                                    let error = ProviderError::from_status(
+                                        event.status(),
+                                        None,
+                                        format!("{}: {}", event.error_type, event.message),
+                                    );
+                                    error_state.message_tracer.trace_error(&error_trace_id, &error.to_string());
+                                    error_state.circuit_breaker.record_failure(&error_mapping.provider, &error_mapping.actual_model, &error);
+                                    error_state.health.record_stream_error(
+                                        &error_mapping.provider, &error_mapping.actual_model, start_time.elapsed(),
+                                    );
+                                },
+                            );
+                            let usage_state = state.clone();
+                            let (usage_model, usage_mapping, usage_route) = (model.to_string(), mapping.clone(), decision.route_type);
+                            let body_stream = UsageStream::new(body_stream, move |usage: &Usage| {
+                                let latency_ms = start_time.elapsed().as_millis() as u64;
+                                let cost = record_usage(&usage_state, &usage_model, &usage_mapping, &usage_route, usage, latency_ms, true);
+                                if let Some(cost) = cost {
+                                    info!(
+                                        "💰 {}@{}{} (total ${:.2})",
+                                        usage_mapping.actual_model, usage_mapping.provider, cost_info(Some(cost)), usage_state.costs.total_cost()
+                                    );
+                                }
                             });
 
-                            let body = Body::from_stream(body_stream);
+                            let body_stream = state.live.watch(body_stream, &mapping.actual_model, &mapping.provider, decision.route_type);
+
+                            let body = match dataset_sample {
+                                Some(sample) => Body::from_stream(DatasetStream::new(body_stream, state.dataset_recorder.clone(), sample)),
+                                None => Body::from_stream(body_stream),
+                            };
                             let mut response_builder = Response::builder()
                                 .status(200)
                                 .header("Content-Type", "text/event-stream")
@@ -793,14 +2111,41 @@ async fn handle_messages(
                         }
                         Err(e) => {
                             state.message_tracer.trace_error(&trace_id, &e.to_string());
+                            state.capabilities.record_failure(&mapping.provider, &mapping.actual_model, &e);
+                            state.circuit_breaker.record_failure(&mapping.provider, &mapping.actual_model, &e);
+                            state.model_warnings.record_error(&mapping.provider, &mapping.actual_model, &e);
+                            state.quota.record_rate_limit(&mapping.provider, &e);
+                            state.health.record(&mapping.provider, &mapping.actual_model, false, elapsed);
+                            if std::ptr::eq(mapping, &sorted_mappings[idx]) && !waited_for_rate_limit {
+                                if let Some(wait) = rate_limit_wait(&inner, &e) {
+                                    info!("⏳ Provider {} rate limited, retrying in {}ms", mapping.provider, wait.as_millis());
+                                    tokio::time::sleep(wait).await;
+                                    waited_for_rate_limit = true;
+                                    attempts.push_front(idx);
+                                    last_error = Some(e);
+                                    continue;
+                                }
+                            }
                             info!("⚠️ Provider {} streaming failed: {}, trying next fallback", mapping.provider, e);
+                            last_error = Some(e);
                             continue;
                         }
                     }
                 } else {
                     // Non-streaming request (original behavior)
-                    match provider.send_message(anthropic_request).await {
+                    let attempt_start = std::time::Instant::now();
+                    match send_non_streaming(provider.as_ref().as_ref(), mapping, anthropic_request).await {
                         Ok(mut response) => {
+                            state.circuit_breaker.record_success(&mapping.provider, &mapping.actual_model);
+                            state.model_warnings.record_success(&mapping.provider, &mapping.actual_model);
+                            state.health.record(&mapping.provider, &mapping.actual_model, true, attempt_start.elapsed());
+                            state.quota.observe(&mapping.provider, &response.rate_limits);
+                            if mapping.prompt_tools {
+                                prompt_tools::extract_tool_calls(&mut response);
+                            }
+                            state.quality.record_served(&conversation, &mapping.provider, &mapping.actual_model);
+                            state.quality.record_response(&mapping.provider, &mapping.actual_model, &response);
+
                             // Restore original model name in response
                             response.model = original_model;
                             info!("✅ Request succeeded with provider: {}, response model: {}", mapping.provider, response.model);
@@ -808,21 +2153,48 @@ async fn handle_messages(
                             // Calculate and log metrics
                             let latency_ms = start_time.elapsed().as_millis() as u64;
                             let tok_s = (response.usage.output_tokens as f32 * 1000.0) / latency_ms as f32;
-                            info!("📊 {}@{} {}ms {:.0}t/s {}tok", mapping.actual_model, mapping.provider, latency_ms, tok_s, response.usage.output_tokens);
+                            let cost = record_usage(&state, model, mapping, &decision.route_type, &response.usage, latency_ms, false);
+                            info!("📊 {}@{} {}ms {:.0}t/s {}tok{}{}", mapping.actual_model, mapping.provider, latency_ms, tok_s, response.usage.output_tokens, response.usage.cache_info(), cost_info(cost));
+                            state.recent_routes.record(model, &decision.route_type, &mapping.provider, &mapping.actual_model, latency_ms);
 
                             // Trace the response
                             state.message_tracer.trace_response(&trace_id, &response, latency_ms);
 
+                            if let Some(sample) = dataset_sample {
+                                state.dataset_recorder.record(sample, &response.content);
+                            }
+
                             // Write routing info on fallback success (idx==0 already wrote above)
                             if idx > 0 {
-                                write_routing_info(&mapping.actual_model, &mapping.provider, &decision.route_type);
+                                state.routing_info.record(&mapping.actual_model, &mapping.provider, &decision.route_type);
+                            }
+
+                            if synthesize_stream {
+                                debug!("🧵 Synthesizing SSE stream for {}@{}", mapping.actual_model, mapping.provider);
+                                return Ok(synthesized_stream_response(&response, &inner.config.server.synthetic_stream));
                             }
 
-                            return Ok(Json(response).into_response());
+                            return Ok(json_response(&response, &explain_steps, &decision, &mapping.provider, &mapping.actual_model));
                         }
                         Err(e) => {
                             state.message_tracer.trace_error(&trace_id, &e.to_string());
+                            state.capabilities.record_failure(&mapping.provider, &mapping.actual_model, &e);
+                            state.circuit_breaker.record_failure(&mapping.provider, &mapping.actual_model, &e);
+                            state.model_warnings.record_error(&mapping.provider, &mapping.actual_model, &e);
+                            state.quota.record_rate_limit(&mapping.provider, &e);
+                            state.health.record(&mapping.provider, &mapping.actual_model, false, attempt_start.elapsed());
+                            if !waited_for_rate_limit {
+                                if let Some(wait) = rate_limit_wait(&inner, &e) {
+                                    info!("⏳ Provider {} rate limited, retrying in {}ms", mapping.provider, wait.as_millis());
+                                    tokio::time::sleep(wait).await;
+                                    waited_for_rate_limit = true;
+                                    attempts.push_front(idx);
+                                    last_error = Some(e);
+                                    continue;
+                                }
+                            }
                             info!("⚠️ Provider {} failed: {}, trying next fallback", mapping.provider, e);
+                            last_error = Some(e);
                             continue;
                         }
                     }
@@ -834,14 +2206,19 @@ async fn handle_messages(
         }
 
         error!("❌ All provider mappings failed for model: {}", decision.model_name);
-        return Err(AppError::ProviderError(format!(
-            "All {} provider mappings failed for model: {}",
-            sorted_mappings.len(),
-            decision.model_name
-        )));
+        // No provider was called: report why the request couldn't be sent
+        if let (None, Some(skipped)) = (&last_error, skipped) {
+            return Err(skipped);
+        }
+        Err(AppError::upstream(
+            format!("All {} provider mappings failed for model: {}", sorted_mappings.len(), decision.model_name),
+            last_error,
+        ))
     } else {
         // No model mapping found, try direct provider registry lookup (backward compatibility)
-        if let Ok(provider) = inner.provider_registry.get_provider_for_model(&decision.model_name) {
+        if let Some((provider_name, provider)) = direct_provider(&inner, forced_provider.as_deref(), &decision.model_name)? {
+            check_route_provider(&inner, &decision, &provider_name)?;
+            check_residency(&inner, &decision, &provider_name)?;
             info!("📦 Using provider from registry (direct lookup): {}", decision.model_name);
 
             // Parse request as Anthropic format
@@ -853,34 +2230,58 @@ async fn handle_messages(
 
             // Update model to routed model
             anthropic_request.model = decision.model_name.clone();
+            anthropic_request.anthropic_version = anthropic_version.clone();
 
-            // Apply routing modifications (system prompt, messages)
+            // Apply routing modifications (system prompt, messages, think keyword budget)
             anthropic_request.system = request_for_routing.system.clone();
             anthropic_request.messages = request_for_routing.messages.clone();
+            anthropic_request.thinking = request_for_routing.thinking.clone();
+            guard_secrets(&inner, &provider_name, &mut anthropic_request)?;
+            images::inline_for(provider.as_ref().as_ref(), &mut anthropic_request).await?;
 
             // Call provider
             let mut provider_response = provider.send_message(anthropic_request)
                 .await
-                .map_err(|e| AppError::ProviderError(e.to_string()))?;
+                .map_err(|e| AppError::upstream(format!("{} failed", decision.model_name), Some(e)))?;
 
             // Restore original model name in response
             provider_response.model = original_model;
+            state.recent_routes.record(
+                model, &decision.route_type, "direct", &decision.model_name,
+                start_time.elapsed().as_millis() as u64,
+            );
 
             // Return provider response
-            return Ok(Json(provider_response).into_response());
+            return Ok(json_response(&provider_response, &explain_steps, &decision, "direct", &decision.model_name));
         }
 
         error!("❌ No model mapping or provider found for model: {}", decision.model_name);
-        return Err(AppError::ProviderError(format!(
+        Err(AppError::ProviderError(format!(
             "No model mapping or provider found for model: {}",
             decision.model_name
-        )));
+        )))
     }
 }
 
+/// Local estimate for a token count the provider didn't answer within
+/// `count_tokens_timeout_ms`, so the client isn't kept waiting on a slow provider
+async fn estimated_count(request: AnthropicRequest, provider: &str, error: &ProviderError) -> Response {
+    info!("🧮 {}: {}, counting tokens locally", provider, error);
+    local_count(request).await
+}
+
+/// Token count from the local tokenizer (CPU-bound on large contexts, so off the async workers)
+async fn local_count(request: AnthropicRequest) -> Response {
+    let input_tokens = tokio::task::spawn_blocking(move || token_count::count(&request))
+        .await
+        .unwrap_or_default();
+    Json(crate::models::CountTokensResponse { input_tokens }).into_response()
+}
+
 /// Handle /v1/messages/count_tokens requests
 async fn handle_count_tokens(
     State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
     Json(request_json): Json<serde_json::Value>,
 ) -> Result<Response, AppError> {
     let model = request_json.get("model").and_then(|m| m.as_str()).unwrap_or("unknown");
@@ -891,8 +2292,9 @@ async fn handle_count_tokens(
 
     // 1. Parse as CountTokensRequest first
     use crate::models::CountTokensRequest;
-    let count_request: CountTokensRequest = serde_json::from_value(request_json.clone())
+    let mut count_request: CountTokensRequest = serde_json::from_value(request_json.clone())
         .map_err(|e| AppError::ParseError(format!("Invalid count_tokens request format: {}", e)))?;
+    count_request.anthropic_version = client_anthropic_version(&headers)?;
 
     // 2. Create a minimal AnthropicRequest for routing
     let mut routing_request = AnthropicRequest {
@@ -901,6 +2303,8 @@ async fn handle_count_tokens(
         max_tokens: 1024, // Dummy value for routing
         system: count_request.system.clone(),
         tools: count_request.tools.clone(),
+        anthropic_version: None,
+        extra_body: None,
         thinking: None,
         temperature: None,
         top_p: None,
@@ -909,10 +2313,10 @@ async fn handle_count_tokens(
         stream: None,
         metadata: None,
     };
-    let decision = inner
-        .router
-        .route(&mut routing_request)
-        .map_err(|e| AppError::RoutingError(e.to_string()))?;
+    if inner.config.server.count_tokens == CountTokensMode::Local {
+        return Ok(local_count(routing_request).await);
+    }
+    let (decision, _) = route_request(&inner, &headers, &mut routing_request)?;
 
     debug!(
         "🧮 Routed count_tokens: {} → {} ({})",
@@ -920,14 +2324,23 @@ async fn handle_count_tokens(
     );
 
     // 3. Try model mappings with fallback (1:N mapping)
-    if let Some(model_config) = inner.config.models.iter().find(|m| m.name.eq_ignore_ascii_case(&decision.model_name)) {
+    let forced_provider = forced_provider(&headers);
+    if let Some(model_config) = resolve_model_config(&inner, &decision.model_name, forced_provider.as_deref()) {
         debug!("📋 Found {} provider mappings for token counting: {}", model_config.mappings.len(), decision.model_name);
 
         // Sort mappings by priority
         let mut sorted_mappings = model_config.mappings.clone();
+        for mapping in &mut sorted_mappings {
+            mapping.actual_model = mapping.resolve_actual_model(model);
+        }
         sorted_mappings.sort_by_key(|m| m.priority);
+        filter_route_providers(&inner, &decision, &mut sorted_mappings)?;
+        filter_residency(&inner, &model_config, &mut sorted_mappings)?;
 
         // Try each mapping in priority order
+        let mut last_error: Option<ProviderError> = None;
+        // Why the last mapping that couldn't take the request was skipped (blocked by guardrails)
+        let mut skipped: Option<AppError> = None;
         for (idx, mapping) in sorted_mappings.iter().enumerate() {
             debug!(
                 "🔄 Trying token count mapping {}/{}: provider={}, actual_model={}",
@@ -944,6 +2357,11 @@ async fn handle_count_tokens(
                 // Update model to actual model name
                 let mut count_request_for_provider = count_request.clone();
                 count_request_for_provider.model = mapping.actual_model.clone();
+                if let Err(e) = guard_count_secrets(&inner, &mapping.provider, &mut count_request_for_provider) {
+                    debug!("⚠️ Skipping {}/{}: {}", mapping.provider, mapping.actual_model, e);
+                    skipped = Some(e);
+                    continue;
+                }
 
                 // Call provider's count_tokens
                 match provider.count_tokens(count_request_for_provider).await {
@@ -951,8 +2369,12 @@ async fn handle_count_tokens(
                         debug!("✅ Token count succeeded with provider: {}", mapping.provider);
                         return Ok(Json(response).into_response());
                     }
+                    Err(e @ ProviderError::Timeout { .. }) => {
+                        return Ok(estimated_count(routing_request, &mapping.provider, &e).await);
+                    }
                     Err(e) => {
                         debug!("⚠️ Provider {} failed: {}, trying next fallback", mapping.provider, e);
+                        last_error = Some(e);
                         continue;
                     }
                 }
@@ -963,70 +2385,124 @@ async fn handle_count_tokens(
         }
 
         error!("❌ All provider mappings failed for token counting: {}", decision.model_name);
-        return Err(AppError::ProviderError(format!(
-            "All {} provider mappings failed for token counting: {}",
-            sorted_mappings.len(),
-            decision.model_name
-        )));
+        if let (None, Some(skipped)) = (&last_error, skipped) {
+            return Err(skipped);
+        }
+        Err(AppError::upstream(
+            format!("All {} provider mappings failed for token counting: {}", sorted_mappings.len(), decision.model_name),
+            last_error,
+        ))
     } else {
         // No model mapping found, try direct provider registry lookup (backward compatibility)
-        if let Ok(provider) = inner.provider_registry.get_provider_for_model(&decision.model_name) {
+        if let Some((provider_name, provider)) = direct_provider(&inner, forced_provider.as_deref(), &decision.model_name)? {
+            check_route_provider(&inner, &decision, &provider_name)?;
+            check_residency(&inner, &decision, &provider_name)?;
             debug!("📦 Using provider from registry (direct lookup) for token counting: {}", decision.model_name);
 
             // Update model to routed model
             let mut count_request_for_provider = count_request.clone();
             count_request_for_provider.model = decision.model_name.clone();
+            guard_count_secrets(&inner, &provider_name, &mut count_request_for_provider)?;
 
             // Call provider's count_tokens
-            let response = provider.count_tokens(count_request_for_provider)
-                .await
-                .map_err(|e| AppError::ProviderError(e.to_string()))?;
+            let response = match provider.count_tokens(count_request_for_provider).await {
+                Err(e @ ProviderError::Timeout { .. }) => {
+                    return Ok(estimated_count(routing_request, &provider_name, &e).await);
+                }
+                result => result.map_err(|e| AppError::upstream(format!("{} failed", decision.model_name), Some(e)))?,
+            };
 
             debug!("✅ Token count completed via provider");
             return Ok(Json(response).into_response());
         }
 
         error!("❌ No model mapping or provider found for token counting: {}", decision.model_name);
-        return Err(AppError::ProviderError(format!(
+        Err(AppError::ProviderError(format!(
             "No model mapping or provider found for token counting: {}",
             decision.model_name
-        )));
+        )))
     }
 }
 
 /// Application error types
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum AppError {
+    InvalidRequest(String),
+    /// Missing or wrong client API key (`[server.auth]`)
+    Unauthorized(String),
     RoutingError(String),
     ParseError(String),
     ProviderError(String),
+    /// Every mapping is over its spend budget; retry once the budget period resets
+    BudgetExceeded(Box<BudgetExceeded>),
+    /// Upstream failure; status, error type and Retry-After come from the provider error
+    UpstreamError { message: String, source: ProviderError },
+}
+
+impl AppError {
+    /// Wrap the last provider error, or a plain 502 if no provider was tried
+    fn upstream(message: String, source: Option<ProviderError>) -> Self {
+        match source {
+            Some(source) => AppError::UpstreamError { message, source },
+            None => AppError::ProviderError(message),
+        }
+    }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, message) = match self {
-            AppError::RoutingError(msg) => (StatusCode::BAD_REQUEST, msg),
-            AppError::ParseError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-            AppError::ProviderError(msg) => (StatusCode::BAD_GATEWAY, msg),
+        let (status, error_type, message, retry_after) = match self {
+            AppError::InvalidRequest(msg) => (StatusCode::BAD_REQUEST, "invalid_request_error", msg, None),
+            AppError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, "authentication_error", msg, None),
+            AppError::RoutingError(msg) => (StatusCode::BAD_REQUEST, "invalid_request_error", msg, None),
+            AppError::ParseError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, "api_error", msg, None),
+            AppError::ProviderError(msg) => (StatusCode::BAD_GATEWAY, "api_error", msg, None),
+            AppError::BudgetExceeded(exceeded) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limit_error",
+                format!("Spend budget exceeded: {}", exceeded),
+                (exceeded.resets_at - chrono::Utc::now()).to_std().ok(),
+            ),
+            AppError::UpstreamError { message, source } => {
+                let (status, error_type) = source.client_error();
+                (
+                    StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY),
+                    error_type,
+                    format!("{}: {}", message, source),
+                    source.retry_after(),
+                )
+            }
         };
 
         let body = Json(serde_json::json!({
+            "type": "error",
             "error": {
-                "type": "error",
+                "type": error_type,
                 "message": message
             }
         }));
 
-        (status, body).into_response()
+        let mut response = (status, body).into_response();
+        if let Some(retry_after) = retry_after {
+            // Round up so clients never retry early
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response.headers_mut().insert("retry-after", secs.into());
+        }
+        response
     }
 }
 
 impl std::fmt::Display for AppError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            AppError::InvalidRequest(msg) => write!(f, "Invalid request: {}", msg),
+            AppError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
             AppError::RoutingError(msg) => write!(f, "Routing error: {}", msg),
             AppError::ParseError(msg) => write!(f, "Parse error: {}", msg),
             AppError::ProviderError(msg) => write!(f, "Provider error: {}", msg),
+            AppError::BudgetExceeded(exceeded) => write!(f, "Spend budget exceeded: {}", exceeded),
+            AppError::UpstreamError { message, source } => write!(f, "Provider error: {}: {}", message, source),
         }
     }
 }
@@ -0,0 +1,149 @@
+//! `GET /v1/models`: the model names the mux answers to, in OpenAI or Anthropic
+//! list format, so clients can enumerate them instead of hardcoding names.
+
+use crate::cli::AppConfig;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+
+/// One model name clients can request, and where it goes
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListedModel {
+    pub id: String,
+    /// "ccm" for `[[models]]` entries, otherwise the provider that lists the model
+    pub owned_by: String,
+    /// (provider, actual model) in priority order
+    pub mappings: Vec<(String, String)>,
+}
+
+/// `[[models]]` entries first, then models enabled providers list directly
+pub fn list_models(config: &AppConfig) -> Vec<ListedModel> {
+    let mut models: Vec<ListedModel> = config
+        .models
+        .iter()
+        .map(|model| {
+            let mut mappings: Vec<_> = model.mappings.iter().collect();
+            mappings.sort_by_key(|m| m.priority);
+            ListedModel {
+                id: model.name.clone(),
+                owned_by: "ccm".to_string(),
+                mappings: mappings.iter().map(|m| (m.provider.clone(), m.actual_model.clone())).collect(),
+            }
+        })
+        .collect();
+
+    for provider in config.providers.iter().filter(|p| p.is_enabled()) {
+        for name in &provider.models {
+            let mapping = (provider.name.clone(), name.clone());
+            match models.iter_mut().find(|m| m.id.eq_ignore_ascii_case(name)) {
+                // Mapped names are routed by their mappings, not by provider lists
+                Some(listed) if listed.owned_by == "ccm" => {}
+                Some(listed) => listed.mappings.push(mapping),
+                None => models.push(ListedModel { id: name.clone(), owned_by: provider.name.clone(), mappings: vec![mapping] }),
+            }
+        }
+    }
+    models
+}
+
+fn mappings_json(model: &ListedModel) -> Value {
+    model
+        .mappings
+        .iter()
+        .map(|(provider, actual_model)| json!({"provider": provider, "actual_model": actual_model}))
+        .collect()
+}
+
+/// OpenAI list: `{"object": "list", "data": [{"id", "object": "model", "created", "owned_by"}]}`
+pub fn openai_list(models: &[ListedModel], created: DateTime<Utc>) -> Value {
+    let data: Vec<Value> = models
+        .iter()
+        .map(|model| {
+            json!({
+                "id": model.id,
+                "object": "model",
+                "created": created.timestamp(),
+                "owned_by": model.owned_by,
+                "mappings": mappings_json(model),
+            })
+        })
+        .collect();
+    json!({"object": "list", "data": data})
+}
+
+/// Anthropic list: `{"data": [{"type": "model", "id", "display_name", "created_at"}], "has_more", "first_id", "last_id"}`
+pub fn anthropic_list(models: &[ListedModel], created: DateTime<Utc>) -> Value {
+    let data: Vec<Value> = models
+        .iter()
+        .map(|model| {
+            json!({
+                "type": "model",
+                "id": model.id,
+                "display_name": model.id,
+                "created_at": created.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                "mappings": mappings_json(model),
+            })
+        })
+        .collect();
+    json!({
+        "data": data,
+        "has_more": false,
+        "first_id": models.first().map(|m| &m.id),
+        "last_id": models.last().map(|m| &m.id),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_models() {
+        let config: AppConfig = toml::from_str(
+            r#"
+            [server]
+            [router]
+            default = "glm-4.6"
+
+            [[providers]]
+            name = "zai"
+            provider_type = "z.ai"
+            models = ["glm-4.6", "glm-4.5-air"]
+
+            [[providers]]
+            name = "openrouter"
+            provider_type = "openrouter"
+            models = ["glm-4.5-air", "deepseek-v3"]
+
+            [[providers]]
+            name = "off"
+            provider_type = "openai"
+            models = ["gpt-5"]
+            enabled = false
+
+            [[models]]
+            name = "glm-4.6"
+            mappings = [
+                { priority = 2, provider = "openrouter", actual_model = "z-ai/glm-4.6" },
+                { priority = 1, provider = "zai", actual_model = "glm-4.6" },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        let models = list_models(&config);
+        let ids: Vec<&str> = models.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["glm-4.6", "glm-4.5-air", "deepseek-v3"]);
+        assert_eq!(models[0].mappings[0], ("zai".to_string(), "glm-4.6".to_string()));
+        assert_eq!(models[0].mappings.len(), 2);
+        assert_eq!((models[1].owned_by.as_str(), models[1].mappings.len()), ("zai", 2));
+
+        let created = DateTime::parse_from_rfc3339("2025-06-01T00:00:00Z").unwrap().with_timezone(&Utc);
+        let openai = openai_list(&models, created);
+        assert_eq!(openai["data"][0]["object"], "model");
+        assert_eq!(openai["data"][0]["owned_by"], "ccm");
+        let anthropic = anthropic_list(&models, created);
+        assert_eq!(anthropic["data"][2]["type"], "model");
+        assert_eq!(anthropic["last_id"], "deepseek-v3");
+        assert_eq!(anthropic["data"][0]["created_at"], "2025-06-01T00:00:00Z");
+    }
+}
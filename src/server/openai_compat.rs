@@ -1,5 +1,8 @@
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
-use crate::models::{AnthropicRequest, MessageContent, ContentBlock, SystemPrompt};
+use serde_json::{json, Value};
+use crate::models::{AnthropicRequest, KnownContentBlock, Message, MessageContent, ContentBlock, SystemPrompt, Tool, ToolResultContent};
+use crate::providers::streaming::parse_sse_events;
 use crate::providers::ProviderResponse;
 
 /// OpenAI Chat Completions request format
@@ -18,6 +21,9 @@ pub struct OpenAIRequest {
     pub stop: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
+    /// `{"include_usage": true}` adds a final usage chunk to streams
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<serde_json::Value>>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -32,6 +38,33 @@ pub struct OpenAIMessage {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[allow(dead_code)]
     pub name: Option<String>,
+    /// Tool calls made by an assistant message
+    #[serde(default)]
+    pub tool_calls: Option<Vec<OpenAIToolCall>>,
+    /// Call a `tool` message answers
+    #[serde(default)]
+    pub tool_call_id: Option<String>,
+}
+
+/// A function call, in assistant messages and responses
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OpenAIToolCall {
+    pub id: String,
+    #[serde(rename = "type", default = "function_type")]
+    pub kind: String,
+    pub function: OpenAIFunctionCall,
+}
+
+fn function_type() -> String {
+    "function".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OpenAIFunctionCall {
+    pub name: String,
+    /// JSON-encoded arguments
+    #[serde(default)]
+    pub arguments: String,
 }
 
 /// Content can be string or array of content parts
@@ -81,6 +114,8 @@ pub struct OpenAIChoice {
 pub struct OpenAIResponseMessage {
     pub role: String,
     pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<OpenAIToolCall>>,
 }
 
 #[derive(Debug, Serialize)]
@@ -131,45 +166,7 @@ pub fn transform_openai_to_anthropic(openai_req: OpenAIRequest) -> Result<Anthro
                                         OpenAIContentPart::Text { text } => {
                                             Some(ContentBlock::text(text.clone(), None))
                                         }
-                                        OpenAIContentPart::ImageUrl { image_url } => {
-                                            // Parse data URL or external URL
-                                            if image_url.url.starts_with("data:") {
-                                                // data:image/png;base64,iVBORw0KG...
-                                                if let Some(comma_idx) = image_url.url.find(',') {
-                                                    let header = &image_url.url[..comma_idx];
-                                                    let data = &image_url.url[comma_idx + 1..];
-
-                                                    let media_type = if header.contains("image/jpeg") {
-                                                        "image/jpeg"
-                                                    } else if header.contains("image/png") {
-                                                        "image/png"
-                                                    } else if header.contains("image/gif") {
-                                                        "image/gif"
-                                                    } else if header.contains("image/webp") {
-                                                        "image/webp"
-                                                    } else {
-                                                        "image/png" // default
-                                                    };
-
-                                                    Some(ContentBlock::image(crate::models::ImageSource {
-                                                        r#type: "base64".to_string(),
-                                                        media_type: Some(media_type.to_string()),
-                                                        data: Some(data.to_string()),
-                                                        url: None,
-                                                    }))
-                                                } else {
-                                                    None
-                                                }
-                                            } else {
-                                                // External URL
-                                                Some(ContentBlock::image(crate::models::ImageSource {
-                                                    r#type: "url".to_string(),
-                                                    media_type: None,
-                                                    data: None,
-                                                    url: Some(image_url.url.clone()),
-                                                }))
-                                            }
-                                        }
+                                        OpenAIContentPart::ImageUrl { image_url } => image_from_url(&image_url.url),
                                     }
                                 })
                                 .collect();
@@ -185,18 +182,63 @@ pub fn transform_openai_to_anthropic(openai_req: OpenAIRequest) -> Result<Anthro
                     MessageContent::Text(String::new())
                 };
 
-                messages.push(crate::models::Message {
-                    role: msg.role,
-                    content,
+                // Assistant tool calls become tool_use blocks after the text
+                let calls = msg.tool_calls.unwrap_or_default();
+                let content = if calls.is_empty() {
+                    content
+                } else {
+                    let mut blocks = into_blocks(content);
+                    blocks.extend(calls.into_iter().map(|call| {
+                        ContentBlock::tool_use(call.id, call.function.name, parse_arguments(&call.function.arguments))
+                    }));
+                    MessageContent::Blocks(blocks)
+                };
+                push_message(&mut messages, msg.role, content);
+            }
+            "tool" => {
+                // Tool results go back to the model in a user message
+                let text = match msg.content {
+                    Some(OpenAIContent::String(text)) => text,
+                    Some(OpenAIContent::Parts(parts)) => parts
+                        .iter()
+                        .filter_map(|p| match p {
+                            OpenAIContentPart::Text { text } => Some(text.as_str()),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    None => String::new(),
+                };
+                let tool_use_id = msg.tool_call_id.ok_or_else(|| "tool message without tool_call_id".to_string())?;
+                let result = ContentBlock::Known(KnownContentBlock::ToolResult {
+                    tool_use_id,
+                    content: ToolResultContent::Text(text),
+                    is_error: false,
+                    cache_control: None,
                 });
+                push_message(&mut messages, "user".to_string(), MessageContent::Blocks(vec![result]));
             }
             _ => {
-                // Skip other roles (tool, function, etc.)
+                // Skip other roles (legacy function, etc.)
                 tracing::warn!("Skipping unsupported message role: {}", msg.role);
             }
         }
     }
 
+    // tool_choice "none" means the model may not call tools, same as sending none.
+    // Other choices ("required", a named function) aren't forwarded.
+    let tools_disabled = openai_req.tool_choice.as_ref().and_then(|c| c.as_str()) == Some("none");
+    if let Some(choice) = openai_req.tool_choice.as_ref().filter(|c| !matches!(c.as_str(), Some("auto" | "none"))) {
+        tracing::debug!("tool_choice {} is not forwarded, using auto", choice);
+    }
+    let tools: Vec<Tool> = openai_req
+        .tools
+        .unwrap_or_default()
+        .iter()
+        .filter(|_| !tools_disabled)
+        .filter_map(transform_tool)
+        .collect();
+
     Ok(AnthropicRequest {
         model: openai_req.model,
         messages,
@@ -209,10 +251,110 @@ pub fn transform_openai_to_anthropic(openai_req: OpenAIRequest) -> Result<Anthro
         stream: openai_req.stream,
         metadata: None,
         system: system_prompt,
-        tools: None, // TODO: Transform tools if needed
+        tools: if tools.is_empty() { None } else { Some(tools) },
+        anthropic_version: None,
+        extra_body: None,
     })
 }
 
+/// `{"type": "function", "function": {name, description, parameters}}` → Anthropic tool
+fn transform_tool(tool: &serde_json::Value) -> Option<Tool> {
+    if tool["type"].as_str().is_some_and(|t| t != "function") {
+        tracing::warn!("Skipping unsupported tool type: {}", tool["type"]);
+        return None;
+    }
+    let function = &tool["function"];
+    Some(Tool {
+        r#type: None,
+        name: Some(function["name"].as_str()?.to_string()),
+        description: function["description"].as_str().map(String::from),
+        input_schema: Some(
+            function
+                .get("parameters")
+                .filter(|p| !p.is_null())
+                .cloned()
+                .unwrap_or_else(|| serde_json::json!({"type": "object", "properties": {}})),
+        ),
+        cache_control: None,
+    })
+}
+
+/// Image block from a `data:` URL or an external URL
+pub(super) fn image_from_url(url: &str) -> Option<ContentBlock> {
+    if url.starts_with("data:") {
+        // data:image/png;base64,iVBORw0KG...
+        let comma_idx = url.find(',')?;
+        let header = &url[..comma_idx];
+        let data = &url[comma_idx + 1..];
+
+        let media_type = if header.contains("image/jpeg") {
+            "image/jpeg"
+        } else if header.contains("image/png") {
+            "image/png"
+        } else if header.contains("image/gif") {
+            "image/gif"
+        } else if header.contains("image/webp") {
+            "image/webp"
+        } else {
+            "image/png" // default
+        };
+
+        Some(ContentBlock::image(crate::models::ImageSource {
+            r#type: "base64".to_string(),
+            media_type: Some(media_type.to_string()),
+            data: Some(data.to_string()),
+            url: None,
+        }))
+    } else {
+        // External URL
+        Some(ContentBlock::image(crate::models::ImageSource {
+            r#type: "url".to_string(),
+            media_type: None,
+            data: None,
+            url: Some(url.to_string()),
+        }))
+    }
+}
+
+/// Tool call arguments as a JSON object (empty or invalid arguments become `{}`)
+pub(super) fn parse_arguments(arguments: &str) -> serde_json::Value {
+    serde_json::from_str(arguments)
+        .ok()
+        .filter(serde_json::Value::is_object)
+        .unwrap_or_else(|| serde_json::json!({}))
+}
+
+pub(super) fn into_blocks(content: MessageContent) -> Vec<ContentBlock> {
+    match content {
+        MessageContent::Text(text) if text.is_empty() => Vec::new(),
+        MessageContent::Text(text) => vec![ContentBlock::text(text, None)],
+        MessageContent::Blocks(blocks) => blocks,
+    }
+}
+
+/// Append a message, merging it into the previous one when both have the same role
+/// (Anthropic wants all results of one assistant turn in a single user message)
+pub(super) fn push_message(messages: &mut Vec<Message>, role: String, content: MessageContent) {
+    match messages.last_mut() {
+        Some(last) if last.role == role => {
+            let previous = std::mem::replace(&mut last.content, MessageContent::Blocks(Vec::new()));
+            let mut blocks = into_blocks(previous);
+            blocks.extend(into_blocks(content));
+            last.content = MessageContent::Blocks(blocks);
+        }
+        _ => messages.push(Message { role, content }),
+    }
+}
+
+/// Anthropic `stop_reason` → OpenAI `finish_reason`
+fn finish_reason(stop_reason: &str) -> &'static str {
+    match stop_reason {
+        "max_tokens" => "length",
+        "tool_use" => "tool_calls",
+        _ => "stop",
+    }
+}
+
 /// Transform Anthropic response to OpenAI format
 pub fn transform_anthropic_to_openai(
     anthropic_resp: ProviderResponse,
@@ -230,16 +372,20 @@ pub fn transform_anthropic_to_openai(
         Some(content)
     };
 
-    // Map finish_reason
-    let finish_reason = anthropic_resp.stop_reason.as_ref().map(|reason| {
-        match reason.as_str() {
-            "end_turn" => "stop",
-            "max_tokens" => "length",
-            "stop_sequence" => "stop",
-            _ => "stop",
-        }
-        .to_string()
-    });
+    let tool_calls: Vec<OpenAIToolCall> = anthropic_resp
+        .content
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::Known(KnownContentBlock::ToolUse { id, name, input }) => Some(OpenAIToolCall {
+                id: id.clone(),
+                kind: function_type(),
+                function: OpenAIFunctionCall { name: name.clone(), arguments: input.to_string() },
+            }),
+            _ => None,
+        })
+        .collect();
+
+    let finish_reason = anthropic_resp.stop_reason.as_deref().map(|reason| finish_reason(reason).to_string());
 
     OpenAIResponse {
         id: anthropic_resp.id,
@@ -254,6 +400,7 @@ pub fn transform_anthropic_to_openai(
             message: OpenAIResponseMessage {
                 role: anthropic_resp.role,
                 content,
+                tool_calls: if tool_calls.is_empty() { None } else { Some(tool_calls) },
             },
             finish_reason,
         }],
@@ -265,3 +412,262 @@ pub fn transform_anthropic_to_openai(
     }
 }
 
+/// Converts an Anthropic SSE stream to Chat Completions chunks, chunk by chunk
+pub struct ChatCompletionStream {
+    model: String,
+    created: u64,
+    id: String,
+    include_usage: bool,
+    buffer: String,
+    /// OpenAI index of the tool call being streamed, and whether it got any arguments
+    tool_call: Option<(usize, bool)>,
+    tool_calls: usize,
+    input_tokens: u32,
+    output_tokens: u32,
+    finish_reason: Option<&'static str>,
+}
+
+impl ChatCompletionStream {
+    pub fn new(model: String, include_usage: bool) -> Self {
+        Self {
+            model,
+            created: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            id: format!("chatcmpl-{}", uuid::Uuid::new_v4().simple()),
+            include_usage,
+            buffer: String::new(),
+            tool_call: None,
+            tool_calls: 0,
+            input_tokens: 0,
+            output_tokens: 0,
+            finish_reason: None,
+        }
+    }
+
+    /// Feed a chunk of the Anthropic stream; returns the `data:` lines it completes
+    pub fn push(&mut self, chunk: &[u8]) -> Bytes {
+        self.buffer.push_str(&String::from_utf8_lossy(chunk));
+        let Some(end) = self.buffer.rfind("\n\n") else {
+            return Bytes::new();
+        };
+        let complete: String = self.buffer.drain(..end + 2).collect();
+        let mut out = String::new();
+        for event in parse_sse_events(&complete) {
+            if let Ok(data) = serde_json::from_str::<Value>(&event.data) {
+                self.convert(&data, &mut out);
+            }
+        }
+        Bytes::from(out)
+    }
+
+    fn emit(&self, out: &mut String, delta: Value, finish_reason: Option<&str>) {
+        let chunk = json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}],
+        });
+        out.push_str(&format!("data: {}\n\n", chunk));
+    }
+
+    fn convert(&mut self, data: &Value, out: &mut String) {
+        match data["type"].as_str() {
+            Some("message_start") => {
+                let message = &data["message"];
+                if let Some(id) = message["id"].as_str() {
+                    self.id = format!("chatcmpl-{}", id);
+                }
+                self.input_tokens = message["usage"]["input_tokens"].as_u64().unwrap_or_default() as u32;
+                self.emit(out, json!({"role": "assistant", "content": ""}), None);
+            }
+            Some("content_block_start") if data["content_block"]["type"] == "tool_use" => {
+                let block = &data["content_block"];
+                let index = self.tool_calls;
+                self.tool_calls += 1;
+                self.tool_call = Some((index, false));
+                self.emit(out, json!({"tool_calls": [{
+                    "index": index,
+                    "id": block["id"],
+                    "type": "function",
+                    "function": {"name": block["name"], "arguments": ""},
+                }]}), None);
+            }
+            Some("content_block_delta") => {
+                let delta = &data["delta"];
+                match (delta["type"].as_str(), self.tool_call.as_mut()) {
+                    (Some("text_delta"), _) => self.emit(out, json!({"content": delta["text"]}), None),
+                    (Some("input_json_delta"), Some((index, has_arguments))) => {
+                        let arguments = delta["partial_json"].as_str().unwrap_or_default();
+                        if arguments.is_empty() {
+                            return;
+                        }
+                        *has_arguments = true;
+                        let index = *index;
+                        self.emit(out, json!({"tool_calls": [{"index": index, "function": {"arguments": arguments}}]}), None);
+                    }
+                    _ => {}
+                }
+            }
+            Some("content_block_stop") => {
+                // Clients parse the arguments once the call ends; an empty string isn't JSON
+                if let Some((index, false)) = self.tool_call.take() {
+                    self.emit(out, json!({"tool_calls": [{"index": index, "function": {"arguments": "{}"}}]}), None);
+                }
+            }
+            Some("message_delta") => {
+                if let Some(reason) = data["delta"]["stop_reason"].as_str() {
+                    self.finish_reason = Some(finish_reason(reason));
+                }
+                if let Some(tokens) = data["usage"]["output_tokens"].as_u64() {
+                    self.output_tokens = tokens as u32;
+                }
+                if let Some(tokens) = data["usage"]["input_tokens"].as_u64().filter(|t| *t > 0) {
+                    self.input_tokens = tokens as u32;
+                }
+            }
+            Some("message_stop") => {
+                self.emit(out, json!({}), Some(self.finish_reason.unwrap_or("stop")));
+                if self.include_usage {
+                    let usage = OpenAIUsage {
+                        prompt_tokens: self.input_tokens,
+                        completion_tokens: self.output_tokens,
+                        total_tokens: self.input_tokens + self.output_tokens,
+                    };
+                    let chunk = json!({
+                        "id": self.id,
+                        "object": "chat.completion.chunk",
+                        "created": self.created,
+                        "model": self.model,
+                        "choices": [],
+                        "usage": usage,
+                    });
+                    out.push_str(&format!("data: {}\n\n", chunk));
+                }
+                out.push_str("data: [DONE]\n\n");
+            }
+            Some("error") => {
+                let error = json!({"error": {
+                    "message": data["error"]["message"].as_str().unwrap_or("upstream error"),
+                    "type": data["error"]["type"].as_str().unwrap_or("api_error"),
+                }});
+                out.push_str(&format!("data: {}\n\ndata: [DONE]\n\n", error));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_tool_calling_round_trip() {
+        let request: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-5",
+            "messages": [
+                {"role": "user", "content": "Weather in Paris and Rome?"},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_1", "type": "function", "function": {"name": "weather", "arguments": "{\"city\":\"Paris\"}"}},
+                    {"id": "call_2", "type": "function", "function": {"name": "weather", "arguments": ""}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "18C"},
+                {"role": "tool", "tool_call_id": "call_2", "content": "24C"}
+            ],
+            "tools": [{"type": "function", "function": {"name": "weather", "description": "Current weather",
+                "parameters": {"type": "object", "properties": {"city": {"type": "string"}}}}}]
+        }))
+        .unwrap();
+        let anthropic = transform_openai_to_anthropic(request).unwrap();
+
+        let body = serde_json::to_value(&anthropic).unwrap();
+        assert_eq!(body["tools"][0]["name"], "weather");
+        assert_eq!(body["tools"][0]["input_schema"]["properties"]["city"]["type"], "string");
+        assert_eq!(body["messages"].as_array().unwrap().len(), 3);
+        assert_eq!(body["messages"][1]["content"][0], json!({"type": "tool_use", "id": "call_1", "name": "weather", "input": {"city": "Paris"}}));
+        assert_eq!(body["messages"][1]["content"][1]["input"], json!({}));
+        // Both results in one user message
+        assert_eq!(body["messages"][2]["role"], "user");
+        assert_eq!(body["messages"][2]["content"][1]["tool_use_id"], "call_2");
+        assert_eq!(body["messages"][2]["content"][1]["content"], "24C");
+
+        let response: ProviderResponse = serde_json::from_value(json!({
+            "id": "msg_1", "type": "message", "role": "assistant", "model": "claude",
+            "content": [
+                {"type": "text", "text": "Checking."},
+                {"type": "tool_use", "id": "toolu_1", "name": "weather", "input": {"city": "Oslo"}}
+            ],
+            "stop_reason": "tool_use", "stop_sequence": null,
+            "usage": {"input_tokens": 10, "output_tokens": 5}
+        }))
+        .unwrap();
+        let openai = serde_json::to_value(transform_anthropic_to_openai(response, "gpt-5".to_string())).unwrap();
+        let choice = &openai["choices"][0];
+        assert_eq!(choice["finish_reason"], "tool_calls");
+        assert_eq!(choice["message"]["content"], "Checking.");
+        assert_eq!(choice["message"]["tool_calls"][0]["function"], json!({"name": "weather", "arguments": "{\"city\":\"Oslo\"}"}));
+    }
+
+    #[test]
+    fn test_tool_choice_none_drops_tools() {
+        let request: OpenAIRequest = serde_json::from_value(json!({
+            "model": "gpt-5",
+            "messages": [{"role": "user", "content": "hi"}],
+            "tools": [{"type": "function", "function": {"name": "weather"}}],
+            "tool_choice": "none"
+        }))
+        .unwrap();
+        assert!(transform_openai_to_anthropic(request).unwrap().tools.is_none());
+    }
+
+    #[test]
+    fn test_stream_tool_call_chunks() {
+        let sse = concat!(
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",\"usage\":{\"input_tokens\":12,\"output_tokens\":1}}}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Checking.\"}}\n\n",
+            "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":0}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":1,\"content_block\":{\"type\":\"tool_use\",\"id\":\"toolu_1\",\"name\":\"weather\",\"input\":{}}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{\\\"city\\\":\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"\\\"Oslo\\\"}\"}}\n\n",
+            "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":1}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":2,\"content_block\":{\"type\":\"tool_use\",\"id\":\"toolu_2\",\"name\":\"now\",\"input\":{}}}\n\n",
+            "event: content_block_stop\ndata: {\"type\":\"content_block_stop\",\"index\":2}\n\n",
+            "event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"tool_use\"},\"usage\":{\"output_tokens\":30}}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        );
+        let mut converter = ChatCompletionStream::new("gpt-5".to_string(), true);
+        // Split mid-event to exercise buffering
+        let mut output = String::from_utf8(converter.push(&sse.as_bytes()[..300]).to_vec()).unwrap();
+        output.push_str(std::str::from_utf8(&converter.push(&sse.as_bytes()[300..])).unwrap());
+
+        let lines: Vec<&str> = output.lines().filter_map(|l| l.strip_prefix("data: ")).collect();
+        assert_eq!(lines.last(), Some(&"[DONE]"));
+        let chunks: Vec<Value> = lines[..lines.len() - 1].iter().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert!(chunks.iter().all(|c| c["id"] == "chatcmpl-msg_1" && c["object"] == "chat.completion.chunk"));
+        let deltas: Vec<&Value> = chunks.iter().filter_map(|c| c["choices"].get(0)).map(|c| &c["delta"]).collect();
+        assert_eq!(deltas[0]["role"], "assistant");
+        assert_eq!(deltas[1]["content"], "Checking.");
+        assert_eq!(deltas[2]["tool_calls"][0], json!({"index": 0, "id": "toolu_1", "type": "function", "function": {"name": "weather", "arguments": ""}}));
+
+        let arguments = |index: usize| -> String {
+            deltas
+                .iter()
+                .filter_map(|d| d["tool_calls"].get(0))
+                .filter(|call| call["index"] == index)
+                .filter_map(|call| call["function"]["arguments"].as_str())
+                .collect()
+        };
+        assert_eq!(arguments(0), "{\"city\":\"Oslo\"}");
+        // A call with no argument deltas still ends as valid JSON
+        assert_eq!(arguments(1), "{}");
+
+        let finish = &chunks[chunks.len() - 2]["choices"][0];
+        assert_eq!(finish["finish_reason"], "tool_calls");
+        assert_eq!(chunks.last().unwrap()["usage"], json!({"prompt_tokens": 12, "completion_tokens": 30, "total_tokens": 42}));
+    }
+}
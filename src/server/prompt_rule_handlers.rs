@@ -0,0 +1,120 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::cli::{AppConfig, PromptRule};
+use crate::router::validate_prompt_rule;
+
+use super::{reload_state, AppError, AppState};
+
+/// Request to add or replace a prompt rule
+#[derive(Debug, Deserialize)]
+pub struct PromptRuleUpsert {
+    /// Replace the rule at this index (appends when omitted)
+    pub index: Option<usize>,
+    #[serde(flatten)]
+    pub rule: PromptRule,
+}
+
+/// List prompt rules in evaluation order
+pub async fn list_prompt_rules(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let rules = read_prompt_rules(&state)?;
+    Ok(Json(serde_json::json!({
+        "prompt_rules": rules,
+    })))
+}
+
+/// Add a prompt rule, or replace one when `index` is given
+pub async fn upsert_prompt_rule(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<PromptRuleUpsert>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    validate_prompt_rule(&request.rule).map_err(AppError::InvalidRequest)?;
+
+    let mut rules = read_prompt_rules(&state)?;
+    let index = match request.index {
+        Some(index) => {
+            let slot = rules
+                .get_mut(index)
+                .ok_or_else(|| AppError::InvalidRequest(format!("No prompt rule at index {}", index)))?;
+            *slot = request.rule;
+            index
+        }
+        None => {
+            rules.push(request.rule);
+            rules.len() - 1
+        }
+    };
+
+    save_prompt_rules(&state, &rules)?;
+    info!("📝 Prompt rule {} saved via admin API", index);
+
+    Ok(Json(serde_json::json!({
+        "status": "success",
+        "index": index,
+        "prompt_rules": rules,
+    })))
+}
+
+/// Delete the prompt rule at `index`
+pub async fn delete_prompt_rule(
+    State(state): State<Arc<AppState>>,
+    Path(index): Path<usize>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let mut rules = read_prompt_rules(&state)?;
+    if index >= rules.len() {
+        return Err(AppError::InvalidRequest(format!("No prompt rule at index {}", index)));
+    }
+    rules.remove(index);
+
+    save_prompt_rules(&state, &rules)?;
+    info!("📝 Prompt rule {} deleted via admin API", index);
+
+    Ok(Json(serde_json::json!({
+        "status": "success",
+        "prompt_rules": rules,
+    })))
+}
+
+/// Read prompt rules from the config file (the source of truth, not the running snapshot)
+fn read_prompt_rules(state: &AppState) -> Result<Vec<PromptRule>, AppError> {
+    let config_str = std::fs::read_to_string(&state.config_path)
+        .map_err(|e| AppError::ParseError(format!("Failed to read config: {}", e)))?;
+    let config: AppConfig = toml::from_str(&config_str)
+        .map_err(|e| AppError::ParseError(format!("Failed to parse config: {}", e)))?;
+    Ok(config.router.prompt_rules)
+}
+
+/// Write just the prompt_rules array back to the config file and reload
+fn save_prompt_rules(state: &AppState, rules: &[PromptRule]) -> Result<(), AppError> {
+    let config_str = std::fs::read_to_string(&state.config_path)
+        .map_err(|e| AppError::ParseError(format!("Failed to read config: {}", e)))?;
+    let mut config: toml::Value = toml::from_str(&config_str)
+        .map_err(|e| AppError::ParseError(format!("Failed to parse config: {}", e)))?;
+
+    let rules_toml = toml::Value::try_from(rules)
+        .map_err(|e| AppError::ParseError(format!("Failed to convert prompt rules: {}", e)))?;
+
+    let router_table = config
+        .get_mut("router")
+        .and_then(|v| v.as_table_mut())
+        .ok_or_else(|| AppError::ParseError("Config has no [router] section".to_string()))?;
+    if rules.is_empty() {
+        router_table.remove("prompt_rules");
+    } else {
+        router_table.insert("prompt_rules".to_string(), rules_toml);
+    }
+
+    let new_config_str = toml::to_string_pretty(&config)
+        .map_err(|e| AppError::ParseError(format!("Failed to serialize config: {}", e)))?;
+    std::fs::write(&state.config_path, new_config_str)
+        .map_err(|e| AppError::ParseError(format!("Failed to write config: {}", e)))?;
+
+    reload_state(state).map_err(AppError::ParseError)
+}
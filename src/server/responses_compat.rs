@@ -0,0 +1,571 @@
+//! OpenAI Responses API (`POST /v1/responses`) on top of the Anthropic pipeline, for
+//! clients that only speak it (Codex CLI). Requests are converted to Anthropic
+//! format and served by `handle_messages`; its JSON or SSE output is converted back.
+//!
+//! Thinking blocks are returned as `reasoning` items whose `encrypted_content`
+//! carries the original block, so they can be replayed on the next turn.
+
+use super::openai_compat::{image_from_url, into_blocks, parse_arguments, push_message};
+use crate::models::{AnthropicRequest, ContentBlock, KnownContentBlock, Message, MessageContent, SystemPrompt, ThinkingConfig, Tool, ToolResultContent};
+use crate::providers::streaming::parse_sse_events;
+use crate::providers::{ProviderResponse, Usage};
+use bytes::Bytes;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+/// Codex never sends `max_output_tokens`, so leave room for long edits
+const DEFAULT_MAX_OUTPUT_TOKENS: u32 = 32000;
+
+/// Responses API request (fields CCM doesn't use are ignored)
+#[derive(Debug, Deserialize)]
+pub struct ResponsesRequest {
+    pub model: String,
+    /// A plain string (one user message) or a list of input items
+    pub input: ResponsesInput,
+    pub instructions: Option<String>,
+    pub max_output_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub stream: Option<bool>,
+    #[serde(default)]
+    pub tools: Vec<Value>,
+    pub tool_choice: Option<Value>,
+    pub reasoning: Option<ResponsesReasoning>,
+    /// Server-side conversation state isn't kept; clients must send the full input
+    pub previous_response_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ResponsesInput {
+    Text(String),
+    Items(Vec<Value>),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResponsesReasoning {
+    pub effort: Option<String>,
+}
+
+/// Thinking budget for a reasoning effort (None = no extended thinking)
+fn thinking_budget(effort: &str) -> Option<u32> {
+    match effort {
+        "low" => Some(4000),
+        "medium" => Some(10000),
+        "high" => Some(20000),
+        _ => None,
+    }
+}
+
+/// Text of a string or a list of text parts (`input_text`, `output_text`)
+fn text_of(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts.iter().filter_map(|p| p["text"].as_str()).collect::<Vec<_>>().join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// Content parts of a message item as Anthropic blocks
+fn content_blocks(content: &Value) -> Vec<ContentBlock> {
+    let Value::Array(parts) = content else {
+        return into_blocks(MessageContent::Text(text_of(content)));
+    };
+    parts
+        .iter()
+        .filter_map(|part| match part["type"].as_str() {
+            Some("input_text" | "output_text") => Some(ContentBlock::text(part["text"].as_str()?.to_string(), None)),
+            Some("input_image") => image_from_url(part["image_url"].as_str()?),
+            other => {
+                tracing::warn!("Skipping unsupported content part: {:?}", other);
+                None
+            }
+        })
+        .collect()
+}
+
+/// `{"type": "function", "name", "description", "parameters"}` → Anthropic tool
+fn transform_tool(tool: &Value) -> Option<Tool> {
+    if tool["type"].as_str() != Some("function") {
+        // Codex also offers built-in tools (web_search, local_shell) on every request
+        tracing::debug!("Skipping unsupported tool type: {}", tool["type"]);
+        return None;
+    }
+    Some(Tool {
+        r#type: None,
+        name: Some(tool["name"].as_str()?.to_string()),
+        description: tool["description"].as_str().map(String::from),
+        input_schema: Some(
+            tool.get("parameters")
+                .filter(|p| !p.is_null())
+                .cloned()
+                .unwrap_or_else(|| json!({"type": "object", "properties": {}})),
+        ),
+        cache_control: None,
+    })
+}
+
+/// Transform a Responses request to Anthropic format
+pub fn transform_request(request: ResponsesRequest) -> Result<AnthropicRequest, String> {
+    if request.previous_response_id.is_some() {
+        return Err("previous_response_id is not supported; send the full conversation in input".to_string());
+    }
+
+    let mut system: Vec<String> = request.instructions.into_iter().filter(|s| !s.is_empty()).collect();
+    let mut messages: Vec<Message> = Vec::new();
+    let mut call_ids = HashSet::new();
+    let items = match request.input {
+        ResponsesInput::Text(text) => vec![json!({"type": "message", "role": "user", "content": text})],
+        ResponsesInput::Items(items) => items,
+    };
+
+    for item in items {
+        // Message items may leave out the type
+        match item["type"].as_str().unwrap_or("message") {
+            "message" => match item["role"].as_str() {
+                Some("system" | "developer") => system.push(text_of(&item["content"])),
+                Some(role @ ("user" | "assistant")) => {
+                    push_message(&mut messages, role.to_string(), MessageContent::Blocks(content_blocks(&item["content"])))
+                }
+                role => tracing::warn!("Skipping message with unsupported role: {:?}", role),
+            },
+            "function_call" => {
+                let (Some(call_id), Some(name)) = (item["call_id"].as_str(), item["name"].as_str()) else {
+                    return Err("function_call item without call_id or name".to_string());
+                };
+                call_ids.insert(call_id.to_string());
+                let arguments = parse_arguments(item["arguments"].as_str().unwrap_or_default());
+                let block = ContentBlock::tool_use(call_id.to_string(), name.to_string(), arguments);
+                push_message(&mut messages, "assistant".to_string(), MessageContent::Blocks(vec![block]));
+            }
+            "function_call_output" => {
+                let call_id = item["call_id"].as_str().ok_or_else(|| "function_call_output item without call_id".to_string())?;
+                if !call_ids.contains(call_id) {
+                    // Output of a call that was skipped (e.g. a built-in tool)
+                    tracing::warn!("Skipping output of unknown call {}", call_id);
+                    continue;
+                }
+                let result = ContentBlock::Known(KnownContentBlock::ToolResult {
+                    tool_use_id: call_id.to_string(),
+                    content: ToolResultContent::Text(text_of(&item["output"])),
+                    is_error: false,
+                    cache_control: None,
+                });
+                push_message(&mut messages, "user".to_string(), MessageContent::Blocks(vec![result]));
+            }
+            "reasoning" => {
+                // Replay the thinking block we returned; reasoning from elsewhere can't be
+                let block = item["encrypted_content"]
+                    .as_str()
+                    .and_then(|raw| serde_json::from_str::<ContentBlock>(raw).ok())
+                    .filter(|block| matches!(block, ContentBlock::Known(KnownContentBlock::Thinking { .. })) || is_redacted_thinking(block));
+                if let Some(block) = block {
+                    push_message(&mut messages, "assistant".to_string(), MessageContent::Blocks(vec![block]));
+                }
+            }
+            other => tracing::warn!("Skipping unsupported input item: {}", other),
+        }
+    }
+
+    let tools_disabled = request.tool_choice.as_ref().and_then(|c| c.as_str()) == Some("none");
+    if let Some(choice) = request.tool_choice.as_ref().filter(|c| !matches!(c.as_str(), Some("auto" | "none"))) {
+        tracing::debug!("tool_choice {} is not forwarded, using auto", choice);
+    }
+    let tools: Vec<Tool> = request.tools.iter().filter(|_| !tools_disabled).filter_map(transform_tool).collect();
+
+    let max_tokens = request.max_output_tokens.unwrap_or(DEFAULT_MAX_OUTPUT_TOKENS);
+    let thinking = request
+        .reasoning
+        .and_then(|r| r.effort)
+        .and_then(|effort| thinking_budget(&effort))
+        .filter(|budget| *budget < max_tokens)
+        .map(|budget| ThinkingConfig { r#type: "enabled".to_string(), budget_tokens: Some(budget) });
+
+    Ok(AnthropicRequest {
+        model: request.model,
+        messages,
+        max_tokens,
+        thinking,
+        temperature: request.temperature,
+        top_p: request.top_p,
+        top_k: None,
+        stop_sequences: None,
+        stream: request.stream,
+        metadata: None,
+        system: if system.is_empty() { None } else { Some(SystemPrompt::Text(system.join("\n\n"))) },
+        tools: if tools.is_empty() { None } else { Some(tools) },
+        anthropic_version: None,
+        extra_body: None,
+    })
+}
+
+fn is_redacted_thinking(block: &ContentBlock) -> bool {
+    matches!(block, ContentBlock::Unknown(raw) if raw["type"] == "redacted_thinking")
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn usage_json(usage: &Usage) -> Value {
+    let cached = usage.cache_read_input_tokens.unwrap_or(0);
+    let input = usage.input_tokens + cached + usage.cache_creation_input_tokens.unwrap_or(0);
+    json!({
+        "input_tokens": input,
+        "input_tokens_details": {"cached_tokens": cached},
+        "output_tokens": usage.output_tokens,
+        "output_tokens_details": {"reasoning_tokens": 0},
+        "total_tokens": input + usage.output_tokens,
+    })
+}
+
+/// `status` and `incomplete_details` for an Anthropic stop reason
+fn completion_status(stop_reason: Option<&str>) -> (&'static str, Value) {
+    match stop_reason {
+        Some("max_tokens") => ("incomplete", json!({"reason": "max_output_tokens"})),
+        _ => ("completed", Value::Null),
+    }
+}
+
+fn response_json(id: &str, model: &str, created_at: u64, status: &str, output: Vec<Value>, usage: Option<Value>) -> Value {
+    json!({
+        "id": id,
+        "object": "response",
+        "created_at": created_at,
+        "status": status,
+        "model": model,
+        "output": output,
+        "usage": usage,
+    })
+}
+
+fn message_item(id: &str, text: &str, status: &str) -> Value {
+    json!({
+        "type": "message",
+        "id": id,
+        "status": status,
+        "role": "assistant",
+        "content": [{"type": "output_text", "text": text, "annotations": []}],
+    })
+}
+
+fn function_call_item(call_id: &str, name: &str, arguments: &str, status: &str) -> Value {
+    json!({
+        "type": "function_call",
+        "id": format!("fc_{}", call_id),
+        "call_id": call_id,
+        "name": name,
+        "arguments": arguments,
+        "status": status,
+    })
+}
+
+fn reasoning_item(id: &str, summary: &str, block: Option<&Value>) -> Value {
+    let summary: Vec<Value> = (!summary.is_empty()).then(|| json!({"type": "summary_text", "text": summary})).into_iter().collect();
+    json!({
+        "type": "reasoning",
+        "id": id,
+        "summary": summary,
+        "encrypted_content": block.map(Value::to_string),
+    })
+}
+
+/// Transform a complete Anthropic response to a Responses API response
+pub fn transform_response(response: ProviderResponse, model: &str) -> Value {
+    let output = response
+        .content
+        .iter()
+        .enumerate()
+        .filter_map(|(i, block)| match block {
+            ContentBlock::Known(KnownContentBlock::Text { text, .. }) => Some(message_item(&format!("msg_{}_{}", response.id, i), text, "completed")),
+            ContentBlock::Known(KnownContentBlock::ToolUse { id, name, input }) => Some(function_call_item(id, name, &input.to_string(), "completed")),
+            ContentBlock::Known(KnownContentBlock::Thinking { raw }) => {
+                let block = serde_json::to_value(block).ok()?;
+                Some(reasoning_item(&format!("rs_{}_{}", response.id, i), raw["thinking"].as_str().unwrap_or_default(), Some(&block)))
+            }
+            block if is_redacted_thinking(block) => {
+                Some(reasoning_item(&format!("rs_{}_{}", response.id, i), "", serde_json::to_value(block).ok().as_ref()))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let (status, incomplete_details) = completion_status(response.stop_reason.as_deref());
+    let mut body = response_json(&format!("resp_{}", response.id), model, now_secs(), status, output, Some(usage_json(&response.usage)));
+    body["incomplete_details"] = incomplete_details;
+    body
+}
+
+/// Output item being streamed
+enum OpenItem {
+    Text { id: String, text: String },
+    Call { call_id: String, name: String, arguments: String },
+    Reasoning { id: String, summary: String, block: Value },
+}
+
+/// Converts an Anthropic SSE stream to Responses API events, chunk by chunk
+pub struct ResponsesStream {
+    model: String,
+    created_at: u64,
+    id: String,
+    buffer: String,
+    sequence: u64,
+    output: Vec<Value>,
+    open: Option<OpenItem>,
+    usage: Usage,
+    stop_reason: Option<String>,
+}
+
+impl ResponsesStream {
+    pub fn new(model: String) -> Self {
+        Self {
+            model,
+            created_at: now_secs(),
+            id: format!("resp_{}", uuid::Uuid::new_v4().simple()),
+            buffer: String::new(),
+            sequence: 0,
+            output: Vec::new(),
+            open: None,
+            usage: Usage { input_tokens: 0, output_tokens: 0, cache_creation_input_tokens: None, cache_read_input_tokens: None },
+            stop_reason: None,
+        }
+    }
+
+    /// Feed a chunk of the Anthropic stream; returns the Responses events it completes
+    pub fn push(&mut self, chunk: &[u8]) -> Bytes {
+        self.buffer.push_str(&String::from_utf8_lossy(chunk));
+        let Some(end) = self.buffer.rfind("\n\n") else {
+            return Bytes::new();
+        };
+        let complete: String = self.buffer.drain(..end + 2).collect();
+        let mut out = String::new();
+        for event in parse_sse_events(&complete) {
+            if let Ok(data) = serde_json::from_str::<Value>(&event.data) {
+                self.convert(&data, &mut out);
+            }
+        }
+        Bytes::from(out)
+    }
+
+    fn emit(&mut self, out: &mut String, kind: &str, mut data: Value) {
+        data["type"] = json!(kind);
+        data["sequence_number"] = json!(self.sequence);
+        self.sequence += 1;
+        out.push_str(&format!("event: {}\ndata: {}\n\n", kind, data));
+    }
+
+    fn response(&self, status: &str, usage: Option<Value>) -> Value {
+        response_json(&self.id, &self.model, self.created_at, status, self.output.clone(), usage)
+    }
+
+    fn convert(&mut self, data: &Value, out: &mut String) {
+        let index = self.output.len();
+        match data["type"].as_str() {
+            Some("message_start") => {
+                let message = &data["message"];
+                if let Some(id) = message["id"].as_str() {
+                    self.id = format!("resp_{}", id);
+                }
+                if let Ok(usage) = serde_json::from_value(message["usage"].clone()) {
+                    self.usage = usage;
+                }
+                let response = self.response("in_progress", None);
+                self.emit(out, "response.created", json!({"response": response}));
+                self.emit(out, "response.in_progress", json!({"response": response}));
+            }
+            Some("content_block_start") => {
+                let block = &data["content_block"];
+                let (item, open) = match block["type"].as_str() {
+                    Some("text") => {
+                        let id = format!("msg_{}_{}", self.id.trim_start_matches("resp_"), index);
+                        (message_item(&id, "", "in_progress"), OpenItem::Text { id, text: String::new() })
+                    }
+                    Some("tool_use") => {
+                        let call_id = block["id"].as_str().unwrap_or_default().to_string();
+                        let name = block["name"].as_str().unwrap_or_default().to_string();
+                        (function_call_item(&call_id, &name, "", "in_progress"), OpenItem::Call { call_id, name, arguments: String::new() })
+                    }
+                    Some("thinking" | "redacted_thinking") => {
+                        let id = format!("rs_{}_{}", self.id.trim_start_matches("resp_"), index);
+                        (reasoning_item(&id, "", None), OpenItem::Reasoning { id, summary: String::new(), block: block.clone() })
+                    }
+                    _ => return,
+                };
+                self.emit(out, "response.output_item.added", json!({"output_index": index, "item": item}));
+                if let OpenItem::Text { id, .. } = &open {
+                    let item_id = id.clone();
+                    self.emit(out, "response.content_part.added", json!({
+                        "item_id": item_id, "output_index": index, "content_index": 0,
+                        "part": {"type": "output_text", "text": "", "annotations": []},
+                    }));
+                }
+                self.open = Some(open);
+            }
+            Some("content_block_delta") => {
+                let delta = &data["delta"];
+                let event = match (&mut self.open, delta["type"].as_str()) {
+                    (Some(OpenItem::Text { id, text }), Some("text_delta")) => {
+                        let chunk = delta["text"].as_str().unwrap_or_default();
+                        text.push_str(chunk);
+                        Some(("response.output_text.delta", json!({"item_id": id, "output_index": index, "content_index": 0, "delta": chunk})))
+                    }
+                    (Some(OpenItem::Call { call_id, arguments, .. }), Some("input_json_delta")) => {
+                        let chunk = delta["partial_json"].as_str().unwrap_or_default();
+                        arguments.push_str(chunk);
+                        Some(("response.function_call_arguments.delta", json!({"item_id": format!("fc_{}", call_id), "output_index": index, "delta": chunk})))
+                    }
+                    (Some(OpenItem::Reasoning { id, summary, block }), Some("thinking_delta")) => {
+                        let chunk = delta["thinking"].as_str().unwrap_or_default();
+                        summary.push_str(chunk);
+                        block["thinking"] = json!(summary);
+                        Some(("response.reasoning_summary_text.delta", json!({"item_id": id, "output_index": index, "summary_index": 0, "delta": chunk})))
+                    }
+                    (Some(OpenItem::Reasoning { block, .. }), Some("signature_delta")) => {
+                        block["signature"] = delta["signature"].clone();
+                        None
+                    }
+                    _ => None,
+                };
+                if let Some((kind, data)) = event {
+                    self.emit(out, kind, data);
+                }
+            }
+            Some("content_block_stop") => {
+                let item = match self.open.take() {
+                    Some(OpenItem::Text { id, text }) => {
+                        self.emit(out, "response.output_text.done", json!({"item_id": id, "output_index": index, "content_index": 0, "text": text}));
+                        self.emit(out, "response.content_part.done", json!({
+                            "item_id": id, "output_index": index, "content_index": 0,
+                            "part": {"type": "output_text", "text": text, "annotations": []},
+                        }));
+                        message_item(&id, &text, "completed")
+                    }
+                    Some(OpenItem::Call { call_id, name, arguments }) => {
+                        let arguments = parse_arguments(&arguments).to_string();
+                        self.emit(out, "response.function_call_arguments.done", json!({"item_id": format!("fc_{}", call_id), "output_index": index, "arguments": arguments}));
+                        function_call_item(&call_id, &name, &arguments, "completed")
+                    }
+                    Some(OpenItem::Reasoning { id, summary, block }) => reasoning_item(&id, &summary, Some(&block)),
+                    None => return,
+                };
+                self.emit(out, "response.output_item.done", json!({"output_index": index, "item": item}));
+                self.output.push(item);
+            }
+            Some("message_delta") => {
+                if let Some(reason) = data["delta"]["stop_reason"].as_str() {
+                    self.stop_reason = Some(reason.to_string());
+                }
+                if let Some(tokens) = data["usage"]["output_tokens"].as_u64() {
+                    self.usage.output_tokens = tokens as u32;
+                }
+            }
+            Some("message_stop") => {
+                let (status, incomplete_details) = completion_status(self.stop_reason.as_deref());
+                let mut response = self.response(status, Some(usage_json(&self.usage)));
+                response["incomplete_details"] = incomplete_details;
+                let kind = if status == "completed" { "response.completed" } else { "response.incomplete" };
+                self.emit(out, kind, json!({"response": response}));
+            }
+            Some("error") => {
+                let mut response = self.response("failed", None);
+                response["error"] = json!({
+                    "code": data["error"]["type"].as_str().unwrap_or("server_error"),
+                    "message": data["error"]["message"].as_str().unwrap_or("upstream error"),
+                });
+                self.emit(out, "response.failed", json!({"response": response}));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codex_request_to_anthropic() {
+        let request: ResponsesRequest = serde_json::from_value(json!({
+            "model": "gpt-5-codex",
+            "instructions": "You are Codex.",
+            "input": [
+                {"type": "message", "role": "developer", "content": [{"type": "input_text", "text": "Sandbox: read-only"}]},
+                {"type": "message", "role": "user", "content": [{"type": "input_text", "text": "List files"}]},
+                {"type": "reasoning", "id": "rs_1", "summary": [], "encrypted_content": "{\"type\":\"thinking\",\"thinking\":\"ls\",\"signature\":\"sig\"}"},
+                {"type": "function_call", "call_id": "call_1", "name": "shell", "arguments": "{\"command\":[\"ls\"]}"},
+                {"type": "function_call_output", "call_id": "call_1", "output": "Cargo.toml\nsrc"},
+                {"type": "function_call_output", "call_id": "call_web", "output": "skipped"}
+            ],
+            "tools": [
+                {"type": "function", "name": "shell", "description": "Run a command", "parameters": {"type": "object", "properties": {"command": {"type": "array"}}}},
+                {"type": "web_search"}
+            ],
+            "reasoning": {"effort": "medium", "summary": "auto"},
+            "store": false,
+            "stream": true
+        }))
+        .unwrap();
+        let body = serde_json::to_value(transform_request(request).unwrap()).unwrap();
+
+        assert_eq!(body["system"], "You are Codex.\n\nSandbox: read-only");
+        assert_eq!(body["thinking"]["budget_tokens"], 10000);
+        assert_eq!(body["tools"].as_array().unwrap().len(), 1);
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["content"][0], json!({"type": "thinking", "thinking": "ls", "signature": "sig"}));
+        assert_eq!(messages[1]["content"][1]["input"], json!({"command": ["ls"]}));
+        assert_eq!(messages[2]["content"], json!([{"type": "tool_result", "tool_use_id": "call_1", "content": "Cargo.toml\nsrc"}]));
+    }
+
+    #[test]
+    fn test_anthropic_stream_to_responses_events() {
+        let mut stream = ResponsesStream::new("gpt-5-codex".to_string());
+        let sse = [
+            json!({"type": "message_start", "message": {"id": "msg_1", "usage": {"input_tokens": 10, "output_tokens": 0, "cache_read_input_tokens": 90}}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "thinking", "thinking": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "Look first."}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "signature_delta", "signature": "sig"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "text_delta", "text": "Listing."}}),
+            json!({"type": "content_block_stop", "index": 1}),
+            json!({"type": "content_block_start", "index": 2, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "shell", "input": {}}}),
+            json!({"type": "content_block_delta", "index": 2, "delta": {"type": "input_json_delta", "partial_json": "{\"command\":"}}),
+            json!({"type": "content_block_delta", "index": 2, "delta": {"type": "input_json_delta", "partial_json": "[\"ls\"]}"}}),
+            json!({"type": "content_block_stop", "index": 2}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 12}}),
+            json!({"type": "message_stop"}),
+        ]
+        .iter()
+        .map(|data| format!("event: {}\ndata: {}\n\n", data["type"].as_str().unwrap(), data))
+        .collect::<String>();
+
+        // Split mid-event to exercise buffering
+        let (first, second) = sse.as_bytes().split_at(100);
+        let mut out = String::from_utf8(stream.push(first).to_vec()).unwrap();
+        out.push_str(std::str::from_utf8(&stream.push(second)).unwrap());
+        let events: Vec<Value> = parse_sse_events(&out).iter().map(|e| serde_json::from_str(&e.data).unwrap()).collect();
+
+        let kinds: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+        assert_eq!(kinds.first(), Some(&"response.created"));
+        assert!(kinds.contains(&"response.reasoning_summary_text.delta"));
+        assert!(kinds.contains(&"response.output_text.delta"));
+        assert_eq!(kinds.iter().filter(|k| **k == "response.output_item.done").count(), 3);
+        assert!(events.iter().enumerate().all(|(i, e)| e["sequence_number"] == i));
+
+        let completed = events.last().unwrap();
+        assert_eq!(completed["type"], "response.completed");
+        let response = &completed["response"];
+        assert_eq!(response["id"], "resp_msg_1");
+        assert_eq!(response["usage"]["input_tokens"], 100);
+        assert_eq!(response["usage"]["input_tokens_details"]["cached_tokens"], 90);
+        assert_eq!(response["usage"]["output_tokens"], 12);
+        let output = response["output"].as_array().unwrap();
+        let replayed: Value = serde_json::from_str(output[0]["encrypted_content"].as_str().unwrap()).unwrap();
+        assert_eq!(replayed, json!({"type": "thinking", "thinking": "Look first.", "signature": "sig"}));
+        assert_eq!(output[1]["content"][0]["text"], "Listing.");
+        assert_eq!(output[2]["call_id"], "toolu_1");
+        assert_eq!(output[2]["arguments"], "{\"command\":[\"ls\"]}");
+    }
+}
@@ -0,0 +1,83 @@
+//! `POST /api/route-test`: route a request without sending it anywhere, to see which
+//! routing step decided it and which provider mappings would be tried, in order.
+
+use axum::{extract::State, http::HeaderMap, Json};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::models::AnthropicRequest;
+use crate::router::RouteStep;
+
+use super::{
+    client_headers, direct_provider, forced_provider, header_flag, mapping_chain, resolve_model_config, AppError,
+    AppState,
+};
+
+/// Routing decision, evaluated steps and mapping chain for a request body. Honors the
+/// same headers as `/v1/messages` (User-Agent/x-app, X-Provider, X-CCM-Bypass) but
+/// leaves session state and round-robin counters untouched.
+pub async fn route_test(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<AnthropicRequest>,
+) -> Result<Json<Value>, AppError> {
+    let inner = state.snapshot();
+    let (user_agent, x_app) = client_headers(&headers);
+    let router = inner.router.for_client(user_agent, x_app);
+    let planned = if header_flag(&headers, "x-ccm-bypass") {
+        router.bypass(&request).map(|decision| {
+            let step = RouteStep { step: "bypass", matched: true, reason: "X-CCM-Bypass header set".to_string() };
+            (decision, vec![step])
+        })
+    } else {
+        router.plan_explained(&request).map(|(plan, steps)| (plan.decision, steps))
+    };
+    let (decision, steps) = planned.map_err(|e| AppError::RoutingError(e.to_string()))?;
+
+    let forced = forced_provider(&headers);
+    let chain = match resolve_model_config(&inner, &decision.model_name, forced.as_deref()) {
+        Some(model_config) => {
+            mapping_chain(&state, &inner, &model_config, &decision, &request.model, forced.as_deref(), false).map(
+                |mappings| {
+                    mappings
+                        .iter()
+                        .map(|m| {
+                            json!({
+                                "provider": m.provider,
+                                "actual_model": m.actual_model,
+                                "priority": m.priority,
+                                "registered": inner.provider_registry.get_provider(&m.provider).is_some(),
+                                "circuit": state.circuit_breaker.state(&m.provider, &m.actual_model),
+                                "quota_exhausted": state.quota.is_exhausted(&m.provider),
+                            })
+                        })
+                        .collect::<Vec<_>>()
+                },
+            )
+        }
+        None => direct_provider(&inner, forced.as_deref(), &decision.model_name).and_then(|provider| match provider {
+            Some((name, _)) => Ok(vec![json!({"provider": name, "actual_model": decision.model_name, "direct": true})]),
+            None => Err(AppError::RoutingError(format!(
+                "No model mapping or provider found for model: {}",
+                decision.model_name
+            ))),
+        }),
+    };
+
+    let mut result = json!({
+        "decision": {
+            "model": decision.model_name,
+            "route_type": decision.route_type.to_string(),
+            "matched_prompt": decision.matched_prompt,
+        },
+        "steps": steps,
+    });
+    match chain {
+        Ok(mappings) => result["mappings"] = json!(mappings),
+        Err(e) => {
+            result["mappings"] = json!([]);
+            result["error"] = json!(e.to_string());
+        }
+    }
+    Ok(Json(result))
+}
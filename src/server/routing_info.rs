@@ -0,0 +1,129 @@
+//! `last_routing.json` for the statusline script. Requests only update the in-memory
+//! history; a background task writes the latest state, at most once per debounce
+//! interval, so a slow disk never adds to request latency.
+
+use crate::models::RouteType;
+use chrono::Local;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Requests kept for the statusline's sparkline
+const RECENT_REQUESTS_WINDOW: usize = 20;
+
+/// Bursts of requests within this interval end up as one write
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Oldest history entries are dropped until the file fits (long model names add up)
+const MAX_FILE_BYTES: usize = 8 * 1024;
+
+pub struct RoutingInfo {
+    /// `model@provider`, newest first
+    recent: Mutex<VecDeque<String>>,
+    tx: watch::Sender<Option<Value>>,
+}
+
+impl RoutingInfo {
+    /// Continue the history in `path` and start the writer task
+    pub fn start(path: PathBuf) -> Self {
+        let recent = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+            .and_then(|existing| {
+                let items = existing.get("recent")?.as_array()?.clone();
+                Some(items.into_iter().filter_map(|item| item.as_str().map(String::from)).collect())
+            })
+            .unwrap_or_default();
+
+        let (tx, rx) = watch::channel(None);
+        tokio::spawn(write_changes(path, rx));
+        Self { recent: Mutex::new(recent), tx }
+    }
+
+    /// Note the model serving a request; written out shortly after
+    pub fn record(&self, model: &str, provider: &str, route_type: &RouteType) {
+        let recent = {
+            let mut recent = self.recent.lock().unwrap();
+            recent.push_front(format!("{}@{}", model, provider));
+            recent.truncate(RECENT_REQUESTS_WINDOW);
+            recent.iter().cloned().collect::<Vec<_>>()
+        };
+        self.tx.send_replace(Some(json!({
+            "model": model,
+            "provider": provider,
+            "route_type": route_type.to_string(),
+            "timestamp": Local::now().format("%H:%M:%S").to_string(),
+            "recent": recent,
+        })));
+    }
+}
+
+async fn write_changes(path: PathBuf, mut rx: watch::Receiver<Option<Value>>) {
+    while rx.changed().await.is_ok() {
+        tokio::time::sleep(DEBOUNCE).await;
+        let Some(info) = rx.borrow_and_update().clone() else {
+            continue;
+        };
+        let json = capped(info);
+        if let Some(dir) = path.parent() {
+            let _ = tokio::fs::create_dir_all(dir).await;
+        }
+        if let Err(e) = tokio::fs::write(&path, json).await {
+            tracing::debug!("Failed to write routing info: {}", e);
+        }
+    }
+}
+
+/// Serialized routing info within [`MAX_FILE_BYTES`]
+fn capped(mut info: Value) -> String {
+    loop {
+        let json = info.to_string();
+        let Some(recent) = info["recent"].as_array_mut().filter(|recent| !recent.is_empty()) else {
+            return json;
+        };
+        if json.len() <= MAX_FILE_BYTES {
+            return json;
+        }
+        recent.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_routing_info_written_off_request_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("last_routing.json");
+        std::fs::write(&path, r#"{"recent":["old@p"]}"#).unwrap();
+
+        let info = RoutingInfo::start(path.clone());
+        info.record("a", "p1", &RouteType::Default);
+        info.record("b", "p2", &RouteType::Think);
+        // Nothing is written until the debounce interval passes
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), r#"{"recent":["old@p"]}"#);
+
+        tokio::time::sleep(DEBOUNCE * 3).await;
+        let written: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["model"], "b");
+        assert_eq!(written["route_type"], "think");
+        assert_eq!(written["recent"], json!(["b@p2", "a@p1", "old@p"]));
+    }
+
+    #[test]
+    fn test_capped_drops_oldest_entries() {
+        let long = "m".repeat(1000);
+        let recent: Vec<String> = (0..RECENT_REQUESTS_WINDOW).map(|i| format!("{}{}@p", long, i)).collect();
+        let json = capped(json!({"model": "m", "recent": recent}));
+        assert!(json.len() <= MAX_FILE_BYTES);
+
+        let written: Value = serde_json::from_str(&json).unwrap();
+        let kept = written["recent"].as_array().unwrap();
+        assert!(!kept.is_empty() && kept.len() < RECENT_REQUESTS_WINDOW);
+        assert_eq!(kept[0], recent[0].as_str());
+    }
+}
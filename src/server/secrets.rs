@@ -0,0 +1,161 @@
+//! Masking of API keys and other credentials in config returned to clients, and
+//! restoring them when a masked config is saved back.
+
+use serde_json::Value;
+
+/// Characters of a secret kept visible at the end
+const VISIBLE_SUFFIX: usize = 4;
+
+/// `sk-proj-abc…wxyz` → `sk-…wxyz`: the key's prefix (up to the first `-`) and its
+/// last four characters. Short secrets are fully hidden.
+pub fn mask_secret(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    if chars.len() < 12 {
+        return "…".to_string();
+    }
+    let prefix: String = match secret.find('-') {
+        Some(i) if i <= 4 => secret[..=i].to_string(),
+        _ => String::new(),
+    };
+    let suffix: String = chars[chars.len() - VISIBLE_SUFFIX..].iter().collect();
+    format!("{}…{}", prefix, suffix)
+}
+
+/// Field names whose values are credentials
+fn is_secret_field(name: &str) -> bool {
+    let name = name.to_ascii_lowercase().replace('-', "_");
+    name == "authorization"
+        || name == "password"
+        || name.ends_with("api_key")
+        || name.ends_with("api_keys")
+        || name.ends_with("token")
+        || name.ends_with("secret")
+}
+
+/// Mask every string value under a credential-named field, at any depth
+/// (provider `api_key`, `server.api_key`, `Authorization`/`x-api-key` headers, ...).
+/// Values that are still `$ENV_VAR` references aren't secret and are kept.
+pub fn mask_secrets(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (name, field) in fields.iter_mut() {
+                match field {
+                    Value::String(secret) if is_secret_field(name) && !secret.starts_with('$') => {
+                        *secret = mask_secret(secret);
+                    }
+                    // Lists of keys, e.g. `server.auth.api_keys`
+                    Value::Array(secrets) if is_secret_field(name) => {
+                        for secret in secrets {
+                            if let Value::String(secret) = secret {
+                                if !secret.starts_with('$') {
+                                    *secret = mask_secret(secret);
+                                }
+                            }
+                        }
+                    }
+                    _ => mask_secrets(field),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(mask_secrets),
+        _ => {}
+    }
+}
+
+/// Whether `value` is a masked secret (as returned by `mask_secret`)
+pub fn is_masked(value: &str) -> bool {
+    value.contains('…')
+}
+
+/// Replace masked values in `new` with the values at the same place in `old`.
+/// Returns the paths of masked values with nothing to restore them from.
+fn restore_masked(new: &mut Value, old: Option<&Value>, path: &str, unresolved: &mut Vec<String>) {
+    match new {
+        Value::String(value) if is_masked(value) => match old.and_then(Value::as_str) {
+            Some(original) => *value = original.to_string(),
+            None => unresolved.push(path.to_string()),
+        },
+        Value::Object(fields) => {
+            for (name, field) in fields.iter_mut() {
+                let old_field = old.and_then(|old| old.get(name));
+                restore_masked(field, old_field, &format!("{}.{}", path, name), unresolved);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Put the real secrets back into providers saved from a masked config, matching
+/// providers by name against the config file's current `providers`. A masked
+/// secret that can't be matched (e.g. a renamed provider) is an error: it has
+/// to be entered again.
+pub fn restore_provider_secrets(providers: &mut Value, current: &Value) -> Result<(), String> {
+    let Some(providers) = providers.as_array_mut() else {
+        return Ok(());
+    };
+    let mut unresolved = Vec::new();
+    for provider in providers {
+        let name = provider["name"].as_str().unwrap_or_default().to_string();
+        let original = current
+            .as_array()
+            .and_then(|current| current.iter().find(|p| p["name"].as_str() == Some(name.as_str())));
+        restore_masked(provider, original, &format!("providers.{}", name), &mut unresolved);
+    }
+    if unresolved.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Masked secret(s) with no saved value: {}. Enter the key again.",
+            unresolved.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_masks_credentials_only() {
+        let mut config = json!({
+            "server": {"port": 13456, "api_key": "ccm-local-secret-1234", "auth": {"api_keys": ["ccm-laptop-key-0001", "$CCM_API_KEY"]}},
+            "providers": [
+                {"name": "openai", "api_key": "sk-proj-abcdefghijklmnop5678", "headers": {"X-Api-Key": "0123456789abcdef"}},
+                {"name": "env", "api_key": "$OPENROUTER_API_KEY"},
+                {"name": "short", "api_key": "abc"}
+            ],
+            "router": {"default": "sonnet", "suggest_max_tokens": 64}
+        });
+        mask_secrets(&mut config);
+
+        assert_eq!(config["server"]["api_key"], "ccm-…1234");
+        assert_eq!(config["server"]["auth"]["api_keys"], json!(["ccm-…0001", "$CCM_API_KEY"]));
+        assert_eq!(config["providers"][0]["api_key"], "sk-…5678");
+        assert_eq!(config["providers"][0]["headers"]["X-Api-Key"], "…cdef");
+        assert_eq!(config["providers"][1]["api_key"], "$OPENROUTER_API_KEY");
+        assert_eq!(config["providers"][2]["api_key"], "…");
+        assert_eq!(config["router"]["default"], "sonnet");
+        assert_eq!(config["router"]["suggest_max_tokens"], 64);
+    }
+
+    #[test]
+    fn test_restore_provider_secrets() {
+        let current = json!([
+            {"name": "openai", "api_key": "$OPENAI_API_KEY", "headers": {"X-Api-Key": "header-secret-1234"}},
+            {"name": "zai", "api_key": "zai-secret-key-9876"}
+        ]);
+        let mut saved = json!([
+            {"name": "zai", "api_key": "zai-…9876", "base_url": "https://api.z.ai"},
+            {"name": "openai", "api_key": "sk-new-key", "headers": {"X-Api-Key": "…1234"}}
+        ]);
+        restore_provider_secrets(&mut saved, &current).unwrap();
+        assert_eq!(saved[0]["api_key"], "zai-secret-key-9876");
+        assert_eq!(saved[1]["api_key"], "sk-new-key");
+        assert_eq!(saved[1]["headers"]["X-Api-Key"], "header-secret-1234");
+
+        let mut renamed = json!([{"name": "zai-2", "api_key": "zai-…9876"}]);
+        let err = restore_provider_secrets(&mut renamed, &current).unwrap_err();
+        assert!(err.contains("providers.zai-2.api_key"));
+    }
+}
@@ -0,0 +1,93 @@
+//! Local token counts for `/v1/messages/count_tokens`: every request with
+//! `server.count_tokens = "local"`, and counts a provider didn't answer in time.
+//!
+//! Text is counted with the cl100k tokenizer. Claude's tokenizer differs, so counts
+//! are approximate (usually within 10-20%), which is enough for context-window UIs.
+
+use crate::models::{AnthropicRequest, ContentBlock, KnownContentBlock, MessageContent, SystemPrompt};
+use once_cell::sync::Lazy;
+use tiktoken_rs::CoreBPE;
+
+static CL100K: Lazy<Option<CoreBPE>> = Lazy::new(|| match tiktoken_rs::cl100k_base() {
+    Ok(bpe) => Some(bpe),
+    Err(e) => {
+        tracing::warn!("⚠️ Tokenizer unavailable, estimating token counts from length: {}", e);
+        None
+    }
+});
+
+/// An image's size isn't known without decoding it; this is Claude's cost for
+/// about 1.15 megapixels, the largest image it takes without resizing
+const IMAGE_TOKENS: u32 = 1_600;
+
+/// Input tokens of `request`: system prompt, messages and tool definitions
+pub fn count(request: &AnthropicRequest) -> u32 {
+    let mut text = String::new();
+    let mut images = 0;
+    match &request.system {
+        Some(SystemPrompt::Text(system)) => text.push_str(system),
+        Some(SystemPrompt::Blocks(blocks)) => blocks.iter().for_each(|b| text.push_str(&b.text)),
+        None => {}
+    }
+
+    for msg in &request.messages {
+        match &msg.content {
+            MessageContent::Text(content) => text.push_str(content),
+            MessageContent::Blocks(blocks) => {
+                for block in blocks {
+                    match block {
+                        ContentBlock::Known(KnownContentBlock::Text { text: t, .. }) => text.push_str(t),
+                        ContentBlock::Known(KnownContentBlock::ToolUse { name, input, .. }) => {
+                            text.push_str(name);
+                            text.push_str(&input.to_string());
+                        }
+                        ContentBlock::Known(KnownContentBlock::ToolResult { content, .. }) => {
+                            text.push_str(&content.to_string())
+                        }
+                        ContentBlock::Known(KnownContentBlock::Thinking { raw }) => {
+                            text.push_str(raw.get("thinking").and_then(|v| v.as_str()).unwrap_or_default())
+                        }
+                        ContentBlock::Known(KnownContentBlock::Image { .. }) => images += 1,
+                        ContentBlock::Unknown(value) => text.push_str(&value.to_string()),
+                    }
+                    text.push('\n');
+                }
+            }
+        }
+        text.push('\n');
+    }
+
+    for tool in request.tools.iter().flatten() {
+        text.push_str(&serde_json::to_string(tool).unwrap_or_default());
+    }
+
+    let tokens = match CL100K.as_ref() {
+        Some(bpe) => bpe.encode_ordinary(&text).len() as u32,
+        None => (text.len() / 4) as u32,
+    };
+    tokens + images * IMAGE_TOKENS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_local_tokens() {
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "m",
+            "max_tokens": 10,
+            "system": "You are a helpful assistant.",
+            "messages": [
+                {"role": "user", "content": "What is the capital of France?"},
+                {"role": "user", "content": [
+                    {"type": "image", "source": {"type": "url", "url": "https://example.com/a.png"}}
+                ]}
+            ]
+        }))
+        .unwrap();
+        let tokens = count(&request);
+        // ~15 tokens of text plus the image
+        assert!((IMAGE_TOKENS + 10..IMAGE_TOKENS + 25).contains(&tokens), "{}", tokens);
+    }
+}
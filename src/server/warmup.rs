@@ -0,0 +1,113 @@
+use super::ReloadableState;
+use crate::cli::ModelMapping;
+use crate::models::{AnthropicRequest, Message, MessageContent, SystemPrompt};
+use crate::providers::AnthropicProvider;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info};
+
+/// Build a 1-token request carrying only the cacheable prefix (tools + system prompt).
+/// None when the system prompt has no cache_control breakpoint, since nothing would be cached.
+pub fn priming_request(request: &AnthropicRequest) -> Option<AnthropicRequest> {
+    let Some(SystemPrompt::Blocks(blocks)) = &request.system else {
+        return None;
+    };
+    if !blocks.iter().any(|b| b.cache_control.is_some()) {
+        return None;
+    }
+
+    let mut priming = request.clone();
+    priming.messages = vec![Message {
+        role: "user".to_string(),
+        content: MessageContent::Text(".".to_string()),
+    }];
+    priming.max_tokens = 1;
+    priming.stream = Some(false);
+    priming.thinking = None;
+    priming.temperature = None;
+    priming.top_p = None;
+    priming.top_k = None;
+    priming.stop_sequences = None;
+    Some(priming)
+}
+
+/// On a session's first request to a provider/model, send a priming request so the
+/// system prompt prefix is already cached. Waits up to `wait_ms` for it to finish;
+/// the priming request keeps running in the background after that.
+pub async fn warm_prompt_cache(
+    inner: &ReloadableState,
+    provider: &Arc<Box<dyn AnthropicProvider>>,
+    mapping: &ModelMapping,
+    request: &AnthropicRequest,
+) {
+    let config = &inner.config.server.cache_warmup;
+    if !config.enabled || !provider.supports_prompt_cache() {
+        return;
+    }
+    let Some(session) = request
+        .metadata
+        .as_ref()
+        .and_then(|m| m.get("user_id"))
+        .and_then(|v| v.as_str())
+    else {
+        return;
+    };
+    let Some(priming) = priming_request(request) else {
+        return;
+    };
+    let target = format!("{}/{}", mapping.provider, mapping.actual_model);
+    if !inner.router.sessions().mark_warmed(session, &target) {
+        return;
+    }
+
+    let provider = provider.clone();
+    let task = tokio::spawn(async move {
+        let start = std::time::Instant::now();
+        match provider.send_message(priming).await {
+            Ok(response) => info!(
+                "🔥 Primed prompt cache for {} in {}ms ({} tokens written, {} read)",
+                target,
+                start.elapsed().as_millis(),
+                response.usage.cache_creation_input_tokens.unwrap_or(0),
+                response.usage.cache_read_input_tokens.unwrap_or(0)
+            ),
+            Err(e) => debug!("🔥 Prompt cache priming for {} failed: {}", target, e),
+        }
+    });
+
+    if config.wait_ms > 0 {
+        let _ = tokio::time::timeout(Duration::from_millis(config.wait_ms), task).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priming_request_keeps_cached_prefix() {
+        let request: AnthropicRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 32000,
+            "stream": true,
+            "thinking": {"type": "enabled", "budget_tokens": 1024},
+            "system": [{"type": "text", "text": "You are Claude Code", "cache_control": {"type": "ephemeral"}}],
+            "tools": [{"name": "Read", "description": "read", "input_schema": {"type": "object"}}],
+            "messages": [{"role": "user", "content": "a long first prompt"}]
+        }))
+        .unwrap();
+
+        let priming = priming_request(&request).unwrap();
+        assert_eq!(priming.max_tokens, 1);
+        assert_eq!(priming.stream, Some(false));
+        assert!(priming.thinking.is_none());
+        assert_eq!(priming.messages.len(), 1);
+        assert_eq!(priming.tools.as_ref().map(|t| t.len()), Some(1));
+        assert!(priming.system.is_some());
+
+        // Nothing to prime without a cache breakpoint
+        let mut plain = request.clone();
+        plain.system = Some(SystemPrompt::Text("You are Claude Code".to_string()));
+        assert!(priming_request(&plain).is_none());
+    }
+}
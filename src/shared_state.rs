@@ -0,0 +1,296 @@
+//! State shared by CCM instances that use the same provider accounts (e.g. on a
+//! desktop and a laptop), so rate limits, budgets and OAuth tokens hold globally.
+//!
+//! The instances meet in one SQLite database on a path they can all reach. Each
+//! keeps serving from memory; every `sync_interval_secs` it pushes its own state
+//! (spend, rate limit windows, tokens) and pulls everyone else's:
+//! - spend is kept per instance and added up,
+//! - the newest quota observation and the latest Retry-After window win,
+//! - the token that expires last (was refreshed most recently) wins, so a rotated
+//!   refresh token reaches the other instances before theirs stops working.
+
+use crate::auth::token_store::OAuthToken;
+use crate::auth::TokenStore;
+use crate::cli::SharedStateConfig;
+use crate::message_tracing::expand_tilde;
+use crate::providers::budget::{BudgetTracker, SpendRecord};
+use crate::providers::quota::{QuotaStatus, QuotaTracker};
+use chrono::{DateTime, Datelike, NaiveDate, SecondsFormat, Utc};
+use rusqlite::{params, Connection};
+use std::sync::Mutex;
+
+/// How long to wait for another instance holding the database lock
+const BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Timestamps are stored in one format so they compare as text
+fn format_ts(ts: DateTime<Utc>) -> String {
+    ts.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn parse_ts(raw: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(raw).ok().map(|ts| ts.with_timezone(&Utc))
+}
+
+/// Name for this instance when none is configured
+fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .or_else(|| {
+            let output = std::process::Command::new("hostname").output().ok()?;
+            String::from_utf8(output.stdout).ok()
+        })
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "ccm".to_string())
+}
+
+/// Create the database (or tighten an existing one) as owner read/write only before
+/// any tokens are written; SQLite creates its journal with the same permissions
+fn restrict_permissions(path: &std::path::Path) -> anyhow::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        std::fs::OpenOptions::new().write(true).create(true).truncate(false).mode(0o600).open(path)?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+pub struct SharedState {
+    conn: Mutex<Connection>,
+    instance: String,
+}
+
+impl SharedState {
+    /// Open (or create) the shared database from config
+    pub fn open(config: &SharedStateConfig) -> anyhow::Result<Self> {
+        let path = expand_tilde(&config.path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        restrict_permissions(&path)?;
+        let instance = config.instance.clone().filter(|name| !name.is_empty()).unwrap_or_else(hostname);
+        Self::with_connection(Connection::open(&path)?, instance)
+    }
+
+    fn with_connection(conn: Connection, instance: String) -> anyhow::Result<Self> {
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS spend (
+                instance TEXT NOT NULL,
+                provider TEXT NOT NULL,
+                model TEXT NOT NULL,
+                day TEXT NOT NULL,
+                day_usd REAL NOT NULL,
+                month_usd REAL NOT NULL,
+                PRIMARY KEY (instance, provider, model)
+            );
+            CREATE TABLE IF NOT EXISTS quota (
+                provider TEXT PRIMARY KEY,
+                observed_at TEXT NOT NULL,
+                status TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS cooldowns (
+                provider TEXT PRIMARY KEY,
+                until TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS tokens (
+                provider_id TEXT PRIMARY KEY,
+                expires_at TEXT NOT NULL,
+                token TEXT NOT NULL
+            );",
+        )?;
+        Ok(Self { conn: Mutex::new(conn), instance })
+    }
+
+    pub fn instance(&self) -> &str {
+        &self.instance
+    }
+
+    /// Push this instance's state and pull the others'. Blocking.
+    pub fn sync(&self, quota: &QuotaTracker, budgets: &BudgetTracker, tokens: &TokenStore) -> anyhow::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        for record in budgets.local_spend() {
+            tx.execute(
+                "INSERT OR REPLACE INTO spend (instance, provider, model, day, day_usd, month_usd)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![self.instance, record.provider, record.model, record.day.to_string(), record.day_usd, record.month_usd],
+            )?;
+        }
+        for status in quota.statuses() {
+            tx.execute(
+                "INSERT INTO quota (provider, observed_at, status) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(provider) DO UPDATE SET observed_at = excluded.observed_at, status = excluded.status
+                 WHERE excluded.observed_at > quota.observed_at",
+                params![status.provider, format_ts(status.observed_at), serde_json::to_string(&status)?],
+            )?;
+        }
+        for (provider, until) in quota.cooldowns() {
+            tx.execute(
+                "INSERT INTO cooldowns (provider, until) VALUES (?1, ?2)
+                 ON CONFLICT(provider) DO UPDATE SET until = excluded.until WHERE excluded.until > cooldowns.until",
+                params![provider, format_ts(until)],
+            )?;
+        }
+        for token in tokens.all().into_values() {
+            tx.execute(
+                "INSERT INTO tokens (provider_id, expires_at, token) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(provider_id) DO UPDATE SET expires_at = excluded.expires_at, token = excluded.token
+                 WHERE excluded.expires_at > tokens.expires_at",
+                params![token.provider_id, format_ts(token.expires_at), serde_json::to_string(&token)?],
+            )?;
+        }
+
+        let today = Utc::now().date_naive();
+        let month_start = today.with_day(1).expect("day 1 exists in every month").to_string();
+        let remote_spend = tx
+            .prepare("SELECT provider, model, day, day_usd, month_usd FROM spend WHERE instance != ?1 AND day >= ?2")?
+            .query_map(params![self.instance, month_start], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get(3)?, row.get(4)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter_map(|(provider, model, day, day_usd, month_usd)| {
+                Some(SpendRecord { provider, model, day: day.parse::<NaiveDate>().ok()?, day_usd, month_usd })
+            })
+            .collect();
+        let statuses: Vec<QuotaStatus> = tx
+            .prepare("SELECT status FROM quota")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?
+            .iter()
+            .filter_map(|status| serde_json::from_str(status).ok())
+            .collect();
+        let cooldowns: Vec<(String, DateTime<Utc>)> = tx
+            .prepare("SELECT provider, until FROM cooldowns WHERE until > ?1")?
+            .query_map(params![format_ts(Utc::now())], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter_map(|(provider, until)| Some((provider, parse_ts(&until)?)))
+            .collect();
+        let shared_tokens: Vec<OAuthToken> = tx
+            .prepare("SELECT token FROM tokens")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?
+            .iter()
+            .filter_map(|token| serde_json::from_str(token).ok())
+            .collect();
+        tx.commit()?;
+        drop(conn);
+
+        budgets.set_remote(remote_spend);
+        quota.merge(statuses, cooldowns);
+        for token in shared_tokens {
+            let provider_id = token.provider_id.clone();
+            if tokens.merge(token)? {
+                tracing::info!("🔗 Adopted refreshed OAuth token for {} from another instance", provider_id);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::QuotaConfig;
+    use secrecy::{ExposeSecret, SecretString};
+    use std::collections::HashMap;
+
+    struct Instance {
+        shared: SharedState,
+        quota: QuotaTracker,
+        budgets: BudgetTracker,
+        tokens: TokenStore,
+    }
+
+    fn instance(dir: &std::path::Path, name: &str) -> Instance {
+        let conn = Connection::open(dir.join("shared.db")).unwrap();
+        Instance {
+            shared: SharedState::with_connection(conn, name.to_string()).unwrap(),
+            quota: QuotaTracker::new(QuotaConfig::default()),
+            budgets: BudgetTracker::new(),
+            tokens: TokenStore::new(dir.join(format!("{}-tokens.json", name))).unwrap(),
+        }
+    }
+
+    fn token(access: &str, expires_in_hours: i64) -> OAuthToken {
+        OAuthToken {
+            provider_id: "claude-max".to_string(),
+            access_token: SecretString::new(access.to_string()),
+            refresh_token: SecretString::new(format!("refresh-{}", access)),
+            expires_at: Utc::now() + chrono::Duration::hours(expires_in_hours),
+            enterprise_url: None,
+            project_id: None,
+        }
+    }
+
+    #[test]
+    fn test_instances_share_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let (desktop, laptop) = (instance(dir.path(), "desktop"), instance(dir.path(), "laptop"));
+        let sync = |i: &Instance| i.shared.sync(&i.quota, &i.budgets, &i.tokens).unwrap();
+
+        desktop.budgets.record("anthropic", "claude-opus-4-1", 3.0);
+        laptop.budgets.record("anthropic", "claude-opus-4-1", 2.5);
+        let headers: HashMap<String, String> = [
+            ("anthropic-ratelimit-requests-limit", "100"),
+            ("anthropic-ratelimit-requests-remaining", "1"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        desktop.quota.observe("anthropic", &headers);
+        desktop.tokens.save(token("old", 1)).unwrap();
+        laptop.tokens.save(token("rotated", 8)).unwrap();
+
+        sync(&desktop);
+        sync(&laptop);
+        sync(&desktop);
+
+        // Spend adds up across instances, and syncing again doesn't double count
+        let budgets = vec![crate::providers::budget::BudgetConfig {
+            provider: Some("anthropic".to_string()),
+            model: None,
+            daily_usd: Some(5.0),
+            monthly_usd: None,
+            on_exceeded: Default::default(),
+        }];
+        for i in [&desktop, &laptop] {
+            let exceeded = i.budgets.check(&budgets, "anthropic", "claude-opus-4-1").unwrap();
+            assert!((exceeded.spent_usd - 5.5).abs() < 1e-9, "{}", exceeded.spent_usd);
+        }
+
+        assert!(laptop.quota.is_exhausted("anthropic"));
+        assert_eq!(desktop.tokens.get("claude-max").unwrap().access_token.expose_secret(), "rotated");
+        assert_eq!(laptop.tokens.get("claude-max").unwrap().access_token.expose_secret(), "rotated");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_database_is_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let mode = |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let config = |name: &str| SharedStateConfig {
+            path: dir.path().join(name).to_string_lossy().to_string(),
+            instance: Some("desktop".to_string()),
+            sync_interval_secs: 5,
+        };
+
+        SharedState::open(&config("new.db")).unwrap();
+        assert_eq!(mode(&dir.path().join("new.db")), 0o600);
+
+        // An existing database created with a looser umask is tightened
+        let existing = dir.path().join("existing.db");
+        std::fs::write(&existing, "").unwrap();
+        std::fs::set_permissions(&existing, std::fs::Permissions::from_mode(0o644)).unwrap();
+        SharedState::open(&config("existing.db")).unwrap();
+        assert_eq!(mode(&existing), 0o600);
+    }
+}
@@ -3,17 +3,22 @@
 # Shows models used in recent requests with sparkline bars
 #
 # Installed via: ccm install-statusline
-# File location: ~/.claude-code-mux/statusline.sh
+# File location: <state dir>/statusline.sh (default ~/.claude-code-mux)
 #
-# Displays: model@provider ████ model2@provider ██
-# Each █ = 1 request (out of last 20)
+# Displays: model@provider ████ model2@provider ██ ⚡42t/s 12s
+# Each █ = 1 request (out of last 20); ⚡ is the output speed and elapsed time of
+# the response streaming now (or the last one), from CCM's /api/live
 
 # Only show CCM info if Claude Code is using CCM (ANTHROPIC_BASE_URL set)
 if [ -z "$ANTHROPIC_BASE_URL" ]; then
     exit 0
 fi
 
-CCM_FILE="$HOME/.claude-code-mux/last_routing.json"
+CCM_FILE="${CCM_STATE_DIR:-$HOME/.claude-code-mux}/last_routing.json"
+
+# Live generation speed (short timeout so a busy or stopped CCM never stalls the statusline)
+SPEED=$(curl -s --max-time 0.3 "${ANTHROPIC_BASE_URL%/}/api/live" 2>/dev/null \
+    | jq -r '(.streams[0] // .last) // empty | " ⚡\(.tokens_per_sec | floor)t/s \(.elapsed_ms / 1000 | floor)s"' 2>/dev/null)
 
 if [ ! -f "$CCM_FILE" ]; then
     echo "CCM: no routing yet"
@@ -27,7 +32,7 @@ if [ "$RECENT" = "[]" ] || [ -z "$RECENT" ]; then
     # Fallback: show current model
     MODEL=$(jq -r '.model // "unknown"' "$CCM_FILE")
     PROVIDER=$(jq -r '.provider // "unknown"' "$CCM_FILE")
-    echo "$MODEL@$PROVIDER"
+    echo "$MODEL@$PROVIDER$SPEED"
     exit 0
 fi
 
@@ -58,4 +63,4 @@ while read -r MODEL; do
     fi
 done <<< "$UNIQUE_MODELS"
 
-echo "$OUTPUT"
+echo "$OUTPUT$SPEED"